@@ -0,0 +1,241 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Throughput of the main loop with registries of 1000 and 10000
+//! services, all but `RUNNING` of them manual services that are never
+//! started. Run with `cargo bench --bench event_loop`.
+//!
+//! - `reap`: the running services are killed at once, until the snapshot
+//!   doesn't list any of their pids
+//! - `control`: `COMMANDS` commands are written to the control FIFO at
+//!   once, then a `snapshot` one whose run set file tells when they have
+//!   all been processed
+//! - `status_write`: a service is stopped then started again, until the
+//!   snapshot lists it as running. The status file and the snapshot are
+//!   rendered for the whole registry on each of the 2 state changes. Not
+//!   a restart, which waits for the timer to start the service again
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use svlopp::{
+    CONTROL_PIPE_NAME, RUN_SET_FILE_NAME, SNAPSHOT_FILE_NAME, opcode,
+    snapshot::{RecordState, Snapshot},
+    wire::WireControlCommand,
+};
+
+const REGISTRY_SIZES: [usize; 2] = [1000, 10000];
+const RUNNING: usize = 100;
+const COMMANDS: usize = 1000;
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+struct Supervisor {
+    child: Child,
+    dir: PathBuf,
+    /// Ids of the running services
+    running_ids: Vec<u64>,
+}
+
+impl Supervisor {
+    fn start(services: usize) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "svlopp-bench-event-loop-{}-{}",
+            std::process::id(),
+            services
+        ));
+        fs::create_dir_all(&dir).expect("bench directory created");
+        let config = dir.join("svlopp.toml");
+        let mut content = String::new();
+        for i in 0..RUNNING {
+            content.push_str(&format!(
+                "[services.running-{i}]\ncommand = \"/bin/sleep\"\nargs = [\"3600\"]\n\n"
+            ));
+        }
+        for i in RUNNING..services {
+            content.push_str(&format!(
+                "[services.idle-{i}]\ncommand = \"/bin/true\"\nautostart = false\n\n"
+            ));
+        }
+        fs::write(&config, content).expect("config written");
+        let child = Command::new(env!("CARGO_BIN_EXE_svlopp"))
+            .args(["--run-dir".as_ref(), dir.join("run").as_os_str()])
+            .args(["--log-level", "error"])
+            .arg(&config)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("svlopp started");
+        let mut supervisor = Self {
+            child,
+            dir,
+            running_ids: Vec::new(),
+        };
+        let snapshot = supervisor.wait(|snapshot| {
+            snapshot.services.len() == services && running_pids(snapshot).len() == RUNNING
+        });
+        supervisor.running_ids = snapshot
+            .services
+            .iter()
+            .filter(|svc| svc.name.starts_with("running-"))
+            .map(|svc| svc.id)
+            .collect();
+        supervisor
+    }
+
+    fn run_dir(&self) -> PathBuf {
+        self.dir.join("run")
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        let buf = fs::read(self.run_dir().join(SNAPSHOT_FILE_NAME)).ok()?;
+        Snapshot::decode(&buf).ok()
+    }
+
+    /// Poll the snapshot until `done`, returning it
+    fn wait(&self, done: impl Fn(&Snapshot) -> bool) -> Snapshot {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            if let Some(snapshot) = self.snapshot()
+                && done(&snapshot)
+            {
+                return snapshot;
+            }
+            assert!(Instant::now() < deadline, "svlopp didn't catch up");
+            sleep(Duration::from_micros(200));
+        }
+    }
+
+    /// Write `commands` to the control FIFO at once
+    fn send(&self, commands: impl IntoIterator<Item = (u8, u64)>) {
+        let frames: Vec<u8> = commands
+            .into_iter()
+            .flat_map(|(op, id)| WireControlCommand::new(op, id).encode())
+            .collect();
+        OpenOptions::new()
+            .write(true)
+            .open(self.run_dir().join(CONTROL_PIPE_NAME))
+            .and_then(|mut fifo| fifo.write_all(&frames))
+            .expect("commands sent");
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        unsafe { libc::kill(self.child.id() as i32, libc::SIGTERM) };
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn running_pids(snapshot: &Snapshot) -> Vec<i32> {
+    snapshot
+        .services
+        .iter()
+        .filter_map(|svc| match svc.state {
+            RecordState::Running { pid } => Some(pid),
+            _ => None,
+        })
+        .collect()
+}
+
+fn reap(c: &mut Criterion) {
+    let mut group = c.benchmark_group("reap");
+    group
+        .sample_size(10)
+        .throughput(Throughput::Elements(RUNNING as u64));
+    for size in REGISTRY_SIZES {
+        let supervisor = Supervisor::start(size);
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let pids = running_pids(&supervisor.wait(|s| running_pids(s).len() == RUNNING));
+                    let start = Instant::now();
+                    for &pid in &pids {
+                        unsafe { libc::kill(pid, libc::SIGKILL) };
+                    }
+                    supervisor.wait(|s| s.services.iter().all(|svc| svc.state.pid().is_none()));
+                    elapsed += start.elapsed();
+                    supervisor.send(supervisor.running_ids.iter().map(|&id| (opcode::START, id)));
+                }
+                elapsed
+            })
+        });
+    }
+    group.finish();
+}
+
+fn control(c: &mut Criterion) {
+    let mut group = c.benchmark_group("control");
+    group
+        .sample_size(10)
+        .throughput(Throughput::Elements(COMMANDS as u64));
+    for size in REGISTRY_SIZES {
+        let supervisor = Supervisor::start(size);
+        let run_set = supervisor.run_dir().join(RUN_SET_FILE_NAME);
+        // starting a running service is a no-op
+        let id = supervisor.running_ids[0];
+        let commands: Vec<(u8, u64)> = std::iter::repeat_n((opcode::START, id), COMMANDS)
+            .chain(std::iter::once((opcode::SNAPSHOT, 0)))
+            .collect();
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let _ = fs::remove_file(&run_set);
+                    let start = Instant::now();
+                    supervisor.send(commands.iter().copied());
+                    while !run_set.exists() {
+                        assert!(start.elapsed() < TIMEOUT, "commands not processed");
+                        sleep(Duration::from_micros(50));
+                    }
+                    elapsed += start.elapsed();
+                }
+                elapsed
+            })
+        });
+    }
+    group.finish();
+}
+
+fn status_write(c: &mut Criterion) {
+    let mut group = c.benchmark_group("status_write");
+    group.sample_size(10).throughput(Throughput::Elements(2));
+    for size in REGISTRY_SIZES {
+        let supervisor = Supervisor::start(size);
+        let id = supervisor.running_ids[0];
+        let state = move |snapshot: &Snapshot| {
+            snapshot
+                .services
+                .iter()
+                .find(|svc| svc.id == id)
+                .map(|svc| svc.state)
+        };
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let start = Instant::now();
+                    supervisor.send([(opcode::STOP, id)]);
+                    supervisor.wait(|s| matches!(state(s), Some(RecordState::Stopped { .. })));
+                    supervisor.send([(opcode::START, id)]);
+                    supervisor.wait(|s| matches!(state(s), Some(RecordState::Running { .. })));
+                    elapsed += start.elapsed();
+                }
+                elapsed
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, reap, control, status_write);
+criterion_main!(benches);
@@ -0,0 +1,107 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Cost of starting a service process with each spawn strategy, and with
+//! the spawn helper. Run with `cargo bench --bench spawn`.
+//!
+//! svlopp is started with `SERVICES` oneshot services and a start
+//! concurrency high enough to start them all in one main loop iteration.
+//! The startup profile then tells when each process was forked: the time
+//! between the first and the last one, over the number of services, is
+//! what a start costs the main loop, from opening the service fds to
+//! registering its pipes.
+
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use svlopp::STARTUP_FILE_NAME;
+
+const SERVICES: usize = 500;
+const ROUNDS: usize = 5;
+
+const STRATEGIES: [(&str, &str, &[&str]); 4] = [
+    ("fork", "fork", &[]),
+    ("posix_spawn", "posix_spawn", &[]),
+    ("clone3", "clone3", &[]),
+    ("--spawner", "fork", &["--spawner"]),
+];
+
+/// Start svlopp with `strategy`, returns the mean time a start took in
+/// the main loop and when the last service exec'd
+fn run(dir: &Path, strategy: &str, args: &[&str]) -> (Duration, Duration) {
+    let config = dir.join("svlopp.toml");
+    let run_dir = dir.join("run");
+    let mut content = format!("start_concurrency = {SERVICES}\nspawn_strategy = \"{strategy}\"\n");
+    // the same work for every strategy, `posix_spawn` not joining a keyring
+    for i in 0..SERVICES {
+        content.push_str(&format!(
+            "\n[services.job-{i}]\ncommand = \"/bin/true\"\nkeyring = \"inherit\"\n"
+        ));
+    }
+    fs::write(&config, content).expect("config written");
+
+    let child = Command::new(env!("CARGO_BIN_EXE_svlopp"))
+        .args(["--run-dir".as_ref(), run_dir.as_os_str()])
+        .args(["--log-level", "error"])
+        .args(args)
+        .arg(&config)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("svlopp started");
+
+    let startup = run_dir.join(STARTUP_FILE_NAME);
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let times = loop {
+        let times: Vec<(u64, u64)> = fs::read_to_string(&startup)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(' ').skip(2);
+                Some((fields.next()?.parse().ok()?, fields.next()?.parse().ok()?))
+            })
+            .collect();
+        if times.len() == SERVICES {
+            break times;
+        }
+        assert!(Instant::now() < deadline, "services didn't start");
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    unsafe { libc::kill(child.id() as i32, libc::SIGTERM) };
+    let status = child.wait_with_output().expect("svlopp exited").status;
+    assert!(status.success(), "svlopp failed: {}", status);
+
+    let first = times.iter().map(|(forked, _)| forked).min().expect("times");
+    let last = times.iter().map(|(forked, _)| forked).max().expect("times");
+    let up = times.iter().map(|(_, exec)| exec).max().expect("times");
+    (
+        Duration::from_micros((last - first) / (SERVICES as u64 - 1)),
+        Duration::from_micros(*up),
+    )
+}
+
+fn main() {
+    let dir = std::env::temp_dir().join(format!("svlopp-bench-spawn-{}", std::process::id()));
+    fs::create_dir_all(&dir).expect("bench directory created");
+    for (name, strategy, args) in STRATEGIES {
+        // the best round, the others being slowed down by something else
+        let (per_start, up) = (0..ROUNDS)
+            .map(|_| run(&dir, strategy, args))
+            .min()
+            .expect("rounds");
+        println!(
+            "{:<12} {:>8} ns/start, {} services up in {:?}",
+            name,
+            per_start.as_nanos(),
+            SERVICES,
+            up
+        );
+    }
+    let _ = fs::remove_dir_all(&dir);
+}
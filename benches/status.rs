@@ -0,0 +1,72 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Rendering of the status file and of the state snapshot for a large
+//! registry, as done on every state change. Run with `cargo bench`.
+
+use std::{fmt::Write, hint::black_box, time::Instant};
+
+use svlopp::snapshot::{
+    RecordFlags, RecordState, ServiceRecordRef, StopReasonKind, encode_snapshot,
+};
+
+const SERVICES: usize = 1000;
+const ITERATIONS: u32 = 2000;
+
+/// Run `f` `ITERATIONS` times after a warm up run, checking that the
+/// buffer it renders into doesn't grow past the warm up, i.e. that it
+/// doesn't allocate
+fn bench<B>(name: &str, buf: &mut B, capacity: fn(&B) -> usize, mut f: impl FnMut(&mut B)) {
+    f(buf);
+    let warm = capacity(buf);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f(black_box(&mut *buf));
+    }
+    let elapsed = start.elapsed();
+    assert_eq!(capacity(buf), warm, "{} allocated", name);
+    println!(
+        "{:<24} {:>10} ns/iter ({} services)",
+        name,
+        (elapsed / ITERATIONS).as_nanos(),
+        SERVICES
+    );
+}
+
+fn main() {
+    let names: Vec<String> = (0..SERVICES).map(|i| format!("service-{}", i)).collect();
+    let records: Vec<ServiceRecordRef<'_>> = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| ServiceRecordRef {
+            id: i as u64 + 1,
+            name,
+            state: match i % 3 {
+                0 => RecordState::Running {
+                    pid: 1000 + i as i32,
+                },
+                1 => RecordState::Stopping {
+                    pid: 1000 + i as i32,
+                },
+                _ => RecordState::Stopped {
+                    reason: StopReasonKind::Error,
+                    value: 1,
+                },
+            },
+            start_count: i as u64,
+            flags: RecordFlags::default(),
+        })
+        .collect();
+
+    bench("status", &mut String::new(), String::capacity, |buf| {
+        buf.clear();
+        for record in &records {
+            let _ = writeln!(buf, "{}", record);
+        }
+    });
+    bench("snapshot", &mut Vec::new(), Vec::capacity, |buf| {
+        buf.clear();
+        encode_snapshot(buf, 0, 0, 1, records.iter().copied());
+    });
+}
@@ -0,0 +1,61 @@
+# bash completion for svloppctl
+
+_svloppctl() {
+    local cur=${COMP_WORDS[COMP_CWORD]}
+    local prev=${COMP_WORDS[COMP_CWORD - 1]}
+    local -a run_dir=()
+    local cmd= cmd_index= i
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case ${COMP_WORDS[i]} in
+            --run-dir)
+                run_dir=(--run-dir "${COMP_WORDS[i + 1]}")
+                ((i++))
+                ;;
+            -*) ;;
+            *)
+                cmd=${COMP_WORDS[i]}
+                cmd_index=$i
+                break
+                ;;
+        esac
+    done
+
+    if [[ $prev == --run-dir ]]; then
+        COMPREPLY=($(compgen -d -- "$cur"))
+        return
+    fi
+    if [[ -z $cmd ]]; then
+        COMPREPLY=($(compgen -W "--run-dir --help inspect-state status ps start stop restart pause resume cancel rolling-restart swap run adopt detach enter set-property list snapshot restore reload analyze graph convert-unit calendar completions version" -- "$cur"))
+        return
+    fi
+    case $cmd in
+        start | stop | restart)
+            if ((COMP_CWORD == cmd_index + 2)); then
+                COMPREPLY=($(compgen -W "--dry-run" -- "$cur"))
+                return
+            fi
+            ;;
+        set-property)
+            if ((COMP_CWORD == cmd_index + 2)); then
+                compopt -o nospace 2>/dev/null
+                COMPREPLY=($(compgen -W "cpu.max=" -- "$cur"))
+                return
+            fi
+            ;;
+    esac
+    # other commands take at most one completable argument
+    ((COMP_CWORD == cmd_index + 1)) || return
+    case $cmd in
+        status) COMPREPLY=($(compgen -W "--no-color" -- "$cur")) ;;
+        graph) COMPREPLY=($(compgen -W "--json" -- "$cur")) ;;
+        restore) COMPREPLY=($(compgen -W "--exact" -- "$cur")) ;;
+        version) COMPREPLY=($(compgen -W "--verbose" -- "$cur")) ;;
+        calendar) COMPREPLY=($(compgen -W "hourly daily weekly monthly yearly" -- "$cur")) ;;
+        restart) COMPREPLY=($(compgen -W "--outdated $(svloppctl "${run_dir[@]}" list 2>/dev/null)" -- "$cur")) ;;
+        ps | start | stop | pause | resume | cancel | rolling-restart | swap | detach | enter | set-property) COMPREPLY=($(compgen -W "$(svloppctl "${run_dir[@]}" list 2>/dev/null)" -- "$cur")) ;;
+        inspect-state | convert-unit) COMPREPLY=($(compgen -f -- "$cur")) ;;
+        completions) COMPREPLY=($(compgen -W "bash zsh fish" -- "$cur")) ;;
+    esac
+}
+
+complete -F _svloppctl svloppctl
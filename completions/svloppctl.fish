@@ -0,0 +1,55 @@
+# fish completion for svloppctl
+
+function __svloppctl_services
+    set -l tokens (commandline -opc)
+    set -l run_dir
+    if set -l i (contains -i -- --run-dir $tokens)
+        set run_dir --run-dir $tokens[(math $i + 1)]
+    end
+    svloppctl $run_dir list 2>/dev/null
+end
+
+set -l commands inspect-state status ps start stop restart pause resume cancel rolling-restart swap run adopt detach enter set-property list snapshot restore reload analyze graph convert-unit calendar completions version
+
+complete -c svloppctl -f
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -l run-dir -r -a '(__fish_complete_directories)' -d 'runtime directory'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -l help -d 'print usage'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a inspect-state -d 'print a state snapshot'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a status -d 'print the services in a table'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a ps -d 'print the process tree of a service'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a start -d 'start a service'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a stop -d 'stop a service'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a restart -d 'restart a service'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a pause -d 'freeze the processes of a service'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a resume -d 'thaw the processes of a paused service'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a cancel -d 'cancel the queued operation of a service, or its stop'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a rolling-restart -d 'restart the instances of a service one at a time'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a swap -d 'replace a service with a new instance of it'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a run -d 'start a transient service'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a adopt -d 'supervise a running process as a service'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a detach -d 'stop supervising a service process, left running'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a enter -d 'run a shell in the context of a service'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a set-property -d 'set a property of a service at runtime'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a list -d 'list the services'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a snapshot -d 'save the set of running services'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a restore -d 'start the saved set of running services'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a reload -d 'reload the configuration'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a analyze -d 'print how long services took to start'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a graph -d 'print the service graph'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a convert-unit -d 'convert a systemd service unit to svlopp config'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a calendar -d 'print the next times a calendar expression triggers at'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a completions -d 'print a shell completion script'
+complete -c svloppctl -n "not __fish_seen_subcommand_from $commands" -a version -d 'print the version'
+complete -c svloppctl -n "__fish_seen_subcommand_from status" -l no-color -d 'do not color states'
+complete -c svloppctl -n "__fish_seen_subcommand_from graph" -l json -d 'print JSON instead of DOT'
+complete -c svloppctl -n "__fish_seen_subcommand_from restore" -l exact -d 'stop the services not in the set'
+complete -c svloppctl -n "__fish_seen_subcommand_from version" -l verbose -d 'print the kernel features svlopp uses'
+complete -c svloppctl -n "__fish_seen_subcommand_from ps start stop restart pause resume cancel rolling-restart swap detach enter set-property" -a '(__svloppctl_services)'
+complete -c svloppctl -n "__fish_seen_subcommand_from set-property" -a 'cpu.max=' -d 'CPU time in percent of one CPU, or max'
+complete -c svloppctl -n "__fish_seen_subcommand_from start stop restart" -l dry-run -d 'print what would be started and stopped'
+complete -c svloppctl -n "__fish_seen_subcommand_from restart" -l outdated -d 'restart the services running an outdated config'
+complete -c svloppctl -n "__fish_seen_subcommand_from inspect-state convert-unit" -F
+complete -c svloppctl -n "__fish_seen_subcommand_from completions" -a 'bash zsh fish'
+complete -c svloppctl -n "__fish_seen_subcommand_from calendar" -a 'hourly daily weekly monthly yearly'
+complete -c svloppctl -n "__fish_seen_subcommand_from calendar" -l count -d 'number of trigger times'
+complete -c svloppctl -n "__fish_seen_subcommand_from calendar" -l from -d 'seconds since the epoch to start from'
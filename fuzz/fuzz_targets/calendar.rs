@@ -0,0 +1,24 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Calendar expressions, whose trigger times must always go forward.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use svlopp::calendar::CalendarSpec;
+
+fuzz_target!(|expr: &str| {
+    let Ok(spec) = CalendarSpec::parse(expr) else {
+        return;
+    };
+    let mut time = 0;
+    for _ in 0..4 {
+        match spec.next_after(time) {
+            Some(next) if next > time => time = next,
+            Some(next) => panic!("{:?}: {} after {}", expr, next, time),
+            None => break,
+        }
+    }
+});
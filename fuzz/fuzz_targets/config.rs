@@ -0,0 +1,123 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Loading of the config file, from the TOML document to the validated
+//! services, which must fail with an error rather than panic. The checks
+//! that depend on the host, reading files, are left out.
+//!
+//! The config is loaded by the supervisor binary, whose modules are built
+//! here as they are in `main.rs`, without the optional features.
+
+#![no_main]
+#![allow(dead_code)]
+
+use std::path::Path;
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/adopt.rs"]
+mod adopt;
+#[path = "../../src/alerts.rs"]
+mod alerts;
+#[path = "../../src/arena.rs"]
+mod arena;
+#[path = "../../src/cgroup.rs"]
+mod cgroup;
+#[path = "../../src/cli.rs"]
+mod cli;
+#[path = "../../src/control.rs"]
+mod control;
+#[path = "../../src/diagnostics.rs"]
+mod diagnostics;
+#[path = "../../src/envfile.rs"]
+mod envfile;
+#[path = "../../src/graph.rs"]
+mod graph;
+#[path = "../../src/hooks.rs"]
+mod hooks;
+#[path = "../../src/init.rs"]
+mod init;
+#[path = "../../src/jobs.rs"]
+mod jobs;
+#[path = "../../src/keyring.rs"]
+mod keyring;
+#[path = "../../src/lint.rs"]
+mod lint;
+#[path = "../../src/logging.rs"]
+mod logging;
+#[path = "../../src/logpump.rs"]
+mod logpump;
+#[path = "../../src/logrotate.rs"]
+mod logrotate;
+#[path = "../../src/mounts.rs"]
+mod mounts;
+#[path = "../../src/netstats.rs"]
+mod netstats;
+#[path = "../../src/notify.rs"]
+mod notify;
+#[path = "../../src/oom.rs"]
+mod oom;
+#[path = "../../src/orphans.rs"]
+mod orphans;
+#[path = "../../src/platform.rs"]
+mod platform;
+#[path = "../../src/ports.rs"]
+mod ports;
+#[path = "../../src/pressure.rs"]
+mod pressure;
+#[path = "../../src/procfs.rs"]
+mod procfs;
+#[path = "../../src/profile.rs"]
+mod profile;
+#[path = "../../src/properties.rs"]
+mod properties;
+#[path = "../../src/reload.rs"]
+mod reload;
+#[path = "../../src/resources.rs"]
+mod resources;
+#[path = "../../src/restarts.rs"]
+mod restarts;
+#[path = "../../src/rollout.rs"]
+mod rollout;
+#[path = "../../src/runset.rs"]
+mod runset;
+#[path = "../../src/scandir.rs"]
+mod scandir;
+#[path = "../../src/secrets.rs"]
+mod secrets;
+#[path = "../../src/service.rs"]
+mod service;
+#[path = "../../src/signalfd.rs"]
+mod signalfd;
+#[path = "../../src/slab.rs"]
+mod slab;
+#[path = "../../src/spawn.rs"]
+mod spawn;
+#[path = "../../src/spawner.rs"]
+mod spawner;
+#[path = "../../src/status.rs"]
+mod status;
+#[path = "../../src/swap.rs"]
+mod swap;
+#[path = "../../src/timer.rs"]
+mod timer;
+#[path = "../../src/timerfd.rs"]
+mod timerfd;
+#[path = "../../src/transient.rs"]
+mod transient;
+#[path = "../../src/userns.rs"]
+mod userns;
+#[path = "../../src/utils.rs"]
+mod utils;
+#[path = "../../src/watchdog.rs"]
+mod watchdog;
+#[path = "../../src/webhooks.rs"]
+mod webhooks;
+
+use service::ServiceConfigData;
+
+fuzz_target!(|content: &str| {
+    let _ = ServiceConfigData::check(Path::new("fuzz.toml"), content, false);
+    let _ = ServiceConfigData::check(Path::new("fuzz.toml"), content, true);
+});
@@ -0,0 +1,26 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Validation of a control frame into a command.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use svlopp::{
+    opcode,
+    wire::{ControlCommand, WIRE_COMMAND_SIZE, WireControlCommand},
+};
+
+fuzz_target!(|frame: [u8; WIRE_COMMAND_SIZE]| {
+    let wire = WireControlCommand::decode(&frame);
+    assert_eq!(wire.encode(), frame);
+    if let Ok(command) = ControlCommand::try_from(wire) {
+        // a valid command is what the frame it comes from says
+        let dry_run = if command.dry_run { opcode::DRY_RUN } else { 0 };
+        assert_eq!(
+            WireControlCommand::new(command.op as u8 | dry_run, command.service_id),
+            wire
+        );
+    }
+});
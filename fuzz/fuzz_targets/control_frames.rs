@@ -0,0 +1,29 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Splitting of what is read from the control FIFO into commands, as the
+//! supervisor does on each read.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use svlopp::wire::{ControlCommand, WIRE_COMMAND_SIZE, split_frames};
+
+fuzz_target!(|data: &[u8]| {
+    let frames = split_frames(data);
+    assert_eq!(frames.len(), data.len().div_ceil(WIRE_COMMAND_SIZE));
+    let mut partial = false;
+    for frame in frames {
+        assert!(!partial, "partial frame before the end of the read");
+        match frame {
+            Ok(wire) => {
+                let _ = ControlCommand::try_from(wire);
+            }
+            Err(len) => {
+                assert!(len > 0 && len < WIRE_COMMAND_SIZE);
+                partial = true;
+            }
+        }
+    }
+});
@@ -0,0 +1,22 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! TZif files and the POSIX TZ rules of their footer.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use svlopp::tz::TimeZone;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(tz) = TimeZone::from_tzif("fuzz", data) else {
+        return;
+    };
+    for time in [i32::MIN as i64, 0, 1_800_000_000, 4_000_000_000] {
+        tz.format(time);
+        if let Some(utc) = tz.to_utc(time) {
+            assert_eq!(utc + tz.offset(utc) as i64, time);
+        }
+    }
+});
@@ -0,0 +1,19 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Conversion of systemd units to svlopp config, which must always be a
+//! valid config file.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use svlopp::unit::convert_unit;
+
+fuzz_target!(|content: &str| {
+    if let Ok(unit) = convert_unit("fuzz", content)
+        && let Err(e) = unit.config.parse::<toml::Table>()
+    {
+        panic!("invalid config {:?}: {}", unit.config, e);
+    }
+});
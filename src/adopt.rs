@@ -0,0 +1,150 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Adoption of running processes svlopp didn't start.
+//!
+//! The `adopt` control operation attaches a running process, e.g. a daemon
+//! started before svlopp took over the host, to the service whose name
+//! `svloppctl` writes to `adopt.d/<pid>` in the run directory: a configured
+//! service, which has to be stopped, or else a transient service running
+//! the command line of the process, see `transient`. The process isn't
+//! restarted. svlopp opens a pidfd to it and supervises it like the ones it
+//! starts, but not being its parent it can't reap it: it finds out that the
+//! process exited when the pidfd becomes readable, on each timerfd tick,
+//! and reports the `exited` stop reason, the exit status being unknown.
+//! From then on, the service is started by svlopp as any other.
+
+use std::{io, path::Path, time::Instant};
+
+use rustix::event::{PollFd, PollFlags, Timespec, poll};
+use rustix::process::{Pid, getpid};
+
+use crate::logging::LogLevel;
+use crate::procfs::ProcStat;
+use crate::service::{
+    Service, ServiceConfig, ServiceIdGen, ServiceRegistry, ServiceState, open_pidfd,
+};
+use crate::svlogg;
+
+/// Adopt the process `pid` as the service named in the request, read
+/// from `dir`
+pub(crate) fn adopt(
+    registry: &mut ServiceRegistry,
+    id_gen: &mut ServiceIdGen,
+    pid: u64,
+    dir: &Path,
+) -> io::Result<()> {
+    let path = dir.join(pid.to_string());
+    let request = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+    let request = request?;
+    let name = request.trim();
+    let pid = i32::try_from(pid)
+        .ok()
+        .and_then(Pid::from_raw)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid pid"))?;
+    // init and svlopp itself can't be stopped as services are
+    if pid == Pid::INIT || pid == getpid() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("process {} can't be adopted", pid),
+        ));
+    }
+    if registry.get_by_pid(pid).is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("process {} is already supervised", pid),
+        ));
+    }
+    let stat = ProcStat::read(pid.as_raw_nonzero().get())?;
+    if stat.is_kernel_thread() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("process {} is a kernel thread", pid),
+        ));
+    }
+    let svc_id = match registry.get_by_name(name) {
+        Some(svc)
+            if svc.is_stopped()
+                && svc.pending_action.is_none()
+                && svc.finish_pid.is_none()
+                && svc.scheduled.is_none() =>
+        {
+            svc.id
+        }
+        Some(svc) => {
+            return Err(io::Error::new(
+                io::ErrorKind::ResourceBusy,
+                format!("service '{}' isn't stopped", svc.name),
+            ));
+        }
+        None => {
+            let svc = transient_service(id_gen, pid, name)?;
+            let svc_id = svc.id;
+            svlogg!(LogLevel::Info, "adding transient service '{}'", svc.name);
+            registry.insert_service(svc);
+            svc_id
+        }
+    };
+    let svc = registry.service_mut(svc_id).expect("service exists");
+    svc.state = ServiceState::Running(pid);
+    svc.start_time = Some(stat.start_time);
+    svc.started_at = Some(Instant::now());
+    svc.ready_notified = false;
+    svc.pidfd = open_pidfd(pid, &svc.name);
+    svc.adopted = true;
+    svlogg!(
+        LogLevel::Info,
+        "adopted process {} ({}) as service '{}'",
+        pid,
+        stat.comm,
+        svc.name
+    );
+    registry.register_pid(pid, svc_id);
+    Ok(())
+}
+
+/// A transient service `name` running the command line of `pid`
+fn transient_service(id_gen: &mut ServiceIdGen, pid: Pid, name: &str) -> io::Result<Service> {
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid))?;
+    let mut argv = cmdline
+        .split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned());
+    // e.g. a kernel thread
+    let command = argv.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("process {} has no command line", pid),
+        )
+    })?;
+    let svc_id = id_gen
+        .nextval()
+        .ok_or_else(|| io::Error::other("service id overflow"))?;
+    let mut config = ServiceConfig::new(command);
+    config.args = argv.collect();
+    let mut svc = Service::new(svc_id, name.to_owned(), config)?;
+    svc.transient = true;
+    Ok(svc)
+}
+
+/// Whether the adopted process of `svc` exited: its pidfd is readable or,
+/// without one, its pid is gone or was reused
+pub(crate) fn has_exited(svc: &Service) -> bool {
+    if let Some(pidfd) = &svc.pidfd {
+        let mut fds = [PollFd::new(pidfd, PollFlags::IN)];
+        let timeout = Timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        return matches!(poll(&mut fds, Some(&timeout)), Ok(n) if n > 0);
+    }
+    let Some(pid) = svc.pid() else {
+        return false;
+    };
+    match ProcStat::read(pid.as_raw_nonzero().get()) {
+        Ok(stat) => svc.start_time.is_some_and(|t| t != stat.start_time),
+        Err(e) => e.kind() == io::ErrorKind::NotFound,
+    }
+}
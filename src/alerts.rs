@@ -0,0 +1,295 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Commands run on service state changes, e.g. to page someone.
+//!
+//! Alert commands run in the background like `finish` commands: they are
+//! reaped by `handle_sigchld`, which hands their pids back here.
+
+use std::{
+    collections::HashMap, collections::HashSet, fmt, io, io::Write, process::Stdio, sync::Arc,
+};
+
+use rustix::process::Pid;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::hooks::{Hook, hook_command};
+use crate::logging::LogLevel;
+use crate::service::{ExitReason, Service, ServiceRegistry, ServiceState, ServiceStopReason};
+use crate::signalfd::SigSet;
+use crate::svlogg;
+use crate::utils::timestamp;
+
+/// Maximum number of alert commands running at once
+const MAX_RUNNING_ALERTS: usize = 16;
+
+const DEFAULT_FAILURE_WINDOW_SECS: u64 = 600;
+
+/// Service states as matched by alert patterns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AlertState {
+    Running,
+    Stopping,
+    Paused,
+    /// Stopped without failing, or never started
+    Stopped,
+    /// Stopped with a failure
+    Failed,
+}
+
+impl AlertState {
+    fn of(state: &ServiceState) -> Self {
+        match state {
+            ServiceState::Running(_) => Self::Running,
+            ServiceState::Stopping(..) => Self::Stopping,
+            ServiceState::Paused(_) => Self::Paused,
+            ServiceState::Stopped(reason) if reason.is_failure() => Self::Failed,
+            ServiceState::Stopped(_) => Self::Stopped,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::Stopping => "stopping",
+            Self::Paused => "paused",
+            Self::Stopped => "stopped",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for AlertState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A `<from> -> <to>` pattern of state changes, where `*` matches any
+/// state, e.g. `* -> failed`
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub(crate) struct Transition {
+    from: Option<AlertState>,
+    to: Option<AlertState>,
+}
+
+impl Transition {
+    fn matches(&self, from: AlertState, to: AlertState) -> bool {
+        self.from.is_none_or(|s| s == from) && self.to.is_none_or(|s| s == to)
+    }
+}
+
+impl TryFrom<String> for Transition {
+    type Error = String;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        let parse = |s: &str| match s.trim().to_ascii_lowercase().as_str() {
+            "*" => Ok(None),
+            "running" => Ok(Some(AlertState::Running)),
+            "stopping" => Ok(Some(AlertState::Stopping)),
+            "paused" => Ok(Some(AlertState::Paused)),
+            "stopped" => Ok(Some(AlertState::Stopped)),
+            "failed" => Ok(Some(AlertState::Failed)),
+            other => Err(format!(
+                "unknown state '{}' in alert pattern '{}', expected running, stopping, paused, stopped, failed or *",
+                other, pattern
+            )),
+        };
+        let (from, to) = pattern
+            .split_once("->")
+            .ok_or_else(|| format!("alert pattern '{}' is not '<from> -> <to>'", pattern))?;
+        Ok(Self {
+            from: parse(from)?,
+            to: parse(to)?,
+        })
+    }
+}
+
+fn default_failure_window_secs() -> u64 {
+    DEFAULT_FAILURE_WINDOW_SECS
+}
+
+/// A command run when a service state change matches `on`
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct Alert {
+    pub(crate) on: Transition,
+    /// Only match changes of these services, all of them by default
+    #[serde(default)]
+    pub(crate) services: Option<Vec<String>>,
+    /// Only run once the service failed at least this many times within
+    /// `failure_window_secs`
+    #[serde(default)]
+    pub(crate) min_failures: usize,
+    #[serde(default = "default_failure_window_secs")]
+    pub(crate) failure_window_secs: u64,
+    #[serde(flatten)]
+    pub(crate) hook: Hook,
+}
+
+impl Alert {
+    fn wants(&self, name: &str, from: AlertState, to: AlertState, failures: usize) -> bool {
+        self.on.matches(from, to)
+            && failures >= self.min_failures
+            && self
+                .services
+                .as_ref()
+                .is_none_or(|services| services.iter().any(|s| s == name))
+    }
+}
+
+/// Number of failures of `svc` in the last `window_secs`
+fn recent_failures(svc: &Service, now_ms: u64, window_secs: u64) -> usize {
+    let since = now_ms.saturating_sub(window_secs.saturating_mul(1000));
+    svc.failures.iter().filter(|&&t| t >= since).count()
+}
+
+/// Runs the configured alert commands on service state changes
+#[derive(Debug, Default)]
+pub(crate) struct Alerts {
+    alerts: Vec<Alert>,
+    /// Signal mask restored in alert commands
+    sigset: Option<SigSet>,
+    /// Last state of each service, to tell which changed
+    states: HashMap<Arc<str>, AlertState>,
+    running: HashSet<Pid>,
+}
+
+impl Alerts {
+    /// Replace the configured alerts, e.g. on reload
+    pub(crate) fn configure(&mut self, alerts: Vec<Alert>, sigset: &SigSet) {
+        self.alerts = alerts;
+        self.sigset = Some(sigset.clone());
+    }
+
+    /// Whether `pid` is a running alert command
+    pub(crate) fn owns(&self, pid: Pid) -> bool {
+        self.running.contains(&pid)
+    }
+
+    /// Forget the alert command `pid`, once reaped
+    pub(crate) fn reaped(&mut self, pid: Pid, exit_reason: ExitReason) {
+        self.running.remove(&pid);
+        svlogg!(
+            LogLevel::Debug,
+            "alert command {} exited: {}",
+            pid,
+            exit_reason
+        );
+    }
+
+    /// Run the alerts matching the changes since the last call
+    pub(crate) fn notify(&mut self, registry: &ServiceRegistry) {
+        let (secs, nsecs) = timestamp();
+        let now_ms = (secs * 1000 + nsecs / 1_000_000) as u64;
+        for svc in registry.services() {
+            let to = AlertState::of(&svc.state);
+            let from = self
+                .states
+                .insert(Arc::clone(&svc.name), to)
+                .unwrap_or(AlertState::Stopped);
+            if from == to {
+                continue;
+            }
+            for alert in &self.alerts {
+                let failures = recent_failures(svc, now_ms, alert.failure_window_secs);
+                if !alert.wants(&svc.name, from, to, failures) {
+                    continue;
+                }
+                if self.running.len() >= MAX_RUNNING_ALERTS {
+                    svlogg!(
+                        LogLevel::Warn,
+                        "too many running alert commands, skipping '{}' for service '{}'",
+                        alert.hook.command,
+                        svc.name
+                    );
+                    continue;
+                }
+                let Some(sigset) = self.sigset.as_ref() else {
+                    continue;
+                };
+                match run_alert(alert, svc, from, to, failures, now_ms, sigset) {
+                    Ok(pid) => {
+                        self.running.insert(pid);
+                    }
+                    Err(e) => svlogg!(
+                        LogLevel::Error,
+                        "failed to run alert command '{}': {}",
+                        alert.hook.command,
+                        e
+                    ),
+                }
+            }
+        }
+        self.states
+            .retain(|name, _| registry.get_by_name(name).is_some());
+    }
+}
+
+/// Start the command of `alert` for the change of `svc` from `from` to
+/// `to`. The details are passed in the environment, and as JSON on stdin
+fn run_alert(
+    alert: &Alert,
+    svc: &Service,
+    from: AlertState,
+    to: AlertState,
+    failures: usize,
+    now_ms: u64,
+    sigset: &SigSet,
+) -> io::Result<Pid> {
+    let (pid, reason) = match svc.state {
+        ServiceState::Running(pid) | ServiceState::Paused(pid) => {
+            (Some(pid.as_raw_nonzero().get()), None)
+        }
+        ServiceState::Stopped(ServiceStopReason::NeverStarted) | ServiceState::Stopping(..) => {
+            (None, None)
+        }
+        ServiceState::Stopped(reason) => (None, Some(reason.to_string())),
+    };
+    svlogg!(
+        LogLevel::Info,
+        "service '{}' went {} -> {}, running alert '{}'",
+        svc.name,
+        from,
+        to,
+        alert.hook.command
+    );
+    let mut command = hook_command("alert", &alert.hook, sigset);
+    command
+        .env("SVLOPP_SERVICE", &*svc.name)
+        .env("SVLOPP_ALERT_FROM", from.as_str())
+        .env("SVLOPP_ALERT_TO", to.as_str())
+        .env("SVLOPP_ALERT_REASON", reason.as_deref().unwrap_or(""))
+        .env("SVLOPP_ALERT_FAILURES", failures.to_string())
+        .stdin(Stdio::piped());
+    let mut child = command.spawn()?;
+    // the child is reaped by `handle_sigchld`, not through `child`
+    let child_pid =
+        Pid::from_raw(child.id() as i32).ok_or_else(|| io::Error::other("invalid pid"))?;
+    let details = json!({
+        "service": svc.name,
+        "id": svc.id,
+        "from": from.as_str(),
+        "to": to.as_str(),
+        "pid": pid,
+        "reason": reason,
+        "error": svc.spawn_failure(),
+        "failures": failures,
+        "timestamp_ms": now_ms,
+    });
+    // way smaller than a pipe buffer, this can't block
+    if let Some(mut stdin) = child.stdin.take()
+        && let Err(e) = writeln!(stdin, "{}", details)
+        && e.kind() != io::ErrorKind::BrokenPipe
+    {
+        svlogg!(
+            LogLevel::Warn,
+            "failed to write alert details to '{}': {}",
+            alert.hook.command,
+            e
+        );
+    }
+    Ok(child_pid)
+}
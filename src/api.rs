@@ -0,0 +1,749 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! HTTP management API.
+//!
+//! A small HTTP/1.1 server answering with JSON, listening on a unix
+//! socket. Like the D-Bus interface it is driven by the main loop: the
+//! listener and the clients are registered with epoll, and actions are
+//! dispatched to `apply_control_op` as control FIFO commands are.
+//!
+//! Connections are closed after each response, except for the
+//! `/v1/events` server-sent events stream, which stays open and receives
+//! an event each time the state of a service changes.
+
+#[cfg(feature = "tls")]
+use std::net::SocketAddr;
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io,
+    os::fd::{AsFd, BorrowedFd, OwnedFd},
+    path::Path,
+    sync::Arc,
+};
+
+use rustix::event::epoll;
+use rustix::fs::{Mode, chmod};
+use rustix::io::{Errno, read};
+use rustix::net::{
+    AddressFamily, SendFlags, SocketAddrUnix, SocketFlags, SocketType, accept_with, bind, listen,
+    send, socket_with,
+};
+use serde_json::{Value, json};
+
+use crate::arena::Arena;
+use crate::control::ControlOp;
+use crate::logging::LogLevel;
+use crate::service::{
+    Service, ServicePendingAction, ServiceRegistry, ServiceState, SpawnContext, apply_control_op,
+    plan_control_op,
+};
+use crate::svlogg;
+#[cfg(feature = "tls")]
+use crate::tls;
+use crate::utils::read_log_tail;
+
+/// Tag of the epoll ids of API clients, the lower bits hold the client id
+pub(crate) const API_ID_TAG: u64 = 1 << 62;
+
+/// Maximum number of connected clients, further connections are closed
+/// right away
+const MAX_CLIENTS: usize = 64;
+
+/// Maximum size of a request, headers and body
+const MAX_REQUEST_LEN: usize = 8 * 1024;
+
+/// Events stream clients are dropped when that much data is waiting to be
+/// sent to them
+const MAX_PENDING_EVENTS_LEN: usize = 64 * 1024;
+
+/// Number of log lines returned when the request doesn't say
+const DEFAULT_LOG_LINES: usize = 100;
+const MAX_LOG_LINES: usize = 10_000;
+
+/// A parsed request
+#[derive(Debug)]
+struct Request<'a> {
+    method: &'a str,
+    path: &'a str,
+    query: &'a str,
+    /// Value of the `Authorization` header
+    authorization: Option<&'a str>,
+}
+
+/// Parse the request at the start of `buf`. Returns `None` if it is not
+/// complete yet
+fn parse_request(buf: &[u8]) -> Option<Result<Request<'_>, &'static str>> {
+    let head_len = buf.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let Ok(head) = std::str::from_utf8(&buf[..head_len]) else {
+        return Some(Err("invalid request encoding"));
+    };
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(target), Some(version), None) = (
+        request_line.next(),
+        request_line.next(),
+        request_line.next(),
+        request_line.next(),
+    ) else {
+        return Some(Err("invalid request line"));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Some(Err("unsupported HTTP version"));
+    }
+    let mut content_len = 0;
+    let mut authorization = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            return Some(Err("invalid header"));
+        };
+        if name.eq_ignore_ascii_case("content-length") {
+            match value.trim().parse() {
+                Ok(len) => content_len = len,
+                Err(_) => return Some(Err("invalid content length")),
+            }
+        } else if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.trim());
+        }
+    }
+    // the body is unused, but waited for so that the connection is not
+    // closed with unread data
+    if buf.len() < head_len + 4 + content_len {
+        return None;
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    Some(Ok(Request {
+        method,
+        path,
+        query,
+        authorization,
+    }))
+}
+
+/// Decode the `%XX` escapes of a path segment
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// The value of `key` in the query string `query`
+fn query_param<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find_map(|(k, v)| (k == key).then_some(v))
+}
+
+fn status_text(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Content Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+/// Queue a complete response to `out`
+fn write_response(out: &mut Vec<u8>, status: u16, content_type: &str, body: &[u8]) {
+    let challenge = match status {
+        401 => "WWW-Authenticate: Bearer\r\n",
+        _ => "",
+    };
+    out.extend_from_slice(
+        format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+            status,
+            status_text(status),
+            content_type,
+            body.len(),
+            challenge
+        )
+        .as_bytes(),
+    );
+    out.extend_from_slice(body);
+}
+
+fn write_json(out: &mut Vec<u8>, status: u16, value: &Value) {
+    write_response(
+        out,
+        status,
+        "application/json",
+        value.to_string().as_bytes(),
+    );
+}
+
+fn write_error(out: &mut Vec<u8>, status: u16, message: &str) {
+    write_json(out, status, &json!({ "error": message }));
+}
+
+/// The JSON representation of `svc`, as listed and sent in events
+fn service_json(svc: &Service) -> Value {
+    let (state, pid, reason) = match svc.state {
+        ServiceState::Running(pid) => ("running", Some(pid.as_raw_nonzero().get()), None),
+        ServiceState::Stopping(pid, _) => ("stopping", Some(pid.as_raw_nonzero().get()), None),
+        ServiceState::Paused(pid) => ("paused", Some(pid.as_raw_nonzero().get()), None),
+        ServiceState::Stopped(reason) => ("stopped", None, Some(reason.to_string())),
+    };
+    json!({
+        "name": svc.name,
+        "id": svc.id,
+        "state": state,
+        "pid": pid,
+        "reason": reason,
+        "error": svc.spawn_failure(),
+        "job": match svc.pending_action {
+            ServicePendingAction::None => svc.scheduled.map(|op| op.to_string()),
+            ServicePendingAction::Restart => Some("restart".to_owned()),
+            ServicePendingAction::Remove => Some("remove".to_owned()),
+        },
+    })
+}
+
+/// A listening socket of the API
+#[derive(Debug)]
+struct Listener {
+    fd: OwnedFd,
+    /// TLS configuration of remote clients, which must also present the
+    /// API token when there is one
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<rustls::ServerConfig>>,
+}
+
+struct Client {
+    fd: OwnedFd,
+    rbuf: Vec<u8>,
+    wbuf: Vec<u8>,
+    /// Whether requests must carry the API token
+    needs_token: bool,
+    /// Whether the response was queued, the connection is closed once it
+    /// is sent
+    responded: bool,
+    /// Whether the client is subscribed to `/v1/events`
+    events: bool,
+    /// Whether the client is registered with epoll to be told when it can
+    /// be written to
+    writing: bool,
+    #[cfg(feature = "tls")]
+    tls: Option<Box<rustls::ServerConnection>>,
+}
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("fd", &self.fd)
+            .field("events", &self.events)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Client {
+    /// Read what the client sent. Returns whether it may still send data
+    fn receive(&mut self) -> io::Result<bool> {
+        #[cfg(feature = "tls")]
+        if let Some(conn) = self.tls.as_mut() {
+            return tls::receive(conn, self.fd.as_fd(), &mut self.rbuf, self.events);
+        }
+        let mut chunk = [0u8; 4096];
+        loop {
+            match read(&self.fd, &mut chunk) {
+                Ok(0) => return Ok(false),
+                // events clients have nothing more to say
+                Ok(_) if self.events => {}
+                Ok(n) => self.rbuf.extend_from_slice(&chunk[..n]),
+                Err(Errno::AGAIN) => return Ok(true),
+                Err(Errno::INTR) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Send as much of the pending data as possible. Returns whether the
+    /// connection is still usable
+    fn send_pending(&mut self) -> bool {
+        #[cfg(feature = "tls")]
+        if let Some(conn) = self.tls.as_mut() {
+            return tls::send_pending(conn, self.fd.as_fd(), &mut self.wbuf).is_ok();
+        }
+        while !self.wbuf.is_empty() {
+            match send(
+                &self.fd,
+                &self.wbuf,
+                SendFlags::DONTWAIT | SendFlags::NOSIGNAL,
+            ) {
+                Ok(n) => {
+                    self.wbuf.drain(..n);
+                }
+                Err(Errno::INTR) => {}
+                Err(Errno::AGAIN) => break,
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+
+    /// Whether data is waiting to be sent
+    fn has_pending(&self) -> bool {
+        #[cfg(feature = "tls")]
+        if let Some(conn) = self.tls.as_ref()
+            && conn.wants_write()
+        {
+            return true;
+        }
+        !self.wbuf.is_empty()
+    }
+
+    /// Mark the response as queued, closing the TLS session after it
+    fn finish_response(&mut self) {
+        self.responded = true;
+        self.rbuf = Vec::new();
+        #[cfg(feature = "tls")]
+        if let Some(conn) = self.tls.as_mut() {
+            tls::send_pending(conn, self.fd.as_fd(), &mut self.wbuf).ok();
+            conn.send_close_notify();
+        }
+    }
+}
+
+/// Whether the `Authorization` header `header` holds `token`, compared in
+/// constant time
+fn token_matches(token: &str, header: Option<&str>) -> bool {
+    let Some(given) = header.and_then(|h| h.strip_prefix("Bearer ")) else {
+        return false;
+    };
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Read the API token from the file at `path`, ignoring surrounding
+/// whitespace
+#[cfg(feature = "tls")]
+pub(crate) fn read_token(path: &Path) -> io::Result<String> {
+    let token = std::fs::read_to_string(path)?.trim().to_owned();
+    if token.is_empty() {
+        return Err(io::Error::other(format!(
+            "API token file '{}' is empty",
+            path.display()
+        )));
+    }
+    Ok(token)
+}
+
+/// The HTTP management API server.
+///
+/// The API is versioned under `/v1`:
+/// - `GET /v1/services`: list the services
+/// - `GET /v1/services/<name>`: show a service
+/// - `POST /v1/services/<name>/{start,stop,restart}`: act on a service
+/// - `GET /v1/services/<name>/logs?lines=N`: the last lines of the log file
+///   of a service
+/// - `GET /v1/events`: server-sent events, a `state` event with the
+///   service as data for each service on connection and then on each
+///   state change
+///
+/// Besides the unix socket, the API can listen on a TCP address for
+/// remote clients, over TLS and optionally authenticated by a token
+#[derive(Debug)]
+pub(crate) struct ApiServer {
+    listeners: Vec<Listener>,
+    /// Token remote clients must present as `Authorization: Bearer`
+    token: Option<String>,
+    clients: HashMap<u64, Client>,
+    next_client_id: u64,
+    /// Last state of each service, to tell which changed
+    states: HashMap<Arc<str>, String>,
+}
+
+impl ApiServer {
+    /// Bind and listen on a unix socket at `path`, which only the owner can
+    /// connect to
+    pub(crate) fn bind(path: &Path) -> io::Result<Self> {
+        let listener = socket_with(
+            AddressFamily::UNIX,
+            SocketType::STREAM,
+            SocketFlags::CLOEXEC | SocketFlags::NONBLOCK,
+            None,
+        )?;
+        bind(&listener, &SocketAddrUnix::new(path)?)?;
+        // before `listen`, for no client to connect while the socket has
+        // the mode the umask left it with
+        chmod(path, Mode::from_bits_truncate(0o600))?;
+        listen(&listener, MAX_CLIENTS as i32)?;
+        Ok(Self {
+            listeners: vec![Listener {
+                fd: listener,
+                #[cfg(feature = "tls")]
+                tls: None,
+            }],
+            token: None,
+            clients: HashMap::new(),
+            next_client_id: 0,
+            states: HashMap::new(),
+        })
+    }
+
+    /// Also listen on the TCP address `addr`, for TLS clients presenting
+    /// `token` if any
+    #[cfg(feature = "tls")]
+    pub(crate) fn listen_tcp(
+        &mut self,
+        addr: SocketAddr,
+        tls: Arc<rustls::ServerConfig>,
+        token: Option<String>,
+    ) -> io::Result<()> {
+        let family = match addr {
+            SocketAddr::V4(_) => AddressFamily::INET,
+            SocketAddr::V6(_) => AddressFamily::INET6,
+        };
+        let listener = socket_with(
+            family,
+            SocketType::STREAM,
+            SocketFlags::CLOEXEC | SocketFlags::NONBLOCK,
+            None,
+        )?;
+        rustix::net::sockopt::set_socket_reuseaddr(&listener, true)?;
+        bind(&listener, &addr)?;
+        listen(&listener, MAX_CLIENTS as i32)?;
+        self.listeners.push(Listener {
+            fd: listener,
+            tls: Some(tls),
+        });
+        self.token = token;
+        Ok(())
+    }
+
+    /// The listening sockets, to be registered with epoll
+    pub(crate) fn listeners(&self) -> impl Iterator<Item = BorrowedFd<'_>> {
+        self.listeners.iter().map(|listener| listener.fd.as_fd())
+    }
+
+    /// Accept all the pending connections, registering them with `epfd`
+    pub(crate) fn accept_pending(&mut self, epfd: BorrowedFd<'_>) -> io::Result<()> {
+        for listener in &self.listeners {
+            loop {
+                let fd =
+                    match accept_with(&listener.fd, SocketFlags::CLOEXEC | SocketFlags::NONBLOCK) {
+                        Ok(fd) => fd,
+                        Err(Errno::AGAIN) => break,
+                        Err(Errno::CONNABORTED | Errno::INTR) => continue,
+                        Err(e) => return Err(e.into()),
+                    };
+                if self.clients.len() >= MAX_CLIENTS {
+                    svlogg!(LogLevel::Warn, "too many API clients, dropping connection");
+                    continue;
+                }
+                #[cfg(feature = "tls")]
+                let tls =
+                    match listener.tls.as_ref().map(|config| {
+                        rustls::ServerConnection::new(Arc::clone(config)).map(Box::new)
+                    }) {
+                        Some(Ok(conn)) => Some(conn),
+                        Some(Err(e)) => {
+                            svlogg!(LogLevel::Warn, "failed to set up API TLS session: {}", e);
+                            continue;
+                        }
+                        None => None,
+                    };
+                let id = API_ID_TAG | self.next_client_id;
+                self.next_client_id = (self.next_client_id + 1) & !API_ID_TAG;
+                epoll::add(
+                    epfd,
+                    &fd,
+                    epoll::EventData::new_u64(id),
+                    epoll::EventFlags::IN,
+                )?;
+                self.clients.insert(
+                    id,
+                    Client {
+                        fd,
+                        rbuf: Vec::new(),
+                        wbuf: Vec::new(),
+                        #[cfg(feature = "tls")]
+                        needs_token: tls.is_some() && self.token.is_some(),
+                        #[cfg(not(feature = "tls"))]
+                        needs_token: false,
+                        responded: false,
+                        events: false,
+                        writing: false,
+                        #[cfg(feature = "tls")]
+                        tls,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle an epoll event of the client `id`. Returns whether a
+    /// request changed the state of a service
+    pub(crate) fn handle(
+        &mut self,
+        id: u64,
+        registry: &mut ServiceRegistry,
+        ctx: &SpawnContext,
+        ps_dir: &Path,
+        arena: &Arena,
+    ) -> bool {
+        let Some(mut client) = self.clients.remove(&id) else {
+            return false;
+        };
+        let Ok(open) = client.receive() else {
+            return false;
+        };
+        let mut changed = false;
+        if !client.events && !client.responded {
+            match parse_request(&client.rbuf) {
+                Some(Ok(request))
+                    if client.needs_token
+                        && !token_matches(
+                            self.token.as_deref().unwrap_or_default(),
+                            request.authorization,
+                        ) =>
+                {
+                    write_error(&mut client.wbuf, 401, "invalid or missing API token")
+                }
+                Some(Ok(request)) => {
+                    changed = dispatch(
+                        &request,
+                        &mut client.wbuf,
+                        &mut client.events,
+                        registry,
+                        ctx,
+                        ps_dir,
+                        arena,
+                    );
+                }
+                Some(Err(message)) => write_error(&mut client.wbuf, 400, message),
+                None if client.rbuf.len() > MAX_REQUEST_LEN => {
+                    write_error(&mut client.wbuf, 413, "request too large")
+                }
+                None if !open => return false,
+                None => {}
+            }
+            if !client.wbuf.is_empty() && !client.events {
+                client.finish_response();
+            }
+        }
+        if !client.send_pending() || (client.events && !open) {
+            return changed;
+        }
+        // the connection is closed once the response is sent. A client may
+        // shut down its side once the request is sent, it still gets the
+        // response
+        let pending = client.has_pending();
+        if client.responded && !pending {
+            return changed;
+        }
+        // events are sent as they come and on each tick, other data as
+        // soon as the client can take it
+        let writing = pending && !client.events;
+        if writing != client.writing {
+            let flags = if writing {
+                epoll::EventFlags::IN | epoll::EventFlags::OUT
+            } else {
+                epoll::EventFlags::IN
+            };
+            if epoll::modify(ctx.epfd, &client.fd, epoll::EventData::new_u64(id), flags).is_err() {
+                return changed;
+            }
+            client.writing = writing;
+        }
+        self.clients.insert(id, client);
+        changed
+    }
+
+    /// Send a `state` event to the events clients for the services whose
+    /// state changed since the last call
+    pub(crate) fn notify(&mut self, registry: &ServiceRegistry, arena: &Arena) {
+        let mut events = Vec::new();
+        for svc in registry.services() {
+            let Ok(state) = arena.alloc_fmt(|w| write!(w, "{}", svc.state)) else {
+                continue;
+            };
+            if self.states.get(&svc.name).map(String::as_str) == Some(state) {
+                continue;
+            }
+            write_event(&mut events, svc);
+            self.states.insert(Arc::clone(&svc.name), state.to_owned());
+        }
+        self.states
+            .retain(|name, _| registry.get_by_name(name).is_some());
+        if events.is_empty() {
+            return;
+        }
+        self.clients.retain(|_, client| {
+            if !client.events {
+                return true;
+            }
+            if client.wbuf.len() + events.len() > MAX_PENDING_EVENTS_LEN {
+                svlogg!(LogLevel::Warn, "API events client too slow, dropping it");
+                return false;
+            }
+            client.wbuf.extend_from_slice(&events);
+            client.send_pending()
+        });
+    }
+
+    /// Send the events the clients could not take yet
+    pub(crate) fn send_events(&mut self) {
+        self.clients
+            .retain(|_, client| !client.events || client.send_pending());
+    }
+}
+
+/// Answer `request` to `out`, setting `events` if the client subscribed to
+/// events. Returns whether it changed the state of a service
+fn dispatch(
+    request: &Request<'_>,
+    out: &mut Vec<u8>,
+    events: &mut bool,
+    registry: &mut ServiceRegistry,
+    ctx: &SpawnContext,
+    ps_dir: &Path,
+    arena: &Arena,
+) -> bool {
+    let path = request.path.trim_end_matches('/');
+    let segments = arena.alloc_iter(path.matches('/').count() + 1, path.split('/'));
+    let (name, action) = match &*segments {
+        ["", "v1", "services"] => {
+            if request.method != "GET" {
+                write_error(out, 405, "method not allowed");
+                return false;
+            }
+            let services = arena.alloc_iter(registry.services().len(), registry.services());
+            services.sort_by_key(|svc| svc.id);
+            let list: Vec<Value> = services.iter().copied().map(service_json).collect();
+            write_json(out, 200, &Value::Array(list));
+            return false;
+        }
+        ["", "v1", "events"] => {
+            if request.method != "GET" {
+                write_error(out, 405, "method not allowed");
+                return false;
+            }
+            out.extend_from_slice(
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+                  Cache-Control: no-cache\r\n\r\n",
+            );
+            let services = arena.alloc_iter(registry.services().len(), registry.services());
+            services.sort_by_key(|svc| svc.id);
+            for svc in services.iter() {
+                write_event(out, svc);
+            }
+            *events = true;
+            return false;
+        }
+        ["", "v1", "services", name] => (*name, None),
+        ["", "v1", "services", name, action] => (*name, Some(*action)),
+        _ => {
+            write_error(out, 404, "not found");
+            return false;
+        }
+    };
+    let Some(name) = percent_decode(name) else {
+        write_error(out, 400, "invalid service name");
+        return false;
+    };
+    let Some(svc) = registry.get_by_name(&name) else {
+        write_error(out, 404, &format!("service '{}' not found", name));
+        return false;
+    };
+    let op = match (request.method, action) {
+        ("GET", None) => {
+            let mut value = service_json(svc);
+            value["start_count"] = json!(svc.start_count);
+            value["command"] = json!(svc.config.command);
+            value["args"] = json!(svc.config.redacted_args());
+            value["env"] = json!(svc.config.redacted_env());
+            value["log_file"] = json!(svc.log_file_path());
+            write_json(out, 200, &value);
+            return false;
+        }
+        ("GET", Some("logs")) => {
+            let lines = match query_param(request.query, "lines").map(str::parse::<usize>) {
+                None => DEFAULT_LOG_LINES,
+                Some(Ok(n)) if n <= MAX_LOG_LINES => n,
+                Some(_) => {
+                    write_error(out, 400, "invalid number of lines");
+                    return false;
+                }
+            };
+            let Some(path) = svc.log_file_path() else {
+                write_error(out, 404, &format!("service '{}' has no log file", name));
+                return false;
+            };
+            match read_log_tail(path, lines) {
+                Ok(tail) => write_response(out, 200, "text/plain; charset=utf-8", &tail),
+                Err(e) => write_error(out, 500, &format!("failed to read logs: {}", e)),
+            }
+            return false;
+        }
+        ("POST", Some("start")) => ControlOp::Start,
+        ("POST", Some("stop")) => ControlOp::Stop,
+        ("POST", Some("restart")) => ControlOp::Restart,
+        ("POST", Some("pause")) => ControlOp::Pause,
+        ("POST", Some("resume")) => ControlOp::Resume,
+        ("POST", Some("cancel")) => ControlOp::Cancel,
+        (_, None | Some("logs" | "start" | "stop" | "restart" | "pause" | "resume" | "cancel")) => {
+            write_error(out, 405, "method not allowed");
+            return false;
+        }
+        _ => {
+            write_error(out, 404, "not found");
+            return false;
+        }
+    };
+    let svc_id = svc.id;
+    if matches!(query_param(request.query, "dry_run"), Some("1" | "true")) {
+        let plan: Vec<Value> = plan_control_op(registry, svc_id, op)
+            .into_iter()
+            .map(|(action, svc)| json!({"action": action.as_str(), "service": svc.name}))
+            .collect();
+        write_json(out, 200, &json!({ "plan": plan }));
+        return false;
+    }
+    svlogg!(LogLevel::Debug, "API {} of '{}'", op, name);
+    if let Err(e) = apply_control_op(registry, svc_id, op, ctx, ps_dir) {
+        let status = match e.kind() {
+            io::ErrorKind::QuotaExceeded => 503,
+            io::ErrorKind::ResourceBusy => 409,
+            _ => 500,
+        };
+        write_error(out, status, &format!("failed to {} '{}': {}", op, name, e));
+        return true;
+    }
+    match registry.service_mut(svc_id) {
+        Some(svc) => write_json(out, 200, &service_json(svc)),
+        None => write_error(out, 404, &format!("service '{}' not found", name)),
+    }
+    true
+}
+
+/// Queue the `state` event of `svc` to `out`
+fn write_event(out: &mut Vec<u8>, svc: &Service) {
+    out.extend_from_slice(b"event: state\ndata: ");
+    out.extend_from_slice(service_json(svc).to_string().as_bytes());
+    out.extend_from_slice(b"\n\n");
+}
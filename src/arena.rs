@@ -0,0 +1,159 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    cell::{Cell, RefCell, UnsafeCell},
+    fmt,
+    mem::MaybeUninit,
+    ptr::NonNull,
+};
+
+/// Size of the first chunk of an arena
+const CHUNK_SIZE: usize = 4096;
+
+/// Bump allocator for the buffers that only live through one main loop
+/// iteration: the service states the status publisher compares, decoded
+/// API request paths, and the like.
+///
+/// Allocations are bumped out of chunks and are never freed one by one,
+/// `reset` takes them all back at once at the start of the next
+/// iteration. Chunks are kept across resets, merged into a single one as
+/// big as the busiest iteration so far, so that once the supervisor has
+/// settled these buffers don't go through the allocator at all. Only
+/// `Copy` values are allocated, nothing has to be dropped
+#[derive(Debug)]
+pub(crate) struct Arena {
+    /// The last chunk is the one allocations are bumped out of. Chunks are
+    /// only accessed through these pointers, allocations point into them
+    chunks: UnsafeCell<Vec<NonNull<[MaybeUninit<u8>]>>>,
+    /// Bytes used in the last chunk
+    used: Cell<usize>,
+    /// What `alloc_fmt` formats to before copying
+    scratch: RefCell<String>,
+}
+
+fn new_chunk(size: usize) -> NonNull<[MaybeUninit<u8>]> {
+    NonNull::from(Box::leak(Box::new_uninit_slice(size)))
+}
+
+/// SAFETY: `chunk` must come from `new_chunk`, with nothing pointing into
+/// it anymore
+unsafe fn free_chunk(chunk: NonNull<[MaybeUninit<u8>]>) {
+    drop(unsafe { Box::from_raw(chunk.as_ptr()) });
+}
+
+impl Arena {
+    pub(crate) fn new() -> Self {
+        Self {
+            chunks: UnsafeCell::new(Vec::new()),
+            used: Cell::new(0),
+            scratch: RefCell::new(String::new()),
+        }
+    }
+
+    /// Take all the allocations back. Borrowing the arena mutably, there
+    /// can't be any left in use
+    pub(crate) fn reset(&mut self) {
+        let chunks = self.chunks.get_mut();
+        if chunks.len() > 1 {
+            let size = chunks.iter().map(|chunk| chunk.len()).sum();
+            for chunk in chunks.drain(..) {
+                // SAFETY: the arena is borrowed mutably, the allocations
+                // pointing into the chunk are gone
+                unsafe { free_chunk(chunk) };
+            }
+            chunks.push(new_chunk(size));
+        }
+        self.used.set(0);
+    }
+
+    /// Room for `len` values of `T`, uninitialized
+    fn alloc_raw<T>(&self, len: usize) -> NonNull<T> {
+        let size = size_of::<T>()
+            .checked_mul(len)
+            .expect("arena allocation size overflows");
+        let align = align_of::<T>();
+        // SAFETY: the arena isn't `Sync`, and the vector isn't borrowed
+        // past this function. Allocations point into the chunks, not into
+        // the vector
+        let chunks = unsafe { &mut *self.chunks.get() };
+        if let Some(&chunk) = chunks.last() {
+            let base = chunk.cast::<u8>().as_ptr() as usize;
+            let start = (base + self.used.get()).next_multiple_of(align) - base;
+            if start + size <= chunk.len() {
+                self.used.set(start + size);
+                // SAFETY: `start + size` is within the chunk
+                return unsafe { chunk.cast::<u8>().add(start).cast() };
+            }
+        }
+        let chunk_size = (size + align)
+            .max(CHUNK_SIZE)
+            .max(chunks.last().map_or(0, |chunk| chunk.len() * 2));
+        let chunk = new_chunk(chunk_size);
+        chunks.push(chunk);
+        let base = chunk.cast::<u8>().as_ptr() as usize;
+        let start = base.next_multiple_of(align) - base;
+        self.used.set(start + size);
+        // SAFETY: the chunk has room for `size` bytes past any alignment
+        unsafe { chunk.cast::<u8>().add(start).cast() }
+    }
+
+    /// Allocate the values of `iter`, up to `len` of them
+    // each allocation gets room of its own, they can't alias
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) fn alloc_iter<T: Copy>(
+        &self,
+        len: usize,
+        iter: impl IntoIterator<Item = T>,
+    ) -> &mut [T] {
+        let ptr = self.alloc_raw::<T>(len);
+        let mut n = 0;
+        for value in iter.into_iter().take(len) {
+            // SAFETY: there is room for `len` values
+            unsafe { ptr.add(n).write(value) };
+            n += 1;
+        }
+        // SAFETY: the first `n` values are initialized, and the room was
+        // handed out to this allocation only
+        unsafe { std::slice::from_raw_parts_mut(ptr.as_ptr(), n) }
+    }
+
+    /// Copy `s` to the arena
+    fn alloc_str(&self, s: &str) -> &str {
+        let ptr = self.alloc_raw::<u8>(s.len());
+        // SAFETY: there is room for `s`, copied as is it stays UTF-8
+        unsafe {
+            ptr.copy_from_nonoverlapping(NonNull::from(s.as_bytes()).cast(), s.len());
+            std::str::from_utf8_unchecked(std::slice::from_raw_parts(ptr.as_ptr(), s.len()))
+        }
+    }
+
+    /// Allocate what `f` formats. `f` must not format to the arena
+    /// itself
+    pub(crate) fn alloc_fmt(
+        &self,
+        f: impl FnOnce(&mut String) -> fmt::Result,
+    ) -> Result<&str, fmt::Error> {
+        let mut scratch = self.scratch.borrow_mut();
+        scratch.clear();
+        f(&mut scratch)?;
+        Ok(self.alloc_str(&scratch))
+    }
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        for chunk in self.chunks.get_mut().drain(..) {
+            // SAFETY: the arena is borrowed mutably, the allocations
+            // pointing into the chunk are gone
+            unsafe { free_chunk(chunk) };
+        }
+    }
+}
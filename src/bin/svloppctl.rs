@@ -0,0 +1,1315 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![deny(clippy::unwrap_used)]
+
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, IsTerminal, Write},
+    os::{
+        fd::AsFd,
+        unix::{
+            fs::{MetadataExt, OpenOptionsExt},
+            process::{CommandExt, ExitStatusExt},
+        },
+    },
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+use rustix::{
+    process::{chroot, fchdir},
+    thread::{LinkNameSpaceType, move_into_link_name_space},
+};
+
+use svlopp::{
+    ADOPT_DIR_NAME, CONTROL_PIPE_NAME, DEFAULT_RUN_DIR, GRAPH_FILE_NAME, PLAN_DIR_NAME,
+    PLATFORM_FILE_NAME, PROPERTY_DIR_NAME, PS_DIR_NAME, RELOAD_FILE_NAME, RUN_SET_FILE_NAME,
+    SNAPSHOT_FILE_NAME, STARTUP_FILE_NAME, STATUS_DIR_NAME, STATUS_FILE_NAME, TRANSIENT_DIR_NAME,
+    calendar::CalendarSpec,
+    graph_format, opcode, restore_mode,
+    snapshot::{RecordState, Snapshot, StopReasonKind},
+    unit::convert_unit,
+    wire::WireControlCommand,
+};
+
+/// How long to wait for svlopp to answer a `ps`, `graph`, `snapshot`,
+/// `reload`, `run`, `adopt` or dry-run request
+const PS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Shell `enter` runs unless given a command, looked up in the root
+/// directory of the service
+const ENTER_SHELL: &str = "/bin/sh";
+
+/// Namespaces `enter` joins, the user one first: joining it grants the
+/// capabilities needed to join the others it owns
+const ENTER_NAMESPACES: [(&str, LinkNameSpaceType); 7] = [
+    ("user", LinkNameSpaceType::User),
+    ("cgroup", LinkNameSpaceType::ControlGroup),
+    ("ipc", LinkNameSpaceType::InterProcessCommunication),
+    ("uts", LinkNameSpaceType::HostNameAndNISDomainName),
+    ("net", LinkNameSpaceType::Network),
+    ("pid", LinkNameSpaceType::ProcessID),
+    ("mnt", LinkNameSpaceType::Mount),
+];
+
+/// Number of services listed by `analyze`
+const ANALYZE_SLOWEST: usize = 10;
+
+/// Number of trigger times printed by `calendar`, unless `--count` is given
+const CALENDAR_COUNT: usize = 5;
+
+const BASH_COMPLETION: &str = include_str!("../../completions/svloppctl.bash");
+const ZSH_COMPLETION: &str = include_str!("../../completions/svloppctl.zsh");
+const FISH_COMPLETION: &str = include_str!("../../completions/svloppctl.fish");
+
+fn usage() -> ! {
+    eprintln!("usage: svloppctl [--run-dir PATH] <command>");
+    eprintln!();
+    eprintln!("commands:");
+    eprintln!("  inspect-state [FILE]  print a state snapshot (default: the one in the run dir)");
+    eprintln!("  status [--no-color]   print the services in a table");
+    eprintln!("  ps SERVICE            print the process tree of a service");
+    eprintln!("  start|stop|restart SERVICE [--dry-run]");
+    eprintln!("                        control a service, or print what it would start and stop");
+    eprintln!("  restart --outdated    restart the services running an outdated config");
+    eprintln!("  pause|resume SERVICE  freeze or thaw the processes of a service");
+    eprintln!("  cancel SERVICE        cancel the queued operation of a service, or its stop");
+    eprintln!("  rolling-restart NAME  restart the NAME@<instance> services one at a time");
+    eprintln!("  swap SERVICE          replace a service with a new instance of it, once ready");
+    eprintln!("  run [--name NAME] -- COMMAND [ARGS...]");
+    eprintln!("                        start a transient service, removed once it stops");
+    eprintln!("  adopt --pid PID --name SERVICE");
+    eprintln!(
+        "                        supervise a running process as a service, without restarting it"
+    );
+    eprintln!("  detach SERVICE        stop supervising the process of a service, left running");
+    eprintln!("  enter SERVICE [-- COMMAND [ARGS...]]");
+    eprintln!("                        run a shell, or COMMAND, in the context of a service");
+    eprintln!("  set-property SERVICE PROPERTY=VALUE");
+    eprintln!("                        set a property of a service at runtime, e.g. cpu.max=50%");
+    eprintln!("  list                  print the names of the services");
+    eprintln!("  snapshot              save the set of running services");
+    eprintln!("  restore [--exact]     start the saved services, and stop the others with --exact");
+    eprintln!("  reload                reload the configuration, and print what changed");
+    eprintln!("  analyze               print how long services took to start with svlopp");
+    eprintln!("  graph [--json]        print the service graph as Graphviz DOT, or JSON");
+    eprintln!("  convert-unit FILE [NAME]");
+    eprintln!("                        convert a systemd service unit to svlopp config");
+    eprintln!("  calendar EXPR [--count N] [--from SECONDS]");
+    eprintln!("                        print the next times a calendar expression triggers at");
+    eprintln!("  completions SHELL     print the completion script for bash, zsh or fish");
+    eprintln!("  version [--verbose]   print the version, and the kernel features svlopp uses");
+    std::process::exit(1);
+}
+
+#[derive(Debug)]
+enum Command {
+    InspectState(Option<PathBuf>),
+    Status {
+        color: bool,
+    },
+    Ps(String),
+    Control {
+        op: u8,
+        name: String,
+        dry_run: bool,
+    },
+    SetProperty {
+        name: String,
+        property: String,
+    },
+    RestartOutdated,
+    RollingRestart(String),
+    Run {
+        name: Option<String>,
+        argv: Vec<String>,
+    },
+    Adopt {
+        pid: u32,
+        name: String,
+    },
+    Enter {
+        name: String,
+        argv: Vec<String>,
+    },
+    List,
+    Snapshot,
+    Restore {
+        exact: bool,
+    },
+    Reload,
+    Analyze,
+    Graph {
+        json: bool,
+    },
+    ConvertUnit(PathBuf, Option<String>),
+    Calendar {
+        expr: String,
+        count: usize,
+        from: Option<i64>,
+    },
+    Completions(&'static str),
+    Version {
+        verbose: bool,
+    },
+}
+
+#[derive(Debug)]
+struct CtlArgs {
+    run_dir: PathBuf,
+    command: Command,
+}
+
+fn parse() -> CtlArgs {
+    let mut args = std::env::args().skip(1);
+    let mut run_dir = None;
+    let mut command = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--run-dir" => {
+                run_dir = Some(PathBuf::from(args.next().unwrap_or_else(|| {
+                    eprintln!("--run-dir requires a value");
+                    usage();
+                })));
+            }
+            "--help" => usage(),
+            "inspect-state" => {
+                command = Some(Command::InspectState(args.next().map(PathBuf::from)));
+            }
+            "ps" => {
+                command = Some(Command::Ps(args.next().unwrap_or_else(|| {
+                    eprintln!("ps requires a service name");
+                    usage();
+                })));
+            }
+            "start" | "stop" | "restart" => {
+                let op = match arg.as_str() {
+                    "start" => opcode::START,
+                    "stop" => opcode::STOP,
+                    _ => opcode::RESTART,
+                };
+                let name = args.next().unwrap_or_else(|| {
+                    eprintln!("{} requires a service name", arg);
+                    usage();
+                });
+                if op == opcode::RESTART && name == "--outdated" {
+                    command = Some(Command::RestartOutdated);
+                    continue;
+                }
+                let mut dry_run = false;
+                for arg in args.by_ref() {
+                    match arg.as_str() {
+                        "--dry-run" => dry_run = true,
+                        other => {
+                            eprintln!("unexpected argument: {}", other);
+                            usage();
+                        }
+                    }
+                }
+                command = Some(Command::Control { op, name, dry_run });
+            }
+            "rolling-restart" => {
+                command = Some(Command::RollingRestart(args.next().unwrap_or_else(|| {
+                    eprintln!("rolling-restart requires a service name");
+                    usage();
+                })));
+            }
+            "pause" | "resume" | "cancel" | "swap" | "detach" => {
+                let op = match arg.as_str() {
+                    "pause" => opcode::PAUSE,
+                    "resume" => opcode::RESUME,
+                    "cancel" => opcode::CANCEL,
+                    "swap" => opcode::SWAP,
+                    _ => opcode::DETACH,
+                };
+                let name = args.next().unwrap_or_else(|| {
+                    eprintln!("{} requires a service name", arg);
+                    usage();
+                });
+                command = Some(Command::Control {
+                    op,
+                    name,
+                    dry_run: false,
+                });
+            }
+            "set-property" => {
+                let name = args.next().unwrap_or_else(|| {
+                    eprintln!("set-property requires a service name");
+                    usage();
+                });
+                let property = args
+                    .next()
+                    .filter(|property| property.contains('='))
+                    .unwrap_or_else(|| {
+                        eprintln!("set-property requires a PROPERTY=VALUE assignment");
+                        usage();
+                    });
+                command = Some(Command::SetProperty { name, property });
+            }
+            "status" => {
+                let mut color = true;
+                for arg in args.by_ref() {
+                    match arg.as_str() {
+                        "--no-color" => color = false,
+                        other => {
+                            eprintln!("unexpected argument: {}", other);
+                            usage();
+                        }
+                    }
+                }
+                command = Some(Command::Status { color });
+            }
+            "list" => command = Some(Command::List),
+            "snapshot" => command = Some(Command::Snapshot),
+            "restore" => {
+                let mut exact = false;
+                for arg in args.by_ref() {
+                    match arg.as_str() {
+                        "--exact" => exact = true,
+                        other => {
+                            eprintln!("unexpected argument: {}", other);
+                            usage();
+                        }
+                    }
+                }
+                command = Some(Command::Restore { exact });
+            }
+            "reload" => command = Some(Command::Reload),
+            "analyze" => command = Some(Command::Analyze),
+            "graph" => {
+                let mut json = false;
+                for arg in args.by_ref() {
+                    match arg.as_str() {
+                        "--json" => json = true,
+                        other => {
+                            eprintln!("unexpected argument: {}", other);
+                            usage();
+                        }
+                    }
+                }
+                command = Some(Command::Graph { json });
+            }
+            "completions" => {
+                let shell = args.next().unwrap_or_else(|| {
+                    eprintln!("completions requires a shell");
+                    usage();
+                });
+                command = Some(Command::Completions(match shell.as_str() {
+                    "bash" => BASH_COMPLETION,
+                    "zsh" => ZSH_COMPLETION,
+                    "fish" => FISH_COMPLETION,
+                    other => {
+                        eprintln!("unsupported shell: {}", other);
+                        usage();
+                    }
+                }));
+            }
+            "version" => {
+                let mut verbose = false;
+                for arg in args.by_ref() {
+                    match arg.as_str() {
+                        "--verbose" => verbose = true,
+                        other => {
+                            eprintln!("unexpected argument: {}", other);
+                            usage();
+                        }
+                    }
+                }
+                command = Some(Command::Version { verbose });
+            }
+            "run" => {
+                let mut name = None;
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "--name" => {
+                            name = Some(args.next().unwrap_or_else(|| {
+                                eprintln!("--name requires a value");
+                                usage();
+                            }));
+                        }
+                        "--" => break,
+                        other => {
+                            eprintln!("unexpected argument: {}", other);
+                            usage();
+                        }
+                    }
+                }
+                let argv: Vec<String> = args.by_ref().collect();
+                if argv.is_empty() {
+                    eprintln!("run requires a command");
+                    usage();
+                }
+                command = Some(Command::Run { name, argv });
+            }
+            "enter" => {
+                let name = args.next().unwrap_or_else(|| {
+                    eprintln!("enter requires a service name");
+                    usage();
+                });
+                let argv: Vec<String> = match args.next().as_deref() {
+                    None => vec![ENTER_SHELL.to_owned()],
+                    Some("--") => args.by_ref().collect(),
+                    Some(other) => {
+                        eprintln!("unexpected argument: {}", other);
+                        usage();
+                    }
+                };
+                if argv.is_empty() {
+                    eprintln!("enter requires a command after --");
+                    usage();
+                }
+                command = Some(Command::Enter { name, argv });
+            }
+            "adopt" => {
+                let mut pid = None;
+                let mut name = None;
+                while let Some(arg) = args.next() {
+                    let value = args.next().unwrap_or_else(|| {
+                        eprintln!("{} requires a value", arg);
+                        usage();
+                    });
+                    match arg.as_str() {
+                        "--pid" => pid = Some(value.parse().unwrap_or_else(|_| usage())),
+                        "--name" => name = Some(value),
+                        other => {
+                            eprintln!("unexpected argument: {}", other);
+                            usage();
+                        }
+                    }
+                }
+                let (Some(pid), Some(name)) = (pid, name) else {
+                    eprintln!("adopt requires --pid and --name");
+                    usage();
+                };
+                command = Some(Command::Adopt { pid, name });
+            }
+            "convert-unit" => {
+                let path = PathBuf::from(args.next().unwrap_or_else(|| {
+                    eprintln!("convert-unit requires a unit file");
+                    usage();
+                }));
+                command = Some(Command::ConvertUnit(path, args.next()));
+            }
+            "calendar" => {
+                let expr = args.next().unwrap_or_else(|| {
+                    eprintln!("calendar requires an expression");
+                    usage();
+                });
+                let mut count = CALENDAR_COUNT;
+                let mut from = None;
+                while let Some(arg) = args.next() {
+                    let value = args.next().unwrap_or_else(|| {
+                        eprintln!("{} requires a value", arg);
+                        usage();
+                    });
+                    match arg.as_str() {
+                        "--count" => count = value.parse().unwrap_or_else(|_| usage()),
+                        "--from" => from = Some(value.parse().unwrap_or_else(|_| usage())),
+                        other => {
+                            eprintln!("unexpected argument: {}", other);
+                            usage();
+                        }
+                    }
+                }
+                command = Some(Command::Calendar { expr, count, from });
+            }
+            other => {
+                eprintln!("unknown argument: {}", other);
+                usage();
+            }
+        }
+        if command.is_some() {
+            if let Some(extra) = args.next() {
+                eprintln!("unexpected argument: {}", extra);
+                usage();
+            }
+            break;
+        }
+    }
+
+    CtlArgs {
+        run_dir: run_dir.unwrap_or_else(|| PathBuf::from(DEFAULT_RUN_DIR)),
+        command: command.unwrap_or_else(|| usage()),
+    }
+}
+
+/// Print the header, then one line per service in the status file format,
+/// with the number of starts before the flags
+fn inspect_state(path: PathBuf) -> io::Result<()> {
+    let bytes = std::fs::read(&path)?;
+    let snapshot =
+        Snapshot::decode(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut out = io::stdout().lock();
+    writeln!(
+        out,
+        "# pid {} at {}.{:09}",
+        snapshot.pid, snapshot.secs, snapshot.nsecs
+    )?;
+    for svc in &snapshot.services {
+        writeln!(
+            out,
+            "{} {} {} {}{}",
+            svc.name, svc.id, svc.state, svc.start_count, svc.flags
+        )?;
+    }
+    Ok(())
+}
+
+/// How long the process `pid` has been running, from its start time in
+/// procfs
+fn process_uptime(pid: i32) -> Option<Duration> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // `starttime` is the 20th field after `comm`, which may contain spaces
+    let (_, fields) = stat.rsplit_once(')')?;
+    let start_ticks: u64 = fields.split_whitespace().nth(19)?.parse().ok()?;
+    let uptime = std::fs::read_to_string("/proc/uptime").ok()?;
+    let since_boot: f64 = uptime.split_whitespace().next()?.parse().ok()?;
+    // SAFETY: `sysconf` has no preconditions
+    let clock_ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as f64;
+    Some(Duration::from_secs_f64(
+        (since_boot - start_ticks as f64 / clock_ticks).max(0.0),
+    ))
+}
+
+/// Format `d` with its two most significant units, e.g. `3d 4h` or `5m 2s`
+fn humanize(d: Duration) -> String {
+    let secs = d.as_secs();
+    let (days, hours, mins) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    match (days, hours, mins) {
+        (0, 0, 0) => format!("{}s", secs),
+        (0, 0, m) => format!("{}m {}s", m, secs % 60),
+        (0, h, m) => format!("{}h {}m", h, m),
+        (d, h, _) => format!("{}d {}h", d, h),
+    }
+}
+
+/// ANSI color of a service state: green when running, yellow when
+/// stopping, cyan when paused and red when stopped after a failure
+fn state_color(state: &RecordState) -> Option<&'static str> {
+    match state {
+        RecordState::Running { .. } => Some("\x1b[32m"),
+        RecordState::Stopping { .. } => Some("\x1b[33m"),
+        RecordState::Paused { .. } => Some("\x1b[36m"),
+        RecordState::Stopped {
+            reason:
+                StopReasonKind::Error
+                | StopReasonKind::Crashed
+                | StopReasonKind::Killed
+                | StopReasonKind::OomKilled
+                | StopReasonKind::Lost
+                | StopReasonKind::SpawnFailed
+                | StopReasonKind::RuntimeExceededExited
+                | StopReasonKind::RuntimeExceededSignaled,
+            ..
+        } => Some("\x1b[31m"),
+        RecordState::Stopped { .. } => None,
+    }
+}
+
+/// Print the services of the snapshot in aligned columns, for humans.
+///
+/// States are colored unless `color` is false, the `NO_COLOR` variable is
+/// set or stdout is not a terminal
+fn status(run_dir: &Path, color: bool) -> io::Result<()> {
+    let bytes = std::fs::read(run_dir.join(SNAPSHOT_FILE_NAME))?;
+    let mut snapshot =
+        Snapshot::decode(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    snapshot
+        .services
+        .sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    let color = color
+        && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+        && io::stdout().is_terminal();
+
+    let header = ["NAME", "STATE", "PID/REASON", "UPTIME", "STARTS"].map(String::from);
+    let rows: Vec<[String; 5]> = snapshot
+        .services
+        .iter()
+        .map(|svc| {
+            let state = svc.state.to_string();
+            let (state, detail) = state.split_once(' ').unwrap_or((&state, ""));
+            let uptime = match svc.state {
+                RecordState::Running { pid } | RecordState::Paused { pid } => {
+                    process_uptime(pid).map(humanize)
+                }
+                _ => None,
+            };
+            [
+                svc.name.clone(),
+                state.to_owned(),
+                detail.to_owned(),
+                uptime.unwrap_or_else(|| "-".to_owned()),
+                svc.start_count.to_string(),
+            ]
+        })
+        .collect();
+    let mut widths = [0; 5];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut out = io::stdout().lock();
+    let write_row = |out: &mut io::StdoutLock, row: &[String; 5], color: Option<&str>| {
+        let [name, state, detail, uptime, starts] = row;
+        let [w_name, w_state, w_detail, w_uptime, w_starts] = widths;
+        let state = format!("{:<w_state$}", state);
+        let state = match color {
+            Some(code) => format!("{}{}\x1b[0m", code, state),
+            None => state,
+        };
+        writeln!(
+            out,
+            "{:<w_name$}  {}  {:<w_detail$}  {:>w_uptime$}  {:>w_starts$}",
+            name, state, detail, uptime, starts
+        )
+    };
+    write_row(&mut out, &header, None)?;
+    for (svc, row) in snapshot.services.iter().zip(&rows) {
+        write_row(&mut out, row, state_color(&svc.state).filter(|_| color))?;
+    }
+    Ok(())
+}
+
+/// Find the status line of the service `name` in the status file, or in
+/// its own status file with `--per-service-status`
+fn status_line(run_dir: &Path, name: &str) -> io::Result<String> {
+    let content = match std::fs::read_to_string(run_dir.join(STATUS_FILE_NAME)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            std::fs::read_to_string(run_dir.join(STATUS_DIR_NAME).join(name))?
+        }
+        Err(e) => return Err(e),
+    };
+    content
+        .lines()
+        .find(|line| line.split_whitespace().next() == Some(name))
+        .map(str::to_owned)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unknown service"))
+}
+
+/// Find the id of the service `name`, see `status_line`
+fn service_id(run_dir: &Path, name: &str) -> io::Result<u64> {
+    status_line(run_dir, name)?
+        .split_whitespace()
+        .nth(1)
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unknown service"))
+}
+
+/// Find the pid of the running service `name`, see `status_line`
+fn service_pid(run_dir: &Path, name: &str) -> io::Result<i32> {
+    let line = status_line(run_dir, name)?;
+    match line.split_whitespace().collect::<Vec<_>>()[..] {
+        [_, _, "running", pid, ..] => pid
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "malformed status")),
+        _ => Err(io::Error::other(format!(
+            "service '{}' isn't running",
+            name
+        ))),
+    }
+}
+
+/// Print the names of the services, from the status file or, with
+/// `--per-service-status`, from the status directory
+/// Print the version, and with `verbose` whether the kernel features
+/// svlopp can use were found by the running supervisor
+fn version(run_dir: &Path, verbose: bool) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    writeln!(out, "svloppctl {}", env!("CARGO_PKG_VERSION"))?;
+    if !verbose {
+        return Ok(());
+    }
+    let content = std::fs::read_to_string(run_dir.join(PLATFORM_FILE_NAME))?;
+    writeln!(out, "kernel features:")?;
+    for line in content.lines() {
+        if let Some((feature, available)) = line.split_once(' ') {
+            writeln!(out, "  {:<12} {}", feature, available)?;
+        }
+    }
+    Ok(())
+}
+
+fn list(run_dir: &Path) -> io::Result<()> {
+    let mut names: Vec<String> = match std::fs::read_to_string(run_dir.join(STATUS_FILE_NAME)) {
+        Ok(content) => content
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .map(str::to_owned)
+            .collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let mut names = Vec::new();
+            for entry in std::fs::read_dir(run_dir.join(STATUS_DIR_NAME))? {
+                if let Ok(name) = entry?.file_name().into_string()
+                    && !name.starts_with('.')
+                {
+                    names.push(name);
+                }
+            }
+            names
+        }
+        Err(e) => return Err(e),
+    };
+    names.sort_unstable();
+    let mut out = io::stdout().lock();
+    for name in names {
+        writeln!(out, "{}", name)?;
+    }
+    Ok(())
+}
+
+/// Format a duration in microseconds with a unit fitting its magnitude
+fn format_us(us: u64) -> String {
+    match us {
+        0..1_000 => format!("{}us", us),
+        1_000..1_000_000 => format!("{:.1}ms", us as f64 / 1e3),
+        _ => format!("{:.3}s", us as f64 / 1e6),
+    }
+}
+
+/// A line of the startup profile
+#[derive(Debug)]
+struct StartupEntry<'a> {
+    name: &'a str,
+    bind_to: Option<&'a str>,
+    forked_us: u64,
+    exec_us: u64,
+}
+
+/// Print the startup profile: when the last service came up, the
+/// services that took the longest from fork to exec, and the critical
+/// chain, the last service to come up along with the services it is
+/// bound to, since it couldn't start before them
+fn analyze(run_dir: &Path) -> io::Result<()> {
+    let content = std::fs::read_to_string(run_dir.join(STARTUP_FILE_NAME))?;
+    let mut entries: Vec<StartupEntry> = content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            Some(StartupEntry {
+                name: fields.next()?,
+                bind_to: fields.next().filter(|&target| target != "-"),
+                forked_us: fields.next()?.parse().ok()?,
+                exec_us: fields.next()?.parse().ok()?,
+            })
+        })
+        .collect();
+    let Some(last) = entries.iter().max_by_key(|e| e.exec_us) else {
+        return Err(io::Error::other("no service started yet"));
+    };
+    let by_name: HashMap<&str, &StartupEntry> = entries.iter().map(|e| (e.name, e)).collect();
+    let mut chain = vec![last];
+    while let Some(target) = chain.last().and_then(|e| e.bind_to)
+        && let Some(&entry) = by_name.get(target)
+        && chain.len() <= entries.len()
+    {
+        chain.push(entry);
+    }
+
+    let mut out = io::stdout().lock();
+    writeln!(
+        out,
+        "{} services up in {}",
+        entries.len(),
+        format_us(last.exec_us)
+    )?;
+    writeln!(out)?;
+    writeln!(out, "critical chain:")?;
+    for (depth, entry) in chain.iter().rev().enumerate() {
+        writeln!(
+            out,
+            "  {:indent$}{} forked @{}, up @{} (+{})",
+            "",
+            entry.name,
+            format_us(entry.forked_us),
+            format_us(entry.exec_us),
+            format_us(entry.exec_us.saturating_sub(entry.forked_us)),
+            indent = depth * 2
+        )?;
+    }
+
+    entries.sort_by_key(|e| std::cmp::Reverse(e.exec_us.saturating_sub(e.forked_us)));
+    writeln!(out)?;
+    writeln!(out, "slowest services, from fork to exec:")?;
+    let width = entries.iter().map(|e| e.name.len()).max().unwrap_or(0);
+    for entry in entries.iter().take(ANALYZE_SLOWEST) {
+        writeln!(
+            out,
+            "  {:<width$}  {:>9}",
+            entry.name,
+            format_us(entry.exec_us.saturating_sub(entry.forked_us))
+        )?;
+    }
+    Ok(())
+}
+
+fn send_command(run_dir: &Path, op: u8, service_id: u64) -> io::Result<()> {
+    OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(run_dir.join(CONTROL_PIPE_NAME))?
+        .write_all(&WireControlCommand::new(op, service_id).encode())
+}
+
+/// Wait for svlopp to write the answer to a request to `path`, then read
+/// and remove it
+fn wait_answer(path: &Path) -> io::Result<String> {
+    let content = wait_file(path)?;
+    let _ = std::fs::remove_file(path);
+    Ok(content)
+}
+
+/// Wait for svlopp to write `path`, then read it
+fn wait_file(path: &Path) -> io::Result<String> {
+    let started = Instant::now();
+    loop {
+        match std::fs::read_to_string(path) {
+            Ok(content) => return Ok(content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && started.elapsed() < PS_TIMEOUT => {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "no answer from svlopp",
+                ));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Ask svlopp for the process tree of `name` and print it, children
+/// indented under their parent
+fn ps(run_dir: &Path, name: &str) -> io::Result<()> {
+    let id = service_id(run_dir, name)?;
+    let path = run_dir.join(PS_DIR_NAME).join(id.to_string());
+    match std::fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    send_command(run_dir, opcode::PS, id)?;
+    let content = wait_answer(&path)?;
+
+    // `<pid> <ppid> <rss_kib> <cpu_ms> <comm>`, root first
+    let procs: Vec<[&str; 5]> = content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(5, ' ');
+            Some([
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+            ])
+        })
+        .collect();
+    let Some(root) = procs.first() else {
+        return Err(io::Error::other("service is not running"));
+    };
+    let mut children: HashMap<&str, Vec<&[&str; 5]>> = HashMap::new();
+    for proc in &procs[1..] {
+        children.entry(proc[1]).or_default().push(proc);
+    }
+
+    let mut out = io::stdout().lock();
+    writeln!(
+        out,
+        "{:>8} {:>10} {:>10}  COMMAND",
+        "PID", "RSS(KiB)", "CPU(ms)"
+    )?;
+    let mut stack = vec![(root, 0)];
+    while let Some((proc, depth)) = stack.pop() {
+        writeln!(
+            out,
+            "{:>8} {:>10} {:>10}  {:indent$}{}",
+            proc[0],
+            proc[2],
+            proc[3],
+            "",
+            proc[4],
+            indent = depth * 2
+        )?;
+        if let Some(procs) = children.get(proc[0]) {
+            stack.extend(procs.iter().rev().map(|&child| (child, depth + 1)));
+        }
+    }
+    Ok(())
+}
+
+/// Send `op` for the service `name`, or with `dry_run` ask svlopp for
+/// what it would start and stop and print it instead
+/// Restart the instances of the replicated service `name`, the services
+/// named `name@<instance>`. The supervisor is sent the id of any instance,
+/// `name` can also be an instance itself
+fn rolling_restart(run_dir: &Path, name: &str) -> io::Result<()> {
+    let prefix = format!("{}@", name);
+    let instance = match std::fs::read_to_string(run_dir.join(STATUS_FILE_NAME)) {
+        Ok(content) => content
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .find(|svc_name| svc_name.starts_with(&prefix))
+            .map(str::to_owned),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            std::fs::read_dir(run_dir.join(STATUS_DIR_NAME))?
+                .flatten()
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .find(|svc_name| svc_name.starts_with(&prefix))
+        }
+        Err(e) => return Err(e),
+    };
+    let id = service_id(run_dir, instance.as_deref().unwrap_or(name))?;
+    send_command(run_dir, opcode::ROLLING_RESTART, id)
+}
+
+fn control(run_dir: &Path, op: u8, name: &str, dry_run: bool) -> io::Result<()> {
+    let id = service_id(run_dir, name)?;
+    if !dry_run {
+        return send_command(run_dir, op, id);
+    }
+    let path = run_dir.join(PLAN_DIR_NAME).join(id.to_string());
+    match std::fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    send_command(run_dir, op | opcode::DRY_RUN, id)?;
+    let content = wait_answer(&path)?;
+
+    let mut out = io::stdout().lock();
+    if content.is_empty() {
+        return writeln!(out, "nothing to do");
+    }
+    // `<start|stop> <name>`, in order
+    for (step, line) in content.lines().enumerate() {
+        let (action, name) = line.split_once(' ').unwrap_or((line, ""));
+        writeln!(out, "{:>3}. {:<5} {}", step + 1, action, name)?;
+    }
+    Ok(())
+}
+
+/// Write `property` for svlopp to read, then ask it to set it on the
+/// service `name`
+fn set_property(run_dir: &Path, name: &str, property: &str) -> io::Result<()> {
+    let id = service_id(run_dir, name)?;
+    std::fs::write(
+        run_dir.join(PROPERTY_DIR_NAME).join(id.to_string()),
+        property,
+    )?;
+    send_command(run_dir, opcode::SET_PROPERTY, id)
+}
+
+/// Write the definition of a transient service running `argv`, named
+/// `run-<request id>` by default, then ask svlopp to start it. Svlopp
+/// removes the definition once read
+fn run(run_dir: &Path, name: Option<String>, argv: &[String]) -> io::Result<()> {
+    let request_id = std::process::id();
+    let name = name.unwrap_or_else(|| format!("run-{}", request_id));
+    let mut request = name.clone();
+    for arg in argv {
+        request.push('\0');
+        request.push_str(arg);
+    }
+    let path = run_dir
+        .join(TRANSIENT_DIR_NAME)
+        .join(request_id.to_string());
+    std::fs::write(&path, request)?;
+    send_command(run_dir, opcode::RUN, request_id as u64)?;
+    let started = Instant::now();
+    while path.exists() {
+        if started.elapsed() >= PS_TIMEOUT {
+            let _ = std::fs::remove_file(&path);
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "no answer from svlopp",
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    writeln!(io::stdout().lock(), "running as service '{}'", name)
+}
+
+/// Write the name of the service to attach the process `pid` to, then ask
+/// svlopp to adopt it, waiting for the service to run it
+fn adopt(run_dir: &Path, pid: u32, name: &str) -> io::Result<()> {
+    std::fs::write(run_dir.join(ADOPT_DIR_NAME).join(pid.to_string()), name)?;
+    send_command(run_dir, opcode::ADOPT, pid as u64)?;
+    let pid = pid.to_string();
+    let started = Instant::now();
+    loop {
+        let content = match std::fs::read_to_string(run_dir.join(STATUS_FILE_NAME)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                std::fs::read_to_string(run_dir.join(STATUS_DIR_NAME).join(name))
+                    .unwrap_or_default()
+            }
+            Err(e) => return Err(e),
+        };
+        // `<name> <id> running <pid>`
+        let adopted = content.lines().any(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            matches!(fields[..], [svc_name, _, "running", svc_pid, ..]
+                if svc_name == name && svc_pid == pid)
+        });
+        if adopted {
+            return writeln!(
+                io::stdout().lock(),
+                "adopted process {} as service '{}'",
+                pid,
+                name
+            );
+        }
+        if started.elapsed() >= PS_TIMEOUT {
+            return Err(io::Error::other(
+                "svlopp didn't adopt the process, see its log",
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Ids a process runs with, from its procfs status, as seen from the user
+/// namespace of the reader
+#[derive(Debug, PartialEq, Eq)]
+struct Credentials {
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+    groups: Vec<libc::gid_t>,
+}
+
+impl Credentials {
+    fn read(pid: i32) -> io::Result<Self> {
+        let status = std::fs::read_to_string(format!("/proc/{}/status", pid))?;
+        let ids = |field: &str| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix(field))
+                .map(|ids| {
+                    ids.split_whitespace()
+                        .filter_map(|id| id.parse().ok())
+                        .collect::<Vec<u32>>()
+                })
+        };
+        // real, effective, saved and filesystem ids
+        match (
+            ids("Uid:").as_deref(),
+            ids("Gid:").as_deref(),
+            ids("Groups:"),
+        ) {
+            (Some(&[_, uid, ..]), Some(&[_, gid, ..]), Some(groups)) => {
+                Ok(Self { uid, gid, groups })
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "malformed process status",
+            )),
+        }
+    }
+
+    /// Switch to the ids, in the child `enter` forks, before it execs
+    fn apply(&self) -> io::Result<()> {
+        // SAFETY: `groups` holds `groups.len()` gids
+        if unsafe { libc::setgroups(self.groups.len(), self.groups.as_ptr()) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `setgid` and `setuid` have no preconditions
+        if unsafe { libc::setgid(self.gid) } == -1 || unsafe { libc::setuid(self.uid) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// The cgroup v2 of the process `pid`
+fn proc_cgroup(pid: i32) -> Option<String> {
+    std::fs::read_to_string(format!("/proc/{}/cgroup", pid))
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(str::to_owned)
+}
+
+/// Where the cgroup v2 hierarchy is mounted, from mountinfo
+fn cgroup2_mount() -> Option<String> {
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+    mountinfo.lines().find_map(|line| {
+        let (mount, fs) = line.split_once(" - ")?;
+        fs.starts_with("cgroup2 ")
+            .then(|| mount.split_whitespace().nth(4).map(str::to_owned))
+            .flatten()
+    })
+}
+
+/// Move svloppctl to the cgroup v2 of the process `pid`, unless it's
+/// already in it or there's no cgroup v2 hierarchy
+fn join_cgroup(pid: i32) -> io::Result<()> {
+    let (Some(cgroup), Some(own)) = (proc_cgroup(pid), proc_cgroup(std::process::id() as i32))
+    else {
+        return Ok(());
+    };
+    if cgroup == own {
+        return Ok(());
+    }
+    let Some(mount) = cgroup2_mount() else {
+        return Ok(());
+    };
+    // 0 is the writer
+    std::fs::write(format!("{}{}/cgroup.procs", mount, cgroup), "0")
+        .map_err(|e| io::Error::new(e.kind(), format!("can't join cgroup {}: {}", cgroup, e)))
+}
+
+/// Run `argv` in the context of the running service `name`: in its cgroup
+/// and namespaces, in its root and working directories and as its user,
+/// then exit with the status of the command. The namespaces svloppctl is
+/// already in are left alone
+fn enter(run_dir: &Path, name: &str, argv: &[String]) -> io::Result<()> {
+    let pid = service_pid(run_dir, name)?;
+    let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+    // opened from the mount namespace of svloppctl, before joining the
+    // one of the service
+    let root = File::open(proc_dir.join("root"))?;
+    let cwd = File::open(proc_dir.join("cwd"))?;
+    let mut namespaces = Vec::new();
+    for (ns, kind) in ENTER_NAMESPACES {
+        let path = proc_dir.join("ns").join(ns);
+        let target = match std::fs::metadata(&path) {
+            Ok(target) => target,
+            // not supported by the kernel
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        // joining its own user namespace fails
+        let own = std::fs::metadata(format!("/proc/self/ns/{}", ns))?;
+        if (own.dev(), own.ino()) != (target.dev(), target.ino()) {
+            namespaces.push((ns, kind, File::open(&path)?));
+        }
+    }
+    join_cgroup(pid)?;
+    let join = |(ns, kind, fd): (&str, LinkNameSpaceType, File)| {
+        move_into_link_name_space(fd.as_fd(), Some(kind))
+            .map_err(|e| io::Error::other(format!("can't join the {} namespace: {}", ns, e)))
+    };
+    let mut namespaces = namespaces.into_iter().peekable();
+    if let Some(user) = namespaces.next_if(|&(_, kind, _)| kind == LinkNameSpaceType::User) {
+        join(user)?;
+    }
+    // the ids of the service as seen from its user namespace
+    let creds = Credentials::read(pid)?;
+    let switch = creds != Credentials::read(std::process::id() as i32)?;
+    namespaces.try_for_each(join)?;
+    let root_meta = root.metadata()?;
+    let own_root = std::fs::metadata("/")?;
+    fchdir(&root)?;
+    if (root_meta.dev(), root_meta.ino()) != (own_root.dev(), own_root.ino()) {
+        chroot(".")?;
+    }
+    fchdir(&cwd)?;
+
+    let mut cmd = std::process::Command::new(&argv[0]);
+    cmd.args(&argv[1..]);
+    if switch {
+        // SAFETY: `apply` only makes async-signal-safe calls
+        unsafe {
+            cmd.pre_exec(move || creds.apply());
+        }
+    }
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", argv[0], e)))?;
+    // like `system`, leave interrupts to the command
+    // SAFETY: ignoring signals has no preconditions
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_IGN);
+        libc::signal(libc::SIGQUIT, libc::SIG_IGN);
+    }
+    let status = child.wait()?;
+    std::process::exit(
+        status
+            .code()
+            .unwrap_or_else(|| 128 + status.signal().unwrap_or(0)),
+    )
+}
+
+/// Ask svlopp to save the set of running services, and print it
+fn snapshot(run_dir: &Path) -> io::Result<()> {
+    let path = run_dir.join(RUN_SET_FILE_NAME);
+    match std::fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    send_command(run_dir, opcode::SNAPSHOT, 0)?;
+    let content = wait_file(&path)?;
+    let mut out = io::stdout().lock();
+    writeln!(out, "saved {} running services", content.lines().count())?;
+    for name in content.lines() {
+        writeln!(out, "  {}", name)?;
+    }
+    Ok(())
+}
+
+/// Ask svlopp to reload its configuration, and print the services the
+/// reload added, removed or changed, with the fields that changed and
+/// what was done about them
+fn reload(run_dir: &Path) -> io::Result<()> {
+    let path = run_dir.join(RELOAD_FILE_NAME);
+    match std::fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    send_command(run_dir, opcode::RELOAD, 0)?;
+    let report: serde_json::Value = serde_json::from_str(&wait_file(&path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let changes = report["changes"].as_array().map_or(&[][..], Vec::as_slice);
+    let width = changes
+        .iter()
+        .filter_map(|change| change["service"].as_str())
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let mut out = io::stdout().lock();
+    if changes.is_empty() && report["ok"] == true {
+        writeln!(out, "nothing changed")?;
+    }
+    // `<added|removed|changed> <name> <action> [fields]`
+    for change in changes {
+        let fields: Vec<&str> = change["fields"]
+            .as_array()
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .filter_map(|field| field.as_str())
+            .collect();
+        writeln!(
+            out,
+            "{:<7} {:<width$}  {:<7} {}",
+            change["change"].as_str().unwrap_or("?"),
+            change["service"].as_str().unwrap_or("?"),
+            change["action"].as_str().unwrap_or("?"),
+            fields.join(", "),
+            width = width
+        )?;
+    }
+    if report["ok"] == true {
+        return Ok(());
+    }
+    let error = report["error"].as_str().unwrap_or("unknown error");
+    Err(io::Error::other(match report["rolled_back"] == true {
+        true => format!("reload failed, changes rolled back: {}", error),
+        false => format!("reload failed: {}", error),
+    }))
+}
+
+/// Ask svlopp for the service graph and print it, as Graphviz DOT or
+/// with `json` as JSON
+fn graph(run_dir: &Path, json: bool) -> io::Result<()> {
+    let (format, ext) = match json {
+        true => (graph_format::JSON, "json"),
+        false => (graph_format::DOT, "dot"),
+    };
+    let path = run_dir.join(format!("{}.{}", GRAPH_FILE_NAME, ext));
+    match std::fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    send_command(run_dir, opcode::GRAPH, format)?;
+    io::stdout()
+        .lock()
+        .write_all(wait_answer(&path)?.as_bytes())
+}
+
+/// Print the svlopp config converted from the unit at `path`, and what
+/// could not be converted on stderr. The service is named after the unit
+/// file unless `name` is given
+fn convert_unit_file(path: &Path, name: Option<String>) -> io::Result<()> {
+    let content = std::fs::read_to_string(path)?;
+    let name = match name {
+        Some(name) => name,
+        None => path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| io::Error::other("can't name the service after the unit file"))?
+            .to_owned(),
+    };
+    let converted =
+        convert_unit(&name, &content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    for warning in &converted.warnings {
+        eprintln!("svloppctl: warning: {}", warning);
+    }
+    io::stdout().lock().write_all(converted.config.as_bytes())
+}
+
+/// Print the next `count` times `expr` triggers at after `from`, now by
+/// default, in the zone of the expression
+fn calendar(expr: &str, count: usize, from: Option<i64>) -> io::Result<()> {
+    let spec =
+        CalendarSpec::parse(expr).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut time = match from {
+        Some(from) => from,
+        None => std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64),
+    };
+    let mut out = io::stdout().lock();
+    for i in 0..count {
+        match spec.next_after(time) {
+            Some(next) => time = next,
+            None if i == 0 => return Err(io::Error::other("the expression never triggers")),
+            None => break,
+        }
+        writeln!(out, "{}", spec.time_zone().format(time))?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = parse();
+    let result = match args.command {
+        Command::InspectState(path) => {
+            inspect_state(path.unwrap_or_else(|| args.run_dir.join(SNAPSHOT_FILE_NAME)))
+        }
+        Command::Ps(name) => ps(&args.run_dir, &name),
+        Command::Control { op, name, dry_run } => control(&args.run_dir, op, &name, dry_run),
+        Command::SetProperty { name, property } => set_property(&args.run_dir, &name, &property),
+        Command::RestartOutdated => send_command(&args.run_dir, opcode::RESTART_OUTDATED, 0),
+        Command::RollingRestart(name) => rolling_restart(&args.run_dir, &name),
+        Command::Run { name, argv } => run(&args.run_dir, name, &argv),
+        Command::Adopt { pid, name } => adopt(&args.run_dir, pid, &name),
+        Command::Enter { name, argv } => enter(&args.run_dir, &name, &argv),
+        Command::Status { color } => status(&args.run_dir, color),
+        Command::List => list(&args.run_dir),
+        Command::Snapshot => snapshot(&args.run_dir),
+        Command::Restore { exact } => {
+            let mode = match exact {
+                true => restore_mode::EXACT,
+                false => restore_mode::START,
+            };
+            send_command(&args.run_dir, opcode::RESTORE, mode)
+        }
+        Command::Reload => reload(&args.run_dir),
+        Command::Analyze => analyze(&args.run_dir),
+        Command::Graph { json } => graph(&args.run_dir, json),
+        Command::ConvertUnit(path, name) => convert_unit_file(&path, name),
+        Command::Calendar { expr, count, from } => calendar(&expr, count, from),
+        Command::Completions(script) => io::stdout().lock().write_all(script.as_bytes()),
+        Command::Version { verbose } => version(&args.run_dir, verbose),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("svloppctl: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
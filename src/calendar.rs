@@ -0,0 +1,444 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Calendar expressions, the times a timer triggers at.
+//!
+//! Two syntaxes are accepted:
+//!
+//! - systemd's `[WEEKDAYS] [[YEAR-]MONTH-DAY] [HOUR:MINUTE[:SECOND]] [TZ]`,
+//!   e.g. `Mon..Fri 02:30` or `*-*-01 00:00:00 Europe/Rome`, and its
+//!   shorthands (`hourly`, `daily`, `weekly`, ...). Components are `*`, a
+//!   value, a `A..B` range, a `/STEP` repetition from a value or a range,
+//!   or a comma separated list of those. A missing date is every day, a
+//!   missing time midnight.
+//! - cron's five fields `MINUTE HOUR DAY MONTH WEEKDAY`, optionally followed
+//!   by a time zone, and its `@daily` style shorthands. As with cron, a
+//!   day matches either its day of the month or its weekday when both
+//!   fields are restricted.
+//!
+//! Expressions of five words or more are cron ones. Times are in the zone
+//! named last, or in the local zone (see `crate::tz`). A local time the
+//! clock skips as daylight saving time starts is skipped too, and one that
+//! happens twice as it ends triggers once, the first time.
+
+use std::fmt;
+
+use crate::tz::{TimeZone, TzError, civil_from_days, days_from_civil, weekday};
+
+const SECS_PER_DAY: i64 = 86_400;
+
+/// Years the expressions can match, as far as the search for the next
+/// trigger time goes
+const MIN_YEAR: u32 = 1970;
+const MAX_YEAR: u32 = 2199;
+
+/// Weekdays, Monday first as `tz::weekday` counts them
+const WEEKDAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+/// Weekdays of cron, Sunday being 0 (and 7)
+const CRON_WEEKDAYS: [&str; 7] = [
+    "sunday",
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+];
+
+const CRON_MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// systemd shorthands and what they stand for
+const SHORTHANDS: [(&str, &str); 9] = [
+    ("minutely", "*-*-* *:*:00"),
+    ("hourly", "*-*-* *:00:00"),
+    ("daily", "*-*-* 00:00:00"),
+    ("weekly", "Mon *-*-* 00:00:00"),
+    ("monthly", "*-*-01 00:00:00"),
+    ("quarterly", "*-01,04,07,10-01 00:00:00"),
+    ("semiannually", "*-01,07-01 00:00:00"),
+    ("yearly", "*-01-01 00:00:00"),
+    ("annually", "*-01-01 00:00:00"),
+];
+
+/// cron shorthands and what they stand for
+const CRON_SHORTHANDS: [(&str, &str); 7] = [
+    ("@hourly", "0 * * * *"),
+    ("@daily", "0 0 * * *"),
+    ("@midnight", "0 0 * * *"),
+    ("@weekly", "0 0 * * 0"),
+    ("@monthly", "0 0 1 * *"),
+    ("@yearly", "0 0 1 1 *"),
+    ("@annually", "0 0 1 1 *"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CalendarError {
+    Empty,
+    /// A component that isn't valid where it is, or out of range
+    Invalid(String),
+    TimeZone(TzError),
+}
+
+impl fmt::Display for CalendarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("empty calendar expression"),
+            Self::Invalid(component) => write!(f, "invalid component '{}'", component),
+            Self::TimeZone(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for CalendarError {}
+
+impl From<TzError> for CalendarError {
+    fn from(e: TzError) -> Self {
+        Self::TimeZone(e)
+    }
+}
+
+/// Values `start`, `start + step`, ... up to `end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Range {
+    start: u32,
+    end: u32,
+    step: u32,
+}
+
+/// The values a component matches
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Field(Vec<Range>);
+
+impl Field {
+    fn any(min: u32, max: u32) -> Self {
+        Self(vec![Range {
+            start: min,
+            end: max,
+            step: 1,
+        }])
+    }
+
+    fn value(v: u32) -> Self {
+        Self::any(v, v)
+    }
+
+    fn matches(&self, v: u32) -> bool {
+        self.0
+            .iter()
+            .any(|r| r.start <= v && v <= r.end && (v - r.start).is_multiple_of(r.step))
+    }
+
+    /// Parse a comma separated list of `*`, values, ranges of values
+    /// separated by `range_sep`, and repetitions of those
+    fn parse(
+        s: &str,
+        min: u32,
+        max: u32,
+        range_sep: &str,
+        value: impl Fn(&str) -> Option<u32>,
+    ) -> Option<Self> {
+        let mut ranges = Vec::new();
+        for item in s.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (range, Some(number(step).filter(|&n| n > 0)?)),
+                None => (item, None),
+            };
+            let (start, end) = match range.split_once(range_sep) {
+                _ if range == "*" => (min, max),
+                Some((start, end)) => (value(start)?, value(end)?),
+                None => {
+                    let v = value(range)?;
+                    (v, if step.is_some() { max } else { v })
+                }
+            };
+            if start < min || end > max || start > end {
+                return None;
+            }
+            ranges.push(Range {
+                start,
+                end,
+                step: step.unwrap_or(1),
+            });
+        }
+        Some(Self(ranges))
+    }
+}
+
+fn number(s: &str) -> Option<u32> {
+    match !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+        true => s.parse().ok(),
+        false => None,
+    }
+}
+
+/// Index of `s` in `names`, matching a whole name or its first three
+/// letters, case insensitively
+fn name_index(s: &str, names: &[&str]) -> Option<u32> {
+    let s = s.to_ascii_lowercase();
+    names
+        .iter()
+        .position(|name| *name == s || (s.len() == 3 && name.starts_with(&s)))
+        .map(|i| i as u32)
+}
+
+/// Bitmask of the weekdays, Monday being bit 0, matched by `field` of
+/// values `0..=max` counting from `first`
+fn weekday_mask(field: &Field, max: u32, first: u32) -> u8 {
+    (0..=max)
+        .filter(|&v| field.matches(v))
+        .fold(0, |mask, v| mask | 1 << ((v + first) % 7))
+}
+
+const ALL_WEEKDAYS: u8 = 0x7f;
+
+fn is_date(token: &str) -> bool {
+    token.contains('-')
+        && token
+            .bytes()
+            .all(|b| b.is_ascii_digit() || b"*,./-".contains(&b))
+}
+
+fn is_time(token: &str) -> bool {
+    token.contains(':')
+        && token
+            .bytes()
+            .all(|b| b.is_ascii_digit() || b"*,./:".contains(&b))
+}
+
+fn parse_weekdays(token: &str) -> Option<u8> {
+    let field = Field::parse(token, 0, 6, "..", |s| name_index(s, &WEEKDAYS))?;
+    Some(weekday_mask(&field, 6, 0))
+}
+
+/// A parsed calendar expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalendarSpec {
+    /// Bitmask, Monday being bit 0
+    weekdays: u8,
+    years: Field,
+    months: Field,
+    days: Field,
+    hours: Field,
+    minutes: Field,
+    seconds: Field,
+    /// A day matches if either its day of the month or its weekday does,
+    /// cron's rule when both are restricted
+    day_or_weekday: bool,
+    tz: TimeZone,
+}
+
+impl CalendarSpec {
+    pub fn parse(expr: &str) -> Result<Self, CalendarError> {
+        let tokens = expr.split_whitespace().collect::<Vec<_>>();
+        let Some(&first) = tokens.first() else {
+            return Err(CalendarError::Empty);
+        };
+        if first.starts_with('@') {
+            let (_, fields) = CRON_SHORTHANDS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(first))
+                .ok_or_else(|| CalendarError::Invalid(first.to_owned()))?;
+            let mut expanded = fields.split(' ').collect::<Vec<_>>();
+            expanded.extend(&tokens[1..]);
+            return Self::parse_cron(&expanded);
+        }
+        match tokens.len() {
+            5.. => Self::parse_cron(&tokens),
+            _ => Self::parse_systemd(&tokens),
+        }
+    }
+
+    fn zone(name: Option<&&str>) -> Result<TimeZone, CalendarError> {
+        match name {
+            Some(name) => Ok(TimeZone::named(name)?),
+            None => Ok(TimeZone::local()?),
+        }
+    }
+
+    fn parse_cron(tokens: &[&str]) -> Result<Self, CalendarError> {
+        if tokens.len() > 6 {
+            return Err(CalendarError::Invalid(tokens[6].to_owned()));
+        }
+        let field = |i: usize, min, max, names: &'static [&'static str], first_name| {
+            Field::parse(tokens[i], min, max, "-", |s| {
+                number(s).or_else(|| Some(name_index(s, names)? + first_name))
+            })
+            .ok_or_else(|| CalendarError::Invalid(tokens[i].to_owned()))
+        };
+        let minutes = field(0, 0, 59, &[], 0)?;
+        let hours = field(1, 0, 23, &[], 0)?;
+        let days = field(2, 1, 31, &[], 0)?;
+        let months = field(3, 1, 12, &CRON_MONTHS, 1)?;
+        let weekdays = field(4, 0, 7, &CRON_WEEKDAYS, 0)?;
+        let tz = Self::zone(tokens.get(5))?;
+        Ok(Self {
+            // Sunday is 6 days after Monday
+            weekdays: weekday_mask(&weekdays, 7, 6),
+            years: Field::any(MIN_YEAR, MAX_YEAR),
+            months,
+            days,
+            hours,
+            minutes,
+            seconds: Field::value(0),
+            day_or_weekday: !tokens[2].starts_with('*') && !tokens[4].starts_with('*'),
+            tz,
+        })
+    }
+
+    fn parse_systemd(tokens: &[&str]) -> Result<Self, CalendarError> {
+        let mut tokens = tokens;
+        // the time zone, last
+        let mut tz_name = None;
+        if let [rest @ .., last] = tokens
+            && !rest.is_empty()
+            && parse_weekdays(last).is_none()
+            && !is_date(last)
+            && !is_time(last)
+        {
+            tz_name = Some(last);
+            tokens = rest;
+        }
+        if let [token] = tokens
+            && let Some((_, expanded)) = SHORTHANDS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(token))
+        {
+            let mut expanded = expanded.split(' ').collect::<Vec<_>>();
+            expanded.extend(tz_name);
+            return Self::parse_systemd(&expanded);
+        }
+
+        let mut tokens = tokens.iter().peekable();
+        let weekdays = match tokens.peek().and_then(|token| parse_weekdays(token)) {
+            Some(mask) => {
+                tokens.next();
+                mask
+            }
+            None => ALL_WEEKDAYS,
+        };
+        let mut spec = Self {
+            weekdays,
+            years: Field::any(MIN_YEAR, MAX_YEAR),
+            months: Field::any(1, 12),
+            days: Field::any(1, 31),
+            hours: Field::value(0),
+            minutes: Field::value(0),
+            seconds: Field::value(0),
+            day_or_weekday: false,
+            tz: Self::zone(tz_name)?,
+        };
+        if let Some(token) = tokens.next_if(|token| is_date(token)) {
+            let invalid = || CalendarError::Invalid((*token).to_owned());
+            let parts = token.split('-').collect::<Vec<_>>();
+            let (year, month, day) = match parts[..] {
+                [year, month, day] => (Some(year), month, day),
+                [month, day] => (None, month, day),
+                _ => return Err(invalid()),
+            };
+            if let Some(year) = year {
+                spec.years =
+                    Field::parse(year, MIN_YEAR, MAX_YEAR, "..", number).ok_or_else(invalid)?;
+            }
+            spec.months = Field::parse(month, 1, 12, "..", number).ok_or_else(invalid)?;
+            spec.days = Field::parse(day, 1, 31, "..", number).ok_or_else(invalid)?;
+        }
+        if let Some(token) = tokens.next_if(|token| is_time(token)) {
+            let invalid = || CalendarError::Invalid((*token).to_owned());
+            let parts = token.split(':').collect::<Vec<_>>();
+            let (hour, minute, second) = match parts[..] {
+                [hour, minute, second] => (hour, minute, Some(second)),
+                [hour, minute] => (hour, minute, None),
+                _ => return Err(invalid()),
+            };
+            spec.hours = Field::parse(hour, 0, 23, "..", number).ok_or_else(invalid)?;
+            spec.minutes = Field::parse(minute, 0, 59, "..", number).ok_or_else(invalid)?;
+            if let Some(second) = second {
+                spec.seconds = Field::parse(second, 0, 59, "..", number).ok_or_else(invalid)?;
+            }
+        }
+        match tokens.next() {
+            Some(token) => Err(CalendarError::Invalid((*token).to_owned())),
+            None => Ok(spec),
+        }
+    }
+
+    /// The zone the expression is in
+    pub fn time_zone(&self) -> &TimeZone {
+        &self.tz
+    }
+
+    fn date_matches(&self, days: i64) -> bool {
+        let (year, month, day) = civil_from_days(days);
+        let year_matches = u32::try_from(year).is_ok_and(|year| self.years.matches(year));
+        let weekday_matches = self.weekdays & (1 << weekday(days)) != 0;
+        let day_matches = match self.day_or_weekday {
+            true => self.days.matches(day) || weekday_matches,
+            false => self.days.matches(day) && weekday_matches,
+        };
+        year_matches && self.months.matches(month) && day_matches
+    }
+
+    /// The first matching time of a day from `from`, in seconds since
+    /// midnight
+    fn first_time(&self, from: u32) -> Option<u32> {
+        let (hour, minute, second) = (from / 3600, from / 60 % 60, from % 60);
+        for h in (hour..24).filter(|&h| self.hours.matches(h)) {
+            let first_minute = if h == hour { minute } else { 0 };
+            for m in (first_minute..60).filter(|&m| self.minutes.matches(m)) {
+                let first_second = if h == hour && m == minute { second } else { 0 };
+                if let Some(s) = (first_second..60).find(|&s| self.seconds.matches(s)) {
+                    return Some(h * 3600 + m * 60 + s);
+                }
+            }
+        }
+        None
+    }
+
+    /// The first time the expression matches strictly after `after`, both
+    /// in seconds since the epoch. `None` if it never does again
+    pub fn next_after(&self, after: i64) -> Option<i64> {
+        let from = after + self.tz.offset(after) as i64 + 1;
+        let mut day = from.div_euclid(SECS_PER_DAY);
+        let mut from_time = from.rem_euclid(SECS_PER_DAY) as u32;
+        let last_day = days_from_civil(MAX_YEAR as i64 + 1, 1, 1);
+        while day < last_day {
+            if self.date_matches(day) {
+                while let Some(time) = self.first_time(from_time) {
+                    // skipped, or already passed the first time it happened
+                    if let Some(utc) = self.tz.to_utc(day * SECS_PER_DAY + time as i64)
+                        && utc > after
+                    {
+                        return Some(utc);
+                    }
+                    from_time = time + 1;
+                }
+            }
+            day += 1;
+            from_time = 0;
+        }
+        None
+    }
+}
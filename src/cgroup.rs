@@ -0,0 +1,296 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Cgroups of services.
+//!
+//! Services that need a cgroup of their own get `<own>/<name>`, `<own>`
+//! being the cgroup svlopp was started in. Controllers can only be
+//! enabled for the children of a cgroup without processes, so before the
+//! first one is created svlopp moves itself and its children to the
+//! `<own>/supervisor` leaf, then enables the controllers it can.
+
+use std::{
+    cell::Cell,
+    fmt::Write,
+    fs::{self, File},
+    io,
+    num::{NonZeroU32, NonZeroU64},
+    os::{fd::OwnedFd, unix::fs::chown},
+    path::{Path, PathBuf},
+};
+
+use rustix::fs::{FileType, major, minor, stat};
+use serde::Deserialize;
+
+use crate::logging::LogLevel;
+use crate::pressure::Cgroups;
+use crate::procfs::ProcStat;
+use crate::service::UserGroup;
+use crate::svlogg;
+
+/// Name of the cgroup svlopp moves itself to, under the one it was
+/// started in
+pub(crate) const SUPERVISOR_CGROUP: &str = "supervisor";
+
+/// Period of the `cpu.max` limits set by svlopp, in microseconds
+const CPU_MAX_PERIOD_US: u64 = 100_000;
+
+/// Files of a delegated cgroup its owner writes to, along with the
+/// cgroup directory itself
+const DELEGATED_FILES: [&str; 3] = ["cgroup.procs", "cgroup.threads", "cgroup.subtree_control"];
+
+/// Bandwidth and IOPS limits of a service on a block device, through the
+/// `io.max` file of its cgroup
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct IoMax {
+    /// Path of the device, e.g. `/dev/sda`, resolved to its numbers on
+    /// each start. The io controller only takes whole disks
+    pub(crate) device: PathBuf,
+    /// Bytes read per second
+    #[serde(default)]
+    pub(crate) rbps: Option<NonZeroU64>,
+    /// Bytes written per second
+    #[serde(default)]
+    pub(crate) wbps: Option<NonZeroU64>,
+    /// Read operations per second
+    #[serde(default)]
+    pub(crate) riops: Option<NonZeroU64>,
+    /// Write operations per second
+    #[serde(default)]
+    pub(crate) wiops: Option<NonZeroU64>,
+}
+
+impl IoMax {
+    pub(crate) fn validate(&self) -> io::Result<()> {
+        if !self.device.is_absolute() {
+            return Err(io::Error::other(format!(
+                "io_max device '{}' is not an absolute path",
+                self.device.display()
+            )));
+        }
+        if self.limits().all(|(_, limit)| limit.is_none()) {
+            return Err(io::Error::other(format!(
+                "io_max of device '{}' sets no limit",
+                self.device.display()
+            )));
+        }
+        Ok(())
+    }
+
+    fn limits(&self) -> impl Iterator<Item = (&'static str, Option<NonZeroU64>)> {
+        [
+            ("rbps", self.rbps),
+            ("wbps", self.wbps),
+            ("riops", self.riops),
+            ("wiops", self.wiops),
+        ]
+        .into_iter()
+    }
+
+    /// `MAJ:MIN` of the device, failing if it isn't a block device
+    pub(crate) fn resolve(&self) -> io::Result<(u32, u32)> {
+        let st = stat(&self.device).map_err(|e| {
+            io::Error::other(format!("io_max device '{}': {}", self.device.display(), e))
+        })?;
+        if FileType::from_raw_mode(st.st_mode) != FileType::BlockDevice {
+            return Err(io::Error::other(format!(
+                "io_max device '{}' is not a block device",
+                self.device.display()
+            )));
+        }
+        Ok((major(st.st_rdev), minor(st.st_rdev)))
+    }
+
+    /// The `io.max` line of the limits, e.g. `8:0 rbps=1048576 wiops=100`.
+    /// Limits that are not set are left to the default, `max`
+    fn line(&self) -> io::Result<String> {
+        let (major, minor) = self.resolve()?;
+        let mut line = format!("{}:{}", major, minor);
+        for (key, limit) in self.limits() {
+            if let Some(limit) = limit {
+                let _ = write!(line, " {}={}", key, limit);
+            }
+        }
+        Ok(line)
+    }
+}
+
+/// Creates the cgroups of services
+#[derive(Debug)]
+pub(crate) struct ServiceCgroups {
+    /// The cgroup svlopp was started in
+    dir: PathBuf,
+    /// Whether svlopp moved to its leaf
+    prepared: Cell<bool>,
+}
+
+impl ServiceCgroups {
+    pub(crate) fn new(cgroups: &Cgroups) -> Self {
+        Self {
+            dir: cgroups.path(&cgroups.own),
+            prepared: Cell::new(false),
+        }
+    }
+
+    /// Move svlopp and its children to the supervisor leaf, and enable
+    /// the available controllers for the services cgroups. Processes
+    /// that aren't svlopp's are left alone, the controllers then staying
+    /// disabled
+    fn prepare(&self) -> io::Result<()> {
+        if self.prepared.get() {
+            return Ok(());
+        }
+        let leaf = self.dir.join(SUPERVISOR_CGROUP);
+        create_dir(&leaf)?;
+        let own = std::process::id() as i32;
+        let procs = fs::read_to_string(self.dir.join("cgroup.procs"))?;
+        for pid in procs.lines().filter_map(|line| line.parse::<i32>().ok()) {
+            let ours = pid == own || ProcStat::read(pid).is_ok_and(|stat| stat.ppid == own);
+            if ours {
+                // children may exit in between
+                let _ = fs::write(leaf.join("cgroup.procs"), pid.to_string());
+            }
+        }
+        let controllers = fs::read_to_string(self.dir.join("cgroup.controllers"))?;
+        for controller in controllers.split_whitespace() {
+            if let Err(e) = fs::write(
+                self.dir.join("cgroup.subtree_control"),
+                format!("+{}", controller),
+            ) {
+                svlogg!(
+                    LogLevel::Warn,
+                    "can't enable the {} controller for services: {}",
+                    controller,
+                    e
+                );
+            }
+        }
+        self.prepared.set(true);
+        Ok(())
+    }
+
+    /// Create the cgroup of service `name`, owned by `owner` if it's
+    /// delegated to the service
+    pub(crate) fn create(&self, name: &str, owner: Option<UserGroup>) -> io::Result<ServiceCgroup> {
+        self.prepare()?;
+        let path = self.dir.join(name);
+        create_dir(&path)?;
+        let cgroup = ServiceCgroup { path };
+        if let Some(UserGroup { uid, gid }) = owner {
+            chown(&cgroup.path, Some(uid), Some(gid))?;
+            for file in DELEGATED_FILES {
+                chown(cgroup.path.join(file), Some(uid), Some(gid))?;
+            }
+        }
+        Ok(cgroup)
+    }
+}
+
+fn create_dir(path: &Path) -> io::Result<()> {
+    match fs::create_dir(path) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// The cgroup of a service, removed on drop along with the cgroups the
+/// service created in it. Cgroups still holding processes are left
+#[derive(Debug)]
+pub(crate) struct ServiceCgroup {
+    path: PathBuf,
+}
+
+impl ServiceCgroup {
+    /// `cgroup.procs`, which the service process writes 0 to in order to
+    /// move itself to the cgroup before it execs
+    pub(crate) fn open_procs(&self) -> io::Result<OwnedFd> {
+        Ok(File::options()
+            .write(true)
+            .open(self.path.join("cgroup.procs"))?
+            .into())
+    }
+
+    /// Freeze or thaw the processes of the cgroup and its descendants. The
+    /// kernel completes a freeze asynchronously
+    pub(crate) fn freeze(&self, frozen: bool) -> io::Result<()> {
+        fs::write(
+            self.path.join("cgroup.freeze"),
+            if frozen { "1" } else { "0" },
+        )
+    }
+
+    /// Limit the CPU time of the cgroup to `percent` of one CPU, or lift
+    /// the limit if `None`
+    pub(crate) fn set_cpu_max(&self, percent: Option<NonZeroU32>) -> io::Result<()> {
+        let max = match percent {
+            Some(percent) => format!(
+                "{} {}",
+                u64::from(percent.get()) * CPU_MAX_PERIOD_US / 100,
+                CPU_MAX_PERIOD_US
+            ),
+            None => format!("max {}", CPU_MAX_PERIOD_US),
+        };
+        fs::write(self.path.join("cpu.max"), max)
+    }
+
+    /// Limit the I/O of the cgroup on each device of `limits`
+    pub(crate) fn set_io_max(&self, limits: &[IoMax]) -> io::Result<()> {
+        for limit in limits {
+            // the kernel takes one device per write
+            fs::write(self.path.join("io.max"), limit.line()?)?;
+        }
+        Ok(())
+    }
+
+    /// Move the processes of the cgroup and of its descendants to the
+    /// supervisor leaf, e.g. those of a detached process, for the cgroup
+    /// to be removed without them
+    pub(crate) fn evict(&self) -> io::Result<()> {
+        let Some(dir) = self.path.parent() else {
+            return Ok(());
+        };
+        evict_tree(
+            &self.path,
+            &dir.join(SUPERVISOR_CGROUP).join("cgroup.procs"),
+        )
+    }
+}
+
+/// Move the processes of the cgroup at `path` and of its descendants to
+/// the cgroup whose `cgroup.procs` is `procs`
+fn evict_tree(path: &Path, procs: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(path)?.flatten() {
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            evict_tree(&entry.path(), procs)?;
+        }
+    }
+    let pids = fs::read_to_string(path.join("cgroup.procs"))?;
+    for pid in pids.lines() {
+        // the kernel takes one pid per write
+        match fs::write(procs, pid) {
+            // the process exited in between
+            Err(e) if e.raw_os_error() == Some(rustix::io::Errno::SRCH.raw_os_error()) => {}
+            r => r?,
+        }
+    }
+    Ok(())
+}
+
+impl Drop for ServiceCgroup {
+    fn drop(&mut self) {
+        remove_tree(&self.path);
+    }
+}
+
+/// Remove the cgroup at `path` and its descendants, deepest first
+fn remove_tree(path: &Path) {
+    if let Ok(entries) = fs::read_dir(path) {
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                remove_tree(&entry.path());
+            }
+        }
+    }
+    let _ = fs::remove_dir(path);
+}
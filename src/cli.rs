@@ -2,21 +2,81 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::path::PathBuf;
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
-use crate::logging::LogLevel;
+use svlopp::DEFAULT_RUN_DIR;
 
-const DEFAULT_RUN_DIR: &str = "/run/svlopp";
+use crate::logging::LogLevel;
 
 #[derive(Debug, Clone)]
 pub(crate) struct CliArgs {
-    pub(crate) config_path: PathBuf,
+    pub(crate) config_path: Option<PathBuf>,
+    pub(crate) scan_dir: Option<PathBuf>,
+    pub(crate) strict_config: bool,
     pub(crate) run_dir: PathBuf,
     pub(crate) log_level: LogLevel,
+    pub(crate) per_service_status: bool,
+    pub(crate) status_notify: bool,
+    pub(crate) watchdog_timeout: Option<Duration>,
+    pub(crate) watchdog_abort: bool,
+    pub(crate) shutdown_report: Option<PathBuf>,
+    pub(crate) init: bool,
+    pub(crate) state_dir: Option<PathBuf>,
+    pub(crate) pressure: bool,
+    pub(crate) net_stats: bool,
+    pub(crate) spawner: bool,
+    #[cfg_attr(not(feature = "dbus"), allow(dead_code))]
+    pub(crate) dbus_address: Option<String>,
+    #[cfg_attr(not(feature = "api"), allow(dead_code))]
+    pub(crate) api: bool,
+    #[cfg_attr(not(feature = "tls"), allow(dead_code))]
+    pub(crate) api_remote: Option<ApiRemote>,
 }
 
+/// TCP listener of the HTTP API (`--api-listen`)
+#[cfg_attr(not(feature = "tls"), allow(dead_code))]
+#[derive(Debug, Clone)]
+pub(crate) struct ApiRemote {
+    pub(crate) listen: SocketAddr,
+    pub(crate) tls_cert: PathBuf,
+    pub(crate) tls_key: PathBuf,
+    /// CA certificates of the clients, enabling mutual TLS
+    pub(crate) tls_client_ca: Option<PathBuf>,
+    pub(crate) token_file: Option<PathBuf>,
+}
+
+/// The main loop wakes up once per second, a shorter watchdog timeout
+/// would fire on an idle supervisor
+const MIN_WATCHDOG_TIMEOUT_MS: u64 = 2000;
+
 fn usage() -> ! {
-    eprintln!("usage: svlopp [--run-dir PATH --log-level LEVEL] <config_file>");
+    eprintln!("usage: svlopp [OPTIONS] <config_file>");
+    eprintln!("       svlopp [OPTIONS] --scan-dir DIR [<config_file>]");
+    eprintln!();
+    eprintln!("options:");
+    eprintln!("  --run-dir PATH             runtime directory (default: {DEFAULT_RUN_DIR})");
+    eprintln!("  --log-level LEVEL          error, warn, info or debug (default: info)");
+    eprintln!("  --per-service-status       write one status file per service");
+    eprintln!("  --status-notify            create the status notification socket");
+    eprintln!("  --watchdog-timeout-ms MS   report a main loop stuck for longer than MS");
+    eprintln!("  --watchdog-abort           abort when the watchdog fires");
+    eprintln!("  --shutdown-report PATH     write how services stopped on shutdown to PATH");
+    eprintln!("  --init                     run as a container init");
+    eprintln!("  --state-dir PATH           persist the restart history of services in PATH");
+    eprintln!("  --scan-dir DIR             also run the services of a runit style directory");
+    eprintln!(
+        "  --strict-config            fail on unknown keys and suspicious values in the config"
+    );
+    eprintln!("  --pressure                 publish the cgroup pressure of services");
+    eprintln!("  --net-stats                publish the network counters of services");
+    eprintln!("  --spawner                  fork services from a preforked helper process");
+    eprintln!("  --dbus ADDRESS             own org.svlopp.Manager on the D-Bus bus at ADDRESS");
+    eprintln!("  --api                      serve the HTTP management API on a socket");
+    eprintln!("  --api-listen ADDR:PORT     also serve the HTTP API over TLS on a TCP address");
+    eprintln!("  --api-tls-cert PATH        PEM certificate chain of the TCP listener");
+    eprintln!("  --api-tls-key PATH         PEM private key of the TCP listener");
+    eprintln!("  --api-tls-client-ca PATH   require TCP clients certificates issued by these CAs");
+    eprintln!("  --api-token-file PATH      require TCP clients to present the token in PATH");
     std::process::exit(1);
 }
 
@@ -25,6 +85,25 @@ pub(crate) fn parse() -> CliArgs {
     let mut config_path = None;
     let mut run_dir = None;
     let mut log_level = None;
+    let mut per_service_status = false;
+    let mut status_notify = false;
+    let mut watchdog_timeout = None;
+    let mut watchdog_abort = false;
+    let mut shutdown_report = None;
+    let mut init = false;
+    let mut state_dir = None;
+    let mut scan_dir = None;
+    let mut strict_config = false;
+    let mut pressure = false;
+    let mut net_stats = false;
+    let mut spawner = false;
+    let mut dbus_address = None;
+    let mut api = false;
+    let mut api_listen = None;
+    let mut api_tls_cert = None;
+    let mut api_tls_key = None;
+    let mut api_tls_client_ca = None;
+    let mut api_token_file = None;
 
     while let Some(arg) = args.next() {
         match arg.as_str() {
@@ -35,6 +114,94 @@ pub(crate) fn parse() -> CliArgs {
                 })));
             }
             "--help" => usage(),
+            "--per-service-status" => per_service_status = true,
+            "--status-notify" => status_notify = true,
+            "--watchdog-abort" => watchdog_abort = true,
+            "--init" => init = true,
+            "--pressure" => pressure = true,
+            "--net-stats" => net_stats = true,
+            "--spawner" => spawner = true,
+            "--strict-config" => strict_config = true,
+            "--api" => api = true,
+            "--state-dir" => {
+                state_dir = Some(PathBuf::from(args.next().unwrap_or_else(|| {
+                    eprintln!("--state-dir requires a value");
+                    usage();
+                })));
+            }
+            "--api-listen" => {
+                let value = args.next().unwrap_or_else(|| {
+                    eprintln!("--api-listen requires a value");
+                    usage();
+                });
+                match value.parse::<SocketAddr>() {
+                    Ok(addr) => api_listen = Some(addr),
+                    Err(_) => {
+                        eprintln!("invalid API listen address: {}", value);
+                        usage();
+                    }
+                }
+            }
+            "--api-tls-cert" => {
+                api_tls_cert = Some(PathBuf::from(args.next().unwrap_or_else(|| {
+                    eprintln!("--api-tls-cert requires a value");
+                    usage();
+                })));
+            }
+            "--api-tls-key" => {
+                api_tls_key = Some(PathBuf::from(args.next().unwrap_or_else(|| {
+                    eprintln!("--api-tls-key requires a value");
+                    usage();
+                })));
+            }
+            "--api-tls-client-ca" => {
+                api_tls_client_ca = Some(PathBuf::from(args.next().unwrap_or_else(|| {
+                    eprintln!("--api-tls-client-ca requires a value");
+                    usage();
+                })));
+            }
+            "--api-token-file" => {
+                api_token_file = Some(PathBuf::from(args.next().unwrap_or_else(|| {
+                    eprintln!("--api-token-file requires a value");
+                    usage();
+                })));
+            }
+            "--dbus" => {
+                dbus_address = Some(args.next().unwrap_or_else(|| {
+                    eprintln!("--dbus requires a value");
+                    usage();
+                }));
+            }
+            "--scan-dir" => {
+                scan_dir = Some(PathBuf::from(args.next().unwrap_or_else(|| {
+                    eprintln!("--scan-dir requires a value");
+                    usage();
+                })));
+            }
+            "--shutdown-report" => {
+                shutdown_report = Some(PathBuf::from(args.next().unwrap_or_else(|| {
+                    eprintln!("--shutdown-report requires a value");
+                    usage();
+                })));
+            }
+            "--watchdog-timeout-ms" => {
+                let value = args.next().unwrap_or_else(|| {
+                    eprintln!("--watchdog-timeout-ms requires a value");
+                    usage();
+                });
+                match value.parse::<u64>() {
+                    Ok(ms) if ms >= MIN_WATCHDOG_TIMEOUT_MS => {
+                        watchdog_timeout = Some(Duration::from_millis(ms))
+                    }
+                    _ => {
+                        eprintln!(
+                            "invalid watchdog timeout: {} (minimum {}ms)",
+                            value, MIN_WATCHDOG_TIMEOUT_MS
+                        );
+                        usage();
+                    }
+                }
+            }
             "--log-level" => {
                 log_level = match args
                     .next()
@@ -67,9 +234,60 @@ pub(crate) fn parse() -> CliArgs {
             }
         }
     }
+    if config_path.is_none() && scan_dir.is_none() {
+        usage();
+    }
+    if cfg!(not(feature = "dbus")) && dbus_address.is_some() {
+        eprintln!("--dbus requires svlopp to be built with the dbus feature");
+        usage();
+    }
+    if cfg!(not(feature = "api")) && api {
+        eprintln!("--api requires svlopp to be built with the api feature");
+        usage();
+    }
+    let api_remote = match (api_listen, api_tls_cert, api_tls_key) {
+        (None, None, None) if api_tls_client_ca.is_none() && api_token_file.is_none() => None,
+        (Some(listen), Some(tls_cert), Some(tls_key)) => {
+            if cfg!(not(feature = "tls")) {
+                eprintln!("--api-listen requires svlopp to be built with the tls feature");
+                usage();
+            }
+            // the TCP listener must not be open to anyone able to connect
+            if api_tls_client_ca.is_none() && api_token_file.is_none() {
+                eprintln!("--api-listen requires --api-tls-client-ca or --api-token-file");
+                usage();
+            }
+            Some(ApiRemote {
+                listen,
+                tls_cert,
+                tls_key,
+                tls_client_ca: api_tls_client_ca,
+                token_file: api_token_file,
+            })
+        }
+        _ => {
+            eprintln!("--api-listen, --api-tls-cert and --api-tls-key go together");
+            usage();
+        }
+    };
     CliArgs {
-        config_path: config_path.unwrap_or_else(|| usage()),
+        config_path,
+        scan_dir,
+        strict_config,
         run_dir: run_dir.unwrap_or_else(|| PathBuf::from(DEFAULT_RUN_DIR)),
         log_level: log_level.unwrap_or(LogLevel::Info),
+        per_service_status,
+        status_notify,
+        watchdog_timeout,
+        watchdog_abort,
+        shutdown_report,
+        init,
+        state_dir,
+        pressure,
+        net_stats,
+        spawner,
+        dbus_address,
+        api: api || api_remote.is_some(),
+        api_remote,
     }
 }
@@ -9,11 +9,13 @@ use std::{
 };
 
 use rustix::fs::{CWD, Mode, OFlags, mkfifoat, open};
+pub(crate) use svlopp::wire::{ControlCommand, ControlOp, ControlProtocolError};
+use svlopp::wire::{WIRE_COMMAND_SIZE, split_frames};
 
-const OP_STOP: u8 = 0x41;
-const OP_START: u8 = 0x42;
-const OP_RESTART: u8 = 0x43;
-const WIRE_COMMAND_SIZE: usize = 9;
+use crate::{arena::Arena, utils::retry_eintr};
+
+/// Maximum number of commands read from the control fifo at once
+const COMMAND_BATCH_LEN: usize = 64;
 
 /// Create (or reuse) the control fifo at `path` and return the read and
 /// write ends.
@@ -39,109 +41,33 @@ pub(crate) fn create_control_fifo(path: &Path) -> io::Result<(OwnedFd, OwnedFd)>
     Ok((read_end_fd, write_end_fd))
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum ControlProtocolError {
-    InvalidOp(u8),
-    PartialFrame(usize),
-}
-
-impl std::fmt::Display for ControlProtocolError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::InvalidOp(op) => write!(f, "invalid opcode: 0x{:02x}", op),
-            Self::PartialFrame(n) => {
-                write!(f, "partial control frame ({} bytes)", n)
-            }
-        }
-    }
-}
-
-/// Control errors encoding.
-///
-/// Control errors fall into two categories:
-/// - `Io`: kernel level I/O failures
-/// - `Invalid`: protocol level validation failures
-#[derive(Debug)]
-pub(crate) enum ControlError {
-    Io(io::Error),
-    InvalidCommand(ControlProtocolError),
-}
-
-impl From<io::Error> for ControlError {
-    #[inline(always)]
-    fn from(value: io::Error) -> Self {
-        Self::Io(value)
-    }
-}
-
-/// Control operations
-#[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub(crate) enum ControlOp {
-    Stop = OP_STOP,
-    Start = OP_START,
-    Restart = OP_RESTART,
-}
-
-impl std::fmt::Display for ControlOp {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Stop => write!(f, "stop"),
-            Self::Start => write!(f, "start"),
-            Self::Restart => write!(f, "restart"),
-        }
-    }
-}
-
-/// Command wire-format representation
-#[derive(Debug, Clone, Copy)]
-pub(crate) struct ControlCommand {
-    pub(crate) op: ControlOp,
-    pub(crate) service_id: u64,
-}
-
-impl ControlCommand {
-    #[inline(always)]
-    pub(crate) fn new(op: ControlOp, service_id: u64) -> Self {
-        Self { op, service_id }
-    }
-}
-
-/// Read a command from `fd`.
+/// Read the commands available on `fd`, up to `COMMAND_BATCH_LEN` of
+/// them, decoded to `arena`. Each frame decodes to a command or to the
+/// error it's invalid with, so that an invalid frame doesn't take the
+/// others down with it.
 ///
-/// TODO: with 9-byte frames, a single pipe buffer can hold thousands of
-/// commands. Currently we read one command per epoll wake (not losing them
-/// since it's level-triggered): if that becomes a bottleneck we could
-/// add a `read_control_command_batch` function to consume more commands
-/// per iteration
-pub(crate) fn read_control_command(
+/// Frames are written to the fifo at once, which is atomic for pipes,
+/// and the read size is a multiple of the frame size: a read doesn't
+/// split frames. Commands left in the pipe are read on the next epoll
+/// wake, as it's level-triggered
+pub(crate) fn read_control_commands<'a>(
     fd: BorrowedFd<'_>,
-) -> Result<Option<ControlCommand>, ControlError> {
-    let mut buf = [0u8; WIRE_COMMAND_SIZE];
-    match rustix::io::read(fd, &mut buf) {
-        Ok(n) if n == WIRE_COMMAND_SIZE => {
-            let op = match buf[0] {
-                OP_STOP => ControlOp::Stop,
-                OP_START => ControlOp::Start,
-                OP_RESTART => ControlOp::Restart,
-                other => {
-                    return Err(ControlError::InvalidCommand(
-                        ControlProtocolError::InvalidOp(other),
-                    ));
-                }
-            };
-            let mut svc_id_bytes = [0u8; 8];
-            svc_id_bytes.copy_from_slice(&buf[1..9]);
-            Ok(Some(ControlCommand::new(
-                op,
-                u64::from_le_bytes(svc_id_bytes),
-            )))
-        }
-        Ok(0) => Ok(None), // should never happen as we keep the write end open
-        Ok(n) => Err(ControlError::InvalidCommand(
-            ControlProtocolError::PartialFrame(n),
-        )),
-        Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
-        Err(e) => Err(ControlError::Io(e.into())),
-    }
+    arena: &'a Arena,
+) -> io::Result<&'a [Result<ControlCommand, ControlProtocolError>]> {
+    let mut buf = [0u8; WIRE_COMMAND_SIZE * COMMAND_BATCH_LEN];
+    // no `EOF` as we keep the write end open
+    let n = match retry_eintr(|| rustix::io::read(fd, &mut buf)) {
+        Ok(n) => n,
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => 0,
+        Err(e) => return Err(e.into()),
+    };
+    let frames = split_frames(&buf[..n]);
+    Ok(arena.alloc_iter(
+        frames.len(),
+        frames.map(|frame| {
+            frame
+                .map_err(ControlProtocolError::PartialFrame)
+                .and_then(ControlCommand::try_from)
+        }),
+    ))
 }
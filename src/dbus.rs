@@ -0,0 +1,751 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A minimal D-Bus client, exposing the `org.svlopp.Manager` service.
+//!
+//! Only what svlopp needs is implemented: `EXTERNAL` authentication over
+//! a unix socket, and the marshalling of the few types used by its
+//! methods and signals. The connection is driven by the main loop like
+//! any other fd, method calls are dispatched to `apply_control_op` as
+//! control FIFO commands are.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io,
+    os::fd::{AsFd, BorrowedFd, OwnedFd},
+    path::Path,
+    sync::Arc,
+};
+
+use rustix::fs::{OFlags, fcntl_getfl, fcntl_setfl};
+use rustix::io::{Errno, read};
+use rustix::net::{
+    AddressFamily, SendFlags, SocketAddrUnix, SocketFlags, SocketType, connect, send, socket_with,
+};
+
+use crate::arena::Arena;
+use crate::control::ControlOp;
+use crate::logging::LogLevel;
+use crate::service::{ServiceRegistry, SpawnContext, apply_control_op};
+use crate::svlogg;
+use crate::utils::retry_eintr;
+
+/// Well-known name owned by svlopp on the bus
+const BUS_NAME: &str = "org.svlopp.Manager";
+const OBJECT_PATH: &str = "/org/svlopp/Manager";
+const INTERFACE: &str = "org.svlopp.Manager";
+
+const INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+ <interface name="org.svlopp.Manager">
+  <method name="StartUnit"><arg name="name" type="s" direction="in"/></method>
+  <method name="StopUnit"><arg name="name" type="s" direction="in"/></method>
+  <method name="RestartUnit"><arg name="name" type="s" direction="in"/></method>
+  <method name="FreezeUnit"><arg name="name" type="s" direction="in"/></method>
+  <method name="ThawUnit"><arg name="name" type="s" direction="in"/></method>
+  <method name="ListUnits"><arg name="units" type="a(stss)" direction="out"/></method>
+  <signal name="UnitStateChanged">
+   <arg name="name" type="s"/><arg name="state" type="s"/><arg name="detail" type="s"/>
+  </signal>
+ </interface>
+ <interface name="org.freedesktop.DBus.Introspectable">
+  <method name="Introspect"><arg name="xml" type="s" direction="out"/></method>
+ </interface>
+ <interface name="org.freedesktop.DBus.Peer">
+  <method name="Ping"/>
+ </interface>
+</node>
+"#;
+
+/// Maximum size of a message, as defined by the specification
+const MAX_MESSAGE_LEN: usize = 128 * 1024 * 1024;
+
+const MSG_METHOD_CALL: u8 = 1;
+const MSG_METHOD_RETURN: u8 = 2;
+const MSG_ERROR: u8 = 3;
+const MSG_SIGNAL: u8 = 4;
+
+const FLAG_NO_REPLY_EXPECTED: u8 = 0x1;
+
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SENDER: u8 = 7;
+const FIELD_SIGNATURE: u8 = 8;
+
+/// `DBUS_NAME_FLAG_DO_NOT_QUEUE`
+const NAME_FLAG_DO_NOT_QUEUE: u32 = 0x4;
+/// `DBUS_REQUEST_NAME_REPLY_PRIMARY_OWNER`
+const NAME_PRIMARY_OWNER: u32 = 1;
+
+/// Appends values to a buffer, aligned as the wire format requires.
+/// Alignments are relative to the start of the buffer, which is always 8
+/// bytes aligned within a message
+#[derive(Debug, Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn pad(&mut self, align: usize) {
+        while !self.buf.len().is_multiple_of(align) {
+            self.buf.push(0);
+        }
+    }
+
+    fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.pad(4);
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.pad(8);
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    /// A string or an object path
+    fn str(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn signature(&mut self, s: &str) {
+        self.buf.push(s.len() as u8);
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+    }
+
+    /// Start an array of elements aligned to `align`, returns what
+    /// `end_array` needs to fill in its length
+    fn begin_array(&mut self, align: usize) -> (usize, usize) {
+        self.u32(0);
+        let len_pos = self.buf.len() - 4;
+        self.pad(align);
+        (len_pos, self.buf.len())
+    }
+
+    fn end_array(&mut self, (len_pos, start): (usize, usize)) {
+        let len = (self.buf.len() - start) as u32;
+        self.buf[len_pos..len_pos + 4].copy_from_slice(&len.to_le_bytes());
+    }
+
+    /// A header field, a `(yv)` struct
+    fn field_str(&mut self, code: u8, sig: &str, value: &str) {
+        self.pad(8);
+        self.u8(code);
+        self.signature(sig);
+        match sig {
+            "g" => self.signature(value),
+            _ => self.str(value),
+        }
+    }
+
+    fn field_u32(&mut self, code: u8, value: u32) {
+        self.pad(8);
+        self.u8(code);
+        self.signature("u");
+        self.u32(value);
+    }
+}
+
+/// Reads values from a message, in its endianness
+#[derive(Debug)]
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn align(&mut self, align: usize) -> Option<()> {
+        self.pos = self.pos.next_multiple_of(align);
+        (self.pos <= self.buf.len()).then_some(())
+    }
+
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.buf.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.align(4)?;
+        let bytes = self.bytes(4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn str(&mut self) -> Option<&'a str> {
+        let len = self.u32()? as usize;
+        let s = std::str::from_utf8(self.bytes(len)?).ok()?;
+        self.bytes(1)?;
+        Some(s)
+    }
+
+    fn signature(&mut self) -> Option<&'a str> {
+        let len = self.u8()? as usize;
+        let s = std::str::from_utf8(self.bytes(len)?).ok()?;
+        self.bytes(1)?;
+        Some(s)
+    }
+}
+
+/// The parts of a received message svlopp looks at
+#[derive(Debug, Default)]
+struct Message<'a> {
+    kind: u8,
+    flags: u8,
+    serial: u32,
+    path: Option<&'a str>,
+    interface: Option<&'a str>,
+    member: Option<&'a str>,
+    error_name: Option<&'a str>,
+    reply_serial: Option<u32>,
+    sender: Option<&'a str>,
+    signature: &'a str,
+    body: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Message<'a> {
+    /// Total length of the message starting `buf`, once its fixed header
+    /// has been received
+    fn len(buf: &[u8]) -> Option<io::Result<usize>> {
+        let header = buf.get(..16)?;
+        let read_u32 = |at: usize| {
+            let bytes = header[at..at + 4].try_into().expect("4 bytes");
+            if header[0] == b'B' {
+                u32::from_be_bytes(bytes)
+            } else {
+                u32::from_le_bytes(bytes)
+            }
+        };
+        let fields_len = read_u32(12) as usize;
+        let len = (16 + fields_len).next_multiple_of(8) + read_u32(4) as usize;
+        if len > MAX_MESSAGE_LEN {
+            return Some(Err(io::Error::other("D-Bus message too long")));
+        }
+        Some(Ok(len))
+    }
+
+    /// Parse the complete message `buf`
+    fn parse(buf: &'a [u8]) -> Option<Self> {
+        let big_endian = match buf.first()? {
+            b'l' => false,
+            b'B' => true,
+            _ => return None,
+        };
+        let mut r = Reader {
+            buf,
+            pos: 1,
+            big_endian,
+        };
+        let mut msg = Message {
+            kind: r.u8()?,
+            flags: r.u8()?,
+            big_endian,
+            ..Default::default()
+        };
+        let _version = r.u8()?;
+        let body_len = r.u32()? as usize;
+        msg.serial = r.u32()?;
+        let fields_end = r.u32()? as usize + 16;
+        while r.pos < fields_end {
+            r.align(8)?;
+            let code = r.u8()?;
+            match (code, r.signature()?) {
+                (FIELD_REPLY_SERIAL, "u") => msg.reply_serial = Some(r.u32()?),
+                (_, "u") => {
+                    r.u32()?;
+                }
+                (FIELD_SIGNATURE, "g") => msg.signature = r.signature()?,
+                (_, "g") => {
+                    r.signature()?;
+                }
+                (code, "s" | "o") => {
+                    let value = Some(r.str()?);
+                    match code {
+                        FIELD_PATH => msg.path = value,
+                        FIELD_INTERFACE => msg.interface = value,
+                        FIELD_MEMBER => msg.member = value,
+                        FIELD_ERROR_NAME => msg.error_name = value,
+                        FIELD_SENDER => msg.sender = value,
+                        _ => {}
+                    }
+                }
+                _ => return None,
+            }
+        }
+        r.align(8)?;
+        msg.body = r.bytes(body_len)?;
+        Some(msg)
+    }
+
+    /// The first argument of the body, if it's a string
+    fn string_arg(&self) -> Option<&'a str> {
+        if !self.signature.starts_with('s') {
+            return None;
+        }
+        Reader {
+            buf: self.body,
+            pos: 0,
+            big_endian: self.big_endian,
+        }
+        .str()
+    }
+
+    /// The first argument of the body, if it's an `u32`
+    fn u32_arg(&self) -> Option<u32> {
+        if !self.signature.starts_with('u') {
+            return None;
+        }
+        Reader {
+            buf: self.body,
+            pos: 0,
+            big_endian: self.big_endian,
+        }
+        .u32()
+    }
+}
+
+/// Header fields of an outgoing message
+#[derive(Debug, Default)]
+struct Header<'a> {
+    kind: u8,
+    path: Option<&'a str>,
+    interface: Option<&'a str>,
+    member: Option<&'a str>,
+    error_name: Option<&'a str>,
+    reply_serial: Option<u32>,
+    destination: Option<&'a str>,
+}
+
+/// Connect to the bus at `address`, e.g. `unix:path=/run/dbus/system_bus_socket`
+/// or `unix:abstract=name`. Only the first of `;` separated addresses is
+/// used
+fn connect_address(address: &str) -> io::Result<OwnedFd> {
+    let invalid = || io::Error::other(format!("unsupported D-Bus address '{}'", address));
+    let first = address.split(';').next().unwrap_or_default();
+    let params = first.strip_prefix("unix:").ok_or_else(invalid)?;
+    let addr = params
+        .split(',')
+        .find_map(|param| match param.split_once('=')? {
+            ("path", path) => Some(SocketAddrUnix::new(Path::new(path))),
+            ("abstract", name) => Some(SocketAddrUnix::new_abstract_name(name.as_bytes())),
+            _ => None,
+        })
+        .ok_or_else(invalid)??;
+    let fd = socket_with(
+        AddressFamily::UNIX,
+        SocketType::STREAM,
+        SocketFlags::CLOEXEC,
+        None,
+    )?;
+    connect(&fd, &addr)?;
+    Ok(fd)
+}
+
+/// Read a `\r\n` terminated line of the authentication protocol
+fn read_auth_line(fd: BorrowedFd<'_>) -> io::Result<String> {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    while !line.ends_with(b"\r\n") {
+        if retry_eintr(|| read(fd, &mut byte))? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        line.push(byte[0]);
+    }
+    line.truncate(line.len() - 2);
+    String::from_utf8(line).map_err(io::Error::other)
+}
+
+/// svlopp connection to the bus, owning `org.svlopp.Manager`
+#[derive(Debug)]
+pub(crate) struct DbusManager {
+    fd: OwnedFd,
+    serial: u32,
+    /// Received bytes not yet parsed as complete messages
+    rbuf: Vec<u8>,
+    /// Bytes not sent yet because the socket buffer was full, sent on the
+    /// next call, at the latest on the next timerfd tick
+    wbuf: Vec<u8>,
+    /// Last state published for each service, to signal changes
+    states: HashMap<Arc<str>, String>,
+}
+
+impl DbusManager {
+    /// Connect to the bus, authenticate and request the well-known name.
+    /// This blocks until the bus replied, which it does right away
+    pub(crate) fn connect(address: &str) -> io::Result<Self> {
+        let fd = connect_address(address)?;
+        let uid = rustix::process::getuid().as_raw();
+        let hex_uid: String = uid
+            .to_string()
+            .bytes()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let auth = format!("\0AUTH EXTERNAL {}\r\n", hex_uid);
+        retry_eintr(|| send(&fd, auth.as_bytes(), SendFlags::NOSIGNAL))?;
+        let reply = read_auth_line(fd.as_fd())?;
+        if !reply.starts_with("OK ") {
+            return Err(io::Error::other(format!(
+                "D-Bus authentication failed: {}",
+                reply
+            )));
+        }
+        retry_eintr(|| send(&fd, b"BEGIN\r\n", SendFlags::NOSIGNAL))?;
+
+        let mut manager = Self {
+            fd,
+            serial: 0,
+            rbuf: Vec::new(),
+            wbuf: Vec::new(),
+            states: HashMap::new(),
+        };
+        let hello = manager.call_bus("Hello", |_| {})?;
+        manager.wait_reply(hello)?;
+        let request = manager.call_bus("RequestName", |body| {
+            body.str(BUS_NAME);
+            body.u32(NAME_FLAG_DO_NOT_QUEUE);
+        })?;
+        let reply = manager.wait_reply(request)?;
+        if reply != Some(NAME_PRIMARY_OWNER) {
+            return Err(io::Error::other(format!(
+                "{} is already owned on the bus",
+                BUS_NAME
+            )));
+        }
+        let flags = fcntl_getfl(&manager.fd)?;
+        fcntl_setfl(&manager.fd, flags | OFlags::NONBLOCK)?;
+        Ok(manager)
+    }
+
+    /// The bus connection, to be registered with epoll
+    #[inline(always)]
+    pub(crate) fn fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+
+    /// Call a method of the bus itself, returning the call serial
+    fn call_bus(&mut self, member: &str, body: impl FnOnce(&mut Writer)) -> io::Result<u32> {
+        let mut w = Writer::default();
+        body(&mut w);
+        let signature = match member {
+            "RequestName" => "su",
+            _ => "",
+        };
+        self.queue(
+            Header {
+                kind: MSG_METHOD_CALL,
+                path: Some("/org/freedesktop/DBus"),
+                interface: Some("org.freedesktop.DBus"),
+                member: Some(member),
+                destination: Some("org.freedesktop.DBus"),
+                ..Default::default()
+            },
+            signature,
+            &w.buf,
+        );
+        self.send_pending()?;
+        Ok(self.serial)
+    }
+
+    /// Wait for the reply to the call `serial`, while connecting. Returns
+    /// its first argument if it's an `u32`
+    fn wait_reply(&mut self, serial: u32) -> io::Result<Option<u32>> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            while let Some(len) = Message::len(&self.rbuf) {
+                let len = len?;
+                if self.rbuf.len() < len {
+                    break;
+                }
+                let msg: Vec<u8> = self.rbuf.drain(..len).collect();
+                let msg = Message::parse(&msg)
+                    .ok_or_else(|| io::Error::other("malformed D-Bus message"))?;
+                if msg.reply_serial != Some(serial) {
+                    continue;
+                }
+                if msg.kind == MSG_ERROR {
+                    return Err(io::Error::other(format!(
+                        "D-Bus error: {} {}",
+                        msg.error_name.unwrap_or_default(),
+                        msg.string_arg().unwrap_or_default()
+                    )));
+                }
+                return Ok(msg.u32_arg());
+            }
+            let n = retry_eintr(|| read(&self.fd, &mut chunk))?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.rbuf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    /// Append a message to the send buffer
+    fn queue(&mut self, header: Header<'_>, signature: &str, body: &[u8]) {
+        self.serial = self.serial.wrapping_add(1).max(1);
+        let mut w = Writer::default();
+        w.u8(b'l');
+        w.u8(header.kind);
+        w.u8(0);
+        w.u8(1);
+        w.u32(body.len() as u32);
+        w.u32(self.serial);
+        let fields = w.begin_array(8);
+        if let Some(path) = header.path {
+            w.field_str(FIELD_PATH, "o", path);
+        }
+        if let Some(interface) = header.interface {
+            w.field_str(FIELD_INTERFACE, "s", interface);
+        }
+        if let Some(member) = header.member {
+            w.field_str(FIELD_MEMBER, "s", member);
+        }
+        if let Some(error_name) = header.error_name {
+            w.field_str(FIELD_ERROR_NAME, "s", error_name);
+        }
+        if let Some(reply_serial) = header.reply_serial {
+            w.field_u32(FIELD_REPLY_SERIAL, reply_serial);
+        }
+        if let Some(destination) = header.destination {
+            w.field_str(FIELD_DESTINATION, "s", destination);
+        }
+        if !signature.is_empty() {
+            w.field_str(FIELD_SIGNATURE, "g", signature);
+        }
+        w.end_array(fields);
+        w.pad(8);
+        self.wbuf.extend_from_slice(&w.buf);
+        self.wbuf.extend_from_slice(body);
+    }
+
+    /// Send as much of the send buffer as the socket takes
+    pub(crate) fn send_pending(&mut self) -> io::Result<()> {
+        while !self.wbuf.is_empty() {
+            match send(&self.fd, &self.wbuf, SendFlags::NOSIGNAL) {
+                Ok(n) => {
+                    self.wbuf.drain(..n);
+                }
+                Err(Errno::AGAIN) => return Ok(()),
+                Err(Errno::INTR) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle the pending messages. Returns whether a method call changed
+    /// the state of a service
+    pub(crate) fn handle(
+        &mut self,
+        registry: &mut ServiceRegistry,
+        ctx: &SpawnContext,
+        ps_dir: &Path,
+    ) -> io::Result<bool> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            match read(&self.fd, &mut chunk) {
+                Ok(0) => return Err(io::Error::other("the D-Bus connection was closed")),
+                Ok(n) => self.rbuf.extend_from_slice(&chunk[..n]),
+                Err(Errno::AGAIN) => break,
+                Err(Errno::INTR) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let mut changed = false;
+        while let Some(len) = Message::len(&self.rbuf) {
+            let len = len?;
+            if self.rbuf.len() < len {
+                break;
+            }
+            let raw: Vec<u8> = self.rbuf.drain(..len).collect();
+            let Some(msg) = Message::parse(&raw) else {
+                return Err(io::Error::other("malformed D-Bus message"));
+            };
+            if msg.kind == MSG_METHOD_CALL {
+                changed |= self.dispatch(&msg, registry, ctx, ps_dir);
+            }
+        }
+        self.send_pending()?;
+        Ok(changed)
+    }
+
+    /// Reply to the method call `msg`
+    fn dispatch(
+        &mut self,
+        msg: &Message<'_>,
+        registry: &mut ServiceRegistry,
+        ctx: &SpawnContext,
+        ps_dir: &Path,
+    ) -> bool {
+        let mut body = Writer::default();
+        let mut signature = "";
+        let mut changed = false;
+        let result = match (msg.path, msg.interface, msg.member) {
+            (_, Some("org.freedesktop.DBus.Peer") | None, Some("Ping")) => Ok(()),
+            (
+                Some(OBJECT_PATH),
+                Some("org.freedesktop.DBus.Introspectable") | None,
+                Some("Introspect"),
+            ) => {
+                signature = "s";
+                body.str(INTROSPECTION);
+                Ok(())
+            }
+            (Some(OBJECT_PATH), Some(INTERFACE) | None, Some("ListUnits")) => {
+                signature = "a(stss)";
+                let units = body.begin_array(8);
+                let mut names: Vec<_> = registry.services().collect();
+                names.sort_by_key(|svc| svc.id);
+                for svc in names {
+                    let state = svc.state.to_string();
+                    let (state, detail) = state.split_once(' ').unwrap_or((&state, ""));
+                    body.pad(8);
+                    body.str(&svc.name);
+                    body.u64(svc.id);
+                    body.str(state);
+                    body.str(detail);
+                }
+                body.end_array(units);
+                Ok(())
+            }
+            (
+                Some(OBJECT_PATH),
+                Some(INTERFACE) | None,
+                Some(
+                    member @ ("StartUnit" | "StopUnit" | "RestartUnit" | "FreezeUnit" | "ThawUnit"),
+                ),
+            ) => {
+                let op = match member {
+                    "StartUnit" => ControlOp::Start,
+                    "StopUnit" => ControlOp::Stop,
+                    "FreezeUnit" => ControlOp::Pause,
+                    "ThawUnit" => ControlOp::Resume,
+                    _ => ControlOp::Restart,
+                };
+                match msg.string_arg() {
+                    None => Err((
+                        "org.freedesktop.DBus.Error.InvalidArgs",
+                        format!("{} expects a unit name", member),
+                    )),
+                    Some(name) => match registry.get_by_name(name).map(|svc| svc.id) {
+                        None => Err((
+                            "org.svlopp.Error.NoSuchUnit",
+                            format!("unit '{}' not found", name),
+                        )),
+                        Some(svc_id) => {
+                            svlogg!(
+                                LogLevel::Debug,
+                                "D-Bus {} of '{}' from {}",
+                                member,
+                                name,
+                                msg.sender.unwrap_or("?")
+                            );
+                            changed = true;
+                            apply_control_op(registry, svc_id, op, ctx, ps_dir).map_err(|e| {
+                                let error = match e.kind() {
+                                    io::ErrorKind::QuotaExceeded => {
+                                        "org.freedesktop.DBus.Error.LimitsExceeded"
+                                    }
+                                    _ => "org.freedesktop.DBus.Error.Failed",
+                                };
+                                (error, format!("failed to {} '{}': {}", op, name, e))
+                            })
+                        }
+                    },
+                }
+            }
+            (_, _, member) => Err((
+                "org.freedesktop.DBus.Error.UnknownMethod",
+                format!("unknown method {}", member.unwrap_or_default()),
+            )),
+        };
+        if msg.flags & FLAG_NO_REPLY_EXPECTED != 0 {
+            return changed;
+        }
+        let sender = msg.sender;
+        match result {
+            Ok(()) => self.queue(
+                Header {
+                    kind: MSG_METHOD_RETURN,
+                    reply_serial: Some(msg.serial),
+                    destination: sender,
+                    ..Default::default()
+                },
+                signature,
+                &body.buf,
+            ),
+            Err((error_name, message)) => {
+                let mut body = Writer::default();
+                body.str(&message);
+                self.queue(
+                    Header {
+                        kind: MSG_ERROR,
+                        error_name: Some(error_name),
+                        reply_serial: Some(msg.serial),
+                        destination: sender,
+                        ..Default::default()
+                    },
+                    "s",
+                    &body.buf,
+                );
+            }
+        }
+        changed
+    }
+
+    /// Emit `UnitStateChanged` for the services whose state changed since
+    /// the last call
+    pub(crate) fn notify(&mut self, registry: &ServiceRegistry, arena: &Arena) {
+        for svc in registry.services() {
+            let Ok(state) = arena.alloc_fmt(|w| write!(w, "{}", svc.state)) else {
+                continue;
+            };
+            if self.states.get(&svc.name).map(String::as_str) == Some(state) {
+                continue;
+            }
+            let (kind, detail) = state.split_once(' ').unwrap_or((state, ""));
+            let mut body = Writer::default();
+            body.str(&svc.name);
+            body.str(kind);
+            body.str(detail);
+            self.queue(
+                Header {
+                    kind: MSG_SIGNAL,
+                    path: Some(OBJECT_PATH),
+                    interface: Some(INTERFACE),
+                    member: Some("UnitStateChanged"),
+                    ..Default::default()
+                },
+                "sss",
+                &body.buf,
+            );
+            self.states.insert(Arc::clone(&svc.name), state.to_owned());
+        }
+        self.states
+            .retain(|name, _| registry.get_by_name(name).is_some());
+        if let Err(e) = self.send_pending() {
+            svlogg!(LogLevel::Warn, "failed to send D-Bus signals: {}", e);
+        }
+    }
+}
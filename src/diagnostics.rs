@@ -0,0 +1,222 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Crash-loop diagnostics.
+//!
+//! When a service hits its `start_limit`, what led to it is written to a
+//! bundle in the diagnostics directory of the run directory, so that it
+//! outlives log rotation and supervisor restarts.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use crate::service::{Service, ServiceStopReason};
+use crate::status::{StatusFilePath, write_status_file};
+use crate::utils::{read_log_tail, timestamp};
+
+/// Number of events kept per service
+const MAX_HISTORY_EVENTS: usize = 16;
+
+/// Number of log lines included in a bundle
+const LOG_TAIL_LINES: usize = 50;
+
+/// CPU time used by a reaped process, in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CpuTime {
+    pub(crate) user_ms: u64,
+    pub(crate) sys_ms: u64,
+}
+
+impl CpuTime {
+    /// CPU time used by the reaped children of the supervisor and their
+    /// reaped descendants. Its difference across a `waitpid` is what the
+    /// reaped child used
+    pub(crate) fn children() -> Option<Self> {
+        let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+        // safe as `getrusage` only writes to `usage`, which is only read
+        // once it succeeded
+        if unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, usage.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        let usage = unsafe { usage.assume_init() };
+        let ms = |tv: libc::timeval| tv.tv_sec as u64 * 1000 + tv.tv_usec as u64 / 1000;
+        Some(Self {
+            user_ms: ms(usage.ru_utime),
+            sys_ms: ms(usage.ru_stime),
+        })
+    }
+
+    pub(crate) fn since(self, earlier: Self) -> Self {
+        Self {
+            user_ms: self.user_ms.saturating_sub(earlier.user_ms),
+            sys_ms: self.sys_ms.saturating_sub(earlier.sys_ms),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum HistoryKind {
+    Started {
+        pid: i32,
+    },
+    Exited {
+        reason: ServiceStopReason,
+        uptime_ms: Option<u64>,
+        cpu: Option<CpuTime>,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HistoryEvent {
+    /// Wall clock milliseconds since the epoch
+    at_ms: u64,
+    kind: HistoryKind,
+}
+
+/// The last starts and exits of the processes of a service, oldest first
+#[derive(Debug, Default)]
+pub(crate) struct ServiceHistory {
+    events: VecDeque<HistoryEvent>,
+}
+
+impl ServiceHistory {
+    fn push(&mut self, kind: HistoryKind) {
+        let (secs, nsecs) = timestamp();
+        if self.events.len() == MAX_HISTORY_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(HistoryEvent {
+            at_ms: (secs * 1000 + nsecs / 1_000_000) as u64,
+            kind,
+        });
+    }
+
+    pub(crate) fn started(&mut self, pid: i32) {
+        self.push(HistoryKind::Started { pid });
+    }
+
+    pub(crate) fn exited(
+        &mut self,
+        reason: ServiceStopReason,
+        uptime_ms: Option<u64>,
+        cpu: Option<CpuTime>,
+    ) {
+        self.push(HistoryKind::Exited {
+            reason,
+            uptime_ms,
+            cpu,
+        });
+    }
+}
+
+/// Keys of the environment of the service process that are added (`+`),
+/// removed (`-`) or changed (`~`) from the environment of the supervisor.
+/// Values are left out, as they may hold secrets
+fn env_diff(svc: &Service) -> io::Result<Vec<String>> {
+    let parse = |entry: &[u8]| -> (Vec<u8>, Vec<u8>) {
+        match entry.iter().position(|&b| b == b'=') {
+            Some(i) => (entry[..i].to_vec(), entry[i + 1..].to_vec()),
+            None => (entry.to_vec(), Vec::new()),
+        }
+    };
+    let ours: BTreeMap<Vec<u8>, Vec<u8>> = std::env::vars_os()
+        .map(|(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec()))
+        .collect();
+    let theirs: BTreeMap<Vec<u8>, Vec<u8>> = svc
+        .build_start_envp(&[], svc.env_file_vars.as_deref().unwrap_or_default())?
+        .iter()
+        .map(|e| parse(e.as_bytes()))
+        .collect();
+    let mut diff = Vec::new();
+    for (key, value) in &theirs {
+        let marker = match ours.get(key) {
+            None => '+',
+            Some(v) if v != value => '~',
+            Some(_) => continue,
+        };
+        diff.push(format!("{}{}", marker, String::from_utf8_lossy(key)));
+    }
+    for key in ours.keys().filter(|k| !theirs.contains_key(*k)) {
+        diff.push(format!("-{}", String::from_utf8_lossy(key)));
+    }
+    diff.sort_unstable_by(|a, b| a[1..].cmp(&b[1..]));
+    Ok(diff)
+}
+
+/// Write the diagnostics bundle of `svc`, which just hit its start limit,
+/// to `<dir>/<name>-<timestamp_ms>.txt`, returning its path.
+///
+/// The bundle holds the command line of the service, `redact` applied, its
+/// recent starts and exits, with the CPU time used by each process, the
+/// keys of its environment that differ from the supervisor's and the end
+/// of its log file
+pub(crate) fn write_bundle(svc: &Service, dir: &Path) -> io::Result<PathBuf> {
+    let (secs, nsecs) = timestamp();
+    let now_ms = (secs * 1000 + nsecs / 1_000_000) as u64;
+    let mut out = String::new();
+    let _ = writeln!(out, "service: {}", svc.name);
+    let _ = writeln!(out, "timestamp_ms: {}", now_ms);
+    if let Some(limit) = svc.config.start_limit {
+        let _ = writeln!(
+            out,
+            "start_limit: {} failures within {}ms",
+            limit.burst, limit.interval_ms
+        );
+    }
+    let _ = writeln!(out, "start_count: {}", svc.start_count);
+    let mut command = svc.config.command.clone();
+    for arg in svc.config.redacted_args() {
+        command.push(' ');
+        command.push_str(&arg);
+    }
+    let _ = writeln!(out, "command: {}", command);
+
+    let _ = writeln!(out, "\nevents:");
+    for event in &svc.history.events {
+        let _ = match event.kind {
+            HistoryKind::Started { pid } => {
+                writeln!(out, "  {} started pid {}", event.at_ms, pid)
+            }
+            HistoryKind::Exited {
+                reason,
+                uptime_ms,
+                cpu,
+            } => {
+                let _ = write!(out, "  {} exited {}", event.at_ms, reason);
+                if let Some(uptime_ms) = uptime_ms {
+                    let _ = write!(out, " after {}ms", uptime_ms);
+                }
+                if let Some(cpu) = cpu {
+                    let _ = write!(out, " user {}ms sys {}ms", cpu.user_ms, cpu.sys_ms);
+                }
+                writeln!(out)
+            }
+        };
+    }
+
+    let _ = writeln!(out, "\nenvironment (vs supervisor):");
+    for key in env_diff(svc)? {
+        let _ = writeln!(out, "  {}", key);
+    }
+
+    let _ = writeln!(out, "\nlog:");
+    match svc.log_file_path() {
+        Some(path) => match read_log_tail(path, LOG_TAIL_LINES) {
+            Ok(tail) => out.push_str(&String::from_utf8_lossy(&tail)),
+            Err(e) => {
+                let _ = writeln!(out, "  failed to read {}: {}", path.display(), e);
+            }
+        },
+        None => out.push_str("  no log file\n"),
+    }
+
+    let path = dir.join(format!("{}-{}.txt", svc.name, now_ms));
+    write_status_file(&StatusFilePath::new(path.clone())?, out.as_bytes())?;
+    Ok(path)
+}
@@ -0,0 +1,197 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Environment files of services (`env_file`).
+//!
+//! The file is read on each start of the service. Its directory is
+//! watched with inotify rather than the file itself, so that files
+//! replaced by a rename, as credential rotation tools do, and symlink
+//! swaps (e.g. Kubernetes secret volumes) are seen as well. Any change in
+//! the directory makes svlopp read the file again, the service being
+//! flagged `env_stale` only if its variables actually differ from those
+//! its process started with.
+
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    mem::MaybeUninit,
+    os::fd::{AsFd, BorrowedFd, OwnedFd},
+    path::{Path, PathBuf},
+};
+
+use rustix::fs::inotify::{self, CreateFlags, ReadFlags, WatchFlags};
+use rustix::io::Errno;
+use serde::Deserialize;
+
+use crate::logging::LogLevel;
+use crate::service::ServiceRegistry;
+use crate::svlogg;
+
+/// Maximum size of an environment file, read on each start
+const MAX_ENV_FILE_LEN: u64 = 1024 * 1024;
+
+/// Size of the buffer inotify events are read into, enough for a few
+/// events with names up to `NAME_MAX`
+const EVENTS_BUF_LEN: usize = 4096;
+
+/// What svlopp does when the `env_file` of a running service changes
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum EnvFileChange {
+    /// Only flag the service `env_stale`
+    #[default]
+    Mark,
+    /// Flag the service and restart it, as with a reload
+    Restart,
+}
+
+/// The variables of an environment file, in order
+pub(crate) type EnvVars = Vec<(String, String)>;
+
+/// Parse the `KEY=VALUE` lines of an environment file. Blank lines and
+/// lines starting with `#` are skipped, and quotes around a value are
+/// removed
+pub(crate) fn parse_env_file(content: &str) -> Result<EnvVars, String> {
+    let mut vars = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("line {}: expected KEY=VALUE", i + 1));
+        };
+        let key = key.trim_end();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(format!("line {}: invalid variable name '{}'", i + 1, key));
+        }
+        let value = value.trim_start();
+        let value = [b'"', b'\'']
+            .iter()
+            .find_map(|&q| {
+                let q = q as char;
+                value.strip_prefix(q).and_then(|v| v.strip_suffix(q))
+            })
+            .unwrap_or(value);
+        vars.push((key.to_owned(), value.to_owned()));
+    }
+    Ok(vars)
+}
+
+/// Read and parse the environment file at `path`
+pub(crate) fn read_env_file(path: &Path) -> io::Result<EnvVars> {
+    let mut content = String::new();
+    std::fs::File::open(path)
+        .and_then(|file| file.take(MAX_ENV_FILE_LEN + 1).read_to_string(&mut content))
+        .map_err(|e| {
+            io::Error::other(format!("can't read env_file '{}': {}", path.display(), e))
+        })?;
+    if content.len() as u64 > MAX_ENV_FILE_LEN {
+        return Err(io::Error::other(format!(
+            "env_file '{}' is larger than {} bytes",
+            path.display(),
+            MAX_ENV_FILE_LEN
+        )));
+    }
+    parse_env_file(&content)
+        .map_err(|e| io::Error::other(format!("env_file '{}' {}", path.display(), e)))
+}
+
+/// Watches the directories of the `env_file`s of services
+#[derive(Debug)]
+pub(crate) struct EnvFileWatcher {
+    fd: OwnedFd,
+    /// Watched directories by watch descriptor
+    dirs: HashMap<i32, PathBuf>,
+    buf: Vec<MaybeUninit<u8>>,
+}
+
+impl EnvFileWatcher {
+    pub(crate) fn new() -> rustix::io::Result<Self> {
+        Ok(Self {
+            fd: inotify::init(CreateFlags::CLOEXEC | CreateFlags::NONBLOCK)?,
+            dirs: HashMap::new(),
+            buf: vec![MaybeUninit::uninit(); EVENTS_BUF_LEN],
+        })
+    }
+
+    /// Watch the directories of the `env_file`s of the services of
+    /// `registry`, and stop watching those no service needs anymore
+    pub(crate) fn watch(&mut self, registry: &ServiceRegistry) {
+        let mut wanted: Vec<&Path> = registry
+            .services()
+            .filter_map(|svc| svc.config.env_file.as_deref()?.parent())
+            .collect();
+        wanted.sort_unstable();
+        wanted.dedup();
+        self.dirs.retain(|&wd, dir| {
+            if wanted.contains(&dir.as_path()) {
+                return true;
+            }
+            let _ = inotify::remove_watch(&self.fd, wd);
+            false
+        });
+        for dir in wanted {
+            if self.dirs.values().any(|watched| watched == dir) {
+                continue;
+            }
+            match inotify::add_watch(
+                &self.fd,
+                dir,
+                WatchFlags::CLOSE_WRITE
+                    | WatchFlags::MOVED_TO
+                    | WatchFlags::CREATE
+                    | WatchFlags::DELETE
+                    | WatchFlags::ONLYDIR,
+            ) {
+                Ok(wd) => {
+                    self.dirs.insert(wd, dir.to_path_buf());
+                }
+                Err(e) => svlogg!(
+                    LogLevel::Warn,
+                    "can't watch env_file directory '{}': {}",
+                    dir.display(),
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Drain the pending events, returning the directories where
+    /// something changed. All of them are returned if events were lost
+    pub(crate) fn read_changes(&mut self) -> io::Result<Vec<PathBuf>> {
+        let mut changed = Vec::new();
+        let mut overflow = false;
+        let mut reader = inotify::Reader::new(&self.fd, &mut self.buf);
+        loop {
+            let event = match reader.next() {
+                Ok(event) => event,
+                Err(Errno::WOULDBLOCK) => break,
+                Err(Errno::INTR) => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let (wd, events) = (event.wd(), event.events());
+            if events.contains(ReadFlags::QUEUE_OVERFLOW) {
+                overflow = true;
+            } else if events.contains(ReadFlags::IGNORED) {
+                // the directory was removed, watched again on reload
+                self.dirs.remove(&wd);
+            } else if let Some(dir) = self.dirs.get(&wd)
+                && !changed.contains(dir)
+            {
+                changed.push(dir.clone());
+            }
+        }
+        if overflow {
+            return Ok(self.dirs.values().cloned().collect());
+        }
+        Ok(changed)
+    }
+}
+
+impl AsFd for EnvFileWatcher {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
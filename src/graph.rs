@@ -0,0 +1,102 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{fmt::Write, io, path::Path};
+
+use serde_json::json;
+use svlopp::{GRAPH_FILE_NAME, graph_format};
+
+use crate::service::{Service, ServiceRegistry, ServiceState};
+use crate::status::{StatusFilePath, write_status_file};
+
+fn state_name(svc: &Service) -> &'static str {
+    match svc.state {
+        ServiceState::Running(_) => "running",
+        ServiceState::Stopping(..) => "stopping",
+        ServiceState::Paused(_) => "paused",
+        ServiceState::Stopped(_) => "stopped",
+    }
+}
+
+/// Escape `s` for a quoted DOT string
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The services as a Graphviz digraph, with an edge from each bound
+/// service to the service it is bound to
+fn format_dot(services: &[&Service]) -> String {
+    let mut out = String::from("digraph svlopp {\n");
+    for svc in services {
+        let name = dot_escape(&svc.name);
+        let _ = writeln!(
+            out,
+            "  \"{}\" [label=\"{}\\n{}\"];",
+            name,
+            name,
+            state_name(svc)
+        );
+    }
+    for svc in services {
+        if let Some(target) = &svc.config.bind_to {
+            let _ = writeln!(
+                out,
+                "  \"{}\" -> \"{}\" [label=\"bind_to\"];",
+                dot_escape(&svc.name),
+                dot_escape(target)
+            );
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// The services as JSON, `{"services": [...], "edges": [...]}`, with the
+/// same edges as the DOT graph
+fn format_json(services: &[&Service]) -> String {
+    let nodes: Vec<_> = services
+        .iter()
+        .map(|svc| {
+            json!({
+                "name": svc.name,
+                "id": svc.id,
+                "state": state_name(svc),
+                "autostart": svc.config.autostart,
+            })
+        })
+        .collect();
+    let edges: Vec<_> = services
+        .iter()
+        .filter_map(|svc| {
+            let target = svc.config.bind_to.as_ref()?;
+            Some(json!({"from": svc.name, "to": target, "kind": "bind_to"}))
+        })
+        .collect();
+    let mut out = json!({"services": nodes, "edges": edges}).to_string();
+    out.push('\n');
+    out
+}
+
+/// Write the service graph in `format`, one of the `graph_format` values,
+/// to `graph.<ext>` in `run_dir`
+pub(crate) fn write_graph(
+    registry: &ServiceRegistry,
+    format: u64,
+    run_dir: &Path,
+) -> io::Result<()> {
+    let mut services: Vec<&Service> = registry.services().collect();
+    services.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    let (content, ext) = match format {
+        graph_format::DOT => (format_dot(&services), "dot"),
+        graph_format::JSON => (format_json(&services), "json"),
+        other => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown graph format {}", other),
+            ));
+        }
+    };
+    let path = StatusFilePath::new(run_dir.join(format!("{}.{}", GRAPH_FILE_NAME, ext)))?;
+    write_status_file(&path, content.as_bytes())
+}
@@ -0,0 +1,57 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    os::unix::process::CommandExt,
+    process::{Command, Stdio},
+};
+
+use serde::Deserialize;
+
+use crate::logging::LogLevel;
+use crate::signalfd::{SigSet, set_thread_signal_mask};
+use crate::svlogg;
+
+/// A command run by the supervisor itself, rather than supervised.
+///
+/// Supervisor hooks run synchronously: the supervisor waits for them to
+/// exit. A service `finish` command runs in the background instead
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct Hook {
+    pub(crate) command: String,
+    #[serde(default)]
+    pub(crate) args: Vec<String>,
+}
+
+/// Build the command running `hook`, with `name` in the `SVLOPP_HOOK`
+/// variable of its environment.
+///
+/// `sigset` is the signal mask to restore in the hook process, since the
+/// supervisor blocks the signals it reads through its signalfd
+pub(crate) fn hook_command(name: &str, hook: &Hook, sigset: &SigSet) -> Command {
+    let mut command = Command::new(&hook.command);
+    command
+        .args(&hook.args)
+        .env("SVLOPP_HOOK", name)
+        .stdin(Stdio::null());
+    let sigset = sigset.clone();
+    // SAFETY: `sigprocmask` is async-signal-safe
+    unsafe {
+        command.pre_exec(move || Ok(set_thread_signal_mask(&sigset)?));
+    }
+    command
+}
+
+/// Run `hook` and wait for it to exit. `name` identifies the hook in logs
+/// and in the hook environment
+pub(crate) fn run_hook(name: &str, hook: &Hook, sigset: &SigSet) {
+    svlogg!(LogLevel::Info, "running {} hook '{}'", name, hook.command);
+    match hook_command(name, hook, sigset).status() {
+        Ok(status) if status.success() => {
+            svlogg!(LogLevel::Debug, "{} hook completed", name)
+        }
+        Ok(status) => svlogg!(LogLevel::Warn, "{} hook failed: {}", name, status),
+        Err(e) => svlogg!(LogLevel::Error, "failed to run {} hook: {}", name, e),
+    }
+}
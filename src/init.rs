@@ -0,0 +1,107 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{collections::HashMap, io};
+
+use rustix::process::Signal;
+use serde::Deserialize;
+
+use crate::logging::LogLevel;
+use crate::service::{ServiceRegistry, ServiceState};
+use crate::svlogg;
+use crate::utils::cvt_r;
+
+/// Signals forwarded to services in init mode (`--init`).
+///
+/// These are the signals a container runtime may send to the container
+/// init that svlopp has no use for itself. `SIGHUP`, `SIGTERM` and `SIGINT`
+/// keep their usual meaning
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "UPPERCASE")]
+pub(crate) enum ForwardedSignal {
+    SigQuit,
+    SigUsr1,
+    SigUsr2,
+    SigWinch,
+}
+
+impl ForwardedSignal {
+    pub(crate) const ALL: [Self; 4] = [Self::SigQuit, Self::SigUsr1, Self::SigUsr2, Self::SigWinch];
+
+    #[inline(always)]
+    pub(crate) fn as_raw(self) -> i32 {
+        Signal::from(self).as_raw()
+    }
+
+    pub(crate) fn from_raw(signo: i32) -> Option<Self> {
+        Self::ALL.into_iter().find(|sig| sig.as_raw() == signo)
+    }
+}
+
+impl From<ForwardedSignal> for Signal {
+    fn from(value: ForwardedSignal) -> Self {
+        match value {
+            ForwardedSignal::SigQuit => Signal::QUIT,
+            ForwardedSignal::SigUsr1 => Signal::USR1,
+            ForwardedSignal::SigUsr2 => Signal::USR2,
+            ForwardedSignal::SigWinch => Signal::WINCH,
+        }
+    }
+}
+
+/// Received signal -> signal sent to services. Every forwarded signal is
+/// sent as is by default
+pub(crate) fn default_forward_signals() -> HashMap<ForwardedSignal, ForwardedSignal> {
+    ForwardedSignal::ALL
+        .into_iter()
+        .map(|sig| (sig, sig))
+        .collect()
+}
+
+/// Send the signal `received` maps to in `map` to every running service
+pub(crate) fn forward_signal(
+    registry: &ServiceRegistry,
+    map: &HashMap<ForwardedSignal, ForwardedSignal>,
+    received: ForwardedSignal,
+) {
+    let Some(&sig) = map.get(&received) else {
+        svlogg!(LogLevel::Debug, "ignoring {:?}, not forwarded", received);
+        return;
+    };
+    for svc in registry.services() {
+        // services being stopped already got their stop signal, paused
+        // ones get it once resumed
+        if let ServiceState::Running(_) | ServiceState::Paused(_) = svc.state
+            && let Err(e) = svc.signal(sig.into())
+        {
+            svlogg!(
+                LogLevel::Warn,
+                "failed to forward {:?} to service '{}': {}",
+                sig,
+                svc.name,
+                e
+            );
+        }
+    }
+}
+
+/// Whether the supervisor still has children, exited or not. These are
+/// services as well as orphans reparented to it
+pub(crate) fn has_children() -> io::Result<bool> {
+    // SAFETY: all zeroes is a valid `siginfo_t`
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    // SAFETY: `info` is a valid `siginfo_t` to write to
+    match cvt_r(|| unsafe {
+        libc::waitid(
+            libc::P_ALL,
+            0,
+            &mut info,
+            libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+        )
+    }) {
+        Ok(_) => Ok(true),
+        Err(rustix::io::Errno::CHILD) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,346 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Serialization of the control operations on a service.
+//!
+//! A service goes through one transition at a time. While it stops, runs
+//! its `finish` command or waits for a pending action, it's busy: a control
+//! operation on it doesn't run right away but is queued as its pending
+//! action, run once the ongoing transition ends. An operation that the
+//! ongoing or queued one already covers is merged into it, and one that
+//! can't be queued is rejected with "operation in progress", so that
+//! operations from the control FIFO, D-Bus and the API never race through
+//! the state machine.
+//!
+//! Across services, `max_concurrent_starts` and `max_concurrent_stops`
+//! bound how many services are starting and stopping at once. Operations
+//! beyond them wait in the `JobScheduler`, one job per service, and run in
+//! the order they were requested as starts and stops complete, so that a
+//! bulk restart rolls through the services instead of stopping them all.
+
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    io,
+    num::NonZeroUsize,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::control::ControlOp;
+use crate::logging::LogLevel;
+use crate::service::{Service, ServicePendingAction, ServiceRegistry, ServiceState};
+use crate::slab::ServiceSlab;
+use crate::status::StatusFile;
+use crate::svlogg;
+
+/// How long a started service counts as starting, unless it notifies
+/// readiness sooner: a start is over once the process survived it
+pub(crate) const START_SETTLE: Duration = Duration::from_secs(1);
+
+/// What a control operation does to a service
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum JobDecision {
+    /// The service isn't busy, the operation runs now
+    Run,
+    /// The service is started once the ongoing transition ends
+    Queue,
+    /// The ongoing or queued operation already does it
+    Merge,
+    /// A stop dropping the queued start, the ongoing stop going on
+    Cancel,
+    /// The operation can't be queued
+    Reject,
+}
+
+/// Whether `svc` is in the middle of a transition
+pub(crate) fn is_busy(svc: &Service) -> bool {
+    matches!(svc.state, ServiceState::Stopping(..))
+        || svc.finish_pid.is_some()
+        || !svc.pending_action.is_none()
+}
+
+/// What `op` does to `svc`
+pub(crate) fn decide(svc: &Service, op: ControlOp) -> JobDecision {
+    if !is_busy(svc) {
+        return JobDecision::Run;
+    }
+    match (op, svc.pending_action) {
+        (
+            ControlOp::Start
+            | ControlOp::Stop
+            | ControlOp::Restart
+            | ControlOp::Pause
+            | ControlOp::Resume,
+            ServicePendingAction::Remove,
+        ) => JobDecision::Reject,
+        (ControlOp::Stop, ServicePendingAction::Restart) => JobDecision::Cancel,
+        (ControlOp::Stop, ServicePendingAction::None) => JobDecision::Merge,
+        (ControlOp::Start | ControlOp::Restart, ServicePendingAction::Restart) => {
+            JobDecision::Merge
+        }
+        (ControlOp::Start | ControlOp::Restart, ServicePendingAction::None) => JobDecision::Queue,
+        (ControlOp::Pause | ControlOp::Resume, _) => JobDecision::Reject,
+        // not service transitions
+        _ => JobDecision::Run,
+    }
+}
+
+/// The error operations are rejected with, `ResourceBusy` as conflicts
+pub(crate) fn in_progress() -> io::Error {
+    io::Error::new(io::ErrorKind::ResourceBusy, "operation in progress")
+}
+
+/// Global limits on the transitions in progress
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct JobLimits {
+    pub(crate) max_concurrent_starts: Option<NonZeroUsize>,
+    pub(crate) max_concurrent_stops: Option<NonZeroUsize>,
+}
+
+impl JobLimits {
+    fn of(&self, kind: JobKind) -> Option<usize> {
+        match kind {
+            JobKind::Start => self.max_concurrent_starts,
+            JobKind::Stop => self.max_concurrent_stops,
+        }
+        .map(NonZeroUsize::get)
+    }
+}
+
+/// The transition a control operation begins, and the limit it's
+/// counted against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum JobKind {
+    Start = 0,
+    Stop = 1,
+}
+
+impl JobKind {
+    /// The transition `op` begins on `svc`, `None` when it doesn't begin
+    /// one, e.g. stopping a stopped service. Restarting a running service
+    /// is a stop, the service being started again right away once stopped
+    pub(crate) fn of(svc: &Service, op: ControlOp) -> Option<Self> {
+        match (op, svc.state) {
+            (ControlOp::Start | ControlOp::Restart, ServiceState::Stopped(_))
+                if svc.pending_action.is_none() =>
+            {
+                Some(Self::Start)
+            }
+            (
+                ControlOp::Stop | ControlOp::Restart,
+                ServiceState::Running(_) | ServiceState::Paused(_),
+            ) => Some(Self::Stop),
+            _ => None,
+        }
+    }
+
+    /// Whether `svc` is in the middle of a transition of this kind at `now`
+    fn in_progress(self, svc: &Service, now: Instant) -> bool {
+        match self {
+            Self::Start => {
+                matches!(svc.state, ServiceState::Running(_))
+                    && !svc.ready_notified
+                    && svc
+                        .started_at
+                        .is_some_and(|t| now.saturating_duration_since(t) < START_SETTLE)
+            }
+            Self::Stop => matches!(svc.state, ServiceState::Stopping(..)),
+        }
+    }
+}
+
+/// A control operation waiting for a start or stop slot
+#[derive(Debug, Clone, Copy)]
+struct QueuedJob {
+    svc_id: u64,
+    queued_at: Instant,
+}
+
+/// Control operations held back by the `JobLimits`.
+///
+/// A service has at most one queued job, the operation of which is kept in
+/// `Service::scheduled`: a later operation on the same service replaces it,
+/// keeping its place in the queue, so that a service asked over and over
+/// doesn't get ahead of the others
+#[derive(Debug, Default)]
+pub(crate) struct JobScheduler {
+    limits: JobLimits,
+    queue: VecDeque<QueuedJob>,
+    /// Number of jobs run after waiting in the queue
+    dispatched: u64,
+    /// Total and longest wait of the dispatched jobs
+    waited: Duration,
+    max_wait: Duration,
+}
+
+impl JobScheduler {
+    #[inline(always)]
+    pub(crate) fn set_limits(&mut self, limits: JobLimits) {
+        self.limits = limits;
+    }
+
+    #[inline(always)]
+    pub(crate) fn limits(&self) -> JobLimits {
+        self.limits
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Drop all queued jobs, e.g. on shutdown
+    pub(crate) fn clear(&mut self, services: &mut ServiceSlab) {
+        for job in self.queue.drain(..) {
+            if let Some(svc) = services.get_mut(job.svc_id) {
+                svc.scheduled = None;
+            }
+        }
+    }
+
+    /// Drop the queued job of `svc_id`, whose `Service::scheduled` is left
+    /// to the caller
+    pub(crate) fn remove(&mut self, svc_id: u64) {
+        self.queue.retain(|job| job.svc_id != svc_id);
+    }
+
+    /// Whether `op` on `svc_id` has to wait, in which case it's queued, or
+    /// merged with the job already queued for the service. Jobs wait for a
+    /// slot, and behind the already queued jobs
+    pub(crate) fn schedule(
+        &mut self,
+        services: &mut ServiceSlab,
+        svc_id: u64,
+        op: ControlOp,
+    ) -> bool {
+        let Some(svc) = services.get(svc_id) else {
+            return false;
+        };
+        let kind = JobKind::of(svc, op);
+        if svc.scheduled.is_some() {
+            let svc = services.get_mut(svc_id).expect("service exists");
+            match kind {
+                Some(_) => svc.scheduled = Some(op),
+                // e.g. a stop cancelling a queued start
+                None => {
+                    svc.scheduled = None;
+                    self.remove(svc_id);
+                }
+            }
+            return true;
+        }
+        let Some(kind) = kind else {
+            return false;
+        };
+        let Some(limit) = self.limits.of(kind) else {
+            return false;
+        };
+        let now = Instant::now();
+        if self.queue.is_empty() && transitions(services, now)[kind as usize] < limit {
+            return false;
+        }
+        services.get_mut(svc_id).expect("service exists").scheduled = Some(op);
+        self.queue.push_back(QueuedJob {
+            svc_id,
+            queued_at: now,
+        });
+        true
+    }
+
+    /// Take the queued jobs that can run now, in order. Jobs that no
+    /// longer begin a transition, e.g. for a service stopped in the
+    /// meantime, are dropped
+    pub(crate) fn take_runnable(&mut self, services: &mut ServiceSlab) -> Vec<(u64, ControlOp)> {
+        let now = Instant::now();
+        let mut counts = transitions(services, now);
+        let mut blocked = [false; 2];
+        let limits = self.limits;
+        let mut runnable = Vec::new();
+        let mut waited = Vec::new();
+        self.queue.retain(|job| {
+            let Some(svc) = services.get_mut(job.svc_id) else {
+                return false;
+            };
+            let Some(op) = svc.scheduled else {
+                return false;
+            };
+            let Some(kind) = JobKind::of(svc, op) else {
+                svc.scheduled = None;
+                return false;
+            };
+            // jobs of a kind run in order, the first one left waiting
+            // holding back the ones behind it
+            let k = kind as usize;
+            if blocked[k] || limits.of(kind).is_some_and(|limit| counts[k] >= limit) {
+                blocked[k] = true;
+                return true;
+            }
+            counts[k] += 1;
+            svc.scheduled = None;
+            waited.push(now.saturating_duration_since(job.queued_at));
+            runnable.push((job.svc_id, op));
+            false
+        });
+        for wait in waited {
+            self.dispatched += 1;
+            self.waited += wait;
+            self.max_wait = self.max_wait.max(wait);
+        }
+        runnable
+    }
+}
+
+/// Number of services starting and stopping at `now`, indexed by `JobKind`
+pub(crate) fn transitions(services: &ServiceSlab, now: Instant) -> [usize; 2] {
+    let mut counts = [0; 2];
+    for svc in services.iter() {
+        for kind in [JobKind::Start, JobKind::Stop] {
+            counts[kind as usize] += kind.in_progress(svc, now) as usize;
+        }
+    }
+    counts
+}
+
+/// Publishes the state of the `JobScheduler` in the jobs file of the run
+/// directory, one `<key> <value>` per line
+#[derive(Debug)]
+pub(crate) struct JobsFile {
+    file: StatusFile,
+}
+
+impl JobsFile {
+    pub(crate) fn new(path: PathBuf) -> io::Result<Self> {
+        Ok(Self {
+            file: StatusFile::create(path)?,
+        })
+    }
+
+    /// Rewrite the jobs file if anything changed. `start_queue` is the
+    /// number of services waiting in the `StartQueue`
+    pub(crate) fn flush(&mut self, registry: &ServiceRegistry, start_queue: usize) {
+        let jobs = registry.jobs();
+        let now = Instant::now();
+        let [starting, stopping] = registry.jobs_in_progress(now);
+        let oldest = jobs.queue.front().map_or(Duration::ZERO, |job| {
+            now.saturating_duration_since(job.queued_at)
+        });
+        let average = match jobs.dispatched {
+            0 => Duration::ZERO,
+            n => jobs.waited / n.min(u32::MAX as u64) as u32,
+        };
+        let buf = self.file.buf();
+        let _ = writeln!(buf, "queued {}", jobs.queue.len());
+        let _ = writeln!(buf, "start_queue {}", start_queue);
+        let _ = writeln!(buf, "starting {}", starting);
+        let _ = writeln!(buf, "stopping {}", stopping);
+        let _ = writeln!(buf, "dispatched {}", jobs.dispatched);
+        let _ = writeln!(buf, "wait_avg_ms {}", average.as_millis());
+        let _ = writeln!(buf, "wait_max_ms {}", jobs.max_wait.as_millis());
+        let _ = writeln!(buf, "oldest_wait_ms {}", oldest.as_millis());
+        if let Err(e) = self.file.flush() {
+            svlogg!(LogLevel::Error, "failed to write jobs file: {}", e);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Session keyrings of service processes.
+//!
+//! Kernel keys (e.g. kerberos tickets or fscrypt keys) stored in the
+//! session keyring of svlopp would be reachable by every service, which
+//! inherits it across the fork. By default, a service process joins a new
+//! anonymous session keyring instead, once it switched to its user so
+//! that the keyring belongs to it. Under `spawn_strategy = "posix_spawn"`,
+//! which can't run anything in the child, it inherits the keyring of
+//! svlopp unless `keyring = "private"` is set, forking it.
+
+use rustix::io::Errno;
+use serde::Deserialize;
+
+use crate::spawn::SpawnStrategy;
+use crate::utils::cvt;
+
+/// `KEYCTL_JOIN_SESSION_KEYRING` of `keyctl(2)`
+const KEYCTL_JOIN_SESSION_KEYRING: libc::c_long = 1;
+
+/// The session keyring of a service process, the `keyring` field
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum KeyringMode {
+    /// A new anonymous session keyring
+    Private,
+    /// The session keyring of svlopp, for services meant to share keys
+    /// with it
+    Inherit,
+}
+
+impl KeyringMode {
+    /// The mode of services that don't set `keyring`, `Inherit` when
+    /// processes are created with `posix_spawn` so that they aren't
+    /// forked for it
+    pub(crate) fn default_for(strategy: SpawnStrategy) -> Self {
+        match strategy {
+            SpawnStrategy::PosixSpawn => Self::Inherit,
+            SpawnStrategy::Fork | SpawnStrategy::Clone3 => Self::Private,
+        }
+    }
+}
+
+/// Join a new anonymous session keyring, in the child. Kernels without
+/// keyrings, a seccomp filter denying `keyctl` as container runtimes do,
+/// or a user out of key quota (`kernel.keys.maxkeys`) leave the process
+/// with the inherited one
+pub(crate) fn join_private_keyring() -> rustix::io::Result<()> {
+    let name: *const libc::c_char = std::ptr::null();
+    match cvt(unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_JOIN_SESSION_KEYRING, name) }) {
+        Ok(_) | Err(Errno::NOSYS | Errno::PERM | Errno::ACCESS | Errno::DQUOT) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,145 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Formats and paths shared by `svlopp` and `svloppctl`.
+
+#![deny(clippy::unwrap_used)]
+
+pub mod calendar;
+pub mod read_buf;
+pub mod snapshot;
+pub mod tz;
+pub mod unit;
+pub mod wire;
+
+/// Default runtime directory
+pub const DEFAULT_RUN_DIR: &str = "/run/svlopp";
+
+/// Name of the binary snapshot file in the runtime directory
+pub const SNAPSHOT_FILE_NAME: &str = "state.snap";
+
+/// Name of the control FIFO in the runtime directory
+pub const CONTROL_PIPE_NAME: &str = "control";
+
+/// Name of the status file in the runtime directory
+pub const STATUS_FILE_NAME: &str = "status";
+
+/// Name of the directory dry-run control operations write their plan
+/// to, in the runtime directory
+pub const PLAN_DIR_NAME: &str = "plan.d";
+
+/// Name of the file the outcome of the last reload is written to, in the
+/// runtime directory
+pub const RELOAD_FILE_NAME: &str = "reload";
+
+/// Name of the startup profile file in the runtime directory
+pub const STARTUP_FILE_NAME: &str = "startup";
+
+/// Name of the per-service status directory in the runtime directory
+pub const STATUS_DIR_NAME: &str = "status.d";
+
+/// Name of the directory the `ps` control operation writes to, in the
+/// runtime directory
+pub const PS_DIR_NAME: &str = "ps.d";
+
+/// Name of the file the `graph` control operation writes to in the
+/// runtime directory, with the extension of the format
+pub const GRAPH_FILE_NAME: &str = "graph";
+
+/// Name of the file the `snapshot` control operation saves the running
+/// services to, and `restore` reads them from, in the runtime directory
+pub const RUN_SET_FILE_NAME: &str = "runset";
+
+/// Name of the directory diagnostics bundles of services hitting their
+/// start limit are written to, in the runtime directory
+pub const DIAGNOSTICS_DIR_NAME: &str = "diagnostics";
+
+/// Name of the directory the private `/tmp` of services are created in,
+/// in the runtime directory
+pub const PRIVATE_TMP_DIR_NAME: &str = "tmp.d";
+
+/// Name of the directory `svloppctl` writes the property the
+/// `set-property` control operation sets to, in the runtime directory
+pub const PROPERTY_DIR_NAME: &str = "property.d";
+
+/// Name of the directory `svloppctl` writes the definitions of the
+/// transient services the `run` control operation starts to, in the
+/// runtime directory
+pub const TRANSIENT_DIR_NAME: &str = "transient.d";
+
+/// Name of the directory `svloppctl` writes the name of the service the
+/// `adopt` control operation attaches a process to, in the runtime
+/// directory
+pub const ADOPT_DIR_NAME: &str = "adopt.d";
+
+/// Name of the file listing the kernel features svlopp detected, in the
+/// runtime directory
+pub const PLATFORM_FILE_NAME: &str = "platform";
+
+/// Control FIFO opcodes. A command is a 9 bytes frame: the opcode
+/// followed by the little-endian service id
+pub mod opcode {
+    pub const STOP: u8 = 0x41;
+    pub const START: u8 = 0x42;
+    pub const RESTART: u8 = 0x43;
+    pub const PS: u8 = 0x44;
+    /// Takes one of the `graph_format` values instead of a service id
+    pub const GRAPH: u8 = 0x45;
+    /// Saves the names of the running services to the run set file. The
+    /// service id is ignored
+    pub const SNAPSHOT: u8 = 0x46;
+    /// Starts the services of the run set file. Takes one of the
+    /// `restore_mode` values instead of a service id
+    pub const RESTORE: u8 = 0x47;
+    /// Reloads the configuration as `SIGHUP` does, the outcome being
+    /// written to the reload file. The service id is ignored
+    pub const RELOAD: u8 = 0x48;
+    /// Freezes the processes of a running service, until `RESUME`
+    pub const PAUSE: u8 = 0x49;
+    pub const RESUME: u8 = 0x4a;
+    /// Sets the property written to `property.d/<service id>`, e.g.
+    /// `cpu.max=50%`
+    pub const SET_PROPERTY: u8 = 0x4b;
+    /// Restarts the services running an outdated config, flagged
+    /// `restart_required`. The service id is ignored
+    pub const RESTART_OUTDATED: u8 = 0x4c;
+    /// Cancels the latest job of the service: the operation waiting for a
+    /// start or stop slot, the start queued behind a stop, or the stop,
+    /// killing the service process
+    pub const CANCEL: u8 = 0x4d;
+    /// Restarts the instances of the replicated service the service is an
+    /// instance of, one at a time, each once the previous one is ready
+    pub const ROLLING_RESTART: u8 = 0x4e;
+    /// Replaces the running service with a new instance of it, started
+    /// next to it, once the new one is ready
+    pub const SWAP: u8 = 0x4f;
+    /// Defines and starts the transient service written to
+    /// `transient.d/<id>`, the id being picked by the writer
+    pub const RUN: u8 = 0x50;
+    /// Attaches the running process whose pid is the id to the service
+    /// named in `adopt.d/<pid>`
+    pub const ADOPT: u8 = 0x51;
+    /// Stops supervising the process of the service, left running
+    pub const DETACH: u8 = 0x52;
+    /// Or'ed with `STOP`, `START` or `RESTART`, only writes the plan of
+    /// the operation to the `plan.d` directory
+    pub const DRY_RUN: u8 = 0x80;
+}
+
+/// What the `restore` control operation does about running services that
+/// are not in the run set
+pub mod restore_mode {
+    /// Leave them running
+    pub const START: u64 = 0;
+    /// Stop them
+    pub const EXACT: u64 = 1;
+}
+
+/// Formats of the service graph written by the `graph` control operation
+pub mod graph_format {
+    /// Graphviz DOT, written to `graph.dot`
+    pub const DOT: u64 = 0;
+    /// JSON, written to `graph.json`
+    pub const JSON: u64 = 1;
+}
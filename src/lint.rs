@@ -0,0 +1,500 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Errors and issues of the config file, with the file, line and column
+//! they're at.
+//!
+//! The config is deserialized in parts, the supervisor options and each
+//! service on its own, so that one error doesn't hide the others: a load
+//! reports all of them at once. Issues are keys svlopp doesn't know, that
+//! serde skips over, and values that are valid but likely mistakes. They
+//! are logged as warnings, or fail the load with `--strict-config`.
+
+use std::{
+    collections::HashMap,
+    fmt, io,
+    ops::Range,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use rustix::process::geteuid;
+use serde::Deserialize;
+use toml::{
+    Spanned,
+    de::{DeTable, DeValue, ValueDeserializer},
+};
+
+use crate::envfile::read_env_file;
+use crate::logging::LogLevel;
+use crate::platform::Platform;
+use crate::service::{ServiceConfig, ServiceConfigData};
+use crate::svlogg;
+
+/// A step of the path to a key of the config file
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Key {
+    Name(String),
+    Index(usize),
+}
+
+fn key_path(path: &serde_ignored::Path<'_>, out: &mut Vec<Key>) {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => {}
+        Path::Seq { parent, index } => {
+            key_path(parent, out);
+            out.push(Key::Index(*index));
+        }
+        Path::Map { parent, key } => {
+            key_path(parent, out);
+            out.push(Key::Name(key.clone()));
+        }
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => key_path(parent, out),
+    }
+}
+
+/// `path` as written in the config file, e.g. `services.web.comand`
+fn dotted(path: &[Key]) -> String {
+    let mut out = String::new();
+    for key in path {
+        match key {
+            Key::Name(name) if out.is_empty() => out.push_str(name),
+            Key::Name(name) => {
+                out.push('.');
+                out.push_str(name);
+            }
+            Key::Index(i) => out.push_str(&format!("[{}]", i)),
+        }
+    }
+    out
+}
+
+/// Where in the config file an issue is
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Provenance {
+    /// Line and column, both starting at 1, `None` if the key couldn't be
+    /// found
+    position: Option<(usize, usize)>,
+    file: PathBuf,
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some((line, column)) => write!(f, "{}:{}:{}", self.file.display(), line, column),
+            None => write!(f, "{}", self.file.display()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ConfigIssue {
+    /// `None` for services of the service directory
+    at: Option<Provenance>,
+    message: String,
+    /// The line of the file the issue is at, with the span underlined
+    snippet: Option<String>,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(at) = &self.at {
+            write!(f, "{}: ", at)?;
+        }
+        f.write_str(&self.message)?;
+        if let Some(snippet) = &self.snippet {
+            write!(f, "\n{}", snippet)?;
+        }
+        Ok(())
+    }
+}
+
+/// The errors and issues found loading the config
+pub(crate) struct Lint<'a> {
+    /// The config file and its content, `None` with only a service
+    /// directory
+    source: Option<(&'a Path, &'a str)>,
+    /// The file parsed again, keeping the spans of keys
+    root: Option<DeTable<'a>>,
+    errors: Vec<ConfigIssue>,
+    /// Services left out as they failed to deserialize
+    invalid: Vec<String>,
+    issues: Vec<ConfigIssue>,
+}
+
+impl<'a> Lint<'a> {
+    pub(crate) fn new(source: Option<(&'a Path, &'a str)>) -> Self {
+        Self {
+            source,
+            root: source
+                .and_then(|(_, content)| DeTable::parse(content).ok().map(Spanned::into_inner)),
+            errors: Vec::new(),
+            invalid: Vec::new(),
+            issues: Vec::new(),
+        }
+    }
+
+    fn issue_at(&self, span: Option<Range<usize>>, message: String) -> ConfigIssue {
+        let Some((file, content)) = self.source else {
+            return ConfigIssue {
+                at: None,
+                message,
+                snippet: None,
+            };
+        };
+        ConfigIssue {
+            at: Some(Provenance {
+                position: span.as_ref().map(|span| position(content, span.start)),
+                file: file.to_path_buf(),
+            }),
+            message,
+            snippet: span.map(|span| snippet(content, span)),
+        }
+    }
+
+    fn locate(&self, path: &[Key]) -> Option<Range<usize>> {
+        self.root.as_ref().and_then(|root| locate(root, path))
+    }
+
+    /// Whether the service `name` is defined but was left out, references
+    /// to it not being errors of their own
+    pub(crate) fn is_invalid(&self, name: &str) -> bool {
+        self.invalid.iter().any(|invalid| invalid == name)
+    }
+
+    /// Record an error about the key at `path`, e.g. `["services", "web"]`
+    pub(crate) fn error(&mut self, path: &[&str], message: String) {
+        let path: Vec<Key> = path.iter().map(|&key| Key::Name(key.into())).collect();
+        self.errors.push(self.issue_at(self.locate(&path), message));
+    }
+
+    /// Record an error about the element `index` of the array `key`
+    pub(crate) fn error_at_index(&mut self, key: &str, index: usize, message: String) {
+        let path = [Key::Name(key.into()), Key::Index(index)];
+        self.errors.push(self.issue_at(self.locate(&path), message));
+    }
+
+    fn toml_error(&mut self, e: toml::de::Error) {
+        self.errors
+            .push(self.issue_at(e.span(), e.message().trim_end().to_string()));
+    }
+
+    fn issue(&mut self, path: &[Key], message: String) {
+        self.issues.push(self.issue_at(self.locate(path), message));
+    }
+
+    /// Deserialize the config file, recording the errors. Parts that fail
+    /// to deserialize are left out: the supervisor options take their
+    /// defaults, and invalid services are missing
+    pub(crate) fn parse(&mut self) -> ServiceConfigData {
+        let Some((_, content)) = self.source else {
+            return ServiceConfigData::default();
+        };
+        let (root, errors) = DeTable::parse_recoverable(content);
+        if !errors.is_empty() {
+            for e in errors {
+                self.toml_error(e);
+            }
+            return ServiceConfigData::default();
+        }
+        let span = root.span();
+        let mut root = root.into_inner();
+        // services are deserialized on their own, in their place the
+        // options get an empty table
+        let services = root.remove_entry("services").map(|(key, services)| {
+            let empty = Spanned::new(services.span(), DeValue::Table(DeTable::new()));
+            root.insert(key, empty);
+            services
+        });
+
+        let mut unknown = Vec::new();
+        let mut data = serde_ignored::deserialize(
+            toml::de::Deserializer::from(Spanned::new(span, root)),
+            |key| {
+                let mut path = Vec::new();
+                key_path(&key, &mut path);
+                unknown.push(path);
+            },
+        )
+        .unwrap_or_else(|e| {
+            self.toml_error(e);
+            ServiceConfigData::default()
+        });
+
+        if let Some(services) = services {
+            let span = services.span();
+            match services.into_inner() {
+                DeValue::Table(services) => {
+                    for (name, cfg) in services {
+                        let name = name.into_inner();
+                        match self.parse_service(&name, cfg, &mut unknown) {
+                            Some(cfg) => {
+                                data.services.insert(name.into_owned(), cfg);
+                            }
+                            None => self.invalid.push(name.into_owned()),
+                        }
+                    }
+                }
+                _ => self
+                    .errors
+                    .push(self.issue_at(Some(span), "services must be a table".to_string())),
+            }
+        }
+        for path in unknown {
+            self.issue(&path, format!("unknown key '{}'", dotted(&path)));
+        }
+        data
+    }
+
+    fn parse_service(
+        &mut self,
+        name: &str,
+        cfg: Spanned<DeValue<'_>>,
+        unknown: &mut Vec<Vec<Key>>,
+    ) -> Option<ServiceConfig> {
+        let span = cfg.span();
+        let result = ServiceConfig::deserialize(serde_ignored::Deserializer::new(
+            ValueDeserializer::from(cfg),
+            &mut |key: serde_ignored::Path<'_>| {
+                let mut path = vec![Key::Name("services".into()), Key::Name(name.into())];
+                key_path(&key, &mut path);
+                unknown.push(path);
+            },
+        ));
+        let cfg = match result {
+            Ok(cfg) => cfg,
+            Err(e) => {
+                self.toml_error(e);
+                return None;
+            }
+        };
+        match cfg.check(name) {
+            Ok(()) => Some(cfg),
+            Err(e) => {
+                let span = self
+                    .locate(&[Key::Name("services".into()), Key::Name(name.into())])
+                    .or(Some(span));
+                self.errors
+                    .push(self.issue_at(span, format!("service '{}': {}", name, e)));
+                None
+            }
+        }
+    }
+
+    /// Record the values of `services` that are likely mistakes
+    pub(crate) fn check_services(&mut self, services: &HashMap<String, ServiceConfig>) {
+        for (name, cfg) in sorted(services) {
+            // services of the service directory run absolute paths
+            if !Path::new(&cfg.command).is_absolute() {
+                let how = match cfg.command.contains('/') {
+                    true => "resolved from the working directory",
+                    false => "looked up in PATH",
+                };
+                self.issue(
+                    &service_key(name, "command"),
+                    format!(
+                        "service '{}': relative command '{}', {}",
+                        name, cfg.command, how
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Record the errors and the likely mistakes of `services` that depend
+    /// on the host: time zones of timers, files, devices, kernel modules
+    /// and privileges
+    pub(crate) fn check_host(&mut self, services: &HashMap<String, ServiceConfig>) {
+        for (name, cfg) in sorted(services) {
+            let key = |field: &str| service_key(name, field);
+            if let Err(e) = cfg.check_host() {
+                self.error(&["services", name], format!("service '{}': {}", name, e));
+            }
+            for (i, secret) in cfg.secrets.iter().enumerate() {
+                if world_writable(&secret.path) {
+                    let mut path = key("secrets");
+                    path.extend([Key::Index(i), Key::Name("path".into())]);
+                    self.issue(
+                        &path,
+                        format!(
+                            "service '{}': secret file '{}' is world-writable",
+                            name,
+                            secret.path.display()
+                        ),
+                    );
+                }
+            }
+            // resolved again on each start, the device may come later
+            for (i, limit) in cfg.io_max.iter().enumerate() {
+                if let Err(e) = limit.resolve() {
+                    let mut path = key("io_max");
+                    path.extend([Key::Index(i), Key::Name("device".into())]);
+                    self.issue(&path, format!("service '{}': {}", name, e));
+                }
+            }
+            // read again on each start, it may be written later
+            if let Some(path) = &cfg.env_file {
+                if let Err(e) = read_env_file(path) {
+                    self.issue(&key("env_file"), format!("service '{}': {}", name, e));
+                } else if world_writable(path) {
+                    self.issue(
+                        &key("env_file"),
+                        format!(
+                            "service '{}': env_file '{}' is world-writable",
+                            name,
+                            path.display()
+                        ),
+                    );
+                }
+            }
+            let platform = Platform::get();
+            for (field, set, module, enabled) in [
+                (
+                    "selinux_context",
+                    cfg.selinux_context.is_some(),
+                    "SELinux",
+                    platform.selinux,
+                ),
+                (
+                    "apparmor_profile",
+                    cfg.apparmor_profile.is_some(),
+                    "AppArmor",
+                    platform.apparmor,
+                ),
+            ] {
+                if set && !enabled {
+                    self.issue(
+                        &key(field),
+                        format!(
+                            "service '{}': {} ignored, {} isn't enabled",
+                            name, field, module
+                        ),
+                    );
+                }
+            }
+            if cfg.private_tmp_size_mib.is_some() && !geteuid().is_root() {
+                self.issue(
+                    &key("private_tmp_size_mib"),
+                    format!(
+                        "service '{}': private_tmp_size_mib ignored, the tmpfs needs root",
+                        name
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Log the issues, or with `strict` count them as errors, then fail
+    /// with all the errors if any
+    pub(crate) fn finish(mut self, strict: bool) -> io::Result<()> {
+        if strict {
+            self.errors.append(&mut self.issues);
+        }
+        self.issues.sort_unstable_by(|a, b| a.at.cmp(&b.at));
+        for issue in &self.issues {
+            svlogg!(LogLevel::Warn, "{}", issue);
+        }
+        self.errors.sort_by(|a, b| a.at.cmp(&b.at));
+        match self.errors.as_slice() {
+            [] => Ok(()),
+            [error] => Err(io::Error::other(error.to_string())),
+            errors => Err(io::Error::other(format!(
+                "{} errors in the config:\n{}",
+                errors.len(),
+                errors
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ))),
+        }
+    }
+}
+
+/// `services` sorted by name, for issues to be reported in a stable order
+fn sorted(services: &HashMap<String, ServiceConfig>) -> Vec<(&String, &ServiceConfig)> {
+    let mut services: Vec<_> = services.iter().collect();
+    services.sort_unstable_by_key(|(name, _)| *name);
+    services
+}
+
+/// Path of the key `field` of the service `name`
+fn service_key(name: &str, field: &str) -> Vec<Key> {
+    vec![
+        Key::Name("services".into()),
+        Key::Name(name.into()),
+        Key::Name(field.into()),
+    ]
+}
+
+/// Span of the last key of `path` in `root`, or of the array element it
+/// ends with
+fn locate(root: &DeTable<'_>, path: &[Key]) -> Option<Range<usize>> {
+    let mut table = Some(root);
+    let mut array: Option<&[Spanned<DeValue<'_>>]> = None;
+    let mut span = None;
+    for key in path {
+        let value = match key {
+            Key::Name(name) => {
+                let (key, value) = table?.iter().find(|(key, _)| key.get_ref() == name)?;
+                span = Some(key.span());
+                value
+            }
+            Key::Index(i) => {
+                let value = array?.get(*i)?;
+                span = Some(value.span());
+                value
+            }
+        };
+        (table, array) = match value.get_ref() {
+            DeValue::Table(t) => (Some(t), None),
+            DeValue::Array(a) => (None, Some(a.as_ref())),
+            _ => (None, None),
+        };
+    }
+    span
+}
+
+/// Line and column of the byte `offset` of `content`
+fn position(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// The line of `content` that `span` starts on, with the span underlined
+/// up to the end of the line
+fn snippet(content: &str, span: Range<usize>) -> String {
+    let start = span.start.min(content.len());
+    let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = content[start..]
+        .find('\n')
+        .map_or(content.len(), |i| start + i);
+    let line = content[line_start..line_end].trim_end_matches('\r');
+    let (number, column) = position(content, start);
+    let underline = content[start..span.end.clamp(start, line_end)]
+        .chars()
+        .count()
+        .max(1);
+    let gutter = number.to_string().len();
+    format!(
+        "{:>gutter$} | {}\n{:>gutter$} | {}{}",
+        number,
+        line,
+        "",
+        " ".repeat(column - 1),
+        "^".repeat(underline),
+        gutter = gutter
+    )
+}
+
+fn world_writable(path: &Path) -> bool {
+    std::fs::metadata(path).is_ok_and(|meta| meta.permissions().mode() & 0o002 != 0)
+}
@@ -0,0 +1,625 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    fmt, io,
+    io::Write,
+    ops::Range,
+    os::fd::{AsFd, BorrowedFd, OwnedFd},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use regex::bytes::Regex;
+use rustix::{
+    fs::{Mode, OFlags, fcntl_getfl, fcntl_setfl, fstat, open},
+    pipe::{PipeFlags, pipe_with},
+};
+use serde::{Deserialize, Deserializer};
+
+use crate::logging::LogLevel;
+use crate::logrotate::{LogRotate, compress_rotated_log, prune_rotated_logs, rotate_log_file};
+use crate::svlogg;
+use crate::utils::{FdWriter, format_rfc3339, retry_eintr, timestamp};
+
+/// Tag bit marking an epoll event id as a log pipe. The remaining bits
+/// encode the service id (shifted left by one) and the stream (lowest bit)
+pub(crate) const EPOLL_ID_TAG: u64 = 1 << 63;
+
+/// Size of the buffer used for a single read from a log pipe
+const READ_BUF_LEN: usize = 16 * 1024;
+
+/// Partial lines longer than this are written out as if they were
+/// complete, so that a service that never writes a newline can't make
+/// the supervisor buffer unbounded amounts of data
+const MAX_LINE_LEN: usize = 16 * 1024;
+
+/// Coalesced records longer than this are written out even if more
+/// continuation lines follow
+const MAX_RECORD_LEN: usize = 64 * 1024;
+
+/// How long a write to the log file may wait for it to become writable,
+/// when it's e.g. a FIFO whose reader fell behind. The supervisor loop is
+/// blocked meanwhile, so the output is dropped past it
+const LOG_WRITE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The standard stream a log pipe is attached to.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LogStream {
+    Stdout = 0,
+    Stderr = 1,
+}
+
+impl fmt::Display for LogStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stdout => write!(f, "stdout"),
+            Self::Stderr => write!(f, "stderr"),
+        }
+    }
+}
+
+impl LogStream {
+    const ALL: [LogStream; 2] = [LogStream::Stdout, LogStream::Stderr];
+
+    /// Encode `svc_id` and `self` into an epoll event id
+    #[inline(always)]
+    pub(crate) const fn epoll_id(self, svc_id: u64) -> u64 {
+        EPOLL_ID_TAG | (svc_id << 1) | self as u64
+    }
+
+    /// Decode an epoll event id built with `LogStream::epoll_id`. The caller
+    /// must have checked that `EPOLL_ID_TAG` is set
+    #[inline(always)]
+    pub(crate) const fn from_epoll_id(id: u64) -> (u64, Self) {
+        let stream = if id & 1 == 0 {
+            Self::Stdout
+        } else {
+            Self::Stderr
+        };
+        ((id & !EPOLL_ID_TAG) >> 1, stream)
+    }
+}
+
+/// Format of the timestamp prepended to each log line.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TimestampFormat {
+    /// No timestamp
+    #[default]
+    None,
+    /// Seconds and nanoseconds since the epoch (`1700000000.000000000`)
+    Unix,
+    /// RFC 3339 in UTC (`2023-11-14T22:13:20.000000000Z`)
+    Rfc3339,
+}
+
+/// Prefixes prepended by the log pump to each line written by a service.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct LogPrefix {
+    #[serde(default)]
+    pub(crate) timestamp: TimestampFormat,
+    #[serde(default)]
+    pub(crate) service_name: bool,
+    #[serde(default)]
+    pub(crate) stream: bool,
+}
+
+/// Regex matching continuation lines of a multi-line record.
+///
+/// Wraps `regex::bytes::Regex` so that it can be deserialized from the
+/// configuration and compared by its source pattern
+#[derive(Debug, Clone)]
+pub(crate) struct ContinuationPattern(Regex);
+
+impl PartialEq for ContinuationPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for ContinuationPattern {}
+
+impl<'de> Deserialize<'de> for ContinuationPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern)
+            .map(Self)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Coalescing of multi-line records (e.g. stack traces) into a single
+/// log record.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq)]
+pub(crate) struct LogMultiline {
+    /// Lines matching this regex are appended to the previous record.
+    /// If `None`, lines starting with a space or a tab are
+    #[serde(default)]
+    pub(crate) continuation_regex: Option<ContinuationPattern>,
+}
+
+impl LogMultiline {
+    #[inline(always)]
+    fn is_continuation(&self, line: &[u8]) -> bool {
+        match &self.continuation_regex {
+            Some(re) => re.0.is_match(line),
+            None => matches!(line.first(), Some(b' ' | b'\t')),
+        }
+    }
+}
+
+/// Maximum number of records written to the log file per interval.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LogRateLimit {
+    /// Records allowed per interval
+    pub(crate) lines: u64,
+    pub(crate) interval: LogInterval,
+}
+
+/// Length of a rate limit window, written as a duration string: numbers
+/// followed by `ms`, `s`, `m` or `h`, e.g. `"10s"` or `"1m30s"`
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub(crate) struct LogInterval(Duration);
+
+impl TryFrom<String> for LogInterval {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid interval '{}', expected e.g. \"10s\"", text);
+        let mut rest = text.trim();
+        if rest.is_empty() {
+            return Err(invalid());
+        }
+        let mut total = Duration::ZERO;
+        while !rest.is_empty() {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let value: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+            rest = &rest[digits..];
+            let unit = rest
+                .find(|c: char| c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let millis = match rest[..unit].trim() {
+                "ms" => 1,
+                "s" => 1000,
+                "m" => 60 * 1000,
+                "h" => 60 * 60 * 1000,
+                _ => return Err(invalid()),
+            };
+            rest = rest[unit..].trim_start();
+            total = value
+                .checked_mul(millis)
+                .and_then(|ms| total.checked_add(Duration::from_millis(ms)))
+                .ok_or_else(invalid)?;
+        }
+        if total.is_zero() {
+            return Err(format!("interval '{}' must be more than 0", text));
+        }
+        Ok(Self(total))
+    }
+}
+
+/// Log pump options of a service.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct LogPumpOptions {
+    pub(crate) prefix: LogPrefix,
+    pub(crate) multiline: Option<LogMultiline>,
+    pub(crate) rate_limit: Option<LogRateLimit>,
+    pub(crate) rotate: Option<LogRotate>,
+}
+
+/// Open a service log file for appending
+/// The log file is only made non-blocking once owned by a pump, as the
+/// flag is shared with children it's handed to directly
+fn set_nonblocking(fd: &OwnedFd) -> rustix::io::Result<()> {
+    fcntl_setfl(fd, fcntl_getfl(fd)? | OFlags::NONBLOCK)
+}
+
+pub(crate) fn open_log_file(path: &Path) -> rustix::io::Result<OwnedFd> {
+    open(
+        path,
+        OFlags::WRONLY | OFlags::CREATE | OFlags::APPEND | OFlags::CLOEXEC,
+        Mode::from_bits_truncate(0o644),
+    )
+}
+
+/// Fixed window rate limiter state.
+///
+/// Records past the budget of the current window are dropped and counted,
+/// the count is reported once the window is over
+#[derive(Debug)]
+struct RateLimiter {
+    lines: u64,
+    interval: Duration,
+    window_start: Instant,
+    written: u64,
+    suppressed: u64,
+}
+
+impl RateLimiter {
+    fn new(limit: LogRateLimit) -> Self {
+        Self {
+            lines: limit.lines,
+            interval: limit.interval.0,
+            window_start: Instant::now(),
+            written: 0,
+            suppressed: 0,
+        }
+    }
+
+    /// Start a new window if the current one is over. Return the number
+    /// of records suppressed in the window that just ended, if any. A
+    /// window whose end can't be represented never ends
+    fn roll(&mut self, now: Instant) -> Option<u64> {
+        if self
+            .window_start
+            .checked_add(self.interval)
+            .is_none_or(|end| now < end)
+        {
+            return None;
+        }
+        self.window_start = now;
+        self.written = 0;
+        Some(std::mem::take(&mut self.suppressed)).filter(|&n| n > 0)
+    }
+
+    /// Count a record against the budget, returning `false` if it has
+    /// to be dropped
+    fn allow(&mut self) -> bool {
+        if self.written < self.lines {
+            self.written += 1;
+            true
+        } else {
+            self.suppressed += 1;
+            false
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PumpStream {
+    /// Read end of the pipe, `None` once `EOF` has been reached
+    fd: Option<OwnedFd>,
+    /// Bytes read after the last newline
+    partial: Vec<u8>,
+    /// Record being coalesced, only used with `LogMultiline`
+    record: Vec<u8>,
+    /// Whether `record` grew since the last call to `LogPump::flush_idle`
+    record_touched: bool,
+}
+
+impl PumpStream {
+    fn new(fd: OwnedFd) -> Self {
+        Self {
+            fd: Some(fd),
+            partial: Vec::new(),
+            record: Vec::new(),
+            record_touched: false,
+        }
+    }
+}
+
+/// Reads the output of a service process from a pair of pipes and writes
+/// it to the service log file, one prefixed line at a time.
+///
+/// The pump is driven by the main epoll loop: each pipe read end is
+/// registered with an id built by `LogStream::epoll_id` and
+/// `LogPump::pump` is called whenever it becomes readable
+#[derive(Debug)]
+pub(crate) struct LogPump {
+    service_name: Arc<str>,
+    options: LogPumpOptions,
+    log_path: PathBuf,
+    /// The log file, buffering the lines written out by a single call
+    log: FdWriter<OwnedFd>,
+    /// Size of the log file, tracked for rotation
+    log_size: u64,
+    streams: [PumpStream; 2],
+    limiter: Option<RateLimiter>,
+    /// Lines of the current read written out straight from the read
+    /// buffer, as `(offset in the log buffer, range in the read buffer)`
+    spliced: Vec<(usize, Range<usize>)>,
+}
+
+impl LogPump {
+    /// Create a new pump writing to `log_fd`, the already opened log file
+    /// at `log_path`. Return the pump and the write ends of the stdout and
+    /// stderr pipes, meant to be handed to the child
+    pub(crate) fn new(
+        service_name: &Arc<str>,
+        options: LogPumpOptions,
+        log_path: &Path,
+        log_fd: OwnedFd,
+    ) -> io::Result<(Self, [OwnedFd; 2])> {
+        let (stdout_rd, stdout_wr) = pipe_with(PipeFlags::CLOEXEC)?;
+        let (stderr_rd, stderr_wr) = pipe_with(PipeFlags::CLOEXEC)?;
+        for fd in [&stdout_rd, &stderr_rd, &log_fd] {
+            set_nonblocking(fd)?;
+        }
+        let pump = Self {
+            service_name: Arc::clone(service_name),
+            limiter: options.rate_limit.map(RateLimiter::new),
+            options,
+            log_path: log_path.to_owned(),
+            log_size: fstat(&log_fd)?.st_size as u64,
+            log: FdWriter::new(log_fd, Some(LOG_WRITE_TIMEOUT)),
+            streams: [PumpStream::new(stdout_rd), PumpStream::new(stderr_rd)],
+            spliced: Vec::new(),
+        };
+        Ok((pump, [stdout_wr, stderr_wr]))
+    }
+
+    /// Iterate over the streams that are still open along with their fds
+    pub(crate) fn open_streams(&self) -> impl Iterator<Item = (LogStream, BorrowedFd<'_>)> {
+        LogStream::ALL.into_iter().filter_map(|stream| {
+            self.streams[stream as usize]
+                .fd
+                .as_ref()
+                .map(|fd| (stream, fd.as_fd()))
+        })
+    }
+
+    /// Return `true` once both pipes have reached `EOF`
+    #[inline(always)]
+    pub(crate) fn is_finished(&self) -> bool {
+        self.streams.iter().all(|s| s.fd.is_none())
+    }
+
+    /// Read what is available on `stream` and write every complete line (or
+    /// record, with `LogMultiline`) to the log file. On `EOF` the pending
+    /// partial line and record are written as well and the read end is
+    /// closed, which also removes it from epoll.
+    ///
+    /// N.B. this performs a single read per call: as for the signalfd, we
+    /// rely on level-triggered epoll to be woken up again
+    pub(crate) fn pump(&mut self, stream: LogStream) -> io::Result<()> {
+        let Some(fd) = self.streams[stream as usize].fd.as_ref() else {
+            return Ok(());
+        };
+        let mut buf = [0u8; READ_BUF_LEN];
+        let n = match retry_eintr(|| rustix::io::read(fd, &mut buf)) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if n == 0 {
+            self.streams[stream as usize].fd = None;
+            self.flush_partial(stream);
+            self.flush_record(stream);
+            if self.is_finished() {
+                self.flush_suppressed();
+            }
+        } else {
+            let mut data = &buf[..n];
+            let mut offset = 0;
+            while let Some(pos) = data.iter().position(|&b| b == b'\n') {
+                let mut line = std::mem::take(&mut self.streams[stream as usize].partial);
+                if line.is_empty() {
+                    self.handle_read_line(stream, &data[..pos], offset);
+                } else {
+                    line.extend_from_slice(&data[..pos]);
+                    self.handle_line(stream, &line);
+                    // keep the allocation around for the next partial line
+                    line.clear();
+                    self.streams[stream as usize].partial = line;
+                }
+                data = &data[pos + 1..];
+                offset += pos + 1;
+            }
+            self.streams[stream as usize]
+                .partial
+                .extend_from_slice(data);
+            if self.streams[stream as usize].partial.len() >= MAX_LINE_LEN {
+                self.flush_partial(stream);
+            }
+        }
+        self.write_out(&buf)
+    }
+
+    /// Write out records that did not grow since the previous call, and
+    /// the number of suppressed records if the rate limit window is over.
+    ///
+    /// Meant to be called periodically (i.e. on each timerfd tick), so that
+    /// the last record written by a service does not wait for the next line
+    /// to show up in the log file
+    pub(crate) fn flush_idle(&mut self) -> io::Result<()> {
+        if let Some(suppressed) = self.limiter.as_mut().and_then(|l| l.roll(Instant::now())) {
+            self.push_suppressed(suppressed);
+        }
+        for stream in LogStream::ALL {
+            let s = &mut self.streams[stream as usize];
+            if std::mem::take(&mut s.record_touched) {
+                continue;
+            }
+            self.flush_record(stream);
+        }
+        self.write_out(&[])
+    }
+
+    /// Write the output buffer, along with the lines spliced in from `read`,
+    /// the buffer of the last read, to the log file, then rotate the log
+    /// file if it has grown past `LogRotate::max_bytes`
+    fn write_out(&mut self, read: &[u8]) -> io::Result<()> {
+        let len = self.log.buffered() + self.spliced.iter().map(|(_, r)| r.len()).sum::<usize>();
+        self.log.flush_spliced(
+            self.spliced
+                .drain(..)
+                .map(|(offset, range)| (offset, &read[range])),
+        )?;
+        self.log_size += len as u64;
+        match self.options.rotate {
+            Some(rotate) if self.log_size >= rotate.max_bytes => self.rotate(rotate),
+            _ => Ok(()),
+        }
+    }
+
+    /// Move the current log file aside and reopen `log_path`. Compression
+    /// and pruning of rotated files happen on a background thread
+    fn rotate(&mut self, rotate: LogRotate) -> io::Result<()> {
+        let rotated = rotate_log_file(&self.log_path)?;
+        let log_fd = open_log_file(&self.log_path)?;
+        set_nonblocking(&log_fd)?;
+        self.log.replace_fd(log_fd);
+        self.log_size = 0;
+        svlogg!(
+            LogLevel::Debug,
+            "rotated log of service '{}' to '{}'",
+            self.service_name,
+            rotated.display()
+        );
+        match rotate.compress {
+            Some(compression) => {
+                compress_rotated_log(rotated, self.log_path.clone(), rotate, compression)
+            }
+            None => prune_rotated_logs(&self.log_path, rotate.keep),
+        }
+    }
+
+    /// Move the pending partial line of `stream`, if any, to the output buffer
+    fn flush_partial(&mut self, stream: LogStream) {
+        let partial = std::mem::take(&mut self.streams[stream as usize].partial);
+        if !partial.is_empty() {
+            self.handle_line(stream, &partial);
+        }
+    }
+
+    /// Move the number of records suppressed in the current rate limit
+    /// window, if any, to the output buffer
+    fn flush_suppressed(&mut self) {
+        if let Some(limiter) = self.limiter.as_mut()
+            && limiter.suppressed > 0
+        {
+            let suppressed = std::mem::take(&mut limiter.suppressed);
+            self.push_suppressed(suppressed);
+        }
+    }
+
+    /// Move the record being coalesced on `stream`, if any, to the output
+    /// buffer
+    fn flush_record(&mut self, stream: LogStream) {
+        let mut record = std::mem::take(&mut self.streams[stream as usize].record);
+        if !record.is_empty() {
+            self.emit(stream, &record);
+            record.clear();
+            self.streams[stream as usize].record = record;
+        }
+    }
+
+    /// Either append `line` to the current record of `stream` or start a new
+    /// one. Without `LogMultiline` every line is a record on its own
+    fn handle_line(&mut self, stream: LogStream, line: &[u8]) {
+        let Some(multiline) = &self.options.multiline else {
+            self.emit(stream, line);
+            return;
+        };
+        let is_continuation = multiline.is_continuation(line);
+        let s = &mut self.streams[stream as usize];
+        if is_continuation && !s.record.is_empty() && s.record.len() < MAX_RECORD_LEN {
+            s.record.push(b'\n');
+            s.record.extend_from_slice(line);
+        } else {
+            self.flush_record(stream);
+            self.streams[stream as usize].record.extend_from_slice(line);
+        }
+        self.streams[stream as usize].record_touched = true;
+    }
+
+    /// Like `handle_line`, for a line found at `offset` in the buffer of
+    /// the current read. Unless it's coalesced into a record, the line is
+    /// spliced into the output rather than copied to the output buffer
+    fn handle_read_line(&mut self, stream: LogStream, line: &[u8], offset: usize) {
+        if self.options.multiline.is_some() {
+            self.handle_line(stream, line);
+            return;
+        }
+        if !self.allow_record() {
+            return;
+        }
+        self.push_prefix(Some(stream));
+        let out = self.log.buffer_mut();
+        self.spliced.push((out.len(), offset..offset + line.len()));
+        out.push(b'\n');
+    }
+
+    /// Append `record` to the output buffer, unless the rate limit has been
+    /// exceeded
+    fn emit(&mut self, stream: LogStream, record: &[u8]) {
+        if self.allow_record() {
+            self.push_line(Some(stream), record);
+        }
+    }
+
+    /// Whether the rate limit allows one more record. The notice about the
+    /// records suppressed in the previous window, if over, is appended to
+    /// the output buffer
+    fn allow_record(&mut self) -> bool {
+        let Some(limiter) = self.limiter.as_mut() else {
+            return true;
+        };
+        let suppressed = limiter.roll(Instant::now());
+        let allowed = limiter.allow();
+        if let Some(suppressed) = suppressed {
+            self.push_suppressed(suppressed);
+        }
+        allowed
+    }
+
+    /// Append the notice about `n` suppressed records to the output buffer.
+    /// It's tagged as coming from `svlopp` rather than from a stream
+    fn push_suppressed(&mut self, n: u64) {
+        let msg = format!("suppressed {} lines (log_rate_limit exceeded)", n);
+        self.push_line(None, msg.as_bytes());
+    }
+
+    /// Append `line` to the output buffer, prefixed as configured. `line`
+    /// may span multiple lines if it's a coalesced record, in which case
+    /// only the first one gets the prefix. Lines without a stream are
+    /// written by svlopp itself
+    fn push_line(&mut self, stream: Option<LogStream>, line: &[u8]) {
+        self.push_prefix(stream);
+        let out = self.log.buffer_mut();
+        out.extend_from_slice(line);
+        out.push(b'\n');
+    }
+
+    /// Append the prefix of a line written to `stream` to the output buffer
+    fn push_prefix(&mut self, stream: Option<LogStream>) {
+        let out = self.log.buffer_mut();
+        let start = out.len();
+        // writes to a `Vec` can't fail
+        let prefix = self.options.prefix;
+        match prefix.timestamp {
+            TimestampFormat::None => {}
+            TimestampFormat::Unix => {
+                let (secs, nsecs) = timestamp();
+                let _ = write!(out, "[{}.{:09}]", secs, nsecs);
+            }
+            TimestampFormat::Rfc3339 => {
+                let (secs, nsecs) = timestamp();
+                out.push(b'[');
+                let _ = format_rfc3339(out, secs, nsecs);
+                out.push(b']');
+            }
+        }
+        if prefix.service_name {
+            let _ = write!(out, "[{}]", self.service_name);
+        }
+        if prefix.stream {
+            match stream {
+                Some(stream) => {
+                    let _ = write!(out, "[{}]", stream);
+                }
+                None => out.extend_from_slice(b"[svlopp]"),
+            }
+        }
+        if out.len() > start {
+            out.push(b' ');
+        }
+    }
+}
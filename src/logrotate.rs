@@ -0,0 +1,225 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    ffi::OsString,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        mpsc::{self, Sender},
+    },
+};
+
+use serde::Deserialize;
+
+use crate::logging::LogLevel;
+use crate::svlogg;
+use crate::utils::timestamp;
+
+/// Default number of rotated files kept
+const DEFAULT_KEEP: usize = 5;
+
+/// Suffix of files being compressed, renamed once complete
+const TMP_SUFFIX: &str = ".tmp";
+
+/// Sender of the jobs of the compression worker, spawned by the first
+/// rotation that needs it
+static COMPRESSOR: Mutex<Option<Sender<CompressJob>>> = Mutex::new(None);
+
+fn default_keep() -> usize {
+    DEFAULT_KEEP
+}
+
+/// Compression algorithm for rotated log files.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogCompression {
+    Gzip,
+    /// Only available when built with the `zstd` feature
+    Zstd,
+}
+
+impl LogCompression {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Gzip => "gz",
+            Self::Zstd => "zst",
+        }
+    }
+}
+
+/// Size based rotation of a service log file.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LogRotate {
+    /// The log file is rotated once it grows past this size
+    pub(crate) max_bytes: u64,
+    /// Number of rotated files to keep, older ones are deleted
+    #[serde(default = "default_keep")]
+    pub(crate) keep: usize,
+    /// Optional compression of rotated files
+    #[serde(default)]
+    pub(crate) compress: Option<LogCompression>,
+    /// Compression level, defaults to the algorithm default
+    #[serde(default)]
+    pub(crate) compression_level: Option<u32>,
+}
+
+impl LogRotate {
+    /// Check that the file can grow before being rotated, and that the
+    /// configured compression is available in this build
+    pub(crate) fn validate(&self) -> io::Result<()> {
+        // would rotate on every write
+        if self.max_bytes == 0 {
+            return Err(io::Error::other("log_rotate max_bytes must be positive"));
+        }
+        if self.compress == Some(LogCompression::Zstd) && !cfg!(feature = "zstd") {
+            return Err(io::Error::other(
+                "zstd log compression requires svlopp to be built with the `zstd` feature",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Rename `path` to `<path>.<secs>.<nsecs>` and return the new path.
+///
+/// Rotated files are named after the rotation time rather than shifted
+/// (`.1`, `.2`, ...), so that a rotated file never changes name while a
+/// background compression is reading it
+pub(crate) fn rotate_log_file(path: &Path) -> io::Result<PathBuf> {
+    let (secs, nsecs) = timestamp();
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}.{:09}", secs, nsecs));
+    let rotated = PathBuf::from(rotated);
+    std::fs::rename(path, &rotated)?;
+    Ok(rotated)
+}
+
+/// Delete the oldest rotated files of `path` so that at most `keep` are left
+pub(crate) fn prune_rotated_logs(path: &Path, keep: usize) -> io::Result<()> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(());
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let mut prefix = name.to_owned();
+    prefix.push(".");
+    let prefix = prefix.as_encoded_bytes();
+    let mut rotated: Vec<OsString> = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry_name = entry?.file_name();
+        let bytes = entry_name.as_encoded_bytes();
+        if bytes.starts_with(prefix)
+            && bytes.get(prefix.len()).is_some_and(u8::is_ascii_digit)
+            && !bytes.ends_with(TMP_SUFFIX.as_bytes())
+        {
+            rotated.push(entry_name);
+        }
+    }
+    if rotated.len() <= keep {
+        return Ok(());
+    }
+    // names only differ by the rotation timestamp, so they sort chronologically
+    rotated.sort();
+    for name in &rotated[..rotated.len() - keep] {
+        std::fs::remove_file(dir.join(name))?;
+    }
+    Ok(())
+}
+
+/// A rotated file to compress, and the log file it was rotated from
+struct CompressJob {
+    path: PathBuf,
+    log_path: PathBuf,
+    rotate: LogRotate,
+    compression: LogCompression,
+}
+
+impl CompressJob {
+    fn run(self) {
+        let result = compress_file(&self.path, self.compression, self.rotate.compression_level)
+            .and_then(|()| prune_rotated_logs(&self.log_path, self.rotate.keep));
+        if let Err(e) = result {
+            svlogg!(
+                LogLevel::Warn,
+                "failed to compress rotated log '{}': {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Compress `path` into `<path>.<ext>` on the compression worker, then
+/// remove `path` and prune old rotated files.
+///
+/// Compression can take a while for big files and the main loop must not
+/// block on it. A single worker compresses one file at a time, in rotation
+/// order, so that services rotating often don't pile up threads
+pub(crate) fn compress_rotated_log(
+    path: PathBuf,
+    log_path: PathBuf,
+    rotate: LogRotate,
+    compression: LogCompression,
+) -> io::Result<()> {
+    let job = CompressJob {
+        path,
+        log_path,
+        rotate,
+        compression,
+    };
+    let mut compressor = COMPRESSOR.lock().unwrap_or_else(|e| e.into_inner());
+    // the worker is gone if a job panicked
+    let job = match compressor.as_ref() {
+        Some(tx) => match tx.send(job) {
+            Ok(()) => return Ok(()),
+            Err(mpsc::SendError(job)) => job,
+        },
+        None => job,
+    };
+    let (tx, rx) = mpsc::channel::<CompressJob>();
+    std::thread::Builder::new()
+        .name("svlopp-logcompress".into())
+        .spawn(move || rx.into_iter().for_each(CompressJob::run))?;
+    tx.send(job).expect("the worker holds the receiver");
+    *compressor = Some(tx);
+    Ok(())
+}
+
+fn compress_file(path: &Path, compression: LogCompression, level: Option<u32>) -> io::Result<()> {
+    let mut dst_path = path.as_os_str().to_owned();
+    dst_path.push(".");
+    dst_path.push(compression.extension());
+    let dst_path = PathBuf::from(dst_path);
+    let mut tmp_path = dst_path.clone().into_os_string();
+    tmp_path.push(TMP_SUFFIX);
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let mut src = File::open(path)?;
+    let dst = File::create(&tmp_path)?;
+    match compression {
+        LogCompression::Gzip => {
+            let level = level.map_or(flate2::Compression::default(), flate2::Compression::new);
+            let mut encoder = flate2::write::GzEncoder::new(dst, level);
+            io::copy(&mut src, &mut encoder)?;
+            encoder.finish()?.sync_all()?;
+        }
+        #[cfg(feature = "zstd")]
+        LogCompression::Zstd => {
+            let level = level.map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |l| l as i32);
+            let mut encoder = zstd::Encoder::new(dst, level)?;
+            io::copy(&mut src, &mut encoder)?;
+            encoder.finish()?.sync_all()?;
+        }
+        #[cfg(not(feature = "zstd"))]
+        LogCompression::Zstd => unreachable!("rejected by `LogRotate::validate`"),
+    }
+    std::fs::rename(&tmp_path, &dst_path)?;
+    std::fs::remove_file(path)
+}
@@ -0,0 +1,66 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Security labels of service processes (`selinux_context` and
+//! `apparmor_profile`).
+//!
+//! The label is set as libselinux's `setexeccon` and libapparmor's
+//! `aa_change_onexec` do, by writing it to an `attr` file of the process
+//! in procfs, so that neither library is linked. It applies from the exec
+//! of the service command on. Whether the security module is enabled is
+//! only known at runtime (see `crate::platform`): a label is ignored with
+//! a warning on systems without its module, so that the same config can
+//! be used on hosts with and without it.
+
+use std::{
+    ffi::{CStr, CString},
+    path::Path,
+};
+
+use crate::logging::LogLevel;
+use crate::platform::Platform;
+use crate::service::ServiceConfig;
+use crate::spawn::ExecLabel;
+use crate::svlogg;
+
+/// The `attr` file of the SELinux exec context
+const SELINUX_EXEC_ATTR: &CStr = c"/proc/self/attr/exec";
+
+/// The `attr` file of AppArmor on kernels with LSM stacking (Linux 5.8),
+/// the shared one being used without it
+const APPARMOR_EXEC_ATTR: &CStr = c"/proc/self/attr/apparmor/exec";
+
+/// The label the service process of `cfg` execs with, `None` if it has
+/// none or if its security module isn't enabled
+pub(crate) fn exec_label(name: &str, cfg: &ServiceConfig) -> Option<ExecLabel> {
+    let platform = Platform::get();
+    let (path, value) = match (&cfg.selinux_context, &cfg.apparmor_profile) {
+        (Some(context), _) if platform.selinux => (SELINUX_EXEC_ATTR, context.clone()),
+        (None, Some(profile)) if platform.apparmor => {
+            let path = match Path::new("/proc/self/attr/apparmor").exists() {
+                true => APPARMOR_EXEC_ATTR,
+                false => SELINUX_EXEC_ATTR,
+            };
+            (path, format!("exec {}", profile))
+        }
+        (None, None) => return None,
+        (context, _) => {
+            let (key, module) = match context {
+                Some(_) => ("selinux_context", "SELinux"),
+                None => ("apparmor_profile", "AppArmor"),
+            };
+            svlogg!(
+                LogLevel::Warn,
+                "service '{}': {} ignored, {} isn't enabled",
+                name,
+                key,
+                module
+            );
+            return None;
+        }
+    };
+    // checked by `ServiceConfig::validate`
+    let value = CString::new(value).ok()?;
+    Some(ExecLabel { path, value })
+}
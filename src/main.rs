@@ -4,43 +4,162 @@
 
 #![deny(clippy::unwrap_used)]
 
-use std::{os::fd::AsFd, time::Instant};
+// signalfd, timerfd, pidfds, procfs and cgroups: the supervisor is Linux
+// only, while the library and `svloppctl` build on any unix
+#[cfg(not(target_os = "linux"))]
+compile_error!("svlopp only supports Linux");
+
+use std::{
+    os::fd::AsFd,
+    path::Path,
+    time::{Duration, Instant},
+};
 
 use rustix::{
     event::epoll,
     fs::{CWD, Mode, mkdirat},
-    process::{Pid, set_child_subreaper},
+    process::{Pid, set_child_subreaper, test_kill_process},
+};
+use svlopp::{
+    ADOPT_DIR_NAME, CONTROL_PIPE_NAME, DIAGNOSTICS_DIR_NAME, PLAN_DIR_NAME, PLATFORM_FILE_NAME,
+    PRIVATE_TMP_DIR_NAME, PROPERTY_DIR_NAME, PS_DIR_NAME, RELOAD_FILE_NAME, RUN_SET_FILE_NAME,
+    SNAPSHOT_FILE_NAME, STARTUP_FILE_NAME, STATUS_DIR_NAME, STATUS_FILE_NAME, TRANSIENT_DIR_NAME,
+    read_buf::ReadBuf, snapshot::Snapshot,
 };
 
+mod adopt;
+mod alerts;
+#[cfg(feature = "api")]
+mod api;
+mod arena;
+mod cgroup;
 mod cli;
 mod control;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod diagnostics;
+mod envfile;
+mod graph;
+mod hooks;
+mod init;
+mod jobs;
+mod keyring;
+mod lint;
 mod logging;
+mod logpump;
+mod logrotate;
+#[cfg(feature = "lsm")]
+mod lsm;
+mod mounts;
+mod netstats;
+mod notify;
+mod oom;
+mod orphans;
+mod platform;
+mod ports;
+mod pressure;
+mod procfs;
+mod profile;
+mod properties;
+mod reload;
+mod resources;
+mod restarts;
+mod rollout;
+mod runset;
+mod scandir;
+mod secrets;
 mod service;
 mod signalfd;
+mod slab;
+mod spawn;
+mod spawner;
 mod status;
+mod swap;
+mod timer;
 mod timerfd;
+#[cfg(feature = "tls")]
+mod tls;
+mod transient;
+mod userns;
 mod utils;
+mod watchdog;
+mod webhooks;
 
-use control::{ControlError, create_control_fifo, read_control_command};
+use adopt::adopt;
+use alerts::Alerts;
+use arena::Arena;
+use cgroup::ServiceCgroups;
+use control::{ControlOp, create_control_fifo, read_control_commands};
+use envfile::EnvFileWatcher;
+use graph::write_graph;
+use hooks::{Hook, run_hook};
+use init::{ForwardedSignal, forward_signal, has_children};
+use jobs::JobsFile;
 use logging::{LogLevel, set_log_level};
+use logpump::{EPOLL_ID_TAG, LogStream};
+use netstats::NetStatsFile;
+use notify::{ReadyListener, StatusNotifier};
+use oom::OomDetector;
+use orphans::OrphanTracker;
+use platform::Platform;
+use pressure::{Cgroups, PressureFile};
+use profile::StartupProfile;
+use properties::{PropertyStore, set_property};
+use reload::{ReloadReport, ReloadTransaction};
+use restarts::RestartStore;
+use rollout::RolloutFile;
+use runset::{restore_run_set, write_run_set};
 use service::{
-    Service, ServiceConfigData, ServiceIdGen, ServicePendingAction, ServiceRegistry, ServiceState,
-    ServiceStopReason, apply_control_op, force_kill_service_process, handle_sigchld,
-    reload_services, start_service, stop_service,
+    EXEC_ID_TAG, Service, ServiceConfigData, ServiceIdGen, ServicePendingAction, ServiceRegistry,
+    ServiceState, ServiceStopReason, SpawnContext, StartQueue, apply_control_op, dispatch_jobs,
+    force_kill_service_process, handle_sigchld, start_finish, start_service, stop_service,
+    write_plan,
 };
 use signalfd::{
     SigSet, SignalfdFlags, SignalfdSiginfo, block_thread_signals, read_signalfd_batch, signalfd,
 };
-use status::{StatusFilePath, write_status_file};
-use timerfd::{create_timerfd_1s_periodic, read_timerfd};
+use spawn::{SpawnError, SpawnStrategy};
+use spawner::Spawner;
+use status::{StatusDir, StatusFile, StatusFilePath, write_status_file};
+use swap::Swaps;
+use timer::TimersFile;
+use timerfd::{ClockStepMonitor, SuspendMonitor, create_timerfd_1s_periodic, read_timerfd};
+use transient::run_transient;
+use utils::retry_eintr;
+use utils::timestamp;
+use watchdog::Watchdog;
+use webhooks::Webhooks;
 
 const ID_SFD: u64 = 1;
 const ID_TFD: u64 = 2;
 const ID_PFD: u64 = 3;
+const ID_RSD: u64 = 4;
+const ID_NSD: u64 = 5;
+const ID_CFD: u64 = 6;
+#[cfg(feature = "dbus")]
+const ID_DBUS: u64 = 7;
+#[cfg(feature = "api")]
+const ID_API: u64 = 8;
+const ID_IFD: u64 = 9;
+/// Timer ticks between sweeps for service processes that vanished
+/// without being reaped
+const SWEEP_INTERVAL_TICKS: u64 = 30;
 const SIGINFO_BUF_LEN: usize = 16;
 const EVENTS_BUF_LEN: usize = 16;
-const CONTROL_PIPE_NAME: &str = "control";
-const STATUS_FILE_NAME: &str = "status";
+const ZERO_TIMEOUT: rustix::time::Timespec = rustix::time::Timespec {
+    tv_sec: 0,
+    tv_nsec: 0,
+};
+const STATUS_NOTIFY_SOCKET_NAME: &str = "status.sock";
+const ORPHANS_FILE_NAME: &str = "orphans";
+const PRESSURE_FILE_NAME: &str = "pressure";
+const NET_STATS_FILE_NAME: &str = "net";
+#[cfg(feature = "api")]
+const API_SOCKET_NAME: &str = "api.sock";
+const TIMERS_FILE_NAME: &str = "timers";
+const JOBS_FILE_NAME: &str = "jobs";
+const ROLLOUT_FILE_NAME: &str = "rollout";
+const READY_SOCKET_NAME: &str = "notify.sock";
 
 /// The status of the supervisor. When a shutdown is requested
 /// the supervisor may not stop immediately since it has to
@@ -55,20 +174,256 @@ pub enum SupervisorState {
     ShutdownRequested,
 }
 
-fn flush_status_file(registry: &ServiceRegistry, buf: &mut String, path: &StatusFilePath) {
-    buf.clear();
-    match registry.format_status(buf) {
-        Ok(()) => {
-            if let Err(e) = write_status_file(path, buf) {
-                svlogg!(LogLevel::Error, "failed to write status file: {}", e);
+/// Where the runtime state is published: either a single status file or
+/// one status file per service (`--per-service-status`)
+#[derive(Debug)]
+enum StatusOutput {
+    File(StatusFile),
+    Dir(StatusDir),
+}
+
+/// Publishes the runtime state to the run directory: status file(s),
+/// binary snapshot and change notifications
+#[derive(Debug)]
+struct StatusPublisher {
+    output: StatusOutput,
+    snapshot_path: StatusFilePath,
+    notifier: Option<StatusNotifier>,
+    /// `org.svlopp.Manager` on the bus (`--dbus`)
+    #[cfg(feature = "dbus")]
+    dbus: Option<dbus::DbusManager>,
+    /// HTTP management API (`--api`)
+    #[cfg(feature = "api")]
+    api: Option<api::ApiServer>,
+    /// Restart history persisted in the state directory (`--state-dir`)
+    restarts: Option<RestartStore>,
+    webhooks: Webhooks,
+    alerts: Alerts,
+    snapshot_buf: Vec<u8>,
+    /// Whether the runtime state may have changed since the last flush
+    pending: bool,
+    /// Minimum time between flushes, the `status_interval_ms` supervisor
+    /// option
+    interval: Duration,
+    last_flush: Option<Instant>,
+}
+
+impl StatusPublisher {
+    fn new(args: &cli::CliArgs) -> std::io::Result<Self> {
+        let output = if args.per_service_status {
+            StatusOutput::Dir(StatusDir::create(args.run_dir.join(STATUS_DIR_NAME))?)
+        } else {
+            StatusOutput::File(StatusFile::new(StatusFilePath::new(
+                args.run_dir.join(STATUS_FILE_NAME),
+            )?))
+        };
+        let notifier = if args.status_notify {
+            Some(StatusNotifier::bind(
+                &args.run_dir.join(STATUS_NOTIFY_SOCKET_NAME),
+            )?)
+        } else {
+            None
+        };
+        Ok(Self {
+            output,
+            snapshot_path: StatusFilePath::new(args.run_dir.join(SNAPSHOT_FILE_NAME))?,
+            notifier,
+            #[cfg(feature = "dbus")]
+            dbus: args
+                .dbus_address
+                .as_deref()
+                .map(dbus::DbusManager::connect)
+                .transpose()?,
+            #[cfg(feature = "api")]
+            api: if args.api {
+                Some(bind_api(args)?)
+            } else {
+                None
+            },
+            restarts: args
+                .state_dir
+                .as_deref()
+                .map(RestartStore::open)
+                .transpose()?,
+            webhooks: Webhooks::default(),
+            alerts: Alerts::default(),
+            snapshot_buf: Vec::new(),
+            pending: false,
+            interval: Duration::ZERO,
+            last_flush: None,
+        })
+    }
+
+    /// Note that the runtime state may have changed, it's published by the
+    /// next `flush_pending`
+    #[inline(always)]
+    fn mark_changed(&mut self) {
+        self.pending = true;
+    }
+
+    /// Time left before pending changes can be published, `None` without
+    /// pending changes
+    fn next_flush(&self) -> Option<Duration> {
+        if !self.pending {
+            return None;
+        }
+        Some(match self.last_flush {
+            Some(last) => self.interval.saturating_sub(last.elapsed()),
+            None => Duration::ZERO,
+        })
+    }
+
+    /// Publish the runtime state if it was marked changed, at most once
+    /// per `interval`. Called once per main loop iteration, so that the
+    /// changes made while handling all of its events are published at once
+    fn flush_pending(&mut self, registry: &ServiceRegistry, arena: &Arena) {
+        if self.next_flush() != Some(Duration::ZERO) {
+            return;
+        }
+        self.pending = false;
+        self.last_flush = Some(Instant::now());
+        self.flush(registry, arena);
+    }
+
+    /// Publish the runtime state, if it changed since the last call
+    fn flush(&mut self, registry: &ServiceRegistry, arena: &Arena) {
+        if !self.flush_status(registry, arena) {
+            return;
+        }
+        self.snapshot_buf.clear();
+        registry.encode_snapshot(&mut self.snapshot_buf);
+        if let Err(e) = write_status_file(&self.snapshot_path, &self.snapshot_buf) {
+            svlogg!(LogLevel::Error, "failed to write state snapshot: {}", e);
+        }
+        if let Some(restarts) = self.restarts.as_mut() {
+            restarts.flush(registry);
+        }
+        if let Some(notifier) = self.notifier.as_mut() {
+            notifier.notify();
+        }
+        self.webhooks.notify(registry);
+        self.alerts.notify(registry);
+        #[cfg(feature = "dbus")]
+        if let Some(dbus) = self.dbus.as_mut() {
+            dbus.notify(registry, arena);
+        }
+        #[cfg(feature = "api")]
+        if let Some(api) = self.api.as_mut() {
+            api.notify(registry, arena);
+        }
+    }
+
+    /// Write the status file(s), returns whether anything changed
+    fn flush_status(&mut self, registry: &ServiceRegistry, arena: &Arena) -> bool {
+        let mut changed = false;
+        match &mut self.output {
+            StatusOutput::File(file) => match registry.format_status(file.buf()) {
+                Ok(()) => match file.flush() {
+                    Ok(written) => changed = written,
+                    Err(e) => svlogg!(LogLevel::Error, "failed to write status file: {}", e),
+                },
+                Err(_) => svlogg!(LogLevel::Error, "failed to format status"),
+            },
+            StatusOutput::Dir(dir) => {
+                for svc in registry.services() {
+                    let Ok(line) = arena.alloc_fmt(|w| svc.format_status_line(w)) else {
+                        svlogg!(LogLevel::Error, "failed to format status");
+                        continue;
+                    };
+                    match dir.update(&svc.name, line) {
+                        Ok(updated) => changed |= updated,
+                        Err(e) => svlogg!(
+                            LogLevel::Error,
+                            "failed to write status file of service '{}': {}",
+                            svc.name,
+                            e
+                        ),
+                    }
+                }
+                match dir.finish_round() {
+                    Ok(removed) => changed |= removed,
+                    Err(e) => svlogg!(LogLevel::Error, "failed to remove status file: {}", e),
+                }
             }
         }
-        Err(_) => svlogg!(LogLevel::Error, "failed to format status"),
+        changed
     }
 }
 
-fn run(args: &cli::CliArgs) -> std::io::Result<()> {
-    let status_file_path = StatusFilePath::new(args.run_dir.join(STATUS_FILE_NAME));
+/// Set up the HTTP API listeners
+#[cfg(feature = "api")]
+fn bind_api(args: &cli::CliArgs) -> std::io::Result<api::ApiServer> {
+    #[cfg_attr(not(feature = "tls"), allow(unused_mut))]
+    let mut api = api::ApiServer::bind(&args.run_dir.join(API_SOCKET_NAME))?;
+    #[cfg(feature = "tls")]
+    if let Some(remote) = &args.api_remote {
+        let config = tls::server_config(
+            &remote.tls_cert,
+            &remote.tls_key,
+            remote.tls_client_ca.as_deref(),
+        )?;
+        let token = remote
+            .token_file
+            .as_deref()
+            .map(api::read_token)
+            .transpose()?;
+        api.listen_tcp(remote.listen, config, token)?;
+        svlogg!(LogLevel::Info, "API listening on {}", remote.listen);
+    }
+    Ok(api)
+}
+
+/// Warn about the services a previous supervisor instance left running.
+///
+/// The run directory is only left behind when the supervisor didn't exit
+/// cleanly, in which case its last snapshot tells which processes may
+/// still be around
+fn report_stale_snapshot(run_dir: &Path) {
+    let bytes = match std::fs::read(run_dir.join(SNAPSHOT_FILE_NAME)) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+        Err(e) => {
+            svlogg!(LogLevel::Warn, "can't read stale state snapshot: {}", e);
+            return;
+        }
+    };
+    let snapshot = match Snapshot::decode(&bytes) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            svlogg!(LogLevel::Warn, "invalid stale state snapshot: {}", e);
+            return;
+        }
+    };
+    svlogg!(
+        LogLevel::Warn,
+        "found the state snapshot of a previous supervisor (pid {}), which did not exit cleanly",
+        snapshot.pid
+    );
+    for svc in &snapshot.services {
+        if let Some(pid) = svc.state.pid().and_then(Pid::from_raw)
+            && test_kill_process(pid).is_ok()
+        {
+            svlogg!(
+                LogLevel::Warn,
+                "service '{}' of the previous supervisor may still be running with pid {}",
+                svc.name,
+                pid.as_raw_nonzero()
+            );
+        }
+    }
+}
+
+/// What's left to do once the supervisor is done with its services
+#[derive(Debug)]
+struct RunOutcome {
+    /// Exit code of the supervisor
+    code: i32,
+    on_shutdown_complete: Option<Hook>,
+}
+
+fn run(args: &cli::CliArgs, original_sigset: &SigSet) -> std::io::Result<RunOutcome> {
+    let started = Instant::now();
+    let mut status = StatusPublisher::new(args)?;
 
     let mut sv_state = SupervisorState::default();
 
@@ -79,19 +434,59 @@ fn run(args: &cli::CliArgs) -> std::io::Result<()> {
     // non-zero value
     unsafe { set_child_subreaper(Some(Pid::from_raw_unchecked(1)))? };
 
-    let original_sigset = SigSet::current()?;
     let mut sigset = SigSet::empty()?;
     sigset.add(libc::SIGHUP)?;
     sigset.add(libc::SIGCHLD)?;
     sigset.add(libc::SIGTERM)?;
     sigset.add(libc::SIGINT)?;
+    if args.init {
+        for sig in ForwardedSignal::ALL {
+            sigset.add(sig.as_raw())?;
+        }
+    }
     block_thread_signals(&sigset)?;
 
+    // forked while svlopp is still small and has a single thread
+    let spawner = if args.spawner {
+        Some(Spawner::start(original_sigset)?)
+    } else {
+        None
+    };
+
+    // started after blocking signals, so that the thread inherits the mask
+    // and never takes signals meant for the signalfd
+    let mut watchdog = Watchdog::start(args.watchdog_timeout, args.watchdog_abort)?;
+
     let (pfd, _wr_pfd) = create_control_fifo(&args.run_dir.join(CONTROL_PIPE_NAME))?;
+    let ps_dir = args.run_dir.join(PS_DIR_NAME);
+    mkdirat(CWD, &ps_dir, Mode::from_bits_truncate(0o755))?;
+    let reload_file = StatusFilePath::new(args.run_dir.join(RELOAD_FILE_NAME))?;
+    let run_set_file = StatusFilePath::new(args.run_dir.join(RUN_SET_FILE_NAME))?;
+    let plan_dir = args.run_dir.join(PLAN_DIR_NAME);
+    mkdirat(CWD, &plan_dir, Mode::from_bits_truncate(0o755))?;
+    let property_dir = args.run_dir.join(PROPERTY_DIR_NAME);
+    mkdirat(CWD, &property_dir, Mode::from_bits_truncate(0o755))?;
+    let transient_dir = args.run_dir.join(TRANSIENT_DIR_NAME);
+    mkdirat(CWD, &transient_dir, Mode::from_bits_truncate(0o755))?;
+    let adopt_dir = args.run_dir.join(ADOPT_DIR_NAME);
+    mkdirat(CWD, &adopt_dir, Mode::from_bits_truncate(0o755))?;
+    let platform = Platform::get();
+    svlogg!(LogLevel::Debug, "kernel features: {:?}", platform);
+    platform.write(&StatusFilePath::new(args.run_dir.join(PLATFORM_FILE_NAME))?)?;
+    let diagnostics_dir = args.run_dir.join(DIAGNOSTICS_DIR_NAME);
+    mkdirat(CWD, &diagnostics_dir, Mode::from_bits_truncate(0o755))?;
+    let private_tmp_dir = args.run_dir.join(PRIVATE_TMP_DIR_NAME);
+    mkdirat(CWD, &private_tmp_dir, Mode::from_bits_truncate(0o755))?;
 
     let sfd = signalfd(&sigset, SignalfdFlags::CLOEXEC | SignalfdFlags::NONBLOCK)?;
 
     let tfd = create_timerfd_1s_periodic()?;
+    let mut ticks: u64 = 0;
+    let mut env_files = EnvFileWatcher::new()?;
+    let mut clock = ClockStepMonitor::new()?;
+    let mut suspend = SuspendMonitor::new();
+
+    let ready = ReadyListener::bind(&args.run_dir.join(READY_SOCKET_NAME))?;
 
     let epfd = epoll::create(epoll::CreateFlags::CLOEXEC)?;
     epoll::add(
@@ -112,90 +507,226 @@ fn run(args: &cli::CliArgs) -> std::io::Result<()> {
         epoll::EventData::new_u64(ID_PFD),
         epoll::EventFlags::IN,
     )?;
+    epoll::add(
+        &epfd,
+        &env_files,
+        epoll::EventData::new_u64(ID_IFD),
+        epoll::EventFlags::IN,
+    )?;
+    epoll::add(
+        &epfd,
+        &clock,
+        epoll::EventData::new_u64(ID_CFD),
+        epoll::EventFlags::IN,
+    )?;
+    if let Some(notifier) = &status.notifier {
+        epoll::add(
+            &epfd,
+            notifier.listener(),
+            epoll::EventData::new_u64(ID_NSD),
+            epoll::EventFlags::IN,
+        )?;
+    }
+    #[cfg(feature = "dbus")]
+    if let Some(dbus) = &status.dbus {
+        epoll::add(
+            &epfd,
+            dbus.fd(),
+            epoll::EventData::new_u64(ID_DBUS),
+            epoll::EventFlags::IN,
+        )?;
+    }
+    #[cfg(feature = "api")]
+    if let Some(api) = &status.api {
+        for listener in api.listeners() {
+            epoll::add(
+                &epfd,
+                listener,
+                epoll::EventData::new_u64(ID_API),
+                epoll::EventFlags::IN,
+            )?;
+        }
+    }
+    epoll::add(
+        &epfd,
+        ready.socket(),
+        epoll::EventData::new_u64(ID_RSD),
+        epoll::EventFlags::IN,
+    )?;
 
-    let mut siginfo_buf = [SignalfdSiginfo::empty(); SIGINFO_BUF_LEN];
+    let mut siginfo_buf = ReadBuf::<SignalfdSiginfo, SIGINFO_BUF_LEN>::new();
     let mut events_buf = [epoll::Event {
         flags: epoll::EventFlags::empty(),
         data: epoll::EventData::new_u64(0),
     }; EVENTS_BUF_LEN];
-    let mut status_buf = String::new();
+
+    // services only have their own pressure when they run in a cgroup
+    // other than the supervisor one
+    let cgroups = Cgroups::detect();
+    if cgroups.is_none() {
+        svlogg!(
+            LogLevel::Debug,
+            "not in a cgroup v2 hierarchy, pressure and service cgroups are not available"
+        );
+    }
+    let service_cgroups = cgroups.as_ref().map(ServiceCgroups::new);
+
+    let mut spawn_ctx = SpawnContext {
+        sigset: original_sigset,
+        epfd: epfd.as_fd(),
+        strategy: SpawnStrategy::default(),
+        spawner: spawner.as_ref(),
+        private_tmp_dir: &private_tmp_dir,
+        cgroups: service_cgroups.as_ref(),
+    };
 
     let mut service_id_generator = ServiceIdGen::new();
     let mut service_registry = ServiceRegistry::new();
-    let service_configs = ServiceConfigData::from_config_file(&args.config_path)?;
+    let service_configs = ServiceConfigData::load(
+        args.config_path.as_deref(),
+        args.scan_dir.as_deref(),
+        args.strict_config,
+    )?;
+    let mut orphans = OrphanTracker::new(
+        args.run_dir.join(ORPHANS_FILE_NAME),
+        service_configs.orphan_policy,
+        cgroups.as_ref(),
+    )?;
 
+    let mut oom = OomDetector::open();
+    let mut net_stats_file = if args.net_stats {
+        Some(NetStatsFile::new(args.run_dir.join(NET_STATS_FILE_NAME))?)
+    } else {
+        None
+    };
+    let mut pressure_file = if args.pressure {
+        Some(PressureFile::new(args.run_dir.join(PRESSURE_FILE_NAME))?)
+    } else {
+        None
+    };
+
+    service_registry.set_process_limits(service_configs.process_limits());
+    service_registry.set_job_limits(service_configs.job_limits());
+    spawn_ctx.strategy = service_configs.spawn_strategy;
+    status.interval = service_configs.status_interval();
+    let mut startup = StartupProfile::new(args.run_dir.join(STARTUP_FILE_NAME), started)?;
+    let mut start_queue = StartQueue::new(service_configs.start_concurrency);
+    let mut on_all_stopped = service_configs.on_all_stopped;
+    let mut on_shutdown_complete = service_configs.on_shutdown_complete;
+    let mut forward_signals = service_configs.forward_signals;
+    let mut main_service = service_configs.main_service;
+    status.webhooks.configure(service_configs.webhooks)?;
+    status
+        .alerts
+        .configure(service_configs.alerts, original_sigset);
     for (name, cfg) in service_configs.services.into_iter() {
-        service_registry.insert_service(Service::new(
-            service_id_generator
-                .nextval()
-                .ok_or_else(|| std::io::Error::other("service id overflow"))?,
-            name,
-            cfg,
-        )?);
-    }
-
-    service_registry.with_maps_mut(|services_map, pids_map| {
-        for (svc_id, svc) in services_map.iter_mut() {
-            match start_service(svc, &original_sigset) {
-                Ok(()) => {
-                    let pid = svc.pid().expect("running service must have a pid");
-                    svlogg!(
-                        LogLevel::Info,
-                        "started service '{}' with pid {:?}",
-                        svc.name,
-                        pid,
-                    );
-                    pids_map.insert(pid, *svc_id);
-                }
-                Err(e) => {
-                    svlogg!(
-                        LogLevel::Error,
-                        "failed to start service '{}': {}",
-                        svc.name,
-                        e
-                    );
-                }
-            }
+        let svc_id = service_id_generator
+            .nextval()
+            .ok_or_else(|| std::io::Error::other("service id overflow"))?;
+        let mut svc = Service::new(svc_id, name, cfg)?;
+        if svc.config.autostart {
+            start_queue.push(&mut svc);
+            startup.track(svc_id);
+        }
+        service_registry.insert_service(svc);
+    }
+
+    env_files.watch(&service_registry);
+
+    if let Some(restarts) = status.restarts.as_mut() {
+        match restarts.load() {
+            Ok(records) => service_registry.restore_restarts(records),
+            Err(e) => svlogg!(LogLevel::Warn, "failed to load restart history: {}", e),
         }
-    });
+    }
+    let properties = args
+        .state_dir
+        .as_deref()
+        .map(PropertyStore::open)
+        .transpose()?;
+    if let Some(properties) = &properties {
+        match properties.load() {
+            Ok(records) => service_registry.restore_properties(records),
+            Err(e) => svlogg!(LogLevel::Warn, "failed to load service properties: {}", e),
+        }
+    }
+    let mut timers_file = TimersFile::new(
+        &args.run_dir.join(TIMERS_FILE_NAME),
+        args.state_dir.as_deref(),
+    )?;
+    match timers_file.load() {
+        Ok(records) => service_registry.restore_timers(records, timestamp().0),
+        Err(e) => svlogg!(LogLevel::Warn, "failed to load timers: {}", e),
+    }
+    let mut jobs_file = JobsFile::new(args.run_dir.join(JOBS_FILE_NAME))?;
+    let mut rollout_file = RolloutFile::new(args.run_dir.join(ROLLOUT_FILE_NAME))?;
+    let mut swaps = Swaps::new();
 
-    flush_status_file(&service_registry, &mut status_buf, &status_file_path);
+    // transient buffers of the main loop, reset on each iteration
+    let mut arena = Arena::new();
+    status.flush(&service_registry, &arena);
 
     svlogg!(LogLevel::Info, "supervisor started. Ctrl+C to exit");
 
+    let mut shutdown_started = None;
+
     'outer: loop {
-        let n = epoll::wait(&epfd, &mut events_buf, None)?;
+        arena.reset();
+        if !service_registry.jobs().is_empty()
+            && dispatch_jobs(&mut service_registry, &spawn_ctx, &ps_dir)
+        {
+            status.mark_changed();
+        }
+        if !start_queue.is_empty() {
+            start_queue.start_batch(&mut service_registry, &spawn_ctx);
+            status.mark_changed();
+        }
+        status.flush_pending(&service_registry, &arena);
+        // with starts still queued, only poll for events, and wake up in
+        // time to publish changes held back by `status_interval_ms`
+        let timeout = if start_queue.is_ready() {
+            Some(ZERO_TIMEOUT)
+        } else {
+            status.next_flush().map(|left| rustix::time::Timespec {
+                tv_sec: left.as_secs() as _,
+                tv_nsec: left.subsec_nanos() as _,
+            })
+        };
+        let n = retry_eintr(|| epoll::wait(&epfd, &mut events_buf, timeout.as_ref()))?;
+        watchdog.kick();
 
+        let mut reload_requested = false;
         for ev in &events_buf[..n as usize] {
             match ev.data.u64() {
                 ID_SFD => {
                     // TODO: if we want to make sure to drain `sfd`, we could call
-                    // `read_signalfd_batch` in a loop until it returns 0
-                    let siginfo_read = read_signalfd_batch(sfd.as_fd(), &mut siginfo_buf)?;
-                    for info in &siginfo_buf[..siginfo_read] {
+                    // `read_signalfd_batch` in a loop until it returns no records
+                    for info in read_signalfd_batch(sfd.as_fd(), &mut siginfo_buf)? {
                         let signo = info.signal();
-                        if (signo.cast_signed() == libc::SIGHUP)
-                            && (sv_state == SupervisorState::Running)
+                        if signo.cast_signed() == libc::SIGHUP {
+                            reload_requested = true;
+                        }
+                        if args.init
+                            && let Some(sig) = ForwardedSignal::from_raw(signo.cast_signed())
                         {
-                            svlogg!(LogLevel::Debug, "reload requested");
-                            match reload_services(
-                                &mut service_registry,
-                                &args.config_path,
-                                &mut service_id_generator,
-                                &original_sigset,
-                            ) {
-                                Ok(()) => svlogg!(LogLevel::Info, "finished reloading services"),
-                                Err(e) => {
-                                    svlogg!(LogLevel::Error, "failed reloading services: {}", e,)
-                                }
-                            }
+                            forward_signal(&service_registry, &forward_signals, sig);
                         }
                         if signo.cast_signed() == libc::SIGCHLD {
-                            handle_sigchld(&mut service_registry)?;
+                            handle_sigchld(
+                                &mut service_registry,
+                                &mut orphans,
+                                &mut oom,
+                                &mut status.alerts,
+                                &diagnostics_dir,
+                            )?;
+                            orphans.flush();
                             if (sv_state == SupervisorState::ShutdownRequested)
-                                && service_registry.services().all(|svc| svc.is_stopped())
+                                && is_shutdown_complete(
+                                    &service_registry,
+                                    spawner.as_ref(),
+                                    args.init,
+                                )?
                             {
-                                svlogg!(LogLevel::Info, "all services stopped, exiting");
                                 break 'outer;
                             }
                         }
@@ -205,51 +736,151 @@ fn run(args: &cli::CliArgs) -> std::io::Result<()> {
                             if sv_state == SupervisorState::Running {
                                 svlogg!(LogLevel::Info, "shutdown requested");
                                 sv_state = SupervisorState::ShutdownRequested;
-                                for svc in service_registry.services_mut() {
-                                    if let Err(e) = stop_service(svc) {
-                                        svlogg!(
-                                            LogLevel::Error,
-                                            "failed to stop service '{}': {}",
-                                            svc.name,
-                                            e
-                                        );
-                                    }
-                                }
+                                shutdown_started = Some(Instant::now());
+                                begin_shutdown(&mut service_registry, &mut start_queue);
                             }
-                            if service_registry.services().all(|svc| svc.is_stopped()) {
-                                svlogg!(LogLevel::Info, "all services stopped, exiting");
+                            if is_shutdown_complete(&service_registry, spawner.as_ref(), args.init)?
+                            {
                                 break 'outer;
                             }
                         }
                     }
-                    flush_status_file(&service_registry, &mut status_buf, &status_file_path);
+                    status.mark_changed();
                 }
                 ID_TFD => {
                     // `timerfd` read value is currently unused, read just to drain it
                     let _ = read_timerfd(tfd.as_fd())?;
                     let now = Instant::now();
+                    #[cfg(feature = "dbus")]
+                    if let Some(dbus) = status.dbus.as_mut()
+                        && let Err(e) = dbus.send_pending()
+                    {
+                        svlogg!(LogLevel::Warn, "failed to send D-Bus messages: {}", e);
+                    }
+                    #[cfg(feature = "api")]
+                    if let Some(api) = status.api.as_mut() {
+                        api.send_events();
+                    }
+                    if let Some(suspended_ms) = suspend.check() {
+                        svlogg!(
+                            LogLevel::Info,
+                            "host resumed after {}ms of suspension",
+                            suspended_ms
+                        );
+                        service_registry.skip_suspended_timers(timestamp().0);
+                    }
+                    // Write out coalesced log records that stopped growing
+                    for svc in service_registry.services_mut() {
+                        if let Some(pump) = svc.log_pump.as_mut()
+                            && let Err(e) = pump.flush_idle()
+                        {
+                            svlogg!(
+                                LogLevel::Warn,
+                                "failed to flush logs of service '{}': {}",
+                                svc.name,
+                                e
+                            );
+                        }
+                    }
+                    for pump in service_registry.detached_pumps_mut() {
+                        if let Err(e) = pump.flush_idle() {
+                            svlogg!(
+                                LogLevel::Warn,
+                                "failed to flush logs of detached process: {}",
+                                e
+                            );
+                        }
+                    }
+                    ticks = ticks.wrapping_add(1);
+                    if ticks.is_multiple_of(SWEEP_INTERVAL_TICKS) {
+                        // safety net against missed `SIGCHLD`s: reap what
+                        // wasn't, then look for processes gone for good
+                        handle_sigchld(
+                            &mut service_registry,
+                            &mut orphans,
+                            &mut oom,
+                            &mut status.alerts,
+                            &diagnostics_dir,
+                        )?;
+                        orphans.flush();
+                        service_registry.sweep_lost(&diagnostics_dir);
+                    }
+                    service_registry.reap_adopted();
+                    service_registry.refresh_descendants();
+                    service_registry.stop_overdue(now);
+                    if let Some(cgroups) = cgroups.as_ref() {
+                        service_registry.sample_pressure(cgroups, pressure_file.is_some());
+                    }
+                    service_registry.check_resources(now);
+                    service_registry.check_fds();
+                    if let Some(pressure_file) = pressure_file.as_mut() {
+                        pressure_file.flush(&service_registry);
+                    }
+                    if let Some(net_stats_file) = net_stats_file.as_mut() {
+                        net_stats_file.flush(&service_registry);
+                    }
+                    let mut main_service_stopped = false;
+                    let mut capacity = service_registry.capacity();
+                    let conflicted = service_registry.conflicted();
                     // Enforce kill deadlines and apply pending actions. Pending actions are applied here
                     // instead of immediately after reaping so that:
                     // - restart attempts are implicitly rate limited by the timer period.
                     // - `handle_sigchld` remains just about state transitions.
                     service_registry.with_maps_mut(|services_map, pids_map| {
                         services_map.retain(|&svc_id, svc| match svc.state {
-                            ServiceState::Stopping(pid, kill_deadline) if now >= kill_deadline => {
-                                if let Err(e) = force_kill_service_process(pid) {
-                                    svlogg!(
+                            ServiceState::Stopping(_, kill_deadline) if now >= kill_deadline => {
+                                match force_kill_service_process(svc) {
+                                    Ok(()) => {
+                                        if let Some(timing) = svc.stop_timing.as_mut() {
+                                            timing.force_killed = true;
+                                        }
+                                    }
+                                    Err(e) => svlogg!(
                                         LogLevel::Error,
                                         "failed to kill service '{}': {}",
                                         svc.name,
                                         e
-                                    );
+                                    ),
                                 }
                                 true
                             }
+                            ServiceState::Stopped(_) if svc.finish_pid.is_some() => true,
                             ServiceState::Stopped(stop_reason) => {
+                                if let Some(exit_reason) = svc.pending_finish.take() {
+                                    match start_finish(svc, exit_reason, &spawn_ctx) {
+                                        Ok(Some(pid)) => {
+                                            pids_map.insert(pid, svc_id);
+                                            return true;
+                                        }
+                                        Ok(None) => {}
+                                        Err(e) => svlogg!(
+                                            LogLevel::Error,
+                                            "failed to run finish command of service '{}': {}",
+                                            svc.name,
+                                            e
+                                        ),
+                                    }
+                                }
                                 let pending = match svc.take_pending_action() {
                                     ServicePendingAction::None => match stop_reason {
                                         ServiceStopReason::NeverStarted
-                                        | ServiceStopReason::SupervisorTerminated(_) => {
+                                        | ServiceStopReason::SupervisorTerminated(_)
+                                        | ServiceStopReason::BoundStopped(_)
+                                        | ServiceStopReason::Detached(_) => {
+                                            ServicePendingAction::None
+                                        }
+                                        // restarting won't make the command appear
+                                        ServiceStopReason::SpawnFailed(e)
+                                            if e.is_not_found()
+                                                && svc.fallback_pending_action()
+                                                == ServicePendingAction::Restart =>
+                                        {
+                                            svlogg!(
+                                                LogLevel::Warn,
+                                                "not restarting service '{}', command '{}' not found",
+                                                svc.name,
+                                                svc.config.command
+                                            );
                                             ServicePendingAction::None
                                         }
                                         _ => svc.fallback_pending_action(),
@@ -257,13 +888,49 @@ fn run(args: &cli::CliArgs) -> std::io::Result<()> {
                                     p => p,
                                 };
                                 match pending {
-                                    ServicePendingAction::None => true,
+                                    // a start waiting for a slot leaves it never started
+                                    ServicePendingAction::None
+                                        if svc.transient
+                                            && stop_reason != ServiceStopReason::NeverStarted =>
+                                    {
+                                        svlogg!(
+                                            LogLevel::Info,
+                                            "removed transient service '{}'",
+                                            svc.name
+                                        );
+                                        false
+                                    }
+                                    ServicePendingAction::None => {
+                                        if !matches!(
+                                            stop_reason,
+                                            ServiceStopReason::NeverStarted
+                                                | ServiceStopReason::BoundStopped(_)
+                                                | ServiceStopReason::Detached(_)
+                                        ) && main_service.as_deref() == Some(&*svc.name)
+                                        {
+                                            main_service_stopped = true;
+                                        }
+                                        true
+                                    }
                                     ServicePendingAction::Remove => {
                                         svlogg!(LogLevel::Info, "removed service '{}'", svc.name);
                                         false
                                     }
+                                    ServicePendingAction::Restart
+                                        if conflicted.contains(&svc_id) =>
+                                    {
+                                        svlogg!(
+                                            LogLevel::Warn,
+                                            "not restarting service '{}', conflicting with a running service",
+                                            svc.name
+                                        );
+                                        true
+                                    }
                                     ServicePendingAction::Restart => {
-                                        match start_service(svc, &original_sigset) {
+                                        match capacity
+                                            .reserve()
+                                            .and_then(|()| start_service(svc, &spawn_ctx))
+                                        {
                                             Ok(()) => {
                                                 let svc_pid = svc
                                                     .pid()
@@ -291,34 +958,451 @@ fn run(args: &cli::CliArgs) -> std::io::Result<()> {
                             _ => true,
                         })
                     });
-                    flush_status_file(&service_registry, &mut status_buf, &status_file_path);
+                    if sv_state == SupervisorState::Running {
+                        service_registry.queue_bound_starts(&mut start_queue);
+                        service_registry.queue_conflict_starts(&mut start_queue);
+                        service_registry.fire_timers(timestamp().0, &mut start_queue);
+                        rollout_file.step(&mut service_registry, &spawn_ctx, &ps_dir);
+                        swaps.step(&mut service_registry);
+                    }
+                    timers_file.flush(&service_registry);
+                    jobs_file.flush(&service_registry, start_queue.len());
+                    rollout_file.flush();
+                    status.mark_changed();
+                    if main_service_stopped && sv_state == SupervisorState::Running {
+                        svlogg!(LogLevel::Info, "main service stopped, shutting down");
+                        sv_state = SupervisorState::ShutdownRequested;
+                        shutdown_started = Some(now);
+                        begin_shutdown(&mut service_registry, &mut start_queue);
+                        status.mark_changed();
+                        if is_shutdown_complete(&service_registry, spawner.as_ref(), args.init)? {
+                            break 'outer;
+                        }
+                    }
                 }
-                ID_PFD => match read_control_command(pfd.as_fd()) {
-                    Ok(Some(cmd)) => {
-                        if let Err(e) = apply_control_op(
-                            &mut service_registry,
-                            cmd.service_id,
-                            cmd.op,
-                            &original_sigset,
-                        ) {
-                            svlogg!(LogLevel::Error, "failed to {} service: {}", cmd.op, e);
+                ID_IFD => {
+                    let dirs = env_files.read_changes()?;
+                    if !dirs.is_empty() {
+                        service_registry.env_files_changed(&dirs);
+                        status.mark_changed();
+                    }
+                }
+                ID_CFD => {
+                    if let Some(step_ms) = clock.check()? {
+                        svlogg!(LogLevel::Info, "system clock stepped by {}ms", step_ms);
+                        service_registry.shift_failures(step_ms);
+                        service_registry.reschedule_timers(timestamp().0);
+                        timers_file.flush(&service_registry);
+                        if let Some(restarts) = status.restarts.as_mut() {
+                            restarts.flush(&service_registry);
                         }
-                        flush_status_file(&service_registry, &mut status_buf, &status_file_path);
                     }
-                    Ok(None) => {}
-                    Err(ControlError::InvalidCommand(e)) => {
-                        svlogg!(LogLevel::Error, "invalid command: {}", e)
+                }
+                ID_RSD => {
+                    for pid in ready.read_ready()? {
+                        service_registry.notify_ready(pid);
                     }
-                    Err(ControlError::Io(e)) => return Err(e),
-                },
+                }
+                ID_PFD => {
+                    for command in read_control_commands(pfd.as_fd(), &arena)? {
+                        match command {
+                            Ok(cmd) if cmd.op == ControlOp::Graph => {
+                                if let Err(e) =
+                                    write_graph(&service_registry, cmd.service_id, &args.run_dir)
+                                {
+                                    svlogg!(
+                                        LogLevel::Error,
+                                        "failed to write service graph: {}",
+                                        e
+                                    );
+                                }
+                            }
+                            Ok(cmd) if cmd.op == ControlOp::Snapshot => {
+                                match write_run_set(&service_registry, &run_set_file) {
+                                    Ok(n) => {
+                                        svlogg!(LogLevel::Info, "saved {} running services", n)
+                                    }
+                                    Err(e) => {
+                                        svlogg!(LogLevel::Error, "failed to save run set: {}", e)
+                                    }
+                                }
+                            }
+                            Ok(cmd) if cmd.op == ControlOp::Reload => reload_requested = true,
+                            Ok(cmd) if cmd.op == ControlOp::Restore => {
+                                if let Err(e) = restore_run_set(
+                                    &mut service_registry,
+                                    cmd.service_id,
+                                    run_set_file.path(),
+                                    &spawn_ctx,
+                                    &ps_dir,
+                                ) {
+                                    svlogg!(LogLevel::Error, "failed to restore run set: {}", e);
+                                }
+                                status.mark_changed();
+                            }
+                            Ok(cmd) if cmd.op == ControlOp::SetProperty => {
+                                if let Err(e) = set_property(
+                                    &mut service_registry,
+                                    cmd.service_id,
+                                    &property_dir,
+                                    properties.as_ref(),
+                                ) {
+                                    svlogg!(LogLevel::Error, "failed to set property: {}", e);
+                                }
+                            }
+                            Ok(cmd) if cmd.op == ControlOp::Adopt => {
+                                if let Err(e) = adopt(
+                                    &mut service_registry,
+                                    &mut service_id_generator,
+                                    cmd.service_id,
+                                    &adopt_dir,
+                                ) {
+                                    svlogg!(
+                                        LogLevel::Error,
+                                        "failed to adopt process {}: {}",
+                                        cmd.service_id,
+                                        e
+                                    );
+                                }
+                                status.mark_changed();
+                            }
+                            Ok(cmd) if cmd.op == ControlOp::Detach => {
+                                if let Err(e) = service_registry.detach(
+                                    cmd.service_id,
+                                    &mut service_id_generator,
+                                    epfd.as_fd(),
+                                ) {
+                                    svlogg!(LogLevel::Error, "failed to detach service: {}", e);
+                                }
+                                status.mark_changed();
+                            }
+                            Ok(cmd) if cmd.op == ControlOp::Run => {
+                                if let Err(e) = run_transient(
+                                    &mut service_registry,
+                                    &mut service_id_generator,
+                                    cmd.service_id,
+                                    &transient_dir,
+                                    &spawn_ctx,
+                                    &ps_dir,
+                                ) {
+                                    svlogg!(
+                                        LogLevel::Error,
+                                        "failed to run transient service: {}",
+                                        e
+                                    );
+                                }
+                                status.mark_changed();
+                            }
+                            Ok(cmd) if cmd.op == ControlOp::Swap => {
+                                if let Err(e) = swaps.begin(
+                                    &mut service_registry,
+                                    &mut service_id_generator,
+                                    cmd.service_id,
+                                    &spawn_ctx,
+                                    &ps_dir,
+                                ) {
+                                    svlogg!(LogLevel::Error, "failed to swap service: {}", e);
+                                }
+                                status.mark_changed();
+                            }
+                            Ok(cmd) if cmd.op == ControlOp::RollingRestart => {
+                                if let Err(e) =
+                                    rollout_file.begin(&service_registry, cmd.service_id)
+                                {
+                                    svlogg!(
+                                        LogLevel::Error,
+                                        "failed to begin rolling restart: {}",
+                                        e
+                                    );
+                                }
+                            }
+                            Ok(cmd) if cmd.op == ControlOp::RestartOutdated => {
+                                for svc_id in service_registry.outdated() {
+                                    if let Err(e) = apply_control_op(
+                                        &mut service_registry,
+                                        svc_id,
+                                        ControlOp::Restart,
+                                        &spawn_ctx,
+                                        &ps_dir,
+                                    ) {
+                                        svlogg!(
+                                            LogLevel::Error,
+                                            "failed to restart service: {}",
+                                            e
+                                        );
+                                    }
+                                }
+                                status.mark_changed();
+                            }
+                            Ok(cmd) if cmd.dry_run => {
+                                if let Err(e) =
+                                    write_plan(&service_registry, cmd.service_id, cmd.op, &plan_dir)
+                                {
+                                    svlogg!(LogLevel::Error, "failed to plan {}: {}", cmd.op, e);
+                                }
+                            }
+                            Ok(cmd) => {
+                                if let Err(e) = apply_control_op(
+                                    &mut service_registry,
+                                    cmd.service_id,
+                                    cmd.op,
+                                    &spawn_ctx,
+                                    &ps_dir,
+                                ) {
+                                    svlogg!(LogLevel::Error, "failed to {} service: {}", cmd.op, e);
+                                }
+                                status.mark_changed();
+                            }
+                            Err(e) => {
+                                svlogg!(LogLevel::Error, "invalid command: {}", e)
+                            }
+                        }
+                    }
+                }
+                #[cfg(feature = "dbus")]
+                ID_DBUS => {
+                    let Some(dbus) = status.dbus.as_mut() else {
+                        continue;
+                    };
+                    match dbus.handle(&mut service_registry, &spawn_ctx, &ps_dir) {
+                        Ok(true) => status.mark_changed(),
+                        Ok(false) => {}
+                        Err(e) => {
+                            svlogg!(LogLevel::Error, "D-Bus connection failed: {}", e);
+                            status.dbus = None;
+                        }
+                    }
+                }
+                #[cfg(feature = "api")]
+                ID_API => {
+                    if let Some(api) = status.api.as_mut()
+                        && let Err(e) = api.accept_pending(epfd.as_fd())
+                    {
+                        svlogg!(LogLevel::Warn, "failed to accept API client: {}", e);
+                    }
+                }
+                ID_NSD => {
+                    if let Some(notifier) = status.notifier.as_mut()
+                        && let Err(e) = notifier.accept_pending()
+                    {
+                        svlogg!(
+                            LogLevel::Warn,
+                            "failed to accept status notification client: {}",
+                            e
+                        );
+                    }
+                }
+                id if id & EPOLL_ID_TAG != 0 => {
+                    let (svc_id, stream) = LogStream::from_epoll_id(id);
+                    if let Some(svc) = service_registry.service_mut(svc_id)
+                        && let Some(pump) = svc.log_pump.as_mut()
+                    {
+                        if let Err(e) = pump.pump(stream) {
+                            svlogg!(
+                                LogLevel::Warn,
+                                "failed to pump {} of service '{}': {}",
+                                stream,
+                                svc.name,
+                                e
+                            );
+                        }
+                        if pump.is_finished() {
+                            svc.log_pump = None;
+                        }
+                    } else if let Some(pump) = service_registry.detached_pump_mut(svc_id) {
+                        if let Err(e) = pump.pump(stream) {
+                            svlogg!(
+                                LogLevel::Warn,
+                                "failed to pump {} of detached process: {}",
+                                stream,
+                                e
+                            );
+                        }
+                        if pump.is_finished() {
+                            service_registry.remove_detached_pump(svc_id);
+                        }
+                    }
+                }
+                id if id & EXEC_ID_TAG != 0 => {
+                    // the write end is closed on exec, the step that failed
+                    // is written to it otherwise
+                    if let Some(svc) = service_registry.service_mut(id & !EXEC_ID_TAG)
+                        && let Some(exec_pipe) = svc.exec_pipe.take()
+                    {
+                        match SpawnError::read(exec_pipe.as_fd()) {
+                            Some(e) => svc.spawn_error = Some(e),
+                            None => startup.exec(svc, Instant::now()),
+                        }
+                    }
+                }
+                #[cfg(feature = "api")]
+                id if id & api::API_ID_TAG != 0 => {
+                    let Some(api) = status.api.as_mut() else {
+                        continue;
+                    };
+                    if api.handle(id, &mut service_registry, &spawn_ctx, &ps_dir, &arena) {
+                        status.mark_changed();
+                    }
+                }
                 other => {
                     svlogg!(LogLevel::Warn, "unknown epoll event id={}", other)
                 }
             }
         }
+        // once the whole batch is handled, so that a reload requested by
+        // both `SIGHUP` and the control fifo runs once
+        if reload_requested && sv_state == SupervisorState::Running {
+            svlogg!(LogLevel::Debug, "reload requested");
+            let report = ServiceConfigData::load(
+                args.config_path.as_deref(),
+                args.scan_dir.as_deref(),
+                args.strict_config,
+            )
+            .and_then(|mut configs| {
+                let report = ReloadTransaction::plan(
+                    &service_registry,
+                    std::mem::take(&mut configs.services),
+                    configs.reload_policy,
+                    &mut service_id_generator,
+                )?
+                .apply(&mut service_registry, &mut start_queue);
+                // the previous configuration stays in effect
+                if report.rolled_back {
+                    return Ok(report);
+                }
+                orphans.set_policy(configs.orphan_policy);
+                start_queue.set_concurrency(configs.start_concurrency);
+                spawn_ctx.strategy = configs.spawn_strategy;
+                status.interval = configs.status_interval();
+                on_all_stopped = configs.on_all_stopped.take();
+                on_shutdown_complete = configs.on_shutdown_complete.take();
+                forward_signals = std::mem::take(&mut configs.forward_signals);
+                main_service = configs.main_service.take();
+                service_registry.set_process_limits(configs.process_limits());
+                service_registry.set_job_limits(configs.job_limits());
+                status
+                    .webhooks
+                    .configure(std::mem::take(&mut configs.webhooks))?;
+                status
+                    .alerts
+                    .configure(std::mem::take(&mut configs.alerts), original_sigset);
+                env_files.watch(&service_registry);
+                Ok(report)
+            })
+            .unwrap_or_else(ReloadReport::failed);
+            match report.error() {
+                None => svlogg!(LogLevel::Info, "finished reloading services"),
+                Some(e) => {
+                    svlogg!(LogLevel::Error, "failed reloading services: {}", e,)
+                }
+            }
+            if let Err(e) = report.write(&reload_file) {
+                svlogg!(LogLevel::Warn, "failed to write reload report: {}", e);
+            }
+        }
+    }
+
+    // changes made by the last iteration, e.g. the services that stopped
+    status.flush(&service_registry, &arena);
+
+    if let Some(since) = shutdown_started {
+        report_shutdown(&service_registry, since, args.shutdown_report.as_deref());
+    }
+
+    if let Some(hook) = &on_all_stopped {
+        run_hook("on_all_stopped", hook, original_sigset);
+    }
+
+    let code = match main_service.as_deref() {
+        Some(name) => main_service_exit_code(&service_registry, name),
+        _ => 0,
+    };
+
+    Ok(RunOutcome {
+        code,
+        on_shutdown_complete,
+    })
+}
+
+/// Drop queued starts and jobs, and stop all services
+fn begin_shutdown(registry: &mut ServiceRegistry, start_queue: &mut StartQueue) {
+    start_queue.clear();
+    registry.clear_jobs();
+    for svc in registry.services_mut() {
+        if let Err(e) = stop_service(svc) {
+            svlogg!(
+                LogLevel::Error,
+                "failed to stop service '{}': {}",
+                svc.name,
+                e
+            );
+        }
+    }
+}
+
+/// Whether the supervisor can exit once shutdown has been requested: all
+/// services have stopped and, in init mode, no orphan is left running
+fn is_shutdown_complete(
+    registry: &ServiceRegistry,
+    spawner: Option<&Spawner>,
+    init: bool,
+) -> std::io::Result<bool> {
+    if !registry.services().all(|svc| svc.is_stopped()) {
+        return Ok(false);
     }
+    // no service is started anymore, and the helper would count as an
+    // orphan
+    if let Some(spawner) = spawner {
+        spawner.stop();
+    }
+    if init && has_children()? {
+        svlogg!(LogLevel::Debug, "all services stopped, waiting for orphans");
+        return Ok(false);
+    }
+    svlogg!(LogLevel::Info, "all services stopped, exiting");
+    Ok(true)
+}
+
+/// Exit code of the stopped service `name`
+fn main_service_exit_code(registry: &ServiceRegistry, name: &str) -> i32 {
+    match registry.get_by_name(name) {
+        Some(svc) => match svc.state {
+            ServiceState::Stopped(reason) => reason.exit_code(),
+            _ => 1,
+        },
+        None => {
+            svlogg!(LogLevel::Warn, "main service '{}' not found", name);
+            1
+        }
+    }
+}
 
-    Ok(())
+/// Log how services stopped during shutdown and, if requested, write the
+/// same report to `path`, outside the run directory which is about to be
+/// removed
+fn report_shutdown(registry: &ServiceRegistry, since: Instant, path: Option<&Path>) {
+    let mut report = String::new();
+    if registry.format_shutdown_report(since, &mut report).is_err() {
+        svlogg!(LogLevel::Error, "failed to format shutdown report");
+        return;
+    }
+    svlogg!(
+        LogLevel::Info,
+        "shutdown report (name, stopped at ms, stop duration ms, graceful/killed, reason):"
+    );
+    for line in report.lines() {
+        svlogg!(LogLevel::Info, "  {}", line);
+    }
+    if let Some(path) = path
+        && let Err(e) = std::fs::write(path, &report)
+    {
+        svlogg!(
+            LogLevel::Error,
+            "failed to write shutdown report to '{}': {}",
+            path.display(),
+            e
+        );
+    }
 }
 
 fn main() {
@@ -326,6 +1410,9 @@ fn main() {
 
     set_log_level(args.log_level);
 
+    report_stale_snapshot(&args.run_dir);
+
+    mounts::unmount_private_tmps(&args.run_dir.join(PRIVATE_TMP_DIR_NAME));
     if let Err(e) = std::fs::remove_dir_all(&args.run_dir)
         && e.kind() != std::io::ErrorKind::NotFound
     {
@@ -354,14 +1441,26 @@ fn main() {
         }
     }
 
-    let code = match run(&args) {
-        Ok(()) => 0,
+    let original_sigset = match SigSet::current() {
+        Ok(sigset) => sigset,
+        Err(e) => {
+            svlogg!(LogLevel::Error, "can't read signal mask: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let outcome = match run(&args, &original_sigset) {
+        Ok(outcome) => outcome,
         Err(e) => {
             svlogg!(LogLevel::Error, "{}", e);
-            1
+            RunOutcome {
+                code: 1,
+                on_shutdown_complete: None,
+            }
         }
     };
 
+    mounts::unmount_private_tmps(&args.run_dir.join(PRIVATE_TMP_DIR_NAME));
     if let Err(e) = std::fs::remove_dir_all(&args.run_dir) {
         svlogg!(
             LogLevel::Warn,
@@ -371,5 +1470,9 @@ fn main() {
         );
     }
 
-    std::process::exit(code);
+    if let Some(hook) = &outcome.on_shutdown_complete {
+        run_hook("on_shutdown_complete", hook, &original_sigset);
+    }
+
+    std::process::exit(outcome.code);
 }
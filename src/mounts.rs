@@ -0,0 +1,203 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Mount namespaces of service processes.
+//!
+//! A service with `mounts` or `private_tmp` gets a private mount
+//! namespace, its bind mounts set up in the child before it switches root
+//! and user. The functions the child calls don't allocate for paths
+//! shorter than 256 bytes, rustix converting them on the stack.
+//!
+//! The private `/tmp` is a directory of the run directory bind mounted at
+//! `/tmp`, a size limited tmpfs when svlopp runs as root. The supervisor
+//! creates it on each start and removes it once the service is reaped,
+//! or leaves it to the process when it's detached.
+
+use std::{
+    ffi::CString,
+    io,
+    mem::ManuallyDrop,
+    num::NonZeroU64,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use rustix::fs::{StatVfsMountFlags, statvfs};
+use rustix::mount::{
+    MountFlags, MountPropagationFlags, UnmountFlags, mount, mount_bind_recursive, mount_change,
+    mount_move, mount_remount, unmount,
+};
+use rustix::process::{Pid, chdir, geteuid, pivot_root};
+use rustix::thread::{UnshareFlags, unshare_unsafe};
+use serde::Deserialize;
+
+/// A directory of the host the service process sees at `dst`
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct BindMount {
+    pub(crate) src: PathBuf,
+    pub(crate) dst: PathBuf,
+    /// Whether the mount is read-only
+    #[serde(default)]
+    pub(crate) ro: bool,
+}
+
+impl BindMount {
+    pub(crate) fn validate(&self) -> io::Result<()> {
+        for path in [&self.src, &self.dst] {
+            if !path.is_absolute() {
+                return Err(io::Error::other(format!(
+                    "mount path '{}' is not an absolute path",
+                    path.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Where `dst` is before the process switches to `root_dir`
+    pub(crate) fn target(&self, root_dir: Option<&Path>) -> PathBuf {
+        match root_dir {
+            Some(root) => root.join(self.dst.strip_prefix("/").unwrap_or(&self.dst)),
+            None => self.dst.clone(),
+        }
+    }
+}
+
+/// A `BindMount` as the child sets it up
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MountSpec<'a> {
+    pub(crate) src: &'a Path,
+    /// `dst`, within the root of the host
+    pub(crate) target: &'a Path,
+    pub(crate) ro: bool,
+}
+
+/// Move the process to a new mount namespace, whose mounts don't
+/// propagate back to the host
+pub(crate) fn enter_mount_namespace() -> rustix::io::Result<()> {
+    // SAFETY: the fd table isn't unshared
+    unsafe { unshare_unsafe(UnshareFlags::NEWNS) }?;
+    mount_change(
+        "/",
+        MountPropagationFlags::PRIVATE | MountPropagationFlags::REC,
+    )
+}
+
+/// Bind `spec.src` at `spec.target`, then remount it read-only if asked
+/// to. The flags the kernel locks on mounts inherited from a more
+/// privileged namespace are kept on the remount, or it's refused
+pub(crate) fn bind(spec: &MountSpec) -> rustix::io::Result<()> {
+    mount_bind_recursive(spec.src, spec.target)?;
+    if !spec.ro {
+        return Ok(());
+    }
+    let kept = statvfs(spec.target)?.f_flag;
+    let mut flags = MountFlags::BIND | MountFlags::RDONLY;
+    for (kept_flag, flag) in [
+        (StatVfsMountFlags::NOSUID, MountFlags::NOSUID),
+        (StatVfsMountFlags::NODEV, MountFlags::NODEV),
+        (StatVfsMountFlags::NOEXEC, MountFlags::NOEXEC),
+        (StatVfsMountFlags::NOATIME, MountFlags::NOATIME),
+        (StatVfsMountFlags::NODIRATIME, MountFlags::NODIRATIME),
+        (StatVfsMountFlags::RELATIME, MountFlags::RELATIME),
+    ] {
+        if kept.contains(kept_flag) {
+            flags |= flag;
+        }
+    }
+    mount_remount(spec.target, flags, "")
+}
+
+/// Make `root` the root of the mount namespace, unmounting the old one
+/// from under it, so that it can't be escaped as a `chroot` can
+pub(crate) fn switch_root(root: &Path) -> rustix::io::Result<()> {
+    // `pivot_root` needs the new root to be a mount point
+    mount_bind_recursive(root, root)?;
+    chdir(root)?;
+    // the old root is stacked on the new one, then detached
+    pivot_root(".", ".")?;
+    unmount(".", UnmountFlags::DETACH)?;
+    chdir("/")
+}
+
+/// The directory backing the private `/tmp` of a service, unmounted and
+/// removed on drop
+#[derive(Debug)]
+pub(crate) struct PrivateTmp {
+    path: PathBuf,
+    mounted: bool,
+}
+
+impl PrivateTmp {
+    /// Create the private `/tmp` of service `name` in `dir`, a tmpfs of
+    /// `size_mib` (half of the memory if `None`) when running as root
+    pub(crate) fn create(dir: &Path, name: &str, size_mib: Option<NonZeroU64>) -> io::Result<Self> {
+        let path = dir.join(name);
+        std::fs::create_dir(&path)?;
+        let mut tmp = Self {
+            path,
+            mounted: false,
+        };
+        if geteuid().is_root() {
+            let options = match size_mib {
+                Some(size) => format!("mode=1777,size={}m", size),
+                None => "mode=1777".to_string(),
+            };
+            let options = CString::new(options).expect("no nul in tmpfs options");
+            mount(
+                "tmpfs",
+                &tmp.path,
+                "tmpfs",
+                MountFlags::NOSUID | MountFlags::NODEV,
+                options.as_c_str(),
+            )?;
+            tmp.mounted = true;
+        } else {
+            std::fs::set_permissions(&tmp.path, std::fs::Permissions::from_mode(0o1777))?;
+        }
+        Ok(tmp)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Leave the directory to the process `pid`, e.g. a detached one,
+    /// neither unmounting nor removing it: it's moved to `.<name>.<pid>`,
+    /// out of the way of the next start, and stays until svlopp exits
+    pub(crate) fn forget(self, pid: Pid) -> io::Result<()> {
+        let mut tmp = ManuallyDrop::new(self);
+        let path = std::mem::take(&mut tmp.path);
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let dst = path.with_file_name(format!(".{}.{}", name, pid));
+        if tmp.mounted {
+            std::fs::create_dir(&dst)?;
+            mount_move(&path, &dst)?;
+            std::fs::remove_dir(&path)
+        } else {
+            std::fs::rename(&path, &dst)
+        }
+    }
+}
+
+impl Drop for PrivateTmp {
+    fn drop(&mut self) {
+        // processes left in the namespace keep the tmpfs until they exit
+        if self.mounted {
+            let _ = unmount(&self.path, UnmountFlags::DETACH);
+        }
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Unmount the private `/tmp`s left in `dir` by a supervisor that didn't
+/// exit cleanly, so that the run directory can be removed
+pub(crate) fn unmount_private_tmps(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let _ = unmount(entry.path(), UnmountFlags::DETACH);
+    }
+}
@@ -0,0 +1,116 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{fmt::Write, io, os::unix::fs::MetadataExt, path::PathBuf};
+
+use crate::logging::LogLevel;
+use crate::service::ServiceRegistry;
+use crate::status::StatusFile;
+use crate::svlogg;
+
+/// Network counters of a network namespace, summed over its interfaces
+/// but the loopback one
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct NetStats {
+    pub(crate) rx_bytes: u64,
+    pub(crate) rx_packets: u64,
+    pub(crate) tx_bytes: u64,
+    pub(crate) tx_packets: u64,
+}
+
+impl NetStats {
+    /// Read the counters of the network namespace of `pid`
+    pub(crate) fn read(pid: i32) -> io::Result<Self> {
+        let dev = std::fs::read_to_string(format!("/proc/{}/net/dev", pid))?;
+        Self::parse(&dev)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed net/dev"))
+    }
+
+    /// Parse `net/dev`: two header lines, then one line per interface,
+    /// `<iface>: <rx_bytes> <rx_packets> <6 more rx fields> <tx_bytes>
+    /// <tx_packets> ...`
+    fn parse(dev: &str) -> Option<Self> {
+        let mut stats = Self::default();
+        for line in dev.lines().skip(2) {
+            let (iface, counters) = line.split_once(':')?;
+            if iface.trim() == "lo" {
+                continue;
+            }
+            let mut fields = counters.split_whitespace().map(str::parse::<u64>);
+            stats.rx_bytes += fields.next()?.ok()?;
+            stats.rx_packets += fields.next()?.ok()?;
+            stats.tx_bytes += fields.nth(6)?.ok()?;
+            stats.tx_packets += fields.next()?.ok()?;
+        }
+        Some(stats)
+    }
+}
+
+/// Inode of the network namespace of `pid`, which identifies it
+fn netns_inode(pid: &str) -> io::Result<u64> {
+    Ok(std::fs::metadata(format!("/proc/{}/ns/net", pid))?.ino())
+}
+
+/// Publishes the network counters of the services running in their own
+/// network namespace in the net file of the run directory, one per line:
+/// `<name> <netns> <rx_bytes> <rx_packets> <tx_bytes> <tx_packets>`.
+///
+/// Services sharing the supervisor network namespace are skipped, their
+/// counters would be the ones of the whole namespace
+#[derive(Debug)]
+pub(crate) struct NetStatsFile {
+    own_netns: u64,
+    file: StatusFile,
+}
+
+impl NetStatsFile {
+    pub(crate) fn new(path: PathBuf) -> io::Result<Self> {
+        Ok(Self {
+            own_netns: netns_inode("self")?,
+            file: StatusFile::create(path)?,
+        })
+    }
+
+    /// Sample the counters of running services and rewrite the net file
+    /// if any changed
+    pub(crate) fn flush(&mut self, registry: &ServiceRegistry) {
+        let buf = self.file.buf();
+        for svc in registry.services() {
+            let Some(pid) = svc.pid() else {
+                continue;
+            };
+            let pid = pid.as_raw_nonzero().get();
+            // the process may exit while sampling
+            let Ok(netns) = netns_inode(&pid.to_string()) else {
+                continue;
+            };
+            if netns == self.own_netns {
+                continue;
+            }
+            match NetStats::read(pid) {
+                Ok(stats) => {
+                    let _ = writeln!(
+                        buf,
+                        "{} {} {} {} {} {}",
+                        svc.name,
+                        netns,
+                        stats.rx_bytes,
+                        stats.rx_packets,
+                        stats.tx_bytes,
+                        stats.tx_packets
+                    );
+                }
+                Err(e) => svlogg!(
+                    LogLevel::Debug,
+                    "failed to read network counters of service '{}': {}",
+                    svc.name,
+                    e
+                ),
+            }
+        }
+        if let Err(e) = self.file.flush() {
+            svlogg!(LogLevel::Error, "failed to write net file: {}", e);
+        }
+    }
+}
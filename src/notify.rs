@@ -0,0 +1,199 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    io::{self, IoSliceMut},
+    mem::MaybeUninit,
+    os::fd::{AsFd, BorrowedFd, OwnedFd},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use rustix::fs::{Mode, chmod};
+use rustix::net::{
+    AddressFamily, RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags, SendFlags, SocketAddrUnix,
+    SocketFlags, SocketType, accept_with, bind, listen, recvmsg, send, socket_with,
+    sockopt::set_socket_passcred,
+};
+use rustix::process::Pid;
+
+use crate::logging::LogLevel;
+use crate::svlogg;
+use crate::utils::retry_eintr;
+
+/// Maximum number of connected clients, further connections are closed
+/// right away
+const MAX_CLIENTS: usize = 64;
+
+/// Payload of the "status changed" message
+const STATUS_CHANGED_MSG: &[u8] = b"\n";
+
+/// Maximum length of a readiness notification, longer ones are truncated
+const MAX_READY_MSG_LEN: usize = 4096;
+
+/// Line of a readiness notification telling that the sender is ready
+const READY_LINE: &[u8] = b"READY=1";
+
+/// Path of the readiness socket, once bound
+static READY_SOCKET: OnceLock<PathBuf> = OnceLock::new();
+
+/// Status change notifications over a `SOCK_SEQPACKET` unix socket.
+///
+/// Every connected client receives a one byte message each time the
+/// status is rewritten. Messages are never queued behind a slow client:
+/// if its receive queue is full the message is dropped, since the queued
+/// ones already tell it that the status changed. Clients are expected to
+/// drain the socket and then read the status, so that notifications only
+/// act as a wakeup
+#[derive(Debug)]
+pub(crate) struct StatusNotifier {
+    listener: OwnedFd,
+    clients: Vec<OwnedFd>,
+}
+
+impl StatusNotifier {
+    /// Bind and listen on a unix socket at `path`, which only the owner
+    /// can connect to
+    pub(crate) fn bind(path: &Path) -> io::Result<Self> {
+        let listener = socket_with(
+            AddressFamily::UNIX,
+            SocketType::SEQPACKET,
+            SocketFlags::CLOEXEC | SocketFlags::NONBLOCK,
+            None,
+        )?;
+        bind(&listener, &SocketAddrUnix::new(path)?)?;
+        // before `listen`, connections being refused until then whatever
+        // mode the umask left the socket with
+        chmod(path, Mode::from_bits_truncate(0o600))?;
+        listen(&listener, MAX_CLIENTS as i32)?;
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+        })
+    }
+
+    /// The listening socket, to be registered with epoll
+    #[inline(always)]
+    pub(crate) fn listener(&self) -> BorrowedFd<'_> {
+        self.listener.as_fd()
+    }
+
+    /// Accept all the pending connections
+    pub(crate) fn accept_pending(&mut self) -> io::Result<()> {
+        loop {
+            let client = match retry_eintr(|| accept_with(&self.listener, SocketFlags::CLOEXEC)) {
+                Ok(fd) => fd,
+                Err(e) if e == rustix::io::Errno::AGAIN => return Ok(()),
+                Err(e) if e == rustix::io::Errno::CONNABORTED => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if self.clients.len() >= MAX_CLIENTS {
+                svlogg!(
+                    LogLevel::Warn,
+                    "too many status notification clients, dropping connection"
+                );
+                continue;
+            }
+            self.clients.push(client);
+        }
+    }
+
+    /// Notify all clients that the status changed, dropping the ones that
+    /// disconnected
+    pub(crate) fn notify(&mut self) {
+        self.clients.retain(|client| {
+            match retry_eintr(|| {
+                send(
+                    client,
+                    STATUS_CHANGED_MSG,
+                    SendFlags::DONTWAIT | SendFlags::NOSIGNAL,
+                )
+            }) {
+                Ok(_) => true,
+                // a notification is already pending
+                Err(e) if e == rustix::io::Errno::AGAIN => true,
+                Err(_) => false,
+            }
+        });
+    }
+}
+
+/// Path of the readiness socket service processes get in
+/// `SVLOPP_NOTIFY_SOCKET`, `None` until a `ReadyListener` is bound
+pub(crate) fn ready_socket() -> Option<&'static Path> {
+    READY_SOCKET.get().map(PathBuf::as_path)
+}
+
+/// Readiness notifications of service processes over a `SOCK_DGRAM` unix
+/// socket.
+///
+/// As in the `sd_notify(3)` protocol, a service process tells that it's
+/// ready by sending a datagram holding a `READY=1` line; other lines are
+/// ignored. Senders are identified by the credentials the kernel attaches
+/// to each datagram (`SO_PASSCRED`), so only the service process itself,
+/// not its descendants, can notify
+#[derive(Debug)]
+pub(crate) struct ReadyListener {
+    socket: OwnedFd,
+}
+
+impl ReadyListener {
+    /// Bind a unix socket at `path`, the path exported to service
+    /// processes from then on. Anyone can send to it, so that services
+    /// with a `user_group` can too: senders are told apart by their pid
+    pub(crate) fn bind(path: &Path) -> io::Result<Self> {
+        let socket = socket_with(
+            AddressFamily::UNIX,
+            SocketType::DGRAM,
+            SocketFlags::CLOEXEC | SocketFlags::NONBLOCK,
+            None,
+        )?;
+        bind(&socket, &SocketAddrUnix::new(path)?)?;
+        chmod(path, Mode::from_bits_truncate(0o666))?;
+        set_socket_passcred(&socket, true)?;
+        let _ = READY_SOCKET.set(path.to_path_buf());
+        Ok(Self { socket })
+    }
+
+    /// The socket, to be registered with epoll
+    #[inline(always)]
+    pub(crate) fn socket(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+
+    /// Read all the pending notifications, returning the pids of the
+    /// processes that notified readiness
+    pub(crate) fn read_ready(&self) -> io::Result<Vec<Pid>> {
+        let mut ready = Vec::new();
+        let mut buf = [0u8; MAX_READY_MSG_LEN];
+        let mut space = [MaybeUninit::uninit(); rustix::cmsg_space!(ScmCredentials(1))];
+        loop {
+            let mut control = RecvAncillaryBuffer::new(&mut space);
+            let msg = match retry_eintr(|| {
+                recvmsg(
+                    &self.socket,
+                    &mut [IoSliceMut::new(&mut buf)],
+                    &mut control,
+                    RecvFlags::DONTWAIT | RecvFlags::CMSG_CLOEXEC,
+                )
+            }) {
+                Ok(msg) => msg,
+                Err(e) if e == rustix::io::Errno::AGAIN => return Ok(ready),
+                Err(e) => return Err(e.into()),
+            };
+            let sender = control.drain().find_map(|message| match message {
+                RecvAncillaryMessage::ScmCredentials(cred) => Some(cred.pid),
+                _ => None,
+            });
+            let is_ready = buf[..msg.bytes]
+                .split(|&b| b == b'\n')
+                .any(|line| line == READY_LINE);
+            if let Some(pid) = sender
+                && is_ready
+            {
+                ready.push(pid);
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{collections::VecDeque, os::fd::OwnedFd};
+
+use rustix::fs::{Mode, OFlags, SeekFrom, open, seek};
+use rustix::io::{Errno, read};
+
+use crate::logging::LogLevel;
+use crate::svlogg;
+
+/// Number of OOM killed pids remembered until their process is reaped
+const MAX_RECENT_OOM_KILLS: usize = 64;
+
+/// Maximum size of a kernel log record
+const KMSG_RECORD_LEN: usize = 8192;
+
+/// Tells whether a process was killed by the kernel OOM killer.
+///
+/// The OOM killer logs `Killed process <pid> (<comm>)` to the kernel log
+/// right after sending `SIGKILL`, so the records logged since the last
+/// check are read from `/dev/kmsg` when a service process is reaped after
+/// a `SIGKILL` the supervisor didn't send. Reading the kernel log needs
+/// `CAP_SYSLOG` unless `kernel.dmesg_restrict` is 0, without it OOM kills
+/// are reported as plain kills
+#[derive(Debug)]
+pub(crate) struct OomDetector {
+    kmsg: Option<OwnedFd>,
+    /// Pids of the last OOM killed processes
+    recent: VecDeque<i32>,
+    buf: Box<[u8]>,
+}
+
+impl OomDetector {
+    /// Open the kernel log, skipping the records logged so far
+    pub(crate) fn open() -> Self {
+        let kmsg = open(
+            "/dev/kmsg",
+            OFlags::RDONLY | OFlags::NONBLOCK | OFlags::CLOEXEC,
+            Mode::empty(),
+        )
+        .and_then(|fd| seek(&fd, SeekFrom::End(0)).map(|_| fd));
+        let kmsg = match kmsg {
+            Ok(fd) => Some(fd),
+            Err(e) => {
+                svlogg!(
+                    LogLevel::Debug,
+                    "can't read the kernel log, OOM kills won't be detected: {}",
+                    e
+                );
+                None
+            }
+        };
+        Self {
+            kmsg,
+            recent: VecDeque::new(),
+            buf: vec![0; KMSG_RECORD_LEN].into_boxed_slice(),
+        }
+    }
+
+    /// Whether `pid` was killed by the OOM killer
+    pub(crate) fn was_oom_killed(&mut self, pid: i32) -> bool {
+        self.drain();
+        match self.recent.iter().position(|&p| p == pid) {
+            Some(index) => {
+                self.recent.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Read the records logged since the last call, each read returns a
+    /// single record
+    fn drain(&mut self) {
+        let Some(kmsg) = self.kmsg.as_ref() else {
+            return;
+        };
+        loop {
+            let n = match read(kmsg, &mut self.buf[..]) {
+                Ok(n) => n,
+                // records were overwritten before being read
+                Err(Errno::PIPE) | Err(Errno::INTR) => continue,
+                Err(Errno::AGAIN) => return,
+                Err(e) => {
+                    svlogg!(LogLevel::Warn, "failed to read the kernel log: {}", e);
+                    return;
+                }
+            };
+            if let Some(pid) = parse_oom_kill(&self.buf[..n]) {
+                if self.recent.len() == MAX_RECENT_OOM_KILLS {
+                    self.recent.pop_front();
+                }
+                self.recent.push_back(pid);
+            }
+        }
+    }
+}
+
+/// The pid of a record like `3,1234,5678,-;Out of memory: Killed process
+/// 42 (comm) ...`, the message following the first `;`
+fn parse_oom_kill(record: &[u8]) -> Option<i32> {
+    let record = std::str::from_utf8(record).ok()?;
+    let (_, message) = record.split_once(';')?;
+    let (_, rest) = message.split_once("Killed process ")?;
+    let end = rest.find(|c: char| !c.is_ascii_digit())?;
+    rest[..end].parse().ok()
+}
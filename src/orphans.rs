@@ -0,0 +1,269 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    io,
+    os::fd::{AsRawFd, BorrowedFd},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use rustix::process::Pid;
+use serde::Deserialize;
+
+use crate::cgroup::SUPERVISOR_CGROUP;
+use crate::logging::LogLevel;
+use crate::pressure::Cgroups;
+use crate::procfs::read_cgroup;
+use crate::service::{ExitReason, ServiceRegistry};
+use crate::status::{StatusFilePath, write_status_file};
+use crate::svlogg;
+use crate::utils::cvt_r;
+
+/// Number of reaped orphans kept in the orphans file
+const MAX_TRACKED_ORPHANS: usize = 64;
+
+/// What to do about reaped children that don't belong to any service.
+///
+/// As a child subreaper, svlopp inherits the descendants of services
+/// that outlive their parent, and reaps them when they exit
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OrphanPolicy {
+    /// Only log the reaped pid
+    #[default]
+    Log,
+    /// Also find the service the orphan comes from
+    Attribute,
+    /// Attribute, then keep the last reaped orphans in the orphans file
+    Track,
+}
+
+/// Where an orphan comes from, read from procfs while it's still a zombie
+#[derive(Debug, Default)]
+pub(crate) struct OrphanOrigin {
+    /// Process group, services run in their own process group which is
+    /// inherited by their descendants unless they move out of it
+    pgid: Option<Pid>,
+    /// cgroup v2 path
+    cgroup: Option<String>,
+}
+
+impl OrphanOrigin {
+    pub(crate) fn inspect(pid: Pid) -> Self {
+        let proc_dir = format!("/proc/{}", pid.as_raw_nonzero());
+        let pgid = std::fs::read_to_string(format!("{}/stat", proc_dir))
+            .ok()
+            .and_then(|stat| {
+                // `comm` may contain spaces and parentheses, fields after it
+                // are `state ppid pgrp`
+                let (_, fields) = stat.rsplit_once(')')?;
+                fields.split_whitespace().nth(2)?.parse().ok()
+            })
+            .and_then(Pid::from_raw);
+        let cgroup = read_cgroup(pid.as_raw_nonzero().get());
+        Self { pgid, cgroup }
+    }
+}
+
+/// Pid of the next exited child, left unreaped so that procfs can still
+/// be inspected
+pub(crate) fn peek_exited_child() -> io::Result<Option<Pid>> {
+    // SAFETY: all zeroes is a valid `siginfo_t`
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    // SAFETY: `info` is a valid `siginfo_t` to write to
+    match cvt_r(|| unsafe {
+        libc::waitid(
+            libc::P_ALL,
+            0,
+            &mut info,
+            libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+        )
+    }) {
+        Ok(_) => {}
+        Err(rustix::io::Errno::CHILD) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    // SAFETY: `si_pid` is set by `waitid`, and left zero when no child
+    // is ready
+    Ok(Pid::from_raw(unsafe { info.si_pid() }))
+}
+
+/// What `waitid` knows of a child, see `peek_child`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ChildState {
+    Running,
+    /// Exited and not reaped yet
+    Exited,
+    /// Not a child anymore, e.g. reaped without the supervisor noticing
+    Gone,
+}
+
+/// State of the child `pid`, left unreaped. Looked up through `pidfd` if
+/// any, so that a recycled pid isn't taken for the child
+pub(crate) fn peek_child(pid: Pid, pidfd: Option<BorrowedFd<'_>>) -> io::Result<ChildState> {
+    let (idtype, id) = match pidfd {
+        Some(pidfd) => (libc::P_PIDFD, pidfd.as_raw_fd() as libc::id_t),
+        None => (libc::P_PID, pid.as_raw_nonzero().get() as libc::id_t),
+    };
+    // SAFETY: all zeroes is a valid `siginfo_t`
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    // SAFETY: `info` is a valid `siginfo_t` to write to
+    match cvt_r(|| unsafe {
+        libc::waitid(
+            idtype,
+            id,
+            &mut info,
+            libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+        )
+    }) {
+        Ok(_) => {}
+        Err(rustix::io::Errno::CHILD) => return Ok(ChildState::Gone),
+        Err(e) => return Err(e.into()),
+    }
+    // SAFETY: `si_pid` is set by `waitid`, and left zero while the child
+    // is running
+    Ok(match unsafe { info.si_pid() } {
+        0 => ChildState::Running,
+        _ => ChildState::Exited,
+    })
+}
+
+/// Name of the service whose cgroup `cgroup` is, or is under: services
+/// running in a cgroup of their own get `<services_cgroup>/<name>`, which
+/// is left in place while processes of the service are still in it
+fn service_cgroup_name<'a>(services_cgroup: &str, cgroup: &'a str) -> Option<&'a str> {
+    let name = cgroup
+        .strip_prefix(services_cgroup)?
+        .strip_prefix('/')?
+        .split('/')
+        .next()?;
+    (!name.is_empty() && name != SUPERVISOR_CGROUP).then_some(name)
+}
+
+#[derive(Debug)]
+struct Orphan {
+    pid: Pid,
+    service: Option<Arc<str>>,
+    cgroup: Option<String>,
+    exit_reason: ExitReason,
+}
+
+/// Applies the `OrphanPolicy` to reaped orphans.
+///
+/// With `OrphanPolicy::Track`, the last reaped orphans are published in
+/// the orphans file of the run directory, one per line:
+/// `<pid> <service|-> <exit_reason> <cgroup|->`
+#[derive(Debug)]
+pub(crate) struct OrphanTracker {
+    policy: OrphanPolicy,
+    /// The cgroup the cgroups of services are created in, the one of the
+    /// supervisor, `None` without cgroup v2
+    services_cgroup: Option<String>,
+    recent: VecDeque<Orphan>,
+    path: StatusFilePath,
+    dirty: bool,
+    buf: String,
+}
+
+impl OrphanTracker {
+    pub(crate) fn new(
+        path: PathBuf,
+        policy: OrphanPolicy,
+        cgroups: Option<&Cgroups>,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            policy,
+            services_cgroup: cgroups.map(|cgroups| cgroups.own.trim_end_matches('/').to_owned()),
+            recent: VecDeque::new(),
+            path: StatusFilePath::new(path)?,
+            dirty: false,
+            buf: String::new(),
+        })
+    }
+
+    #[inline(always)]
+    pub(crate) fn policy(&self) -> OrphanPolicy {
+        self.policy
+    }
+
+    #[inline(always)]
+    pub(crate) fn set_policy(&mut self, policy: OrphanPolicy) {
+        self.policy = policy;
+    }
+
+    /// Handle an orphan that has just been reaped. `origin` is only
+    /// available when the policy asks for attribution
+    pub(crate) fn reaped(
+        &mut self,
+        pid: Pid,
+        exit_reason: ExitReason,
+        origin: Option<OrphanOrigin>,
+        registry: &ServiceRegistry,
+    ) {
+        let Some(origin) = origin else {
+            svlogg!(
+                LogLevel::Info,
+                "reaped unknown pid {} (likely adopted descendant)",
+                pid
+            );
+            return;
+        };
+        // the cgroup first, as it's kept by processes that leave the
+        // process group or outlive the service process
+        let service = origin
+            .cgroup
+            .as_deref()
+            .zip(self.services_cgroup.as_deref())
+            .and_then(|(cgroup, services_cgroup)| service_cgroup_name(services_cgroup, cgroup))
+            .and_then(|name| registry.get_by_name(name))
+            .or_else(|| origin.pgid.and_then(|pgid| registry.get_by_pid(pgid)))
+            .map(|svc| Arc::clone(&svc.name));
+        svlogg!(
+            LogLevel::Info,
+            "reaped orphan pid {} of service '{}' (cgroup {}): {}",
+            pid,
+            service.as_deref().unwrap_or("?"),
+            origin.cgroup.as_deref().unwrap_or("?"),
+            exit_reason
+        );
+        if self.policy != OrphanPolicy::Track {
+            return;
+        }
+        if self.recent.len() == MAX_TRACKED_ORPHANS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(Orphan {
+            pid,
+            service,
+            cgroup: origin.cgroup,
+            exit_reason,
+        });
+        self.dirty = true;
+    }
+
+    /// Rewrite the orphans file if new orphans were tracked
+    pub(crate) fn flush(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        self.buf.clear();
+        for orphan in &self.recent {
+            let _ = writeln!(
+                self.buf,
+                "{} {} {} {}",
+                orphan.pid.as_raw_nonzero(),
+                orphan.service.as_deref().unwrap_or("-"),
+                orphan.exit_reason,
+                orphan.cgroup.as_deref().unwrap_or("-"),
+            );
+        }
+        if let Err(e) = write_status_file(&self.path, self.buf.as_bytes()) {
+            svlogg!(LogLevel::Error, "failed to write orphans file: {}", e);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Kernel feature detection.
+//!
+//! Features that are missing from older kernels are probed once, at
+//! startup, so that the same binary runs on both old and new kernels by
+//! selecting an implementation accordingly. The outcome is written to the
+//! platform file of the run directory, which `svloppctl version --verbose`
+//! reports.
+
+use std::{io, path::Path, sync::OnceLock};
+
+use rustix::process::{PidfdFlags, getpid, pidfd_open};
+
+use crate::pressure::Cgroups;
+use crate::status::{StatusFilePath, write_status_file};
+use crate::utils::cvt;
+
+/// Kernel features svlopp can make use of
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Platform {
+    /// `pidfd_open` and `pidfd_send_signal` (Linux 5.3), service processes
+    /// are signaled by pid without them
+    pub(crate) pidfd: bool,
+    /// `clone3` (Linux 5.3)
+    pub(crate) clone3: bool,
+    /// `close_range` (Linux 5.9)
+    pub(crate) close_range: bool,
+    /// A mounted cgroup v2 hierarchy, pressure sampling and per-service
+    /// cgroups are disabled without it
+    pub(crate) cgroup_v2: bool,
+    /// `io_uring_setup` (Linux 5.1), unless disabled by
+    /// `kernel.io_uring_disabled`
+    pub(crate) io_uring: bool,
+    /// SELinux, with `selinuxfs` mounted, `selinux_context` being ignored
+    /// without it
+    pub(crate) selinux: bool,
+    /// AppArmor, `apparmor_profile` being ignored without it
+    pub(crate) apparmor: bool,
+}
+
+/// Whether the system call made by `probe` exists. It's expected to fail
+/// with `EINVAL` for the arguments it's made with, `ENOSYS` meaning the
+/// kernel doesn't know it and `EPERM` that it's disabled, e.g. by seccomp
+fn syscall_available(probe: impl FnOnce() -> libc::c_long) -> bool {
+    match cvt(probe()) {
+        Ok(_) => true,
+        Err(e) => e != rustix::io::Errno::NOSYS && e != rustix::io::Errno::PERM,
+    }
+}
+
+impl Platform {
+    /// The features of the running kernel, probed on the first call
+    pub(crate) fn get() -> &'static Self {
+        static PLATFORM: OnceLock<Platform> = OnceLock::new();
+        PLATFORM.get_or_init(Self::probe)
+    }
+
+    fn probe() -> Self {
+        Self {
+            pidfd: pidfd_open(getpid(), PidfdFlags::empty()).is_ok(),
+            // a null `clone_args` of size 0 is rejected before anything
+            // is cloned
+            clone3: syscall_available(|| unsafe {
+                libc::syscall(libc::SYS_clone3, std::ptr::null::<u8>(), 0usize)
+            }),
+            // closes no fd, as none can be that high
+            close_range: syscall_available(|| unsafe {
+                libc::syscall(libc::SYS_close_range, u32::MAX, u32::MAX, 0u32)
+            }),
+            cgroup_v2: Cgroups::detect().is_some(),
+            // 0 entries is rejected before any ring is set up
+            io_uring: syscall_available(|| unsafe {
+                libc::syscall(libc::SYS_io_uring_setup, 0u32, std::ptr::null::<u8>())
+            }),
+            // as libselinux's `is_selinux_enabled` and libapparmor's
+            // `aa_is_enabled` tell
+            selinux: Path::new("/sys/fs/selinux/enforce").exists(),
+            apparmor: std::fs::read_to_string("/sys/module/apparmor/parameters/enabled")
+                .is_ok_and(|enabled| enabled.starts_with('Y')),
+        }
+    }
+
+    fn features(&self) -> [(&'static str, bool); 7] {
+        [
+            ("pidfd", self.pidfd),
+            ("clone3", self.clone3),
+            ("close_range", self.close_range),
+            ("cgroup_v2", self.cgroup_v2),
+            ("io_uring", self.io_uring),
+            ("selinux", self.selinux),
+            ("apparmor", self.apparmor),
+        ]
+    }
+
+    /// Write one `<feature> <yes|no>` line per feature to `path`
+    pub(crate) fn write(&self, path: &StatusFilePath) -> io::Result<()> {
+        let mut content = String::new();
+        for (name, available) in self.features() {
+            content.push_str(name);
+            content.push_str(if available { " yes\n" } else { " no\n" });
+        }
+        write_status_file(path, content.as_bytes())
+    }
+}
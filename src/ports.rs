@@ -0,0 +1,175 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{collections::HashSet, fmt, io};
+
+use rustix::process::Pid;
+use serde::Deserialize;
+
+/// `st` of listening TCP sockets in `/proc/net/tcp`
+const TCP_LISTEN: &str = "0A";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Tcp => "tcp",
+            Self::Udp => "udp",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum PortSpec {
+    Number(u16),
+    Text(String),
+}
+
+/// A port a service listens on, declared in `ports` either as a TCP port
+/// number or as `"<port>/tcp"` or `"<port>/udp"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "PortSpec")]
+pub(crate) struct ListenPort {
+    port: u16,
+    protocol: Protocol,
+}
+
+impl TryFrom<PortSpec> for ListenPort {
+    type Error = String;
+
+    fn try_from(spec: PortSpec) -> Result<Self, Self::Error> {
+        let (port, protocol) = match spec {
+            PortSpec::Number(port) => (port, Protocol::Tcp),
+            PortSpec::Text(text) => {
+                let (port, protocol) = text.split_once('/').unwrap_or((&text, "tcp"));
+                let protocol = match protocol {
+                    "tcp" => Protocol::Tcp,
+                    "udp" => Protocol::Udp,
+                    other => return Err(format!("unknown protocol '{}'", other)),
+                };
+                let port = port
+                    .parse()
+                    .map_err(|_| format!("invalid port '{}'", port))?;
+                (port, protocol)
+            }
+        };
+        if port == 0 {
+            return Err("port 0 can't be declared".to_owned());
+        }
+        Ok(Self { port, protocol })
+    }
+}
+
+impl fmt::Display for ListenPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.port, self.protocol.as_str())
+    }
+}
+
+/// Inodes of the sockets bound to `port` in a `/proc/net/{tcp,udp}{,6}`
+/// table, listening for TCP. Lines are `sl local_address rem_address st
+/// tx_queue:rx_queue tr:tm->when retrnsmt uid timeout inode ...`, with
+/// addresses as `<hex ip>:<hex port>`
+fn bound_sockets(table: &str, port: u16, protocol: Protocol) -> impl Iterator<Item = u64> + '_ {
+    table.lines().skip(1).filter_map(move |line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (_, local_port) = fields.get(1)?.rsplit_once(':')?;
+        if u16::from_str_radix(local_port, 16).ok()? != port {
+            return None;
+        }
+        if protocol == Protocol::Tcp && *fields.get(3)? != TCP_LISTEN {
+            return None;
+        }
+        fields.get(9)?.parse().ok()
+    })
+}
+
+/// Contents of the tables listing the sockets of `port`, skipping the
+/// missing ones, e.g. with no IPv6
+fn socket_tables(port: &ListenPort) -> impl Iterator<Item = String> {
+    let tables: [&str; 2] = match port.protocol {
+        Protocol::Tcp => ["/proc/net/tcp", "/proc/net/tcp6"],
+        Protocol::Udp => ["/proc/net/udp", "/proc/net/udp6"],
+    };
+    tables
+        .into_iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+}
+
+/// Pid and command name of a process holding the socket `inode`
+fn socket_owner(inode: u64) -> Option<(i32, String)> {
+    let target = format!("socket:[{}]", inode);
+    for entry in std::fs::read_dir("/proc").ok()?.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<i32>().ok())
+        else {
+            continue;
+        };
+        let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            if std::fs::read_link(fd.path()).is_ok_and(|link| link.as_os_str() == target.as_str()) {
+                let comm = std::fs::read_to_string(entry.path().join("comm")).unwrap_or_default();
+                return Some((pid, comm.trim_end().to_owned()));
+            }
+        }
+    }
+    None
+}
+
+/// Inode of a socket bound to `port` in the network namespace of the
+/// supervisor, if any
+fn bound_socket(port: &ListenPort) -> Option<u64> {
+    socket_tables(port).find_map(|table| bound_sockets(&table, port.port, port.protocol).next())
+}
+
+/// Check that none of `ports` is already bound in the network namespace
+/// of the supervisor, failing with `AddrInUse` and the process holding
+/// the port when one is
+pub(crate) fn check_ports(ports: &[ListenPort]) -> io::Result<()> {
+    for port in ports {
+        let Some(inode) = bound_socket(port) else {
+            continue;
+        };
+        let msg = match socket_owner(inode) {
+            Some((pid, comm)) => {
+                format!("port {} is already in use by pid {} ({})", port, pid, comm)
+            }
+            None => format!("port {} is already in use", port),
+        };
+        return Err(io::Error::new(io::ErrorKind::AddrInUse, msg));
+    }
+    Ok(())
+}
+
+/// Whether the process `pid` holds a socket bound to each of `ports`, a
+/// TCP port once it listens on it, the readiness probe of a service
+/// declaring them
+pub(crate) fn are_bound_by(ports: &[ListenPort], pid: Pid) -> bool {
+    let Ok(fds) = std::fs::read_dir(format!("/proc/{}/fd", pid.as_raw_nonzero())) else {
+        return false;
+    };
+    let held: HashSet<u64> = fds
+        .flatten()
+        .filter_map(|fd| {
+            let link = std::fs::read_link(fd.path()).ok()?;
+            let inode = link.to_str()?.strip_prefix("socket:[")?.strip_suffix(']')?;
+            inode.parse().ok()
+        })
+        .collect();
+    ports.iter().all(|port| {
+        socket_tables(port).any(|table| {
+            bound_sockets(&table, port.port, port.protocol).any(|inode| held.contains(&inode))
+        })
+    })
+}
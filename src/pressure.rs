@@ -0,0 +1,146 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{fmt::Write, io, path::PathBuf};
+
+use crate::cgroup::SUPERVISOR_CGROUP;
+use crate::logging::LogLevel;
+use crate::procfs::read_cgroup;
+use crate::service::ServiceRegistry;
+use crate::status::StatusFile;
+use crate::svlogg;
+
+/// Where the cgroup v2 hierarchy is mounted, and the cgroup of the
+/// supervisor in it
+#[derive(Debug, Clone)]
+pub(crate) struct Cgroups {
+    mount: String,
+    pub(crate) own: String,
+}
+
+impl Cgroups {
+    /// Find the cgroup v2 mount point in mountinfo, which is
+    /// `/sys/fs/cgroup` on most systems but `/sys/fs/cgroup/unified` on
+    /// hybrid ones. `None` without cgroup v2
+    pub(crate) fn detect() -> Option<Self> {
+        let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+        let mount = mountinfo.lines().find_map(|line| {
+            let (mount, fs) = line.split_once(" - ")?;
+            fs.starts_with("cgroup2 ")
+                .then(|| mount.split_whitespace().nth(4))
+                .flatten()
+        })?;
+        Some(Self {
+            mount: mount.to_owned(),
+            own: read_cgroup(std::process::id() as i32)?,
+        })
+    }
+
+    /// Directory of `cgroup` in the hierarchy
+    pub(crate) fn path(&self, cgroup: &str) -> PathBuf {
+        PathBuf::from(format!("{}{}", self.mount, cgroup))
+    }
+
+    /// Whether `cgroup` is the one of the supervisor, or the leaf it moves
+    /// to once services get cgroups of their own
+    pub(crate) fn is_supervisor(&self, cgroup: &str) -> bool {
+        cgroup == self.own
+            || cgroup
+                .strip_prefix(self.own.as_str())
+                .is_some_and(|leaf| leaf.trim_start_matches('/') == SUPERVISOR_CGROUP)
+    }
+}
+
+/// Stall percentages of a PSI (pressure stall information) file, averaged
+/// over the last 10 seconds
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct Pressure {
+    /// Share of time at least one task was stalled
+    pub(crate) some_avg10: f64,
+    /// Share of time all non idle tasks were stalled at once
+    pub(crate) full_avg10: f64,
+}
+
+impl Pressure {
+    /// Read `<resource>.pressure` of the cgroup v2 `cgroup`
+    pub(crate) fn read(cgroups: &Cgroups, cgroup: &str, resource: &str) -> io::Result<Self> {
+        let path = cgroups.path(cgroup).join(format!("{}.pressure", resource));
+        Self::parse(&std::fs::read_to_string(path)?)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed pressure"))
+    }
+
+    /// Parse lines like `some avg10=1.50 avg60=0.20 avg300=0.00 total=1234`,
+    /// `full` being missing from `cpu.pressure` on older kernels
+    fn parse(content: &str) -> Option<Self> {
+        let mut pressure = Self::default();
+        for line in content.lines() {
+            let mut fields = line.split_whitespace();
+            let kind = fields.next()?;
+            let avg10 = fields.next()?.strip_prefix("avg10=")?.parse().ok()?;
+            match kind {
+                "some" => pressure.some_avg10 = avg10,
+                "full" => pressure.full_avg10 = avg10,
+                _ => {}
+            }
+        }
+        Some(pressure)
+    }
+}
+
+/// Memory and CPU pressure of the cgroup of a service
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ServicePressure {
+    pub(crate) cgroup: String,
+    pub(crate) memory: Pressure,
+    pub(crate) cpu: Pressure,
+}
+
+impl ServicePressure {
+    pub(crate) fn read(cgroups: &Cgroups, cgroup: String) -> io::Result<Self> {
+        Ok(Self {
+            memory: Pressure::read(cgroups, &cgroup, "memory")?,
+            cpu: Pressure::read(cgroups, &cgroup, "cpu")?,
+            cgroup,
+        })
+    }
+}
+
+/// Publishes the pressure of services running in their own cgroup in the
+/// pressure file of the run directory, one per line:
+/// `<name> <cgroup> <memory_some> <memory_full> <cpu_some> <cpu_full>`
+#[derive(Debug)]
+pub(crate) struct PressureFile {
+    file: StatusFile,
+}
+
+impl PressureFile {
+    pub(crate) fn new(path: PathBuf) -> io::Result<Self> {
+        Ok(Self {
+            file: StatusFile::create(path)?,
+        })
+    }
+
+    /// Rewrite the pressure file if the pressure of any service changed
+    pub(crate) fn flush(&mut self, registry: &ServiceRegistry) {
+        let buf = self.file.buf();
+        for svc in registry.services() {
+            let Some(p) = &svc.pressure else {
+                continue;
+            };
+            let _ = writeln!(
+                buf,
+                "{} {} {:.2} {:.2} {:.2} {:.2}",
+                svc.name,
+                p.cgroup,
+                p.memory.some_avg10,
+                p.memory.full_avg10,
+                p.cpu.some_avg10,
+                p.cpu.full_avg10
+            );
+        }
+        if let Err(e) = self.file.flush() {
+            svlogg!(LogLevel::Error, "failed to write pressure file: {}", e);
+        }
+    }
+}
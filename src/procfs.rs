@@ -0,0 +1,186 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{collections::HashMap, fmt, io};
+
+use rustix::process::{Pid, Signal, kill_process};
+
+/// `PF_KTHREAD` of the process flags, set on kernel threads
+const PF_KTHREAD: u32 = 0x0020_0000;
+
+/// The fields of `/proc/<pid>/stat` svlopp cares about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ProcStat {
+    pub(crate) pid: i32,
+    pub(crate) ppid: i32,
+    pub(crate) comm: String,
+    /// The `PF_*` flags of the process
+    pub(crate) flags: u32,
+    /// User and system CPU time, in clock ticks
+    pub(crate) cpu_ticks: u64,
+    /// Start time in clock ticks since boot, tells apart processes
+    /// reusing the same pid
+    pub(crate) start_time: u64,
+    /// Resident set size, in pages
+    pub(crate) rss_pages: u64,
+}
+
+impl ProcStat {
+    pub(crate) fn read(pid: i32) -> io::Result<Self> {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid))?;
+        Self::parse(pid, &stat)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed stat"))
+    }
+
+    fn parse(pid: i32, stat: &str) -> Option<Self> {
+        // `comm` may contain spaces and parentheses, fields after it
+        // start with `state`
+        let (head, fields) = stat.rsplit_once(')')?;
+        let (_, comm) = head.split_once('(')?;
+        let mut fields = fields.split_whitespace();
+        let ppid = fields.nth(1)?.parse().ok()?;
+        let flags = fields.nth(4)?.parse().ok()?;
+        let utime: u64 = fields.nth(4)?.parse().ok()?;
+        let stime: u64 = fields.next()?.parse().ok()?;
+        let start_time = fields.nth(6)?.parse().ok()?;
+        let rss_pages = fields.nth(1)?.parse().ok()?;
+        Some(Self {
+            pid,
+            ppid,
+            comm: comm.to_owned(),
+            flags,
+            cpu_ticks: utime + stime,
+            start_time,
+            rss_pages,
+        })
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_kernel_thread(&self) -> bool {
+        self.flags & PF_KTHREAD != 0
+    }
+
+    /// Whether the process is still the one this entry was read from
+    pub(crate) fn is_alive(&self) -> bool {
+        Self::read(self.pid).is_ok_and(|stat| stat.start_time == self.start_time)
+    }
+}
+
+/// The cgroup v2 path of `pid`, relative to the cgroup mount point
+pub(crate) fn read_cgroup(pid: i32) -> Option<String> {
+    let cgroups = std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    cgroups
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(str::to_owned)
+}
+
+/// Number of file descriptors `pid` has open
+pub(crate) fn fd_count(pid: i32) -> io::Result<u64> {
+    let mut count = 0;
+    for entry in std::fs::read_dir(format!("/proc/{}/fd", pid))? {
+        entry?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// The soft open file limit (`RLIMIT_NOFILE`) of `pid`, `None` if it's
+/// unlimited
+pub(crate) fn nofile_limit(pid: i32) -> io::Result<Option<u64>> {
+    let limits = std::fs::read_to_string(format!("/proc/{}/limits", pid))?;
+    let soft = limits
+        .lines()
+        .find_map(|line| line.strip_prefix("Max open files"))
+        .and_then(|rest| rest.split_whitespace().next())
+        .ok_or_else(|| io::Error::other("no open file limit in procfs"))?;
+    if soft == "unlimited" {
+        return Ok(None);
+    }
+    soft.parse()
+        .map(Some)
+        .map_err(|_| io::Error::other(format!("invalid open file limit '{}'", soft)))
+}
+
+/// Processes of the system, grouped by parent
+#[derive(Debug, Default)]
+pub(crate) struct ProcessTable {
+    children: HashMap<i32, Vec<ProcStat>>,
+}
+
+impl ProcessTable {
+    pub(crate) fn scan() -> io::Result<Self> {
+        let mut children: HashMap<i32, Vec<ProcStat>> = HashMap::new();
+        for entry in std::fs::read_dir("/proc")? {
+            let Some(pid) = entry?.file_name().to_str().and_then(|s| s.parse().ok()) else {
+                continue;
+            };
+            // processes may exit while scanning
+            if let Ok(stat) = ProcStat::read(pid) {
+                children.entry(stat.ppid).or_default().push(stat);
+            }
+        }
+        Ok(Self { children })
+    }
+
+    /// All the descendants of `pid`, following PPID chains
+    pub(crate) fn descendants(&self, pid: i32, out: &mut Vec<ProcStat>) {
+        out.clear();
+        let mut next = 0;
+        out.extend(self.children.get(&pid).into_iter().flatten().cloned());
+        while next < out.len() {
+            let parent = out[next].pid;
+            out.extend(self.children.get(&parent).into_iter().flatten().cloned());
+            next += 1;
+        }
+    }
+}
+
+/// Size of a memory page, in KiB
+pub(crate) fn page_kib() -> u64 {
+    // SAFETY: `sysconf` has no preconditions
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    page_size.max(0) as u64 / 1024
+}
+
+/// Number of clock ticks per second, the unit of CPU times in procfs
+pub(crate) fn clock_ticks_per_sec() -> u64 {
+    // SAFETY: `sysconf` has no preconditions
+    let clock_ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    clock_ticks.max(1) as u64
+}
+
+/// Write the process tree rooted at `pid`, one process per line:
+/// `<pid> <ppid> <rss_kib> <cpu_ms> <comm>`, the root first
+pub(crate) fn format_process_tree(pid: i32, w: &mut impl fmt::Write) -> io::Result<()> {
+    let root = ProcStat::read(pid)?;
+    let mut procs = Vec::new();
+    ProcessTable::scan()?.descendants(pid, &mut procs);
+    let page_kib = page_kib();
+    let tick_ms = 1000 / clock_ticks_per_sec();
+    for stat in std::iter::once(&root).chain(&procs) {
+        writeln!(
+            w,
+            "{} {} {} {} {}",
+            stat.pid,
+            stat.ppid,
+            stat.rss_pages * page_kib,
+            stat.cpu_ticks * tick_ms,
+            stat.comm
+        )
+        .map_err(io::Error::other)?;
+    }
+    Ok(())
+}
+
+/// Send `SIGKILL` to the processes of `procs` that are still alive,
+/// returning how many were killed
+pub(crate) fn kill_survivors(procs: &[ProcStat]) -> usize {
+    procs
+        .iter()
+        .filter(|stat| stat.is_alive())
+        .filter_map(|stat| Pid::from_raw(stat.pid))
+        .filter(|&pid| kill_process(pid, Signal::KILL).is_ok())
+        .count()
+}
@@ -0,0 +1,64 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{collections::HashSet, fmt::Write, io, path::PathBuf, time::Instant};
+
+use crate::logging::LogLevel;
+use crate::service::Service;
+use crate::status::{StatusFilePath, write_status_file};
+use crate::svlogg;
+
+/// Records how long the services started along with the supervisor took
+/// to come up, in the startup file of the run directory, one line per
+/// service as they exec: `<name> <bind_to|-> <forked_us> <exec_us>`.
+///
+/// Times are microseconds since the supervisor started. `forked_us` is
+/// when the service process was forked, which for bound services includes
+/// waiting for their target, and `exec_us` when it exec'd its command
+#[derive(Debug)]
+pub(crate) struct StartupProfile {
+    base: Instant,
+    path: StatusFilePath,
+    /// Services started along with the supervisor that didn't exec yet
+    pending: HashSet<u64>,
+    buf: String,
+}
+
+impl StartupProfile {
+    pub(crate) fn new(path: PathBuf, base: Instant) -> io::Result<Self> {
+        Ok(Self {
+            base,
+            path: StatusFilePath::new(path)?,
+            pending: HashSet::new(),
+            buf: String::new(),
+        })
+    }
+
+    /// Profile the first start of the service `svc_id`
+    pub(crate) fn track(&mut self, svc_id: u64) {
+        self.pending.insert(svc_id);
+    }
+
+    /// Record that the process of `svc` exec'd at `now`, if its start is
+    /// profiled
+    pub(crate) fn exec(&mut self, svc: &Service, now: Instant) {
+        if !self.pending.remove(&svc.id) {
+            return;
+        }
+        let Some(forked) = svc.started_at else {
+            return;
+        };
+        let _ = writeln!(
+            self.buf,
+            "{} {} {} {}",
+            svc.name,
+            svc.config.bind_to.as_deref().unwrap_or("-"),
+            forked.duration_since(self.base).as_micros(),
+            now.duration_since(self.base).as_micros()
+        );
+        if let Err(e) = write_status_file(&self.path, self.buf.as_bytes()) {
+            svlogg!(LogLevel::Warn, "failed to write startup profile: {}", e);
+        }
+    }
+}
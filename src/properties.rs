@@ -0,0 +1,160 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Properties of services changed at runtime, by the `set-property`
+//! control operation.
+//!
+//! The assignment, e.g. `cpu.max=50%`, doesn't fit in a command frame:
+//! `svloppctl` writes it to `property.d/<service id>` in the runtime
+//! directory before sending the command, and svlopp removes the file once
+//! read. A property applies to the cgroup of the running process right
+//! away, then to the following ones, and survives supervisor restarts
+//! when there is a state directory.
+
+use std::{collections::HashMap, fmt, fmt::Write, io, num::NonZeroU32, path::Path, str::FromStr};
+
+use crate::logging::LogLevel;
+use crate::restarts::create_state_dir;
+use crate::service::ServiceRegistry;
+use crate::status::{StatusFilePath, write_status_file};
+use crate::svlogg;
+
+/// Name of the properties file in the state directory
+const PROPERTIES_FILE_NAME: &str = "properties";
+
+/// A property of a service, set by `set-property`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Property {
+    /// `cpu.max`: the CPU time the service gets, in percent of one CPU,
+    /// e.g. `50%` or `200%`. `max` lifts the limit
+    CpuMax(Option<NonZeroU32>),
+}
+
+impl FromStr for Property {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| io::Error::other(format!("expected <property>=<value>, got '{}'", s)))?;
+        match name {
+            "cpu.max" => {
+                if value == "max" {
+                    return Ok(Self::CpuMax(None));
+                }
+                value
+                    .strip_suffix('%')
+                    .and_then(|percent| percent.parse().ok())
+                    .map(|percent| Self::CpuMax(Some(percent)))
+                    .ok_or_else(|| {
+                        io::Error::other(format!(
+                            "invalid cpu.max '{}', expected a percentage or 'max'",
+                            value
+                        ))
+                    })
+            }
+            _ => Err(io::Error::other(format!("unknown property '{}'", name))),
+        }
+    }
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CpuMax(Some(percent)) => write!(f, "cpu.max={}%", percent),
+            Self::CpuMax(None) => write!(f, "cpu.max=max"),
+        }
+    }
+}
+
+/// Persists the properties set on services in the state directory
+/// (`--state-dir`), so that they survive supervisor restarts.
+///
+/// The properties file has one line per property set:
+/// `<name> <property>=<value>`
+#[derive(Debug)]
+pub(crate) struct PropertyStore {
+    path: StatusFilePath,
+}
+
+impl PropertyStore {
+    /// Open the store in `state_dir`, creating the directory if needed
+    pub(crate) fn open(state_dir: &Path) -> io::Result<Self> {
+        create_state_dir(state_dir)?;
+        Ok(Self {
+            path: StatusFilePath::new(state_dir.join(PROPERTIES_FILE_NAME))?,
+        })
+    }
+
+    /// Read the properties persisted by a previous supervisor, malformed
+    /// lines are skipped
+    pub(crate) fn load(&self) -> io::Result<HashMap<String, Vec<Property>>> {
+        let content = match std::fs::read_to_string(self.path.path()) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e),
+        };
+        let mut properties: HashMap<String, Vec<Property>> = HashMap::new();
+        for line in content.lines() {
+            let Some((name, Ok(property))) = line
+                .split_once(' ')
+                .map(|(name, property)| (name, property.parse()))
+            else {
+                svlogg!(
+                    LogLevel::Warn,
+                    "skipping malformed properties line: {}",
+                    line
+                );
+                continue;
+            };
+            properties
+                .entry(name.to_owned())
+                .or_default()
+                .push(property);
+        }
+        Ok(properties)
+    }
+
+    /// Rewrite the properties file with those of the services
+    pub(crate) fn write(&self, registry: &ServiceRegistry) -> io::Result<()> {
+        let mut content = String::new();
+        for svc in registry.services() {
+            for property in svc.properties() {
+                let _ = writeln!(content, "{} {}", svc.name, property);
+            }
+        }
+        write_status_file(&self.path, content.as_bytes())
+    }
+}
+
+/// Set the property requested in `<dir>/<svc_id>` on service `svc_id`,
+/// then persist the properties to `store`
+pub(crate) fn set_property(
+    registry: &mut ServiceRegistry,
+    svc_id: u64,
+    dir: &Path,
+    store: Option<&PropertyStore>,
+) -> io::Result<()> {
+    let path = dir.join(svc_id.to_string());
+    let request = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+    let property: Property = request?.trim().parse()?;
+    let Some(svc) = registry.service_mut(svc_id) else {
+        return Err(io::Error::other(format!("unknown service id {}", svc_id)));
+    };
+    let applied = svc.set_property(property);
+    svlogg!(LogLevel::Info, "set {} on service '{}'", property, svc.name);
+    if svc.pid().is_some() && svc.cgroup.is_none() {
+        svlogg!(
+            LogLevel::Info,
+            "service '{}' has no cgroup, {} applies from its next start",
+            svc.name,
+            property
+        );
+    }
+    if let Some(store) = store {
+        store.write(registry)?;
+    }
+    applied
+}
@@ -0,0 +1,97 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Buffers of fixed size records read from the kernel.
+//!
+//! Some fds are read as arrays of C structs, e.g. a signalfd returns
+//! `signalfd_siginfo` records. `ReadBuf` is read into as bytes, then hands
+//! out the records the read returned, checking that it returned whole
+//! ones. This is the only place bytes are reinterpreted as records, the
+//! tests in `tests/read_buf.rs` run under Miri.
+
+use std::fmt;
+
+/// Types that any sequence of `size_of::<Self>()` bytes is a valid value
+/// of.
+///
+/// # Safety
+///
+/// Implementors must have no padding, no pointers or references, and no
+/// field with invalid bit patterns (`bool`, enums, `NonZero*`). Their size
+/// must not be zero
+pub unsafe trait Pod: Copy {}
+
+macro_rules! impl_pod {
+    ($($t:ty),*) => {
+        $(unsafe impl Pod for $t {})*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadBufError {
+    /// More bytes read than the buffer holds
+    Oversized(usize),
+    /// Bytes read past the last whole record
+    Partial(usize),
+}
+
+impl fmt::Display for ReadBufError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Oversized(n) => write!(f, "read of {} bytes overflows the buffer", n),
+            Self::Partial(n) => write!(f, "read ends with a partial record ({} bytes)", n),
+        }
+    }
+}
+
+impl std::error::Error for ReadBufError {}
+
+/// Buffer of up to `N` records of type `T`
+#[derive(Debug, Clone)]
+pub struct ReadBuf<T: Pod, const N: usize> {
+    records: [T; N],
+}
+
+impl<T: Pod, const N: usize> ReadBuf<T, N> {
+    pub fn new() -> Self {
+        const { assert!(size_of::<T>() > 0, "zero sized records") };
+        Self {
+            // SAFETY: `T` is `Pod`, zeroes are a valid value
+            records: unsafe { std::mem::zeroed() },
+        }
+    }
+
+    /// The buffer as bytes, to read into
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        // SAFETY: `T` being `Pod`, the records have no padding: all their
+        // bytes are initialized, and any bytes written make valid records
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.records.as_mut_ptr().cast::<u8>(),
+                size_of_val(&self.records),
+            )
+        }
+    }
+
+    /// The records read, `n` being the number of bytes the read returned
+    pub fn records(&self, n: usize) -> Result<&[T], ReadBufError> {
+        if n > size_of_val(&self.records) {
+            return Err(ReadBufError::Oversized(n));
+        }
+        match n % size_of::<T>() {
+            0 => Ok(&self.records[..n / size_of::<T>()]),
+            partial => Err(ReadBufError::Partial(partial)),
+        }
+    }
+}
+
+impl<T: Pod, const N: usize> Default for ReadBuf<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,496 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{collections::HashMap, fmt, io, time::SystemTime};
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::logging::LogLevel;
+use crate::service::{
+    PreparedConfig, Service, ServiceConfig, ServiceIdGen, ServicePendingAction, ServiceRegistry,
+    ServiceState, StartQueue, stop_service,
+};
+use crate::status::{StatusFilePath, write_status_file};
+use crate::svlogg;
+
+/// What a reload does about the running services whose config changed,
+/// the `reload_policy` supervisor option
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ReloadPolicy {
+    /// Restart them with their new config
+    #[default]
+    Restart,
+    /// Leave them running their previous config, flagged
+    /// `restart_required` until restarted, e.g. by `restart --outdated`
+    Defer,
+}
+
+/// A change to the services of the registry
+#[derive(Debug)]
+enum Step {
+    /// Remove a service that is no longer configured
+    Remove(u64),
+    /// Add a newly configured service
+    Add(Box<Service>),
+    /// Replace the config of a service whose definition changed
+    Update(u64, Box<PreparedConfig>),
+}
+
+/// How to revert an applied step
+#[derive(Debug)]
+enum Undo {
+    /// Insert a removed service back
+    Removed(Box<Service>),
+    /// Remove an added service
+    Added(u64),
+    /// Swap the previous config back
+    Updated(u64, Box<PreparedConfig>),
+    /// Restore the pending action of a service, or restart it once reaped
+    /// if the step stopped it
+    Pending {
+        id: u64,
+        previous: ServicePendingAction,
+        stopped: bool,
+    },
+}
+
+/// What a reload does about a service it adds, removes or changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReloadAction {
+    /// Nothing, the service being stopped and not started automatically
+    None,
+    Start,
+    /// Stop the service, and remove it once reaped
+    Stop,
+    /// Remove the service right away, as it's stopped
+    Remove,
+    /// Stop the service, and start it with its new definition once reaped
+    Restart,
+    /// Leave the service running its previous definition
+    Defer,
+}
+
+impl ReloadAction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Start => "start",
+            Self::Stop => "stop",
+            Self::Remove => "remove",
+            Self::Restart => "restart",
+            Self::Defer => "defer",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl ChangeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Added => "added",
+            Self::Removed => "removed",
+            Self::Changed => "changed",
+        }
+    }
+}
+
+/// A service added, removed or changed by a reload, and why
+#[derive(Debug)]
+struct ServiceChange {
+    name: String,
+    kind: ChangeKind,
+    /// Config fields whose value changed, for changed services
+    fields: Vec<&'static str>,
+    action: ReloadAction,
+}
+
+impl fmt::Display for ServiceChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "service '{}' {}", self.name, self.kind.as_str())?;
+        if !self.fields.is_empty() {
+            write!(f, " ({})", self.fields.join(", "))?;
+        }
+        write!(f, ", action: {}", self.action.as_str())
+    }
+}
+
+/// Outcome of a reload, written as JSON to the reload file of the run
+/// directory
+#[derive(Debug, Default)]
+pub(crate) struct ReloadReport {
+    added: Vec<String>,
+    removed: Vec<String>,
+    updated: Vec<String>,
+    /// Running services stopped to be removed or restarted
+    stopped: Vec<String>,
+    /// The diff of the services, sorted by name
+    changes: Vec<ServiceChange>,
+    error: Option<String>,
+    /// Whether the changes were reverted after `error`
+    pub(crate) rolled_back: bool,
+}
+
+impl ReloadReport {
+    /// Report of a reload that failed before changing any service
+    pub(crate) fn failed(e: io::Error) -> Self {
+        Self {
+            error: Some(e.to_string()),
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub(crate) fn write(&self, path: &StatusFilePath) -> io::Result<()> {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let mut content = json!({
+            "ok": self.error.is_none(),
+            "error": self.error,
+            "rolled_back": self.rolled_back,
+            "added": self.added,
+            "removed": self.removed,
+            "updated": self.updated,
+            "stopped": self.stopped,
+            "changes": self
+                .changes
+                .iter()
+                .map(|change| json!({
+                    "service": change.name,
+                    "change": change.kind.as_str(),
+                    "fields": change.fields,
+                    "action": change.action.as_str(),
+                }))
+                .collect::<Vec<_>>(),
+            "timestamp_ms": timestamp_ms,
+        })
+        .to_string();
+        content.push('\n');
+        write_status_file(path, content.as_bytes())
+    }
+}
+
+/// The changes bringing the services of the registry to a newly parsed
+/// configuration.
+///
+/// New services are built and changed configs validated when planning,
+/// so that applying the transaction can only fail at stopping services,
+/// in which case the applied steps are reverted and the services stopped
+/// so far are restarted with their previous definition.
+///
+/// For removed services (present in the registry, but not present in the
+/// new config):
+/// * If the service state is `ServiceState::Stopped(_)`: remove the service
+///   from the registry immediately.
+/// * If `ServiceState::Running`: call `stop_service` and mark for removal so
+///   that it can be removed once the process has been reaped.
+/// * If `ServiceState::Stopping(_)`: just mark for removal.
+///
+/// New services are inserted in the registry and queued for start.
+///
+/// For changed services (present in both the registry and the new config
+/// but with different configurations):
+/// * If the service state is `ServiceState::Stopped(_)`: update the config,
+///   then queue it for start.
+/// * If `ServiceState::Running`: call `stop_service`, store new config and
+///   mark for restart so that it can be restarted after the process has been
+///   reaped.
+/// * if `ServiceState::Stopping(_)`: just store the new config and mark for
+///   restart.
+///
+/// With `ReloadPolicy::Defer`, running and stopping changed services only
+/// get their new config, used from their next start.
+///
+/// Services are only queued for start once the whole transaction applied
+#[derive(Debug)]
+pub(crate) struct ReloadTransaction {
+    steps: Vec<Step>,
+    report: ReloadReport,
+    policy: ReloadPolicy,
+}
+
+impl ReloadTransaction {
+    pub(crate) fn plan(
+        registry: &ServiceRegistry,
+        configs: HashMap<String, ServiceConfig>,
+        policy: ReloadPolicy,
+        id_gen: &mut ServiceIdGen,
+    ) -> io::Result<Self> {
+        let mut steps = Vec::new();
+        let mut report = ReloadReport::default();
+
+        let mut removed: Vec<&Service> = registry
+            .services()
+            .filter(|svc| !svc.transient && !configs.contains_key(&*svc.name))
+            .collect();
+        removed.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        for svc in removed {
+            steps.push(Step::Remove(svc.id));
+            report.removed.push(svc.name.to_string());
+            report.changes.push(ServiceChange {
+                name: svc.name.to_string(),
+                kind: ChangeKind::Removed,
+                fields: Vec::new(),
+                action: match svc.state {
+                    ServiceState::Stopped(_) => ReloadAction::Remove,
+                    ServiceState::Stopping(_, _)
+                    | ServiceState::Running(_)
+                    | ServiceState::Paused(_) => ReloadAction::Stop,
+                },
+            });
+        }
+
+        let mut configs: Vec<(String, ServiceConfig)> = configs.into_iter().collect();
+        configs.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        for (name, cfg) in configs {
+            match registry.get_by_name(&name) {
+                None => {
+                    let svc_id = id_gen
+                        .nextval()
+                        .ok_or_else(|| io::Error::other("service id overflow"))?;
+                    let svc = Service::new(svc_id, name.clone(), cfg).map_err(|e| {
+                        io::Error::new(e.kind(), format!("service '{}': {}", name, e))
+                    })?;
+                    report.changes.push(ServiceChange {
+                        name: name.clone(),
+                        kind: ChangeKind::Added,
+                        fields: Vec::new(),
+                        action: match svc.config.autostart {
+                            true => ReloadAction::Start,
+                            false => ReloadAction::None,
+                        },
+                    });
+                    steps.push(Step::Add(Box::new(svc)));
+                    report.added.push(name);
+                }
+                Some(svc) if svc.transient => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("service '{}' is a transient service", name),
+                    ));
+                }
+                Some(svc) if svc.config != cfg => {
+                    let fields = svc.config.changed_fields(&cfg);
+                    let action = match svc.state {
+                        ServiceState::Stopped(_) if cfg.autostart => ReloadAction::Start,
+                        ServiceState::Stopped(_) => ReloadAction::None,
+                        _ if policy == ReloadPolicy::Defer => ReloadAction::Defer,
+                        ServiceState::Stopping(_, _)
+                        | ServiceState::Running(_)
+                        | ServiceState::Paused(_) => ReloadAction::Restart,
+                    };
+                    let prepared = PreparedConfig::new(cfg).map_err(|e| {
+                        io::Error::new(e.kind(), format!("service '{}': {}", name, e))
+                    })?;
+                    steps.push(Step::Update(svc.id, Box::new(prepared)));
+                    report.changes.push(ServiceChange {
+                        name: name.clone(),
+                        kind: ChangeKind::Changed,
+                        fields,
+                        action,
+                    });
+                    report.updated.push(name);
+                }
+                Some(_) => {}
+            }
+        }
+        report.changes.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        for change in &report.changes {
+            svlogg!(LogLevel::Info, "reload: {}", change);
+        }
+        Ok(Self {
+            steps,
+            report,
+            policy,
+        })
+    }
+
+    /// Apply the steps in order, reverting them all if one fails
+    pub(crate) fn apply(
+        self,
+        registry: &mut ServiceRegistry,
+        start_queue: &mut StartQueue,
+    ) -> ReloadReport {
+        let Self {
+            steps,
+            mut report,
+            policy,
+        } = self;
+        let mut undo = Vec::with_capacity(steps.len());
+        let mut queued = Vec::new();
+        for step in steps {
+            if let Err(e) = apply_step(registry, step, policy, &mut undo, &mut queued, &mut report)
+            {
+                svlogg!(LogLevel::Error, "reload failed, rolling back: {}", e);
+                report.error = Some(e.to_string());
+                report.rolled_back = true;
+                for undo in undo.into_iter().rev() {
+                    revert(registry, undo);
+                }
+                return report;
+            }
+        }
+        for svc_id in queued {
+            if let Some(svc) = registry.service_mut(svc_id) {
+                start_queue.push(svc);
+            }
+        }
+        report
+    }
+}
+
+fn apply_step(
+    registry: &mut ServiceRegistry,
+    step: Step,
+    policy: ReloadPolicy,
+    undo: &mut Vec<Undo>,
+    queued: &mut Vec<u64>,
+    report: &mut ReloadReport,
+) -> io::Result<()> {
+    match step {
+        Step::Remove(svc_id) => {
+            let Some(svc) = registry.service_mut(svc_id) else {
+                return Ok(());
+            };
+            match svc.state {
+                ServiceState::Stopped(_) => {
+                    svlogg!(LogLevel::Info, "removing stopped service '{}'", svc.name);
+                    svc.pending_action = ServicePendingAction::None;
+                    if let Some(svc) = registry.remove_service(svc_id) {
+                        undo.push(Undo::Removed(Box::new(svc)));
+                    }
+                    Ok(())
+                }
+                ServiceState::Stopping(_, _)
+                | ServiceState::Running(_)
+                | ServiceState::Paused(_) => {
+                    svlogg!(
+                        LogLevel::Info,
+                        "stopping service '{}' for removal",
+                        svc.name
+                    );
+                    mark_and_stop(svc, ServicePendingAction::Remove, undo, report)
+                }
+            }
+        }
+        Step::Add(svc) => {
+            svlogg!(LogLevel::Debug, "adding new service '{}'", svc.name);
+            let svc_id = svc.id;
+            if svc.config.autostart {
+                queued.push(svc_id);
+            }
+            registry.insert_service(*svc);
+            undo.push(Undo::Added(svc_id));
+            Ok(())
+        }
+        Step::Update(svc_id, prepared) => {
+            let Some(svc) = registry.service_mut(svc_id) else {
+                return Ok(());
+            };
+            svlogg!(LogLevel::Debug, "config changed for service {}", svc.name);
+            // Update the config now so that when the process is eventually
+            // restarted, it uses the new definition. The currently running
+            // process continues with the old config until it exits.
+            let previous = svc.swap_config(*prepared);
+            undo.push(Undo::Updated(svc_id, Box::new(previous)));
+            match svc.state {
+                ServiceState::Stopped(_) if svc.config.autostart => {
+                    svlogg!(
+                        LogLevel::Info,
+                        "service '{}' was stopped, starting with new config",
+                        svc.name
+                    );
+                    queued.push(svc_id);
+                    Ok(())
+                }
+                ServiceState::Stopped(_) => Ok(()),
+                _ if policy == ReloadPolicy::Defer => {
+                    svlogg!(
+                        LogLevel::Info,
+                        "service '{}' keeps running its previous config until restarted",
+                        svc.name
+                    );
+                    Ok(())
+                }
+                ServiceState::Stopping(_, _)
+                | ServiceState::Running(_)
+                | ServiceState::Paused(_) => {
+                    svlogg!(LogLevel::Info, "service '{}' will be restarted", svc.name);
+                    mark_and_stop(svc, ServicePendingAction::Restart, undo, report)
+                }
+            }
+        }
+    }
+}
+
+/// Set the pending action of `svc` to `action`, and stop it if it runs
+fn mark_and_stop(
+    svc: &mut Service,
+    action: ServicePendingAction,
+    undo: &mut Vec<Undo>,
+    report: &mut ReloadReport,
+) -> io::Result<()> {
+    let previous = std::mem::replace(&mut svc.pending_action, action);
+    let running = matches!(
+        svc.state,
+        ServiceState::Running(_) | ServiceState::Paused(_)
+    );
+    let result = stop_service(svc);
+    let stopped = running && result.is_ok();
+    if stopped {
+        report.stopped.push(svc.name.to_string());
+    }
+    undo.push(Undo::Pending {
+        id: svc.id,
+        previous,
+        stopped,
+    });
+    result
+}
+
+fn revert(registry: &mut ServiceRegistry, undo: Undo) {
+    match undo {
+        Undo::Removed(svc) => registry.insert_service(*svc),
+        Undo::Added(svc_id) => {
+            let _ = registry.remove_service(svc_id);
+        }
+        Undo::Updated(svc_id, previous) => {
+            if let Some(svc) = registry.service_mut(svc_id) {
+                let _ = svc.swap_config(*previous);
+            }
+        }
+        Undo::Pending {
+            id,
+            previous,
+            stopped,
+        } => {
+            let Some(svc) = registry.service_mut(id) else {
+                return;
+            };
+            svc.pending_action = match stopped {
+                true => {
+                    svlogg!(
+                        LogLevel::Info,
+                        "service '{}' will be restarted with its previous definition",
+                        svc.name
+                    );
+                    ServicePendingAction::Restart
+                }
+                false => previous,
+            };
+        }
+    }
+}
@@ -0,0 +1,151 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{io, num::NonZeroU32, time::Instant};
+
+use serde::Deserialize;
+
+use crate::pressure::ServicePressure;
+use crate::procfs::{ProcStat, clock_ticks_per_sec, page_kib};
+
+/// Default number of consecutive samples over a limit before acting
+const DEFAULT_RESOURCE_SAMPLES: NonZeroU32 = NonZeroU32::new(3).expect("non-zero");
+
+fn default_resource_samples() -> NonZeroU32 {
+    DEFAULT_RESOURCE_SAMPLES
+}
+
+/// What to do about a service that exceeds its resource limits
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ResourceAction {
+    /// Only log a warning
+    #[default]
+    Log,
+    /// Stop the service and start it again
+    Restart,
+    /// Stop the service
+    Stop,
+}
+
+/// Limits on the resources used by the process tree of a service, sampled
+/// from procfs on each timerfd tick.
+///
+/// A poor man's OOM guard for when cgroups are not available: `action` is
+/// taken once a limit has been exceeded for `samples` consecutive samples
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ResourceLimits {
+    /// Maximum resident set size, in KiB
+    #[serde(default)]
+    pub(crate) max_rss_kib: Option<u64>,
+    /// Maximum CPU usage between two samples, in percent of one CPU
+    #[serde(default)]
+    pub(crate) max_cpu_percent: Option<u64>,
+    /// Maximum memory pressure (`some avg10`), in percent, of the service
+    /// cgroup, only checked when the service runs in its own cgroup
+    #[serde(default)]
+    pub(crate) max_memory_pressure: Option<u64>,
+    /// Maximum CPU pressure (`some avg10`), in percent, as for
+    /// `max_memory_pressure`
+    #[serde(default)]
+    pub(crate) max_cpu_pressure: Option<u64>,
+    #[serde(default = "default_resource_samples")]
+    pub(crate) samples: NonZeroU32,
+    #[serde(default)]
+    pub(crate) action: ResourceAction,
+}
+
+impl ResourceLimits {
+    pub(crate) fn validate(&self) -> io::Result<()> {
+        if self.max_rss_kib.is_none() && self.max_cpu_percent.is_none() && !self.uses_pressure() {
+            return Err(io::Error::other(
+                "resource_limits needs max_rss_kib, max_cpu_percent, max_memory_pressure or \
+                 max_cpu_pressure",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the limits need the pressure of the service cgroup
+    pub(crate) fn uses_pressure(&self) -> bool {
+        self.max_memory_pressure.is_some() || self.max_cpu_pressure.is_some()
+    }
+}
+
+/// Resource usage of a process tree, summed over its processes
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct ResourceUsage {
+    pub(crate) rss_kib: u64,
+    pub(crate) cpu_ticks: u64,
+}
+
+impl ResourceUsage {
+    pub(crate) fn of<'a>(procs: impl IntoIterator<Item = &'a ProcStat>) -> Self {
+        let page_kib = page_kib();
+        procs.into_iter().fold(Self::default(), |usage, stat| Self {
+            rss_kib: usage.rss_kib + stat.rss_pages * page_kib,
+            cpu_ticks: usage.cpu_ticks + stat.cpu_ticks,
+        })
+    }
+}
+
+/// Counts the consecutive samples a service spends over its limits
+#[derive(Debug, Default)]
+pub(crate) struct ResourceMonitor {
+    /// CPU time and time of the last sample
+    last: Option<(u64, Instant)>,
+    /// Consecutive samples over a limit
+    strikes: u32,
+}
+
+impl ResourceMonitor {
+    /// Record the sample `usage` taken at `now`, along with the cgroup
+    /// `pressure` if known. Returns a description of
+    /// the exceeded limit once exceeded for `limits.samples` consecutive
+    /// samples, after which counting starts over
+    pub(crate) fn sample(
+        &mut self,
+        limits: &ResourceLimits,
+        usage: ResourceUsage,
+        pressure: Option<&ServicePressure>,
+        now: Instant,
+    ) -> Option<String> {
+        // CPU usage needs a previous sample, and the CPU time of exited
+        // processes leaves the sum
+        let cpu_percent = self.last.and_then(|(cpu_ticks, at)| {
+            let elapsed_ms = now.duration_since(at).as_millis() as u64;
+            let cpu_ms = usage.cpu_ticks.saturating_sub(cpu_ticks) * 1000 / clock_ticks_per_sec();
+            (elapsed_ms > 0).then(|| cpu_ms * 100 / elapsed_ms)
+        });
+        self.last = Some((usage.cpu_ticks, now));
+        let memory_pressure = pressure.map(|p| p.memory.some_avg10);
+        let cpu_pressure = pressure.map(|p| p.cpu.some_avg10);
+        let exceeded = if let Some(max) = limits.max_rss_kib
+            && usage.rss_kib > max
+        {
+            format!("rss {} KiB over {} KiB", usage.rss_kib, max)
+        } else if let (Some(max), Some(cpu)) = (limits.max_cpu_percent, cpu_percent)
+            && cpu > max
+        {
+            format!("cpu {}% over {}%", cpu, max)
+        } else if let (Some(max), Some(p)) = (limits.max_memory_pressure, memory_pressure)
+            && p > max as f64
+        {
+            format!("memory pressure {:.2}% over {}%", p, max)
+        } else if let (Some(max), Some(p)) = (limits.max_cpu_pressure, cpu_pressure)
+            && p > max as f64
+        {
+            format!("cpu pressure {:.2}% over {}%", p, max)
+        } else {
+            self.strikes = 0;
+            return None;
+        };
+        self.strikes += 1;
+        if self.strikes < limits.samples.get() {
+            return None;
+        }
+        self.strikes = 0;
+        Some(exceeded)
+    }
+}
@@ -0,0 +1,135 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Write,
+    io,
+    num::NonZeroU32,
+    path::Path,
+};
+
+use rustix::fs::{CWD, Mode, mkdirat};
+use serde::Deserialize;
+
+use crate::logging::LogLevel;
+use crate::service::ServiceRegistry;
+use crate::status::{StatusFile, StatusFilePath};
+use crate::svlogg;
+
+/// Name of the restart history file in the state directory
+const RESTARTS_FILE_NAME: &str = "restarts";
+
+/// Number of failure timestamps kept per service, and so the maximum
+/// `start_limit.burst`
+pub(crate) const MAX_RECORDED_FAILURES: u32 = 64;
+
+/// Limit on automatic restarts: a service that failed `burst` times within
+/// `interval_ms` is not restarted by `on_exit = "Restart"` anymore, until
+/// it's started explicitly
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StartLimit {
+    pub(crate) burst: NonZeroU32,
+    pub(crate) interval_ms: u64,
+}
+
+impl StartLimit {
+    pub(crate) fn validate(&self) -> io::Result<()> {
+        if self.burst.get() > MAX_RECORDED_FAILURES {
+            return Err(io::Error::other(format!(
+                "start_limit.burst must be at most {}",
+                MAX_RECORDED_FAILURES
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether `failures`, wall clock milliseconds since the epoch, hit
+    /// the limit at `now_ms`
+    pub(crate) fn is_hit(&self, failures: &VecDeque<u64>, now_ms: u64) -> bool {
+        let since = now_ms.saturating_sub(self.interval_ms);
+        failures.iter().filter(|&&t| t >= since).count() >= self.burst.get() as usize
+    }
+}
+
+/// Create the state directory, if it doesn't exist yet
+pub(crate) fn create_state_dir(state_dir: &Path) -> io::Result<()> {
+    match mkdirat(CWD, state_dir, Mode::from_bits_truncate(0o755)) {
+        Err(e) if e != rustix::io::Errno::EXIST => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Restart history of a service, as persisted in the state directory
+#[derive(Debug, Default)]
+pub(crate) struct RestartRecord {
+    pub(crate) start_count: u64,
+    pub(crate) failures: VecDeque<u64>,
+}
+
+/// Persists the restart history of services in the state directory
+/// (`--state-dir`), so that it survives supervisor restarts.
+///
+/// The restarts file has one line per service:
+/// `<name> <start_count> [<failure_ms>...]`, failures being wall clock
+/// milliseconds since the epoch, oldest first
+#[derive(Debug)]
+pub(crate) struct RestartStore {
+    file: StatusFile,
+}
+
+impl RestartStore {
+    /// Open the store in `state_dir`, creating the directory if needed
+    pub(crate) fn open(state_dir: &Path) -> io::Result<Self> {
+        create_state_dir(state_dir)?;
+        Ok(Self {
+            file: StatusFile::new(StatusFilePath::new(state_dir.join(RESTARTS_FILE_NAME))?),
+        })
+    }
+
+    /// Read the history persisted by a previous supervisor, malformed
+    /// lines are skipped
+    pub(crate) fn load(&mut self) -> io::Result<HashMap<String, RestartRecord>> {
+        let content = match std::fs::read_to_string(self.file.path()) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e),
+        };
+        let mut records = HashMap::new();
+        for line in content.lines() {
+            let mut fields = line.split_whitespace();
+            let (Some(name), Some(Ok(start_count))) =
+                (fields.next(), fields.next().map(str::parse))
+            else {
+                svlogg!(LogLevel::Warn, "skipping malformed restarts line: {}", line);
+                continue;
+            };
+            let failures = fields.filter_map(|t| t.parse().ok()).collect();
+            records.insert(
+                name.to_owned(),
+                RestartRecord {
+                    start_count,
+                    failures,
+                },
+            );
+        }
+        self.file.set_written(content);
+        Ok(records)
+    }
+
+    /// Rewrite the restarts file if the history changed
+    pub(crate) fn flush(&mut self, registry: &ServiceRegistry) {
+        let buf = self.file.buf();
+        for svc in registry.services() {
+            let _ = write!(buf, "{} {}", svc.name, svc.start_count);
+            for t in &svc.failures {
+                let _ = write!(buf, " {}", t);
+            }
+            buf.push('\n');
+        }
+        if let Err(e) = self.file.flush() {
+            svlogg!(LogLevel::Error, "failed to write restarts file: {}", e);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Rolling restarts of the instances of a replicated service.
+//!
+//! The instances of a service `name` are the services named
+//! `name@<instance>`. The `rolling-restart` control operation restarts
+//! them one at a time, in the order of their names, moving on to the next
+//! one once the restarted instance is ready, see `is_ready`.
+//! The rollout is aborted, the instances left to restart being left as
+//! they are, when an instance fails to come back: its new process exits,
+//! or it isn't ready within `READY_TIMEOUT`.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    fmt::Write,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::control::ControlOp;
+use crate::jobs::START_SETTLE;
+use crate::logging::LogLevel;
+use crate::ports::are_bound_by;
+use crate::service::{Service, ServiceRegistry, ServiceState, SpawnContext, apply_control_op};
+use crate::status::StatusFile;
+use crate::svlogg;
+
+/// How long a restarted instance has to become ready
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Separates the name of a replicated service from the instance
+const INSTANCE_SEPARATOR: char = '@';
+
+/// Name of the replicated service `name` is an instance of, if any
+pub(crate) fn instance_of(name: &str) -> Option<&str> {
+    name.split_once(INSTANCE_SEPARATOR)
+        .filter(|(base, instance)| !base.is_empty() && !instance.is_empty())
+        .map(|(base, _)| base)
+}
+
+/// Whether the running service `svc` is ready at `now`: once its process
+/// notified readiness, holds a socket bound to each of its `ports` or,
+/// without `ports`, once it has been running for `START_SETTLE`
+pub(crate) fn is_ready(svc: &Service, now: Instant) -> bool {
+    let ServiceState::Running(pid) = svc.state else {
+        return false;
+    };
+    if svc.ready_notified {
+        return true;
+    }
+    match svc.config.ports.is_empty() {
+        true => svc
+            .started_at
+            .is_some_and(|t| now.saturating_duration_since(t) >= START_SETTLE),
+        false => are_bound_by(&svc.config.ports, pid),
+    }
+}
+
+/// Whether `svc`, started at `since` from `start_count` starts, came
+/// back: `Ok(true)` once it's ready, `Ok(false)` while it starts, and
+/// `Err` with what went wrong when its new process exited, or it wasn't
+/// ready within `READY_TIMEOUT`
+pub(crate) fn check_ready(
+    svc: &Service,
+    start_count: u64,
+    since: Instant,
+    now: Instant,
+) -> Result<bool, String> {
+    match svc.start_count - start_count {
+        // still stopping, or waiting for a start slot
+        0 => {}
+        1 => match svc.state {
+            ServiceState::Running(_) if is_ready(svc, now) => return Ok(true),
+            ServiceState::Running(_) | ServiceState::Paused(_) => {}
+            ServiceState::Stopped(reason) => return Err(format!("exited: {}", reason)),
+            ServiceState::Stopping(..) => return Err("was stopped".to_owned()),
+        },
+        _ => return Err("exited and was restarted".to_owned()),
+    }
+    match now.saturating_duration_since(since) >= READY_TIMEOUT {
+        true => Err(format!("wasn't ready within {}s", READY_TIMEOUT.as_secs())),
+        false => Ok(false),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RolloutState {
+    Running,
+    Done,
+    Aborted,
+}
+
+impl fmt::Display for RolloutState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Running => write!(f, "running"),
+            Self::Done => write!(f, "done"),
+            Self::Aborted => write!(f, "aborted"),
+        }
+    }
+}
+
+/// The instance being restarted
+#[derive(Debug)]
+struct Restarting {
+    svc_id: u64,
+    name: Arc<str>,
+    /// Start count of the instance before the restart, a restarted
+    /// instance having started exactly once more
+    start_count: u64,
+    since: Instant,
+}
+
+#[derive(Debug)]
+struct Rollout {
+    name: String,
+    state: RolloutState,
+    /// Instances left to restart, in order
+    pending: VecDeque<u64>,
+    current: Option<Restarting>,
+    restarted: usize,
+    total: usize,
+}
+
+/// Drives the rolling restart, one at a time, and publishes its progress
+/// in the rollout file of the run directory as a single line:
+/// `<name> <running|done|aborted> <restarted>/<total> <instance|->`, the
+/// instance being the one restarted at the moment
+#[derive(Debug)]
+pub(crate) struct RolloutFile {
+    file: StatusFile,
+    rollout: Option<Rollout>,
+}
+
+impl RolloutFile {
+    pub(crate) fn new(path: PathBuf) -> io::Result<Self> {
+        Ok(Self {
+            file: StatusFile::create(path)?,
+            rollout: None,
+        })
+    }
+
+    /// Begin the rolling restart of the replicated service of which the
+    /// service `svc_id` is an instance
+    pub(crate) fn begin(&mut self, registry: &ServiceRegistry, svc_id: u64) -> io::Result<()> {
+        if let Some(rollout) = &self.rollout
+            && rollout.state == RolloutState::Running
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::ResourceBusy,
+                format!("the rolling restart of '{}' is in progress", rollout.name),
+            ));
+        }
+        let svc = registry
+            .service(svc_id)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unknown service"))?;
+        let name = instance_of(&svc.name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "'{}' isn't an instance of a replicated service, named <name>@<instance>",
+                    svc.name
+                ),
+            )
+        })?;
+        let mut instances: Vec<_> = registry
+            .services()
+            .filter(|svc| instance_of(&svc.name) == Some(name))
+            .collect();
+        instances.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        svlogg!(
+            LogLevel::Info,
+            "rolling restart of '{}', {} instances",
+            name,
+            instances.len()
+        );
+        self.rollout = Some(Rollout {
+            name: name.to_owned(),
+            state: RolloutState::Running,
+            total: instances.len(),
+            pending: instances.into_iter().map(|svc| svc.id).collect(),
+            current: None,
+            restarted: 0,
+        });
+        Ok(())
+    }
+
+    /// Move the rolling restart on, on each timerfd tick: check whether the
+    /// instance being restarted is ready, and restart the next one if so
+    pub(crate) fn step(
+        &mut self,
+        registry: &mut ServiceRegistry,
+        ctx: &SpawnContext,
+        ps_dir: &Path,
+    ) {
+        let Some(rollout) = self
+            .rollout
+            .as_mut()
+            .filter(|rollout| rollout.state == RolloutState::Running)
+        else {
+            return;
+        };
+        let now = Instant::now();
+        if let Some(current) = &rollout.current {
+            let ready = match registry.service(current.svc_id) {
+                Some(svc) => check_ready(svc, current.start_count, current.since, now),
+                None => Err("was removed".to_owned()),
+            };
+            match ready {
+                Ok(false) => return,
+                Ok(true) => {
+                    svlogg!(LogLevel::Info, "instance '{}' is ready", current.name);
+                    rollout.restarted += 1;
+                    rollout.current = None;
+                }
+                Err(e) => {
+                    svlogg!(
+                        LogLevel::Error,
+                        "rolling restart of '{}' aborted, {} of {} instances restarted: '{}' {}",
+                        rollout.name,
+                        rollout.restarted,
+                        rollout.total,
+                        current.name,
+                        e
+                    );
+                    rollout.state = RolloutState::Aborted;
+                    return;
+                }
+            }
+        }
+        // instances removed by a reload in the meantime are skipped
+        while let Some(svc_id) = rollout.pending.pop_front() {
+            let Some(svc) = registry.service(svc_id) else {
+                rollout.total -= 1;
+                continue;
+            };
+            let current = Restarting {
+                svc_id,
+                name: svc.name.clone(),
+                start_count: svc.start_count,
+                since: now,
+            };
+            if let Err(e) = apply_control_op(registry, svc_id, ControlOp::Restart, ctx, ps_dir) {
+                svlogg!(
+                    LogLevel::Error,
+                    "rolling restart of '{}' aborted: failed to restart '{}': {}",
+                    rollout.name,
+                    current.name,
+                    e
+                );
+                rollout.state = RolloutState::Aborted;
+                return;
+            }
+            rollout.current = Some(current);
+            return;
+        }
+        svlogg!(
+            LogLevel::Info,
+            "rolling restart of '{}' done, {} instances restarted",
+            rollout.name,
+            rollout.restarted
+        );
+        rollout.state = RolloutState::Done;
+    }
+
+    /// Rewrite the rollout file if the rolling restart moved on
+    pub(crate) fn flush(&mut self) {
+        let Some(rollout) = &self.rollout else {
+            return;
+        };
+        let _ = writeln!(
+            self.file.buf(),
+            "{} {} {}/{} {}",
+            rollout.name,
+            rollout.state,
+            rollout.restarted,
+            rollout.total,
+            rollout
+                .current
+                .as_ref()
+                .map_or("-", |current| &current.name)
+        );
+        if let Err(e) = self.file.flush() {
+            svlogg!(LogLevel::Error, "failed to write rollout file: {}", e);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{collections::HashSet, io, path::Path, sync::Arc};
+
+use svlopp::restore_mode;
+
+use crate::control::ControlOp;
+use crate::logging::LogLevel;
+use crate::service::{Service, ServiceRegistry, ServiceState, SpawnContext, apply_control_op};
+use crate::status::{StatusFilePath, write_status_file};
+use crate::svlogg;
+
+/// Save the names of the running services to `path`, one per line,
+/// returning how many there are
+pub(crate) fn write_run_set(
+    registry: &ServiceRegistry,
+    path: &StatusFilePath,
+) -> io::Result<usize> {
+    let mut names: Vec<&str> = registry
+        .services()
+        .filter(|svc| {
+            matches!(
+                svc.state,
+                ServiceState::Running(_) | ServiceState::Paused(_)
+            )
+        })
+        .map(|svc| &*svc.name)
+        .collect();
+    names.sort_unstable();
+    let mut content = String::new();
+    for name in &names {
+        content.push_str(name);
+        content.push('\n');
+    }
+    write_status_file(path, content.as_bytes())?;
+    Ok(names.len())
+}
+
+/// Number of `bind_to` hops from `svc` to a service that isn't bound
+fn bind_depth(registry: &ServiceRegistry, svc: &Service) -> usize {
+    let mut depth = 0;
+    let mut current = svc;
+    while let Some(target) = current.config.bind_to.as_deref() {
+        let Some(next) = registry.get_by_name(target) else {
+            break;
+        };
+        depth += 1;
+        current = next;
+    }
+    depth
+}
+
+/// Start the services saved to `path` by `write_run_set` that are not
+/// running, targets before the services bound to them. With
+/// `restore_mode::EXACT`, running services that are not in the set are
+/// stopped too.
+///
+/// The services are started as by the `start` control operation, and a
+/// service that fails to start doesn't prevent the others from starting
+pub(crate) fn restore_run_set(
+    registry: &mut ServiceRegistry,
+    mode: u64,
+    path: &Path,
+    ctx: &SpawnContext,
+    ps_dir: &Path,
+) -> io::Result<()> {
+    let exact = match mode {
+        restore_mode::START => false,
+        restore_mode::EXACT => true,
+        other => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown restore mode {}", other),
+            ));
+        }
+    };
+    let content = std::fs::read_to_string(path)?;
+    let names: HashSet<&str> = content.lines().filter(|l| !l.is_empty()).collect();
+    for name in &names {
+        if registry.get_by_name(name).is_none() {
+            svlogg!(LogLevel::Warn, "run set service '{}' does not exist", name);
+        }
+    }
+
+    let mut ops: Vec<(usize, Arc<str>, u64, ControlOp)> = Vec::new();
+    for svc in registry.services() {
+        let running = matches!(
+            svc.state,
+            ServiceState::Running(_) | ServiceState::Paused(_)
+        );
+        let op = match (names.contains(&*svc.name), running) {
+            (true, false) => ControlOp::Start,
+            (false, true) if exact => ControlOp::Stop,
+            _ => continue,
+        };
+        ops.push((bind_depth(registry, svc), Arc::clone(&svc.name), svc.id, op));
+    }
+    ops.sort_unstable();
+
+    for (_, name, svc_id, op) in ops {
+        if let Err(e) = apply_control_op(registry, svc_id, op, ctx, ps_dir) {
+            svlogg!(
+                LogLevel::Error,
+                "failed to {} service '{}': {}",
+                op,
+                name,
+                e
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,62 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{collections::HashMap, io, path::Path};
+
+use crate::hooks::Hook;
+use crate::logging::LogLevel;
+use crate::service::{ServiceConfig, ServicePendingAction};
+use crate::svlogg;
+
+/// Read the services of a runit/daemontools style service directory.
+///
+/// Each subdirectory `<dir>/<name>` containing a `run` file is a service
+/// named `<name>`, running `run` from the subdirectory and restarted
+/// whenever it exits:
+/// - an optional `finish` file is run after `run` exits, with the exit
+///   code and the signal number as arguments
+/// - an optional `down` file keeps the service from being started
+///   automatically
+pub(crate) fn scan_services(dir: &Path) -> io::Result<HashMap<String, ServiceConfig>> {
+    // services change to their directory, so `run` must not be relative
+    let dir = std::path::absolute(dir)?;
+    let mut services = HashMap::new();
+    for entry in std::fs::read_dir(&dir)? {
+        let entry = entry?;
+        let Ok(name) = entry.file_name().into_string() else {
+            svlogg!(
+                LogLevel::Warn,
+                "skipping non utf-8 service directory {:?}",
+                entry.file_name()
+            );
+            continue;
+        };
+        let svc_dir = entry.path();
+        if name.starts_with('.') || !svc_dir.is_dir() {
+            continue;
+        }
+        let run = svc_dir.join("run");
+        if !run.is_file() {
+            svlogg!(
+                LogLevel::Debug,
+                "skipping '{}', it has no run file",
+                svc_dir.display()
+            );
+            continue;
+        }
+        let mut cfg = ServiceConfig::new(run.to_string_lossy().into_owned());
+        cfg.fallback_pending_action = ServicePendingAction::Restart;
+        cfg.autostart = !svc_dir.join("down").exists();
+        let finish = svc_dir.join("finish");
+        if finish.is_file() {
+            cfg.finish = Some(Hook {
+                command: finish.to_string_lossy().into_owned(),
+                args: Vec::new(),
+            });
+        }
+        cfg.working_directory = Some(svc_dir);
+        services.insert(name, cfg);
+    }
+    Ok(services)
+}
@@ -0,0 +1,64 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    io,
+    os::fd::{AsFd, AsRawFd, OwnedFd},
+    path::PathBuf,
+};
+
+use rustix::fs::{MemfdFlags, SealFlags, SeekFrom, fcntl_add_seals, memfd_create, seek};
+use rustix::io::fcntl_dupfd_cloexec;
+use serde::Deserialize;
+
+use crate::utils::write_all;
+
+/// Lowest fd number for secrets, so that they don't end up on the stdio
+/// fds the service process gets
+const MIN_SECRET_FD: i32 = 3;
+
+/// A secret handed to the service process as an inherited memfd, instead
+/// of through its environment or a file it can read.
+///
+/// The secret is read from `path` by the supervisor on each start, before
+/// dropping privileges, and copied to a sealed memfd whose fd number is
+/// exported in the `env` variable
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct Secret {
+    pub(crate) env: String,
+    pub(crate) path: PathBuf,
+}
+
+impl Secret {
+    pub(crate) fn validate(&self) -> io::Result<()> {
+        if self.env.is_empty() || self.env.contains(['=', '\0']) {
+            return Err(io::Error::other(format!(
+                "invalid secret variable name '{}'",
+                self.env
+            )));
+        }
+        Ok(())
+    }
+
+    /// Create the memfd holding the secret, sealed against any change and
+    /// positioned at its start. It is `O_CLOEXEC`, the service process
+    /// must clear the flag to inherit it
+    pub(crate) fn open(&self) -> io::Result<OwnedFd> {
+        let content = std::fs::read(&self.path)?;
+        let memfd = memfd_create(
+            "svlopp-secret",
+            MemfdFlags::CLOEXEC | MemfdFlags::ALLOW_SEALING,
+        )?;
+        write_all(memfd.as_fd(), &content)?;
+        fcntl_add_seals(
+            &memfd,
+            SealFlags::SHRINK | SealFlags::GROW | SealFlags::WRITE | SealFlags::SEAL,
+        )?;
+        seek(&memfd, SeekFrom::Start(0))?;
+        if memfd.as_raw_fd() >= MIN_SECRET_FD {
+            return Ok(memfd);
+        }
+        Ok(fcntl_dupfd_cloexec(&memfd, MIN_SECRET_FD)?)
+    }
+}
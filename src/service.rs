@@ -6,40 +6,122 @@ use std::ffi::CString;
 use std::fmt;
 use std::io;
 use std::os::fd::AsFd;
+use std::os::fd::AsRawFd;
 use std::os::fd::BorrowedFd;
+use std::os::fd::OwnedFd;
+use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    hash::{DefaultHasher, Hash, Hasher},
+    num::{NonZeroU8, NonZeroU32, NonZeroU64, NonZeroUsize},
     time::{Duration, Instant},
 };
 
 use rustix::{
+    event::epoll,
     fs::{Mode, OFlags, open},
+    pipe::{PipeFlags, pipe_with},
     process::{
-        Pid, Signal, WaitOptions, WaitStatus, chdir, kill_process, kill_process_group, setpgid,
-        wait,
+        Pid, PidfdFlags, Signal, WaitOptions, WaitStatus, kill_process, kill_process_group,
+        pidfd_open, pidfd_send_signal, wait, waitpid,
     },
-    stdio::{dup2_stderr, dup2_stdin, dup2_stdout},
 };
 use serde::Deserialize;
+use svlopp::calendar::CalendarSpec;
+use svlopp::snapshot::{
+    RecordFlags, RecordState, ServiceRecordRef, StopReasonKind, encode_snapshot,
+};
 
+use crate::adopt::has_exited;
+use crate::alerts::{Alert, Alerts};
+use crate::cgroup::{IoMax, ServiceCgroup, ServiceCgroups};
 use crate::control::ControlOp;
+use crate::diagnostics::{CpuTime, ServiceHistory, write_bundle};
+use crate::envfile::{EnvFileChange, EnvVars, read_env_file};
+use crate::hooks::{Hook, hook_command};
+use crate::init::{ForwardedSignal, default_forward_signals};
+use crate::jobs::{self, JobDecision, JobKind, JobLimits, JobScheduler};
+use crate::keyring::KeyringMode;
+use crate::lint::Lint;
 use crate::logging::LogLevel;
-use crate::svlogg;
-use crate::utils::cvt;
-use crate::{
-    signalfd::{SigSet, set_thread_signal_mask},
-    utils::is_crash_signal,
+use crate::logpump::{
+    LogMultiline, LogPrefix, LogPump, LogPumpOptions, LogRateLimit, open_log_file,
+};
+use crate::logrotate::LogRotate;
+use crate::mounts::{BindMount, MountSpec, PrivateTmp};
+use crate::notify::ready_socket;
+use crate::oom::OomDetector;
+use crate::orphans::{
+    ChildState, OrphanOrigin, OrphanPolicy, OrphanTracker, peek_child, peek_exited_child,
+};
+use crate::platform::Platform;
+use crate::ports::{ListenPort, check_ports};
+use crate::pressure::{Cgroups, ServicePressure};
+use crate::procfs::{
+    ProcStat, ProcessTable, fd_count, format_process_tree, kill_survivors, nofile_limit,
+    read_cgroup,
 };
+use crate::properties::Property;
+use crate::reload::ReloadPolicy;
+use crate::resources::{ResourceAction, ResourceLimits, ResourceMonitor, ResourceUsage};
+use crate::restarts::{MAX_RECORDED_FAILURES, RestartRecord, StartLimit};
+use crate::scandir::scan_services;
+use crate::secrets::Secret;
+use crate::slab::ServiceSlab;
+use crate::spawn::{ExecSpec, SpawnError, SpawnStep, SpawnStrategy, Spawned};
+use crate::spawner::Spawner;
+use crate::status::{StatusFilePath, write_status_file};
+use crate::svlogg;
+use crate::timer::{LATE_AFTER_SECS, OverlapPolicy, Timer, TimerClock, TimerConfig, TimerRecord};
+use crate::userns::UserNamespace;
+use crate::utils::{retry_eintr, timestamp};
+use crate::webhooks::Webhook;
+use crate::{signalfd::SigSet, utils::is_crash_signal};
+
+/// Tag bit marking an epoll event id as the exec pipe of a service, the
+/// remaining bits hold the service id
+pub(crate) const EXEC_ID_TAG: u64 = 1 << 61;
 
 /// Default graceful shutdown timeout in milliseconds
 const DEFAULT_STOP_TIMEOUT_MS: u64 = 5000;
 
+/// Default number of queued services started per main loop iteration
+const DEFAULT_START_CONCURRENCY: NonZeroUsize = NonZeroUsize::new(16).expect("non-zero");
+
+/// Environment variables injected into every service process so that
+/// it can identify itself when running under svlopp. They take precedence
+/// over both the inherited environment and the service `env` table
+const ENV_SERVICE_NAME: &str = "SVLOPP_SERVICE_NAME";
+const ENV_SERVICE_ID: &str = "SVLOPP_SERVICE_ID";
+const ENV_RESTART_COUNT: &str = "SVLOPP_RESTART_COUNT";
+/// Path of the readiness socket, see `ReadyListener`
+const ENV_NOTIFY_SOCKET: &str = "SVLOPP_NOTIFY_SOCKET";
+
+/// Variables of the `sd_notify(3)` protocol svlopp may be started with,
+/// left out of the environment services inherit: they address svlopp's
+/// own supervisor, which would take the notifications of any process of
+/// the services for svlopp's
+const SYSTEMD_NOTIFY_ENV: [&str; 3] = ["NOTIFY_SOCKET", "WATCHDOG_USEC", "WATCHDOG_PID"];
+
+/// `PATH` and `LANG` of service processes with `clear_env`, unless pinned
+/// or set in `env`
+const CLEAR_ENV_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+const CLEAR_ENV_LANG: &str = "C";
+
+/// What the values hidden by `redact` are shown as
+const REDACTED: &str = "***";
+
 fn default_stop_timeout_ms() -> u64 {
     DEFAULT_STOP_TIMEOUT_MS
 }
 
+fn default_autostart() -> bool {
+    true
+}
+
 /// Process exit reason.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub(crate) enum ExitReason {
@@ -86,6 +168,14 @@ pub(crate) enum ServiceStopReason {
     /// Service has been gracefully terminated by
     /// the supervisor
     SupervisorTerminated(ExitReason),
+    /// Service has been gracefully terminated by
+    /// the supervisor because the service it is
+    /// bound to (`bind_to`) stopped
+    BoundStopped(ExitReason),
+    /// Service has been terminated by the supervisor
+    /// because it ran for longer than its
+    /// `runtime_max_ms`
+    RuntimeExceeded(ExitReason),
     /// Service successfully completed (i.e.
     /// exited with code == 0)
     Success,
@@ -100,6 +190,20 @@ pub(crate) enum ServiceStopReason {
     /// leading to either `Self::Success` or
     /// `Self::Error(code)`
     Killed(i32),
+    /// Service killed by the kernel OOM killer
+    OomKilled,
+    /// Service process vanished without being reaped
+    /// by the supervisor, e.g. after a missed `SIGCHLD`
+    Lost,
+    /// Adopted service process exited, with an exit status the supervisor
+    /// can't know, not being its parent
+    Exited,
+    /// Service process detached from supervision, left running with the
+    /// given pid
+    Detached(i32),
+    /// Service process failed to be set up or to
+    /// exec its command
+    SpawnFailed(SpawnError),
 }
 
 impl fmt::Display for ServiceStopReason {
@@ -107,15 +211,38 @@ impl fmt::Display for ServiceStopReason {
         match self {
             Self::NeverStarted => write!(f, "never_started"),
             Self::SupervisorTerminated(er) => write!(f, "supervisor_terminated({})", er),
+            Self::BoundStopped(er) => write!(f, "bound_stopped({})", er),
+            Self::RuntimeExceeded(er) => write!(f, "runtime_exceeded({})", er),
             Self::Success => write!(f, "success"),
             Self::Error(e) => write!(f, "error({})", e),
             Self::Crashed(s) => write!(f, "crashed({})", s),
             Self::Killed(s) => write!(f, "killed({})", s),
+            Self::OomKilled => write!(f, "oom_killed"),
+            Self::Lost => write!(f, "lost"),
+            Self::Exited => write!(f, "exited"),
+            Self::Detached(pid) => write!(f, "detached({})", pid),
+            Self::SpawnFailed(e) => write!(f, "spawn_failed({})", e.errno),
         }
     }
 }
 
 impl ServiceStopReason {
+    /// Whether the service process failed, on its own or by exceeding
+    /// its maximum runtime
+    #[inline(always)]
+    pub(crate) fn is_failure(&self) -> bool {
+        matches!(
+            self,
+            Self::RuntimeExceeded(_)
+                | Self::Error(_)
+                | Self::Crashed(_)
+                | Self::Killed(_)
+                | Self::OomKilled
+                | Self::Lost
+                | Self::SpawnFailed(_)
+        )
+    }
+
     pub(crate) fn from_exit_reason_and_service_state(
         exit_reason: ExitReason,
         svc_state: ServiceState,
@@ -141,6 +268,32 @@ impl ServiceStopReason {
             }
         }
     }
+
+    /// Exit code a shell would report for the service process, `128 + n`
+    /// for processes terminated by signal `n`
+    pub(crate) fn exit_code(&self) -> i32 {
+        match self {
+            Self::NeverStarted | Self::Lost => 1,
+            Self::SupervisorTerminated(ExitReason::Exited(code))
+            | Self::BoundStopped(ExitReason::Exited(code))
+            | Self::RuntimeExceeded(ExitReason::Exited(code))
+            | Self::Error(code) => *code,
+            Self::Success | Self::Exited | Self::Detached(_) => 0,
+            Self::SupervisorTerminated(ExitReason::Signaled(sig))
+            | Self::BoundStopped(ExitReason::Signaled(sig))
+            | Self::RuntimeExceeded(ExitReason::Signaled(sig))
+            | Self::Crashed(sig)
+            | Self::Killed(sig) => 128 + sig,
+            Self::OomKilled => 128 + Signal::KILL.as_raw(),
+            // what a shell reports for a command it couldn't run
+            Self::SpawnFailed(e) if e.is_not_found() => 127,
+            Self::SpawnFailed(SpawnError {
+                step: SpawnStep::Exec,
+                ..
+            }) => 126,
+            Self::SpawnFailed(_) => 111,
+        }
+    }
 }
 
 /// All possible states in which a service
@@ -158,8 +311,16 @@ pub(crate) enum ServiceState {
     /// Services are put in this state after
     /// a stop request, which can be issued
     /// from different actors and in
-    /// different forms
+    /// different forms.
+    /// The kill deadline is measured on
+    /// `CLOCK_MONOTONIC`, which doesn't advance
+    /// while the system is suspended, so that
+    /// it doesn't expire right after a resume
     Stopping(Pid, Instant),
+    /// The service process is frozen by the `pause`
+    /// control operation, until `resume`.
+    /// Stopping it thaws it first
+    Paused(Pid),
 }
 
 impl Default for ServiceState {
@@ -174,6 +335,40 @@ impl fmt::Display for ServiceState {
             Self::Stopped(r) => write!(f, "stopped {}", r),
             Self::Running(p) => write!(f, "running {}", p.as_raw_nonzero()),
             Self::Stopping(p, _) => write!(f, "stopping {}", p.as_raw_nonzero()),
+            Self::Paused(p) => write!(f, "paused {}", p.as_raw_nonzero()),
+        }
+    }
+}
+
+/// What starting a service does about the running services it conflicts
+/// with
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum ConflictPolicy {
+    /// Stop them, and start the service once they have all stopped
+    #[default]
+    Stop,
+    /// Refuse to start the service
+    Refuse,
+}
+
+/// What a failure of the service a service is bound to does to it
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum TargetFailurePolicy {
+    /// Stop it, and start it again once that service runs again
+    #[default]
+    Restart,
+    /// Stop it, until it's started explicitly
+    Stop,
+    /// Leave it running
+    Keep,
+}
+
+impl fmt::Display for TargetFailurePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Restart => write!(f, "Restart"),
+            Self::Stop => write!(f, "Stop"),
+            Self::Keep => write!(f, "Keep"),
         }
     }
 }
@@ -214,6 +409,39 @@ pub(crate) struct UserGroup {
     pub(crate) gid: u32,
 }
 
+/// Variables set in the environment of every service process, whatever
+/// the environment svlopp itself was started with, the `pinned_env`
+/// supervisor option. The `env` of a service still takes precedence
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq)]
+pub(crate) struct PinnedEnv {
+    #[serde(rename = "TZ")]
+    pub(crate) tz: Option<String>,
+    #[serde(rename = "LANG")]
+    pub(crate) lang: Option<String>,
+    #[serde(rename = "PATH")]
+    pub(crate) path: Option<String>,
+}
+
+impl PinnedEnv {
+    /// The pinned variables, with the `clear_env` defaults of those that
+    /// aren't pinned if `clear_env`
+    fn vars(&self, clear_env: bool) -> Vec<(&'static str, &str)> {
+        [
+            ("TZ", self.tz.as_deref(), None),
+            ("LANG", self.lang.as_deref(), Some(CLEAR_ENV_LANG)),
+            ("PATH", self.path.as_deref(), Some(CLEAR_ENV_PATH)),
+        ]
+        .into_iter()
+        .filter_map(|(key, pinned, default)| Some((key, pinned.or(default.filter(|_| clear_env))?)))
+        .collect()
+    }
+}
+
+/// Whether the environment `entry` sets `key`
+fn env_entry_is(entry: &[u8], key: &str) -> bool {
+    entry.len() > key.len() && entry.starts_with(key.as_bytes()) && entry[key.len()] == b'='
+}
+
 /// Service configuration
 #[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
 pub(crate) struct ServiceConfig {
@@ -225,25 +453,100 @@ pub(crate) struct ServiceConfig {
     /// Optional environment to replace the parent one
     #[serde(default)]
     pub(crate) env: Option<HashMap<String, String>>,
+    /// Optional file of `KEY=VALUE` lines read on each start, whose
+    /// variables are added to the environment, `env` taking precedence
+    #[serde(default)]
+    pub(crate) env_file: Option<PathBuf>,
+    /// What to do when `env_file` changes while the service runs
+    #[serde(default)]
+    pub(crate) on_env_file_change: EnvFileChange,
+    /// Whether the service process starts from a minimal environment
+    /// rather than svlopp's one, `env` being added to it
+    #[serde(default)]
+    pub(crate) clear_env: bool,
+    /// Options of `args` and keys of `env` whose values are masked
+    /// wherever svlopp shows them, the service process still getting them
+    #[serde(default)]
+    pub(crate) redact: Vec<String>,
+    /// The `pinned_env` supervisor option, set once the config is loaded
+    #[serde(skip)]
+    pub(crate) pinned_env: PinnedEnv,
     /// Optional working directory for the service process.
     /// If `None` the service inherits the current working directory
     #[serde(default)]
     pub(crate) working_directory: Option<PathBuf>,
+    /// Optional directory the service process is chrooted to, before
+    /// `working_directory` and `command` are resolved in it
+    #[serde(default)]
+    pub(crate) root_dir: Option<PathBuf>,
+    /// Directories of the host bind mounted in a private mount namespace
+    /// of the service process
+    #[serde(default)]
+    pub(crate) mounts: Vec<BindMount>,
+    /// Whether the service process gets its own `/tmp`, removed once it
+    /// exits
+    #[serde(default)]
+    pub(crate) private_tmp: bool,
+    /// Optional size of the tmpfs of `private_tmp`
+    #[serde(default)]
+    pub(crate) private_tmp_size_mib: Option<NonZeroU64>,
+    /// Whether the service process runs in a cgroup of its own, delegated
+    /// to its user so that it can manage sub-cgroups
+    #[serde(default)]
+    pub(crate) cgroup_delegate: bool,
+    /// I/O limits of the service per block device, the service process
+    /// then running in a cgroup of its own
+    #[serde(default)]
+    pub(crate) io_max: Vec<IoMax>,
     /// Optional file to redirect the service `stdout` and
     /// `stderr` to.
     /// If `None` they are piped to `/dev/null`
     #[serde(default)]
     pub(crate) log_file_path: Option<PathBuf>,
+    /// Optional prefixes for each line written to `log_file_path`.
+    /// If `Some`, the service output goes through the log pump instead
+    /// of being written to the log file directly
+    #[serde(default)]
+    pub(crate) log_prefix: Option<LogPrefix>,
+    /// Optional coalescing of multi-line records (e.g. stack traces)
+    /// written to `log_file_path`. As `log_prefix`, it enables the
+    /// log pump
+    #[serde(default)]
+    pub(crate) log_multiline: Option<LogMultiline>,
+    /// Optional cap on the number of records written to `log_file_path`
+    /// per interval, excess records are dropped and counted. It enables
+    /// the log pump
+    #[serde(default)]
+    pub(crate) log_rate_limit: Option<LogRateLimit>,
+    /// Optional size based rotation of `log_file_path`, with optional
+    /// compression of rotated files. It enables the log pump
+    #[serde(default)]
+    pub(crate) log_rotate: Option<LogRotate>,
     /// Optional `uid` and `gid` for the service process.
     #[serde(default)]
     pub(crate) user_group: Option<UserGroup>,
+    /// Optional user namespace the service process runs in, `user_group`
+    /// then being ids of the namespace
+    #[serde(default)]
+    pub(crate) user_namespace: Option<UserNamespace>,
+    /// Optional SELinux context the service process execs with
+    #[serde(default)]
+    pub(crate) selinux_context: Option<String>,
+    /// Optional AppArmor profile the service process execs with
+    #[serde(default)]
+    pub(crate) apparmor_profile: Option<String>,
+    /// The session keyring of the service process, see
+    /// `KeyringMode::default_for` when unset
+    #[serde(default)]
+    pub(crate) keyring: Option<KeyringMode>,
     /// Fallback pending action to take.
     /// This allows to define restart behavior (e.g.
     /// if a service exits, restart it), but only
     /// as a fallback to `Service::pending_action` as
     /// reload takes precedence.
     /// This is ignored if a service is stopped with
-    /// reason `ServiceStopReason::SupervisorTerminated`
+    /// reason `ServiceStopReason::SupervisorTerminated` or
+    /// `ServiceStopReason::BoundStopped`
     #[serde(rename = "on_exit")]
     #[serde(default)]
     pub(crate) fallback_pending_action: ServicePendingAction,
@@ -256,9 +559,402 @@ pub(crate) struct ServiceConfig {
     /// stopping the service. Defaults to 5000
     #[serde(default = "default_stop_timeout_ms")]
     pub(crate) stop_timeout_ms: u64,
+    /// Optional name of a service this one is bound to: it is only
+    /// started once that service is running, and stopped whenever it
+    /// stops
+    #[serde(default)]
+    pub(crate) bind_to: Option<String>,
+    /// What a failure of the `bind_to` service does to this one. Defaults
+    /// to `Restart`
+    #[serde(default)]
+    pub(crate) on_target_failure: TargetFailurePolicy,
+    /// Services that must not run along with this one, e.g. another
+    /// version of the same daemon. Conflicts go both ways
+    #[serde(default)]
+    pub(crate) conflicts_with: Vec<String>,
+    /// Optional name of a group of services of which only one runs at a
+    /// time
+    #[serde(default)]
+    pub(crate) mutex_group: Option<String>,
+    /// What starting the service does about the running services it
+    /// conflicts with. Defaults to `Stop`
+    #[serde(default)]
+    pub(crate) on_conflict: ConflictPolicy,
+    /// Whether to kill the descendants of the service process that
+    /// survive it, even outside of its process group
+    #[serde(default)]
+    pub(crate) kill_descendants: bool,
+    /// Optional limit on automatic restarts of a failing service
+    #[serde(default)]
+    pub(crate) start_limit: Option<StartLimit>,
+    /// Whether the service is started along with the supervisor and when
+    /// added by a reload. If `false`, it's only started explicitly.
+    /// Defaults to `true`
+    #[serde(default = "default_autostart")]
+    pub(crate) autostart: bool,
+    /// Optional command run in the background after the service process
+    /// exits, with the exit code (`-1` if signaled) and the signal number
+    /// (`0` if exited) appended to its arguments. Pending actions wait for
+    /// it to exit
+    #[serde(default)]
+    pub(crate) finish: Option<Hook>,
+    /// Secrets handed to the service process as sealed memfds
+    #[serde(default)]
+    pub(crate) secrets: Vec<Secret>,
+    /// Optional maximum runtime in milliseconds, after which the service
+    /// is stopped as with `stop_signal` and `stop_timeout_ms`
+    #[serde(default)]
+    pub(crate) runtime_max_ms: Option<u64>,
+    /// Optional limits on the memory and CPU used by the service process
+    /// tree, with the action taken when they are exceeded
+    #[serde(default)]
+    pub(crate) resource_limits: Option<ResourceLimits>,
+    /// Optional share of its open file limit, in percent, above which the
+    /// service process is flagged with `fds_high`
+    #[serde(default)]
+    pub(crate) fd_warn_percent: Option<NonZeroU8>,
+    /// Ports the service listens on, checked to be free before each start
+    #[serde(default)]
+    pub(crate) ports: Vec<ListenPort>,
+    /// Optional timer starting the service at the times of a calendar
+    /// expression
+    #[serde(default)]
+    pub(crate) timer: Option<TimerConfig>,
 }
 
 impl ServiceConfig {
+    /// Config of a service running `command`, with defaults for all the
+    /// other fields
+    pub(crate) fn new(command: String) -> Self {
+        Self {
+            command,
+            args: Vec::new(),
+            env: None,
+            env_file: None,
+            on_env_file_change: EnvFileChange::default(),
+            clear_env: false,
+            redact: Vec::new(),
+            pinned_env: PinnedEnv::default(),
+            working_directory: None,
+            root_dir: None,
+            mounts: Vec::new(),
+            private_tmp: false,
+            private_tmp_size_mib: None,
+            cgroup_delegate: false,
+            io_max: Vec::new(),
+            log_file_path: None,
+            log_prefix: None,
+            log_multiline: None,
+            log_rate_limit: None,
+            log_rotate: None,
+            user_group: None,
+            user_namespace: None,
+            selinux_context: None,
+            apparmor_profile: None,
+            keyring: None,
+            fallback_pending_action: ServicePendingAction::None,
+            stop_signal: StopSignal::default(),
+            stop_timeout_ms: default_stop_timeout_ms(),
+            bind_to: None,
+            on_target_failure: TargetFailurePolicy::default(),
+            conflicts_with: Vec::new(),
+            mutex_group: None,
+            on_conflict: ConflictPolicy::default(),
+            kill_descendants: false,
+            start_limit: None,
+            autostart: default_autostart(),
+            finish: None,
+            secrets: Vec::new(),
+            runtime_max_ms: None,
+            resource_limits: None,
+            fd_warn_percent: None,
+            ports: Vec::new(),
+            timer: None,
+        }
+    }
+
+    /// Names of the fields, as in the config file, whose value differs in
+    /// `other`
+    pub(crate) fn changed_fields(&self, other: &Self) -> Vec<&'static str> {
+        macro_rules! changed {
+            ($($field:ident => $key:literal),* $(,)?) => {{
+                // fails to compile when a field isn't compared
+                let Self { $($field: _),* } = self;
+                let mut fields = Vec::new();
+                $(
+                    if self.$field != other.$field {
+                        fields.push($key);
+                    }
+                )*
+                fields
+            }};
+        }
+        changed!(
+            command => "command",
+            args => "args",
+            env => "env",
+            env_file => "env_file",
+            on_env_file_change => "on_env_file_change",
+            clear_env => "clear_env",
+            redact => "redact",
+            pinned_env => "pinned_env",
+            working_directory => "working_directory",
+            root_dir => "root_dir",
+            mounts => "mounts",
+            private_tmp => "private_tmp",
+            private_tmp_size_mib => "private_tmp_size_mib",
+            cgroup_delegate => "cgroup_delegate",
+            io_max => "io_max",
+            log_file_path => "log_file_path",
+            log_prefix => "log_prefix",
+            log_multiline => "log_multiline",
+            log_rate_limit => "log_rate_limit",
+            log_rotate => "log_rotate",
+            user_group => "user_group",
+            user_namespace => "user_namespace",
+            selinux_context => "selinux_context",
+            apparmor_profile => "apparmor_profile",
+            keyring => "keyring",
+            fallback_pending_action => "on_exit",
+            stop_signal => "stop_signal",
+            stop_timeout_ms => "stop_timeout_ms",
+            bind_to => "bind_to",
+            on_target_failure => "on_target_failure",
+            conflicts_with => "conflicts_with",
+            mutex_group => "mutex_group",
+            on_conflict => "on_conflict",
+            kill_descendants => "kill_descendants",
+            start_limit => "start_limit",
+            autostart => "autostart",
+            timer => "timer",
+            finish => "finish",
+            secrets => "secrets",
+            runtime_max_ms => "runtime_max_ms",
+            resource_limits => "resource_limits",
+            fd_warn_percent => "fd_warn_percent",
+            ports => "ports",
+        )
+    }
+
+    /// Hash of the fields, telling apart configs that `changed_fields`
+    /// would. Only meant to be compared within a run of svlopp
+    pub(crate) fn config_hash(&self) -> u64 {
+        macro_rules! hash {
+            ($($field:ident),* $(,)?) => {{
+                // fails to compile when a field isn't hashed
+                let Self { env, $($field: _),* } = self;
+                let mut hasher = DefaultHasher::new();
+                // sorted, as the iteration order of maps is random
+                env.as_ref()
+                    .map(|env| env.iter().collect::<BTreeMap<_, _>>())
+                    .hash(&mut hasher);
+                $(
+                    format!("{:?}", self.$field).hash(&mut hasher);
+                )*
+                hasher.finish()
+            }};
+        }
+        hash!(
+            command,
+            args,
+            env_file,
+            on_env_file_change,
+            clear_env,
+            redact,
+            pinned_env,
+            working_directory,
+            root_dir,
+            mounts,
+            private_tmp,
+            private_tmp_size_mib,
+            cgroup_delegate,
+            io_max,
+            log_file_path,
+            log_prefix,
+            log_multiline,
+            log_rate_limit,
+            log_rotate,
+            user_group,
+            user_namespace,
+            selinux_context,
+            apparmor_profile,
+            keyring,
+            fallback_pending_action,
+            stop_signal,
+            stop_timeout_ms,
+            bind_to,
+            on_target_failure,
+            conflicts_with,
+            mutex_group,
+            on_conflict,
+            kill_descendants,
+            start_limit,
+            autostart,
+            timer,
+            finish,
+            secrets,
+            runtime_max_ms,
+            resource_limits,
+            fd_warn_percent,
+            ports,
+        )
+    }
+
+    /// Check that a service named `name` can be built from this config,
+    /// as `Service::new` would, but for what depends on the host, see
+    /// `check_host`
+    pub(crate) fn check(&self, name: &str) -> io::Result<()> {
+        validate_service_name(name)?;
+        self.validate()?;
+        self.build_svc_argv()?;
+        self.build_svc_envp()?;
+        Ok(())
+    }
+
+    /// Check constraints that can't be expressed by the config types
+    fn validate(&self) -> io::Result<()> {
+        if let Some(rotate) = &self.log_rotate {
+            rotate.validate()?;
+        }
+        if let Some(limit) = &self.start_limit {
+            limit.validate()?;
+        }
+        for secret in &self.secrets {
+            secret.validate()?;
+        }
+        if let Some(limits) = &self.resource_limits {
+            limits.validate()?;
+        }
+        if let Some(userns) = &self.user_namespace {
+            userns.validate()?;
+        }
+        for mount in &self.mounts {
+            mount.validate()?;
+        }
+        for limit in &self.io_max {
+            limit.validate()?;
+        }
+        if let Some(timer) = &self.timer {
+            timer.validate()?;
+        }
+        if let Some(path) = &self.env_file
+            && !path.is_absolute()
+        {
+            return Err(io::Error::other(format!(
+                "env_file '{}' is not an absolute path",
+                path.display()
+            )));
+        }
+        if let Some(percent) = self.fd_warn_percent
+            && percent.get() > 100
+        {
+            return Err(io::Error::other(format!(
+                "fd_warn_percent {} is above 100",
+                percent
+            )));
+        }
+        if self.selinux_context.is_some() && self.apparmor_profile.is_some() {
+            return Err(io::Error::other(
+                "selinux_context and apparmor_profile are mutually exclusive",
+            ));
+        }
+        for (key, label) in [
+            ("selinux_context", &self.selinux_context),
+            ("apparmor_profile", &self.apparmor_profile),
+        ] {
+            let Some(label) = label else {
+                continue;
+            };
+            if !cfg!(feature = "lsm") {
+                return Err(io::Error::other(format!(
+                    "{} requires svlopp to be built with the `lsm` feature",
+                    key
+                )));
+            }
+            if label.is_empty() || label.contains('\0') {
+                return Err(io::Error::other(format!("invalid {} '{}'", key, label)));
+            }
+        }
+        if self.private_tmp_size_mib.is_some() && !self.private_tmp {
+            return Err(io::Error::other("private_tmp_size_mib needs private_tmp"));
+        }
+        if let Some(root) = &self.root_dir
+            && !root.is_absolute()
+        {
+            return Err(io::Error::other(format!(
+                "root_dir '{}' is not an absolute path",
+                root.display()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check what depends on the host, which `check` leaves out: that
+    /// `root_dir` is a directory, and the calendar of the timer, whose time
+    /// zone is read from the zoneinfo database
+    pub(crate) fn check_host(&self) -> io::Result<()> {
+        self.validate_root_dir()?;
+        self.build_calendar()?;
+        Ok(())
+    }
+
+    fn validate_root_dir(&self) -> io::Result<()> {
+        if let Some(root) = &self.root_dir
+            && !root.is_dir()
+        {
+            return Err(io::Error::other(format!(
+                "root_dir '{}' is not a directory",
+                root.display()
+            )));
+        }
+        Ok(())
+    }
+
+    /// `args`, with the values of the options listed in `redact` masked,
+    /// whether given as `--option value` or `--option=value`
+    pub(crate) fn redacted_args(&self) -> Vec<String> {
+        let mut args = Vec::with_capacity(self.args.len());
+        let mut mask_next = false;
+        for arg in &self.args {
+            if std::mem::take(&mut mask_next) {
+                args.push(REDACTED.to_owned());
+                continue;
+            }
+            match arg.split_once('=') {
+                Some((option, _)) if self.redact.iter().any(|r| r == option) => {
+                    args.push(format!("{}={}", option, REDACTED));
+                }
+                _ => {
+                    mask_next = self.redact.contains(arg);
+                    args.push(arg.clone());
+                }
+            }
+        }
+        args
+    }
+
+    /// `env`, with the values of the keys listed in `redact` masked
+    #[cfg(feature = "api")]
+    pub(crate) fn redacted_env(&self) -> Option<BTreeMap<&str, &str>> {
+        let env = self.env.as_ref()?;
+        Some(
+            env.iter()
+                .map(|(key, value)| {
+                    let value = if self.redact.contains(key) {
+                        REDACTED
+                    } else {
+                        value.as_str()
+                    };
+                    (key.as_str(), value)
+                })
+                .collect(),
+        )
+    }
+
+    fn build_calendar(&self) -> io::Result<Option<CalendarSpec>> {
+        self.timer.as_ref().map(TimerConfig::calendar).transpose()
+    }
+
     fn build_svc_argv(&self) -> io::Result<Vec<CString>> {
         let mut argv = Vec::with_capacity(self.args.len() + 1);
         argv.push(CString::new(self.command.as_str())?);
@@ -289,17 +985,434 @@ impl ServiceConfig {
 /// to define service configs. This struct is used
 /// to deserialized a `HashMap<String, ServiceConfig>` from that
 /// file, where the string represent the service name.
-#[repr(transparent)]
 #[derive(Debug, Deserialize)]
 pub(crate) struct ServiceConfigData {
+    /// What to do about reaped children that are not services
+    #[serde(default)]
+    pub(crate) orphan_policy: OrphanPolicy,
+    /// Maximum number of queued services started per main loop iteration
+    #[serde(default = "default_start_concurrency")]
+    pub(crate) start_concurrency: NonZeroUsize,
+    /// Run once all services have stopped on shutdown
+    #[serde(default)]
+    pub(crate) on_all_stopped: Option<Hook>,
+    /// Run right before the supervisor exits after a shutdown, once the
+    /// run directory has been removed
+    #[serde(default)]
+    pub(crate) on_shutdown_complete: Option<Hook>,
+    /// Signals forwarded to services in init mode
+    #[serde(default = "default_forward_signals")]
+    pub(crate) forward_signals: HashMap<ForwardedSignal, ForwardedSignal>,
+    /// Service whose exit code the supervisor exits with. The supervisor
+    /// shuts down once it stops for good
+    #[serde(default)]
+    pub(crate) main_service: Option<String>,
+    /// Notified of service state changes
+    #[serde(default)]
+    pub(crate) webhooks: Vec<Webhook>,
+    /// Commands run on service state changes
+    #[serde(default)]
+    pub(crate) alerts: Vec<Alert>,
+    /// Maximum number of services running at once
+    #[serde(default)]
+    pub(crate) max_services: Option<NonZeroUsize>,
+    /// Maximum number of processes of running services, service processes
+    /// and their descendants
+    #[serde(default)]
+    pub(crate) max_total_processes: Option<NonZeroUsize>,
+    /// Maximum number of services starting at once, see `jobs`
+    #[serde(default)]
+    pub(crate) max_concurrent_starts: Option<NonZeroUsize>,
+    /// Maximum number of services stopping at once
+    #[serde(default)]
+    pub(crate) max_concurrent_stops: Option<NonZeroUsize>,
+    /// How service processes are created
+    #[serde(default)]
+    pub(crate) spawn_strategy: SpawnStrategy,
+    /// Minimum time between two publications of the runtime state, `0`
+    /// publishing it once per main loop iteration
+    #[serde(default)]
+    pub(crate) status_interval_ms: u64,
+    /// `TZ`, `LANG` and `PATH` of service processes
+    #[serde(default)]
+    pub(crate) pinned_env: PinnedEnv,
+    /// What a reload does about the running services whose config changed
+    #[serde(default)]
+    pub(crate) reload_policy: ReloadPolicy,
     pub(crate) services: HashMap<String, ServiceConfig>,
 }
 
+impl Default for ServiceConfigData {
+    fn default() -> Self {
+        Self {
+            orphan_policy: OrphanPolicy::default(),
+            start_concurrency: default_start_concurrency(),
+            on_all_stopped: None,
+            on_shutdown_complete: None,
+            forward_signals: default_forward_signals(),
+            main_service: None,
+            webhooks: Vec::new(),
+            alerts: Vec::new(),
+            max_services: None,
+            max_total_processes: None,
+            max_concurrent_starts: None,
+            max_concurrent_stops: None,
+            spawn_strategy: SpawnStrategy::default(),
+            status_interval_ms: 0,
+            pinned_env: PinnedEnv::default(),
+            reload_policy: ReloadPolicy::default(),
+            services: HashMap::new(),
+        }
+    }
+}
+
+impl ServiceConfigData {
+    /// Load the services of the config file at `config_path` and of the
+    /// service directory `scan_dir`, at least one of which must be given.
+    /// Without a config file, supervisor options take their defaults.
+    /// All the errors found are reported at once, with their location in
+    /// the config file. With `strict`, issues of the config file fail the
+    /// load instead of being logged, see `lint`
+    pub(crate) fn load(
+        config_path: Option<&Path>,
+        scan_dir: Option<&Path>,
+        strict: bool,
+    ) -> io::Result<Self> {
+        let content = config_path.map(std::fs::read_to_string).transpose()?;
+        let mut lint = Lint::new(config_path.zip(content.as_deref()));
+        let mut data = lint.parse();
+        if let Some(dir) = scan_dir {
+            for (name, cfg) in scan_services(dir)? {
+                if data.services.contains_key(&name) {
+                    lint.error(
+                        &["services", &name],
+                        format!(
+                            "service '{}' is defined both in the config file and in '{}'",
+                            name,
+                            dir.display()
+                        ),
+                    );
+                    continue;
+                }
+                data.services.insert(name, cfg);
+            }
+        }
+        data.validate(&mut lint);
+        data.resolve(&mut lint);
+        lint.finish(strict)?;
+        Ok(data)
+    }
+
+    /// Deserialize and validate the config file `content`, as `load` does
+    /// without a service directory, leaving out the checks that depend on
+    /// the host (see `resolve`), so that no file is read. Entry point of
+    /// the `config` fuzz target
+    #[cfg(fuzzing)]
+    #[doc(hidden)]
+    pub fn check(path: &Path, content: &str, strict: bool) -> io::Result<()> {
+        let mut lint = Lint::new(Some((path, content)));
+        let mut data = lint.parse();
+        data.validate(&mut lint);
+        lint.finish(strict)
+    }
+
+    /// Check the references between services and the supervisor options,
+    /// recording the errors to `lint`, and hand the pinned environment to
+    /// the services
+    fn validate(&mut self, lint: &mut Lint<'_>) {
+        if let Some(name) = &self.main_service
+            && !self.services.contains_key(name)
+            && !lint.is_invalid(name)
+        {
+            lint.error(
+                &["main_service"],
+                format!("main_service '{}' is not a service", name),
+            );
+        }
+        self.validate_bindings(lint);
+        self.validate_conflicts(lint);
+        for (key, value) in self.pinned_env.vars(false) {
+            if value.contains('\0') {
+                lint.error(
+                    &["pinned_env", key],
+                    format!("pinned {} contains a nul byte", key),
+                );
+            }
+        }
+        for cfg in self.services.values_mut() {
+            cfg.pinned_env = self.pinned_env.clone();
+        }
+        for (i, hook) in self.webhooks.iter().enumerate() {
+            if let Err(e) = hook.validate() {
+                lint.error_at_index("webhooks", i, e.to_string());
+            }
+        }
+        lint.check_services(&self.services);
+    }
+
+    /// Check what depends on the host, reading the webhook secrets and
+    /// the time zones of timers, and looking at the files and devices of
+    /// services
+    fn resolve(&mut self, lint: &mut Lint<'_>) {
+        for (i, hook) in self.webhooks.iter_mut().enumerate() {
+            if let Err(e) = hook.read_secret() {
+                lint.error_at_index("webhooks", i, e.to_string());
+            }
+        }
+        lint.check_host(&self.services);
+    }
+}
+
+impl ServiceConfigData {
+    #[inline(always)]
+    pub(crate) fn status_interval(&self) -> Duration {
+        Duration::from_millis(self.status_interval_ms)
+    }
+
+    pub(crate) fn process_limits(&self) -> ProcessLimits {
+        ProcessLimits {
+            max_services: self.max_services,
+            max_total_processes: self.max_total_processes,
+        }
+    }
+
+    pub(crate) fn job_limits(&self) -> JobLimits {
+        JobLimits {
+            max_concurrent_starts: self.max_concurrent_starts,
+            max_concurrent_stops: self.max_concurrent_stops,
+        }
+    }
+
+    /// Check that `bind_to` names another service, and that following
+    /// bindings never leads back to the same service, in which case none
+    /// of them would ever start
+    fn validate_bindings(&self, lint: &mut Lint<'_>) {
+        let mut names: Vec<&String> = self.services.keys().collect();
+        names.sort_unstable();
+        'services: for name in names {
+            let mut current = &self.services[name];
+            let mut hops = 0;
+            while let Some(target) = &current.bind_to {
+                let error = if target == name {
+                    format!("service '{}' is bound to itself", name)
+                } else if let Some(cfg) = self.services.get(target) {
+                    current = cfg;
+                    hops += 1;
+                    if hops <= self.services.len() {
+                        continue;
+                    }
+                    format!("service '{}' is bound to a cycle of services", name)
+                } else if lint.is_invalid(target) {
+                    continue 'services;
+                } else {
+                    format!(
+                        "service '{}' is bound to unknown service '{}'",
+                        name, target
+                    )
+                };
+                lint.error(&["services", name, "bind_to"], error);
+                continue 'services;
+            }
+        }
+    }
+}
+
 impl ServiceConfigData {
+    /// Check that `conflicts_with` names other services
+    fn validate_conflicts(&self, lint: &mut Lint<'_>) {
+        let mut names: Vec<&String> = self.services.keys().collect();
+        names.sort_unstable();
+        for name in names {
+            for other in &self.services[name].conflicts_with {
+                let error = if other == name {
+                    format!("service '{}' conflicts with itself", name)
+                } else if !self.services.contains_key(other) && !lint.is_invalid(other) {
+                    format!(
+                        "service '{}' conflicts with unknown service '{}'",
+                        name, other
+                    )
+                } else {
+                    continue;
+                };
+                lint.error(&["services", name, "conflicts_with"], error);
+            }
+        }
+    }
+}
+
+fn default_start_concurrency() -> NonZeroUsize {
+    DEFAULT_START_CONCURRENCY
+}
+
+/// Services waiting to be started.
+///
+/// Starting all the services in one go, at boot or when a reload adds many
+/// of them, would keep the main loop from handling events until the last
+/// one has been forked. Services are queued instead, and started in
+/// batches of at most `concurrency` per main loop iteration
+#[derive(Debug)]
+pub(crate) struct StartQueue {
+    pending: VecDeque<u64>,
+    concurrency: NonZeroUsize,
+    /// Whether the last batch stopped at `max_concurrent_starts`
+    held: bool,
+}
+
+impl StartQueue {
+    #[inline(always)]
+    pub(crate) fn new(concurrency: NonZeroUsize) -> Self {
+        Self {
+            pending: VecDeque::new(),
+            concurrency,
+            held: false,
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn set_concurrency(&mut self, concurrency: NonZeroUsize) {
+        self.concurrency = concurrency;
+    }
+
+    #[inline(always)]
+    pub(crate) fn push(&mut self, svc: &mut Service) {
+        svc.start_queued = true;
+        self.pending.push_back(svc.id);
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
     #[inline(always)]
-    pub(crate) fn from_config_file(path: &Path) -> io::Result<Self> {
-        let content = std::fs::read_to_string(path)?;
-        toml::from_str(&content).map_err(|e| io::Error::other(e.message()))
+    pub(crate) fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Whether the next batch can start services right away, which it
+    /// can't while `max_concurrent_starts` services are starting
+    #[inline(always)]
+    pub(crate) fn is_ready(&self) -> bool {
+        !self.pending.is_empty() && !self.held
+    }
+
+    /// Drop all queued starts, e.g. on shutdown
+    #[inline(always)]
+    pub(crate) fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Start the next batch of queued services. Services that have been
+    /// removed, started by other means or explicitly stopped in the
+    /// meantime are skipped
+    pub(crate) fn start_batch(&mut self, registry: &mut ServiceRegistry, ctx: &SpawnContext) {
+        let mut started = 0;
+        let mut capacity = None;
+        let mut slots = registry.free_start_slots();
+        self.held = false;
+        while started < self.concurrency.get()
+            && let Some(svc_id) = self.pending.pop_front()
+        {
+            if !registry
+                .service_mut(svc_id)
+                .is_some_and(|svc| std::mem::take(&mut svc.start_queued))
+            {
+                continue;
+            }
+            // bound services are queued again once their target runs
+            if !registry.is_bind_target_running(svc_id) {
+                continue;
+            }
+            if !registry.service(svc_id).is_some_and(|svc| {
+                svc.is_stopped() && svc.pending_action != ServicePendingAction::Remove
+            }) {
+                continue;
+            }
+            // only explicit starts stop the services they conflict with
+            if let Some(other) = registry.running_conflict(svc_id) {
+                svlogg!(
+                    LogLevel::Warn,
+                    "not starting service '{}', conflicting with running service '{}'",
+                    registry.service(svc_id).map_or("?", |svc| &*svc.name),
+                    other
+                );
+                continue;
+            }
+            if slots == Some(0) {
+                if let Some(svc) = registry.service_mut(svc_id) {
+                    svc.start_queued = true;
+                }
+                self.pending.push_front(svc_id);
+                self.held = true;
+                break;
+            }
+            let capacity = capacity.get_or_insert_with(|| registry.capacity());
+            let Some(svc) = registry.service_mut(svc_id) else {
+                continue;
+            };
+            started += 1;
+            slots = slots.map(|n| n - 1);
+            match capacity.reserve().and_then(|()| start_service(svc, ctx)) {
+                Ok(()) => {
+                    let pid = svc.pid().expect("running service must have a pid");
+                    svlogg!(
+                        LogLevel::Info,
+                        "started service '{}' with pid {}",
+                        svc.name,
+                        pid
+                    );
+                    registry.register_pid(pid, svc_id);
+                }
+                Err(e) => svlogg!(
+                    LogLevel::Error,
+                    "failed to start service '{}': {}",
+                    svc.name,
+                    e
+                ),
+            }
+        }
+    }
+}
+
+/// Global limits on what services run, protecting the host from e.g. a
+/// reload adding services by the thousands
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ProcessLimits {
+    pub(crate) max_services: Option<NonZeroUsize>,
+    pub(crate) max_total_processes: Option<NonZeroUsize>,
+}
+
+/// Room left under the `ProcessLimits`, taken as services are started
+#[derive(Debug)]
+pub(crate) struct Capacity {
+    limits: ProcessLimits,
+    services: usize,
+    processes: usize,
+}
+
+impl Capacity {
+    /// Take room for one more service, fails with `QuotaExceeded` once a
+    /// limit is hit
+    pub(crate) fn reserve(&mut self) -> io::Result<()> {
+        if let Some(max) = self.limits.max_services
+            && self.services >= max.get()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::QuotaExceeded,
+                format!("max_services limit of {} running services reached", max),
+            ));
+        }
+        if let Some(max) = self.limits.max_total_processes
+            && self.processes >= max.get()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::QuotaExceeded,
+                format!("max_total_processes limit of {} processes reached", max),
+            ));
+        }
+        self.services += 1;
+        self.processes += 1;
+        Ok(())
     }
 }
 
@@ -345,30 +1458,207 @@ impl ServicePendingAction {
 }
 
 /// A minimal service representation.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub(crate) struct Service {
     pub(crate) id: u64,
-    pub(crate) name: String,
+    /// Shared with the per-service state of the notifiers, which is
+    /// updated on every status flush
+    pub(crate) name: Arc<str>,
     pub(crate) config: ServiceConfig,
+    /// `config.config_hash()`
+    pub(crate) config_hash: u64,
     pub(crate) argv: Vec<CString>,
     pub(crate) envp: Option<Vec<CString>>,
     pub(crate) state: ServiceState,
     pub(crate) pending_action: ServicePendingAction,
+    /// Control operation waiting in the `JobScheduler` for a start or stop
+    /// slot
+    pub(crate) scheduled: Option<ControlOp>,
+    /// Whether the service waits in the `StartQueue`, which an explicit
+    /// stop takes it out of
+    pub(crate) start_queued: bool,
+    /// Number of times the service process has been started
+    pub(crate) start_count: u64,
+    /// Whether the service process sent `READY=1` to the readiness socket
+    pub(crate) ready_notified: bool,
+    /// Log pump of the last started process, if the service uses one
+    pub(crate) log_pump: Option<LogPump>,
+    /// Timing of the last stop requested by the supervisor
+    pub(crate) stop_timing: Option<StopTiming>,
+    /// Whether the ongoing stop was propagated from the bound service
+    pub(crate) bound_stop: bool,
+    /// Whether the service stays stopped once the service it is bound to
+    /// runs again, after a failure of it with `on_target_failure = "Stop"`
+    pub(crate) bound_hold: bool,
+    /// Id of the service this one replaces in an ongoing swap, see `swap`
+    pub(crate) replaces: Option<u64>,
+    /// Whether the service was defined at runtime with `svloppctl run`, and
+    /// is removed once it stops, see `transient`
+    pub(crate) transient: bool,
+    /// Whether the service process was adopted rather than started by the
+    /// supervisor, which can't reap it, see `adopt`
+    pub(crate) adopted: bool,
+    /// Descendants of the service process as of the last scan, only
+    /// tracked with `kill_descendants`
+    pub(crate) descendants: Vec<ProcStat>,
+    /// Wall clock times, in milliseconds since the epoch, of the last
+    /// failures of the service process, oldest first
+    pub(crate) failures: VecDeque<u64>,
+    /// Whether automatic restarts are suspended because the service hit
+    /// its `start_limit`
+    pub(crate) start_limited: bool,
+    /// Last starts and exits of the service process, written to a
+    /// diagnostics bundle when it hits its `start_limit`
+    pub(crate) history: ServiceHistory,
+    /// Exit reason of the last service process, until its `finish`
+    /// command is started
+    pub(crate) pending_finish: Option<ExitReason>,
+    /// Pid of the running `finish` command
+    pub(crate) finish_pid: Option<Pid>,
+    /// When the service process was last started
+    pub(crate) started_at: Option<Instant>,
+    /// Start time of the service process in clock ticks since boot, read
+    /// right after the fork, tells it apart from a process reusing its pid
+    pub(crate) start_time: Option<u64>,
+    /// Pidfd of the service process, signals are sent through it when the
+    /// kernel supports pidfds
+    pub(crate) pidfd: Option<OwnedFd>,
+    /// Whether the ongoing stop is due to `runtime_max_ms`
+    pub(crate) runtime_exceeded: bool,
+    /// Samples of the resource usage of the service process, only taken
+    /// with `resource_limits`
+    pub(crate) resource_monitor: ResourceMonitor,
+    /// Pressure of the cgroup of the service process as of the last
+    /// sample, only known when it runs in its own cgroup
+    pub(crate) pressure: Option<ServicePressure>,
+    /// Read end of a close-on-exec pipe whose write end the last started
+    /// process holds until it execs, registered with the main loop
+    pub(crate) exec_pipe: Option<OwnedFd>,
+    /// What the setup of the last started process failed at, read from
+    /// `exec_pipe`
+    pub(crate) spawn_error: Option<SpawnError>,
+    /// The private `/tmp` of the running process, if `private_tmp`
+    pub(crate) private_tmp: Option<PrivateTmp>,
+    /// The cgroup of the running process, if `cgroup_delegate`, `io_max`
+    /// or `cpu_max`
+    pub(crate) cgroup: Option<ServiceCgroup>,
+    /// CPU time limit set by `set-property`, in percent of one CPU
+    pub(crate) cpu_max: Option<NonZeroU32>,
+    /// Flags of the status file line and snapshot record
+    pub(crate) flags: RecordFlags,
+    /// Variables of `env_file` the last started process got
+    pub(crate) env_file_vars: Option<EnvVars>,
+    /// Hash of the config the last started process got
+    pub(crate) running_config_hash: Option<u64>,
+    /// Timer of the service, if `timer`
+    pub(crate) timer: Option<Timer>,
 }
 
-impl Service {
-    #[inline(always)]
+/// Timing of a stop requested by the supervisor, used to report how long
+/// services take to stop
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StopTiming {
+    /// When the stop signal was sent
+    pub(crate) requested_at: Instant,
+    /// When the process was reaped
+    pub(crate) reaped_at: Option<Instant>,
+    /// Whether the process had to be killed with `SIGKILL` after the stop
+    /// timeout
+    pub(crate) force_killed: bool,
+}
+
+/// Check that `name` can be used as a service name.
+///
+/// Names end up in whitespace separated status lines and, with
+/// `--per-service-status`, as file names in the run directory
+fn validate_service_name(name: &str) -> io::Result<()> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || name.contains(|c: char| c == '/' || c == '\0' || c.is_whitespace())
+    {
+        return Err(io::Error::other(format!("invalid service name '{}'", name)));
+    }
+    Ok(())
+}
+
+/// A validated service config, along with the argv and environment built
+/// from it
+#[derive(Debug)]
+pub(crate) struct PreparedConfig {
+    config: ServiceConfig,
+    hash: u64,
+    argv: Vec<CString>,
+    envp: Option<Vec<CString>>,
+    calendar: Option<CalendarSpec>,
+}
+
+impl PreparedConfig {
+    pub(crate) fn new(config: ServiceConfig) -> io::Result<Self> {
+        config.validate()?;
+        config.validate_root_dir()?;
+        Ok(Self {
+            argv: config.build_svc_argv()?,
+            envp: config.build_svc_envp()?,
+            hash: config.config_hash(),
+            calendar: config.build_calendar()?,
+            config,
+        })
+    }
+}
+
+impl Service {
+    #[inline(always)]
     pub(crate) fn new(id: u64, name: String, config: ServiceConfig) -> io::Result<Self> {
+        validate_service_name(&name)?;
+        config.validate()?;
+        config.validate_root_dir()?;
         let argv = config.build_svc_argv()?;
         let envp = config.build_svc_envp()?;
+        let timer = config.build_calendar()?.map(|calendar| {
+            let delay = config.timer.as_ref().map_or(0, |timer| timer.delay(&name));
+            Timer::new(calendar, delay, timestamp().0)
+        });
         Ok(Self {
             id,
-            name,
+            name: Arc::from(name),
+            config_hash: config.config_hash(),
             config,
             argv,
             envp,
             state: ServiceState::Stopped(ServiceStopReason::NeverStarted),
             pending_action: ServicePendingAction::None,
+            scheduled: None,
+            start_queued: false,
+            start_count: 0,
+            ready_notified: false,
+            log_pump: None,
+            stop_timing: None,
+            bound_stop: false,
+            bound_hold: false,
+            replaces: None,
+            transient: false,
+            adopted: false,
+            descendants: Vec::new(),
+            failures: VecDeque::new(),
+            start_limited: false,
+            history: ServiceHistory::default(),
+            pending_finish: None,
+            finish_pid: None,
+            started_at: None,
+            start_time: None,
+            pidfd: None,
+            runtime_exceeded: false,
+            resource_monitor: ResourceMonitor::default(),
+            pressure: None,
+            exec_pipe: None,
+            spawn_error: None,
+            private_tmp: None,
+            cgroup: None,
+            cpu_max: None,
+            flags: RecordFlags::default(),
+            env_file_vars: None,
+            running_config_hash: None,
+            timer,
         })
     }
 
@@ -377,6 +1667,7 @@ impl Service {
         match self.state {
             ServiceState::Running(p) => Some(p),
             ServiceState::Stopping(p, _) => Some(p),
+            ServiceState::Paused(p) => Some(p),
             _ => None,
         }
     }
@@ -391,19 +1682,172 @@ impl Service {
         self.config.working_directory.as_deref()
     }
 
+    #[inline(always)]
+    pub(crate) fn root_dir(&self) -> Option<&Path> {
+        self.config.root_dir.as_deref()
+    }
+
     #[inline(always)]
     pub(crate) fn log_file_path(&self) -> Option<&Path> {
         self.config.log_file_path.as_deref()
     }
 
+    /// Return the log pump options if any of the log pump features is
+    /// configured, `None` if the log file is to be handed to the service
+    /// process as is
+    #[inline(always)]
+    pub(crate) fn log_pump_options(&self) -> Option<LogPumpOptions> {
+        if self.config.log_prefix.is_none()
+            && self.config.log_multiline.is_none()
+            && self.config.log_rate_limit.is_none()
+            && self.config.log_rotate.is_none()
+        {
+            return None;
+        }
+        Some(LogPumpOptions {
+            prefix: self.config.log_prefix.unwrap_or_default(),
+            multiline: self.config.log_multiline.clone(),
+            rate_limit: self.config.log_rate_limit,
+            rotate: self.config.log_rotate,
+        })
+    }
+
     #[inline(always)]
     pub(crate) fn user_group(&self) -> Option<UserGroup> {
         self.config.user_group
     }
 
+    /// Who the cgroup of the service is delegated to, the ids of the host
+    /// its process runs with. `None` if it runs as root
+    fn cgroup_owner(&self) -> Option<UserGroup> {
+        let ug = self.config.user_group?;
+        match &self.config.user_namespace {
+            Some(userns) => userns.outside(ug),
+            None => Some(ug),
+        }
+    }
+
+    /// The properties set on the service by `set-property`
+    pub(crate) fn properties(&self) -> impl Iterator<Item = Property> {
+        self.cpu_max
+            .map(|percent| Property::CpuMax(Some(percent)))
+            .into_iter()
+    }
+
+    /// Set `property` on the service, and on the cgroup of its running
+    /// process if it has one
+    pub(crate) fn set_property(&mut self, property: Property) -> io::Result<()> {
+        match property {
+            Property::CpuMax(percent) => {
+                self.cpu_max = percent;
+                match &self.cgroup {
+                    Some(cgroup) => cgroup.set_cpu_max(percent),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// What the setup of the process failed at, e.g. `setuid(1000) failed:
+    /// EPERM`, if the service is stopped for that
+    pub(crate) fn spawn_failure(&self) -> Option<String> {
+        match self.state {
+            ServiceState::Stopped(ServiceStopReason::SpawnFailed(e)) => {
+                Some(e.describe(&self.config))
+            }
+            _ => None,
+        }
+    }
+
     #[inline(always)]
     pub(crate) fn fallback_pending_action(&self) -> ServicePendingAction {
-        self.config.fallback_pending_action
+        match self.config.fallback_pending_action {
+            ServicePendingAction::Restart if self.start_limited => ServicePendingAction::None,
+            action => action,
+        }
+    }
+
+    /// Send `signal` to the service process through its pidfd, which
+    /// can't refer to another process even once the pid is reused.
+    ///
+    /// Without a pidfd, the signal is sent to the pid once checked that it
+    /// still belongs to the process the supervisor started. After a missed
+    /// reap the pid may have been reused by an unrelated process, which is
+    /// left alone and reported as `ESRCH`
+    pub(crate) fn signal(&self, signal: Signal) -> io::Result<()> {
+        let Some(pid) = self.pid() else {
+            return Err(rustix::io::Errno::SRCH.into());
+        };
+        if let Some(pidfd) = &self.pidfd {
+            pidfd_send_signal(pidfd, signal)?;
+            return Ok(());
+        }
+        if let Some(start_time) = self.start_time {
+            match ProcStat::read(pid.as_raw_nonzero().get()) {
+                Ok(stat) if stat.start_time == start_time => {}
+                Ok(_) => {
+                    svlogg!(
+                        LogLevel::Warn,
+                        "pid {} of service '{}' was reused by another process, not signaling it",
+                        pid,
+                        self.name
+                    );
+                    return Err(rustix::io::Errno::SRCH.into());
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    return Err(rustix::io::Errno::SRCH.into());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        kill_process(pid, signal)?;
+        Ok(())
+    }
+
+    /// Freeze or thaw the processes of the service: those of its cgroup
+    /// through the freezer if it has one, or else its process group with
+    /// `SIGSTOP` and `SIGCONT`, which the processes can observe. An adopted
+    /// process only gets them itself, through its pidfd, its group being
+    /// none of svlopp's business
+    fn freeze(&self, frozen: bool) -> io::Result<()> {
+        if let Some(cgroup) = &self.cgroup {
+            return cgroup.freeze(frozen);
+        }
+        let Some(pid) = self.pid() else {
+            return Err(rustix::io::Errno::SRCH.into());
+        };
+        let signal = if frozen { Signal::STOP } else { Signal::CONT };
+        if self.adopted {
+            return self.signal(signal);
+        }
+        // the group can't be reused while its leader isn't reaped
+        kill_process_group(pid, signal)?;
+        Ok(())
+    }
+
+    /// Record a failure of the service process, and check it against the
+    /// start limit. Returns whether the service just hit it
+    fn record_failure(&mut self) -> bool {
+        let (secs, nsecs) = timestamp();
+        let now_ms = (secs * 1000 + nsecs / 1_000_000) as u64;
+        if self.failures.len() == MAX_RECORDED_FAILURES as usize {
+            self.failures.pop_front();
+        }
+        self.failures.push_back(now_ms);
+        if let Some(limit) = self.config.start_limit
+            && limit.is_hit(&self.failures, now_ms)
+        {
+            svlogg!(
+                LogLevel::Warn,
+                "service '{}' failed {} times within {}ms, not restarting it",
+                self.name,
+                limit.burst,
+                limit.interval_ms
+            );
+            self.start_limited = true;
+            return true;
+        }
+        false
     }
 
     #[inline(always)]
@@ -416,13 +1860,109 @@ impl Service {
         Duration::from_millis(self.config.stop_timeout_ms)
     }
 
-    /// Update the service config and rebuild argv
-    #[inline(always)]
-    pub(crate) fn update_config(&mut self, config: ServiceConfig) -> io::Result<()> {
-        self.argv = config.build_svc_argv()?;
-        self.envp = config.build_svc_envp()?;
-        self.config = config;
-        Ok(())
+    /// Swap in `prepared`, returning the config it replaces, so that it can
+    /// be swapped back
+    pub(crate) fn swap_config(&mut self, prepared: PreparedConfig) -> PreparedConfig {
+        let now = timestamp().0;
+        let delay = prepared
+            .config
+            .timer
+            .as_ref()
+            .map_or(0, |timer| timer.delay(&self.name));
+        // a timer kept by the new config keeps its counters
+        let calendar = match (&mut self.timer, prepared.calendar) {
+            (Some(timer), Some(calendar)) => Some(timer.swap_calendar(calendar, delay, now)),
+            (timer, calendar) => std::mem::replace(
+                timer,
+                calendar.map(|calendar| Timer::new(calendar, delay, now)),
+            )
+            .map(|timer| timer.calendar),
+        };
+        PreparedConfig {
+            config: std::mem::replace(&mut self.config, prepared.config),
+            hash: std::mem::replace(&mut self.config_hash, prepared.hash),
+            argv: std::mem::replace(&mut self.argv, prepared.argv),
+            envp: std::mem::replace(&mut self.envp, prepared.envp),
+            calendar,
+        }
+    }
+
+    /// Build the environment for the next service process: the configured
+    /// `env`, with `clear_env` on top of a minimal environment, or else
+    /// svlopp's own environment. Then the variables of `env_file`
+    /// (`env_vars`) and the pinned ones that `env` doesn't set, and the
+    /// `SVLOPP_*` self-identification variables.
+    ///
+    /// Unlike `argv`, this is rebuilt on every start as the restart
+    /// count (the number of previous starts) changes. The fd numbers of
+    /// `secret_fds`, opened from `config.secrets`, are exported as well
+    pub(crate) fn build_start_envp(
+        &self,
+        secret_fds: &[OwnedFd],
+        env_vars: &[(String, String)],
+    ) -> io::Result<Vec<CString>> {
+        let mut injected = vec![
+            (ENV_SERVICE_NAME, self.name.to_string()),
+            (ENV_SERVICE_ID, self.id.to_string()),
+            (ENV_RESTART_COUNT, self.start_count.to_string()),
+        ];
+        // a path of the host, out of reach from a `root_dir`
+        if let Some(path) = ready_socket()
+            && self.config.root_dir.is_none()
+        {
+            injected.push((ENV_NOTIFY_SOCKET, path.display().to_string()));
+        }
+        for (secret, fd) in self.config.secrets.iter().zip(secret_fds) {
+            injected.push((secret.env.as_str(), fd.as_raw_fd().to_string()));
+        }
+        let is_injected = |entry: &[u8]| injected.iter().any(|(key, _)| env_entry_is(entry, key));
+        let mut envp = match &self.envp {
+            Some(env) => env
+                .iter()
+                .filter(|e| !is_injected(e.as_bytes()))
+                .cloned()
+                .collect::<Vec<_>>(),
+            None if self.config.clear_env => Vec::new(),
+            None => {
+                let mut envp = Vec::new();
+                for (key, value) in std::env::vars_os() {
+                    if SYSTEMD_NOTIFY_ENV.iter().any(|k| key == *k) {
+                        continue;
+                    }
+                    let mut entry = Vec::with_capacity(key.len() + value.len() + 1);
+                    entry.extend_from_slice(key.as_bytes());
+                    entry.push(b'=');
+                    entry.extend_from_slice(value.as_bytes());
+                    if !is_injected(&entry) {
+                        envp.push(CString::new(entry)?);
+                    }
+                }
+                envp
+            }
+        };
+        let config_env = self.config.env.as_ref();
+        for (key, value) in env_vars {
+            if config_env.is_some_and(|env| env.contains_key(key))
+                || injected.iter().any(|(k, _)| k == key)
+            {
+                continue;
+            }
+            envp.retain(|e| !env_entry_is(e.as_bytes(), key));
+            envp.push(CString::new(format!("{key}={value}"))?);
+        }
+        for (key, value) in self.config.pinned_env.vars(self.config.clear_env) {
+            if config_env.is_some_and(|env| env.contains_key(key))
+                || env_vars.iter().any(|(k, _)| k == key)
+            {
+                continue;
+            }
+            envp.retain(|e| !env_entry_is(e.as_bytes(), key));
+            envp.push(CString::new(format!("{key}={value}"))?);
+        }
+        for (key, value) in injected {
+            envp.push(CString::new(format!("{key}={value}"))?);
+        }
+        Ok(envp)
     }
 
     /// Returns the `ServicePendingAction` and leave `ServicePendingAction::None`
@@ -432,92 +1972,103 @@ impl Service {
         std::mem::replace(&mut self.pending_action, ServicePendingAction::None)
     }
 
+    /// Write the line of the service in the status file, the snapshot
+    /// record being formatted the same way
     #[inline(always)]
     pub(crate) fn format_status_line(&self, w: &mut impl fmt::Write) -> fmt::Result {
-        write!(w, "{} {} {}", self.name, self.id, self.state)
-    }
-}
-
-/// Configure standard file descriptors.
-/// processes.
-///
-/// Invariants:
-/// - `devnull_fd` must be an open fd referring to `/dev/null`, opened for read-write
-/// - `log_fd`, if present, must be open for write
-/// - Both fds must remain valid across fork and must not have been closed in the child
-///
-/// It is intended to be called in the child arm of a fork, before execvp, so it must
-/// not perform any non async-signal-safe operation
-fn setup_child_stdio(devnull_fd: BorrowedFd, log_fd: Option<BorrowedFd>) -> rustix::io::Result<()> {
-    dup2_stdin(devnull_fd)?;
-    match log_fd {
-        Some(fd) => {
-            dup2_stdout(fd)?;
-            dup2_stderr(fd)?;
-        }
-        None => {
-            dup2_stdout(devnull_fd)?;
-            dup2_stderr(devnull_fd)?;
-        }
+        write!(w, "{}", self.snapshot_record())
     }
-    Ok(())
-}
 
-fn child_exec(
-    svc: &Service,
-    sigset: &SigSet,
-    devnull_fd: BorrowedFd,
-    log_fd: Option<BorrowedFd>,
-) -> ! {
-    if set_thread_signal_mask(sigset).is_err() {
-        unsafe { libc::_exit(111) }
-    }
-    if setpgid(None, None).is_err() {
-        unsafe { libc::_exit(111) }
-    }
-    if let Some(ug) = svc.user_group() {
-        unsafe {
-            if cvt(libc::setgid(ug.gid)).is_err() {
-                libc::_exit(111)
-            }
-            if cvt(libc::setuid(ug.uid)).is_err() {
-                libc::_exit(111)
+    pub(crate) fn snapshot_record(&self) -> ServiceRecordRef<'_> {
+        let state = match self.state {
+            ServiceState::Running(pid) => RecordState::Running {
+                pid: pid.as_raw_nonzero().get(),
+            },
+            ServiceState::Stopping(pid, _) => RecordState::Stopping {
+                pid: pid.as_raw_nonzero().get(),
+            },
+            ServiceState::Paused(pid) => RecordState::Paused {
+                pid: pid.as_raw_nonzero().get(),
+            },
+            ServiceState::Stopped(reason) => {
+                let (reason, value) = match reason {
+                    ServiceStopReason::NeverStarted => (StopReasonKind::NeverStarted, 0),
+                    ServiceStopReason::SupervisorTerminated(ExitReason::Exited(code)) => {
+                        (StopReasonKind::SupervisorTerminatedExited, code)
+                    }
+                    ServiceStopReason::SupervisorTerminated(ExitReason::Signaled(sig)) => {
+                        (StopReasonKind::SupervisorTerminatedSignaled, sig)
+                    }
+                    ServiceStopReason::BoundStopped(ExitReason::Exited(code)) => {
+                        (StopReasonKind::BoundStoppedExited, code)
+                    }
+                    ServiceStopReason::BoundStopped(ExitReason::Signaled(sig)) => {
+                        (StopReasonKind::BoundStoppedSignaled, sig)
+                    }
+                    ServiceStopReason::RuntimeExceeded(ExitReason::Exited(code)) => {
+                        (StopReasonKind::RuntimeExceededExited, code)
+                    }
+                    ServiceStopReason::RuntimeExceeded(ExitReason::Signaled(sig)) => {
+                        (StopReasonKind::RuntimeExceededSignaled, sig)
+                    }
+                    ServiceStopReason::Success => (StopReasonKind::Success, 0),
+                    ServiceStopReason::Error(code) => (StopReasonKind::Error, code),
+                    ServiceStopReason::Crashed(sig) => (StopReasonKind::Crashed, sig),
+                    ServiceStopReason::Killed(sig) => (StopReasonKind::Killed, sig),
+                    ServiceStopReason::OomKilled => (StopReasonKind::OomKilled, 0),
+                    ServiceStopReason::Lost => (StopReasonKind::Lost, 0),
+                    ServiceStopReason::Exited => (StopReasonKind::Exited, 0),
+                    ServiceStopReason::Detached(pid) => (StopReasonKind::Detached, pid),
+                    ServiceStopReason::SpawnFailed(e) => (StopReasonKind::SpawnFailed, e.errno),
+                };
+                RecordState::Stopped { reason, value }
             }
+        };
+        ServiceRecordRef {
+            id: self.id,
+            name: &self.name,
+            state,
+            start_count: self.start_count,
+            flags: self.record_flags(),
         }
     }
-    if let Some(cwd) = svc.working_directory()
-        && chdir(cwd).is_err()
-    {
-        unsafe { libc::_exit(111) }
-    }
-    if setup_child_stdio(devnull_fd, log_fd).is_err() {
-        unsafe { libc::_exit(111) }
+
+    /// Whether the process runs a config other than the loaded one, e.g.
+    /// changed by a reload with `reload_policy = "defer"`
+    pub(crate) fn is_outdated(&self) -> bool {
+        self.pid().is_some()
+            && self
+                .running_config_hash
+                .is_some_and(|hash| hash != self.config_hash)
     }
-    let argv: Vec<*const libc::c_char> = svc
-        .argv
-        .iter()
-        .map(|s| s.as_ptr())
-        .chain(std::iter::once(std::ptr::null()))
-        .collect();
 
-    unsafe {
-        match &svc.envp {
-            None => {
-                libc::execvp(argv[0], argv.as_ptr());
-            }
-            Some(env) => {
-                let envp: Vec<*const libc::c_char> = env
-                    .iter()
-                    .map(|s| s.as_ptr())
-                    .chain(std::iter::once(std::ptr::null()))
-                    .collect();
-                libc::execvpe(argv[0], argv.as_ptr(), envp.as_ptr());
-            }
-        }
-        libc::_exit(127);
+    fn record_flags(&self) -> RecordFlags {
+        let mut flags = self.flags;
+        flags.set(RecordFlags::RESTART_REQUIRED, self.is_outdated());
+        flags
     }
 }
 
+/// Supervisor wide state needed to start service processes.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SpawnContext<'a> {
+    /// Signal mask to restore in the child before exec
+    pub(crate) sigset: &'a SigSet,
+    /// The main loop epoll instance, log pump pipes are registered
+    /// with it
+    pub(crate) epfd: BorrowedFd<'a>,
+    /// How service processes are created, the `spawn_strategy`
+    /// supervisor option
+    pub(crate) strategy: SpawnStrategy,
+    /// Spawn helper service processes are forked from, if enabled. It
+    /// takes precedence over `strategy`
+    pub(crate) spawner: Option<&'a Spawner>,
+    /// Directory of the run directory private `/tmp`s are created in
+    pub(crate) private_tmp_dir: &'a Path,
+    /// Where the cgroups of services are created, `None` without cgroup v2
+    pub(crate) cgroups: Option<&'a ServiceCgroups>,
+}
+
 /// Start a new service.
 ///
 /// a successful call to `fork` return `0` in the child process
@@ -526,83 +2077,364 @@ fn child_exec(
 /// `execvp` is used as we don't know the exact lenght of `argv`
 /// and of course we want it to check for the executable in path
 ///
+/// If the service has a log file and any log pump option configured, its
+/// `stdout` and `stderr` are pipes drained by a `LogPump` registered
+/// with `ctx.epfd`; otherwise the log file (or `/dev/null`) is handed
+/// to the child directly.
+///
+/// With a spawner in `ctx`, the process is forked by the helper instead,
+/// as a child of the supervisor all the same.
+///
 /// TODO: Currently we're redirecting `/dev/std*` to dev null
 /// in the child processes, but we have to decide what to do
 /// with it
-pub(crate) fn start_service(svc: &mut Service, sigset: &SigSet) -> io::Result<()> {
-    let devnull_fd = open("/dev/null", OFlags::RDWR | OFlags::CLOEXEC, Mode::empty())?;
-    let log_fd = svc
-        .log_file_path()
-        .map(|p| {
-            open(
-                p,
-                OFlags::WRONLY | OFlags::CREATE | OFlags::APPEND | OFlags::CLOEXEC,
-                Mode::from_bits_truncate(0o644),
-            )
-        })
+pub(crate) fn start_service(svc: &mut Service, ctx: &SpawnContext) -> io::Result<()> {
+    // fail before the service crash-loops on a port held by another process,
+    // the replaced service of a swap holding them until it's stopped
+    if svc.replaces.is_none() {
+        check_ports(&svc.config.ports)?;
+    }
+    // the `/dev/null` of the root the process sees
+    let devnull_path = match svc.root_dir() {
+        Some(root) => root.join("dev/null"),
+        None => PathBuf::from("/dev/null"),
+    };
+    let devnull_fd = open(&devnull_path, OFlags::RDWR | OFlags::CLOEXEC, Mode::empty())
+        .map_err(|e| io::Error::other(format!("{}: {}", devnull_path.display(), e)))?;
+    let log_fd = svc.log_file_path().map(open_log_file).transpose()?;
+    let (log_fd, log_pump, log_pipes) = match (log_fd, svc.log_file_path(), svc.log_pump_options())
+    {
+        (Some(fd), Some(path), Some(options)) => {
+            let (pump, pipes) = LogPump::new(&svc.name, options, path, fd)?;
+            (None, Some(pump), Some(pipes))
+        }
+        (log_fd, _, _) => (log_fd, None, None),
+    };
+    let (stdout_fd, stderr_fd) = match (&log_pipes, &log_fd) {
+        (Some([out, err]), _) => (Some(out.as_fd()), Some(err.as_fd())),
+        (None, Some(fd)) => (Some(fd.as_fd()), Some(fd.as_fd())),
+        (None, None) => (None, None),
+    };
+    let secret_fds = svc
+        .config
+        .secrets
+        .iter()
+        .map(Secret::open)
+        .collect::<io::Result<Vec<_>>>()?;
+    let env_file_vars = svc
+        .config
+        .env_file
+        .as_deref()
+        .map(read_env_file)
         .transpose()?;
-    match unsafe { libc::fork() } {
-        0 => child_exec(
-            svc,
-            sigset,
-            devnull_fd.as_fd(),
-            log_fd.as_ref().map(|fd| fd.as_fd()),
-        ),
-        raw if raw > 0 => {
-            // safe as we just checked that the pid is > 0
-            let pid = unsafe { Pid::from_raw_unchecked(raw) };
-            svc.state = ServiceState::Running(pid);
-            Ok(())
+    let envp = svc.build_start_envp(&secret_fds, env_file_vars.as_deref().unwrap_or_default())?;
+    // non blocking as it's read again when the process is reaped
+    let (exec_rd, exec_wr) = pipe_with(PipeFlags::CLOEXEC | PipeFlags::NONBLOCK)?;
+    let inherited_fds = secret_fds
+        .iter()
+        .map(|fd| (fd.as_fd(), fd.as_raw_fd()))
+        .collect::<Vec<_>>();
+    // the one of the previous process is gone with it, if it wasn't yet
+    svc.private_tmp = None;
+    let private_tmp = if svc.config.private_tmp {
+        Some(PrivateTmp::create(
+            ctx.private_tmp_dir,
+            &svc.name,
+            svc.config.private_tmp_size_mib,
+        )?)
+    } else {
+        None
+    };
+    let private_tmp_mount = private_tmp.as_ref().map(|tmp| BindMount {
+        src: tmp.path().to_path_buf(),
+        dst: PathBuf::from("/tmp"),
+        ro: false,
+    });
+    let mount_targets = svc
+        .config
+        .mounts
+        .iter()
+        .chain(&private_tmp_mount)
+        .map(|mount| (mount, mount.target(svc.root_dir())))
+        .collect::<Vec<_>>();
+    let mounts = mount_targets
+        .iter()
+        .map(|(mount, target)| MountSpec {
+            src: &mount.src,
+            target,
+            ro: mount.ro,
+        })
+        .collect::<Vec<_>>();
+    svc.cgroup = None;
+    let needs_cgroup = [
+        (svc.config.cgroup_delegate, "cgroup_delegate"),
+        (!svc.config.io_max.is_empty(), "io_max"),
+        (svc.cpu_max.is_some(), "cpu.max"),
+    ]
+    .into_iter()
+    .find_map(|(needed, by)| needed.then_some(by));
+    let cgroup = match needs_cgroup {
+        Some(by) => {
+            let cgroups = ctx
+                .cgroups
+                .ok_or_else(|| io::Error::other(format!("{} needs a cgroup v2 hierarchy", by)))?;
+            let owner = svc
+                .config
+                .cgroup_delegate
+                .then(|| svc.cgroup_owner())
+                .flatten();
+            let cgroup = cgroups.create(&svc.name, owner)?;
+            if svc.cpu_max.is_some() {
+                cgroup.set_cpu_max(svc.cpu_max)?;
+            }
+            cgroup.set_io_max(&svc.config.io_max)?;
+            Some(cgroup)
+        }
+        None => None,
+    };
+    let cgroup_fd = cgroup.as_ref().map(ServiceCgroup::open_procs).transpose()?;
+    #[cfg(feature = "lsm")]
+    let exec_label = crate::lsm::exec_label(&svc.name, &svc.config);
+    #[cfg(not(feature = "lsm"))]
+    let exec_label = None;
+    let spec = ExecSpec {
+        argv: &svc.argv,
+        envp: &envp,
+        user_group: svc.user_group(),
+        user_namespace: svc.config.user_namespace.as_ref(),
+        userns_fd: None,
+        working_directory: svc.working_directory(),
+        root_dir: svc.root_dir(),
+        mounts: &mounts,
+        exec_label: exec_label.as_ref(),
+        private_keyring: svc
+            .config
+            .keyring
+            .unwrap_or_else(|| KeyringMode::default_for(ctx.strategy))
+            == KeyringMode::Private,
+        cgroup_fd: cgroup_fd.as_ref().map(|fd| fd.as_fd()),
+        devnull_fd: devnull_fd.as_fd(),
+        stdout_fd,
+        stderr_fd,
+        inherited_fds: &inherited_fds,
+        exec_fd: Some(exec_wr.as_fd()),
+    };
+    let Spawned { pid: raw, pidfd } = match ctx.spawner.and_then(|spawner| spawner.spawn(&spec)) {
+        Some(spawned) => Spawned {
+            pid: spawned?,
+            pidfd: None,
+        },
+        None => ctx.strategy.spawn(&spec, ctx.sigset)?,
+    };
+    // safe as both the spawn strategies and the spawner return a pid > 0
+    let pid = unsafe { Pid::from_raw_unchecked(raw) };
+    let start_time = ProcStat::read(raw).ok().map(|stat| stat.start_time);
+    // registered before the start is committed, so that a failure doesn't
+    // leave a process that nothing reaps
+    if let Err(e) = register_start_fds(ctx.epfd, svc.id, &exec_rd, log_pump.as_ref()) {
+        let _ = kill_process(pid, Signal::KILL);
+        let _ = waitpid(Some(pid), WaitOptions::empty());
+        return Err(e);
+    }
+    svc.state = ServiceState::Running(pid);
+    svc.start_count += 1;
+    svc.ready_notified = false;
+    svc.start_limited = false;
+    svc.history.started(raw);
+    svc.pending_finish = None;
+    svc.started_at = Some(Instant::now());
+    svc.bound_hold = false;
+    svc.start_time = start_time;
+    svc.pidfd = pidfd.or_else(|| open_pidfd(pid, &svc.name));
+    svc.resource_monitor = ResourceMonitor::default();
+    svc.spawn_error = None;
+    svc.private_tmp = private_tmp;
+    svc.cgroup = cgroup;
+    svc.env_file_vars = env_file_vars;
+    svc.running_config_hash = Some(svc.config_hash);
+    svc.flags.set(RecordFlags::ENV_STALE, false);
+    if let Some(timer) = svc.timer.as_mut()
+        && timer.queued
+    {
+        // the start runs the activation the timer queued
+        timer.queued = false;
+        timer.last = Some(timestamp().0);
+    }
+    // close our copy of the write ends, so that the pump sees
+    // `EOF` once the service (and its descendants) are gone, and
+    // the exec pipe as soon as the service process execs
+    drop(log_pipes);
+    drop(exec_wr);
+    svc.exec_pipe = Some(exec_rd);
+    svc.log_pump = log_pump;
+    Ok(())
+}
+
+/// Open a pidfd of the process `pid` of service `name`, a child which
+/// isn't reaped yet or an adopted process. Returns `None` if the kernel
+/// doesn't support pidfds or it fails, the service process is then
+/// signaled by pid
+pub(crate) fn open_pidfd(pid: Pid, name: &str) -> Option<OwnedFd> {
+    if !Platform::get().pidfd {
+        return None;
+    }
+    match pidfd_open(pid, PidfdFlags::empty()) {
+        Ok(pidfd) => Some(pidfd),
+        Err(e) => {
+            svlogg!(
+                LogLevel::Warn,
+                "failed to open pidfd of service '{}': {}",
+                name,
+                e
+            );
+            None
         }
-        _ => Err(io::Error::last_os_error()),
     }
 }
 
+/// Start the `finish` command of a stopped service, if it has one and its
+/// last process exited with `exit_reason`.
+///
+/// The command runs in the service working directory. Its pid is stored in
+/// `svc.finish_pid`, the caller is responsible for registering it
+pub(crate) fn start_finish(
+    svc: &mut Service,
+    exit_reason: ExitReason,
+    ctx: &SpawnContext,
+) -> io::Result<Option<Pid>> {
+    let Some(finish) = &svc.config.finish else {
+        return Ok(None);
+    };
+    let (code, signal) = match exit_reason {
+        ExitReason::Exited(code) => (code, 0),
+        ExitReason::Signaled(signal) => (-1, signal),
+    };
+    let mut command = hook_command("finish", finish, ctx.sigset);
+    command
+        .args([code.to_string(), signal.to_string()])
+        .env("SVLOPP_SERVICE", &*svc.name);
+    if let Some(dir) = svc.working_directory() {
+        command.current_dir(dir);
+    }
+    let child = command.spawn()?;
+    // the child is reaped by `handle_sigchld`, not through `child`
+    let pid = Pid::from_raw(child.id() as i32).ok_or_else(|| io::Error::other("invalid pid"))?;
+    svc.finish_pid = Some(pid);
+    Ok(Some(pid))
+}
+
+/// Register the exec pipe and the log pump streams of a process of the
+/// service `svc_id` with the main loop `epfd`
+fn register_start_fds(
+    epfd: BorrowedFd,
+    svc_id: u64,
+    exec_rd: &OwnedFd,
+    log_pump: Option<&LogPump>,
+) -> io::Result<()> {
+    epoll::add(
+        epfd,
+        exec_rd,
+        epoll::EventData::new_u64(EXEC_ID_TAG | svc_id),
+        epoll::EventFlags::IN,
+    )?;
+    for (stream, fd) in log_pump.into_iter().flat_map(LogPump::open_streams) {
+        epoll::add(
+            epfd,
+            fd,
+            epoll::EventData::new_u64(stream.epoll_id(svc_id)),
+            epoll::EventFlags::IN,
+        )?;
+    }
+    Ok(())
+}
+
 /// Stop a service by sending the configured stop signal and marks it as
 /// stopping by setting state to `ServiceState::Stopping`.
 /// This is a state transition: it only acts on `ServiceState::Running`
-/// services and is a no-op for any other state
+/// and `ServiceState::Paused` services, the latter being thawed first so
+/// that they can handle the signal, and is a no-op for any other state
 pub(crate) fn stop_service(svc: &mut Service) -> io::Result<()> {
+    if let ServiceState::Paused(p) = svc.state {
+        svc.freeze(false)?;
+        svc.state = ServiceState::Running(p);
+    }
     match svc.state {
         ServiceState::Running(p) => {
-            kill_process(p, svc.stop_signal())?;
-            svc.state = ServiceState::Stopping(p, Instant::now() + svc.stop_timeout());
+            svc.signal(svc.stop_signal())?;
+            let now = Instant::now();
+            svc.state = ServiceState::Stopping(p, now + svc.stop_timeout());
+            svc.stop_timing = Some(StopTiming {
+                requested_at: now,
+                reaped_at: None,
+                force_killed: false,
+            });
             Ok(())
         }
         _ => Ok(()),
     }
 }
 
-/// Send `SIGKILL` to the given process.
+/// Freeze the processes of a running service and mark it as paused by
+/// setting state to `ServiceState::Paused`.
+/// This is a state transition: it only acts on `ServiceState::Running`
+/// services and is a no-op for any other state
+pub(crate) fn pause_service(svc: &mut Service) -> io::Result<()> {
+    if let ServiceState::Running(p) = svc.state {
+        svc.freeze(true)?;
+        svc.state = ServiceState::Paused(p);
+    }
+    Ok(())
+}
+
+/// Thaw the processes of a paused service and mark it as running again.
+/// This is a state transition: it only acts on `ServiceState::Paused`
+/// services and is a no-op for any other state
+pub(crate) fn resume_service(svc: &mut Service) -> io::Result<()> {
+    if let ServiceState::Paused(p) = svc.state {
+        svc.freeze(false)?;
+        svc.state = ServiceState::Running(p);
+    }
+    Ok(())
+}
+
+/// Send `SIGKILL` to the service process.
 ///
 /// This is pure mechanism and has no state awareness. The caller is
 /// responsible for maintaining the invariants and performing any
 /// required state transitions
-pub(crate) fn force_kill_service_process(pid: Pid) -> io::Result<()> {
-    kill_process(pid, Signal::KILL)?;
-    Ok(())
+pub(crate) fn force_kill_service_process(svc: &Service) -> io::Result<()> {
+    svc.signal(Signal::KILL)
 }
 
 /// The services registry.
 ///
 /// Holds all the services in the form of
-/// two hashmaps:
-/// 1. `service_id -> service` to lookup
-///    services fast via their id.
-/// 2. `pid -> service_id` to get a service_id
-///    from a pid.
+/// two maps:
+/// 1. `service_id -> service`, a `ServiceSlab`,
+///    to lookup services fast via their id.
+/// 2. `pid -> service_id`, a hashmap, to get a
+///    service_id from a pid.
 ///
 /// Services are loaded into `service_id -> service` as
 /// soon as they're discovered (e.g. when deserializing
 /// from config files) and pid association are inserted
 /// in `pid -> service_id` after the child process has
 /// been successfully forked (in the parent).
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Default)]
 pub(crate) struct ServiceRegistry {
     /// `service_id -> service`
-    services_map: HashMap<u64, Service>,
+    services_map: ServiceSlab,
     /// `pid -> service_id`
     pids_map: HashMap<Pid, u64>,
+    limits: ProcessLimits,
+    /// Services whose start waits for the services they conflict with to
+    /// stop
+    conflict_waiters: HashSet<u64>,
+    /// Control operations waiting for a start or stop slot
+    jobs: JobScheduler,
+    /// Log pumps of detached processes, drained until they reach `EOF`,
+    /// by the id of their epoll events
+    detached_pumps: HashMap<u64, LogPump>,
 }
 
 impl ServiceRegistry {
@@ -614,7 +2446,7 @@ impl ServiceRegistry {
     /// Insert a new service in the `service_id -> service` map
     #[inline(always)]
     pub(crate) fn insert_service(&mut self, svc: Service) {
-        self.services_map.insert(svc.id, svc);
+        self.services_map.insert(svc);
     }
 
     /// Get a shared reference to the service correspondig to
@@ -622,14 +2454,14 @@ impl ServiceRegistry {
     #[allow(dead_code)]
     #[inline(always)]
     pub(crate) fn service(&self, svc_id: u64) -> Option<&Service> {
-        self.services_map.get(&svc_id)
+        self.services_map.get(svc_id)
     }
 
     /// Get a mutable reference to the service corresponding to
     /// `svc_id` if it exists in the `service_id -> service` map
     #[inline(always)]
     pub(crate) fn service_mut(&mut self, svc_id: u64) -> Option<&mut Service> {
-        self.services_map.get_mut(&svc_id)
+        self.services_map.get_mut(svc_id)
     }
 
     /// Insert a new pid in the `pid -> service_id` map.
@@ -645,7 +2477,26 @@ impl ServiceRegistry {
     #[inline(always)]
     pub(crate) fn get_by_pid(&self, pid: Pid) -> Option<&Service> {
         let svc_id = self.pids_map.get(&pid)?;
-        self.services_map.get(svc_id)
+        self.services_map.get(*svc_id)
+    }
+
+    /// Record that the service process `pid` notified readiness
+    pub(crate) fn notify_ready(&mut self, pid: Pid) {
+        let Some(svc_id) = self.pids_map.get(&pid) else {
+            svlogg!(
+                LogLevel::Debug,
+                "readiness notification from unknown pid {}",
+                pid.as_raw_nonzero()
+            );
+            return;
+        };
+        if let Some(svc) = self.services_map.get_mut(*svc_id)
+            && matches!(svc.state, ServiceState::Running(p) if p == pid)
+            && !svc.ready_notified
+        {
+            svc.ready_notified = true;
+            svlogg!(LogLevel::Info, "service '{}' is ready", svc.name);
+        }
     }
 
     /// Remove `pid` from the `pid -> service_id` map if exists and
@@ -654,197 +2505,1388 @@ impl ServiceRegistry {
     #[inline(always)]
     pub(crate) fn take_by_pid(&mut self, pid: Pid) -> Option<&mut Service> {
         let svc_id = self.pids_map.remove(&pid)?;
-        self.services_map.get_mut(&svc_id)
+        self.services_map.get_mut(svc_id)
     }
 
     #[inline(always)]
-    pub(crate) fn services(&self) -> std::collections::hash_map::Values<'_, u64, Service> {
-        self.services_map.values()
+    pub(crate) fn services(&self) -> std::slice::Iter<'_, Service> {
+        self.services_map.iter()
     }
 
     #[inline(always)]
-    pub(crate) fn services_mut(
-        &mut self,
-    ) -> std::collections::hash_map::ValuesMut<'_, u64, Service> {
-        self.services_map.values_mut()
+    pub(crate) fn services_mut(&mut self) -> std::slice::IterMut<'_, Service> {
+        self.services_map.iter_mut()
+    }
+
+    pub(crate) fn get_by_name(&self, name: &str) -> Option<&Service> {
+        self.services_map.iter().find(|svc| &*svc.name == name)
+    }
+
+    /// Whether the service `svc_id` can be started as far as `bind_to` is
+    /// concerned, i.e. it's not bound or its bound service is running
+    pub(crate) fn is_bind_target_running(&self, svc_id: u64) -> bool {
+        let Some(target) = self
+            .service(svc_id)
+            .and_then(|svc| svc.config.bind_to.as_deref())
+        else {
+            return true;
+        };
+        self.get_by_name(target).is_some_and(|svc| {
+            matches!(
+                svc.state,
+                ServiceState::Running(_) | ServiceState::Paused(_)
+            )
+        })
     }
 
     #[inline(always)]
-    pub(crate) fn remove_service(&mut self, svc_id: u64) -> Option<Service> {
-        self.services_map.remove(&svc_id)
+    pub(crate) fn set_process_limits(&mut self, limits: ProcessLimits) {
+        self.limits = limits;
     }
 
-    /// Execute a closure with mutable access to both `services_map` and
-    /// `pids_map`.
-    ///
-    /// This exists to allow for use cases like iterating through services
-    /// to restart or remove them while also keep the pid index in sync,
-    /// without exposing the maps publicly
     #[inline(always)]
-    pub(crate) fn with_maps_mut<R>(
-        &mut self,
-        f: impl FnOnce(&mut HashMap<u64, Service>, &mut HashMap<Pid, u64>) -> R,
-    ) -> R {
-        f(&mut self.services_map, &mut self.pids_map)
+    pub(crate) fn set_job_limits(&mut self, limits: JobLimits) {
+        self.jobs.set_limits(limits);
     }
 
-    pub(crate) fn format_status(&self, w: &mut impl fmt::Write) -> fmt::Result {
-        for svc in self.services_map.values() {
-            svc.format_status_line(w)?;
-            w.write_char('\n')?;
+    #[inline(always)]
+    pub(crate) fn jobs(&self) -> &JobScheduler {
+        &self.jobs
+    }
+
+    /// Number of services starting and stopping at `now`, indexed by
+    /// `JobKind`
+    pub(crate) fn jobs_in_progress(&self, now: Instant) -> [usize; 2] {
+        jobs::transitions(&self.services_map, now)
+    }
+
+    /// Number of services that can start now under
+    /// `max_concurrent_starts`, `None` without a limit
+    pub(crate) fn free_start_slots(&self) -> Option<usize> {
+        let limit = self.jobs.limits().max_concurrent_starts?;
+        let starting = self.jobs_in_progress(Instant::now())[JobKind::Start as usize];
+        Some(limit.get().saturating_sub(starting))
+    }
+
+    /// Whether `op` on `svc_id` waits for a start or stop slot, see
+    /// `JobScheduler::schedule`
+    fn schedule_job(&mut self, svc_id: u64, op: ControlOp) -> bool {
+        if !matches!(op, ControlOp::Start | ControlOp::Stop | ControlOp::Restart) {
+            return false;
+        }
+        let scheduled = self.jobs.schedule(&mut self.services_map, svc_id, op);
+        if scheduled && let Some(svc) = self.services_map.get(svc_id) {
+            match svc.scheduled {
+                Some(op) => svlogg!(
+                    LogLevel::Info,
+                    "{} of service '{}' waits for a free slot",
+                    op,
+                    svc.name
+                ),
+                None => svlogg!(
+                    LogLevel::Info,
+                    "dropped the queued operation of service '{}'",
+                    svc.name
+                ),
+            }
+        }
+        scheduled
+    }
+
+    /// Cancel the latest job of `svc_id`: the operation waiting for a
+    /// slot, else the start queued behind the ongoing stop, else the stop
+    /// itself, by killing the service process right away. Each cancel
+    /// undoes one of them, a restart needing two to be killed
+    pub(crate) fn cancel_job(&mut self, svc_id: u64) -> io::Result<()> {
+        let Some(svc) = self.services_map.get_mut(svc_id) else {
+            svlogg!(LogLevel::Warn, "unkown service id: {}", svc_id);
+            return Ok(());
+        };
+        if let Some(op) = svc.scheduled.take() {
+            self.jobs.remove(svc_id);
+            svlogg!(LogLevel::Info, "cancelled {} of service '{}'", op, svc.name);
+            return Ok(());
         }
+        if svc.pending_action == ServicePendingAction::Restart {
+            svc.pending_action = ServicePendingAction::None;
+            svlogg!(
+                LogLevel::Info,
+                "cancelled the start of service '{}', once stopped",
+                svc.name
+            );
+            return Ok(());
+        }
+        if let ServiceState::Stopping(..) = svc.state {
+            force_kill_service_process(svc)?;
+            if let Some(timing) = svc.stop_timing.as_mut() {
+                timing.force_killed = true;
+            }
+            svlogg!(
+                LogLevel::Info,
+                "cancelled the stop of service '{}', killed it",
+                svc.name
+            );
+            return Ok(());
+        }
+        svlogg!(
+            LogLevel::Debug,
+            "no job of service '{}' to cancel",
+            svc.name
+        );
         Ok(())
     }
-}
 
-/// Parse the configuration file and apply changes
-///
-/// For new services (not present in the registry, but present in the new
-/// config), insert it in the registry and starts it.
-///
-/// For removed services (present in the registry, but not present in the
-/// new config):
-/// * If the service state is `ServiceState::Stopped(_)`: remove the service
-///   from the registry immediately.
-/// * If `ServiceState::Running`: call `stop_service` and mark for removal so
-///   that it can be removed once the process has been reaped.
-/// * If `ServiceState::Stopping(_)`: just mark for removal.
-///
-/// For changed services (present in both the registry and the new config
-/// but with different configurations):
-/// * If the service state is `ServiceState::Stopped(_)`: update the config,
-///   rebuild argv, then start it.
-/// * If `ServiceState::Running`: call `stop_service`, store new config and
-///   mark for restart so that it can be restarted after the process has been
-///   reaped.
-/// * if `ServiceState::Stopping(_)`: just store the new config and mark for
-///   restart.
-pub(crate) fn reload_services(
-    registry: &mut ServiceRegistry,
-    cfg_path: &Path,
-    id_gen: &mut ServiceIdGen,
-    sigset: &SigSet,
-) -> io::Result<()> {
-    let service_configs = ServiceConfigData::from_config_file(cfg_path)?;
-    let mut service_ids = HashMap::new();
-    for svc in registry.services() {
-        service_ids.insert(svc.name.clone(), svc.id);
-    }
-    for (name, &svc_id) in service_ids.iter() {
-        if !service_configs.services.contains_key(name)
-            && let Some(svc) = registry.service_mut(svc_id)
-        {
-            match svc.state {
-                ServiceState::Stopped(_) => {
-                    svlogg!(LogLevel::Info, "removing stopped service '{}'", name);
-                    svc.pending_action = ServicePendingAction::None;
-                    let _ = registry.remove_service(svc_id);
-                }
-                ServiceState::Stopping(_, _) => {
-                    svlogg!(LogLevel::Info, "stopping service '{}' for removal", name);
-                    svc.pending_action = ServicePendingAction::Remove;
-                }
-                ServiceState::Running(_) => {
-                    svlogg!(LogLevel::Info, "stopping service '{}' for removal", name);
-                    svc.pending_action = ServicePendingAction::Remove;
-                    stop_service(svc)?;
+    /// Drop the control operations waiting for a slot, e.g. on shutdown
+    pub(crate) fn clear_jobs(&mut self) {
+        self.jobs.clear(&mut self.services_map);
+    }
+
+    /// Room left to start services under the `ProcessLimits`. The process
+    /// table is only scanned with `max_total_processes`
+    pub(crate) fn capacity(&self) -> Capacity {
+        let running = self.services_map.iter().filter_map(Service::pid);
+        let services = running.clone().count();
+        let mut processes = services;
+        if self.limits.max_total_processes.is_some() {
+            match ProcessTable::scan() {
+                Ok(table) => {
+                    let mut descendants = Vec::new();
+                    for pid in running {
+                        table.descendants(pid.as_raw_nonzero().get(), &mut descendants);
+                        processes += descendants.len();
+                    }
                 }
+                Err(e) => svlogg!(LogLevel::Warn, "failed to scan processes: {}", e),
             }
         }
+        Capacity {
+            limits: self.limits,
+            services,
+            processes,
+        }
     }
 
-    for (name, cfg) in service_configs.services {
-        match service_ids.get(&name) {
-            None => {
-                svlogg!(LogLevel::Debug, "adding new service '{}'", name);
-                let svc_id = id_gen
-                    .nextval()
-                    .ok_or_else(|| io::Error::other("service id overflow"))?;
-                let mut svc = Service::new(svc_id, name, cfg)?;
-                start_service(&mut svc, sigset)?;
-                let svc_pid = svc.pid().expect("running service must have a pid");
-                svlogg!(
-                    LogLevel::Info,
-                    "started new service '{}' with pid {}",
-                    svc.name,
-                    svc_pid
-                );
-                registry.insert_service(svc);
-                registry.register_pid(svc_pid, svc_id);
+    /// Refresh the known descendants of the services with
+    /// `kill_descendants`. The process table is only scanned when at least
+    /// one of them is running
+    pub(crate) fn refresh_descendants(&mut self) {
+        if !self
+            .services_map
+            .iter()
+            .any(|svc| svc.config.kill_descendants && svc.pid().is_some())
+        {
+            return;
+        }
+        let table = match ProcessTable::scan() {
+            Ok(table) => table,
+            Err(e) => {
+                svlogg!(LogLevel::Warn, "failed to scan processes: {}", e);
+                return;
             }
-            Some(&svc_id) => {
-                if let Some(svc) = registry.service_mut(svc_id)
-                    && (svc.config != cfg)
-                {
-                    svlogg!(LogLevel::Debug, "config changed for service {}", name);
-                    // Update the config now so that when the process is eventually
-                    // restarted, it uses the new definition. The currently running
-                    // process continues with the old config until it exits.
-                    svc.update_config(cfg)?;
-                    match svc.state {
-                        ServiceState::Stopped(_) => {
-                            svlogg!(
-                                LogLevel::Info,
-                                "service '{}' was stopped, starting with new config",
-                                name
-                            );
-                            start_service(svc, sigset)?;
-                            let svc_pid = svc.pid().expect("running service must have a pid");
-                            registry.register_pid(svc_pid, svc_id);
-                        }
-                        ServiceState::Stopping(_, _) => {
-                            svlogg!(LogLevel::Info, "service '{}' will be restarted", name);
-                            svc.pending_action = ServicePendingAction::Restart;
-                        }
-                        ServiceState::Running(_) => {
-                            svlogg!(LogLevel::Info, "service '{}' will be restarted", name);
-                            svc.pending_action = ServicePendingAction::Restart;
-                            stop_service(svc)?;
-                        }
-                    }
+        };
+        for svc in self.services_map.iter_mut() {
+            if svc.config.kill_descendants
+                && let Some(pid) = svc.pid()
+            {
+                table.descendants(pid.as_raw_nonzero().get(), &mut svc.descendants);
+            }
+        }
+    }
+
+    /// Reconcile services whose process vanished without being reaped,
+    /// e.g. after a missed `SIGCHLD`. A process that `waitid` doesn't know
+    /// as a child anymore is marked as stopped with
+    /// `ServiceStopReason::Lost`, a failure. Without a pidfd, so is one
+    /// whose pid is now used by a child started at another time, when
+    /// procfs is there to tell.
+    ///
+    /// Exited processes that weren't reaped are still around as zombies,
+    /// and are left to `handle_sigchld`
+    pub(crate) fn sweep_lost(&mut self, diagnostics_dir: &Path) {
+        let mut lost = Vec::new();
+        for svc in self.services_map.iter_mut() {
+            // see `reap_adopted`
+            if svc.adopted {
+                continue;
+            }
+            let Some(pid) = svc.pid() else {
+                continue;
+            };
+            let pidfd = svc.pidfd.as_ref().map(|pidfd| pidfd.as_fd());
+            let vanished = match peek_child(pid, pidfd) {
+                Ok(ChildState::Gone) => true,
+                Ok(ChildState::Running) if pidfd.is_none() => svc.start_time.is_some_and(|t| {
+                    ProcStat::read(pid.as_raw_nonzero().get())
+                        .is_ok_and(|stat| stat.start_time != t)
+                }),
+                Ok(ChildState::Running | ChildState::Exited) => false,
+                Err(e) => {
+                    svlogg!(
+                        LogLevel::Warn,
+                        "failed to check process {} of service '{}': {}",
+                        pid,
+                        svc.name,
+                        e
+                    );
+                    false
                 }
+            };
+            if !vanished {
+                continue;
+            }
+            svlogg!(
+                LogLevel::Warn,
+                "process {} of service '{}' vanished without being reaped",
+                pid,
+                svc.name
+            );
+            self.pids_map.remove(&pid);
+            let uptime_ms = svc.started_at.map(|t| t.elapsed().as_millis() as u64);
+            svc.history.exited(ServiceStopReason::Lost, uptime_ms, None);
+            if let ServiceState::Stopping(_, _) = svc.state
+                && let Some(timing) = svc.stop_timing.as_mut()
+            {
+                timing.reaped_at = Some(Instant::now());
+            }
+            svc.state = ServiceState::Stopped(ServiceStopReason::Lost);
+            svc.start_time = None;
+            svc.pidfd = None;
+            svc.bound_stop = false;
+            svc.runtime_exceeded = false;
+            if svc.record_failure() {
+                write_diagnostics(svc, diagnostics_dir);
             }
+            lost.push(svc.name.clone());
+        }
+        for name in lost {
+            self.stop_bound_to(&name, ServiceStopReason::Lost);
         }
     }
-    Ok(())
-}
 
-/// Apply a control operation,
-///
-/// Control operations are treated as *requests*, meaning they must:
-/// - Respect the current state.
-/// - Never override an existing pending action.
+    /// Mark as stopped the services whose adopted process exited, with the
+    /// `ServiceStopReason::Exited` stop reason, not a failure. Run on each
+    /// timerfd tick
+    pub(crate) fn reap_adopted(&mut self) {
+        let mut exited = Vec::new();
+        for svc in self.services_map.iter_mut() {
+            if !svc.adopted || !has_exited(svc) {
+                continue;
+            }
+            let Some(pid) = svc.pid() else {
+                continue;
+            };
+            svlogg!(
+                LogLevel::Info,
+                "adopted process {} of service '{}' exited",
+                pid,
+                svc.name
+            );
+            self.pids_map.remove(&pid);
+            let uptime_ms = svc.started_at.map(|t| t.elapsed().as_millis() as u64);
+            svc.history
+                .exited(ServiceStopReason::Exited, uptime_ms, None);
+            if let ServiceState::Stopping(_, _) = svc.state
+                && let Some(timing) = svc.stop_timing.as_mut()
+            {
+                timing.reaped_at = Some(Instant::now());
+            }
+            svc.state = ServiceState::Stopped(ServiceStopReason::Exited);
+            svc.start_time = None;
+            svc.pidfd = None;
+            svc.adopted = false;
+            svc.bound_stop = false;
+            svc.runtime_exceeded = false;
+            exited.push(svc.name.clone());
+        }
+        for name in exited {
+            self.stop_bound_to(&name, ServiceStopReason::Exited);
+        }
+    }
+
+    /// Stop supervising the process of the running service `svc_id`, which
+    /// is left running: the service is marked as stopped with the
+    /// `ServiceStopReason::Detached` stop reason and isn't restarted.
+    /// Services bound to it are left alone, the process being still there.
+    /// The process leaves the cgroup of the service and keeps its private
+    /// `/tmp`, and its output is still pumped to the log file, under an id
+    /// from `id_gen` so that the next start doesn't collide with it.
+    /// svlopp reaps the process as any orphan once it exits, or it's
+    /// reparented to init when svlopp exits
+    pub(crate) fn detach(
+        &mut self,
+        svc_id: u64,
+        id_gen: &mut ServiceIdGen,
+        epfd: BorrowedFd,
+    ) -> io::Result<()> {
+        let svc = self
+            .services_map
+            .get_mut(svc_id)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unknown service"))?;
+        let ServiceState::Running(pid) = svc.state else {
+            return Err(io::Error::new(
+                io::ErrorKind::ResourceBusy,
+                format!("service '{}' isn't running", svc.name),
+            ));
+        };
+        if !svc.pending_action.is_none() {
+            return Err(jobs::in_progress());
+        }
+        if let Some(pump) = &svc.log_pump {
+            let id = id_gen
+                .nextval()
+                .ok_or_else(|| io::Error::other("service ids exhausted"))?;
+            for (stream, fd) in pump.open_streams() {
+                epoll::modify(
+                    epfd,
+                    fd,
+                    epoll::EventData::new_u64(stream.epoll_id(id)),
+                    epoll::EventFlags::IN,
+                )?;
+            }
+            self.detached_pumps
+                .extend(svc.log_pump.take().map(|pump| (id, pump)));
+        }
+        if let Some(cgroup) = svc.cgroup.take()
+            && let Err(e) = cgroup.evict()
+        {
+            svlogg!(
+                LogLevel::Warn,
+                "failed to move detached process {} out of the cgroup of service '{}': {}",
+                pid,
+                svc.name,
+                e
+            );
+        }
+        if let Some(tmp) = svc.private_tmp.take()
+            && let Err(e) = tmp.forget(pid)
+        {
+            svlogg!(
+                LogLevel::Warn,
+                "failed to move aside the private /tmp of detached process {}: {}",
+                pid,
+                e
+            );
+        }
+        self.pids_map.remove(&pid);
+        let reason = ServiceStopReason::Detached(pid.as_raw_nonzero().get());
+        let uptime_ms = svc.started_at.map(|t| t.elapsed().as_millis() as u64);
+        svc.history.exited(reason, uptime_ms, None);
+        svc.state = ServiceState::Stopped(reason);
+        svc.start_time = None;
+        svc.pidfd = None;
+        svc.adopted = false;
+        svc.descendants.clear();
+        svlogg!(
+            LogLevel::Info,
+            "detached process {} of service '{}', left running",
+            pid,
+            svc.name
+        );
+        Ok(())
+    }
+
+    /// Get the log pump of a detached process by the id of its epoll events
+    pub(crate) fn detached_pump_mut(&mut self, id: u64) -> Option<&mut LogPump> {
+        self.detached_pumps.get_mut(&id)
+    }
+
+    /// Drop the log pump of a detached process, once it reached `EOF`
+    pub(crate) fn remove_detached_pump(&mut self, id: u64) {
+        self.detached_pumps.remove(&id);
+    }
+
+    /// Iterate over the log pumps of detached processes
+    pub(crate) fn detached_pumps_mut(&mut self) -> impl Iterator<Item = &mut LogPump> {
+        self.detached_pumps.values_mut()
+    }
+
+    /// Restore the properties persisted by a previous supervisor
+    pub(crate) fn restore_properties(&mut self, mut properties: HashMap<String, Vec<Property>>) {
+        for svc in self.services_map.iter_mut() {
+            for property in properties.remove(&*svc.name).into_iter().flatten() {
+                // the service has no cgroup yet
+                let _ = svc.set_property(property);
+            }
+        }
+    }
+
+    /// Restore the restart history persisted by a previous supervisor
+    pub(crate) fn restore_restarts(&mut self, mut records: HashMap<String, RestartRecord>) {
+        for svc in self.services_map.iter_mut() {
+            if let Some(record) = records.remove(&*svc.name) {
+                svc.start_count = record.start_count;
+                svc.failures = record.failures;
+            }
+        }
+    }
+
+    /// Stop the running services that ran for longer than their
+    /// `runtime_max_ms` as of `now`
+    pub(crate) fn stop_overdue(&mut self, now: Instant) {
+        for svc in self.services_map.iter_mut() {
+            let (Some(max_ms), Some(started_at)) = (svc.config.runtime_max_ms, svc.started_at)
+            else {
+                continue;
+            };
+            if !matches!(
+                svc.state,
+                ServiceState::Running(_) | ServiceState::Paused(_)
+            ) || now < started_at + Duration::from_millis(max_ms)
+            {
+                continue;
+            }
+            match stop_service(svc) {
+                Ok(()) => {
+                    svc.runtime_exceeded = true;
+                    svlogg!(
+                        LogLevel::Info,
+                        "stopping service '{}', running for longer than {}ms",
+                        svc.name,
+                        max_ms
+                    );
+                }
+                Err(e) => svlogg!(
+                    LogLevel::Error,
+                    "failed to stop service '{}': {}",
+                    svc.name,
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Sample the cgroup pressure of the running services that don't share
+    /// the supervisor cgroup. Only the services whose `resource_limits`
+    /// use it are sampled, unless `all` is set
+    pub(crate) fn sample_pressure(&mut self, cgroups: &Cgroups, all: bool) {
+        for svc in self.services_map.iter_mut() {
+            let wanted = all
+                || svc
+                    .config
+                    .resource_limits
+                    .is_some_and(|limits| limits.uses_pressure());
+            svc.pressure = match svc.pid() {
+                Some(pid) if wanted => read_cgroup(pid.as_raw_nonzero().get())
+                    .filter(|cgroup| !cgroups.is_supervisor(cgroup))
+                    .and_then(|cgroup| match ServicePressure::read(cgroups, cgroup) {
+                        Ok(pressure) => Some(pressure),
+                        Err(e) => {
+                            svlogg!(
+                                LogLevel::Debug,
+                                "failed to read pressure of service '{}': {}",
+                                svc.name,
+                                e
+                            );
+                            None
+                        }
+                    }),
+                _ => None,
+            };
+        }
+    }
+
+    /// Sample the resource usage of the running services with
+    /// `resource_limits`, and act on those exceeding them. The process
+    /// table is only scanned when at least one of them is running
+    pub(crate) fn check_resources(&mut self, now: Instant) {
+        if !self
+            .services_map
+            .iter()
+            .any(|svc| svc.config.resource_limits.is_some() && svc.pid().is_some())
+        {
+            return;
+        }
+        let table = match ProcessTable::scan() {
+            Ok(table) => table,
+            Err(e) => {
+                svlogg!(LogLevel::Warn, "failed to scan processes: {}", e);
+                return;
+            }
+        };
+        let mut procs = Vec::new();
+        for svc in self.services_map.iter_mut() {
+            let (Some(limits), ServiceState::Running(pid)) =
+                (svc.config.resource_limits, svc.state)
+            else {
+                continue;
+            };
+            let pid = pid.as_raw_nonzero().get();
+            let Ok(root) = ProcStat::read(pid) else {
+                continue;
+            };
+            table.descendants(pid, &mut procs);
+            let usage = ResourceUsage::of(std::iter::once(&root).chain(&procs));
+            let Some(exceeded) =
+                svc.resource_monitor
+                    .sample(&limits, usage, svc.pressure.as_ref(), now)
+            else {
+                continue;
+            };
+            svlogg!(
+                LogLevel::Warn,
+                "service '{}' exceeded its resource limits: {}",
+                svc.name,
+                exceeded
+            );
+            let result = match limits.action {
+                ResourceAction::Log => Ok(()),
+                ResourceAction::Restart => {
+                    svc.pending_action = ServicePendingAction::Restart;
+                    stop_service(svc)
+                }
+                ResourceAction::Stop => stop_service(svc),
+            };
+            if let Err(e) = result {
+                svlogg!(
+                    LogLevel::Error,
+                    "failed to stop service '{}': {}",
+                    svc.name,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Flag the services whose process holds at least `fd_warn_percent` of
+    /// its open file limit in file descriptors, and clear the flag of
+    /// the others
+    pub(crate) fn check_fds(&mut self) {
+        for svc in self.services_map.iter_mut() {
+            let high = match (svc.config.fd_warn_percent, svc.pid()) {
+                (Some(percent), Some(pid)) => {
+                    let pid = pid.as_raw_nonzero().get();
+                    // the process may exit between the reads
+                    match (fd_count(pid), nofile_limit(pid)) {
+                        (Ok(count), Ok(Some(limit))) => {
+                            count * 100 >= limit * u64::from(percent.get())
+                        }
+                        _ => svc.flags.contains(RecordFlags::FDS_HIGH),
+                    }
+                }
+                _ => false,
+            };
+            if high == svc.flags.contains(RecordFlags::FDS_HIGH) {
+                continue;
+            }
+            svc.flags.set(RecordFlags::FDS_HIGH, high);
+            if high {
+                svlogg!(
+                    LogLevel::Warn,
+                    "service '{}' holds at least {}% of its open file limit",
+                    svc.name,
+                    svc.config.fd_warn_percent.map_or(0, NonZeroU8::get)
+                );
+            }
+        }
+    }
+
+    /// Read again the `env_file`s in `dirs` of the running services, and
+    /// flag those whose variables differ from the ones their process got.
+    /// Newly flagged services are restarted with `on_env_file_change =
+    /// "restart"`
+    pub(crate) fn env_files_changed(&mut self, dirs: &[PathBuf]) {
+        for svc in self.services_map.iter_mut() {
+            let (Some(path), Some(started_with)) =
+                (svc.config.env_file.as_deref(), svc.env_file_vars.as_ref())
+            else {
+                continue;
+            };
+            if !matches!(
+                svc.state,
+                ServiceState::Running(_) | ServiceState::Paused(_)
+            ) || !path
+                .parent()
+                .is_some_and(|dir| dirs.iter().any(|d| d == dir))
+            {
+                continue;
+            }
+            let stale = match read_env_file(path) {
+                Ok(vars) => vars != *started_with,
+                Err(e) => {
+                    // e.g. in the middle of a rotation, the next start fails
+                    // if it's still missing
+                    svlogg!(LogLevel::Warn, "service '{}': {}", svc.name, e);
+                    continue;
+                }
+            };
+            if stale == svc.flags.contains(RecordFlags::ENV_STALE) {
+                continue;
+            }
+            svc.flags.set(RecordFlags::ENV_STALE, stale);
+            if !stale {
+                continue;
+            }
+            if svc.config.on_env_file_change == EnvFileChange::Mark {
+                svlogg!(
+                    LogLevel::Info,
+                    "env_file of service '{}' changed, applies from its next start",
+                    svc.name
+                );
+                continue;
+            }
+            svlogg!(
+                LogLevel::Info,
+                "env_file of service '{}' changed, restarting it",
+                svc.name
+            );
+            svc.pending_action = ServicePendingAction::Restart;
+            if let Err(e) = stop_service(svc) {
+                svlogg!(
+                    LogLevel::Error,
+                    "failed to stop service '{}': {}",
+                    svc.name,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Ids of the services whose process runs an outdated config
+    pub(crate) fn outdated(&self) -> Vec<u64> {
+        self.services_map
+            .iter()
+            .filter(|svc| svc.is_outdated())
+            .map(|svc| svc.id)
+            .collect()
+    }
+
+    /// Restore the timers persisted by a previous supervisor, counting the
+    /// activations elapsed while it was down at `now`, in seconds since
+    /// the epoch, as missed. A `persistent` timer instead queues the start
+    /// of its service for the latest of them
+    pub(crate) fn restore_timers(&mut self, mut records: HashMap<String, TimerRecord>, now: i64) {
+        for svc in self.services_map.iter_mut() {
+            let (Some(config), Some(timer), Some(record)) = (
+                svc.config.timer.as_ref(),
+                svc.timer.as_mut(),
+                records.remove(&*svc.name),
+            ) else {
+                continue;
+            };
+            timer.last = record.last;
+            timer.missed = record.missed;
+            let next = std::mem::replace(&mut timer.next, record.next);
+            let (mut down, _) = timer.elapse(now);
+            timer.next = next;
+            if down > 0 && config.persistent {
+                down -= 1;
+                timer.queued = true;
+                svlogg!(
+                    LogLevel::Info,
+                    "service '{}': running the timer activation missed while svlopp was down",
+                    svc.name
+                );
+            }
+            if down > 0 {
+                timer.missed += down;
+                svlogg!(
+                    LogLevel::Warn,
+                    "service '{}': {} timer activation(s) missed while svlopp was down",
+                    svc.name,
+                    down
+                );
+            }
+        }
+    }
+
+    /// Move the recorded failure times by `step_ms` after a step of the
+    /// system clock, so that `start_limit` windows keep their length
+    pub(crate) fn shift_failures(&mut self, step_ms: i64) {
+        for svc in self.services_map.iter_mut() {
+            for t in svc.failures.iter_mut() {
+                *t = t.saturating_add_signed(step_ms);
+            }
+        }
+    }
+
+    /// Activate the timers elapsed at `now`, in seconds since the epoch,
+    /// queueing the start of their services or applying their `overlap`
+    /// policy if they still run
+    pub(crate) fn fire_timers(&mut self, now: i64, start_queue: &mut StartQueue) {
+        for svc in self.services_map.iter_mut() {
+            let startable =
+                svc.is_stopped() && svc.finish_pid.is_none() && svc.pending_action.is_none();
+            let running = matches!(
+                svc.state,
+                ServiceState::Running(_) | ServiceState::Paused(_)
+            );
+            let (Some(config), Some(timer)) = (svc.config.timer.as_ref(), svc.timer.as_mut())
+            else {
+                continue;
+            };
+            let (elapsed, due) = timer.elapse(now);
+            let Some(due) = due else {
+                if timer.queued && startable {
+                    timer.queued = false;
+                    timer.last = Some(now);
+                    start_queue.push(svc);
+                }
+                continue;
+            };
+            // e.g. the clock jumped, or the host was suspended
+            timer.missed += elapsed - 1;
+            if !config.catch_up && now - due > LATE_AFTER_SECS {
+                timer.missed += 1;
+                svlogg!(
+                    LogLevel::Warn,
+                    "service '{}': late timer activation missed",
+                    svc.name
+                );
+                continue;
+            }
+            if startable {
+                timer.queued = false;
+                timer.last = Some(now);
+                start_queue.push(svc);
+                continue;
+            }
+            match config.overlap {
+                OverlapPolicy::Kill if running && svc.pending_action.is_none() => {
+                    timer.last = Some(now);
+                    svlogg!(
+                        LogLevel::Info,
+                        "service '{}' will be restarted by its timer",
+                        svc.name
+                    );
+                    svc.pending_action = ServicePendingAction::Restart;
+                    if let Err(e) = stop_service(svc) {
+                        svlogg!(
+                            LogLevel::Error,
+                            "failed to stop service '{}': {}",
+                            svc.name,
+                            e
+                        );
+                    }
+                }
+                OverlapPolicy::Queue | OverlapPolicy::Kill if !timer.queued => {
+                    timer.queued = true;
+                }
+                _ => {
+                    timer.missed += 1;
+                    svlogg!(
+                        LogLevel::Warn,
+                        "service '{}': timer activation missed, still running",
+                        svc.name
+                    );
+                }
+            }
+        }
+    }
+
+    /// Drop the activations of monotonic timers due at `now`, in seconds
+    /// since the epoch, once the host resumed from a suspension
+    pub(crate) fn skip_suspended_timers(&mut self, now: i64) {
+        for svc in self.services_map.iter_mut() {
+            if let (Some(config), Some(timer)) = (&svc.config.timer, svc.timer.as_mut())
+                && config.clock == TimerClock::Monotonic
+            {
+                timer.skip(now);
+            }
+        }
+    }
+
+    /// Compute the next activation of the timers again from `now`, in
+    /// seconds since the epoch, after a step of the system clock
+    pub(crate) fn reschedule_timers(&mut self, now: i64) {
+        for svc in self.services_map.iter_mut() {
+            if let Some(timer) = svc.timer.as_mut() {
+                timer.reschedule(now);
+            }
+        }
+    }
+
+    /// Stop the running services bound to the service `name`, which just
+    /// stopped with `reason`. A failure is propagated to them as their
+    /// `on_target_failure` says
+    pub(crate) fn stop_bound_to(&mut self, name: &str, reason: ServiceStopReason) {
+        for svc in self.services_map.iter_mut() {
+            if svc.config.bind_to.as_deref() != Some(name)
+                || !matches!(
+                    svc.state,
+                    ServiceState::Running(_) | ServiceState::Paused(_)
+                )
+            {
+                continue;
+            }
+            let policy = match reason.is_failure() {
+                true => {
+                    let policy = svc.config.on_target_failure;
+                    svlogg!(
+                        LogLevel::Info,
+                        "service '{}' failed ({}), on_target_failure of bound service '{}': {}",
+                        name,
+                        reason,
+                        svc.name,
+                        policy
+                    );
+                    policy
+                }
+                false => TargetFailurePolicy::Restart,
+            };
+            if policy == TargetFailurePolicy::Keep {
+                continue;
+            }
+            match stop_service(svc) {
+                Ok(()) => {
+                    svc.bound_stop = true;
+                    svc.bound_hold = policy == TargetFailurePolicy::Stop;
+                    svlogg!(
+                        LogLevel::Info,
+                        "stopping service '{}', bound to '{}'",
+                        svc.name,
+                        name
+                    );
+                }
+                Err(e) => svlogg!(
+                    LogLevel::Error,
+                    "failed to stop service '{}': {}",
+                    svc.name,
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Whether `a` and `b`, two different services, must not run together
+    fn in_conflict(a: &Service, b: &Service) -> bool {
+        a.config.conflicts_with.iter().any(|n| **n == *b.name)
+            || b.config.conflicts_with.iter().any(|n| **n == *a.name)
+            || a.config
+                .mutex_group
+                .as_ref()
+                .is_some_and(|group| b.config.mutex_group.as_ref() == Some(group))
+    }
+
+    /// Ids of the services that are not stopped and conflict with the
+    /// service `svc_id`
+    fn running_conflicts(&self, svc_id: u64) -> Vec<u64> {
+        let Some(svc) = self.service(svc_id) else {
+            return Vec::new();
+        };
+        self.services_map
+            .iter()
+            .filter(|other| {
+                other.id != svc_id && !other.is_stopped() && Self::in_conflict(svc, other)
+            })
+            .map(|other| other.id)
+            .collect()
+    }
+
+    /// Name of a running or stopping service the service `svc_id`
+    /// conflicts with
+    pub(crate) fn running_conflict(&self, svc_id: u64) -> Option<&str> {
+        let id = *self.running_conflicts(svc_id).first()?;
+        self.service(id).map(|svc| &*svc.name)
+    }
+
+    /// Ids of the services that some running or stopping service
+    /// conflicts with, which can't start right away
+    pub(crate) fn conflicted(&self) -> HashSet<u64> {
+        let mut conflicted = HashSet::new();
+        let declares_conflicts = |svc: &Service| {
+            !svc.config.conflicts_with.is_empty() || svc.config.mutex_group.is_some()
+        };
+        if !self.services_map.iter().any(declares_conflicts) {
+            return conflicted;
+        }
+        for svc in self.services_map.iter() {
+            if !self.running_conflicts(svc.id).is_empty() {
+                conflicted.insert(svc.id);
+            }
+        }
+        conflicted
+    }
+
+    /// Whether the service `svc_id` may start now as far as its conflicts
+    /// are concerned. With `on_conflict = "Stop"`, the services it
+    /// conflicts with are stopped, and it's queued by
+    /// `queue_conflict_starts` once they have all stopped. With `Refuse`,
+    /// fails with `ResourceBusy`
+    pub(crate) fn admit(&mut self, svc_id: u64) -> io::Result<bool> {
+        let conflicts = self.running_conflicts(svc_id);
+        let Some(svc) = self.service(svc_id) else {
+            return Ok(true);
+        };
+        if conflicts.is_empty() {
+            return Ok(true);
+        }
+        let name = svc.name.clone();
+        if svc.config.on_conflict == ConflictPolicy::Refuse {
+            let other = self.running_conflict(svc_id).unwrap_or("?");
+            return Err(io::Error::new(
+                io::ErrorKind::ResourceBusy,
+                format!("conflicts with running service '{}'", other),
+            ));
+        }
+        for other_id in conflicts {
+            let Some(other) = self.service_mut(other_id) else {
+                continue;
+            };
+            // it would come back right away otherwise
+            if other.pending_action == ServicePendingAction::Restart {
+                other.pending_action = ServicePendingAction::None;
+            }
+            if !matches!(
+                other.state,
+                ServiceState::Running(_) | ServiceState::Paused(_)
+            ) {
+                continue;
+            }
+            match stop_service(other) {
+                Ok(()) => svlogg!(
+                    LogLevel::Info,
+                    "stopping service '{}', conflicting with '{}'",
+                    other.name,
+                    name
+                ),
+                Err(e) => svlogg!(
+                    LogLevel::Error,
+                    "failed to stop service '{}': {}",
+                    other.name,
+                    e
+                ),
+            }
+        }
+        svlogg!(
+            LogLevel::Info,
+            "service '{}' will be started once its conflicts have stopped",
+            name
+        );
+        self.conflict_waiters.insert(svc_id);
+        Ok(false)
+    }
+
+    /// Queue the services waiting for the services they conflict with to
+    /// stop, once they have
+    pub(crate) fn queue_conflict_starts(&mut self, start_queue: &mut StartQueue) {
+        if self.conflict_waiters.is_empty() {
+            return;
+        }
+        let waiters: Vec<u64> = self.conflict_waiters.iter().copied().collect();
+        for svc_id in waiters {
+            if self.service(svc_id).is_none() {
+                self.conflict_waiters.remove(&svc_id);
+            } else if self.running_conflicts(svc_id).is_empty() {
+                self.conflict_waiters.remove(&svc_id);
+                if let Some(svc) = self.service_mut(svc_id) {
+                    start_queue.push(svc);
+                }
+            }
+        }
+    }
+
+    /// Queue the bound services that are waiting for their bound service
+    /// to run: those never started and those stopped because of it
+    pub(crate) fn queue_bound_starts(&mut self, start_queue: &mut StartQueue) {
+        let waiting: Vec<u64> = self
+            .services_map
+            .iter()
+            .filter(|svc| {
+                svc.config.bind_to.is_some()
+                    && svc.pending_action.is_none()
+                    && match svc.state {
+                        ServiceState::Stopped(ServiceStopReason::NeverStarted) => {
+                            svc.config.autostart
+                        }
+                        ServiceState::Stopped(ServiceStopReason::BoundStopped(_)) => {
+                            !svc.bound_hold
+                        }
+                        _ => false,
+                    }
+                    && self.is_bind_target_running(svc.id)
+            })
+            .map(|svc| svc.id)
+            .collect();
+        for svc_id in waiting {
+            if let Some(svc) = self.service_mut(svc_id) {
+                start_queue.push(svc);
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn remove_service(&mut self, svc_id: u64) -> Option<Service> {
+        self.services_map.remove(svc_id)
+    }
+
+    /// Execute a closure with mutable access to both `services_map` and
+    /// `pids_map`.
+    ///
+    /// This exists to allow for use cases like iterating through services
+    /// to restart or remove them while also keep the pid index in sync,
+    /// without exposing the maps publicly
+    #[inline(always)]
+    pub(crate) fn with_maps_mut<R>(
+        &mut self,
+        f: impl FnOnce(&mut ServiceSlab, &mut HashMap<Pid, u64>) -> R,
+    ) -> R {
+        f(&mut self.services_map, &mut self.pids_map)
+    }
+
+    pub(crate) fn format_status(&self, w: &mut impl fmt::Write) -> fmt::Result {
+        for svc in self.services_map.iter() {
+            svc.format_status_line(w)?;
+            w.write_char('\n')?;
+        }
+        Ok(())
+    }
+
+    /// Write the stop order, stop duration and stop reason of the services
+    /// stopped by the supervisor since `since`, one per line:
+    /// `<name> <stopped_at_ms> <stop_duration_ms> <graceful|killed> <reason>`
+    /// where `stopped_at_ms` is relative to `since`
+    pub(crate) fn format_shutdown_report(
+        &self,
+        since: Instant,
+        w: &mut impl fmt::Write,
+    ) -> fmt::Result {
+        let mut stopped: Vec<(&Service, StopTiming, Instant)> = self
+            .services()
+            .filter_map(|svc| {
+                let timing = svc.stop_timing?;
+                let reaped_at = timing.reaped_at?;
+                (timing.requested_at >= since).then_some((svc, timing, reaped_at))
+            })
+            .collect();
+        stopped.sort_by_key(|&(_, _, reaped_at)| reaped_at);
+        for (svc, timing, reaped_at) in stopped {
+            let ServiceState::Stopped(reason) = svc.state else {
+                continue;
+            };
+            writeln!(
+                w,
+                "{} {} {} {} {}",
+                svc.name,
+                reaped_at.duration_since(since).as_millis(),
+                reaped_at.duration_since(timing.requested_at).as_millis(),
+                if timing.force_killed {
+                    "killed"
+                } else {
+                    "graceful"
+                },
+                reason
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Append a snapshot of the current state of all services to `buf`
+    pub(crate) fn encode_snapshot(&self, buf: &mut Vec<u8>) {
+        let (secs, nsecs) = timestamp();
+        encode_snapshot(
+            buf,
+            secs as u64,
+            nsecs as u32,
+            std::process::id(),
+            self.services().map(Service::snapshot_record),
+        );
+    }
+}
+
+/// Write the process tree of `svc` to `<ps_dir>/<service id>`, the file
+/// being empty when the service is not running
+fn write_process_tree(svc: &Service, ps_dir: &Path) -> io::Result<()> {
+    let mut content = String::new();
+    if let Some(pid) = svc.pid() {
+        format_process_tree(pid.as_raw_nonzero().get(), &mut content)?;
+    }
+    let path = StatusFilePath::new(ps_dir.join(svc.id.to_string()))?;
+    write_status_file(&path, content.as_bytes())
+}
+
+/// What applying a control operation does to a service
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PlanAction {
+    Start,
+    Stop,
+}
+
+impl PlanAction {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Start => "start",
+            Self::Stop => "stop",
+        }
+    }
+}
+
+/// The services applying `op` to the service `svc_id` would start and
+/// stop, in order, including those following through `bind_to`, without
+/// applying it. Mirrors `apply_control_op`: the plan is empty when the
+/// operation would do nothing
+pub(crate) fn plan_control_op(
+    registry: &ServiceRegistry,
+    svc_id: u64,
+    op: ControlOp,
+) -> Vec<(PlanAction, &Service)> {
+    let mut plan = Vec::new();
+    let Some(svc) = registry.service(svc_id) else {
+        return plan;
+    };
+    let idle = svc.pending_action.is_none();
+    match (op, svc.state) {
+        (ControlOp::Stop, ServiceState::Running(_) | ServiceState::Paused(_)) => {
+            plan_stops(registry, svc, &mut plan)
+        }
+        (ControlOp::Start | ControlOp::Restart, ServiceState::Stopped(_)) if idle => {
+            if svc.config.on_conflict == ConflictPolicy::Stop {
+                let mut conflicts: Vec<&Service> = registry
+                    .running_conflicts(svc_id)
+                    .into_iter()
+                    .filter_map(|id| registry.service(id))
+                    .filter(|other| {
+                        matches!(
+                            other.state,
+                            ServiceState::Running(_) | ServiceState::Paused(_)
+                        )
+                    })
+                    .collect();
+                conflicts.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+                for other in conflicts {
+                    plan_stops(registry, other, &mut plan);
+                }
+            }
+            plan_starts(registry, svc, false, &mut plan)
+        }
+        (ControlOp::Restart, ServiceState::Running(_) | ServiceState::Paused(_)) if idle => {
+            plan_stops(registry, svc, &mut plan);
+            plan_starts(registry, svc, true, &mut plan);
+        }
+        _ => {}
+    }
+    plan
+}
+
+/// Services directly bound to `target` matching `pred`, by name
+fn bound_to<'a>(
+    registry: &'a ServiceRegistry,
+    target: &str,
+    pred: impl Fn(&Service) -> bool,
+) -> Vec<&'a Service> {
+    let mut bound: Vec<&Service> = registry
+        .services()
+        .filter(|svc| svc.config.bind_to.as_deref() == Some(target) && pred(svc))
+        .collect();
+    bound.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    bound
+}
+
+/// Stop `svc`, then the running services bound to it as they get stopped
+/// once it is reaped
+fn plan_stops<'a>(
+    registry: &'a ServiceRegistry,
+    svc: &'a Service,
+    plan: &mut Vec<(PlanAction, &'a Service)>,
+) {
+    let first = plan.len();
+    plan.push((PlanAction::Stop, svc));
+    let mut next = first;
+    while next < plan.len() {
+        let target = &plan[next].1.name;
+        plan.extend(
+            bound_to(registry, target, |b| {
+                matches!(b.state, ServiceState::Running(_) | ServiceState::Paused(_))
+            })
+            .into_iter()
+            .map(|b| (PlanAction::Stop, b)),
+        );
+        next += 1;
+    }
+}
+
+/// Start `svc`, then the services bound to it that wait for it to run,
+/// as `queue_bound_starts` would. With `restarting`, the running ones
+/// are about to be stopped along with `svc`, and are started again too
+fn plan_starts<'a>(
+    registry: &'a ServiceRegistry,
+    svc: &'a Service,
+    restarting: bool,
+    plan: &mut Vec<(PlanAction, &'a Service)>,
+) {
+    let first = plan.len();
+    plan.push((PlanAction::Start, svc));
+    let mut next = first;
+    while next < plan.len() {
+        let target = &plan[next].1.name;
+        plan.extend(
+            bound_to(registry, target, |b| {
+                b.pending_action.is_none()
+                    && match b.state {
+                        ServiceState::Stopped(ServiceStopReason::NeverStarted) => {
+                            b.config.autostart
+                        }
+                        ServiceState::Stopped(ServiceStopReason::BoundStopped(_)) => true,
+                        ServiceState::Running(_) | ServiceState::Paused(_) => restarting,
+                        _ => false,
+                    }
+            })
+            .into_iter()
+            .map(|b| (PlanAction::Start, b)),
+        );
+        next += 1;
+    }
+}
+
+/// Write the plan of `op` on the service `svc_id` to
+/// `<plan_dir>/<service id>`, one `<start|stop> <name>` line per step
+pub(crate) fn write_plan(
+    registry: &ServiceRegistry,
+    svc_id: u64,
+    op: ControlOp,
+    plan_dir: &Path,
+) -> io::Result<()> {
+    if registry.service(svc_id).is_none() {
+        return Err(io::Error::other(format!("unknown service id {}", svc_id)));
+    }
+    let mut content = String::new();
+    for (action, svc) in plan_control_op(registry, svc_id, op) {
+        content.push_str(action.as_str());
+        content.push(' ');
+        content.push_str(&svc.name);
+        content.push('\n');
+    }
+    let path = StatusFilePath::new(plan_dir.join(svc_id.to_string()))?;
+    write_status_file(&path, content.as_bytes())
+}
+
+/// Apply a control operation,
+///
+/// Control operations are treated as *requests*, meaning they must:
+/// - Respect the current state.
+/// - Never override an existing pending action.
 /// - Never act on a stopped service whose pending action has yet to
 ///   be processed.
 ///
 /// In particular:
-/// - `Stop`: stops a service *only* if it is running. Never clears a
-///   pending action. This is safe, as any pending action will be applied
-///   after the service process is reaped.
+/// - `Stop`: stops a service *only* if it is running or paused. Never
+///   clears a pending action. This is safe, as any pending action will be
+///   applied after the service process is reaped.
 /// - `Start`: starts a service *only* if it is stopped *and* has no
 ///   pending action. Never sets/clears a pending action.
 /// - `Restart`: if the service is stopped *and* has no pending action,
-///   starts it. If it is running *and* has no pending action, stops it
-///   and sets `pending_action = ServicePendingAction::Restart`. Does
-///   nothing otherwise.
+///   starts it. If it is running or paused *and* has no pending action,
+///   stops it and sets `pending_action = ServicePendingAction::Restart`.
+///   Does nothing otherwise.
+/// - `Pause`: freezes a service *only* if it is running, `Resume` thaws
+///   it *only* if it is paused. Never sets/clears a pending action.
+/// - `Ps`: writes the process tree of the service, never changes its
+///   state.
+/// - `Cancel`: cancels the latest job of the service, the only operation
+///   clearing a pending action, see `ServiceRegistry::cancel_job`.
+/// - `Graph`, `Snapshot`, `Restore`, `SetProperty`, `RestartOutdated`,
+///   `RollingRestart`, `Swap`, `Run`, `Adopt` and `Detach`: do nothing,
+///   see `write_graph`, `write_run_set`, `restore_run_set`,
+///   `set_property`, `ServiceRegistry::outdated`, `rollout`, `swap`,
+///   `transient`, `adopt` and `ServiceRegistry::detach`.
+///
+/// Operations on a busy service, and starts and stops beyond the global
+/// limits, are queued, see `jobs`.
 pub(crate) fn apply_control_op(
     registry: &mut ServiceRegistry,
     svc_id: u64,
     op: ControlOp,
-    sigset: &SigSet,
+    ctx: &SpawnContext,
+    ps_dir: &Path,
 ) -> io::Result<()> {
+    if let Some(svc) = registry.service_mut(svc_id) {
+        match jobs::decide(svc, op) {
+            JobDecision::Run => {}
+            JobDecision::Queue => {
+                svlogg!(
+                    LogLevel::Info,
+                    "service '{}' will be started again once stopped",
+                    svc.name
+                );
+                svc.pending_action = ServicePendingAction::Restart;
+                return Ok(());
+            }
+            JobDecision::Merge => {
+                svlogg!(
+                    LogLevel::Debug,
+                    "{} of service '{}' merged with the ongoing one",
+                    op,
+                    svc.name
+                );
+                return Ok(());
+            }
+            JobDecision::Cancel => {
+                svlogg!(
+                    LogLevel::Info,
+                    "service '{}' won't be started again",
+                    svc.name
+                );
+                svc.pending_action = ServicePendingAction::None;
+                return Ok(());
+            }
+            JobDecision::Reject => return Err(jobs::in_progress()),
+        }
+    }
+    if op == ControlOp::Cancel {
+        return registry.cancel_job(svc_id);
+    }
+    if registry.schedule_job(svc_id, op) {
+        return Ok(());
+    }
+    run_control_op(registry, svc_id, op, ctx, ps_dir)
+}
+
+/// Run the control operations that waited for a start or stop slot, and
+/// got one. Returns whether any did
+pub(crate) fn dispatch_jobs(
+    registry: &mut ServiceRegistry,
+    ctx: &SpawnContext,
+    ps_dir: &Path,
+) -> bool {
+    let runnable = registry.jobs.take_runnable(&mut registry.services_map);
+    for &(svc_id, op) in &runnable {
+        if let Err(e) = run_control_op(registry, svc_id, op, ctx, ps_dir) {
+            svlogg!(LogLevel::Error, "failed to {} service: {}", op, e);
+        }
+    }
+    !runnable.is_empty()
+}
+
+/// `apply_control_op`, once the operation is known to run now
+fn run_control_op(
+    registry: &mut ServiceRegistry,
+    svc_id: u64,
+    op: ControlOp,
+    ctx: &SpawnContext,
+    ps_dir: &Path,
+) -> io::Result<()> {
+    let starting = matches!(op, ControlOp::Start | ControlOp::Restart)
+        && registry
+            .service(svc_id)
+            .is_some_and(|svc| svc.is_stopped() && svc.pending_action.is_none());
+    // started once its conflicts have stopped
+    if starting && !registry.admit(svc_id)? {
+        return Ok(());
+    }
+    let mut capacity =
+        matches!(op, ControlOp::Start | ControlOp::Restart).then(|| registry.capacity());
     if let Some(svc) = registry.service_mut(svc_id) {
         let svc_id = svc.id;
         match op {
             ControlOp::Stop => {
-                if matches!(svc.state, ServiceState::Running(_)) {
+                let queued = std::mem::take(&mut svc.start_queued);
+                if matches!(
+                    svc.state,
+                    ServiceState::Running(_) | ServiceState::Paused(_)
+                ) {
                     svlogg!(LogLevel::Info, "stopping service '{}'", svc.name);
                     stop_service(svc)?;
+                } else if queued {
+                    svlogg!(
+                        LogLevel::Info,
+                        "service '{}' won't be started, dropped from the start queue",
+                        svc.name
+                    );
                 }
             }
             ControlOp::Start => {
                 if matches!(svc.state, ServiceState::Stopped(_)) && svc.pending_action.is_none() {
-                    start_service(svc, sigset)?;
+                    capacity.as_mut().map_or(Ok(()), Capacity::reserve)?;
+                    start_service(svc, ctx)?;
                     let svc_pid = svc.pid().expect("running service must have a pid");
                     svlogg!(
                         LogLevel::Info,
@@ -857,7 +3899,8 @@ pub(crate) fn apply_control_op(
             }
             ControlOp::Restart => match svc.state {
                 ServiceState::Stopped(_) if svc.pending_action.is_none() => {
-                    start_service(svc, sigset)?;
+                    capacity.as_mut().map_or(Ok(()), Capacity::reserve)?;
+                    start_service(svc, ctx)?;
                     let svc_pid = svc.pid().expect("running service must have a pid");
                     svlogg!(
                         LogLevel::Info,
@@ -867,13 +3910,42 @@ pub(crate) fn apply_control_op(
                     );
                     registry.register_pid(svc_pid, svc_id);
                 }
-                ServiceState::Running(_) if svc.pending_action.is_none() => {
+                ServiceState::Running(_) | ServiceState::Paused(_)
+                    if svc.pending_action.is_none() =>
+                {
                     svlogg!(LogLevel::Info, "service '{}' will be restarted", svc.name);
                     svc.pending_action = ServicePendingAction::Restart;
                     stop_service(svc)?;
                 }
                 _ => {}
             },
+            ControlOp::Pause => {
+                if matches!(svc.state, ServiceState::Running(_)) {
+                    pause_service(svc)?;
+                    svlogg!(LogLevel::Info, "paused service '{}'", svc.name);
+                }
+            }
+            ControlOp::Resume => {
+                if matches!(svc.state, ServiceState::Paused(_)) {
+                    resume_service(svc)?;
+                    svlogg!(LogLevel::Info, "resumed service '{}'", svc.name);
+                }
+            }
+            ControlOp::Ps => write_process_tree(svc, ps_dir)?,
+            // see `ServiceRegistry::cancel_job`
+            ControlOp::Cancel => {}
+            // not service operations, or needing more than the service id
+            ControlOp::Graph
+            | ControlOp::Snapshot
+            | ControlOp::Restore
+            | ControlOp::Reload
+            | ControlOp::SetProperty
+            | ControlOp::RestartOutdated
+            | ControlOp::RollingRestart
+            | ControlOp::Swap
+            | ControlOp::Run
+            | ControlOp::Adopt
+            | ControlOp::Detach => {}
         }
     } else {
         svlogg!(LogLevel::Warn, "unkown service id: {}", svc_id);
@@ -881,6 +3953,25 @@ pub(crate) fn apply_control_op(
     Ok(())
 }
 
+/// Write the diagnostics bundle of `svc`, which just hit its start limit,
+/// to `dir`
+fn write_diagnostics(svc: &Service, dir: &Path) {
+    match write_bundle(svc, dir) {
+        Ok(path) => svlogg!(
+            LogLevel::Info,
+            "wrote diagnostics of service '{}' to {}",
+            svc.name,
+            path.display()
+        ),
+        Err(e) => svlogg!(
+            LogLevel::Warn,
+            "failed to write diagnostics of service '{}': {}",
+            svc.name,
+            e
+        ),
+    }
+}
+
 /// SIGCHLD handler
 ///
 /// **N.B.** `rustix::process::wait` correspond to `waitpid(-1, ...)`, the syscall
@@ -891,17 +3982,87 @@ pub(crate) fn apply_control_op(
 /// the caller to specify options. Here we're using `WNOHANG` to avoid actually blocking
 /// if no status information is available immediately when calling. In this way
 /// `waitpid(-1, ...)` differs completely from `wait`
-pub(crate) fn handle_sigchld(registry: &mut ServiceRegistry) -> io::Result<()> {
+pub(crate) fn handle_sigchld(
+    registry: &mut ServiceRegistry,
+    orphans: &mut OrphanTracker,
+    oom: &mut OomDetector,
+    alerts: &mut Alerts,
+    diagnostics_dir: &Path,
+) -> io::Result<()> {
     loop {
-        match wait(WaitOptions::NOHANG) {
+        // unknown children are inspected before being reaped, while their
+        // procfs entry is still around
+        let mut target = None;
+        let mut origin = None;
+        if orphans.policy() != OrphanPolicy::Log
+            && let Some(pid) = peek_exited_child()?
+            && registry.get_by_pid(pid).is_none()
+            && !alerts.owns(pid)
+        {
+            target = Some(pid);
+            origin = Some(OrphanOrigin::inspect(pid));
+        }
+        let cpu_before = CpuTime::children();
+        let reaped = retry_eintr(|| match target {
+            Some(pid) => waitpid(Some(pid), WaitOptions::NOHANG),
+            None => wait(WaitOptions::NOHANG),
+        });
+        match reaped {
             Ok(Some((pid, status))) => {
                 if let Some(exit_reason) = ExitReason::from_wait_status(status) {
                     match registry.take_by_pid(pid) {
-                        Some(svc) => {
-                            let stop_reason = ServiceStopReason::from_exit_reason_and_service_state(
+                        Some(svc) if svc.finish_pid == Some(pid) => {
+                            svc.finish_pid = None;
+                            svlogg!(
+                                LogLevel::Debug,
+                                "finish command of service '{}' exited: {:?}",
+                                svc.name,
                                 exit_reason,
-                                svc.state,
                             );
+                        }
+                        Some(svc) => {
+                            let mut stop_reason =
+                                ServiceStopReason::from_exit_reason_and_service_state(
+                                    exit_reason,
+                                    svc.state,
+                                );
+                            // the exec pipe event may not be handled yet
+                            let spawn_error = svc.spawn_error.take().or_else(|| {
+                                let e = SpawnError::read(svc.exec_pipe.as_ref()?.as_fd());
+                                if e.is_some() {
+                                    svc.exec_pipe = None;
+                                }
+                                e
+                            });
+                            if let Some(e) = spawn_error {
+                                stop_reason = ServiceStopReason::SpawnFailed(e);
+                            }
+                            if let ServiceStopReason::SupervisorTerminated(er) = stop_reason
+                                && svc.bound_stop
+                            {
+                                stop_reason = ServiceStopReason::BoundStopped(er);
+                            }
+                            if let ServiceStopReason::SupervisorTerminated(er) = stop_reason
+                                && svc.runtime_exceeded
+                            {
+                                stop_reason = ServiceStopReason::RuntimeExceeded(er);
+                            }
+                            if let ServiceStopReason::Killed(sig) = stop_reason
+                                && sig == Signal::KILL.as_raw()
+                                && oom.was_oom_killed(pid.as_raw_nonzero().get())
+                            {
+                                stop_reason = ServiceStopReason::OomKilled;
+                            }
+                            svc.bound_stop = false;
+                            svc.runtime_exceeded = false;
+                            let cpu = CpuTime::children()
+                                .zip(cpu_before)
+                                .map(|(after, before)| after.since(before));
+                            let uptime_ms = svc.started_at.map(|t| t.elapsed().as_millis() as u64);
+                            svc.history.exited(stop_reason, uptime_ms, cpu);
+                            let limited = stop_reason.is_failure() && svc.record_failure();
+                            svc.start_time = None;
+                            svc.pidfd = None;
                             debug_assert!(
                                 !matches!(stop_reason, ServiceStopReason::NeverStarted),
                                 "reaped service '{}' that was never started",
@@ -919,19 +4080,53 @@ pub(crate) fn handle_sigchld(registry: &mut ServiceRegistry) -> io::Result<()> {
                                     );
                                 }
                             };
+                            if svc.config.kill_descendants {
+                                let killed = kill_survivors(&svc.descendants);
+                                svc.descendants.clear();
+                                if killed > 0 {
+                                    svlogg!(
+                                        LogLevel::Info,
+                                        "killed {} leftover descendants of service '{}'",
+                                        killed,
+                                        svc.name
+                                    );
+                                }
+                            }
+                            // leftover descendants keep the tmpfs until they exit
+                            svc.private_tmp = None;
+                            // and the cgroup, which then isn't removed
+                            svc.cgroup = None;
+                            if let ServiceState::Stopping(_, _) = svc.state
+                                && let Some(timing) = svc.stop_timing.as_mut()
+                            {
+                                timing.reaped_at = Some(Instant::now());
+                            }
                             svc.state = ServiceState::Stopped(stop_reason);
-                            svlogg!(
-                                LogLevel::Info,
-                                "service '{}' exited: {:?}",
-                                svc.name,
-                                exit_reason,
-                            );
+                            if svc.config.finish.is_some() {
+                                svc.pending_finish = Some(exit_reason);
+                            }
+                            match spawn_error {
+                                Some(e) => svlogg!(
+                                    LogLevel::Error,
+                                    "service '{}' failed to start: {}",
+                                    svc.name,
+                                    e.describe(&svc.config)
+                                ),
+                                None => svlogg!(
+                                    LogLevel::Info,
+                                    "service '{}' exited: {:?}",
+                                    svc.name,
+                                    exit_reason,
+                                ),
+                            }
+                            if limited {
+                                write_diagnostics(svc, diagnostics_dir);
+                            }
+                            let name = svc.name.clone();
+                            registry.stop_bound_to(&name, stop_reason);
                         }
-                        None => svlogg!(
-                            LogLevel::Info,
-                            "reaped unknown pid {} (likely adopted descendant)",
-                            pid
-                        ),
+                        None if alerts.owns(pid) => alerts.reaped(pid, exit_reason),
+                        None => orphans.reaped(pid, exit_reason, origin.take(), registry),
                     }
                 } else {
                     match registry.get_by_pid(pid) {
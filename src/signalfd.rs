@@ -4,12 +4,13 @@
 
 use std::{
     io,
-    os::fd::{BorrowedFd, FromRawFd, OwnedFd},
+    os::fd::{BorrowedFd, OwnedFd},
 };
 
 use bitflags::bitflags;
+use svlopp::read_buf::{Pod, ReadBuf};
 
-use crate::utils::cvt;
+use crate::utils::{cvt, cvt_fd, retry_eintr};
 
 bitflags! {
     #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -90,10 +91,8 @@ pub(crate) fn set_thread_signal_mask(sigset: &SigSet) -> rustix::io::Result<()>
 /// TODO: we're hardcoding fd to be -1, causing `signalfd` to only ask for
 /// a new file descriptor
 pub(crate) fn signalfd(sigset: &SigSet, flags: SignalfdFlags) -> rustix::io::Result<OwnedFd> {
-    unsafe {
-        let fd = cvt(libc::signalfd(-1, sigset.as_ptr(), flags.bits() as _))?;
-        Ok(OwnedFd::from_raw_fd(fd))
-    }
+    // SAFETY: with -1, `signalfd` returns a new file descriptor
+    unsafe { cvt_fd(libc::signalfd(-1, sigset.as_ptr(), flags.bits() as _)) }
 }
 
 #[repr(transparent)]
@@ -131,51 +130,26 @@ impl SignalfdSiginfo {
     pub(crate) const fn uid(&self) -> u32 {
         self.raw.ssi_uid
     }
-
-    /// Create an empty `SignalfdSiginfo`.
-    ///
-    /// It is intended to be used as a buffer passed to
-    /// `libc::read`. The returned value does not contain
-    /// any meaningful value until populated via `libc::read`
-    /// and reading its fields before that is logically
-    /// incorrect.
-    #[inline(always)]
-    pub(crate) fn empty() -> Self {
-        Self {
-            raw: unsafe { std::mem::zeroed() },
-        }
-    }
-
-    #[inline(always)]
-    pub(crate) fn as_mut_ptr(&mut self) -> *mut libc::signalfd_siginfo {
-        &mut self.raw
-    }
 }
 
-/// Read up to `buf.len()` `SignalfdSiginfo` from `fd` and
-/// return the number of items read. Return `Ok(0)` if reading
-/// from `fd` would block (`WOULDBLOCK`).
+// SAFETY: `signalfd_siginfo` is made of integers, its explicit padding
+// included, and has no implicit padding
+unsafe impl Pod for SignalfdSiginfo {}
+
+/// Read up to `N` `SignalfdSiginfo` from `fd` and return them. Return no
+/// records if reading from `fd` would block (`WOULDBLOCK`).
 ///
 /// N.B. this may not drain the fd. This is fine as we're
 /// using level-triggered epoll, which will fire again on
 /// the next call to wait
-pub(crate) fn read_signalfd_batch(
+pub(crate) fn read_signalfd_batch<'a, const N: usize>(
     fd: BorrowedFd<'_>,
-    buf: &mut [SignalfdSiginfo],
-) -> rustix::io::Result<usize> {
-    if buf.is_empty() {
-        return Ok(0);
-    }
-    let bytes_buf = unsafe {
-        std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, std::mem::size_of_val(buf))
-    };
-    match rustix::io::read(fd, bytes_buf) {
-        Ok(0) => Ok(0),
-        Ok(sz) if sz % std::mem::size_of::<SignalfdSiginfo>() == 0 => {
-            Ok(sz / std::mem::size_of::<SignalfdSiginfo>())
-        }
-        Ok(_) => Err(rustix::io::Errno::IO),
-        Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(0),
+    buf: &'a mut ReadBuf<SignalfdSiginfo, N>,
+) -> rustix::io::Result<&'a [SignalfdSiginfo]> {
+    match retry_eintr(|| rustix::io::read(fd, buf.as_bytes_mut())) {
+        // the kernel only returns whole records
+        Ok(n) => buf.records(n).map_err(|_| rustix::io::Errno::IO),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(&[]),
         Err(e) => Err(e),
     }
 }
@@ -0,0 +1,97 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::service::Service;
+
+/// Marks a service id without a service in `ServiceSlab::slots`
+const VACANT: u32 = u32::MAX;
+
+/// Services indexed by id.
+///
+/// Services are stored contiguously, so that iterating over them walks a
+/// single allocation, and `slots` maps each id to the position of its
+/// service. Ids are handed out progressively by `ServiceIdGen`, which
+/// makes `slots` a dense vector lookups go through without hashing.
+/// Removing a service moves the last one in its place, so iteration order
+/// is not insertion order
+#[derive(Debug, Default)]
+pub(crate) struct ServiceSlab {
+    services: Vec<Service>,
+    /// `service_id -> position in services`, `VACANT` for ids without a
+    /// service
+    slots: Vec<u32>,
+}
+
+impl ServiceSlab {
+    #[inline(always)]
+    fn position(&self, svc_id: u64) -> Option<usize> {
+        let pos = *self.slots.get(usize::try_from(svc_id).ok()?)?;
+        (pos != VACANT).then_some(pos as usize)
+    }
+
+    /// Insert `svc`, returning the service it replaces if one with the
+    /// same id was there
+    pub(crate) fn insert(&mut self, svc: Service) -> Option<Service> {
+        if let Some(pos) = self.position(svc.id) {
+            return Some(std::mem::replace(&mut self.services[pos], svc));
+        }
+        let slot = usize::try_from(svc.id).expect("service id fits in usize");
+        if slot >= self.slots.len() {
+            self.slots.resize(slot + 1, VACANT);
+        }
+        self.slots[slot] = u32::try_from(self.services.len()).expect("too many services");
+        self.services.push(svc);
+        None
+    }
+
+    #[inline(always)]
+    pub(crate) fn get(&self, svc_id: u64) -> Option<&Service> {
+        self.position(svc_id).map(|pos| &self.services[pos])
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_mut(&mut self, svc_id: u64) -> Option<&mut Service> {
+        self.position(svc_id).map(|pos| &mut self.services[pos])
+    }
+
+    pub(crate) fn remove(&mut self, svc_id: u64) -> Option<Service> {
+        let pos = self.position(svc_id)?;
+        self.slots[svc_id as usize] = VACANT;
+        let svc = self.services.swap_remove(pos);
+        if let Some(moved) = self.services.get(pos) {
+            self.slots[moved.id as usize] = pos as u32;
+        }
+        // ids are not reused, don't keep the slots of the last ones around
+        while self.slots.last() == Some(&VACANT) {
+            self.slots.pop();
+        }
+        Some(svc)
+    }
+
+    #[inline(always)]
+    pub(crate) fn iter(&self) -> std::slice::Iter<'_, Service> {
+        self.services.iter()
+    }
+
+    #[inline(always)]
+    pub(crate) fn iter_mut(&mut self) -> std::slice::IterMut<'_, Service> {
+        self.services.iter_mut()
+    }
+
+    /// Keep only the services for which `f`, called with their id, returns
+    /// `true`
+    pub(crate) fn retain(&mut self, mut f: impl FnMut(&u64, &mut Service) -> bool) {
+        let mut pos = 0;
+        while pos < self.services.len() {
+            let svc = &mut self.services[pos];
+            let svc_id = svc.id;
+            if f(&svc_id, svc) {
+                pos += 1;
+            } else {
+                // the last service is moved to `pos`, which is visited next
+                self.remove(svc_id);
+            }
+        }
+    }
+}
@@ -0,0 +1,427 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Binary snapshot of the supervisor state.
+//!
+//! The snapshot is written next to the status file and carries the same
+//! information in a form that doesn't need text parsing. All integers are
+//! little-endian.
+//!
+//! Header (32 bytes):
+//! `{magic[8], version: u16, reserved: u16, count: u32, secs: u64, nsecs: u32, pid: u32}`
+//!
+//! followed by `count` service records (28 bytes + name):
+//! `{id: u64, start_count: u64, state: u8, reason: u8, flags: u16, value: i32, name_len: u32, name[name_len]}`
+//!
+//! `value` is the pid for running, stopping and paused services, and the exit code
+//! or signal number of the stop reason for stopped ones (zero when the
+//! reason carries none). `flags` is a bit set of `RecordFlags`, unknown
+//! bits being kept.
+
+use std::fmt;
+
+/// Identifies a snapshot file
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"SVLPSNAP";
+
+/// Current snapshot format version, bumped on incompatible changes
+pub const SNAPSHOT_VERSION: u16 = 1;
+
+const HEADER_LEN: usize = 32;
+const RECORD_LEN: usize = 28;
+
+/// Why a stopped service is stopped, mirroring the status file reasons
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReasonKind {
+    NeverStarted = 0,
+    /// Terminated by the supervisor, exited with a code
+    SupervisorTerminatedExited = 1,
+    /// Terminated by the supervisor, killed by a signal
+    SupervisorTerminatedSignaled = 2,
+    Success = 3,
+    Error = 4,
+    Crashed = 5,
+    Killed = 6,
+    /// Terminated because of `bind_to`, exited with a code
+    BoundStoppedExited = 7,
+    /// Terminated because of `bind_to`, killed by a signal
+    BoundStoppedSignaled = 8,
+    /// Terminated because of `runtime_max_ms`, exited with a code
+    RuntimeExceededExited = 9,
+    /// Terminated because of `runtime_max_ms`, killed by a signal
+    RuntimeExceededSignaled = 10,
+    /// Killed by the kernel OOM killer
+    OomKilled = 11,
+    /// Vanished without being reaped by the supervisor
+    Lost = 12,
+    /// Failed to exec its command, the value is the errno
+    SpawnFailed = 13,
+    /// Adopted, exited with an unknown status
+    Exited = 14,
+    /// Detached from supervision, the value is the pid left running
+    Detached = 15,
+}
+
+impl TryFrom<u8> for StopReasonKind {
+    type Error = SnapshotError;
+
+    fn try_from(value: u8) -> Result<Self, SnapshotError> {
+        Ok(match value {
+            0 => Self::NeverStarted,
+            1 => Self::SupervisorTerminatedExited,
+            2 => Self::SupervisorTerminatedSignaled,
+            3 => Self::Success,
+            4 => Self::Error,
+            5 => Self::Crashed,
+            6 => Self::Killed,
+            7 => Self::BoundStoppedExited,
+            8 => Self::BoundStoppedSignaled,
+            9 => Self::RuntimeExceededExited,
+            10 => Self::RuntimeExceededSignaled,
+            11 => Self::OomKilled,
+            12 => Self::Lost,
+            13 => Self::SpawnFailed,
+            14 => Self::Exited,
+            15 => Self::Detached,
+            other => return Err(SnapshotError::InvalidStopReason(other)),
+        })
+    }
+}
+
+/// State of a service in a snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordState {
+    Stopped { reason: StopReasonKind, value: i32 },
+    Running { pid: i32 },
+    Stopping { pid: i32 },
+    Paused { pid: i32 },
+}
+
+impl RecordState {
+    const STOPPED: u8 = 0;
+    const RUNNING: u8 = 1;
+    const STOPPING: u8 = 2;
+    const PAUSED: u8 = 3;
+
+    /// The pid of the service process, if any
+    pub fn pid(&self) -> Option<i32> {
+        match self {
+            Self::Stopped { .. } => None,
+            Self::Running { pid } | Self::Stopping { pid } | Self::Paused { pid } => Some(*pid),
+        }
+    }
+}
+
+/// Conditions of a service worth attention, whatever its state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecordFlags(pub u16);
+
+impl RecordFlags {
+    /// The service holds more file descriptors than `fd_warn_percent` of
+    /// its open file limit
+    pub const FDS_HIGH: u16 = 1 << 0;
+    /// The `env_file` of the service changed since its process started
+    pub const ENV_STALE: u16 = 1 << 1;
+    /// The service process runs a config other than the loaded one
+    pub const RESTART_REQUIRED: u16 = 1 << 2;
+
+    const NAMES: [(u16, &'static str); 3] = [
+        (Self::FDS_HIGH, "fds_high"),
+        (Self::ENV_STALE, "env_stale"),
+        (Self::RESTART_REQUIRED, "restart_required"),
+    ];
+
+    pub fn contains(&self, flag: u16) -> bool {
+        self.0 & flag != 0
+    }
+
+    pub fn set(&mut self, flag: u16, value: bool) {
+        if value {
+            self.0 |= flag;
+        } else {
+            self.0 &= !flag;
+        }
+    }
+}
+
+/// Formats the names of the flags set, each preceded by a space
+impl fmt::Display for RecordFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (flag, name) in Self::NAMES {
+            if self.contains(flag) {
+                write!(f, " {}", name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats like the state and pid or reason columns of the status file
+impl fmt::Display for RecordState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Running { pid } => write!(f, "running {}", pid),
+            Self::Stopping { pid } => write!(f, "stopping {}", pid),
+            Self::Paused { pid } => write!(f, "paused {}", pid),
+            Self::Stopped { reason, value } => {
+                f.write_str("stopped ")?;
+                match reason {
+                    StopReasonKind::NeverStarted => f.write_str("never_started"),
+                    StopReasonKind::SupervisorTerminatedExited => {
+                        write!(f, "supervisor_terminated(exited({}))", value)
+                    }
+                    StopReasonKind::SupervisorTerminatedSignaled => {
+                        write!(f, "supervisor_terminated(signaled({}))", value)
+                    }
+                    StopReasonKind::Success => f.write_str("success"),
+                    StopReasonKind::Error => write!(f, "error({})", value),
+                    StopReasonKind::Crashed => write!(f, "crashed({})", value),
+                    StopReasonKind::Killed => write!(f, "killed({})", value),
+                    StopReasonKind::BoundStoppedExited => {
+                        write!(f, "bound_stopped(exited({}))", value)
+                    }
+                    StopReasonKind::BoundStoppedSignaled => {
+                        write!(f, "bound_stopped(signaled({}))", value)
+                    }
+                    StopReasonKind::RuntimeExceededExited => {
+                        write!(f, "runtime_exceeded(exited({}))", value)
+                    }
+                    StopReasonKind::RuntimeExceededSignaled => {
+                        write!(f, "runtime_exceeded(signaled({}))", value)
+                    }
+                    StopReasonKind::OomKilled => f.write_str("oom_killed"),
+                    StopReasonKind::Lost => f.write_str("lost"),
+                    StopReasonKind::SpawnFailed => write!(f, "spawn_failed({})", value),
+                    StopReasonKind::Exited => f.write_str("exited"),
+                    StopReasonKind::Detached => write!(f, "detached({})", value),
+                }
+            }
+        }
+    }
+}
+
+/// A service in a snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceRecord {
+    pub id: u64,
+    pub name: String,
+    pub state: RecordState,
+    /// Number of times the service has been started
+    pub start_count: u64,
+    pub flags: RecordFlags,
+}
+
+/// The supervisor state at a point in time
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Creation time, as seconds and nanoseconds since the unix epoch
+    pub secs: u64,
+    pub nsecs: u32,
+    /// Pid of the supervisor that wrote the snapshot
+    pub pid: u32,
+    pub services: Vec<ServiceRecord>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    InvalidState(u8),
+    InvalidStopReason(u8),
+    InvalidName,
+    TrailingData(usize),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => f.write_str("not a svlopp snapshot"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported snapshot version {}", v),
+            Self::Truncated => f.write_str("truncated snapshot"),
+            Self::InvalidState(s) => write!(f, "invalid service state {}", s),
+            Self::InvalidStopReason(r) => write!(f, "invalid stop reason {}", r),
+            Self::InvalidName => f.write_str("service name is not valid utf-8"),
+            Self::TrailingData(n) => write!(f, "{} bytes of trailing data", n),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// Consumes fixed size little-endian fields from a byte slice
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.buf.len() < n {
+            return Err(SnapshotError::Truncated);
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        Ok(self.take(N)?.try_into().expect("`take` returned N bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SnapshotError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, SnapshotError> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+}
+
+/// A service in a snapshot, borrowing its name, so that a snapshot can
+/// be encoded without building a `Snapshot` first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServiceRecordRef<'a> {
+    pub id: u64,
+    pub name: &'a str,
+    pub state: RecordState,
+    pub start_count: u64,
+    pub flags: RecordFlags,
+}
+
+/// Formats like a line of the status file, `<name> <id> <state>` followed
+/// by the flags set
+impl fmt::Display for ServiceRecordRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}{}", self.name, self.id, self.state, self.flags)
+    }
+}
+
+impl ServiceRecord {
+    pub fn borrowed(&self) -> ServiceRecordRef<'_> {
+        ServiceRecordRef {
+            id: self.id,
+            name: &self.name,
+            state: self.state,
+            start_count: self.start_count,
+            flags: self.flags,
+        }
+    }
+}
+
+/// Append a snapshot of `services`, created at `secs` and `nsecs` by the
+/// supervisor `pid`, to `buf`. Nothing is allocated once `buf` is large
+/// enough, which makes it cheap to encode on every state change
+pub fn encode_snapshot<'a>(
+    buf: &mut Vec<u8>,
+    secs: u64,
+    nsecs: u32,
+    pid: u32,
+    services: impl ExactSizeIterator<Item = ServiceRecordRef<'a>>,
+) {
+    buf.reserve(HEADER_LEN + services.len() * RECORD_LEN);
+    buf.extend_from_slice(&SNAPSHOT_MAGIC);
+    buf.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+    buf.extend_from_slice(&0u16.to_le_bytes());
+    buf.extend_from_slice(&(services.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&secs.to_le_bytes());
+    buf.extend_from_slice(&nsecs.to_le_bytes());
+    buf.extend_from_slice(&pid.to_le_bytes());
+    for svc in services {
+        let (state, reason, value) = match svc.state {
+            RecordState::Stopped { reason, value } => (RecordState::STOPPED, reason as u8, value),
+            RecordState::Running { pid } => (RecordState::RUNNING, 0, pid),
+            RecordState::Stopping { pid } => (RecordState::STOPPING, 0, pid),
+            RecordState::Paused { pid } => (RecordState::PAUSED, 0, pid),
+        };
+        buf.extend_from_slice(&svc.id.to_le_bytes());
+        buf.extend_from_slice(&svc.start_count.to_le_bytes());
+        buf.push(state);
+        buf.push(reason);
+        buf.extend_from_slice(&svc.flags.0.to_le_bytes());
+        buf.extend_from_slice(&value.to_le_bytes());
+        buf.extend_from_slice(&(svc.name.len() as u32).to_le_bytes());
+        buf.extend_from_slice(svc.name.as_bytes());
+    }
+}
+
+impl Snapshot {
+    /// Append the encoded snapshot to `buf`
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        encode_snapshot(
+            buf,
+            self.secs,
+            self.nsecs,
+            self.pid,
+            self.services.iter().map(ServiceRecord::borrowed),
+        );
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self, SnapshotError> {
+        let mut r = Reader { buf };
+        if r.array::<8>()? != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = r.u16()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let _reserved = r.u16()?;
+        let count = r.u32()? as usize;
+        let secs = r.u64()?;
+        let nsecs = r.u32()?;
+        let pid = r.u32()?;
+        // don't trust `count` for the allocation size
+        let mut services = Vec::with_capacity(count.min(r.buf.len() / RECORD_LEN));
+        for _ in 0..count {
+            let id = r.u64()?;
+            let start_count = r.u64()?;
+            let state = r.u8()?;
+            let reason = r.u8()?;
+            let flags = RecordFlags(r.u16()?);
+            let value = r.i32()?;
+            let name_len = r.u32()? as usize;
+            let name = std::str::from_utf8(r.take(name_len)?)
+                .map_err(|_| SnapshotError::InvalidName)?
+                .to_owned();
+            let state = match state {
+                RecordState::STOPPED => RecordState::Stopped {
+                    reason: StopReasonKind::try_from(reason)?,
+                    value,
+                },
+                RecordState::RUNNING => RecordState::Running { pid: value },
+                RecordState::STOPPING => RecordState::Stopping { pid: value },
+                RecordState::PAUSED => RecordState::Paused { pid: value },
+                other => return Err(SnapshotError::InvalidState(other)),
+            };
+            services.push(ServiceRecord {
+                id,
+                name,
+                state,
+                start_count,
+                flags,
+            });
+        }
+        if !r.buf.is_empty() {
+            return Err(SnapshotError::TrailingData(r.buf.len()));
+        }
+        Ok(Self {
+            secs,
+            nsecs,
+            pid,
+            services,
+        })
+    }
+}
@@ -0,0 +1,612 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Creation of service processes.
+//!
+//! The child side, from restoring the signal mask to `execvpe`, is shared
+//! by all the ways a process is created: the `SpawnStrategy` the
+//! supervisor forks with and the spawn helper of `--spawner`.
+
+use std::{
+    ffi::{CStr, CString},
+    io,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+    path::Path,
+};
+
+use rustix::{
+    fs::{Mode, OFlags},
+    pipe::{PipeFlags, pipe_with},
+    process::{Pid, Signal, WaitOptions, chdir, chroot, kill_process, setpgid, waitpid},
+    stdio::{dup2_stderr, dup2_stdin, dup2_stdout},
+};
+use serde::Deserialize;
+
+use crate::keyring::join_private_keyring;
+use crate::mounts::{MountSpec, bind, enter_mount_namespace, switch_root};
+use crate::platform::Platform;
+use crate::service::{ServiceConfig, UserGroup};
+use crate::signalfd::{SigSet, set_thread_signal_mask};
+use crate::userns::UserNamespace;
+use crate::utils::{cvt, errno, errno_name, retry_eintr, write_all};
+
+/// How the supervisor creates service processes, the `spawn_strategy`
+/// supervisor option
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SpawnStrategy {
+    /// `fork`, then set the child up and exec
+    #[default]
+    Fork,
+    /// `posix_spawnp`, which the C library implements with
+    /// `CLONE_VM | CLONE_VFORK`: the supervisor memory isn't copied, and
+    /// exec failures are reported as start failures. It can't switch user
+    /// or root, services with a `user_group`, a `user_namespace`, a
+    /// `root_dir`, `mounts`, a cgroup of their own, a security label or
+    /// `keyring = "private"` are forked
+    PosixSpawn,
+    /// `clone3` with `CLONE_PIDFD`, so that the pidfd of the process comes
+    /// along with it instead of being opened afterwards. Processes are
+    /// forked without `clone3`
+    Clone3,
+}
+
+/// Security label a service process execs with, see `crate::lsm`
+#[derive(Debug)]
+#[cfg_attr(not(feature = "lsm"), allow(dead_code))]
+pub(crate) struct ExecLabel {
+    /// `attr` file of the process in procfs the label is written to
+    pub(crate) path: &'static CStr,
+    pub(crate) value: CString,
+}
+
+/// What a service process is started with
+#[derive(Debug)]
+pub(crate) struct ExecSpec<'a> {
+    pub(crate) argv: &'a [CString],
+    pub(crate) envp: &'a [CString],
+    pub(crate) user_group: Option<UserGroup>,
+    /// User namespace the process is cloned in, see `crate::userns`
+    pub(crate) user_namespace: Option<&'a UserNamespace>,
+    /// Read end of the pipe the supervisor writes the outcome of mapping
+    /// the ids of `user_namespace` to, an errno or 0. Set by `spawn`
+    pub(crate) userns_fd: Option<BorrowedFd<'a>>,
+    pub(crate) working_directory: Option<&'a Path>,
+    /// Directory the process is chrooted to, `working_directory` and the
+    /// `PATH` lookup of the command are relative to it
+    pub(crate) root_dir: Option<&'a Path>,
+    /// Bind mounts set up in a new mount namespace, none if empty
+    pub(crate) mounts: &'a [MountSpec<'a>],
+    /// Set before the mounts, while the procfs of the supervisor is
+    /// still reachable
+    pub(crate) exec_label: Option<&'a ExecLabel>,
+    /// Whether the process joins a new session keyring, see
+    /// `crate::keyring`
+    pub(crate) private_keyring: bool,
+    /// `cgroup.procs` of the cgroup the process moves to, see
+    /// `crate::cgroup`
+    pub(crate) cgroup_fd: Option<BorrowedFd<'a>>,
+    pub(crate) devnull_fd: BorrowedFd<'a>,
+    pub(crate) stdout_fd: Option<BorrowedFd<'a>>,
+    pub(crate) stderr_fd: Option<BorrowedFd<'a>>,
+    /// Fds the process inherits, each with the fd number it gets them as
+    pub(crate) inherited_fds: &'a [(BorrowedFd<'a>, RawFd)],
+    /// Write end of a `O_CLOEXEC` pipe, closed once the process execs. If
+    /// the setup or the exec fails, a `SpawnError` is written to it before
+    /// exiting
+    pub(crate) exec_fd: Option<BorrowedFd<'a>>,
+}
+
+/// `argv` and `envp` of an `ExecSpec` as the null terminated arrays
+/// `execvpe` takes. They are built before forking, so that the child
+/// doesn't allocate
+#[derive(Debug)]
+pub(crate) struct ExecArgs {
+    argv: Vec<*const libc::c_char>,
+    envp: Vec<*const libc::c_char>,
+}
+
+impl ExecArgs {
+    pub(crate) fn new(spec: &ExecSpec) -> Self {
+        let pointers = |strings: &[CString]| {
+            strings
+                .iter()
+                .map(|s| s.as_ptr())
+                .chain(std::iter::once(std::ptr::null()))
+                .collect()
+        };
+        Self {
+            argv: pointers(spec.argv),
+            envp: pointers(spec.envp),
+        }
+    }
+}
+
+/// A started service process
+#[derive(Debug)]
+pub(crate) struct Spawned {
+    pub(crate) pid: i32,
+    /// Its pidfd, if it came with it
+    pub(crate) pidfd: Option<OwnedFd>,
+}
+
+impl SpawnStrategy {
+    /// Start the process described by `spec`, which starts with the
+    /// `sigset` signal mask
+    pub(crate) fn spawn(self, spec: &ExecSpec, sigset: &SigSet) -> io::Result<Spawned> {
+        let args = ExecArgs::new(spec);
+        if let Some(userns) = spec.user_namespace {
+            return self.spawn_in_user_namespace(spec, userns, &args, sigset);
+        }
+        match self {
+            Self::PosixSpawn
+                if spec.user_group.is_none()
+                    && spec.root_dir.is_none()
+                    && spec.mounts.is_empty()
+                    && spec.cgroup_fd.is_none()
+                    && spec.exec_label.is_none()
+                    && !spec.private_keyring =>
+            {
+                posix_spawn(spec, &args, sigset)
+            }
+            Self::Clone3 if Platform::get().clone3 => clone3(spec, &args, sigset, 0),
+            _ => fork(spec, &args, sigset),
+        }
+    }
+
+    /// Clone the process in a new user namespace and map its ids, which
+    /// it waits for before going on with its setup. It can't be done by
+    /// `posix_spawn`, that strategy clones as `fork`
+    fn spawn_in_user_namespace(
+        self,
+        spec: &ExecSpec,
+        userns: &UserNamespace,
+        args: &ExecArgs,
+        sigset: &SigSet,
+    ) -> io::Result<Spawned> {
+        let (rd, wr) = pipe_with(PipeFlags::CLOEXEC)?;
+        let spec = ExecSpec {
+            userns_fd: Some(rd.as_fd()),
+            ..*spec
+        };
+        let flags = libc::CLONE_NEWUSER as u64;
+        let spawned = match self {
+            Self::Clone3 if Platform::get().clone3 => clone3(&spec, args, sigset, flags)?,
+            _ => clone(&spec, args, sigset, flags)?,
+        };
+        let errno = match userns.write_maps(spawned.pid) {
+            Ok(()) => 0,
+            Err(e) => e.raw_os_error().unwrap_or(libc::EIO),
+        };
+        if let Err(e) = write_all(wr.as_fd(), &errno.to_ne_bytes()) {
+            // the child would otherwise be left waiting on the pipe, with
+            // nothing to reap it
+            if let Some(pid) = Pid::from_raw(spawned.pid) {
+                let _ = kill_process(pid, Signal::KILL);
+                let _ = retry_eintr(|| waitpid(Some(pid), WaitOptions::empty()));
+            }
+            return Err(e);
+        }
+        Ok(spawned)
+    }
+}
+
+fn fork(spec: &ExecSpec, args: &ExecArgs, sigset: &SigSet) -> io::Result<Spawned> {
+    match unsafe { libc::fork() } {
+        0 => child_exec(spec, args, sigset),
+        pid if pid > 0 => Ok(Spawned { pid, pidfd: None }),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// `fork` with the extra clone `flags`
+fn clone(spec: &ExecSpec, args: &ExecArgs, sigset: &SigSet, flags: u64) -> io::Result<Spawned> {
+    let pid = unsafe {
+        libc::syscall(
+            libc::SYS_clone,
+            flags | libc::SIGCHLD as u64,
+            0usize,
+            0usize,
+            0usize,
+            0usize,
+        )
+    };
+    match pid {
+        0 => child_exec(spec, args, sigset),
+        pid if pid > 0 => Ok(Spawned {
+            pid: pid as i32,
+            pidfd: None,
+        }),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// The `CLONE_ARGS_SIZE_VER0` fields of `struct clone_args`
+#[repr(C)]
+#[derive(Default)]
+struct CloneArgs {
+    flags: u64,
+    pidfd: u64,
+    child_tid: u64,
+    parent_tid: u64,
+    exit_signal: u64,
+    stack: u64,
+    stack_size: u64,
+    tls: u64,
+}
+
+fn clone3(spec: &ExecSpec, args: &ExecArgs, sigset: &SigSet, flags: u64) -> io::Result<Spawned> {
+    let mut pidfd: RawFd = -1;
+    let mut clone_args = CloneArgs {
+        flags: flags | libc::CLONE_PIDFD as u64,
+        pidfd: &raw mut pidfd as u64,
+        exit_signal: libc::SIGCHLD as u64,
+        ..Default::default()
+    };
+    // the pidfd is opened `O_CLOEXEC`
+    let pid = unsafe {
+        libc::syscall(
+            libc::SYS_clone3,
+            &raw mut clone_args,
+            std::mem::size_of::<CloneArgs>(),
+        )
+    };
+    match pid {
+        0 => child_exec(spec, args, sigset),
+        pid if pid > 0 => Ok(Spawned {
+            pid: pid as i32,
+            // SAFETY: the kernel opened the pidfd along with the process
+            pidfd: Some(unsafe { OwnedFd::from_raw_fd(pidfd) }),
+        }),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Owns the `posix_spawn` attributes and file actions, destroyed on drop
+struct PosixSpawnConfig {
+    attr: libc::posix_spawnattr_t,
+    actions: libc::posix_spawn_file_actions_t,
+}
+
+impl Drop for PosixSpawnConfig {
+    fn drop(&mut self) {
+        unsafe {
+            libc::posix_spawnattr_destroy(&mut self.attr);
+            libc::posix_spawn_file_actions_destroy(&mut self.actions);
+        }
+    }
+}
+
+/// Errors of the `posix_spawn` functions are returned, not set in `errno`
+fn check_spawn(ret: libc::c_int) -> io::Result<()> {
+    match ret {
+        0 => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+fn posix_spawn(spec: &ExecSpec, args: &ExecArgs, sigset: &SigSet) -> io::Result<Spawned> {
+    // SAFETY: both are initialized right away, the C library functions
+    // initializing them don't fail
+    let mut config: PosixSpawnConfig = unsafe { std::mem::zeroed() };
+    check_spawn(unsafe { libc::posix_spawnattr_init(&mut config.attr) })?;
+    check_spawn(unsafe { libc::posix_spawn_file_actions_init(&mut config.actions) })?;
+    let attr = &raw mut config.attr;
+    let actions = &raw mut config.actions;
+    unsafe {
+        check_spawn(libc::posix_spawnattr_setflags(
+            attr,
+            (libc::POSIX_SPAWN_SETSIGMASK | libc::POSIX_SPAWN_SETPGROUP) as libc::c_short,
+        ))?;
+        check_spawn(libc::posix_spawnattr_setpgroup(attr, 0))?;
+        check_spawn(libc::posix_spawnattr_setsigmask(attr, sigset.as_ptr()))?;
+        if let Some(cwd) = spec.working_directory {
+            let cwd = CString::new(cwd.as_os_str().as_encoded_bytes())?;
+            check_spawn(libc::posix_spawn_file_actions_addchdir_np(
+                actions,
+                cwd.as_ptr(),
+            ))?;
+        }
+        let devnull_fd = spec.devnull_fd.as_raw_fd();
+        for (fd, target) in [
+            (devnull_fd, libc::STDIN_FILENO),
+            (
+                spec.stdout_fd.map_or(devnull_fd, |fd| fd.as_raw_fd()),
+                libc::STDOUT_FILENO,
+            ),
+            (
+                spec.stderr_fd.map_or(devnull_fd, |fd| fd.as_raw_fd()),
+                libc::STDERR_FILENO,
+            ),
+        ]
+        .into_iter()
+        .chain(
+            spec.inherited_fds
+                .iter()
+                .map(|(fd, target)| (fd.as_raw_fd(), *target)),
+        ) {
+            // a `dup2` to the same fd clears `FD_CLOEXEC`
+            check_spawn(libc::posix_spawn_file_actions_adddup2(actions, fd, target))?;
+        }
+        let mut pid = 0;
+        check_spawn(libc::posix_spawnp(
+            &mut pid,
+            args.argv[0],
+            actions,
+            attr,
+            args.argv.as_ptr().cast(),
+            args.envp.as_ptr().cast(),
+        ))?;
+        Ok(Spawned { pid, pidfd: None })
+    }
+}
+
+/// Configure standard file descriptors.
+///
+/// Invariants:
+/// - `devnull_fd` must be an open fd referring to `/dev/null`, opened for read-write
+/// - `stdout_fd` and `stderr_fd`, if present, must be open for write
+/// - All fds must remain valid across fork and must not have been closed in the child
+///
+/// It is intended to be called in the child arm of a fork, before execvp, so it must
+/// not perform any non async-signal-safe operation
+fn setup_child_stdio(
+    devnull_fd: BorrowedFd,
+    stdout_fd: Option<BorrowedFd>,
+    stderr_fd: Option<BorrowedFd>,
+) -> rustix::io::Result<()> {
+    dup2_stdin(devnull_fd)?;
+    dup2_stdout(stdout_fd.unwrap_or(devnull_fd))?;
+    dup2_stderr(stderr_fd.unwrap_or(devnull_fd))?;
+    Ok(())
+}
+
+/// Step of the setup of a service process, between the fork and the
+/// exec
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) enum SpawnStep {
+    SignalMask,
+    ProcessGroup,
+    Cgroup,
+    UserNamespace,
+    ExecLabel,
+    MountNamespace,
+    Mount,
+    Chroot,
+    Setgid,
+    Setuid,
+    Keyring,
+    Chdir,
+    Stdio,
+    InheritFds,
+    Exec,
+}
+
+impl SpawnStep {
+    const ALL: [Self; 15] = [
+        Self::SignalMask,
+        Self::ProcessGroup,
+        Self::Cgroup,
+        Self::UserNamespace,
+        Self::ExecLabel,
+        Self::MountNamespace,
+        Self::Mount,
+        Self::Chroot,
+        Self::Setgid,
+        Self::Setuid,
+        Self::Keyring,
+        Self::Chdir,
+        Self::Stdio,
+        Self::InheritFds,
+        Self::Exec,
+    ];
+}
+
+/// The step the setup of a service process failed at, and its errno
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct SpawnError {
+    pub(crate) step: SpawnStep,
+    pub(crate) errno: i32,
+    /// The entry of the config the step failed on, e.g. of `mounts`
+    pub(crate) index: u32,
+}
+
+impl SpawnError {
+    const LEN: usize = 12;
+
+    /// Read the error a process wrote to the exec pipe `rd`, see
+    /// `ExecSpec::exec_fd`. `None` if the process exec'd, or is yet to
+    pub(crate) fn read(rd: BorrowedFd) -> Option<Self> {
+        let mut buf = [0u8; Self::LEN];
+        match retry_eintr(|| rustix::io::read(rd, &mut buf)) {
+            Ok(Self::LEN) => {
+                let word = |i: usize| buf[i * 4..][..4].try_into().expect("4 bytes");
+                Some(Self {
+                    step: *SpawnStep::ALL.get(u32::from_ne_bytes(word(0)) as usize)?,
+                    errno: i32::from_ne_bytes(word(1)),
+                    index: u32::from_ne_bytes(word(2)),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether retrying can't help, the command not existing
+    pub(crate) fn is_not_found(&self) -> bool {
+        self.step == SpawnStep::Exec && self.errno == libc::ENOENT
+    }
+
+    /// The failed call with the arguments it got from `cfg`, and the errno
+    /// name, e.g. `setuid(1000) failed: EPERM`
+    pub(crate) fn describe(&self, cfg: &ServiceConfig) -> String {
+        let call = match (self.step, cfg.user_group) {
+            (SpawnStep::SignalMask, _) => "sigprocmask".to_string(),
+            (SpawnStep::ProcessGroup, _) => "setpgid".to_string(),
+            (SpawnStep::Cgroup, _) => "cgroup.procs write".to_string(),
+            (SpawnStep::UserNamespace, _) => "user_namespace id mapping".to_string(),
+            (SpawnStep::ExecLabel, _) => match (&cfg.selinux_context, &cfg.apparmor_profile) {
+                (Some(context), _) => format!("setexeccon({})", context),
+                (None, Some(profile)) => format!("aa_change_onexec({})", profile),
+                (None, None) => "exec label".to_string(),
+            },
+            (SpawnStep::MountNamespace, _) => "unshare(mount namespace)".to_string(),
+            (SpawnStep::Mount, _) => match cfg.mounts.get(self.index as usize) {
+                Some(mount) => format!(
+                    "mount({} on {}{})",
+                    mount.src.display(),
+                    mount.dst.display(),
+                    if mount.ro { ", ro" } else { "" }
+                ),
+                None if cfg.private_tmp => "mount(private_tmp on /tmp)".to_string(),
+                None => "mount".to_string(),
+            },
+            (SpawnStep::Chroot, _) => match &cfg.root_dir {
+                Some(root) if !cfg.mounts.is_empty() || cfg.private_tmp => {
+                    format!("pivot_root({})", root.display())
+                }
+                Some(root) => format!("chroot({})", root.display()),
+                None => "chroot".to_string(),
+            },
+            (SpawnStep::Setgid, Some(ug)) => format!("setgid({})", ug.gid),
+            (SpawnStep::Setuid, Some(ug)) => format!("setuid({})", ug.uid),
+            (SpawnStep::Setgid, None) => "setgid".to_string(),
+            (SpawnStep::Setuid, None) => "setuid".to_string(),
+            (SpawnStep::Keyring, _) => "keyctl(join_session_keyring)".to_string(),
+            (SpawnStep::Chdir, _) => match &cfg.working_directory {
+                Some(cwd) => format!("chdir({})", cwd.display()),
+                None => "chdir".to_string(),
+            },
+            (SpawnStep::Stdio, _) => "dup2(stdio)".to_string(),
+            (SpawnStep::InheritFds, _) => "dup2(secrets)".to_string(),
+            (SpawnStep::Exec, _) => format!("exec({})", cfg.command),
+        };
+        match errno_name(self.errno) {
+            Some(name) => format!("{} failed: {}", call, name),
+            None => format!("{} failed: errno {}", call, self.errno),
+        }
+    }
+}
+
+/// Wait for the supervisor to map the ids of the user namespace, in the
+/// child. An errno is returned if it failed to
+fn wait_user_namespace(fd: BorrowedFd) -> rustix::io::Result<()> {
+    let mut buf = [0u8; 4];
+    match retry_eintr(|| rustix::io::read(fd, &mut buf))? {
+        4 => match i32::from_ne_bytes(buf) {
+            0 => Ok(()),
+            errno => Err(rustix::io::Errno::from_raw_os_error(errno)),
+        },
+        _ => Err(rustix::io::Errno::IO),
+    }
+}
+
+/// Write `label` to its `attr` file, for it to apply from the exec on
+fn set_exec_label(label: &ExecLabel) -> rustix::io::Result<()> {
+    let fd = rustix::fs::open(label.path, OFlags::WRONLY | OFlags::CLOEXEC, Mode::empty())?;
+    let value = label.value.as_bytes();
+    if retry_eintr(|| rustix::io::write(&fd, value))? != value.len() {
+        return Err(rustix::io::Errno::IO);
+    }
+    Ok(())
+}
+
+/// Report the failure of `step` on the entry `index` with `errno` to the
+/// supervisor and exit, with 127 for the exec as a shell would and 111 for
+/// the steps before it
+fn child_fail(spec: &ExecSpec, step: SpawnStep, index: usize, errno: i32) -> ! {
+    let mut buf = [0u8; SpawnError::LEN];
+    buf[..4].copy_from_slice(&(step as u32).to_ne_bytes());
+    buf[4..8].copy_from_slice(&errno.to_ne_bytes());
+    buf[8..].copy_from_slice(&(index as u32).to_ne_bytes());
+    unsafe {
+        if let Some(fd) = spec.exec_fd {
+            libc::write(fd.as_raw_fd(), buf.as_ptr().cast(), buf.len());
+        }
+        libc::_exit(if step == SpawnStep::Exec { 127 } else { 111 })
+    }
+}
+
+/// Set the child up as described by `spec` and exec, in the child arm of
+/// a fork. A failing step is reported over `spec.exec_fd`
+pub(crate) fn child_exec(spec: &ExecSpec, args: &ExecArgs, sigset: &SigSet) -> ! {
+    let fail = |step, e: rustix::io::Errno| child_fail(spec, step, 0, e.raw_os_error());
+    if let Err(e) = set_thread_signal_mask(sigset) {
+        fail(SpawnStep::SignalMask, e)
+    }
+    if let Err(e) = setpgid(None, None) {
+        fail(SpawnStep::ProcessGroup, e)
+    }
+    // the fd was opened by the supervisor, whose permissions the kernel
+    // checks, before any namespace or id switch
+    if let Some(fd) = spec.cgroup_fd
+        && let Err(e) = rustix::io::write(fd, b"0")
+    {
+        fail(SpawnStep::Cgroup, e)
+    }
+    if let Some(fd) = spec.userns_fd
+        && let Err(e) = wait_user_namespace(fd)
+    {
+        fail(SpawnStep::UserNamespace, e)
+    }
+    if let Some(label) = spec.exec_label
+        && let Err(e) = set_exec_label(label)
+    {
+        fail(SpawnStep::ExecLabel, e)
+    }
+    if !spec.mounts.is_empty() {
+        if let Err(e) = enter_mount_namespace() {
+            fail(SpawnStep::MountNamespace, e)
+        }
+        for (i, mount) in spec.mounts.iter().enumerate() {
+            if let Err(e) = bind(mount) {
+                child_fail(spec, SpawnStep::Mount, i, e.raw_os_error())
+            }
+        }
+    }
+    // while still privileged, the cwd is moved inside the new root
+    if let Some(root) = spec.root_dir {
+        let switched = if spec.mounts.is_empty() {
+            chroot(root).and_then(|()| chdir("/"))
+        } else {
+            switch_root(root)
+        };
+        if let Err(e) = switched {
+            fail(SpawnStep::Chroot, e)
+        }
+    }
+    if let Some(ug) = spec.user_group {
+        if let Err(e) = cvt(unsafe { libc::setgid(ug.gid) }) {
+            fail(SpawnStep::Setgid, e)
+        }
+        if let Err(e) = cvt(unsafe { libc::setuid(ug.uid) }) {
+            fail(SpawnStep::Setuid, e)
+        }
+    }
+    if spec.private_keyring
+        && let Err(e) = join_private_keyring()
+    {
+        fail(SpawnStep::Keyring, e)
+    }
+    if let Some(cwd) = spec.working_directory
+        && let Err(e) = chdir(cwd)
+    {
+        fail(SpawnStep::Chdir, e)
+    }
+    if let Err(e) = setup_child_stdio(spec.devnull_fd, spec.stdout_fd, spec.stderr_fd) {
+        fail(SpawnStep::Stdio, e)
+    }
+    for &(fd, target) in spec.inherited_fds {
+        // `dup2` clears `FD_CLOEXEC` on the new fd
+        let ret = if fd.as_raw_fd() == target {
+            unsafe { cvt(libc::fcntl(target, libc::F_SETFD, 0)) }
+        } else {
+            unsafe { cvt(libc::dup2(fd.as_raw_fd(), target)) }
+        };
+        if let Err(e) = ret {
+            fail(SpawnStep::InheritFds, e)
+        }
+    }
+    unsafe { libc::execvpe(args.argv[0], args.argv.as_ptr(), args.envp.as_ptr()) };
+    child_fail(spec, SpawnStep::Exec, 0, errno())
+}
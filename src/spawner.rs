@@ -0,0 +1,486 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Preforked spawn helper (`--spawner`).
+//!
+//! Forking the supervisor costs in proportion to its mappings, which grow
+//! with the registry and the log pump buffers, and the copy-on-write
+//! faults taken until the child execs. The spawner is a process forked
+//! before any of that is allocated and before any thread is started: the
+//! main loop hands it spawn requests over a `SOCK_SEQPACKET` socketpair,
+//! along with the fds the service process inherits, and it forks and
+//! execs in place of the supervisor.
+//!
+//! The spawner clones with `CLONE_PARENT`, so that service processes are
+//! still children of the supervisor: they are reaped, signaled and traced
+//! exactly as the ones it forks itself. Whenever the spawner can't be
+//! used, the supervisor falls back to forking.
+
+use std::{
+    cell::Cell,
+    ffi::{CStr, CString, OsStr},
+    io::{self, IoSlice, IoSliceMut},
+    mem::MaybeUninit,
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+    path::Path,
+};
+
+use rustix::io::{Errno, fcntl_dupfd_cloexec};
+use rustix::net::{
+    AddressFamily, RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags, ReturnFlags,
+    SendAncillaryBuffer, SendAncillaryMessage, SendFlags, Shutdown, SocketFlags, SocketType, recv,
+    recvmsg, send, sendmsg, shutdown, socketpair,
+};
+use rustix::process::{Pid, Signal, WaitOptions, getpid, getppid, waitpid};
+
+use crate::logging::LogLevel;
+use crate::mounts::MountSpec;
+use crate::platform::Platform;
+use crate::service::UserGroup;
+use crate::signalfd::SigSet;
+use crate::spawn::{ExecArgs, ExecSpec, child_exec};
+use crate::svlogg;
+use crate::utils::{cvt, cvt_r, retry_eintr};
+
+/// Largest request, kept below the default socket send buffer size so
+/// that a request never fails for its size alone
+const MAX_REQUEST_LEN: usize = 128 * 1024;
+
+/// Largest number of fds sent along with a request, the kernel rejects
+/// more than 253 (`SCM_MAX_FD`)
+const MAX_REQUEST_FDS: usize = 64;
+
+const FLAG_USER_GROUP: u8 = 1;
+const FLAG_WORKING_DIRECTORY: u8 = 2;
+const FLAG_STDOUT: u8 = 4;
+const FLAG_STDERR: u8 = 8;
+const FLAG_ROOT_DIR: u8 = 16;
+const FLAG_MOUNTS: u8 = 32;
+const FLAG_CGROUP: u8 = 64;
+const FLAG_PRIVATE_KEYRING: u8 = 128;
+
+/// `flags`, then `uid`, `gid` and the number of inherited fds, arguments
+/// and environment entries, each a native endian `u32`
+const HEADER_LEN: usize = 1 + 5 * 4;
+
+/// Handle of the spawn helper process
+#[derive(Debug)]
+pub(crate) struct Spawner {
+    sock: OwnedFd,
+    pid: Pid,
+    /// Set once the helper fails or is stopped, requests aren't sent
+    /// anymore
+    broken: Cell<bool>,
+}
+
+impl Spawner {
+    /// Fork the helper. Must be called while the process has a single
+    /// thread and with the supervisor signal mask already in place, the
+    /// helper keeps the signals blocked and `sigset` is the mask service
+    /// processes start with
+    pub(crate) fn start(sigset: &SigSet) -> io::Result<Self> {
+        let (sock, helper_sock) = socketpair(
+            AddressFamily::UNIX,
+            SocketType::SEQPACKET,
+            SocketFlags::CLOEXEC,
+            None,
+        )?;
+        let supervisor = getpid();
+        match unsafe { libc::fork() } {
+            0 => {
+                drop(sock);
+                serve(helper_sock, supervisor, sigset)
+            }
+            raw if raw > 0 => {
+                // safe as we just checked that the pid is > 0
+                let pid = unsafe { Pid::from_raw_unchecked(raw) };
+                svlogg!(LogLevel::Debug, "spawn helper started with pid {}", raw);
+                Ok(Self {
+                    sock,
+                    pid,
+                    broken: Cell::new(false),
+                })
+            }
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    /// Have the helper start the process described by `spec`.
+    ///
+    /// Returns `None` if the request didn't go through the helper, the
+    /// caller must then fork itself, or the result of the helper `clone`
+    pub(crate) fn spawn(&self, spec: &ExecSpec) -> Option<io::Result<i32>> {
+        if self.broken.get() {
+            return None;
+        }
+        let (request, fds) = encode_request(spec)?;
+        match self.request(&request, &fds) {
+            Ok(raw) if raw > 0 => Some(Ok(raw)),
+            Ok(raw) => Some(Err(io::Error::from_raw_os_error(-raw))),
+            Err(e) => {
+                svlogg!(
+                    LogLevel::Error,
+                    "spawn helper failed, forking service processes: {}",
+                    e
+                );
+                self.broken.set(true);
+                None
+            }
+        }
+    }
+
+    fn request(&self, request: &[u8], fds: &[BorrowedFd]) -> rustix::io::Result<i32> {
+        let mut space = [MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(MAX_REQUEST_FDS))];
+        let mut control = SendAncillaryBuffer::new(&mut space);
+        if !control.push(SendAncillaryMessage::ScmRights(fds)) {
+            return Err(Errno::NOBUFS);
+        }
+        retry_eintr(|| {
+            sendmsg(
+                &self.sock,
+                &[IoSlice::new(request)],
+                &mut control,
+                SendFlags::NOSIGNAL,
+            )
+        })?;
+        let mut reply = [0u8; 4];
+        let n = retry_eintr(|| recv(&self.sock, &mut reply, RecvFlags::empty()).map(|(_, n)| n))?;
+        if n != reply.len() {
+            return Err(Errno::PIPE);
+        }
+        Ok(i32::from_ne_bytes(reply))
+    }
+
+    /// Stop the helper and reap it, service processes are forked from then
+    /// on. Called on shutdown, so that no child is left once services are
+    /// gone
+    pub(crate) fn stop(&self) {
+        if self.broken.replace(true) {
+            return;
+        }
+        let _ = shutdown(&self.sock, Shutdown::Both);
+        // the helper exits as soon as it reads the end of the socket, it
+        // may already be reaped if it died earlier
+        let _ = retry_eintr(|| waitpid(Some(self.pid), WaitOptions::empty()));
+    }
+}
+
+/// Serialize `spec`, returns the request and the fds to send along with it
+/// or `None` if they exceed what a single request can carry, `spec` has
+/// no exec pipe, needs a user namespace, which the helper doesn't map, or
+/// a security label, which it doesn't set
+fn encode_request<'a>(spec: &ExecSpec<'a>) -> Option<(Vec<u8>, Vec<BorrowedFd<'a>>)> {
+    let exec_fd = spec.exec_fd?;
+    if spec.user_namespace.is_some() || spec.exec_label.is_some() {
+        return None;
+    }
+    let mut flags = 0;
+    if spec.user_group.is_some() {
+        flags |= FLAG_USER_GROUP;
+    }
+    if spec.working_directory.is_some() {
+        flags |= FLAG_WORKING_DIRECTORY;
+    }
+    if spec.root_dir.is_some() {
+        flags |= FLAG_ROOT_DIR;
+    }
+    if !spec.mounts.is_empty() {
+        flags |= FLAG_MOUNTS;
+    }
+    if spec.stdout_fd.is_some() {
+        flags |= FLAG_STDOUT;
+    }
+    if spec.stderr_fd.is_some() {
+        flags |= FLAG_STDERR;
+    }
+    if spec.cgroup_fd.is_some() {
+        flags |= FLAG_CGROUP;
+    }
+    if spec.private_keyring {
+        flags |= FLAG_PRIVATE_KEYRING;
+    }
+    let ug = spec.user_group.unwrap_or(UserGroup { uid: 0, gid: 0 });
+    let mut request = Vec::with_capacity(HEADER_LEN);
+    request.push(flags);
+    for value in [
+        ug.uid,
+        ug.gid,
+        spec.inherited_fds.len() as u32,
+        spec.argv.len() as u32,
+        spec.envp.len() as u32,
+    ] {
+        request.extend_from_slice(&value.to_ne_bytes());
+    }
+    for (_, target) in spec.inherited_fds {
+        request.extend_from_slice(&target.to_ne_bytes());
+    }
+    let push_path = |request: &mut Vec<u8>, path: &Path| {
+        request.extend_from_slice(path.as_os_str().as_bytes());
+        request.push(0);
+    };
+    for path in [spec.working_directory, spec.root_dir]
+        .into_iter()
+        .flatten()
+    {
+        push_path(&mut request, path);
+    }
+    if !spec.mounts.is_empty() {
+        request.extend_from_slice(&(spec.mounts.len() as u32).to_ne_bytes());
+        for mount in spec.mounts {
+            request.push(u8::from(mount.ro));
+            push_path(&mut request, mount.src);
+            push_path(&mut request, mount.target);
+        }
+    }
+    for s in spec.argv.iter().chain(spec.envp) {
+        request.extend_from_slice(s.as_bytes_with_nul());
+    }
+
+    let mut fds = vec![spec.devnull_fd, exec_fd];
+    fds.extend(spec.stdout_fd);
+    fds.extend(spec.stderr_fd);
+    fds.extend(spec.cgroup_fd);
+    fds.extend(spec.inherited_fds.iter().map(|(fd, _)| *fd));
+    if request.len() > MAX_REQUEST_LEN || fds.len() > MAX_REQUEST_FDS {
+        svlogg!(
+            LogLevel::Debug,
+            "spawn request of {} too large for the helper",
+            spec.argv[0].to_string_lossy()
+        );
+        return None;
+    }
+    Some((request, fds))
+}
+
+/// Reads the fields of a request
+struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> rustix::io::Result<&'a [u8]> {
+        if self.buf.len() < n {
+            return Err(Errno::INVAL);
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> rustix::io::Result<u32> {
+        Ok(u32::from_ne_bytes(
+            self.take(4)?.try_into().expect("took 4 bytes"),
+        ))
+    }
+
+    fn cstr(&mut self) -> rustix::io::Result<&'a CStr> {
+        let s = CStr::from_bytes_until_nul(self.buf).map_err(|_| Errno::INVAL)?;
+        self.buf = &self.buf[s.count_bytes() + 1..];
+        Ok(s)
+    }
+
+    fn path(&mut self) -> rustix::io::Result<&'a Path> {
+        Ok(Path::new(OsStr::from_bytes(self.cstr()?.to_bytes())))
+    }
+
+    fn cstrings(&mut self, n: u32) -> rustix::io::Result<Vec<CString>> {
+        (0..n).map(|_| Ok(self.cstr()?.to_owned())).collect()
+    }
+}
+
+/// Main loop of the helper, until the supervisor closes its socket
+fn serve(sock: OwnedFd, supervisor: Pid, sigset: &SigSet) -> ! {
+    // don't outlive a supervisor killed before it could stop us
+    if rustix::process::set_parent_process_death_signal(Some(Signal::KILL)).is_err()
+        || getppid() != Some(supervisor)
+    {
+        unsafe { libc::_exit(1) }
+    }
+    unsafe { libc::prctl(libc::PR_SET_NAME, c"svlopp-spawner".as_ptr()) };
+    let Ok(sock) = close_other_fds(sock) else {
+        unsafe { libc::_exit(1) }
+    };
+
+    let mut buf = vec![0u8; MAX_REQUEST_LEN];
+    let mut space = [MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(MAX_REQUEST_FDS))];
+    loop {
+        let mut control = RecvAncillaryBuffer::new(&mut space);
+        let msg = match retry_eintr(|| {
+            recvmsg(
+                &sock,
+                &mut [IoSliceMut::new(&mut buf)],
+                &mut control,
+                RecvFlags::CMSG_CLOEXEC,
+            )
+        }) {
+            Ok(msg) if msg.bytes > 0 => msg,
+            _ => unsafe { libc::_exit(0) },
+        };
+        let mut fds = Vec::new();
+        for message in control.drain() {
+            if let RecvAncillaryMessage::ScmRights(received) = message {
+                fds.extend(received);
+            }
+        }
+        let reply = if msg
+            .flags
+            .intersects(ReturnFlags::TRUNC | ReturnFlags::CTRUNC)
+        {
+            -Errno::MSGSIZE.raw_os_error()
+        } else {
+            match spawn(&buf[..msg.bytes], fds, sigset) {
+                Ok(raw) => raw,
+                Err(e) => -e.raw_os_error(),
+            }
+        };
+        if retry_eintr(|| send(&sock, &reply.to_ne_bytes(), SendFlags::NOSIGNAL)).is_err() {
+            unsafe { libc::_exit(1) }
+        }
+    }
+}
+
+/// Close every fd but the standard ones and `sock`, inherited from the
+/// supervisor, which is moved to fd 3
+fn close_other_fds(sock: OwnedFd) -> rustix::io::Result<OwnedFd> {
+    const SOCK_FD: RawFd = 3;
+    let sock = if sock.as_raw_fd() == SOCK_FD {
+        sock
+    } else {
+        cvt(unsafe { libc::dup3(sock.as_raw_fd(), SOCK_FD, libc::O_CLOEXEC) })?;
+        drop(sock);
+        // SAFETY: fd 3 was just set to a copy of the socket, which nothing
+        // else owns
+        unsafe { OwnedFd::from_raw_fd(SOCK_FD) }
+    };
+    if Platform::get().close_range {
+        cvt(unsafe { libc::syscall(libc::SYS_close_range, SOCK_FD + 1, u32::MAX, 0u32) })?;
+    } else {
+        let fds = std::fs::read_dir("/proc/self/fd")
+            .map_err(|_| Errno::NOENT)?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<RawFd>().ok())
+            .filter(|&fd| fd > SOCK_FD)
+            .collect::<Vec<_>>();
+        for fd in fds {
+            unsafe { libc::close(fd) };
+        }
+    }
+    Ok(sock)
+}
+
+/// Start the process described by `request`, returns its pid
+fn spawn(request: &[u8], fds: Vec<OwnedFd>, sigset: &SigSet) -> rustix::io::Result<i32> {
+    let mut d = Decoder { buf: request };
+    let flags = d.take(1)?[0];
+    let (uid, gid) = (d.u32()?, d.u32()?);
+    let (nfds, argc, envc) = (d.u32()? as usize, d.u32()?, d.u32()?);
+    let targets = (0..nfds)
+        .map(|_| Ok(d.u32()? as RawFd))
+        .collect::<rustix::io::Result<Vec<_>>>()?;
+    let working_directory = if flags & FLAG_WORKING_DIRECTORY != 0 {
+        Some(d.path()?)
+    } else {
+        None
+    };
+    let root_dir = if flags & FLAG_ROOT_DIR != 0 {
+        Some(d.path()?)
+    } else {
+        None
+    };
+    let mounts = if flags & FLAG_MOUNTS != 0 {
+        (0..d.u32()?)
+            .map(|_| {
+                let ro = d.take(1)?[0] != 0;
+                Ok(MountSpec {
+                    src: d.path()?,
+                    target: d.path()?,
+                    ro,
+                })
+            })
+            .collect::<rustix::io::Result<Vec<_>>>()?
+    } else {
+        Vec::new()
+    };
+    let argv = d.cstrings(argc)?;
+    let envp = d.cstrings(envc)?;
+    let expected = 2
+        + usize::from(flags & FLAG_STDOUT != 0)
+        + usize::from(flags & FLAG_STDERR != 0)
+        + usize::from(flags & FLAG_CGROUP != 0)
+        + nfds;
+    if argv.is_empty() || fds.len() != expected {
+        return Err(Errno::INVAL);
+    }
+
+    // received fds land on the lowest free numbers, move them out of the
+    // way of the ones they are handed to the service process as
+    let lowest = targets.iter().copied().max().unwrap_or(2) + 1;
+    let fds = fds
+        .into_iter()
+        .map(|fd| {
+            if fd.as_raw_fd() >= lowest {
+                Ok(fd)
+            } else {
+                fcntl_dupfd_cloexec(&fd, lowest)
+            }
+        })
+        .collect::<rustix::io::Result<Vec<_>>>()?;
+    let mut fds = fds.iter().map(|fd| fd.as_fd());
+    let devnull_fd = fds.next().ok_or(Errno::INVAL)?;
+    // inherited by the clone, until it execs
+    let exec_fd = fds.next().ok_or(Errno::INVAL)?;
+    let stdout_fd = if flags & FLAG_STDOUT != 0 {
+        fds.next()
+    } else {
+        None
+    };
+    let stderr_fd = if flags & FLAG_STDERR != 0 {
+        fds.next()
+    } else {
+        None
+    };
+    let cgroup_fd = if flags & FLAG_CGROUP != 0 {
+        fds.next()
+    } else {
+        None
+    };
+    let inherited_fds = fds.zip(targets).collect::<Vec<_>>();
+    let spec = ExecSpec {
+        argv: &argv,
+        envp: &envp,
+        user_group: (flags & FLAG_USER_GROUP != 0).then_some(UserGroup { uid, gid }),
+        user_namespace: None,
+        userns_fd: None,
+        working_directory,
+        root_dir,
+        mounts: &mounts,
+        exec_label: None,
+        private_keyring: flags & FLAG_PRIVATE_KEYRING != 0,
+        cgroup_fd,
+        devnull_fd,
+        stdout_fd,
+        stderr_fd,
+        inherited_fds: &inherited_fds,
+        exec_fd: Some(exec_fd),
+    };
+    let args = ExecArgs::new(&spec);
+    // `CLONE_PARENT` makes the process a child of the supervisor, which is
+    // signaled with `SIGCHLD` when it exits
+    let raw = cvt_r(|| unsafe {
+        libc::syscall(
+            libc::SYS_clone,
+            (libc::CLONE_PARENT | libc::SIGCHLD) as libc::c_ulong,
+            0usize,
+            0usize,
+            0usize,
+            0usize,
+        )
+    })?;
+    if raw == 0 {
+        child_exec(&spec, &args, sigset)
+    }
+    Ok(raw as i32)
+}
@@ -3,34 +3,66 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use std::{
-    io,
-    os::fd::AsFd,
+    cell::Cell,
+    collections::HashMap,
+    io::{self, IoSlice},
+    os::fd::{AsFd, AsRawFd, OwnedFd},
     path::{Path, PathBuf},
+    rc::Rc,
 };
 
-use rustix::fs::{Mode, OFlags, fsync, open, rename};
+use rustix::fs::{
+    AtFlags, CWD, Mode, OFlags, fsync, linkat, mkdirat, open, openat, rename, unlinkat,
+};
+use rustix::io::Errno;
 
-use crate::utils::write_all;
+use crate::utils::writev_all;
 
 /// Holds the paths used to maintain the status file.
 ///
 /// The status file is written atomically by first writing to a
 /// temporary file and then renaming it over the final path.
-/// Both paths are precomputed to avoid repeated allocations
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Both paths are precomputed to avoid repeated allocations, and the
+/// containing directory is kept open so that it can be synced after
+/// each rename
+#[derive(Debug)]
 pub(crate) struct StatusFilePath {
     /// The status file path
     path: PathBuf,
     /// The temporary file path
     tmp_path: PathBuf,
+    /// The directory containing both paths, shared by the files of a
+    /// `StatusDir`
+    dir: Rc<OwnedFd>,
+    /// Whether the temporary file can be created with `O_TMPFILE`. Cleared
+    /// on the first failure, e.g. when the filesystem doesn't support it
+    use_tmpfile: Cell<bool>,
 }
 
 impl StatusFilePath {
-    #[inline(always)]
-    pub(crate) fn new(path: PathBuf) -> Self {
-        Self {
+    pub(crate) fn new(path: PathBuf) -> io::Result<Self> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        Ok(Self {
             tmp_path: path.with_extension("tmp"),
+            dir: Rc::new(open_dir(dir)?),
             path,
+            use_tmpfile: Cell::new(true),
+        })
+    }
+
+    /// Paths for the file `name` in `dir`, using a hidden `.<name>.tmp`
+    /// temporary file so that names sharing a stem don't collide.
+    /// `dir_fd` must be an open handle to `dir`
+    #[inline(always)]
+    pub(crate) fn in_dir(dir: &Path, dir_fd: &Rc<OwnedFd>, name: &str) -> Self {
+        Self {
+            path: dir.join(name),
+            tmp_path: dir.join(format!(".{name}.tmp")),
+            dir: Rc::clone(dir_fd),
+            use_tmpfile: Cell::new(true),
         }
     }
 
@@ -45,14 +77,241 @@ impl StatusFilePath {
     }
 }
 
-pub(crate) fn write_status_file(path: &StatusFilePath, content: &str) -> io::Result<()> {
-    let fd = open(
-        path.tmp_path(),
-        OFlags::WRONLY | OFlags::CREATE | OFlags::TRUNC | OFlags::CLOEXEC,
+fn open_dir(path: &Path) -> io::Result<OwnedFd> {
+    Ok(open(
+        path,
+        OFlags::RDONLY | OFlags::DIRECTORY | OFlags::CLOEXEC,
+        Mode::empty(),
+    )?)
+}
+
+/// Create the temporary file as an anonymous `O_TMPFILE` and only link it
+/// at the temporary path once its content is complete and synced, so that
+/// a partially written temporary file is never visible.
+///
+/// Returns `Ok(false)` when `O_TMPFILE` is not usable here
+fn write_tmp_file_anonymous(path: &StatusFilePath, bufs: &mut [IoSlice<'_>]) -> io::Result<bool> {
+    let fd = match openat(
+        &*path.dir,
+        ".",
+        OFlags::WRONLY | OFlags::TMPFILE | OFlags::CLOEXEC,
         Mode::from_bits_truncate(0o644),
-    )?;
-    write_all(fd.as_fd(), content.as_bytes())?;
+    ) {
+        Ok(fd) => fd,
+        Err(Errno::OPNOTSUPP | Errno::ISDIR | Errno::INVAL) => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    writev_all(fd.as_fd(), bufs)?;
     fsync(&fd)?;
+    // a leftover of an interrupted write would make `linkat` fail
+    match unlinkat(CWD, path.tmp_path(), AtFlags::empty()) {
+        Ok(()) | Err(Errno::NOENT) => {}
+        Err(e) => return Err(e.into()),
+    }
+    // linking with `AT_EMPTY_PATH` requires `CAP_DAC_READ_SEARCH`, going
+    // through procfs doesn't
+    let proc_path = format!("/proc/self/fd/{}", fd.as_raw_fd());
+    match linkat(
+        CWD,
+        &proc_path,
+        CWD,
+        path.tmp_path(),
+        AtFlags::SYMLINK_FOLLOW,
+    ) {
+        Ok(()) => Ok(true),
+        Err(Errno::NOENT) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Atomically replace the status file with `content`.
+///
+/// The temporary file is synced and closed before being renamed, so that
+/// inotify watchers of the status file see exactly one `IN_MOVED_TO` per
+/// update and never a write or close on the final path. The directory is
+/// synced after the rename, for the update to survive a power loss
+pub(crate) fn write_status_file(path: &StatusFilePath, content: &[u8]) -> io::Result<()> {
+    write_status_file_vectored(path, &mut [IoSlice::new(content)])
+}
+
+/// Like `write_status_file`, with the concatenation of `bufs` as content
+pub(crate) fn write_status_file_vectored(
+    path: &StatusFilePath,
+    bufs: &mut [IoSlice<'_>],
+) -> io::Result<()> {
+    let linked = path.use_tmpfile.get() && write_tmp_file_anonymous(path, bufs)?;
+    if !linked {
+        path.use_tmpfile.set(false);
+        let fd = open(
+            path.tmp_path(),
+            OFlags::WRONLY | OFlags::CREATE | OFlags::TRUNC | OFlags::CLOEXEC,
+            Mode::from_bits_truncate(0o644),
+        )?;
+        writev_all(fd.as_fd(), bufs)?;
+        fsync(&fd)?;
+    }
     rename(path.tmp_path(), path.path())?;
+    fsync(&*path.dir)?;
     Ok(())
 }
+
+/// A file of the run directory whose content is built in a buffer on each
+/// flush, and only rewritten when it changed since the last write
+#[derive(Debug)]
+pub(crate) struct StatusFile {
+    path: StatusFilePath,
+    /// Another file the same content is written to, e.g. in the state
+    /// directory
+    copy: Option<StatusFilePath>,
+    /// Last written content
+    written: String,
+    buf: String,
+}
+
+impl StatusFile {
+    #[inline(always)]
+    pub(crate) fn new(path: StatusFilePath) -> Self {
+        Self {
+            path,
+            copy: None,
+            written: String::new(),
+            buf: String::new(),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn path(&self) -> &Path {
+        self.path.path()
+    }
+
+    /// The file at `path`, created empty
+    pub(crate) fn create(path: PathBuf) -> io::Result<Self> {
+        let file = Self::new(StatusFilePath::new(path)?);
+        write_status_file(&file.path, b"")?;
+        Ok(file)
+    }
+
+    /// Also write the content to `copy`
+    #[inline(always)]
+    pub(crate) fn with_copy(mut self, copy: StatusFilePath) -> Self {
+        self.copy = Some(copy);
+        self
+    }
+
+    /// Take `content` as already written, e.g. read back at startup
+    #[inline(always)]
+    pub(crate) fn set_written(&mut self, content: String) {
+        self.written = content;
+    }
+
+    /// The buffer the next content is built in, cleared
+    #[inline(always)]
+    pub(crate) fn buf(&mut self) -> &mut String {
+        self.buf.clear();
+        &mut self.buf
+    }
+
+    /// Write the content built in the buffer, to the copy too, if it
+    /// differs from the last written one. Returns whether it was written
+    pub(crate) fn flush(&mut self) -> io::Result<bool> {
+        if self.buf == self.written {
+            return Ok(false);
+        }
+        for path in std::iter::once(&self.path).chain(&self.copy) {
+            write_status_file(path, self.buf.as_bytes())?;
+        }
+        std::mem::swap(&mut self.buf, &mut self.written);
+        Ok(true)
+    }
+}
+
+/// Status file of a single service in a `StatusDir`.
+#[derive(Debug)]
+struct StatusDirEntry {
+    path: StatusFilePath,
+    /// Last written status line
+    line: String,
+    /// Value of `StatusDir::round` when the service was last seen
+    round: u64,
+}
+
+/// Per-service status files.
+///
+/// Each service gets its own file, `<dir>/<name>`, holding its status
+/// line. Files are written atomically like the status file, but only
+/// when the service status line changed since the last write, which
+/// reduces write amplification with many services.
+///
+/// Each flush is a *round*: every current service is passed to
+/// `StatusDir::update`, then `StatusDir::finish_round` removes the files
+/// of the services that were not seen, i.e. that have been removed
+#[derive(Debug)]
+pub(crate) struct StatusDir {
+    dir: PathBuf,
+    dir_fd: Rc<OwnedFd>,
+    entries: HashMap<String, StatusDirEntry>,
+    round: u64,
+}
+
+impl StatusDir {
+    /// Create the status directory at `dir`
+    pub(crate) fn create(dir: PathBuf) -> io::Result<Self> {
+        mkdirat(CWD, &dir, Mode::from_bits_truncate(0o755))?;
+        Ok(Self {
+            dir_fd: Rc::new(open_dir(&dir)?),
+            dir,
+            entries: HashMap::new(),
+            round: 0,
+        })
+    }
+
+    /// Write the status file of service `name`, `line` followed by a
+    /// newline, if `line` differs from the last written one. Returns
+    /// whether the file was written
+    pub(crate) fn update(&mut self, name: &str, line: &str) -> io::Result<bool> {
+        if !self.entries.contains_key(name) {
+            let entry = StatusDirEntry {
+                path: StatusFilePath::in_dir(&self.dir, &self.dir_fd, name),
+                line: String::new(),
+                round: 0,
+            };
+            self.entries.insert(name.to_owned(), entry);
+        }
+        let entry = self.entries.get_mut(name).expect("entry just inserted");
+        entry.round = self.round;
+        if entry.line == line {
+            return Ok(false);
+        }
+        entry.line.clear();
+        write_status_file_vectored(
+            &entry.path,
+            &mut [IoSlice::new(line.as_bytes()), IoSlice::new(b"\n")],
+        )?;
+        entry.line.push_str(line);
+        Ok(true)
+    }
+
+    /// Remove the status files of the services not updated in this round
+    /// and start a new one. Returns whether any file was removed
+    pub(crate) fn finish_round(&mut self) -> io::Result<bool> {
+        let mut result = Ok(false);
+        let round = self.round;
+        self.entries.retain(|_, entry| {
+            if entry.round == round {
+                return true;
+            }
+            match std::fs::remove_file(entry.path.path()) {
+                Ok(()) => {
+                    if result.is_ok() {
+                        result = Ok(true);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => result = Err(e),
+            }
+            false
+        });
+        self.round = self.round.wrapping_add(1);
+        result
+    }
+}
@@ -0,0 +1,176 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Blue/green swaps of a running service.
+//!
+//! The `swap` control operation replaces the running service `name` with a
+//! new instance of it, running its loaded config (e.g. changed by a reload
+//! with `reload_policy = "defer"`), with no moment without a ready
+//! instance: the service `name-new` is started next to it and, once ready
+//! (see `rollout::is_ready`), the running one is renamed `name-old` and
+//! stopped for removal while `name-new` is renamed `name`. The new instance
+//! skips the `ports` check, the service it replaces holding them, both
+//! binding them e.g. with `SO_REUSEPORT`. The swap is aborted, `name-new`
+//! being stopped and removed, when it fails to come up.
+
+use std::{io, path::Path, sync::Arc, time::Instant};
+
+use crate::control::ControlOp;
+use crate::logging::LogLevel;
+use crate::rollout::check_ready;
+use crate::service::{
+    Service, ServiceIdGen, ServicePendingAction, ServiceRegistry, ServiceState, SpawnContext,
+    apply_control_op, stop_service,
+};
+use crate::svlogg;
+
+/// Suffix of the name of the new instance, until the swap completes
+const NEW_SUFFIX: &str = "-new";
+/// Suffix of the name of the replaced instance, until it's removed
+const OLD_SUFFIX: &str = "-old";
+
+/// A swap in progress
+#[derive(Debug)]
+struct Swap {
+    old: u64,
+    new: u64,
+    /// When `new` was started
+    since: Instant,
+}
+
+/// The swaps in progress, at most one per service
+#[derive(Debug, Default)]
+pub(crate) struct Swaps {
+    swaps: Vec<Swap>,
+}
+
+impl Swaps {
+    #[inline(always)]
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Begin the swap of the running service `svc_id`, starting its new
+    /// instance
+    pub(crate) fn begin(
+        &mut self,
+        registry: &mut ServiceRegistry,
+        id_gen: &mut ServiceIdGen,
+        svc_id: u64,
+        ctx: &SpawnContext,
+        ps_dir: &Path,
+    ) -> io::Result<()> {
+        let svc = registry
+            .service(svc_id)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unknown service"))?;
+        if svc.replaces.is_some() || self.swaps.iter().any(|swap| swap.old == svc_id) {
+            return Err(io::Error::new(
+                io::ErrorKind::ResourceBusy,
+                format!("'{}' is being swapped", svc.name),
+            ));
+        }
+        if !matches!(svc.state, ServiceState::Running(_)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{}' isn't running", svc.name),
+            ));
+        }
+        let new_name = format!("{}{}", svc.name, NEW_SUFFIX);
+        let old_name = format!("{}{}", svc.name, OLD_SUFFIX);
+        if let Some(name) = [&new_name, &old_name]
+            .into_iter()
+            .find(|name| registry.get_by_name(name).is_some())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("service '{}' already exists", name),
+            ));
+        }
+        let new_id = id_gen
+            .nextval()
+            .ok_or_else(|| io::Error::other("service id overflow"))?;
+        let mut new = Service::new(new_id, new_name, svc.config.clone())?;
+        new.replaces = Some(svc_id);
+        new.transient = svc.transient;
+        svlogg!(LogLevel::Info, "swapping service '{}'", svc.name);
+        registry.insert_service(new);
+        if let Err(e) = apply_control_op(registry, new_id, ControlOp::Start, ctx, ps_dir) {
+            registry.remove_service(new_id);
+            return Err(e);
+        }
+        self.swaps.push(Swap {
+            old: svc_id,
+            new: new_id,
+            since: Instant::now(),
+        });
+        Ok(())
+    }
+
+    /// Move the swaps on, on each timerfd tick: complete the ones whose new
+    /// instance is ready, and abort the ones whose new instance failed
+    pub(crate) fn step(&mut self, registry: &mut ServiceRegistry) {
+        let now = Instant::now();
+        self.swaps.retain(|swap| {
+            let ready = match (registry.service(swap.old), registry.service(swap.new)) {
+                (Some(_), Some(new)) => check_ready(new, 0, swap.since, now)
+                    .map_err(|e| format!("'{}' {}", new.name, e)),
+                (None, Some(_)) => Err("the replaced service was removed".to_owned()),
+                (_, None) => Err("the new instance was removed".to_owned()),
+            };
+            match ready {
+                Ok(false) => true,
+                Ok(true) => {
+                    complete(registry, swap);
+                    false
+                }
+                Err(e) => {
+                    abort(registry, swap, &e);
+                    false
+                }
+            }
+        });
+    }
+}
+
+/// Rename the new instance after the service it replaces, which is renamed
+/// and stopped for removal
+fn complete(registry: &mut ServiceRegistry, swap: &Swap) {
+    let Some(old) = registry.service_mut(swap.old) else {
+        return;
+    };
+    let name = old.name.clone();
+    old.name = Arc::from(format!("{}{}", name, OLD_SUFFIX));
+    old.pending_action = ServicePendingAction::Remove;
+    if let Err(e) = stop_service(old) {
+        svlogg!(
+            LogLevel::Error,
+            "failed to stop service '{}': {}",
+            old.name,
+            e
+        );
+    }
+    if let Some(new) = registry.service_mut(swap.new) {
+        new.name = name;
+        new.replaces = None;
+        svlogg!(LogLevel::Info, "swapped service '{}'", new.name);
+    }
+}
+
+/// Stop and remove the new instance, the replaced service being left
+/// running
+fn abort(registry: &mut ServiceRegistry, swap: &Swap, reason: &str) {
+    svlogg!(LogLevel::Error, "swap aborted: {}", reason);
+    let Some(new) = registry.service_mut(swap.new) else {
+        return;
+    };
+    new.pending_action = ServicePendingAction::Remove;
+    if let Err(e) = stop_service(new) {
+        svlogg!(
+            LogLevel::Error,
+            "failed to stop service '{}': {}",
+            new.name,
+            e
+        );
+    }
+}
@@ -0,0 +1,348 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Timers, starting a service at the times of a calendar expression
+//! (`timer.on_calendar`, see `svlopp::calendar`).
+//!
+//! Timers are checked on each timerfd tick. An activation is missed when
+//! the service is still running from the previous one and `overlap` is
+//! `skip` (or `queue`, with one activation already queued), or when svlopp
+//! wasn't running at the time. Activations while svlopp was down are only
+//! known with `--state-dir`, where the next activation of each timer is
+//! persisted, and the latest of them is run at startup with `persistent`.
+//! When the system clock is stepped, the next activations are computed
+//! again from the new time.
+//!
+//! An activation is late when it's checked more than `LATE_AFTER_SECS`
+//! after it was due, e.g. after the host was suspended or the clock
+//! stepped forward. Only the latest of the activations elapsed at once
+//! can start the service, and a late one only does with `catch_up`.
+//!
+//! With `randomized_delay_ms`, each activation is delayed by the same
+//! amount for the whole boot, picked from the boot id and the service
+//! name, so that the timers of a fleet of hosts don't all start their
+//! service at once. `next` is the delayed activation.
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use svlopp::calendar::CalendarSpec;
+
+use crate::logging::LogLevel;
+use crate::restarts::create_state_dir;
+use crate::service::ServiceRegistry;
+use crate::status::{StatusFile, StatusFilePath};
+use crate::svlogg;
+
+/// Name of the file timers are persisted to, in the state directory
+const TIMERS_STORE_NAME: &str = "timers";
+
+/// Random id the kernel picks on each boot, seeding the delay of timers
+const BOOT_ID_PATH: &str = "/proc/sys/kernel/random/boot_id";
+
+/// Number of missed activations counted at once, bounding the search for
+/// the activations of a frequent timer after a long downtime
+const MAX_COUNTED_ACTIVATIONS: u64 = 100_000;
+
+/// Seconds after which an activation that is still to be checked is late,
+/// timers being checked on each timerfd tick
+pub(crate) const LATE_AFTER_SECS: i64 = 2;
+
+fn default_catch_up() -> bool {
+    true
+}
+
+/// What an activation does while the service still runs from the previous
+/// one, the `timer.overlap` field
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OverlapPolicy {
+    /// Miss the activation
+    #[default]
+    Skip,
+    /// Start the service again once it exits, activations being missed
+    /// while one is already queued
+    Queue,
+    /// Stop the running process, and start the service again
+    Kill,
+}
+
+/// What the time the host spends suspended counts as, the `timer.clock`
+/// field
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TimerClock {
+    /// Wall clock time, which keeps going while the host is suspended, as
+    /// `CLOCK_BOOTTIME` does: activations due meanwhile are late once it
+    /// resumes
+    #[default]
+    Realtime,
+    /// Stops while the host is suspended, as `CLOCK_MONOTONIC` does:
+    /// activations due meanwhile are dropped, neither run nor missed
+    Monotonic,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct TimerConfig {
+    /// Calendar expression of the activations
+    pub(crate) on_calendar: String,
+    #[serde(default)]
+    pub(crate) overlap: OverlapPolicy,
+    /// Whether a late activation starts the service, otherwise it's missed
+    #[serde(default = "default_catch_up")]
+    pub(crate) catch_up: bool,
+    #[serde(default)]
+    pub(crate) clock: TimerClock,
+    /// Upper bound of the delay of the activations
+    #[serde(default)]
+    pub(crate) randomized_delay_ms: u64,
+    /// Whether an activation missed while svlopp was down starts the
+    /// service once it's back, which needs `--state-dir`
+    #[serde(default)]
+    pub(crate) persistent: bool,
+}
+
+impl TimerConfig {
+    pub(crate) fn calendar(&self) -> io::Result<CalendarSpec> {
+        CalendarSpec::parse(&self.on_calendar).map_err(|e| {
+            io::Error::other(format!(
+                "invalid timer.on_calendar '{}': {}",
+                self.on_calendar, e
+            ))
+        })
+    }
+
+    pub(crate) fn validate(&self) -> io::Result<()> {
+        if (1..1000).contains(&self.randomized_delay_ms) {
+            return Err(io::Error::other(
+                "timer.randomized_delay_ms must be 0 or at least 1000",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Delay of the activations of the timer of service `name`, in whole
+    /// seconds as timers are checked on timerfd ticks: picked up to
+    /// `randomized_delay_ms` from the boot id and `name`, it's the same
+    /// for the whole boot, across supervisor restarts. Without a boot id,
+    /// activations aren't delayed
+    pub(crate) fn delay(&self, name: &str) -> i64 {
+        if self.randomized_delay_ms == 0 {
+            return 0;
+        }
+        static BOOT_ID: OnceLock<Option<String>> = OnceLock::new();
+        let boot_id = BOOT_ID.get_or_init(|| match std::fs::read_to_string(BOOT_ID_PATH) {
+            Ok(boot_id) => Some(boot_id.trim().to_owned()),
+            Err(e) => {
+                svlogg!(
+                    LogLevel::Error,
+                    "failed to read the boot id, timer activations won't be delayed: {}",
+                    e
+                );
+                None
+            }
+        });
+        let Some(boot_id) = boot_id else {
+            return 0;
+        };
+        let digest = Sha256::new()
+            .chain_update(boot_id)
+            .chain_update(name)
+            .finalize();
+        let mut seed = [0; 8];
+        seed.copy_from_slice(&digest[..8]);
+        let delay_ms = u64::from_be_bytes(seed) % (self.randomized_delay_ms + 1);
+        (delay_ms / 1000) as i64
+    }
+}
+
+/// Runtime state of the timer of a service. Times are wall clock seconds
+/// since the epoch
+#[derive(Debug)]
+pub(crate) struct Timer {
+    pub(crate) calendar: CalendarSpec,
+    /// Seconds activations are delayed by, see `TimerConfig::delay`
+    delay: i64,
+    /// `None` once the expression doesn't match anymore
+    pub(crate) next: Option<i64>,
+    /// Last activation that started the service
+    pub(crate) last: Option<i64>,
+    /// Activations that didn't start the service
+    pub(crate) missed: u64,
+    /// Whether an activation waits for the service to start, e.g. one that
+    /// found it running with `overlap = "queue"`, or one missed while
+    /// svlopp was down with `persistent`. Any start of the service runs it
+    pub(crate) queued: bool,
+}
+
+impl Timer {
+    pub(crate) fn new(calendar: CalendarSpec, delay: i64, now: i64) -> Self {
+        let mut timer = Self {
+            calendar,
+            delay,
+            next: None,
+            last: None,
+            missed: 0,
+            queued: false,
+        };
+        timer.next = timer.next_after(now);
+        timer
+    }
+
+    /// Swap in `calendar` and `delay`, e.g. changed by a reload, returning
+    /// the calendar it replaces. The counters are kept
+    pub(crate) fn swap_calendar(
+        &mut self,
+        calendar: CalendarSpec,
+        delay: i64,
+        now: i64,
+    ) -> CalendarSpec {
+        let calendar = std::mem::replace(&mut self.calendar, calendar);
+        self.delay = delay;
+        self.next = self.next_after(now);
+        calendar
+    }
+
+    /// First delayed activation after `time`
+    fn next_after(&self, time: i64) -> Option<i64> {
+        self.calendar
+            .next_after(time - self.delay)
+            .map(|next| next + self.delay)
+    }
+
+    /// Compute `next` again from `now` after a step of the system clock, so
+    /// that a clock set back doesn't delay it. An activation already due is
+    /// kept, for the next check to count it
+    pub(crate) fn reschedule(&mut self, now: i64) {
+        if self.next.is_none_or(|next| next > now) {
+            self.next = self.next_after(now);
+        }
+    }
+
+    /// Drop the activations due at `now`, which are neither run nor
+    /// missed, e.g. those of a monotonic timer while the host was suspended
+    pub(crate) fn skip(&mut self, now: i64) {
+        if self.next.is_some_and(|next| next <= now) {
+            self.next = self.next_after(now);
+        }
+    }
+
+    /// Number of activations elapsed at `now`, and when the latest of them
+    /// was due, moving `next` past it
+    pub(crate) fn elapse(&mut self, now: i64) -> (u64, Option<i64>) {
+        let mut count = 0;
+        let mut due = None;
+        while let Some(next) = self.next
+            && next <= now
+        {
+            count += 1;
+            due = Some(next);
+            self.next = match count < MAX_COUNTED_ACTIVATIONS {
+                true => self.next_after(next),
+                false => self.next_after(now),
+            };
+        }
+        (count, due)
+    }
+}
+
+/// A timer as persisted in the state directory
+#[derive(Debug)]
+pub(crate) struct TimerRecord {
+    pub(crate) next: Option<i64>,
+    pub(crate) last: Option<i64>,
+    pub(crate) missed: u64,
+}
+
+/// Publishes the timers of services in the timers file of the run
+/// directory, one per line: `<name> <next> <last> <missed>[ queued]`,
+/// times being seconds since the epoch or `-`. With `--state-dir` the
+/// same lines are persisted there, so that activations are still known
+/// after a supervisor restart
+#[derive(Debug)]
+pub(crate) struct TimersFile {
+    file: StatusFile,
+    /// Path of the timers persisted in the state directory
+    store: Option<PathBuf>,
+}
+
+impl TimersFile {
+    pub(crate) fn new(path: &Path, state_dir: Option<&Path>) -> io::Result<Self> {
+        let mut file = StatusFile::create(path.to_path_buf())?;
+        let store = match state_dir {
+            Some(dir) => {
+                create_state_dir(dir)?;
+                let store = dir.join(TIMERS_STORE_NAME);
+                file = file.with_copy(StatusFilePath::new(store.clone())?);
+                Some(store)
+            }
+            None => None,
+        };
+        Ok(Self { file, store })
+    }
+
+    /// Read the timers persisted by a previous supervisor, malformed lines
+    /// are skipped
+    pub(crate) fn load(&self) -> io::Result<HashMap<String, TimerRecord>> {
+        let Some(store) = &self.store else {
+            return Ok(HashMap::new());
+        };
+        let content = match std::fs::read_to_string(store) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e),
+        };
+        let time = |field: &str| match field {
+            "-" => Some(None),
+            t => t.parse().ok().map(Some),
+        };
+        let mut records = HashMap::new();
+        for line in content.lines() {
+            let mut fields = line.split_whitespace();
+            let (Some(name), Some(Some(next)), Some(Some(last)), Some(Ok(missed))) = (
+                fields.next(),
+                fields.next().map(time),
+                fields.next().map(time),
+                fields.next().map(str::parse),
+            ) else {
+                svlogg!(LogLevel::Warn, "skipping malformed timers line: {}", line);
+                continue;
+            };
+            records.insert(name.to_owned(), TimerRecord { next, last, missed });
+        }
+        Ok(records)
+    }
+
+    /// Rewrite the timers file if any timer changed
+    pub(crate) fn flush(&mut self, registry: &ServiceRegistry) {
+        let buf = self.file.buf();
+        for svc in registry.services() {
+            let Some(timer) = &svc.timer else {
+                continue;
+            };
+            let _ = write!(buf, "{}", svc.name);
+            for time in [timer.next, timer.last] {
+                let _ = match time {
+                    Some(t) => write!(buf, " {}", t),
+                    None => write!(buf, " -"),
+                };
+            }
+            let _ = write!(buf, " {}", timer.missed);
+            if timer.queued {
+                buf.push_str(" queued");
+            }
+            buf.push('\n');
+        }
+        if let Err(e) = self.file.flush() {
+            svlogg!(LogLevel::Error, "failed to write timers file: {}", e);
+        }
+    }
+}
@@ -2,13 +2,16 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::os::fd::{BorrowedFd, OwnedFd};
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
 
+use rustix::io::Errno;
 use rustix::time::{
-    Itimerspec, TimerfdClockId, TimerfdFlags, TimerfdTimerFlags, Timespec, timerfd_create,
-    timerfd_settime,
+    ClockId, Itimerspec, TimerfdClockId, TimerfdFlags, TimerfdTimerFlags, Timespec, clock_gettime,
+    timerfd_create, timerfd_settime,
 };
 
+use crate::utils::retry_eintr;
+
 pub(crate) fn create_timerfd_1s_periodic() -> rustix::io::Result<OwnedFd> {
     let fd = timerfd_create(
         TimerfdClockId::Monotonic,
@@ -30,9 +33,114 @@ pub(crate) fn create_timerfd_1s_periodic() -> rustix::io::Result<OwnedFd> {
 
 pub(crate) fn read_timerfd(fd: BorrowedFd<'_>) -> rustix::io::Result<u64> {
     let mut buf = [0u8; 8];
-    let n = rustix::io::read(fd, &mut buf)?;
+    let n = retry_eintr(|| rustix::io::read(fd, &mut buf))?;
     if n != 8 {
         return Err(rustix::io::Errno::IO);
     }
     Ok(u64::from_ne_bytes(buf))
 }
+
+/// Detects steps of the system clock (`CLOCK_REALTIME`), e.g. by NTP or
+/// `date -s`.
+///
+/// Uses a `CLOCK_REALTIME` timerfd armed far in the future with
+/// `TFD_TIMER_CANCEL_ON_SET`: the kernel cancels it, and makes it
+/// readable, whenever the clock is set discontinuously
+#[derive(Debug)]
+pub(crate) struct ClockStepMonitor {
+    fd: OwnedFd,
+    /// `CLOCK_REALTIME - CLOCK_MONOTONIC` as of the last check, in
+    /// milliseconds
+    offset_ms: i64,
+}
+
+impl ClockStepMonitor {
+    pub(crate) fn new() -> rustix::io::Result<Self> {
+        let fd = timerfd_create(
+            TimerfdClockId::Realtime,
+            TimerfdFlags::CLOEXEC | TimerfdFlags::NONBLOCK,
+        )?;
+        let new_value = Itimerspec {
+            it_interval: Timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: Timespec {
+                tv_sec: i64::MAX,
+                tv_nsec: 0,
+            },
+        };
+        timerfd_settime(
+            &fd,
+            TimerfdTimerFlags::ABSTIME | TimerfdTimerFlags::CANCEL_ON_SET,
+            &new_value,
+        )?;
+        Ok(Self {
+            fd,
+            offset_ms: realtime_offset_ms(),
+        })
+    }
+
+    /// Drain the timerfd, returning by how many milliseconds the clock
+    /// was stepped if it was
+    pub(crate) fn check(&mut self) -> rustix::io::Result<Option<i64>> {
+        match read_timerfd(self.fd.as_fd()) {
+            Err(Errno::CANCELED) => {}
+            Ok(_) | Err(Errno::AGAIN) => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let offset_ms = realtime_offset_ms();
+        let step_ms = offset_ms - self.offset_ms;
+        self.offset_ms = offset_ms;
+        Ok(Some(step_ms))
+    }
+}
+
+impl AsFd for ClockStepMonitor {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+/// Detects suspensions of the host, during which `CLOCK_BOOTTIME` keeps
+/// going while `CLOCK_MONOTONIC` stops. Checked on timerfd ticks, which
+/// are on the monotonic clock and so come right after a resume
+#[derive(Debug)]
+pub(crate) struct SuspendMonitor {
+    /// `CLOCK_BOOTTIME - CLOCK_MONOTONIC` as of the last check, in
+    /// milliseconds
+    offset_ms: i64,
+}
+
+impl SuspendMonitor {
+    pub(crate) fn new() -> Self {
+        Self {
+            offset_ms: boottime_offset_ms(),
+        }
+    }
+
+    /// How long the host was suspended since the last check, in
+    /// milliseconds, if it was
+    pub(crate) fn check(&mut self) -> Option<i64> {
+        let offset_ms = boottime_offset_ms();
+        let suspended_ms = offset_ms - self.offset_ms;
+        self.offset_ms = offset_ms;
+        // the two clocks aren't read at once, which is off by a bit
+        (suspended_ms >= MIN_SUSPEND_MS).then_some(suspended_ms)
+    }
+}
+
+/// Shortest suspension `SuspendMonitor` reports
+const MIN_SUSPEND_MS: i64 = 1000;
+
+fn ms(ts: Timespec) -> i64 {
+    ts.tv_sec * 1000 + ts.tv_nsec / 1_000_000
+}
+
+fn realtime_offset_ms() -> i64 {
+    ms(clock_gettime(ClockId::Realtime)) - ms(clock_gettime(ClockId::Monotonic))
+}
+
+fn boottime_offset_ms() -> i64 {
+    ms(clock_gettime(ClockId::Boottime)) - ms(clock_gettime(ClockId::Monotonic))
+}
@@ -0,0 +1,184 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! TLS of the remote HTTP API listener and of https webhooks, on top of
+//! rustls.
+//!
+//! rustls doesn't do any I/O itself: for the API, records are read from
+//! and written to the non-blocking client sockets here, as the main loop
+//! tells they're ready, and the plaintext is exchanged through the same
+//! buffers as unencrypted clients use. Webhooks are delivered by their
+//! worker thread over blocking streams.
+
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    os::fd::BorrowedFd,
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+use rustix::net::{SendFlags, send};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, pem::PemObject};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{
+    ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection, StreamOwned,
+};
+
+use crate::utils::retry_eintr;
+
+/// Adapter for rustls to read and write records on a socket
+struct FdIo<'a>(BorrowedFd<'a>);
+
+impl Read for FdIo<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(retry_eintr(|| rustix::io::read(self.0, &mut *buf))?)
+    }
+}
+
+impl Write for FdIo<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(retry_eintr(|| {
+            send(self.0, buf, SendFlags::DONTWAIT | SendFlags::NOSIGNAL)
+        })?)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn pem_error(path: &Path, e: rustls::pki_types::pem::Error) -> io::Error {
+    io::Error::other(format!("can't load '{}': {}", path.display(), e))
+}
+
+/// Build the server configuration from the PEM certificate chain `cert`
+/// and private key `key`. With `client_ca`, clients must present a
+/// certificate issued by one of its certificates
+pub(crate) fn server_config(
+    cert: &Path,
+    key: &Path,
+    client_ca: Option<&Path>,
+) -> io::Result<Arc<ServerConfig>> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| pem_error(cert, e))?;
+    let key_der = PrivateKeyDer::from_pem_file(key).map_err(|e| pem_error(key, e))?;
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?;
+    let builder = match client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for ca in CertificateDer::pem_file_iter(path).map_err(|e| pem_error(path, e))? {
+                roots
+                    .add(ca.map_err(|e| pem_error(path, e))?)
+                    .map_err(io::Error::other)?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(io::Error::other)?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder
+        .with_single_cert(chain, key_der)
+        .map_err(io::Error::other)?;
+    Ok(Arc::new(config))
+}
+
+/// Read the records available on `fd` and append the plaintext to `out`,
+/// or drop it with `discard`. Returns whether the client may still send
+/// data
+pub(crate) fn receive(
+    conn: &mut ServerConnection,
+    fd: BorrowedFd<'_>,
+    out: &mut Vec<u8>,
+    discard: bool,
+) -> io::Result<bool> {
+    let mut chunk = [0u8; 4096];
+    loop {
+        let eof = match conn.read_tls(&mut FdIo(fd)) {
+            Ok(n) => n == 0,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if let Err(e) = conn.process_new_packets() {
+            // let the client know why, if possible
+            let _ = conn.write_tls(&mut FdIo(fd));
+            return Err(io::Error::other(e));
+        }
+        loop {
+            match conn.reader().read(&mut chunk) {
+                Ok(0) => return Ok(false),
+                Ok(n) if !discard => out.extend_from_slice(&chunk[..n]),
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+                Err(e) => return Err(e),
+            }
+        }
+        if eof {
+            return Ok(false);
+        }
+    }
+}
+
+/// Encrypt as much of `plaintext` as rustls buffers and send the pending
+/// records on `fd`
+pub(crate) fn send_pending(
+    conn: &mut ServerConnection,
+    fd: BorrowedFd<'_>,
+    plaintext: &mut Vec<u8>,
+) -> io::Result<()> {
+    while !plaintext.is_empty() {
+        let n = conn.writer().write(plaintext)?;
+        if n == 0 {
+            break;
+        }
+        plaintext.drain(..n);
+    }
+    while conn.wants_write() {
+        match conn.write_tls(&mut FdIo(fd)) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Start a TLS session on `stream`, verifying that the server certificate
+/// is valid for `host` and issued by one of the Mozilla trusted roots
+pub(crate) fn client_stream(
+    host: &str,
+    stream: TcpStream,
+) -> io::Result<StreamOwned<ClientConnection, TcpStream>> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    let config = match CONFIG.get() {
+        Some(config) => Arc::clone(config),
+        None => {
+            let roots = RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            };
+            let config = ClientConfig::builder_with_provider(Arc::new(
+                rustls::crypto::ring::default_provider(),
+            ))
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+            Arc::clone(CONFIG.get_or_init(|| Arc::new(config)))
+        }
+    };
+    let name = ServerName::try_from(host.trim_start_matches('[').trim_end_matches(']'))
+        .map_err(io::Error::other)?
+        .to_owned();
+    let conn = ClientConnection::new(config, name).map_err(io::Error::other)?;
+    Ok(StreamOwned::new(conn, stream))
+}
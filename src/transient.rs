@@ -0,0 +1,66 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Transient services, defined and started at runtime with `svloppctl run`.
+//!
+//! `svloppctl` writes the definition to `transient.d/<request id>` in the
+//! run directory and sends the `run` control operation with the request
+//! id. The definition is NUL separated: the name of the service, the
+//! command and its arguments. The service is supervised like the
+//! configured ones, with the defaults of the service options, and removed
+//! from the registry once it stops. A reload leaves transient services
+//! alone.
+
+use std::{io, path::Path};
+
+use crate::control::ControlOp;
+use crate::logging::LogLevel;
+use crate::service::{
+    Service, ServiceConfig, ServiceIdGen, ServiceRegistry, SpawnContext, apply_control_op,
+};
+use crate::svlogg;
+
+/// Define and start the transient service of the request `request_id`,
+/// read from `dir`
+pub(crate) fn run_transient(
+    registry: &mut ServiceRegistry,
+    id_gen: &mut ServiceIdGen,
+    request_id: u64,
+    dir: &Path,
+    ctx: &SpawnContext,
+    ps_dir: &Path,
+) -> io::Result<()> {
+    let path = dir.join(request_id.to_string());
+    let request = std::fs::read(&path);
+    let _ = std::fs::remove_file(&path);
+    let request = String::from_utf8(request?)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid UTF-8"))?;
+    let mut fields = request.split('\0');
+    let (Some(name), Some(command)) = (fields.next(), fields.next()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing name or command",
+        ));
+    };
+    if registry.get_by_name(name).is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("service '{}' already exists", name),
+        ));
+    }
+    let svc_id = id_gen
+        .nextval()
+        .ok_or_else(|| io::Error::other("service id overflow"))?;
+    let mut config = ServiceConfig::new(command.to_owned());
+    config.args = fields.map(str::to_owned).collect();
+    let mut svc = Service::new(svc_id, name.to_owned(), config)?;
+    svc.transient = true;
+    svlogg!(LogLevel::Info, "adding transient service '{}'", svc.name);
+    registry.insert_service(svc);
+    if let Err(e) = apply_control_op(registry, svc_id, ControlOp::Start, ctx, ps_dir) {
+        registry.remove_service(svc_id);
+        return Err(e);
+    }
+    Ok(())
+}
@@ -0,0 +1,624 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Time zones of the system zoneinfo database.
+//!
+//! Zones are read from their TZif file (RFC 8536), in `$TZDIR` or
+//! `/usr/share/zoneinfo`. Times past the last transition of the file, all
+//! of them with the "slim" files of recent distributions, follow the
+//! POSIX TZ rule of its footer. The local zone is `$TZ` if set, else
+//! `/etc/localtime`, else UTC.
+
+use std::{fmt, path::PathBuf};
+
+/// Directory of the zoneinfo database, unless `$TZDIR` is set
+const ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+
+/// TZif file of the local zone
+const LOCALTIME_PATH: &str = "/etc/localtime";
+
+const SECS_PER_DAY: i64 = 86_400;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TzError {
+    /// No zone of that name in the zoneinfo database
+    Unknown(String),
+    /// The TZif file or the POSIX TZ rule of the zone is malformed
+    Invalid(String),
+}
+
+impl fmt::Display for TzError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown(name) => write!(f, "unknown time zone '{}'", name),
+            Self::Invalid(name) => write!(f, "invalid time zone data for '{}'", name),
+        }
+    }
+}
+
+impl std::error::Error for TzError {}
+
+/// Days since 1970-01-01 of a date of the proleptic Gregorian calendar
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// The date of a number of days since 1970-01-01, as year, month and day
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (
+        if month <= 2 {
+            yoe + era * 400 + 1
+        } else {
+            yoe + era * 400
+        },
+        month,
+        day,
+    )
+}
+
+/// Day of the week of a number of days since 1970-01-01, 0 being Monday
+pub fn weekday(days: i64) -> u32 {
+    // 1970-01-01 was a Thursday
+    (days + 3).rem_euclid(7) as u32
+}
+
+pub fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+pub fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// An offset from UTC and the abbreviation of the zone while it applies
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LocalType {
+    /// Seconds east of UTC
+    utoff: i32,
+    abbr: String,
+}
+
+/// When a POSIX TZ rule switches to or from daylight saving time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RuleDate {
+    /// `Jn`: day 1 to 365, February 29 never counted
+    Julian(u32),
+    /// `n`: day 0 to 365, February 29 counted in leap years
+    Zero(u32),
+    /// `Mm.w.d`: day `d` (0 being Sunday) of week `w` (5 being the last)
+    /// of month `m`
+    Month { month: u32, week: u32, day: u32 },
+}
+
+impl RuleDate {
+    /// Days since 1970-01-01 of the date in `year`
+    fn days(self, year: i64) -> i64 {
+        let jan1 = days_from_civil(year, 1, 1);
+        match self {
+            Self::Julian(n) => {
+                let after_feb = n > 59 && is_leap_year(year);
+                jan1 + n as i64 - 1 + i64::from(after_feb)
+            }
+            Self::Zero(n) => jan1 + n as i64,
+            Self::Month { month, week, day } => {
+                let first = days_from_civil(year, month, 1);
+                // `weekday` counts from Monday, the rule from Sunday
+                let first_weekday = (weekday(first) + 1) % 7;
+                let mut date =
+                    first + ((day + 7 - first_weekday) % 7) as i64 + 7 * (week as i64 - 1);
+                let last = first + days_in_month(year, month) as i64 - 1;
+                while date > last {
+                    date -= 7;
+                }
+                date
+            }
+        }
+    }
+}
+
+/// The daylight saving time part of a POSIX TZ rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DstRule {
+    dst: LocalType,
+    start: RuleDate,
+    /// Local standard time of the day of `start` the switch happens at
+    start_time: i32,
+    end: RuleDate,
+    /// Local daylight saving time of the day of `end` the switch happens
+    /// at
+    end_time: i32,
+}
+
+/// A POSIX TZ rule, e.g. `CET-1CEST,M3.5.0,M10.5.0/3`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PosixTz {
+    std: LocalType,
+    dst: Option<DstRule>,
+}
+
+impl PosixTz {
+    fn parse(s: &str) -> Option<Self> {
+        let mut p = RuleParser { s };
+        let std_abbr = p.abbr()?;
+        // POSIX offsets are west of UTC
+        let std_off = -p.time()?;
+        let std = LocalType {
+            utoff: std_off,
+            abbr: std_abbr,
+        };
+        if p.s.is_empty() {
+            return Some(Self { std, dst: None });
+        }
+        let dst_abbr = p.abbr()?;
+        let dst_off = match p.s.starts_with(',') {
+            true => std_off + 3600,
+            false => -p.time()?,
+        };
+        // rules of the US, the default of POSIX
+        let (start, start_time, end, end_time) = match p.s.strip_prefix(',') {
+            None => (
+                RuleDate::Month {
+                    month: 3,
+                    week: 2,
+                    day: 0,
+                },
+                7200,
+                RuleDate::Month {
+                    month: 11,
+                    week: 1,
+                    day: 0,
+                },
+                7200,
+            ),
+            Some(rest) => {
+                p.s = rest;
+                let start = p.date()?;
+                let start_time = p.switch_time()?;
+                p.s = p.s.strip_prefix(',')?;
+                let end = p.date()?;
+                let end_time = p.switch_time()?;
+                (start, start_time, end, end_time)
+            }
+        };
+        if !p.s.is_empty() {
+            return None;
+        }
+        Some(Self {
+            std,
+            dst: Some(DstRule {
+                dst: LocalType {
+                    utoff: dst_off,
+                    abbr: dst_abbr,
+                },
+                start,
+                start_time,
+                end,
+                end_time,
+            }),
+        })
+    }
+
+    fn local_type(&self, utc: i64) -> &LocalType {
+        let Some(rule) = &self.dst else {
+            return &self.std;
+        };
+        let (year, _, _) = civil_from_days((utc + self.std.utoff as i64).div_euclid(SECS_PER_DAY));
+        let start =
+            rule.start.days(year) * SECS_PER_DAY + rule.start_time as i64 - self.std.utoff as i64;
+        let end = rule.end.days(year) * SECS_PER_DAY + rule.end_time as i64 - rule.dst.utoff as i64;
+        let in_dst = match start <= end {
+            true => start <= utc && utc < end,
+            // southern hemisphere, daylight saving time across new year
+            false => !(end <= utc && utc < start),
+        };
+        if in_dst { &rule.dst } else { &self.std }
+    }
+}
+
+/// Reads a POSIX TZ rule
+struct RuleParser<'a> {
+    s: &'a str,
+}
+
+impl RuleParser<'_> {
+    fn abbr(&mut self) -> Option<String> {
+        let (abbr, rest) = match self.s.strip_prefix('<') {
+            Some(quoted) => {
+                let (abbr, rest) = quoted.split_once('>')?;
+                (abbr, rest)
+            }
+            None => {
+                let end = self
+                    .s
+                    .find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(self.s.len());
+                self.s.split_at(end)
+            }
+        };
+        if abbr.len() < 3 {
+            return None;
+        }
+        self.s = rest;
+        Some(abbr.to_owned())
+    }
+
+    fn number(&mut self) -> Option<i32> {
+        let end = self
+            .s
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.s.len());
+        let (digits, rest) = self.s.split_at(end);
+        self.s = rest;
+        digits.parse().ok()
+    }
+
+    /// `[+-]hh[:mm[:ss]]`, as seconds
+    fn time(&mut self) -> Option<i32> {
+        let sign = match self.s.as_bytes().first() {
+            Some(b'-') => -1,
+            Some(b'+') => 1,
+            _ => 0,
+        };
+        if sign != 0 {
+            self.s = &self.s[1..];
+        }
+        // RFC 8536 allows hours up to 167 in the rules of TZif footers
+        let mut secs = self.number().filter(|&h| h <= 167)? * 3600;
+        for unit in [60, 1] {
+            let Some(rest) = self.s.strip_prefix(':') else {
+                break;
+            };
+            self.s = rest;
+            secs += self.number().filter(|&n| n <= 59)? * unit;
+        }
+        Some(if sign < 0 { -secs } else { secs })
+    }
+
+    fn date(&mut self) -> Option<RuleDate> {
+        if let Some(rest) = self.s.strip_prefix('J') {
+            self.s = rest;
+            return Some(RuleDate::Julian(
+                self.number().filter(|n| (1..=365).contains(n))? as u32,
+            ));
+        }
+        let Some(rest) = self.s.strip_prefix('M') else {
+            return Some(RuleDate::Zero(
+                self.number().filter(|n| (0..=365).contains(n))? as u32,
+            ));
+        };
+        self.s = rest;
+        let month = self.number().filter(|n| (1..=12).contains(n))? as u32;
+        self.s = self.s.strip_prefix('.')?;
+        let week = self.number().filter(|n| (1..=5).contains(n))? as u32;
+        self.s = self.s.strip_prefix('.')?;
+        let day = self.number().filter(|n| (0..=6).contains(n))? as u32;
+        Some(RuleDate::Month { month, week, day })
+    }
+
+    /// The optional `/time` of a date, 02:00:00 by default
+    fn switch_time(&mut self) -> Option<i32> {
+        match self.s.strip_prefix('/') {
+            Some(rest) => {
+                self.s = rest;
+                self.time()
+            }
+            None => Some(7200),
+        }
+    }
+}
+
+/// Reads the fields of a TZif file
+struct TzifReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> TzifReader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.buf.len() < n {
+            return None;
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Some(head)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        Some(i64::from_be_bytes(self.take(8)?.try_into().ok()?))
+    }
+}
+
+/// The counts of the header of a TZif data block
+struct TzifCounts {
+    isutcnt: usize,
+    isstdcnt: usize,
+    leapcnt: usize,
+    timecnt: usize,
+    typecnt: usize,
+    charcnt: usize,
+}
+
+impl TzifCounts {
+    fn read(r: &mut TzifReader) -> Option<(u8, Self)> {
+        if r.take(4)? != b"TZif" {
+            return None;
+        }
+        let version = r.take(1)?[0];
+        r.take(15)?;
+        let mut count = || r.u32().map(|n| n as usize);
+        Some((
+            version,
+            Self {
+                isutcnt: count()?,
+                isstdcnt: count()?,
+                leapcnt: count()?,
+                timecnt: count()?,
+                typecnt: count()?,
+                charcnt: count()?,
+            },
+        ))
+    }
+
+    /// Length of the data block, with times of `time_len` bytes
+    fn block_len(&self, time_len: usize) -> usize {
+        self.timecnt * (time_len + 1)
+            + self.typecnt * 6
+            + self.charcnt
+            + self.leapcnt * (time_len + 4)
+            + self.isstdcnt
+            + self.isutcnt
+    }
+}
+
+/// A time zone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeZone {
+    name: String,
+    /// UTC times the offset changes at, ascending
+    transitions: Vec<i64>,
+    /// Index in `types` of the type from each transition on
+    transition_types: Vec<usize>,
+    /// At least one, the first applying before the first transition
+    types: Vec<LocalType>,
+    /// Applies after the last transition
+    rule: Option<PosixTz>,
+}
+
+impl TimeZone {
+    pub fn utc() -> Self {
+        Self {
+            name: "UTC".to_owned(),
+            transitions: Vec::new(),
+            transition_types: Vec::new(),
+            types: vec![LocalType {
+                utoff: 0,
+                abbr: "UTC".to_owned(),
+            }],
+            rule: None,
+        }
+    }
+
+    /// The zone `name` of the zoneinfo database, e.g. `Europe/Rome`
+    pub fn named(name: &str) -> Result<Self, TzError> {
+        if matches!(name, "UTC" | "Etc/UTC") {
+            return Ok(Self::utc());
+        }
+        let unknown = || TzError::Unknown(name.to_owned());
+        // never out of the database
+        if name.is_empty()
+            || name.starts_with('/')
+            || name.split('/').any(|part| part == ".." || part.is_empty())
+        {
+            return Err(unknown());
+        }
+        let dir =
+            std::env::var_os("TZDIR").map_or_else(|| PathBuf::from(ZONEINFO_DIR), PathBuf::from);
+        let data = std::fs::read(dir.join(name)).map_err(|_| unknown())?;
+        Self::from_tzif(name, &data)
+    }
+
+    /// The local zone: `$TZ`, a zone name or a POSIX TZ rule, if set,
+    /// else `/etc/localtime`, else UTC
+    pub fn local() -> Result<Self, TzError> {
+        if let Ok(tz) = std::env::var("TZ") {
+            let tz = tz.strip_prefix(':').unwrap_or(&tz);
+            if tz.is_empty() {
+                return Ok(Self::utc());
+            }
+            return Self::named(tz).or_else(|e| match PosixTz::parse(tz) {
+                Some(rule) => Ok(Self {
+                    name: tz.to_owned(),
+                    transitions: Vec::new(),
+                    transition_types: Vec::new(),
+                    types: vec![rule.std.clone()],
+                    rule: Some(rule),
+                }),
+                None => Err(e),
+            });
+        }
+        match std::fs::read(LOCALTIME_PATH) {
+            Ok(data) => {
+                // the zone name, when it's a link into the database
+                let name = std::fs::read_link(LOCALTIME_PATH)
+                    .ok()
+                    .and_then(|target| {
+                        let target = target.to_string_lossy().into_owned();
+                        let (_, name) = target.split_once("zoneinfo/")?;
+                        Some(name.to_owned())
+                    })
+                    .unwrap_or_else(|| "localtime".to_owned());
+                Self::from_tzif(&name, &data)
+            }
+            Err(_) => Ok(Self::utc()),
+        }
+    }
+
+    /// Parse the TZif file `data` of the zone `name`
+    pub fn from_tzif(name: &str, data: &[u8]) -> Result<Self, TzError> {
+        Self::parse_tzif(name, data).ok_or_else(|| TzError::Invalid(name.to_owned()))
+    }
+
+    fn parse_tzif(name: &str, data: &[u8]) -> Option<Self> {
+        let mut r = TzifReader { buf: data };
+        let (version, mut counts) = TzifCounts::read(&mut r)?;
+        let time_len = if version >= b'2' {
+            // the version 1 block only has 32-bit times
+            r.take(counts.block_len(4))?;
+            counts = TzifCounts::read(&mut r)?.1;
+            8
+        } else {
+            4
+        };
+        if counts.typecnt == 0 {
+            return None;
+        }
+        let mut transitions = Vec::with_capacity(counts.timecnt);
+        for _ in 0..counts.timecnt {
+            transitions.push(match time_len {
+                8 => r.i64()?,
+                _ => r.i32()? as i64,
+            });
+        }
+        let transition_types = r
+            .take(counts.timecnt)?
+            .iter()
+            .map(|&i| i as usize)
+            .collect::<Vec<_>>();
+        let mut raw_types = Vec::with_capacity(counts.typecnt);
+        for _ in 0..counts.typecnt {
+            let utoff = r.i32()?;
+            r.take(1)?;
+            let abbr_index = r.take(1)?[0] as usize;
+            raw_types.push((utoff, abbr_index));
+        }
+        let chars = r.take(counts.charcnt)?;
+        let types = raw_types
+            .into_iter()
+            .map(|(utoff, abbr_index)| {
+                let abbr = chars.get(abbr_index..)?;
+                let end = abbr.iter().position(|&b| b == 0)?;
+                Some(LocalType {
+                    utoff,
+                    abbr: String::from_utf8_lossy(&abbr[..end]).into_owned(),
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if transition_types.iter().any(|&i| i >= types.len()) {
+            return None;
+        }
+        r.take(counts.leapcnt * (time_len + 4) + counts.isstdcnt + counts.isutcnt)?;
+        let rule = match time_len {
+            8 => {
+                let footer = std::str::from_utf8(r.buf).ok()?;
+                let footer = footer.strip_prefix('\n')?;
+                let (rule, _) = footer.split_once('\n')?;
+                match rule.is_empty() {
+                    true => None,
+                    false => Some(PosixTz::parse(rule)?),
+                }
+            }
+            _ => None,
+        };
+        Some(Self {
+            name: name.to_owned(),
+            transitions,
+            transition_types,
+            types,
+            rule,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn local_type(&self, utc: i64) -> &LocalType {
+        let next = self.transitions.partition_point(|&t| t <= utc);
+        if next == 0 {
+            return match (&self.rule, self.transitions.is_empty()) {
+                (Some(rule), true) => rule.local_type(utc),
+                _ => &self.types[0],
+            };
+        }
+        if next == self.transitions.len()
+            && let Some(rule) = &self.rule
+        {
+            return rule.local_type(utc);
+        }
+        &self.types[self.transition_types[next - 1]]
+    }
+
+    /// Seconds east of UTC of the zone at the UTC time `utc`
+    pub fn offset(&self, utc: i64) -> i32 {
+        self.local_type(utc).utoff
+    }
+
+    /// Abbreviation of the zone at the UTC time `utc`, e.g. `CEST`
+    pub fn abbreviation(&self, utc: i64) -> &str {
+        &self.local_type(utc).abbr
+    }
+
+    /// The UTC time of the local time `local`, in seconds since the epoch
+    /// as if the zone was UTC. The earliest one if the local time happens
+    /// twice as the clock goes back, `None` if it's skipped as the clock
+    /// goes forward
+    pub fn to_utc(&self, local: i64) -> Option<i64> {
+        // offsets don't change more than once within a day
+        let mut candidates = [
+            self.offset(local - SECS_PER_DAY),
+            self.offset(local + SECS_PER_DAY),
+        ]
+        .map(|off| local - off as i64);
+        candidates.sort_unstable();
+        candidates
+            .into_iter()
+            .find(|&utc| utc + self.offset(utc) as i64 == local)
+    }
+
+    /// `utc` as a local date and time, e.g. `Mon 2026-10-19 02:30:00 CEST`
+    pub fn format(&self, utc: i64) -> String {
+        const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+        let local = utc + self.offset(utc) as i64;
+        let days = local.div_euclid(SECS_PER_DAY);
+        let secs = local.rem_euclid(SECS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        format!(
+            "{} {:04}-{:02}-{:02} {:02}:{:02}:{:02} {}",
+            WEEKDAYS[weekday(days) as usize],
+            year,
+            month,
+            day,
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60,
+            self.abbreviation(utc)
+        )
+    }
+}
@@ -0,0 +1,372 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Best effort conversion of systemd `.service` units to svlopp config.
+//!
+//! Only a common subset of directives is understood: `ExecStart=`,
+//! `Restart=`, `User=`, `Group=`, `Environment=`, `WorkingDirectory=`,
+//! `KillSignal=`, `TimeoutStopSec=` and `RuntimeMaxSec=`. `Description=`, `Documentation=`,
+//! `Type=simple|exec` and `WantedBy=` are accepted without effect, every
+//! other directive is reported as a warning rather than silently dropped.
+
+use std::{ffi::CString, fmt};
+
+use toml::{Table, Value};
+
+/// Stop signals accepted by svlopp
+const STOP_SIGNALS: [&str; 6] = [
+    "SIGTERM", "SIGINT", "SIGQUIT", "SIGHUP", "SIGUSR1", "SIGUSR2",
+];
+
+/// The result of converting a unit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConvertedUnit {
+    /// The service table, as a svlopp configuration file
+    pub config: String,
+    /// What could not be converted, or was converted approximately
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnitError {
+    /// A line that is neither a section, a directive nor a comment
+    InvalidLine(usize),
+    /// Unbalanced quotes in the value of a directive
+    UnbalancedQuotes(usize),
+    /// The unit has no `ExecStart=`
+    NoExecStart,
+}
+
+impl fmt::Display for UnitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLine(n) => write!(f, "line {}: invalid line", n),
+            Self::UnbalancedQuotes(n) => write!(f, "line {}: unbalanced quotes", n),
+            Self::NoExecStart => f.write_str("the unit has no ExecStart="),
+        }
+    }
+}
+
+impl std::error::Error for UnitError {}
+
+/// Split `value` into words the way systemd does for `ExecStart=` and
+/// `Environment=`: words are separated by whitespace, and can be quoted
+/// with single or double quotes. Returns `None` on unbalanced quotes
+fn split_words(value: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut chars = value.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Some(words);
+        }
+        let mut word = String::new();
+        let mut quote = None;
+        while let Some(c) = chars.next() {
+            match (c, quote) {
+                ('\\', _) => word.push(chars.next()?),
+                (c, Some(q)) if c == q => quote = None,
+                ('"' | '\'', None) => quote = Some(c),
+                (c, None) if c.is_whitespace() => break,
+                (c, _) => word.push(c),
+            }
+        }
+        if quote.is_some() {
+            return None;
+        }
+        words.push(word);
+    }
+}
+
+/// Parse a systemd time span (`90`, `1min 30s`, `500ms`) as milliseconds
+fn parse_timespan_ms(value: &str) -> Option<u64> {
+    let mut total = 0u64;
+    for part in value.split_whitespace() {
+        let split = part
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(part.len());
+        let (number, unit) = part.split_at(split);
+        let number: u64 = number.parse().ok()?;
+        let factor = match unit {
+            "ms" | "msec" => 1,
+            "" | "s" | "sec" | "second" | "seconds" => 1000,
+            "m" | "min" | "minute" | "minutes" => 60_000,
+            "h" | "hr" | "hour" | "hours" => 3_600_000,
+            _ => return None,
+        };
+        total = total.checked_add(number.checked_mul(factor)?)?;
+    }
+    Some(total)
+}
+
+/// Resolve the user `name`, numeric or not, to its uid and primary gid
+fn lookup_user(name: &str) -> Option<(u32, Option<u32>)> {
+    if let Ok(uid) = name.parse() {
+        return Some((uid, None));
+    }
+    let name = CString::new(name).ok()?;
+    // SAFETY: `name` is a valid C string, and the returned entry is read
+    // before any other call that may overwrite it
+    unsafe {
+        let pw = libc::getpwnam(name.as_ptr());
+        (!pw.is_null()).then(|| ((*pw).pw_uid, Some((*pw).pw_gid)))
+    }
+}
+
+/// Resolve the group `name`, numeric or not, to its gid
+fn lookup_group(name: &str) -> Option<u32> {
+    if let Ok(gid) = name.parse() {
+        return Some(gid);
+    }
+    let name = CString::new(name).ok()?;
+    // SAFETY: as in `lookup_user`
+    unsafe {
+        let gr = libc::getgrnam(name.as_ptr());
+        (!gr.is_null()).then(|| (*gr).gr_gid)
+    }
+}
+
+/// Convert the `.service` unit `content` to the config of a service named
+/// `name`. User and group names are resolved on the current system
+pub fn convert_unit(name: &str, content: &str) -> Result<ConvertedUnit, UnitError> {
+    let mut svc = Table::new();
+    let mut env = Table::new();
+    let mut exec_start = None;
+    let mut user = None;
+    let mut group = None;
+    let mut warnings = Vec::new();
+    let mut section = String::new();
+
+    let mut lines = content.lines().enumerate();
+    while let Some((index, raw)) = lines.next() {
+        let lineno = index + 1;
+        // a trailing backslash continues the line
+        let mut line = raw.trim().to_owned();
+        while line.ends_with('\\') {
+            line.pop();
+            line.push(' ');
+            match lines.next() {
+                Some((_, next)) => line.push_str(next.trim()),
+                None => break,
+            }
+        }
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            section = header
+                .strip_suffix(']')
+                .ok_or(UnitError::InvalidLine(lineno))?
+                .to_owned();
+            if !matches!(section.as_str(), "Unit" | "Service" | "Install") {
+                warnings.push(format!(
+                    "line {}: section [{}] is not supported",
+                    lineno, section
+                ));
+            }
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or(UnitError::InvalidLine(lineno))?;
+        let (key, value) = (key.trim(), value.trim());
+        match (section.as_str(), key) {
+            ("Unit", "Description" | "Documentation") | ("Install", "WantedBy") => {}
+            ("Service", "Type") => {
+                if !matches!(value, "simple" | "exec") {
+                    warnings.push(format!(
+                        "line {}: Type={} is not supported, svlopp expects the process to run \
+                         in the foreground",
+                        lineno, value
+                    ));
+                }
+            }
+            ("Service", "ExecStart") => {
+                if exec_start.is_some() {
+                    warnings.push(format!(
+                        "line {}: only the first ExecStart= is used",
+                        lineno
+                    ));
+                    continue;
+                }
+                let command = value.trim_start_matches(['@', '-', ':', '+', '!']);
+                if command.len() != value.len() {
+                    warnings.push(format!(
+                        "line {}: ExecStart= prefixes are not supported and were dropped",
+                        lineno
+                    ));
+                }
+                if command.contains(['$', '%']) {
+                    warnings.push(format!(
+                        "line {}: variables and specifiers in ExecStart= are not expanded",
+                        lineno
+                    ));
+                }
+                let words = split_words(command).ok_or(UnitError::UnbalancedQuotes(lineno))?;
+                if !words.is_empty() {
+                    exec_start = Some(words);
+                }
+            }
+            ("Service", "Restart") => match value {
+                "no" => {}
+                "always" => {
+                    svc.insert("on_exit".into(), "Restart".into());
+                }
+                "on-success" | "on-failure" | "on-abnormal" | "on-abort" | "on-watchdog" => {
+                    warnings.push(format!(
+                        "line {}: Restart={} converted to on_exit = \"Restart\", which restarts \
+                         on any exit",
+                        lineno, value
+                    ));
+                    svc.insert("on_exit".into(), "Restart".into());
+                }
+                _ => warnings.push(format!(
+                    "line {}: unknown Restart={} ignored",
+                    lineno, value
+                )),
+            },
+            ("Service", "User") => user = Some((lineno, value.to_owned())),
+            ("Service", "Group") => group = Some((lineno, value.to_owned())),
+            ("Service", "Environment") => {
+                for word in split_words(value).ok_or(UnitError::UnbalancedQuotes(lineno))? {
+                    match word.split_once('=') {
+                        Some((k, v)) => {
+                            env.insert(k.to_owned(), v.into());
+                        }
+                        None => warnings.push(format!(
+                            "line {}: invalid environment assignment '{}' ignored",
+                            lineno, word
+                        )),
+                    }
+                }
+            }
+            ("Service", "WorkingDirectory") => {
+                let dir = value.strip_prefix('-').unwrap_or(value);
+                if dir.starts_with('/') {
+                    svc.insert("working_directory".into(), dir.into());
+                } else {
+                    warnings.push(format!(
+                        "line {}: WorkingDirectory={} is not an absolute path, ignored",
+                        lineno, value
+                    ));
+                }
+            }
+            ("Service", "KillSignal") => {
+                let signal = if value.starts_with("SIG") {
+                    value.to_owned()
+                } else {
+                    format!("SIG{}", value)
+                };
+                if STOP_SIGNALS.contains(&signal.as_str()) {
+                    svc.insert("stop_signal".into(), signal.into());
+                } else {
+                    warnings.push(format!(
+                        "line {}: KillSignal={} is not a supported stop signal",
+                        lineno, value
+                    ));
+                }
+            }
+            ("Service", "TimeoutStopSec") => match parse_timespan_ms(value) {
+                Some(ms) => {
+                    svc.insert("stop_timeout_ms".into(), (ms as i64).into());
+                }
+                None => warnings.push(format!(
+                    "line {}: TimeoutStopSec={} is not supported",
+                    lineno, value
+                )),
+            },
+            ("Service", "RuntimeMaxSec") => match parse_timespan_ms(value) {
+                Some(ms) => {
+                    svc.insert("runtime_max_ms".into(), (ms as i64).into());
+                }
+                None => warnings.push(format!(
+                    "line {}: RuntimeMaxSec={} is not supported",
+                    lineno, value
+                )),
+            },
+            ("Service", "KeyringMode") => match value {
+                "private" => {}
+                "inherit" => {
+                    svc.insert("keyring".into(), "inherit".into());
+                }
+                "shared" => warnings.push(format!(
+                    "line {}: KeyringMode=shared converted to a private keyring, the user \
+                     keyring isn't linked to it",
+                    lineno
+                )),
+                _ => warnings.push(format!(
+                    "line {}: unknown KeyringMode={} ignored",
+                    lineno, value
+                )),
+            },
+            ("Service", "SELinuxContext" | "AppArmorProfile") => {
+                // the `-` prefix, ignoring failures to set it, is dropped
+                let label = value.strip_prefix('-').unwrap_or(value);
+                let field = match key {
+                    "SELinuxContext" => "selinux_context",
+                    _ => "apparmor_profile",
+                };
+                svc.insert(field.into(), label.into());
+            }
+            _ => warnings.push(format!("line {}: {}= is not supported", lineno, key)),
+        }
+    }
+
+    let mut argv = exec_start.ok_or(UnitError::NoExecStart)?.into_iter();
+    let command = argv.next().ok_or(UnitError::NoExecStart)?;
+    let mut config = Table::new();
+    config.insert("command".into(), command.into());
+    let args: Vec<Value> = argv.map(Value::from).collect();
+    if !args.is_empty() {
+        config.insert("args".into(), args.into());
+    }
+    config.extend(svc);
+
+    match (user, group) {
+        (Some((lineno, user)), group) => match lookup_user(&user) {
+            Some((uid, primary_gid)) => {
+                let gid = match group {
+                    Some((lineno, group)) => lookup_group(&group).or_else(|| {
+                        warnings.push(format!("line {}: unknown group '{}'", lineno, group));
+                        primary_gid
+                    }),
+                    None => primary_gid,
+                };
+                match gid {
+                    Some(gid) => {
+                        let mut user_group = Table::new();
+                        user_group.insert("uid".into(), i64::from(uid).into());
+                        user_group.insert("gid".into(), i64::from(gid).into());
+                        config.insert("user_group".into(), user_group.into());
+                    }
+                    None => warnings.push(format!(
+                        "line {}: numeric User= needs a Group=, ignored",
+                        lineno
+                    )),
+                }
+            }
+            None => warnings.push(format!("line {}: unknown user '{}'", lineno, user)),
+        },
+        (None, Some((lineno, _))) => warnings.push(format!(
+            "line {}: Group= without User= is not supported",
+            lineno
+        )),
+        (None, None) => {}
+    }
+
+    if !env.is_empty() {
+        warnings.push(
+            "env replaces the environment inherited from svlopp, while Environment= adds to it"
+                .to_owned(),
+        );
+        config.insert("env".into(), env.into());
+    }
+
+    let mut services = Table::new();
+    services.insert(name.to_owned(), config.into());
+    let mut root = Table::new();
+    root.insert("services".into(), services.into());
+    Ok(ConvertedUnit {
+        config: root.to_string(),
+        warnings,
+    })
+}
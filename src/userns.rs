@@ -0,0 +1,100 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! User namespaces of service processes.
+//!
+//! A service with a `user_namespace` is cloned with `CLONE_NEWUSER`, then
+//! the supervisor writes its uid and gid maps from the parent namespace
+//! while the child waits for them, before switching to `user_group`.
+
+use std::{fmt::Write, io};
+
+use rustix::process::geteuid;
+use serde::Deserialize;
+
+use crate::service::UserGroup;
+
+/// Most lines a map can have since Linux 4.15
+const MAX_MAP_LINES: usize = 340;
+
+/// A range of ids of the namespace, mapped to ids of the parent one
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct IdMap {
+    /// First id of the range in the namespace
+    pub(crate) inside: u32,
+    /// First id of the range in the parent namespace
+    pub(crate) outside: u32,
+    pub(crate) count: u32,
+}
+
+/// The `user_namespace` of a service, the ids its process runs with are
+/// those of the maps. Without privileges, the supervisor may only map its
+/// own uid and gid, once each
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct UserNamespace {
+    pub(crate) uid_map: Vec<IdMap>,
+    pub(crate) gid_map: Vec<IdMap>,
+}
+
+impl UserNamespace {
+    pub(crate) fn validate(&self) -> io::Result<()> {
+        for (name, map) in [("uid_map", &self.uid_map), ("gid_map", &self.gid_map)] {
+            if map.is_empty() || map.len() > MAX_MAP_LINES {
+                return Err(io::Error::other(format!(
+                    "user_namespace {} needs between 1 and {} ranges",
+                    name, MAX_MAP_LINES
+                )));
+            }
+            if map.iter().any(|range| range.count == 0) {
+                return Err(io::Error::other(format!(
+                    "user_namespace {} has an empty range",
+                    name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the maps of the namespace process `pid` was cloned in. An
+    /// unprivileged supervisor denies `setgroups` first, as the kernel
+    /// requires before it writes the gid map
+    pub(crate) fn write_maps(&self, pid: i32) -> io::Result<()> {
+        if !geteuid().is_root() {
+            std::fs::write(format!("/proc/{}/setgroups", pid), "deny")?;
+        }
+        std::fs::write(format!("/proc/{}/uid_map", pid), lines(&self.uid_map))?;
+        std::fs::write(format!("/proc/{}/gid_map", pid), lines(&self.gid_map))?;
+        Ok(())
+    }
+
+    /// The ids of the parent namespace `ug` maps to, `None` if either
+    /// isn't mapped
+    pub(crate) fn outside(&self, ug: UserGroup) -> Option<UserGroup> {
+        Some(UserGroup {
+            uid: outside_id(&self.uid_map, ug.uid)?,
+            gid: outside_id(&self.gid_map, ug.gid)?,
+        })
+    }
+}
+
+fn outside_id(map: &[IdMap], id: u32) -> Option<u32> {
+    map.iter().find_map(|range| {
+        id.checked_sub(range.inside)
+            .filter(|offset| *offset < range.count)
+            .map(|offset| range.outside + offset)
+    })
+}
+
+/// The content of a map file, written at once as the kernel requires
+fn lines(map: &[IdMap]) -> String {
+    let mut content = String::new();
+    for range in map {
+        let _ = writeln!(
+            content,
+            "{} {} {}",
+            range.inside, range.outside, range.count
+        );
+    }
+    content
+}
@@ -2,42 +2,161 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::{io, os::fd::BorrowedFd};
+use std::{
+    cell::Cell,
+    io::{self, IoSlice, Read, Seek, SeekFrom},
+    os::fd::{AsFd, BorrowedFd, FromRawFd, OwnedFd},
+    path::Path,
+    time::{Duration, Instant},
+};
 
+use rustix::event::{PollFd, PollFlags, Timespec, poll};
 use rustix::time::{ClockId, clock_gettime};
 
+/// Only the end of a log file is read to find its last lines
+const MAX_LOG_TAIL_LEN: u64 = 1024 * 1024;
+
+/// Number of buffers passed to a single `writev`, well below `IOV_MAX`
+const WRITEV_BATCH: usize = 64;
+
 pub(crate) fn timestamp() -> (i64, i64) {
     let now = clock_gettime(ClockId::Realtime);
     (now.tv_sec, now.tv_nsec)
 }
 
+/// Write `secs` and `nsecs` since the epoch as an RFC 3339 UTC timestamp
+/// with nanosecond precision (e.g. `2023-11-14T22:13:20.000000000Z`).
+///
+/// Days are converted to a civil date with Howard Hinnant's
+/// `civil_from_days` algorithm, to avoid pulling a date crate in
+pub(crate) fn format_rfc3339(w: &mut impl io::Write, secs: i64, nsecs: i64) -> io::Result<()> {
+    let days = secs.div_euclid(86400);
+    let secs_of_day = secs.rem_euclid(86400);
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    write!(
+        w,
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        nsecs
+    )
+}
+
+/// Return value of a libc call, `-1` meaning that it failed and set
+/// `errno`
 pub(crate) trait RetCode: Copy {
     fn is_error(self) -> bool;
 }
 
-impl RetCode for i32 {
-    #[inline(always)]
-    fn is_error(self) -> bool {
-        self == -1
-    }
+macro_rules! impl_ret_code {
+    ($($t:ty),*) => {
+        $(
+            impl RetCode for $t {
+                #[inline(always)]
+                fn is_error(self) -> bool {
+                    self == -1
+                }
+            }
+        )*
+    };
 }
 
-impl RetCode for isize {
-    #[inline(always)]
-    fn is_error(self) -> bool {
-        self == -1
-    }
+// `c_int`, `c_long` and `ssize_t` on both 32 and 64-bit targets
+impl_ret_code!(i32, i64, isize);
+
+/// The `errno` of the calling thread.
+///
+/// The libc symbol it lives behind differs across libcs and targets
+/// (`__errno_location` on glibc and musl, `__errno` on bionic), `std`
+/// already knows which one to use. Reading it doesn't allocate, so it's
+/// fine between `fork` and `exec`
+#[inline(always)]
+pub(crate) fn errno() -> i32 {
+    io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+/// Symbolic name of `errno`, e.g. `EPERM`, for the ones that setting a
+/// process up or exec can fail with
+pub(crate) fn errno_name(errno: i32) -> Option<&'static str> {
+    Some(match errno {
+        libc::EPERM => "EPERM",
+        libc::ENOENT => "ENOENT",
+        libc::EINTR => "EINTR",
+        libc::EIO => "EIO",
+        libc::E2BIG => "E2BIG",
+        libc::ENOEXEC => "ENOEXEC",
+        libc::EBADF => "EBADF",
+        libc::EAGAIN => "EAGAIN",
+        libc::ENOMEM => "ENOMEM",
+        libc::EACCES => "EACCES",
+        libc::EFAULT => "EFAULT",
+        libc::ENOTDIR => "ENOTDIR",
+        libc::EISDIR => "EISDIR",
+        libc::EINVAL => "EINVAL",
+        libc::ENFILE => "ENFILE",
+        libc::EMFILE => "EMFILE",
+        libc::ETXTBSY => "ETXTBSY",
+        libc::ELOOP => "ELOOP",
+        libc::ENAMETOOLONG => "ENAMETOOLONG",
+        libc::ENOSYS => "ENOSYS",
+        libc::ELIBBAD => "ELIBBAD",
+        _ => return None,
+    })
 }
 
 pub(crate) fn cvt<T: RetCode>(ret: T) -> rustix::io::Result<T> {
     if ret.is_error() {
-        let errno = unsafe { *libc::__errno_location() };
-        Err(rustix::io::Errno::from_raw_os_error(errno))
+        Err(rustix::io::Errno::from_raw_os_error(errno()))
     } else {
         Ok(ret)
     }
 }
 
+/// Call `f` again as long as it fails with `EINTR`.
+///
+/// Signals are blocked and read from a signalfd, but a blocking call can
+/// still be interrupted, e.g. when a tracer stops and continues svlopp
+#[inline(always)]
+pub(crate) fn retry_eintr<T>(
+    mut f: impl FnMut() -> rustix::io::Result<T>,
+) -> rustix::io::Result<T> {
+    loop {
+        match f() {
+            Err(rustix::io::Errno::INTR) => {}
+            ret => return ret,
+        }
+    }
+}
+
+/// Like `cvt`, calling `f` again as long as it fails with `EINTR`
+pub(crate) fn cvt_r<T: RetCode>(mut f: impl FnMut() -> T) -> rustix::io::Result<T> {
+    retry_eintr(|| cvt(f()))
+}
+
+/// Like `cvt`, for calls returning a new file descriptor
+///
+/// # Safety
+///
+/// `ret` must be `-1` or a file descriptor nothing else owns, as the
+/// returned `OwnedFd` closes it
+pub(crate) unsafe fn cvt_fd(ret: libc::c_int) -> rustix::io::Result<OwnedFd> {
+    let fd = cvt(ret)?;
+    // SAFETY: `fd` is owned by nothing else, as guaranteed by the caller
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
 #[inline(always)]
 pub(crate) fn is_crash_signal(sig: i32) -> bool {
     matches!(
@@ -46,11 +165,207 @@ pub(crate) fn is_crash_signal(sig: i32) -> bool {
     )
 }
 
-#[inline(always)]
-pub(crate) fn write_all(fd: BorrowedFd<'_>, mut buf: &[u8]) -> io::Result<()> {
-    while !buf.is_empty() {
-        let n = rustix::io::write(fd, buf)?;
-        buf = &buf[n..];
+/// Wait until `fd` is writable, or until `deadline` if any. Returns
+/// whether it became writable
+fn poll_writable(fd: BorrowedFd<'_>, deadline: Option<Instant>) -> io::Result<bool> {
+    let mut fds = [PollFd::new(&fd, PollFlags::OUT)];
+    loop {
+        let timeout = match deadline {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return Ok(false);
+                }
+                Some(Timespec {
+                    tv_sec: left.as_secs() as _,
+                    tv_nsec: left.subsec_nanos() as _,
+                })
+            }
+            None => None,
+        };
+        match poll(&mut fds, timeout.as_ref()) {
+            // `POLLERR` and `POLLHUP` are left to the next write to report
+            Ok(0) | Err(rustix::io::Errno::INTR) => {}
+            Ok(_) => return Ok(true),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+fn writev_all_until(
+    fd: BorrowedFd<'_>,
+    mut bufs: &mut [IoSlice<'_>],
+    deadline: Option<Instant>,
+) -> io::Result<()> {
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        match retry_eintr(|| rustix::io::writev(fd, bufs)) {
+            Ok(n) => IoSlice::advance_slices(&mut bufs, n),
+            Err(rustix::io::Errno::AGAIN) => {
+                if !poll_writable(fd, deadline)? {
+                    let left: usize = bufs.iter().map(|b| b.len()).sum();
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("write timed out with {} bytes left", left),
+                    ));
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
     }
     Ok(())
 }
+
+#[inline(always)]
+fn write_all_until(fd: BorrowedFd<'_>, buf: &[u8], deadline: Option<Instant>) -> io::Result<()> {
+    writev_all_until(fd, &mut [IoSlice::new(buf)], deadline)
+}
+
+/// Write the whole of `buf` to `fd`. If `fd` is non-blocking, wait for it
+/// to become writable whenever it's full
+#[inline(always)]
+pub(crate) fn write_all(fd: BorrowedFd<'_>, buf: &[u8]) -> io::Result<()> {
+    write_all_until(fd, buf, None)
+}
+
+/// Like `write_all`, writing the concatenation of `bufs` with as few
+/// `writev` calls as possible, so that e.g. a header and a payload don't
+/// need to be copied next to each other first
+#[inline(always)]
+pub(crate) fn writev_all(fd: BorrowedFd<'_>, bufs: &mut [IoSlice<'_>]) -> io::Result<()> {
+    writev_all_until(fd, bufs, None)
+}
+
+/// Like `write_all`, failing with `TimedOut` if `fd` is non-blocking and
+/// `buf` could not be written in full within `timeout`, e.g. because the
+/// reader of a pipe stopped reading. What was written is not undone
+#[inline(always)]
+pub(crate) fn write_all_timeout(
+    fd: BorrowedFd<'_>,
+    buf: &[u8],
+    timeout: Duration,
+) -> io::Result<()> {
+    write_all_until(fd, buf, Some(Instant::now() + timeout))
+}
+
+/// Buffers writes to a file descriptor until `flush`, which writes them
+/// out with `write_all`, or with `write_all_timeout` if a timeout is set.
+///
+/// The buffer is emptied by `flush` even when it fails, so that a reader
+/// that stopped reading can't make it grow without bound
+#[derive(Debug)]
+pub(crate) struct FdWriter<F> {
+    fd: F,
+    buf: Vec<u8>,
+    timeout: Option<Duration>,
+}
+
+impl<F: AsFd> FdWriter<F> {
+    pub(crate) fn new(fd: F, timeout: Option<Duration>) -> Self {
+        Self {
+            fd,
+            buf: Vec::new(),
+            timeout,
+        }
+    }
+
+    /// Replace the file descriptor, returning the previous one. Buffered
+    /// data is not flushed, and is written to `fd` by the next `flush`
+    pub(crate) fn replace_fd(&mut self, fd: F) -> F {
+        std::mem::replace(&mut self.fd, fd)
+    }
+
+    /// The unflushed data, which can be appended to directly
+    #[inline(always)]
+    pub(crate) fn buffer_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+
+    #[inline(always)]
+    pub(crate) fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Like `flush`, writing each `(offset, payload)` of `payloads` as if
+    /// it had been inserted in the buffer at `offset`, without copying it.
+    /// Offsets must be ascending and at most `buffered()`
+    pub(crate) fn flush_spliced<'a>(
+        &mut self,
+        payloads: impl IntoIterator<Item = (usize, &'a [u8])>,
+    ) -> io::Result<()> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let fd = self.fd.as_fd();
+        let buf = &self.buf;
+        let start = Cell::new(0);
+        let parts = payloads
+            .into_iter()
+            .flat_map(|(offset, payload)| [&buf[start.replace(offset)..offset], payload])
+            .chain(std::iter::once_with(|| &buf[start.get()..]));
+        let mut iovs = [IoSlice::new(&[]); WRITEV_BATCH];
+        let mut n = 0;
+        let mut result = Ok(());
+        for part in parts.filter(|part| !part.is_empty()) {
+            if n == WRITEV_BATCH {
+                result = writev_all_until(fd, &mut iovs, deadline);
+                if result.is_err() {
+                    break;
+                }
+                n = 0;
+            }
+            iovs[n] = IoSlice::new(part);
+            n += 1;
+        }
+        if result.is_ok() {
+            result = writev_all_until(fd, &mut iovs[..n], deadline);
+        }
+        self.buf.clear();
+        result
+    }
+}
+
+impl<F: AsFd> io::Write for FdWriter<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = match self.timeout {
+            Some(timeout) => write_all_timeout(self.fd.as_fd(), &self.buf, timeout),
+            None => write_all(self.fd.as_fd(), &self.buf),
+        };
+        self.buf.clear();
+        result
+    }
+}
+
+/// The last `lines` lines of the log file at `path`
+pub(crate) fn read_log_tail(path: &Path, lines: usize) -> io::Result<Vec<u8>> {
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(MAX_LOG_TAIL_LEN);
+    file.seek(SeekFrom::Start(start))?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    // drop the line cut by the start of the read
+    let begin = match start {
+        0 => 0,
+        _ => buf
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(buf.len(), |i| i + 1),
+    };
+    let content = &buf[begin..];
+    if lines == 0 {
+        return Ok(Vec::new());
+    }
+    let complete = content.strip_suffix(b"\n").unwrap_or(content);
+    let skip = complete
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|&(_, &b)| b == b'\n')
+        .nth(lines - 1)
+        .map_or(0, |(i, _)| i + 1);
+    Ok(content[skip..].to_vec())
+}
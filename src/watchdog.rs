@@ -0,0 +1,180 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    io,
+    os::{linux::net::SocketAddrExt, unix::net::SocketAddr, unix::net::UnixDatagram},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::logging::LogLevel;
+use crate::svlogg;
+
+/// Detects a stuck main loop.
+///
+/// The main loop wakes up at least once per second (timerfd tick) and
+/// kicks the watchdog on each iteration. A helper thread checks that the
+/// last kick is not older than the configured timeout, and when it is
+/// dumps where the main thread is blocked and optionally aborts, so that
+/// an external supervisor can restart svlopp. Both the kicks and the
+/// helper thread sleeps use `CLOCK_MONOTONIC`, so the time the system
+/// spends suspended doesn't count as a stall.
+///
+/// When running under systemd with `WatchdogSec=` set, kicks are also
+/// forwarded to systemd as `WATCHDOG=1` notifications
+#[derive(Debug)]
+pub(crate) struct Watchdog {
+    base: Instant,
+    /// Milliseconds since `base` at the last kick, shared with the helper
+    /// thread
+    last_kick: Arc<AtomicU64>,
+    systemd: Option<SystemdWatchdog>,
+}
+
+impl Watchdog {
+    /// Start the watchdog. `timeout` arms the helper thread, `abort` makes
+    /// it abort the process when the timeout expires
+    pub(crate) fn start(timeout: Option<Duration>, abort: bool) -> io::Result<Self> {
+        let base = Instant::now();
+        let last_kick = Arc::new(AtomicU64::new(0));
+        if let Some(timeout) = timeout {
+            let last_kick = Arc::clone(&last_kick);
+            // SAFETY: `gettid` has no preconditions
+            let main_tid = unsafe { libc::gettid() };
+            std::thread::Builder::new()
+                .name("svlopp-watchdog".into())
+                .spawn(move || watch(base, &last_kick, timeout, abort, main_tid))?;
+        }
+        Ok(Self {
+            base,
+            last_kick,
+            systemd: SystemdWatchdog::from_env(),
+        })
+    }
+
+    /// Record main loop progress
+    pub(crate) fn kick(&mut self) {
+        let now = Instant::now();
+        self.last_kick.store(
+            now.duration_since(self.base).as_millis() as u64,
+            Ordering::Relaxed,
+        );
+        if let Some(systemd) = self.systemd.as_mut() {
+            systemd.kick(now);
+        }
+    }
+}
+
+fn watch(base: Instant, last_kick: &AtomicU64, timeout: Duration, abort: bool, main_tid: i32) {
+    let timeout_ms = timeout.as_millis() as u64;
+    let mut reported = false;
+    loop {
+        std::thread::sleep(timeout / 2);
+        let now_ms = base.elapsed().as_millis() as u64;
+        let stalled_ms = now_ms.saturating_sub(last_kick.load(Ordering::Relaxed));
+        if stalled_ms <= timeout_ms {
+            if reported {
+                svlogg!(LogLevel::Warn, "watchdog: main loop made progress again");
+                reported = false;
+            }
+            continue;
+        }
+        if reported {
+            continue;
+        }
+        reported = true;
+        svlogg!(
+            LogLevel::Error,
+            "watchdog: main loop made no progress for {}ms",
+            stalled_ms
+        );
+        dump_thread_state(main_tid);
+        if abort {
+            svlogg!(LogLevel::Error, "watchdog: aborting");
+            std::process::abort();
+        }
+    }
+}
+
+/// Log where the thread `tid` is blocked, as far as procfs tells
+fn dump_thread_state(tid: i32) {
+    for file in ["wchan", "syscall", "stack"] {
+        let path = format!("/proc/self/task/{}/{}", tid, file);
+        match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                for line in content.lines() {
+                    svlogg!(LogLevel::Error, "watchdog: {}: {}", file, line);
+                }
+            }
+            // `stack` is only readable with `CAP_SYS_ADMIN`
+            Err(e) => svlogg!(LogLevel::Debug, "watchdog: can't read '{}': {}", path, e),
+        }
+    }
+}
+
+/// systemd's service watchdog (`sd_notify(3)` protocol)
+#[derive(Debug)]
+struct SystemdWatchdog {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+    /// Notifications are sent at half of `WATCHDOG_USEC`
+    interval: Duration,
+    last_sent: Option<Instant>,
+}
+
+impl SystemdWatchdog {
+    fn from_env() -> Option<Self> {
+        let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+        // `WATCHDOG_PID` may target another process of the service
+        if let Ok(pid) = std::env::var("WATCHDOG_PID")
+            && pid.parse() != Ok(std::process::id())
+        {
+            return None;
+        }
+        let path = std::env::var_os("NOTIFY_SOCKET")?;
+        let bytes = path.as_encoded_bytes();
+        let addr = match bytes.strip_prefix(b"@") {
+            Some(name) => SocketAddr::from_abstract_name(name),
+            None => SocketAddr::from_pathname(&path),
+        };
+        let addr = match addr {
+            Ok(addr) => addr,
+            Err(e) => {
+                svlogg!(LogLevel::Warn, "invalid NOTIFY_SOCKET: {}", e);
+                return None;
+            }
+        };
+        let socket = match UnixDatagram::unbound() {
+            Ok(socket) => socket,
+            Err(e) => {
+                svlogg!(LogLevel::Warn, "can't create notification socket: {}", e);
+                return None;
+            }
+        };
+        svlogg!(LogLevel::Debug, "systemd watchdog enabled ({}us)", usec);
+        Some(Self {
+            socket,
+            addr,
+            interval: Duration::from_micros(usec / 2),
+            last_sent: None,
+        })
+    }
+
+    fn kick(&mut self, now: Instant) {
+        if self
+            .last_sent
+            .is_some_and(|last| now.duration_since(last) < self.interval)
+        {
+            return;
+        }
+        if let Err(e) = self.socket.send_to_addr(b"WATCHDOG=1", &self.addr) {
+            svlogg!(LogLevel::Warn, "failed to notify systemd watchdog: {}", e);
+        }
+        self.last_sent = Some(now);
+    }
+}
@@ -0,0 +1,406 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Webhook notifications of service state changes.
+//!
+//! The main loop only detects the changes and queues the deliveries: the
+//! requests are made by a worker thread, one at a time, so that a slow or
+//! unreachable endpoint never delays supervision. Deliveries are dropped
+//! rather than queued without bound when the worker falls behind.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    sync::{
+        Arc,
+        mpsc::{Receiver, SyncSender, TrySendError, sync_channel},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+
+use svlopp::snapshot::RecordFlags;
+
+use crate::logging::LogLevel;
+use crate::service::{Service, ServiceRegistry, ServiceState, ServiceStopReason};
+use crate::svlogg;
+
+/// Maximum number of deliveries waiting for the worker
+const QUEUE_LEN: usize = 256;
+
+const DEFAULT_TIMEOUT_MS: u64 = 5000;
+
+/// Header holding the signature of the body, `sha256=<hex>`
+const SIGNATURE_HEADER: &str = "X-Svlopp-Signature";
+
+/// Service state changes webhooks are fired on
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum WebhookEvent {
+    /// The service process was started, or resumed
+    Running,
+    /// The service process was paused
+    Paused,
+    /// The service process stopped without failing, e.g. on request
+    Stopped,
+    /// The service process stopped with a failure
+    Failed,
+    /// The service process reached `fd_warn_percent` of its open file
+    /// limit, only notified when asked for
+    #[serde(rename = "fds_high")]
+    FdsHigh,
+}
+
+impl WebhookEvent {
+    fn of(state: &ServiceState) -> Option<Self> {
+        match state {
+            ServiceState::Running(_) => Some(Self::Running),
+            ServiceState::Paused(_) => Some(Self::Paused),
+            ServiceState::Stopping(..) | ServiceState::Stopped(ServiceStopReason::NeverStarted) => {
+                None
+            }
+            ServiceState::Stopped(reason) if reason.is_failure() => Some(Self::Failed),
+            ServiceState::Stopped(_) => Some(Self::Stopped),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::Paused => "paused",
+            Self::Stopped => "stopped",
+            Self::Failed => "failed",
+            Self::FdsHigh => "fds_high",
+        }
+    }
+}
+
+fn default_events() -> Vec<WebhookEvent> {
+    vec![
+        WebhookEvent::Running,
+        WebhookEvent::Paused,
+        WebhookEvent::Stopped,
+        WebhookEvent::Failed,
+    ]
+}
+
+fn default_timeout_ms() -> u64 {
+    DEFAULT_TIMEOUT_MS
+}
+
+/// An HTTP endpoint notified of service state changes, with a `POST`
+/// request whose JSON body describes the change
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct Webhook {
+    /// `http://` or `https://` URL, where `{name}`, `{id}` and `{event}` are
+    /// replaced by the service name, id and the event
+    pub(crate) url: String,
+    #[serde(default = "default_events")]
+    pub(crate) on: Vec<WebhookEvent>,
+    /// Only notify changes of these services, all of them by default
+    #[serde(default)]
+    pub(crate) services: Option<Vec<String>>,
+    /// File holding the key the body is signed with, as an HMAC-SHA256
+    #[serde(default)]
+    pub(crate) secret_file: Option<PathBuf>,
+    #[serde(default = "default_timeout_ms")]
+    pub(crate) timeout_ms: u64,
+    /// Content of `secret_file`, read when the config is loaded
+    #[serde(skip)]
+    secret: Option<Vec<u8>>,
+}
+
+impl Webhook {
+    /// Check the URL and the timeout
+    pub(crate) fn validate(&self) -> io::Result<()> {
+        let https = if self.url.starts_with("https://") {
+            true
+        } else if self.url.starts_with("http://") {
+            false
+        } else {
+            return Err(io::Error::other(format!(
+                "webhook URL '{}' is not http:// or https://",
+                self.url
+            )));
+        };
+        if https && cfg!(not(feature = "tls")) {
+            return Err(io::Error::other(format!(
+                "webhook URL '{}' requires svlopp to be built with the tls feature",
+                self.url
+            )));
+        }
+        if self.timeout_ms == 0 {
+            return Err(io::Error::other("webhook timeout_ms must not be zero"));
+        }
+        Ok(())
+    }
+
+    /// Read the secret, if any
+    pub(crate) fn read_secret(&mut self) -> io::Result<()> {
+        if let Some(path) = &self.secret_file {
+            let secret = std::fs::read(path).map_err(|e| {
+                io::Error::other(format!(
+                    "can't read webhook secret '{}': {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            self.secret = Some(secret.trim_ascii_end().to_vec());
+        }
+        Ok(())
+    }
+
+    fn wants(&self, name: &str, event: WebhookEvent) -> bool {
+        self.on.contains(&event)
+            && self
+                .services
+                .as_ref()
+                .is_none_or(|services| services.iter().any(|s| s == name))
+    }
+}
+
+/// Percent-encode `s` for use in a URL
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// A request for the worker
+#[derive(Debug)]
+struct Delivery {
+    url: String,
+    body: String,
+    signature: Option<String>,
+    timeout: Duration,
+}
+
+/// Fires the configured webhooks on service state changes
+#[derive(Debug, Default)]
+pub(crate) struct Webhooks {
+    hooks: Vec<Webhook>,
+    /// Started along with the worker, on the first configured webhook
+    queue: Option<SyncSender<Delivery>>,
+    /// Last event of each service, to tell which changed
+    events: HashMap<Arc<str>, WebhookEvent>,
+    /// Services flagged with `fds_high` as of the last call
+    fds_high: HashSet<Arc<str>>,
+}
+
+impl Webhooks {
+    /// Replace the configured webhooks, e.g. on reload
+    pub(crate) fn configure(&mut self, hooks: Vec<Webhook>) -> io::Result<()> {
+        if !hooks.is_empty() && self.queue.is_none() {
+            let (tx, rx) = sync_channel(QUEUE_LEN);
+            std::thread::Builder::new()
+                .name("svlopp-webhooks".into())
+                .spawn(move || deliver_all(rx))?;
+            self.queue = Some(tx);
+        }
+        self.hooks = hooks;
+        Ok(())
+    }
+
+    /// Queue the deliveries of the webhooks interested in the changes since
+    /// the last call
+    pub(crate) fn notify(&mut self, registry: &ServiceRegistry) {
+        for svc in registry.services() {
+            if let Some(event) = WebhookEvent::of(&svc.state)
+                && self.events.insert(Arc::clone(&svc.name), event) != Some(event)
+                && !self.queue_deliveries(svc, event)
+            {
+                return;
+            }
+            if !svc.flags.contains(RecordFlags::FDS_HIGH) {
+                self.fds_high.remove(&svc.name);
+            } else if self.fds_high.insert(Arc::clone(&svc.name))
+                && !self.queue_deliveries(svc, WebhookEvent::FdsHigh)
+            {
+                return;
+            }
+        }
+        self.events
+            .retain(|name, _| registry.get_by_name(name).is_some());
+        self.fds_high
+            .retain(|name| registry.get_by_name(name).is_some());
+    }
+
+    /// Queue the deliveries of `event` of `svc`. Returns false if the
+    /// worker exited
+    fn queue_deliveries(&mut self, svc: &Service, event: WebhookEvent) -> bool {
+        let Some(queue) = self.queue.as_ref() else {
+            return true;
+        };
+        let (pid, reason) = match svc.state {
+            ServiceState::Running(pid) | ServiceState::Paused(pid) => {
+                (Some(pid.as_raw_nonzero().get()), None)
+            }
+            ServiceState::Stopped(reason) => (None, Some(reason.to_string())),
+            ServiceState::Stopping(..) => (None, None),
+        };
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        let body = json!({
+            "event": event.as_str(),
+            "name": svc.name,
+            "id": svc.id,
+            "pid": pid,
+            "reason": reason,
+            "error": svc.spawn_failure(),
+            "timestamp_ms": timestamp_ms,
+        })
+        .to_string();
+        for hook in self.hooks.iter().filter(|h| h.wants(&svc.name, event)) {
+            let url = hook
+                .url
+                .replace("{name}", &percent_encode(&svc.name))
+                .replace("{id}", &svc.id.to_string())
+                .replace("{event}", event.as_str());
+            let signature = hook.secret.as_deref().map(|secret| sign(secret, &body));
+            let delivery = Delivery {
+                url,
+                body: body.clone(),
+                signature,
+                timeout: Duration::from_millis(hook.timeout_ms),
+            };
+            match queue.try_send(delivery) {
+                Ok(()) => {}
+                Err(TrySendError::Full(d)) => svlogg!(
+                    LogLevel::Warn,
+                    "webhook queue full, dropping '{}' notification to {}",
+                    event.as_str(),
+                    d.url
+                ),
+                Err(TrySendError::Disconnected(_)) => {
+                    svlogg!(LogLevel::Error, "webhook worker exited");
+                    self.queue = None;
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+/// HMAC-SHA256 of `body` with `secret`, as sent in the signature header
+fn sign(secret: &[u8], body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(body.as_bytes());
+    let mut out = String::from("sha256=");
+    for b in mac.finalize().into_bytes() {
+        out.push_str(&format!("{:02x}", b));
+    }
+    out
+}
+
+/// Worker loop, until the main thread drops the queue
+fn deliver_all(rx: Receiver<Delivery>) {
+    for delivery in rx {
+        match deliver(&delivery) {
+            Ok(status) if (200..300).contains(&status) => svlogg!(
+                LogLevel::Debug,
+                "webhook {} answered {}",
+                delivery.url,
+                status
+            ),
+            Ok(status) => svlogg!(
+                LogLevel::Warn,
+                "webhook {} answered {}",
+                delivery.url,
+                status
+            ),
+            Err(e) => svlogg!(LogLevel::Warn, "webhook {} failed: {}", delivery.url, e),
+        }
+    }
+}
+
+/// Make the request of `delivery`, returns the response status code
+fn deliver(delivery: &Delivery) -> io::Result<u16> {
+    let (https, rest) = match delivery.url.split_once("://") {
+        Some(("https", rest)) => (true, rest),
+        Some(("http", rest)) => (false, rest),
+        _ => return Err(io::Error::other("unsupported URL scheme")),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => (
+            host,
+            port.parse()
+                .map_err(|_| io::Error::other("invalid URL port"))?,
+        ),
+        _ => (authority, if https { 443 } else { 80 }),
+    };
+    let mut last_err = io::Error::other("host has no address");
+    let mut stream = None;
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, delivery.timeout) {
+            Ok(s) => {
+                stream = Some(s);
+                break;
+            }
+            Err(e) => last_err = e,
+        }
+    }
+    let stream = stream.ok_or(last_err)?;
+    stream.set_read_timeout(Some(delivery.timeout))?;
+    stream.set_write_timeout(Some(delivery.timeout))?;
+
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: svlopp/{}\r\n\
+         Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        path,
+        authority,
+        env!("CARGO_PKG_VERSION"),
+        delivery.body.len()
+    );
+    if let Some(signature) = &delivery.signature {
+        request.push_str(&format!("{}: {}\r\n", SIGNATURE_HEADER, signature));
+    }
+    request.push_str("\r\n");
+    request.push_str(&delivery.body);
+
+    // https URLs are rejected on load without TLS support
+    #[cfg(feature = "tls")]
+    if https {
+        return exchange(crate::tls::client_stream(host, stream)?, &request);
+    }
+    exchange(stream, &request)
+}
+
+/// Send `request` and read the status code of the response
+fn exchange(mut stream: impl Read + Write, request: &str) -> io::Result<u16> {
+    stream.write_all(request.as_bytes())?;
+    stream.flush()?;
+    let mut head = Vec::new();
+    let mut chunk = [0u8; 512];
+    while !head.windows(2).any(|w| w == b"\r\n") {
+        let n = stream.read(&mut chunk)?;
+        if n == 0 || head.len() > 4096 {
+            return Err(io::Error::other("invalid response"));
+        }
+        head.extend_from_slice(&chunk[..n]);
+    }
+    let status = head
+        .split(|&b| b == b' ')
+        .nth(1)
+        .and_then(|code| std::str::from_utf8(code).ok()?.parse().ok())
+        .ok_or_else(|| io::Error::other("invalid response"))?;
+    Ok(status)
+}
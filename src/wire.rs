@@ -0,0 +1,200 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Wire format of the control FIFO commands.
+
+use crate::opcode::{
+    ADOPT as OP_ADOPT, CANCEL as OP_CANCEL, DETACH as OP_DETACH, DRY_RUN as OP_DRY_RUN,
+    GRAPH as OP_GRAPH, PAUSE as OP_PAUSE, PS as OP_PS, RELOAD as OP_RELOAD, RESTART as OP_RESTART,
+    RESTART_OUTDATED as OP_RESTART_OUTDATED, RESTORE as OP_RESTORE, RESUME as OP_RESUME,
+    ROLLING_RESTART as OP_ROLLING_RESTART, RUN as OP_RUN, SET_PROPERTY as OP_SET_PROPERTY,
+    SNAPSHOT as OP_SNAPSHOT, START as OP_START, STOP as OP_STOP, SWAP as OP_SWAP,
+};
+
+/// Size of a command frame
+pub const WIRE_COMMAND_SIZE: usize = 9;
+
+/// A command frame as written to the control FIFO, not validated yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WireControlCommand {
+    /// One of the `opcode` values, possibly or'ed with `opcode::DRY_RUN`
+    pub opcode: u8,
+    pub service_id: u64,
+}
+
+impl WireControlCommand {
+    #[inline(always)]
+    pub fn new(opcode: u8, service_id: u64) -> Self {
+        Self { opcode, service_id }
+    }
+
+    pub fn encode(&self) -> [u8; WIRE_COMMAND_SIZE] {
+        let mut frame = [0u8; WIRE_COMMAND_SIZE];
+        frame[0] = self.opcode;
+        frame[1..].copy_from_slice(&self.service_id.to_le_bytes());
+        frame
+    }
+
+    pub fn decode(frame: &[u8; WIRE_COMMAND_SIZE]) -> Self {
+        let mut svc_id_bytes = [0u8; 8];
+        svc_id_bytes.copy_from_slice(&frame[1..]);
+        Self::new(frame[0], u64::from_le_bytes(svc_id_bytes))
+    }
+}
+
+/// Split `buf`, read from the control FIFO, into frames. A trailing
+/// partial frame is yielded as `Err` with its length
+pub fn split_frames(
+    buf: &[u8],
+) -> impl ExactSizeIterator<Item = Result<WireControlCommand, usize>> + '_ {
+    buf.chunks(WIRE_COMMAND_SIZE).map(|frame| {
+        <&[u8; WIRE_COMMAND_SIZE]>::try_from(frame)
+            .map(WireControlCommand::decode)
+            .map_err(|_| frame.len())
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlProtocolError {
+    InvalidOp(u8),
+    PartialFrame(usize),
+}
+
+impl std::fmt::Display for ControlProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidOp(op) => write!(f, "invalid opcode: 0x{:02x}", op),
+            Self::PartialFrame(n) => {
+                write!(f, "partial control frame ({} bytes)", n)
+            }
+        }
+    }
+}
+
+/// Control operations
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ControlOp {
+    Stop = OP_STOP,
+    Start = OP_START,
+    Restart = OP_RESTART,
+    /// Write the process tree of the service to the `ps.d` directory of
+    /// the run directory
+    Ps = OP_PS,
+    /// Write the service graph to the run directory. Not a service
+    /// operation: the service id field holds the format
+    Graph = OP_GRAPH,
+    /// Save the running services to the run set file of the run
+    /// directory. Not a service operation
+    Snapshot = OP_SNAPSHOT,
+    /// Start the services of the run set file. Not a service operation:
+    /// the service id field holds the restore mode
+    Restore = OP_RESTORE,
+    /// Reload the configuration. Not a service operation
+    Reload = OP_RELOAD,
+    /// Freeze the processes of the service, through its cgroup if it has
+    /// one or else its process group
+    Pause = OP_PAUSE,
+    /// Thaw the processes of a paused service
+    Resume = OP_RESUME,
+    /// Set a property of the service, read from the `property.d`
+    /// directory of the run directory
+    SetProperty = OP_SET_PROPERTY,
+    /// Restart the services running an outdated config. Not a service
+    /// operation
+    RestartOutdated = OP_RESTART_OUTDATED,
+    /// Cancel the latest job of the service
+    Cancel = OP_CANCEL,
+    /// Restart the instances of a replicated service one at a time, see
+    /// `rollout`
+    RollingRestart = OP_ROLLING_RESTART,
+    /// Replace the service with a new instance of it, see `swap`
+    Swap = OP_SWAP,
+    /// Define and start a transient service, see `transient`. Not a
+    /// service operation, the id being the one of the request
+    Run = OP_RUN,
+    /// Adopt a running process as a service, see `adopt`. Not a service
+    /// operation, the id being the pid of the process
+    Adopt = OP_ADOPT,
+    /// Stop supervising the service process, left running, see
+    /// `ServiceRegistry::detach`
+    Detach = OP_DETACH,
+}
+
+impl std::fmt::Display for ControlOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Stop => write!(f, "stop"),
+            Self::Start => write!(f, "start"),
+            Self::Restart => write!(f, "restart"),
+            Self::Ps => write!(f, "ps"),
+            Self::Graph => write!(f, "graph"),
+            Self::Snapshot => write!(f, "snapshot"),
+            Self::Restore => write!(f, "restore"),
+            Self::Reload => write!(f, "reload"),
+            Self::Pause => write!(f, "pause"),
+            Self::Resume => write!(f, "resume"),
+            Self::SetProperty => write!(f, "set-property"),
+            Self::RestartOutdated => write!(f, "restart-outdated"),
+            Self::Cancel => write!(f, "cancel"),
+            Self::RollingRestart => write!(f, "rolling-restart"),
+            Self::Swap => write!(f, "swap"),
+            Self::Run => write!(f, "run"),
+            Self::Adopt => write!(f, "adopt"),
+            Self::Detach => write!(f, "detach"),
+        }
+    }
+}
+
+/// A validated control command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControlCommand {
+    pub op: ControlOp,
+    pub service_id: u64,
+    /// Only write the plan of `op` to the `plan.d` directory of the run
+    /// directory, without applying it
+    pub dry_run: bool,
+}
+
+impl ControlCommand {
+    #[inline(always)]
+    pub fn new(op: ControlOp, service_id: u64, dry_run: bool) -> Self {
+        Self {
+            op,
+            service_id,
+            dry_run,
+        }
+    }
+}
+
+impl TryFrom<WireControlCommand> for ControlCommand {
+    type Error = ControlProtocolError;
+
+    /// Only `stop`, `start` and `restart` can be dry-run
+    fn try_from(wire: WireControlCommand) -> Result<Self, Self::Error> {
+        let dry_run = wire.opcode & OP_DRY_RUN != 0;
+        let op = match (wire.opcode & !OP_DRY_RUN, dry_run) {
+            (OP_STOP, _) => ControlOp::Stop,
+            (OP_START, _) => ControlOp::Start,
+            (OP_RESTART, _) => ControlOp::Restart,
+            (OP_PS, false) => ControlOp::Ps,
+            (OP_GRAPH, false) => ControlOp::Graph,
+            (OP_SNAPSHOT, false) => ControlOp::Snapshot,
+            (OP_RESTORE, false) => ControlOp::Restore,
+            (OP_RELOAD, false) => ControlOp::Reload,
+            (OP_PAUSE, false) => ControlOp::Pause,
+            (OP_RESUME, false) => ControlOp::Resume,
+            (OP_SET_PROPERTY, false) => ControlOp::SetProperty,
+            (OP_RESTART_OUTDATED, false) => ControlOp::RestartOutdated,
+            (OP_CANCEL, false) => ControlOp::Cancel,
+            (OP_ROLLING_RESTART, false) => ControlOp::RollingRestart,
+            (OP_SWAP, false) => ControlOp::Swap,
+            (OP_RUN, false) => ControlOp::Run,
+            (OP_ADOPT, false) => ControlOp::Adopt,
+            (OP_DETACH, false) => ControlOp::Detach,
+            _ => return Err(ControlProtocolError::InvalidOp(wire.opcode)),
+        };
+        Ok(Self::new(op, wire.service_id, dry_run))
+    }
+}
@@ -24,11 +24,21 @@ def svlopp_bin() -> Path:
 def svlopp_proc(svlopp_bin: Path, run_dir: Path):
     procs = []
 
-    def _run(config_path):
+    def _run(config_path, *extra_args, cgroup=None):
+        def enter_cgroup():
+            (cgroup / "cgroup.procs").write_text("0")
+
         proc = subprocess.Popen(
-            [svlopp_bin, "--run-dir", str(run_dir), str(config_path)],
+            [
+                svlopp_bin,
+                "--run-dir",
+                str(run_dir),
+                *extra_args,
+                *([str(config_path)] if config_path is not None else []),
+            ],
             stdout=subprocess.PIPE,
             stderr=subprocess.PIPE,
+            preexec_fn=enter_cgroup if cgroup is not None else None,
         )
         procs.append(proc)
         return proc
@@ -3,24 +3,37 @@
 # file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 SVLOPP_BINARY_PATH = "./target/debug/svlopp"
+SVLOPPCTL_BINARY_PATH = "./target/debug/svloppctl"
 
 CONFIG_FILE_NAME = "services.toml"
 RUN_DIR_NAME = "svlopp"
 STATUS_FILE_NAME = "status"
+STATUS_DIR_NAME = "status.d"
+STATUS_NOTIFY_SOCKET_NAME = "status.sock"
+SNAPSHOT_FILE_NAME = "state.snap"
+ORPHANS_FILE_NAME = "orphans"
+READY_SOCKET_NAME = "notify.sock"
 CONTROL_FIFO_NAME = "control"
 
 STATE_RUNNING = "running"
 STATE_STOPPING = "stopping"
 STATE_STOPPED = "stopped"
+STATE_PAUSED = "paused"
 
 REASON_EXITED = "exited"
 REASON_SIGNALED = "signaled"
 REASON_SUPERVISOR_TERMINATED = "supervisor_terminated"
+REASON_BOUND_STOPPED = "bound_stopped"
+REASON_RUNTIME_EXCEEDED = "runtime_exceeded"
 REASON_SUCCESS = "success"
 REASON_ERROR = "error"
 REASON_CRASHED = "crashed"
 REASON_KILLED = "killed"
+REASON_OOM_KILLED = "oom_killed"
+REASON_SPAWN_FAILED = "spawn_failed"
 
 STOP_OPCODE = 0x41
 START_OPCDOE = 0x42
 RESTART_OPCODE = 0x43
+PAUSE_OPCODE = 0x49
+RESUME_OPCODE = 0x4A
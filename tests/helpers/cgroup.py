@@ -0,0 +1,49 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+from pathlib import Path
+
+import pytest
+
+
+def _cgroup2_mount():
+    for line in Path("/proc/self/mountinfo").read_text().splitlines():
+        mount, _, fs = line.partition(" - ")
+        if fs.startswith("cgroup2 "):
+            return Path(mount.split()[4])
+    return None
+
+
+def _own_cgroup():
+    for line in Path("/proc/self/cgroup").read_text().splitlines():
+        if line.startswith("0::"):
+            return line[3:]
+    return None
+
+
+def make_cgroup():
+    """A cgroup for svlopp to run in, returns it and its directory"""
+    mount = _cgroup2_mount()
+    own = _own_cgroup()
+    if mount is None or own is None or not os.access(mount / own.lstrip("/"), os.W_OK):
+        pytest.skip("needs a writable cgroup v2 hierarchy")
+    cgroup = f"{own.rstrip('/')}/svlopp-test-{os.getpid()}"
+    cgroup_dir = mount / cgroup.lstrip("/")
+    cgroup_dir.mkdir()
+    return cgroup, cgroup_dir
+
+
+def remove_cgroup(cgroup_dir):
+    """Remove `cgroup_dir` and its descendants, once their processes are gone"""
+    for child in cgroup_dir.iterdir():
+        if child.is_dir():
+            remove_cgroup(child)
+    cgroup_dir.rmdir()
+
+
+def has_controller(cgroup_dir, controller):
+    """Whether `controller` is available to `cgroup_dir`, and so can be
+    enabled for its children"""
+    return controller in (cgroup_dir / "cgroup.controllers").read_text().split()
@@ -0,0 +1,23 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import contextlib
+import time
+
+import pytest
+
+
+@contextlib.contextmanager
+def stepped_clock(seconds):
+    """Step the system clock by `seconds`, and back on exit as if it had
+    kept running"""
+    offset = time.clock_gettime(time.CLOCK_REALTIME) - time.monotonic()
+    try:
+        time.clock_settime(time.CLOCK_REALTIME, time.time() + seconds)
+    except PermissionError:
+        pytest.skip("needs CAP_SYS_TIME to step the clock")
+    try:
+        yield
+    finally:
+        time.clock_settime(time.CLOCK_REALTIME, time.monotonic() + offset)
@@ -0,0 +1,28 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+import re
+import shutil
+import stat
+import subprocess
+
+import pytest
+
+
+def make_jail(tmp_path, dev_null=True):
+    """A root with `/bin/sh`, the libraries it links and `/dev/null`"""
+    if os.geteuid() != 0:
+        pytest.skip("needs root to chroot")
+    jail = tmp_path / "jail"
+    sh = os.path.realpath("/bin/sh")
+    ldd = subprocess.run(["ldd", sh], capture_output=True, text=True).stdout
+    for path in [sh, *re.findall(r"(/\S+) \(0x", ldd)]:
+        target = jail / ("bin/sh" if path == sh else path.lstrip("/"))
+        target.parent.mkdir(parents=True, exist_ok=True)
+        shutil.copy(path, target)
+    if dev_null:
+        (jail / "dev").mkdir()
+        os.mknod(jail / "dev/null", 0o666 | stat.S_IFCHR, os.makedev(1, 3))
+    return jail
@@ -2,11 +2,17 @@
 # License, v. 2.0. If a copy of the MPL was not distributed with this
 # file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-from dataclasses import dataclass
+from dataclasses import dataclass, field
 from pathlib import Path
 from typing import Self
 
-from constants import STATE_RUNNING, STATE_STOPPED, STATUS_FILE_NAME
+from constants import (
+    STATE_PAUSED,
+    STATE_RUNNING,
+    STATE_STOPPED,
+    STATUS_DIR_NAME,
+    STATUS_FILE_NAME,
+)
 
 
 @dataclass
@@ -15,13 +21,15 @@ class StatusLine:
     service_id: int
     state: str
     pid_or_reason: str
+    flags: list[str] = field(default_factory=list)
 
     def __repr__(self) -> str:
         return (
             f"StatusLine(id={self.service_id}, "
             f"name={self.service_name}, "
             f"state={self.state}, "
-            f"extra={self.pid_or_reason})"
+            f"extra={self.pid_or_reason}, "
+            f"flags={self.flags})"
         )
 
 
@@ -52,6 +60,7 @@ class StatusFile:
                     ) from None
                 state = parts[2]
                 pid_or_reason = parts[3]
+                flags = parts[4:]
 
                 lines.append(
                     StatusLine(
@@ -59,6 +68,7 @@ class StatusFile:
                         service_id,
                         state,
                         pid_or_reason,
+                        flags,
                     )
                 )
 
@@ -79,6 +89,15 @@ class StatusFile:
     def is_stopped(self, service_name: str) -> bool:
         return self.get(service_name).state == STATE_STOPPED
 
+    def is_paused(self, service_name: str) -> bool:
+        return self.get(service_name).state == STATE_PAUSED
+
 
 def read_status(run_dir: Path) -> StatusFile:
     return StatusFile.from_path(run_dir / STATUS_FILE_NAME)
+
+
+def read_service_status(run_dir: Path, service_name: str) -> StatusLine:
+    return StatusFile.from_path(run_dir / STATUS_DIR_NAME / service_name).get(
+        service_name
+    )
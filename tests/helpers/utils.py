@@ -3,8 +3,12 @@
 # file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 from pathlib import Path
+import subprocess
 import time
 
+from constants import CONFIG_FILE_NAME, SVLOPPCTL_BINARY_PATH
+from helpers.status_file import read_status
+
 
 def wait_until(cond, timeout=1.0, interval=0.01):
     start = time.time()
@@ -15,6 +19,67 @@ def wait_until(cond, timeout=1.0, interval=0.01):
     raise TimeoutError("condition not met within timeout")
 
 
+def svloppctl(run_dir, *args, **kwargs):
+    """Run svloppctl with `args`, against the supervisor of `run_dir`
+    unless it's `None`"""
+    run_dir_args = ["--run-dir", str(run_dir)] if run_dir is not None else []
+    return subprocess.run(
+        [SVLOPPCTL_BINARY_PATH, *run_dir_args, *args],
+        capture_output=True,
+        text=True,
+        timeout=5,
+        **kwargs,
+    )
+
+
+def status_matches(run_dir, predicate):
+    """Condition for `wait_until` that the status in `run_dir` satisfies
+    `predicate`"""
+
+    def check():
+        try:
+            return predicate(read_status(run_dir))
+        except (FileNotFoundError, KeyError):
+            return False
+
+    return check
+
+
+def is_running(run_dir, name):
+    try:
+        return read_status(run_dir).is_running(name)
+    except (FileNotFoundError, KeyError):
+        return False
+
+
+def is_stopped(run_dir, name):
+    try:
+        return read_status(run_dir).is_stopped(name)
+    except (FileNotFoundError, KeyError):
+        return False
+
+
+def service_state(run_dir, name):
+    """State and pid or stop reason of the service `name`, `None`s until
+    it's in the status"""
+    try:
+        svc = read_status(run_dir).get(name)
+        return svc.state, svc.pid_or_reason
+    except (FileNotFoundError, KeyError):
+        return None, None
+
+
+def start_svlopp(tmp_path, run_dir, svlopp_proc, config, names, *extra_args, **kwargs):
+    """Start svlopp with `config`, written to `tmp_path`, and wait until the
+    services `names` are running"""
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(config)
+    proc = svlopp_proc(config_path, *extra_args, **kwargs)
+    for name in names:
+        wait_until(lambda: is_running(run_dir, name), timeout=5.0)
+    return proc
+
+
 def is_zombie(pid: int) -> bool:
     try:
         with open(f"/proc/{pid}/stat", "r") as f:
@@ -0,0 +1,208 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import time
+
+from constants import (
+    CONFIG_FILE_NAME,
+    REASON_BOUND_STOPPED,
+    START_OPCDOE,
+    STOP_OPCODE,
+)
+from helpers.control_fifo import send_control_op
+from helpers.status_file import read_status
+from helpers.utils import status_matches, wait_until
+
+BIND_CONFIG = """
+[services.app]
+command = "/bin/sleep"
+args = ["10"]
+
+[services.shipper]
+command = "/bin/sleep"
+args = ["10"]
+bind_to = "app"
+"""
+
+
+# fails once `fail` exists in the working directory, restarted
+FAILING_CONFIG = """
+[services.app]
+command = "/bin/sh"
+args = ["-c", "while [ ! -e fail ]; do sleep 0.1; done; rm fail; exit 1"]
+working_directory = "{tmp_path}"
+on_exit = "Restart"
+
+[services.shipper]
+command = "/bin/sleep"
+args = ["10"]
+bind_to = "app"
+{policy}
+"""
+
+
+def _fail_app(tmp_path, run_dir, svlopp_proc, policy=""):
+    """Start `app` and `shipper`, make `app` fail and wait for it to run
+    again, returning the pid `shipper` had"""
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(FAILING_CONFIG.format(tmp_path=tmp_path, policy=policy))
+    _ = svlopp_proc(config_path)
+    wait_until(
+        status_matches(
+            run_dir, lambda s: s.is_running("app") and s.is_running("shipper")
+        ),
+        timeout=2.0,
+    )
+    app_pid = read_status(run_dir).get("app").pid_or_reason
+    shipper_pid = read_status(run_dir).get("shipper").pid_or_reason
+
+    (tmp_path / "fail").touch()
+    wait_until(
+        status_matches(
+            run_dir,
+            lambda s: s.is_running("app") and s.get("app").pid_or_reason != app_pid,
+        ),
+        timeout=3.0,
+    )
+    return shipper_pid
+
+
+def test_bind_to_follows_bound_service(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(BIND_CONFIG)
+
+    _ = svlopp_proc(config_path)
+
+    wait_until(
+        status_matches(
+            run_dir, lambda s: s.is_running("app") and s.is_running("shipper")
+        ),
+        timeout=2.0,
+    )
+    app_id = read_status(run_dir).get("app").service_id
+    shipper_pid = read_status(run_dir).get("shipper").pid_or_reason
+
+    send_control_op(run_dir, STOP_OPCODE, app_id)
+
+    wait_until(
+        status_matches(
+            run_dir,
+            lambda s: s.is_stopped("app")
+            and s.is_stopped("shipper")
+            and s.get("shipper").pid_or_reason.startswith(REASON_BOUND_STOPPED),
+        ),
+        timeout=2.0,
+    )
+
+    send_control_op(run_dir, START_OPCDOE, app_id)
+
+    wait_until(
+        status_matches(
+            run_dir,
+            lambda s: s.is_running("app")
+            and s.is_running("shipper")
+            and s.get("shipper").pid_or_reason != shipper_pid,
+        ),
+        timeout=3.0,
+    )
+
+
+def test_bind_to_waits_for_bound_service(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.app]
+command = "/bin/false"
+
+[services.shipper]
+command = "/bin/sleep"
+args = ["10"]
+bind_to = "app"
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    # `app` either never runs long enough for `shipper` to start, or
+    # `shipper` is stopped along with it
+    wait_until(
+        status_matches(
+            run_dir, lambda s: s.is_stopped("app") and s.is_stopped("shipper")
+        ),
+        timeout=3.0,
+    )
+
+
+def test_bind_to_unknown_service_rejected(tmp_path, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.shipper]
+command = "/bin/sleep"
+args = ["10"]
+bind_to = "missing"
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    assert proc.wait(timeout=2.0) != 0
+
+
+def test_bind_to_cycle_rejected(tmp_path, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.a]
+command = "/bin/sleep"
+args = ["10"]
+bind_to = "b"
+
+[services.b]
+command = "/bin/sleep"
+args = ["10"]
+bind_to = "a"
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    assert proc.wait(timeout=2.0) != 0
+
+
+def test_target_failure_restart(tmp_path, run_dir, svlopp_proc):
+    shipper_pid = _fail_app(tmp_path, run_dir, svlopp_proc)
+
+    wait_until(
+        status_matches(
+            run_dir,
+            lambda s: s.is_running("shipper")
+            and s.get("shipper").pid_or_reason != shipper_pid,
+        ),
+        timeout=3.0,
+    )
+
+
+def test_target_failure_stop(tmp_path, run_dir, svlopp_proc):
+    _fail_app(tmp_path, run_dir, svlopp_proc, 'on_target_failure = "Stop"')
+
+    # not started again along with `app`
+    time.sleep(1.5)
+    status = read_status(run_dir)
+    assert status.is_stopped("shipper")
+    assert status.get("shipper").pid_or_reason.startswith(REASON_BOUND_STOPPED)
+
+    send_control_op(run_dir, START_OPCDOE, status.get("shipper").service_id)
+    wait_until(
+        status_matches(run_dir, lambda s: s.is_running("shipper")), timeout=2.0
+    )
+
+
+def test_target_failure_keep(tmp_path, run_dir, svlopp_proc):
+    policy = 'on_target_failure = "Keep"'
+    shipper_pid = _fail_app(tmp_path, run_dir, svlopp_proc, policy)
+
+    status = read_status(run_dir)
+    assert status.is_running("shipper")
+    assert status.get("shipper").pid_or_reason == shipper_pid
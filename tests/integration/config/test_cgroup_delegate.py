@@ -0,0 +1,101 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+from pathlib import Path
+
+import pytest
+
+from constants import CONFIG_FILE_NAME, REASON_SUCCESS
+from helpers.cgroup import make_cgroup, remove_cgroup
+from helpers.utils import wait_until
+from helpers.status_file import read_status
+
+NOBODY = 65534
+
+
+def _has_exited(run_dir, name):
+    try:
+        status = read_status(run_dir)
+        # the status may be written before the first start
+        return status.is_stopped(name) and status.get(name).pid_or_reason != "never_started"
+    except (FileNotFoundError, KeyError):
+        return False
+
+
+def _proc_cgroup(pid):
+    for line in Path(f"/proc/{pid}/cgroup").read_text().splitlines():
+        if line.startswith("0::"):
+            return line[3:]
+    return None
+
+
+def _run_delegated(tmp_path, run_dir, svlopp_proc, script, *extra_args, extra=""):
+    cgroup, cgroup_dir = make_cgroup()
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test.log"
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "{script}"]
+log_file_path = "{log_file_path}"
+env = {{ CGROUP_DIR = "{cgroup_dir / 'test'}", PATH = "/usr/bin:/bin" }}
+cgroup_delegate = true
+{extra}
+"""
+    )
+
+    proc = svlopp_proc(config_path, *extra_args, cgroup=cgroup_dir)
+    try:
+        wait_until(lambda: _has_exited(run_dir, "test"), timeout=3.0)
+        assert read_status(run_dir).get("test").pid_or_reason == REASON_SUCCESS
+        assert _proc_cgroup(proc.pid) == f"{cgroup}/supervisor"
+        assert not (cgroup_dir / "test").exists()
+        return cgroup, log_file_path.read_text().splitlines()
+    finally:
+        proc.terminate()
+        proc.wait(timeout=5)
+        remove_cgroup(cgroup_dir)
+
+
+def test_cgroup_delegate(tmp_path, run_dir, svlopp_proc):
+    cgroup, lines = _run_delegated(
+        tmp_path, run_dir, svlopp_proc, "grep ^0:: /proc/self/cgroup"
+    )
+    assert lines == [f"0::{cgroup}/test"]
+
+
+def test_cgroup_delegate_with_spawner(tmp_path, run_dir, svlopp_proc):
+    cgroup, lines = _run_delegated(
+        tmp_path, run_dir, svlopp_proc, "grep ^0:: /proc/self/cgroup", "--spawner"
+    )
+    assert lines == [f"0::{cgroup}/test"]
+
+
+def test_cgroup_delegate_owner(tmp_path, run_dir, svlopp_proc):
+    if os.geteuid() != 0:
+        pytest.skip("needs root to switch user")
+    script = (
+        "cd $CGROUP_DIR; stat -c %u . cgroup.procs cgroup.subtree_control; "
+        "mkdir sub && echo $$ > sub/cgroup.procs && grep ^0:: /proc/self/cgroup"
+    )
+    cgroup, lines = _run_delegated(
+        tmp_path,
+        run_dir,
+        svlopp_proc,
+        script,
+        extra=f"user_group = {{ uid = {NOBODY}, gid = {NOBODY} }}",
+    )
+    assert lines == [str(NOBODY)] * 3 + [f"0::{cgroup}/test/sub"]
+
+
+def test_cgroup_delegate_removes_sub_cgroups(tmp_path, run_dir, svlopp_proc):
+    cgroup, lines = _run_delegated(
+        tmp_path,
+        run_dir,
+        svlopp_proc,
+        "mkdir -p $CGROUP_DIR/a/b && echo created",
+    )
+    assert lines == ["created"]
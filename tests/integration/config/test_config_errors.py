@@ -0,0 +1,135 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import json
+import os
+import signal
+
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+from constants import CONFIG_FILE_NAME
+
+RELOAD_FILE_NAME = "reload"
+
+INVALID_CONFIG = """
+max_services = "three"
+
+[services.a]
+command = "/bin/sleep"
+stop_signal = "SIGFOO"
+
+[services.b]
+command = "/bin/sleep"
+args = "10"
+
+[services.c]
+command = "/bin/sleep"
+args = ["10"]
+conflicts_with = ["nope"]
+"""
+
+
+def _failure(tmp_path, svlopp_proc, config) -> str:
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(config)
+    proc = svlopp_proc(config_path)
+    assert proc.wait(timeout=2.0) != 0
+    return proc.stderr.read().decode()
+
+
+def test_all_errors_reported(tmp_path, run_dir, svlopp_proc):
+    stderr = _failure(tmp_path, svlopp_proc, INVALID_CONFIG)
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    assert "4 errors in the config:" in stderr
+    assert f"{config_path}:2:16: invalid type: string \"three\"" in stderr
+    assert f"{config_path}:6:15: unknown variant `SIGFOO`" in stderr
+    assert f"{config_path}:10:8: invalid type: string \"10\", expected a sequence" in stderr
+    assert (
+        f"{config_path}:15:1: service 'c' conflicts with unknown service 'nope'"
+        in stderr
+    )
+
+
+def test_error_snippet(tmp_path, run_dir, svlopp_proc):
+    stderr = _failure(
+        tmp_path,
+        svlopp_proc,
+        """
+[services.a]
+command = "/bin/sleep"
+stop_signal = "SIGFOO"
+""",
+    )
+    lines = stderr.splitlines()
+    at = next(i for i, line in enumerate(lines) if "unknown variant" in line)
+    assert lines[at + 1] == '4 | stop_signal = "SIGFOO"'
+    assert lines[at + 2] == "  |               ^^^^^^^^"
+
+
+def test_syntax_errors_reported(tmp_path, run_dir, svlopp_proc):
+    stderr = _failure(
+        tmp_path,
+        svlopp_proc,
+        """
+[services.a]
+command = "/bin/sleep
+args = ["10"]
+
+[services.b
+""",
+    )
+    assert "2 errors in the config:" in stderr
+    assert ":3:22: invalid basic string" in stderr
+    assert ":6:12: unclosed table" in stderr
+
+
+def test_invalid_service_not_reported_as_unknown(tmp_path, run_dir, svlopp_proc):
+    stderr = _failure(
+        tmp_path,
+        svlopp_proc,
+        """
+[services.a]
+command = "/bin/sleep"
+args = 10
+
+[services.b]
+command = "/bin/sleep"
+bind_to = "a"
+""",
+    )
+    assert ":4:8: invalid type: integer `10`, expected a sequence" in stderr
+    assert "unknown service" not in stderr
+
+
+def test_reload_reports_all_errors(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.a]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+    proc = svlopp_proc(config_path)
+
+    def is_a_running():
+        try:
+            return read_status(run_dir).is_running("a")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_a_running, timeout=2.0)
+    pid = read_status(run_dir).get("a").pid_or_reason
+
+    config_path.write_text(INVALID_CONFIG)
+    os.kill(proc.pid, signal.SIGHUP)
+
+    report_path = run_dir / RELOAD_FILE_NAME
+    wait_until(report_path.exists, timeout=2.0)
+    report = json.loads(report_path.read_text())
+    assert report["ok"] is False
+    assert report["error"].startswith("4 errors in the config:")
+    assert "unknown variant `SIGFOO`" in report["error"]
+    assert read_status(run_dir).get("a").pid_or_reason == pid
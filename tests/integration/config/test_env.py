@@ -2,11 +2,22 @@
 # License, v. 2.0. If a copy of the MPL was not distributed with this
 # file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-from constants import CONFIG_FILE_NAME
+import os
+import stat
+import tempfile
+import time
+from pathlib import Path
+
+import pytest
+
+from constants import CONFIG_FILE_NAME, READY_SOCKET_NAME, RUN_DIR_NAME
 from helpers.utils import wait_until
 from helpers.status_file import read_status
 
 
+NOBODY = 65534
+
+
 def test_env_happy_path(tmp_path, run_dir, svlopp_proc):
     config_path = tmp_path / CONFIG_FILE_NAME
     output_file_path = tmp_path / "output"
@@ -132,3 +143,188 @@ FOO = "{foo}"
     assert log_file_path.exists()
     content = log_file_path.read_text().strip()
     assert content == foo
+
+
+def test_env_service_identification(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    output_file_path = tmp_path / "output"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "/bin/echo $SVLOPP_SERVICE_NAME $SVLOPP_SERVICE_ID $SVLOPP_RESTART_COUNT > {output_file_path}"]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    def is_test_stopped():
+        try:
+            status = read_status(run_dir)
+            return status.is_stopped("test")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_stopped, timeout=3.0)
+
+    test = read_status(run_dir).get("test")
+    assert output_file_path.exists()
+    content = output_file_path.read_text().strip()
+    assert content == f"test {test.service_id} 0"
+
+
+def test_env_notify_socket(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    output_file_path = tmp_path / "output"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "/bin/echo $SVLOPP_NOTIFY_SOCKET > {output_file_path}"]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    def has_test_written():
+        try:
+            return output_file_path.read_text().endswith("\n")
+        except FileNotFoundError:
+            return False
+
+    wait_until(has_test_written, timeout=3.0)
+
+    content = output_file_path.read_text().strip()
+    assert content == str(run_dir / READY_SOCKET_NAME)
+    assert stat.S_ISSOCK((run_dir / READY_SOCKET_NAME).stat().st_mode)
+
+
+def test_env_notify_socket_ready(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    sent_file_path = tmp_path / "sent"
+    script = (
+        "import os, socket, time; "
+        "s = socket.socket(socket.AF_UNIX, socket.SOCK_DGRAM); "
+        "s.sendto(b'READY=1', os.environ['SVLOPP_NOTIFY_SOCKET']); "
+        f"open('{sent_file_path}', 'w').close(); "
+        "time.sleep(60)"
+    )
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "python3"
+args = ["-c", "{script}"]
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    wait_until(sent_file_path.exists, timeout=3.0)
+    # leave the main loop an iteration to read the notification
+    time.sleep(0.2)
+    proc.terminate()
+    stderr = proc.communicate(timeout=5.0)[1].decode()
+    assert "service 'test' is ready" in stderr
+
+
+def test_env_notify_socket_ready_user_group(tmp_path, svlopp_proc):
+    if os.geteuid() != 0:
+        pytest.skip("needs root to switch user")
+    config_path = tmp_path / CONFIG_FILE_NAME
+    script = (
+        "import os, socket, time; "
+        "s = socket.socket(socket.AF_UNIX, socket.SOCK_DGRAM); "
+        "s.sendto(b'READY=1', os.environ['SVLOPP_NOTIFY_SOCKET']); "
+        "time.sleep(60)"
+    )
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "python3"
+args = ["-c", "{script}"]
+user_group = {{ uid = {NOBODY}, gid = {NOBODY} }}
+"""
+    )
+
+    # the run directory has to be reachable by the service user, which
+    # the temporary directory of the test isn't
+    with tempfile.TemporaryDirectory() as base:
+        os.chmod(base, 0o755)
+        run_dir = Path(base) / RUN_DIR_NAME
+        proc = svlopp_proc(config_path, "--run-dir", str(run_dir))
+
+        def is_test_running():
+            try:
+                return read_status(run_dir).is_running("test")
+            except (FileNotFoundError, KeyError):
+                return False
+
+        wait_until(is_test_running, timeout=3.0)
+        # leave the service time to notify, and the main loop an iteration
+        # to read the notification
+        time.sleep(0.5)
+        proc.terminate()
+        stderr = proc.communicate(timeout=5.0)[1].decode()
+    assert "service 'test' is ready" in stderr
+
+
+def test_env_service_identification_overrides_env(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    output_file_path = tmp_path / "output"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "/bin/echo $HOME $SVLOPP_SERVICE_NAME > {output_file_path}"]
+
+[services.test.env]
+SVLOPP_SERVICE_NAME = "overridden"
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    def is_test_stopped():
+        try:
+            status = read_status(run_dir)
+            return status.is_stopped("test")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_stopped, timeout=3.0)
+
+    assert output_file_path.exists()
+    content = output_file_path.read_text().strip()
+    assert content == "test"
+
+
+def test_env_restart_count(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    output_file_path = tmp_path / "output"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "/bin/echo $SVLOPP_RESTART_COUNT >> {output_file_path}"]
+on_exit = "Restart"
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    def has_test_run_multiple_times():
+        try:
+            return len(output_file_path.read_text().strip().splitlines()) > 2
+        except FileNotFoundError:
+            return False
+
+    wait_until(has_test_run_multiple_times, timeout=4.0)
+
+    lines = output_file_path.read_text().strip().splitlines()
+    assert lines[:3] == ["0", "1", "2"]
@@ -0,0 +1,180 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+import time
+
+from constants import CONFIG_FILE_NAME
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+
+ENV_STALE = "env_stale"
+
+
+def _running(run_dir, name):
+    try:
+        status = read_status(run_dir)
+        if not status.is_running(name):
+            return None
+        return status.get(name)
+    except (FileNotFoundError, KeyError):
+        return None
+
+
+def _log_lines(path):
+    try:
+        return path.read_text().splitlines()
+    except FileNotFoundError:
+        return []
+
+
+def _replace(path, content):
+    # written aside then renamed, as rotation tools do
+    tmp = path.with_name(path.name + ".tmp")
+    tmp.write_text(content)
+    os.rename(tmp, path)
+
+
+def test_env_file_variables(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test.log"
+    env_file = tmp_path / "test.env"
+    env_file.write_text(
+        "# credentials\n\nTOKEN = 'abc def'\nUSER=\"svc\"\nOVERRIDDEN=file\n"
+    )
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo $TOKEN-$USER-$OVERRIDDEN"]
+log_file_path = "{log_file_path}"
+env_file = "{env_file}"
+env = {{ OVERRIDDEN = "env" }}
+"""
+    )
+
+    svlopp_proc(config_path)
+
+    wait_until(lambda: _log_lines(log_file_path) != [], timeout=3.0)
+    assert _log_lines(log_file_path) == ["abc def-svc-env"]
+
+
+def test_env_file_change_marks_stale(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test.log"
+    env_file = tmp_path / "secrets" / "test.env"
+    env_file.parent.mkdir()
+    env_file.write_text("TOKEN=first\n")
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo $TOKEN; exec sleep 10"]
+log_file_path = "{log_file_path}"
+env_file = "{env_file}"
+"""
+    )
+
+    svlopp_proc(config_path)
+    wait_until(lambda: _log_lines(log_file_path) == ["first"], timeout=3.0)
+    test = _running(run_dir, "test")
+    assert test.flags == []
+
+    _replace(env_file, "TOKEN=second\n")
+    wait_until(lambda: _running(run_dir, "test").flags == [ENV_STALE], timeout=2.0)
+    assert _running(run_dir, "test").pid_or_reason == test.pid_or_reason
+
+
+def test_env_file_same_content_not_stale(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test.log"
+    env_file = tmp_path / "secrets" / "test.env"
+    env_file.parent.mkdir()
+    env_file.write_text("TOKEN=first\n")
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo $TOKEN; exec sleep 10"]
+log_file_path = "{log_file_path}"
+env_file = "{env_file}"
+"""
+    )
+
+    svlopp_proc(config_path)
+    wait_until(lambda: _log_lines(log_file_path) == ["first"], timeout=3.0)
+
+    _replace(env_file, "# rewritten\nTOKEN=first\n")
+    # leave time for the event to be handled
+    time.sleep(0.5)
+    assert _running(run_dir, "test").flags == []
+
+
+def test_env_file_change_restarts(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test.log"
+    env_file = tmp_path / "secrets" / "test.env"
+    env_file.parent.mkdir()
+    env_file.write_text("TOKEN=first\n")
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo $TOKEN; exec sleep 10"]
+log_file_path = "{log_file_path}"
+env_file = "{env_file}"
+on_env_file_change = "restart"
+"""
+    )
+
+    svlopp_proc(config_path)
+    wait_until(lambda: _log_lines(log_file_path) == ["first"], timeout=3.0)
+    test = _running(run_dir, "test")
+
+    _replace(env_file, "TOKEN=second\n")
+    wait_until(lambda: _log_lines(log_file_path) == ["first", "second"], timeout=3.0)
+    wait_until(lambda: _running(run_dir, "test") is not None, timeout=2.0)
+    restarted = _running(run_dir, "test")
+    assert restarted.pid_or_reason != test.pid_or_reason
+    assert restarted.flags == []
+
+
+def test_env_file_too_large(tmp_path, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    env_file = tmp_path / "test.env"
+    env_file.write_text("TOKEN=" + "x" * 1024 * 1024 + "\n")
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/true"
+env_file = "{env_file}"
+"""
+    )
+
+    proc = svlopp_proc(config_path, "--strict-config")
+    assert proc.wait(timeout=2.0) != 0
+    stderr = proc.stderr.read().decode()
+    assert f"env_file '{env_file}' is larger than 1048576 bytes" in stderr
+
+
+def test_env_file_relative_path(tmp_path, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/true"
+env_file = "test.env"
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+    assert proc.wait(timeout=2.0) != 0
+    stderr = proc.stderr.read().decode()
+    assert "service 'test': env_file 'test.env' is not an absolute path" in stderr
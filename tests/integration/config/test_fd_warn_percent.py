@@ -0,0 +1,78 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import time
+
+from constants import CONFIG_FILE_NAME
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+
+FDS_HIGH = "fds_high"
+
+# 8 descriptors open out of 16: stdin, stdout, stderr and 3 to 7
+OPEN_FDS = (
+    "ulimit -n 16; "
+    "exec 3</dev/null 4</dev/null 5</dev/null 6</dev/null 7</dev/null; "
+    "exec sleep 10"
+)
+
+
+def _flags(run_dir, name):
+    try:
+        status = read_status(run_dir)
+        if not status.is_running(name):
+            return None
+        return status.get(name).flags
+    except (FileNotFoundError, KeyError):
+        return None
+
+
+def test_fd_warn_percent_reached(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "{OPEN_FDS}"]
+fd_warn_percent = 50
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+    wait_until(lambda: _flags(run_dir, "test") == [FDS_HIGH], timeout=3.0)
+
+
+def test_fd_warn_percent_not_reached(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "{OPEN_FDS}"]
+fd_warn_percent = 75
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+    wait_until(lambda: _flags(run_dir, "test") is not None, timeout=3.0)
+    # leave time for a few ticks
+    time.sleep(0.5)
+    assert _flags(run_dir, "test") == []
+
+
+def test_fd_warn_percent_above_100(tmp_path, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "{OPEN_FDS}"]
+fd_warn_percent = 101
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+    assert proc.wait(timeout=2.0) != 0
+    stderr = proc.stderr.read().decode()
+    assert "service 'test': fd_warn_percent 101 is above 100" in stderr
@@ -0,0 +1,122 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+import stat
+
+import pytest
+
+from constants import CONFIG_FILE_NAME, STATUS_FILE_NAME
+from helpers.cgroup import has_controller, make_cgroup, remove_cgroup
+from helpers.status_file import read_status
+from helpers.utils import is_running, wait_until
+
+
+def _block_device():
+    for name in sorted(os.listdir("/dev")):
+        path = f"/dev/{name}"
+        try:
+            st = os.stat(path)
+        except OSError:
+            continue
+        if stat.S_ISBLK(st.st_mode):
+            return path, f"{os.major(st.st_rdev)}:{os.minor(st.st_rdev)}"
+    pytest.skip("needs a block device")
+
+
+def test_io_max(tmp_path, run_dir, svlopp_proc):
+    device, numbers = _block_device()
+    _, cgroup_dir = make_cgroup()
+    if not has_controller(cgroup_dir, "io"):
+        remove_cgroup(cgroup_dir)
+        pytest.skip("needs the io controller")
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+io_max = [{{ device = "{device}", rbps = 1048576, wiops = 100 }}]
+"""
+    )
+
+    proc = svlopp_proc(config_path, cgroup=cgroup_dir)
+    try:
+        wait_until(lambda: is_running(run_dir, "test"), timeout=2.0)
+        lines = (cgroup_dir / "test" / "io.max").read_text().splitlines()
+        assert lines == [f"{numbers} rbps=1048576 wbps=max riops=max wiops=100"]
+    finally:
+        proc.terminate()
+        proc.wait(timeout=5)
+        remove_cgroup(cgroup_dir)
+
+
+def test_io_max_not_a_block_device(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+io_max = [{ device = "/dev/null", rbps = 1048576 }]
+"""
+    )
+    _, cgroup_dir = make_cgroup()
+
+    proc = svlopp_proc(config_path, cgroup=cgroup_dir)
+    try:
+        # the first start is attempted before signals are handled
+        wait_until(lambda: (run_dir / STATUS_FILE_NAME).exists(), timeout=2.0)
+        assert read_status(run_dir).is_stopped("test")
+        proc.terminate()
+        proc.wait(timeout=5)
+        # the cgroup created for the start is removed
+        assert not (cgroup_dir / "test").exists()
+    finally:
+        proc.terminate()
+        proc.wait(timeout=5)
+        remove_cgroup(cgroup_dir)
+
+    stderr = proc.stderr.read().decode()
+    # warned about when the config is loaded, as the device may come later
+    assert "service 'test': io_max device '/dev/null' is not a block device" in stderr
+    assert (
+        "failed to start service 'test': io_max device '/dev/null' is not a block device"
+        in stderr
+    )
+
+
+def test_io_max_needs_a_limit(tmp_path, svlopp_proc):
+    device, _ = _block_device()
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+io_max = [{{ device = "{device}" }}]
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+    assert proc.wait(timeout=2.0) != 0
+    stderr = proc.stderr.read().decode()
+    assert f"service 'test': io_max of device '{device}' sets no limit" in stderr
+
+
+def test_io_max_relative_device(tmp_path, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+io_max = [{ device = "sda", wbps = 1048576 }]
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+    assert proc.wait(timeout=2.0) != 0
+    stderr = proc.stderr.read().decode()
+    assert "service 'test': io_max device 'sda' is not an absolute path" in stderr
@@ -0,0 +1,112 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import ctypes
+import os
+import sys
+
+import pytest
+
+from constants import CONFIG_FILE_NAME
+from helpers.utils import wait_until
+
+SYS_KEYCTL = {"x86_64": 250, "aarch64": 219}
+KEYCTL_GET_KEYRING_ID = 0
+KEY_SPEC_SESSION_KEYRING = -3
+
+# prints the id of the session keyring of the service process
+SCRIPT = (
+    "import ctypes, sys; "
+    "print(ctypes.CDLL(None).syscall({sys_keyctl}, {get_id}, {session}, 0), flush=True)"
+)
+
+
+def _session_keyring():
+    machine = os.uname().machine
+    if machine not in SYS_KEYCTL:
+        pytest.skip(f"keyctl number unknown on {machine}")
+    libc = ctypes.CDLL(None, use_errno=True)
+    keyring = libc.syscall(
+        SYS_KEYCTL[machine], KEYCTL_GET_KEYRING_ID, KEY_SPEC_SESSION_KEYRING, 0
+    )
+    if keyring < 0:
+        pytest.skip(f"keyrings unavailable: {os.strerror(ctypes.get_errno())}")
+    return keyring
+
+
+def _keyrings(tmp_path, svlopp_proc, extra="", args=(), options=""):
+    """The session keyrings of two services, by name"""
+    script = SCRIPT.format(
+        sys_keyctl=SYS_KEYCTL[os.uname().machine],
+        get_id=KEYCTL_GET_KEYRING_ID,
+        session=KEY_SPEC_SESSION_KEYRING,
+    )
+    config = options
+    for name in ["a", "b"]:
+        config += f"""
+[services.{name}]
+command = "{sys.executable}"
+args = ["-c", "{script}"]
+log_file_path = "{tmp_path / name}.log"
+{extra}
+"""
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(config)
+    svlopp_proc(config_path, *args)
+
+    def read(name):
+        try:
+            return (tmp_path / f"{name}.log").read_text().strip()
+        except FileNotFoundError:
+            return ""
+
+    wait_until(lambda: read("a") and read("b"), timeout=3.0)
+    return int(read("a")), int(read("b"))
+
+
+def test_keyring_private(tmp_path, run_dir, svlopp_proc):
+    ours = _session_keyring()
+
+    a, b = _keyrings(tmp_path, svlopp_proc)
+
+    assert a > 0 and b > 0
+    assert a != ours
+    assert b != ours
+    assert a != b
+
+
+def test_keyring_inherit(tmp_path, run_dir, svlopp_proc):
+    ours = _session_keyring()
+
+    a, b = _keyrings(tmp_path, svlopp_proc, 'keyring = "inherit"')
+
+    assert a == b == ours
+
+
+def test_keyring_private_with_spawner(tmp_path, run_dir, svlopp_proc):
+    ours = _session_keyring()
+
+    a, b = _keyrings(tmp_path, svlopp_proc, args=["--spawner"])
+
+    assert ours not in (a, b)
+    assert a != b
+
+
+def test_keyring_inherit_by_default_with_posix_spawn(tmp_path, run_dir, svlopp_proc):
+    ours = _session_keyring()
+
+    options = 'spawn_strategy = "posix_spawn"\n'
+    a, b = _keyrings(tmp_path, svlopp_proc, options=options)
+
+    assert a == b == ours
+
+
+def test_keyring_private_with_posix_spawn(tmp_path, run_dir, svlopp_proc):
+    ours = _session_keyring()
+
+    options = 'spawn_strategy = "posix_spawn"\n'
+    a, b = _keyrings(tmp_path, svlopp_proc, 'keyring = "private"', options=options)
+
+    assert ours not in (a, b)
+    assert a != b
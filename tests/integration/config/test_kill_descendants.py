@@ -0,0 +1,77 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+import signal
+import time
+from pathlib import Path
+
+from constants import CONFIG_FILE_NAME, STOP_OPCODE
+from helpers.control_fifo import send_control_op
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+
+
+def _stop_service(run_dir, pid_path):
+    def is_running():
+        try:
+            return read_status(run_dir).is_running("test") and pid_path.exists()
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_running, timeout=1.0)
+    # descendants are tracked on timerfd ticks
+    time.sleep(1.5)
+
+    svc_id = read_status(run_dir).get("test").service_id
+    send_control_op(run_dir, STOP_OPCODE, svc_id)
+    wait_until(lambda: read_status(run_dir).is_stopped("test"), timeout=2.0)
+
+    return int(pid_path.read_text())
+
+
+def test_kill_descendants(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    pid_path = tmp_path / "descendant.pid"
+
+    # the background process leaves the service process group, so that
+    # only `kill_descendants` can reach it
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "setsid /bin/sleep 30 & echo $! > {pid_path}; exec sleep 10"]
+kill_descendants = true
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    pid = _stop_service(run_dir, pid_path)
+    wait_until(lambda: not Path(f"/proc/{pid}").exists(), timeout=2.0)
+
+
+def test_kill_descendants_disabled(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    pid_path = tmp_path / "descendant.pid"
+
+    # the background process leaves the service process group, so that
+    # only `kill_descendants` can reach it
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "setsid /bin/sleep 30 & echo $! > {pid_path}; exec sleep 10"]
+kill_descendants = false
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    pid = _stop_service(run_dir, pid_path)
+    try:
+        time.sleep(0.5)
+        assert Path(f"/proc/{pid}").exists()
+    finally:
+        os.kill(pid, signal.SIGKILL)
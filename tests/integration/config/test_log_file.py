@@ -142,3 +142,39 @@ log_file_path = "{log_file_path}"
     content = log_file_path.read_text().strip().splitlines()
     assert stdout_msg in content
     assert stderr_msg in content
+
+
+def test_log_file_fifo_reader_stalled(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test_log"
+    os.mkfifo(log_file_path)
+    # keep a reader open that never reads, so that the FIFO fills up
+    reader = os.open(log_file_path, os.O_RDONLY | os.O_NONBLOCK)
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "head -c 200000 /dev/zero | tr '\\\\0' 'x' | fold -w 100"]
+log_file_path = "{log_file_path}"
+
+[services.test.log_prefix]
+stream = true
+"""
+    )
+
+    try:
+        _ = svlopp_proc(config_path)
+
+        def is_test_stopped():
+            try:
+                status = read_status(run_dir)
+                return status.is_stopped("test")
+            except (FileNotFoundError, KeyError):
+                return False
+
+        # output that doesn't fit in the FIFO is dropped rather than
+        # blocking the supervisor
+        wait_until(is_test_stopped, timeout=15.0)
+    finally:
+        os.close(reader)
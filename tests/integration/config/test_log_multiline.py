@@ -0,0 +1,102 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+from constants import CONFIG_FILE_NAME
+from helpers.utils import wait_until
+from helpers.status_file import read_status
+
+
+def _wait_log_content(run_dir, log_file_path, expected):
+    def is_test_done():
+        try:
+            status = read_status(run_dir)
+            return status.is_stopped("test") and log_file_path.read_text() == expected
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_done, timeout=3.0)
+
+
+def test_log_multiline_leading_whitespace(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test_log"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "printf 'start\\\\n  at a\\\\n\\\\tat b\\\\nend\\\\n'"]
+log_file_path = "{log_file_path}"
+
+[services.test.log_prefix]
+stream = true
+
+[services.test.log_multiline]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    _wait_log_content(
+        run_dir, log_file_path, "[stdout] start\n  at a\n\tat b\n[stdout] end\n"
+    )
+
+
+def test_log_multiline_regex(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test_log"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "printf 'start\\\\n  at a\\\\nCaused by: x\\\\n  at b\\\\nend\\\\n'"]
+log_file_path = "{log_file_path}"
+
+[services.test.log_prefix]
+service_name = true
+
+[services.test.log_multiline]
+continuation_regex = "^(\\\\s|Caused by:)"
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    _wait_log_content(
+        run_dir,
+        log_file_path,
+        "[test] start\n  at a\nCaused by: x\n  at b\n[test] end\n",
+    )
+
+
+def test_log_multiline_flushed_while_running(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test_log"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "printf 'start\\\\n  at a\\\\n'; exec sleep 10"]
+log_file_path = "{log_file_path}"
+
+[services.test.log_multiline]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    # the record is written out once it stops growing, without waiting
+    # for the next line or for the service to exit
+    def is_record_written():
+        try:
+            return log_file_path.read_text() == "start\n  at a\n"
+        except FileNotFoundError:
+            return False
+
+    wait_until(is_record_written, timeout=4.0)
+
+    status = read_status(run_dir)
+    assert status.is_running("test")
@@ -0,0 +1,145 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import re
+
+from constants import CONFIG_FILE_NAME
+from helpers.utils import wait_until
+from helpers.status_file import read_status
+
+
+def _wait_test_stopped_and_flushed(run_dir, log_file_path, expected_lines):
+    def is_test_done():
+        try:
+            status = read_status(run_dir)
+            if not status.is_stopped("test"):
+                return False
+            return len(log_file_path.read_text().splitlines()) >= expected_lines
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_done, timeout=3.0)
+
+
+def test_log_prefix_service_name_and_stream(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test_log"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo out; echo err >&2"]
+log_file_path = "{log_file_path}"
+
+[services.test.log_prefix]
+service_name = true
+stream = true
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    _wait_test_stopped_and_flushed(run_dir, log_file_path, 2)
+
+    lines = log_file_path.read_text().splitlines()
+    assert sorted(lines) == ["[test][stderr] err", "[test][stdout] out"]
+
+
+def test_log_prefix_unix_timestamp(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test_log"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo hello"]
+log_file_path = "{log_file_path}"
+
+[services.test.log_prefix]
+timestamp = "unix"
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    _wait_test_stopped_and_flushed(run_dir, log_file_path, 1)
+
+    content = log_file_path.read_text()
+    assert re.fullmatch(r"\[\d+\.\d{9}\] hello\n", content)
+
+
+def test_log_prefix_rfc3339_timestamp(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test_log"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo hello"]
+log_file_path = "{log_file_path}"
+
+[services.test.log_prefix]
+timestamp = "rfc3339"
+service_name = true
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    _wait_test_stopped_and_flushed(run_dir, log_file_path, 1)
+
+    content = log_file_path.read_text()
+    assert re.fullmatch(
+        r"\[\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}\.\d{9}Z\]\[test\] hello\n", content
+    )
+
+
+def test_log_prefix_empty_is_raw(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test_log"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "printf 'first\\\\nsecond'"]
+log_file_path = "{log_file_path}"
+
+[services.test.log_prefix]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    _wait_test_stopped_and_flushed(run_dir, log_file_path, 2)
+
+    # the trailing partial line is terminated once the pipe is closed
+    assert log_file_path.read_text() == "first\nsecond\n"
+
+
+def test_log_prefix_many_lines_keep_order(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test_log"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "seq 1 5000"]
+log_file_path = "{log_file_path}"
+
+[services.test.log_prefix]
+stream = true
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    _wait_test_stopped_and_flushed(run_dir, log_file_path, 5000)
+
+    lines = log_file_path.read_text().splitlines()
+    assert lines == [f"[stdout] {i}" for i in range(1, 5001)]
@@ -0,0 +1,126 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+from constants import CONFIG_FILE_NAME
+from helpers.utils import wait_until
+from helpers.status_file import read_status
+
+
+def test_log_rate_limit_suppresses_excess_lines(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test_log"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "i=0; while [ $i -lt 100 ]; do echo line $i; i=$((i+1)); done"]
+log_file_path = "{log_file_path}"
+log_rate_limit = {{ lines = 10, interval = "60s" }}
+
+[services.test.log_prefix]
+stream = true
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    def is_test_done():
+        try:
+            status = read_status(run_dir)
+            return status.is_stopped("test") and "suppressed" in log_file_path.read_text()
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_done, timeout=3.0)
+
+    lines = log_file_path.read_text().splitlines()
+    assert lines == [f"[stdout] line {i}" for i in range(10)] + [
+        "[svlopp] suppressed 90 lines (log_rate_limit exceeded)"
+    ]
+
+
+def test_log_rate_limit_new_interval(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test_log"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo a; echo b; echo c; sleep 1; echo d"]
+log_file_path = "{log_file_path}"
+log_rate_limit = {{ lines = 2, interval = "500ms" }}
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    def is_test_done():
+        try:
+            status = read_status(run_dir)
+            return status.is_stopped("test") and "d" in log_file_path.read_text()
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_done, timeout=4.0)
+
+    lines = log_file_path.read_text().splitlines()
+    assert lines == [
+        "a",
+        "b",
+        "suppressed 1 lines (log_rate_limit exceeded)",
+        "d",
+    ]
+
+
+def test_log_rate_limit_notice_on_tick(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test_log"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo a; echo b; echo c; exec sleep 10"]
+log_file_path = "{log_file_path}"
+log_rate_limit = {{ lines = 2, interval = "1s" }}
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    def is_notice_written():
+        try:
+            return "suppressed" in log_file_path.read_text()
+        except FileNotFoundError:
+            return False
+
+    # written once the window is over, without another line to trigger it
+    wait_until(is_notice_written, timeout=4.0)
+
+    assert read_status(run_dir).is_running("test")
+    assert log_file_path.read_text().splitlines() == [
+        "a",
+        "b",
+        "suppressed 1 lines (log_rate_limit exceeded)",
+    ]
+
+
+def test_log_rate_limit_invalid_interval(tmp_path, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "exit 0"]
+log_rate_limit = { lines = 2, interval = "10" }
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    assert proc.wait(timeout=2.0) != 0
+    assert "invalid interval '10'" in proc.stderr.read().decode()
@@ -0,0 +1,98 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import gzip
+
+from constants import CONFIG_FILE_NAME
+from helpers.utils import wait_until
+from helpers.status_file import read_status
+
+
+def _rotated(tmp_path, suffix=""):
+    return sorted(
+        p
+        for p in tmp_path.glob("test_log.*")
+        if p.name.endswith(suffix) and not p.name.endswith(".tmp")
+    )
+
+
+def test_log_rotate_keeps_newest(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test_log"
+
+    # each line is 10 bytes, so every `echo` rotates the file
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "for i in 1 2 3 4 5; do echo line-0000$i; sleep 0.05; done"]
+log_file_path = "{log_file_path}"
+log_rotate = {{ max_bytes = 10, keep = 2 }}
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    def is_test_stopped():
+        try:
+            status = read_status(run_dir)
+            return status.is_stopped("test")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_stopped, timeout=3.0)
+
+    rotated = _rotated(tmp_path)
+    assert [p.read_text() for p in rotated] == ["line-00004\n", "line-00005\n"]
+    assert log_file_path.read_text() == ""
+
+
+def test_log_rotate_gzip(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test_log"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo first; sleep 0.1; echo second"]
+log_file_path = "{log_file_path}"
+log_rotate = {{ max_bytes = 4, compress = "gzip", compression_level = 9 }}
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    def are_logs_compressed():
+        try:
+            status = read_status(run_dir)
+            return (
+                status.is_stopped("test")
+                and len(_rotated(tmp_path, ".gz")) == 2
+                and len(_rotated(tmp_path)) == 2
+            )
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(are_logs_compressed, timeout=3.0)
+
+    contents = [gzip.decompress(p.read_bytes()) for p in _rotated(tmp_path, ".gz")]
+    assert contents == [b"first\n", b"second\n"]
+
+
+def test_log_rotate_zero_max_bytes_rejected(tmp_path, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/true"
+log_file_path = "{tmp_path / "test_log"}"
+log_rotate = {{ max_bytes = 0 }}
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+    assert proc.wait(timeout=2.0) != 0
+    stderr = proc.stderr.read().decode()
+    assert "service 'test': log_rotate max_bytes must be positive" in stderr
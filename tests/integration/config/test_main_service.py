@@ -0,0 +1,107 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import time
+
+from constants import CONFIG_FILE_NAME
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+
+
+def test_main_service_exit_shuts_down(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    sidecar_path = tmp_path / "sidecar_stopped"
+
+    config_path.write_text(
+        f"""
+main_service = "app"
+
+[services.app]
+command = "/bin/sh"
+args = ["-c", "sleep 0.5; exit 7"]
+
+[services.sidecar]
+command = "/bin/sh"
+args = ["-c", "trap 'touch {sidecar_path}; exit 0' TERM; while true; do sleep 0.1; done"]
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    assert proc.wait(timeout=5.0) == 7
+    assert sidecar_path.exists()
+
+
+def test_main_service_restarted_keeps_running(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    output_path = tmp_path / "output"
+
+    config_path.write_text(
+        f"""
+main_service = "app"
+
+[services.app]
+command = "/bin/sh"
+args = ["-c", "echo run >> {output_path}; exit 1"]
+on_exit = "Restart"
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    def has_restarted():
+        try:
+            return len(output_path.read_text().splitlines()) > 1
+        except FileNotFoundError:
+            return False
+
+    wait_until(has_restarted, timeout=3.0)
+    assert proc.poll() is None
+
+
+def test_main_service_exit_code_on_shutdown(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+main_service = "app"
+
+[services.app]
+command = "/bin/sh"
+args = ["-c", "trap 'exit 5' TERM; while true; do sleep 0.1; done"]
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    def is_running():
+        try:
+            return read_status(run_dir).is_running("app")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_running, timeout=1.0)
+    # give the shell time to install its trap
+    time.sleep(0.2)
+
+    proc.terminate()
+    assert proc.wait(timeout=5.0) == 5
+
+
+def test_main_service_unknown_rejected(tmp_path, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+main_service = "missing"
+
+[services.app]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    assert proc.wait(timeout=2.0) != 0
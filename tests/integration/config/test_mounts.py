@@ -0,0 +1,137 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import errno
+import os
+
+import pytest
+
+from constants import CONFIG_FILE_NAME, REASON_SPAWN_FAILED, REASON_SUCCESS
+from helpers.jail import make_jail
+from helpers.utils import is_stopped, wait_until
+from helpers.status_file import read_status
+
+
+def _data_dirs(tmp_path):
+    if os.geteuid() != 0:
+        pytest.skip("needs root to mount")
+    src = tmp_path / "src"
+    dst = tmp_path / "dst"
+    src.mkdir()
+    dst.mkdir()
+    (src / "data").write_text("hello\n")
+    return src, dst
+
+
+def _run_mounted(tmp_path, run_dir, svlopp_proc, ro, *extra_args):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test.log"
+    src, dst = _data_dirs(tmp_path)
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "cat {dst}/data; echo written > {dst}/new"]
+log_file_path = "{log_file_path}"
+mounts = [{{ src = "{src}", dst = "{dst}", ro = {str(ro).lower()} }}]
+"""
+    )
+
+    _ = svlopp_proc(config_path, *extra_args)
+    wait_until(lambda: is_stopped(run_dir, "test"), timeout=3.0)
+
+    assert log_file_path.read_text().splitlines()[0] == "hello"
+    # the mount isn't seen outside of the service namespace
+    assert not (dst / "data").exists()
+    return src, read_status(run_dir).get("test").pid_or_reason
+
+
+def test_mount(tmp_path, run_dir, svlopp_proc):
+    src, reason = _run_mounted(tmp_path, run_dir, svlopp_proc, False)
+    assert reason == REASON_SUCCESS
+    assert (src / "new").read_text() == "written\n"
+
+
+def test_mount_read_only(tmp_path, run_dir, svlopp_proc):
+    src, reason = _run_mounted(tmp_path, run_dir, svlopp_proc, True)
+    assert reason != REASON_SUCCESS
+    assert not (src / "new").exists()
+
+
+def test_mount_with_spawner(tmp_path, run_dir, svlopp_proc):
+    src, reason = _run_mounted(tmp_path, run_dir, svlopp_proc, True, "--spawner")
+    assert reason != REASON_SUCCESS
+    assert not (src / "new").exists()
+
+
+def test_mount_in_root_dir(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test.log"
+    src, _ = _data_dirs(tmp_path)
+    jail = make_jail(tmp_path)
+    (jail / "data").mkdir()
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "read line < /data/data; echo $line; test -e {src} || echo pivoted"]
+log_file_path = "{log_file_path}"
+root_dir = "{jail}"
+mounts = [{{ src = "{src}", dst = "/data", ro = true }}]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+    wait_until(lambda: is_stopped(run_dir, "test"), timeout=3.0)
+
+    assert read_status(run_dir).get("test").pid_or_reason == REASON_SUCCESS
+    assert log_file_path.read_text().splitlines() == ["hello", "pivoted"]
+    assert not (jail / "data/data").exists()
+
+
+def test_mount_missing_source(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    _, dst = _data_dirs(tmp_path)
+    missing = tmp_path / "missing"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/true"
+mounts = [{{ src = "{missing}", dst = "{dst}", ro = true }}]
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+    wait_until(lambda: is_stopped(run_dir, "test"), timeout=3.0)
+    assert (
+        read_status(run_dir).get("test").pid_or_reason
+        == f"{REASON_SPAWN_FAILED}({errno.ENOENT})"
+    )
+    proc.terminate()
+    proc.wait(timeout=2.0)
+    stderr = proc.stderr.read().decode()
+    assert (
+        f"service 'test' failed to start: mount({missing} on {dst}, ro) failed: ENOENT"
+        in stderr
+    )
+
+
+def test_mount_relative_path_fails_load(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/true"
+mounts = [{ src = "data", dst = "/data" }]
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+    assert proc.wait(timeout=2.0) != 0
+    stderr = proc.stderr.read().decode()
+    assert "service 'test': mount path 'data' is not an absolute path" in stderr
@@ -0,0 +1,102 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import time
+
+from constants import CONFIG_FILE_NAME, ORPHANS_FILE_NAME
+from helpers.cgroup import make_cgroup, remove_cgroup
+from helpers.utils import wait_until
+from helpers.status_file import read_status
+
+# the inner shell exits right away, orphaning its background subshell
+# which is then adopted and reaped by svlopp
+ORPHANING_SCRIPT = "sh -c '(sleep 0.3; exit 7) &'; exec sleep 10"
+# the orphan is forked by setsid in a session, and process group, of its own
+SESSION_ORPHANING_SCRIPT = "setsid -f sh -c 'sleep 0.3; exit 7'; exec sleep 10"
+
+
+def test_orphan_policy_track(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        f"""
+orphan_policy = "track"
+
+[services.test]
+command = "/bin/sh"
+args = ["-c", "{ORPHANING_SCRIPT}"]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    orphans_path = run_dir / ORPHANS_FILE_NAME
+    wait_until(orphans_path.exists, timeout=3.0)
+
+    lines = orphans_path.read_text().splitlines()
+    assert len(lines) == 1
+    pid, service, exit_reason, _cgroup = lines[0].split()
+    assert int(pid) > 0
+    assert service == "test"
+    assert exit_reason == "exited(7)"
+    assert read_status(run_dir).is_running("test")
+
+
+def test_orphan_policy_default_log(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "{ORPHANING_SCRIPT}"]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    def is_test_running():
+        try:
+            status = read_status(run_dir)
+            return status.is_running("test")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_running, timeout=1.0)
+    time.sleep(1.0)
+
+    assert not (run_dir / ORPHANS_FILE_NAME).exists()
+
+
+def test_orphan_policy_track_by_cgroup(tmp_path, run_dir, svlopp_proc):
+    cgroup, cgroup_dir = make_cgroup()
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        f"""
+orphan_policy = "track"
+
+[services.test]
+command = "/bin/sh"
+args = ["-c", "{SESSION_ORPHANING_SCRIPT}"]
+env = {{ PATH = "/usr/bin:/bin" }}
+cgroup_delegate = true
+"""
+    )
+
+    proc = svlopp_proc(config_path, cgroup=cgroup_dir)
+    try:
+        orphans_path = run_dir / ORPHANS_FILE_NAME
+        wait_until(orphans_path.exists, timeout=3.0)
+
+        lines = orphans_path.read_text().splitlines()
+        assert len(lines) == 1
+        _pid, service, exit_reason, orphan_cgroup = lines[0].split()
+        assert service == "test"
+        assert exit_reason == "exited(7)"
+        assert orphan_cgroup == f"{cgroup}/test"
+    finally:
+        proc.terminate()
+        proc.wait(timeout=5)
+        remove_cgroup(cgroup_dir)
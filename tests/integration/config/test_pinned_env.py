@@ -0,0 +1,153 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import json
+import os
+import signal
+from pathlib import Path
+
+from constants import CONFIG_FILE_NAME, READY_SOCKET_NAME
+from helpers.utils import wait_until
+from helpers.status_file import read_status
+
+RELOAD_FILE_NAME = "reload"
+
+CLEAR_ENV_PATH = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin"
+
+
+def _service_env(tmp_path, run_dir, svlopp_proc, options, service="") -> dict:
+    """Run a service dumping its environment, return it"""
+    config_path = tmp_path / CONFIG_FILE_NAME
+    output_path = tmp_path / "output"
+    config_path.write_text(
+        f"""
+{options}
+
+[services.test]
+command = "/usr/bin/env"
+log_file_path = "{output_path}"
+{service}
+"""
+    )
+    svlopp_proc(config_path)
+
+    def is_test_stopped():
+        try:
+            return read_status(run_dir).is_stopped("test")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_stopped, timeout=3.0)
+    return dict(line.split("=", 1) for line in output_path.read_text().splitlines())
+
+
+def test_pinned_env_overrides_inherited(tmp_path, run_dir, svlopp_proc):
+    path = f"{tmp_path}/bin:/usr/bin:/bin"
+    env = _service_env(
+        tmp_path,
+        run_dir,
+        svlopp_proc,
+        f'pinned_env = {{ TZ = "Europe/Rome", LANG = "C.UTF-8", PATH = "{path}" }}',
+    )
+    assert env["TZ"] == "Europe/Rome"
+    assert env["LANG"] == "C.UTF-8"
+    assert env["PATH"] == path
+    # the rest is still inherited
+    assert env["HOME"] == os.environ["HOME"]
+
+
+def test_pinned_env_service_env_wins(tmp_path, run_dir, svlopp_proc):
+    env = _service_env(
+        tmp_path,
+        run_dir,
+        svlopp_proc,
+        'pinned_env = { TZ = "UTC", LANG = "C.UTF-8" }',
+        'env = { TZ = "Asia/Tokyo" }',
+    )
+    assert env["TZ"] == "Asia/Tokyo"
+    assert env["LANG"] == "C.UTF-8"
+    assert "HOME" not in env
+
+
+def test_clear_env_minimal(tmp_path, run_dir, svlopp_proc):
+    env = _service_env(tmp_path, run_dir, svlopp_proc, "", "clear_env = true")
+    assert env == {
+        "PATH": CLEAR_ENV_PATH,
+        "LANG": "C",
+        "SVLOPP_SERVICE_NAME": "test",
+        "SVLOPP_SERVICE_ID": env["SVLOPP_SERVICE_ID"],
+        "SVLOPP_RESTART_COUNT": "0",
+        "SVLOPP_NOTIFY_SOCKET": str(run_dir / READY_SOCKET_NAME),
+    }
+
+
+def test_clear_env_with_env_and_pinned(tmp_path, run_dir, svlopp_proc):
+    env = _service_env(
+        tmp_path,
+        run_dir,
+        svlopp_proc,
+        'pinned_env = { TZ = "UTC", LANG = "C.UTF-8" }',
+        'clear_env = true\nenv = { FOO = "BAR" }',
+    )
+    assert env["FOO"] == "BAR"
+    assert env["TZ"] == "UTC"
+    assert env["LANG"] == "C.UTF-8"
+    assert env["PATH"] == CLEAR_ENV_PATH
+    assert "HOME" not in env
+
+
+def test_pinned_env_change_restarts_on_reload(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config = """
+pinned_env = {{ TZ = "{tz}" }}
+
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    config_path.write_text(config.format(tz="UTC"))
+    proc = svlopp_proc(config_path)
+
+    def is_test_running():
+        try:
+            return read_status(run_dir).is_running("test")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_running, timeout=2.0)
+    pid = read_status(run_dir).get("test").pid_or_reason
+
+    config_path.write_text(config.format(tz="Europe/Rome"))
+    os.kill(proc.pid, signal.SIGHUP)
+
+    report_path = run_dir / RELOAD_FILE_NAME
+    wait_until(report_path.exists, timeout=2.0)
+    report = json.loads(report_path.read_text())
+    assert report["ok"] is True
+    [change] = report["changes"]
+    assert change["service"] == "test"
+    assert change["fields"] == ["pinned_env"]
+
+    def is_restarted():
+        return is_test_running() and read_status(run_dir).get("test").pid_or_reason != pid
+
+    wait_until(is_restarted, timeout=3.0)
+    new_pid = read_status(run_dir).get("test").pid_or_reason
+    environ = Path(f"/proc/{new_pid}/environ").read_text().split("\0")
+    assert "TZ=Europe/Rome" in environ
+
+
+def test_pinned_env_nul_byte(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+pinned_env = { LANG = "C\\u0000" }
+
+[services.test]
+command = "/bin/true"
+"""
+    )
+    proc = svlopp_proc(config_path)
+    assert proc.wait(timeout=2.0) != 0
+    assert ":2:16: pinned LANG contains a nul byte" in proc.stderr.read().decode()
@@ -0,0 +1,149 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+import signal
+
+import pytest
+
+from constants import CONFIG_FILE_NAME, REASON_ERROR, REASON_SUCCESS
+from helpers.utils import is_running, is_stopped, wait_until
+from helpers.status_file import read_status
+
+PRIVATE_TMP_DIR_NAME = "tmp.d"
+
+
+def _is_mounted(path):
+    with open("/proc/self/mountinfo") as f:
+        return any(line.split()[4] == str(path) for line in f)
+
+
+def test_private_tmp(tmp_path, run_dir, svlopp_proc):
+    if os.geteuid() != 0:
+        pytest.skip("needs root to mount")
+    config_path = tmp_path / CONFIG_FILE_NAME
+    name = f"svlopp-private-{os.getpid()}"
+    log_file_path = tmp_path / "test.log"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo hidden > /tmp/{name}; ls /tmp"]
+log_file_path = "{log_file_path}"
+private_tmp = true
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+    wait_until(lambda: is_stopped(run_dir, "test"), timeout=3.0)
+
+    assert read_status(run_dir).get("test").pid_or_reason == REASON_SUCCESS
+    assert log_file_path.read_text().split() == [name]
+    assert not os.path.exists(f"/tmp/{name}")
+
+
+def test_private_tmp_removed_on_stop(tmp_path, run_dir, svlopp_proc):
+    if os.geteuid() != 0:
+        pytest.skip("needs root to mount")
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo leaked > /tmp/leaked; exec sleep 10"]
+private_tmp = true
+"""
+    )
+
+    backing = run_dir / PRIVATE_TMP_DIR_NAME / "test"
+
+    _ = svlopp_proc(config_path)
+    wait_until(lambda: is_running(run_dir, "test"), timeout=2.0)
+    wait_until(lambda: (backing / "leaked").exists(), timeout=2.0)
+    assert _is_mounted(backing)
+
+    os.kill(int(read_status(run_dir).get("test").pid_or_reason), signal.SIGKILL)
+    wait_until(lambda: is_stopped(run_dir, "test"), timeout=2.0)
+    assert not backing.exists()
+    assert not _is_mounted(backing)
+
+
+def test_private_tmp_size(tmp_path, run_dir, svlopp_proc):
+    if os.geteuid() != 0:
+        pytest.skip("needs root to mount")
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test.log"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "head -c 2097152 /dev/zero > /tmp/big"]
+log_file_path = "{log_file_path}"
+private_tmp = true
+private_tmp_size_mib = 1
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+    wait_until(lambda: is_stopped(run_dir, "test"), timeout=3.0)
+
+    assert read_status(run_dir).get("test").pid_or_reason.startswith(REASON_ERROR)
+    assert "No space left on device" in log_file_path.read_text()
+
+
+def test_private_tmp_left_by_killed_supervisor(tmp_path, run_dir, svlopp_proc):
+    if os.geteuid() != 0:
+        pytest.skip("needs root to mount")
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "exec sleep 10"]
+private_tmp = true
+"""
+    )
+
+    backing = run_dir / PRIVATE_TMP_DIR_NAME / "test"
+
+    proc = svlopp_proc(config_path)
+    wait_until(lambda: is_running(run_dir, "test"), timeout=2.0)
+    pid = int(read_status(run_dir).get("test").pid_or_reason)
+    os.kill(proc.pid, signal.SIGKILL)
+    proc.wait(timeout=2.0)
+    os.kill(pid, signal.SIGKILL)
+    assert _is_mounted(backing)
+
+    def is_restarted():
+        try:
+            status = read_status(run_dir).get("test")
+        except (FileNotFoundError, KeyError):
+            return False
+        return status.pid_or_reason not in (str(pid), "never_started")
+
+    proc = svlopp_proc(config_path)
+    wait_until(is_restarted, timeout=2.0)
+    assert read_status(run_dir).is_running("test")
+    with open("/proc/self/mountinfo") as f:
+        assert sum(line.split()[4] == str(backing) for line in f) == 1
+
+
+def test_private_tmp_size_needs_private_tmp(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/true"
+private_tmp_size_mib = 16
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+    assert proc.wait(timeout=2.0) != 0
+    stderr = proc.stderr.read().decode()
+    assert "service 'test': private_tmp_size_mib needs private_tmp" in stderr
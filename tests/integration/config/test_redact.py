@@ -0,0 +1,62 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+from constants import CONFIG_FILE_NAME
+from helpers.utils import wait_until
+
+DIAGNOSTICS_DIR_NAME = "diagnostics"
+
+
+def _log_lines(path):
+    try:
+        return path.read_text().splitlines()
+    except FileNotFoundError:
+        return []
+
+
+def test_redact_process_gets_real_values(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test.log"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo $1 $2 $3 $TOKEN; exec sleep 10", "sh", "--password", "hunter2", "--token=abc"]
+env = {{ TOKEN = "abc" }}
+log_file_path = "{log_file_path}"
+redact = ["--password", "--token", "TOKEN"]
+"""
+    )
+
+    svlopp_proc(config_path)
+
+    wait_until(lambda: _log_lines(log_file_path) != [], timeout=3.0)
+    assert _log_lines(log_file_path) == ["--password hunter2 --token=abc abc"]
+
+
+def test_redact_diagnostics_bundle(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "exit 1", "sh", "--password", "hunter2", "--token=abc"]
+env = {{ TOKEN = "abc" }}
+redact = ["--password", "--token", "TOKEN"]
+on_exit = "Restart"
+start_limit = {{ burst = 2, interval_ms = 60000 }}
+"""
+    )
+
+    diagnostics_dir = run_dir / DIAGNOSTICS_DIR_NAME
+    svlopp_proc(config_path)
+
+    wait_until(lambda: list(diagnostics_dir.glob("test-*.txt")), timeout=5.0)
+    (bundle,) = diagnostics_dir.glob("test-*.txt")
+    content = bundle.read_text()
+    assert "command: /bin/sh -c exit 1 sh --password *** --token=***" in content
+    assert "hunter2" not in content
+    assert "abc" not in content
@@ -0,0 +1,94 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import time
+
+from constants import CONFIG_FILE_NAME, REASON_SUPERVISOR_TERMINATED
+from helpers.status_file import read_status
+from helpers.utils import status_matches, wait_until
+
+
+def _runs(output_path):
+    try:
+        return len(output_path.read_text().splitlines())
+    except FileNotFoundError:
+        return 0
+
+
+def test_max_rss_stops_service(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "sleep"
+args = ["60"]
+resource_limits = { max_rss_kib = 1, samples = 2, action = "stop" }
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    wait_until(
+        status_matches(
+            run_dir,
+            lambda s: s.get("test").pid_or_reason.startswith(
+                REASON_SUPERVISOR_TERMINATED
+            ),
+        ),
+        timeout=5.0,
+    )
+
+
+def test_max_cpu_percent_restarts_service(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    output_path = tmp_path / "output"
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo run >> {output_path}; while :; do :; done"]
+resource_limits = {{ max_cpu_percent = 50, samples = 2, action = "restart" }}
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    wait_until(lambda: _runs(output_path) >= 2, timeout=8.0)
+    wait_until(status_matches(run_dir, lambda s: s.is_running("test")), timeout=2.0)
+
+
+def test_log_action_keeps_service_running(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "sleep"
+args = ["60"]
+resource_limits = { max_rss_kib = 1, samples = 1 }
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    wait_until(status_matches(run_dir, lambda s: s.is_running("test")), timeout=2.0)
+    pid = read_status(run_dir).get("test").pid_or_reason
+    time.sleep(2.5)
+
+    assert read_status(run_dir).get("test").pid_or_reason == pid
+
+
+def test_resource_limits_need_a_limit(tmp_path, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "sleep"
+args = ["60"]
+resource_limits = { action = "stop" }
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    assert proc.wait(timeout=2.0) != 0
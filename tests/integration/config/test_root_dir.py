@@ -0,0 +1,114 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+from constants import CONFIG_FILE_NAME, REASON_SUCCESS
+from helpers.jail import make_jail
+from helpers.utils import is_stopped, wait_until
+from helpers.status_file import read_status
+
+
+def _run_in_jail(tmp_path, run_dir, svlopp_proc, *extra_args):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    jail = make_jail(tmp_path)
+    (jail / "work").mkdir()
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "sh"
+args = ["-c", "pwd > out; test -e /work && echo jailed >> out"]
+root_dir = "{jail}"
+working_directory = "/work"
+env = {{ PATH = "/bin" }}
+"""
+    )
+
+    _ = svlopp_proc(config_path, *extra_args)
+    wait_until(lambda: is_stopped(run_dir, "test"), timeout=3.0)
+
+    assert (jail / "work/out").read_text().splitlines() == ["/work", "jailed"]
+    assert read_status(run_dir).get("test").pid_or_reason == REASON_SUCCESS
+
+
+def test_root_dir(tmp_path, run_dir, svlopp_proc):
+    _run_in_jail(tmp_path, run_dir, svlopp_proc)
+
+
+def test_root_dir_with_spawner(tmp_path, run_dir, svlopp_proc):
+    _run_in_jail(tmp_path, run_dir, svlopp_proc, "--spawner")
+
+
+def test_root_dir_no_notify_socket(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    jail = make_jail(tmp_path)
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo $SVLOPP_SERVICE_NAME ${{SVLOPP_NOTIFY_SOCKET-unset}} > /out"]
+root_dir = "{jail}"
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+    wait_until(lambda: is_stopped(run_dir, "test"), timeout=3.0)
+
+    assert (jail / "out").read_text().strip() == "test unset"
+
+
+def test_root_dir_missing_fails_load(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    jail = tmp_path / "jail"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+root_dir = "{jail}"
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+    assert proc.wait(timeout=2.0) != 0
+    stderr = proc.stderr.read().decode()
+    assert f"service 'test': root_dir '{jail}' is not a directory" in stderr
+
+
+def test_root_dir_relative_fails_load(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sh"
+root_dir = "jail"
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+    assert proc.wait(timeout=2.0) != 0
+    stderr = proc.stderr.read().decode()
+    assert "service 'test': root_dir 'jail' is not an absolute path" in stderr
+
+
+def test_root_dir_without_dev_null(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    jail = make_jail(tmp_path, dev_null=False)
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "true"]
+root_dir = "{jail}"
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+    wait_until(lambda: is_stopped(run_dir, "test"), timeout=3.0)
+    proc.terminate()
+    proc.wait(timeout=2.0)
+    stderr = proc.stderr.read().decode()
+    assert f"failed to start service 'test': {jail}/dev/null:" in stderr
@@ -0,0 +1,74 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import time
+
+from constants import CONFIG_FILE_NAME, REASON_RUNTIME_EXCEEDED
+from helpers.status_file import read_status
+from helpers.utils import status_matches, wait_until
+
+
+def _runtime_exceeded(status):
+    return status.get("test").pid_or_reason.startswith(REASON_RUNTIME_EXCEEDED)
+
+
+def test_runtime_max_stops_service(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "sleep"
+args = ["60"]
+runtime_max_ms = 1500
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    wait_until(status_matches(run_dir, lambda s: s.is_running("test")), timeout=2.0)
+    wait_until(status_matches(run_dir, _runtime_exceeded), timeout=5.0)
+    status = read_status(run_dir).get("test")
+    assert status.pid_or_reason == "runtime_exceeded(signaled(15))"
+
+
+def test_runtime_max_escalates_to_sigkill(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "trap '' TERM; while :; do sleep 0.1; done"]
+runtime_max_ms = 1000
+stop_timeout_ms = 1000
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    wait_until(status_matches(run_dir, _runtime_exceeded), timeout=6.0)
+    status = read_status(run_dir).get("test")
+    assert status.pid_or_reason == "runtime_exceeded(signaled(9))"
+
+
+def test_runtime_max_restart(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    output_path = tmp_path / "output"
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo run >> {output_path}; exec sleep 60"]
+runtime_max_ms = 1000
+on_exit = "Restart"
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    wait_until(
+        lambda: output_path.exists() and len(output_path.read_text().splitlines()) >= 2,
+        timeout=6.0,
+    )
+    time.sleep(0.2)
+    assert read_status(run_dir).is_running("test")
@@ -0,0 +1,62 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import time
+
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+from constants import CONFIG_FILE_NAME
+
+SECRET = "hunter2"
+
+
+def test_secret_memfd(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    secret_path = tmp_path / "secret"
+    secret_path.write_text(SECRET)
+    output_path = tmp_path / "output"
+
+    script = (
+        f"cat /proc/self/fd/$DB_PASSWORD_FD > {output_path}.secret; "
+        f"echo x >> /proc/self/fd/$DB_PASSWORD_FD && echo writable >> {output_path}; "
+        f"env > {output_path}.env; "
+        f"echo done >> {output_path}"
+    )
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", '{script}']
+secrets = [{{ env = "DB_PASSWORD_FD", path = "{secret_path}" }}]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    wait_until(lambda: output_path.exists(), timeout=2.0)
+
+    assert output_path.read_text() == "done\n"
+    assert (tmp_path / "output.secret").read_text() == SECRET
+    env = (tmp_path / "output.env").read_text()
+    assert "DB_PASSWORD_FD=" in env
+    assert SECRET not in env
+
+
+def test_secret_unreadable_fails_start(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+secrets = [{{ env = "TOKEN_FD", path = "{tmp_path / "missing"}" }}]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    wait_until(lambda: (run_dir / "status").exists(), timeout=2.0)
+    time.sleep(1.0)
+
+    assert read_status(run_dir).get("test").pid_or_reason == "never_started"
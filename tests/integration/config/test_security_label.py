@@ -0,0 +1,104 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import subprocess
+
+import pytest
+
+from constants import CONFIG_FILE_NAME
+from helpers.utils import is_running, wait_until
+
+PLATFORM_FILE_NAME = "platform"
+
+
+def _start(svlopp_proc, config_path):
+    """Start svlopp, skipping the test if it's built without labels"""
+    proc = svlopp_proc(config_path)
+    try:
+        proc.wait(timeout=0.5)
+    except subprocess.TimeoutExpired:
+        return proc, None
+    stderr = proc.stderr.read().decode()
+    if "`lsm` feature" in stderr:
+        pytest.skip("svlopp built without the lsm feature")
+    return proc, stderr
+
+
+def _enabled(run_dir, module):
+    lines = (run_dir / PLATFORM_FILE_NAME).read_text().splitlines()
+    return f"{module} yes" in lines
+
+
+def test_security_labels_mutually_exclusive(tmp_path, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+selinux_context = "system_u:system_r:svc_t:s0"
+apparmor_profile = "svc"
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+    assert proc.wait(timeout=2.0) != 0
+    stderr = proc.stderr.read().decode()
+    assert "selinux_context and apparmor_profile are mutually exclusive" in stderr
+
+
+def test_selinux_context_empty(tmp_path, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+selinux_context = ""
+"""
+    )
+
+    _, stderr = _start(svlopp_proc, config_path)
+    assert stderr is not None
+    assert "service 'test': invalid selinux_context ''" in stderr
+
+
+def _check_ignored(run_dir, svlopp_proc, config_path, key, module):
+    proc, stderr = _start(svlopp_proc, config_path)
+    assert stderr is None, stderr
+    wait_until(lambda: is_running(run_dir, "test"), timeout=2.0)
+    if _enabled(run_dir, module.lower()):
+        pytest.skip(f"{module} is enabled")
+    proc.terminate()
+    proc.wait(timeout=5.0)
+    stderr = proc.stderr.read().decode()
+    assert f"service 'test': {key} ignored, {module} isn't enabled" in stderr
+
+
+def test_selinux_context_ignored_without_selinux(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+selinux_context = "system_u:system_r:svc_t:s0"
+"""
+    )
+
+    _check_ignored(run_dir, svlopp_proc, config_path, "selinux_context", "SELinux")
+
+
+def test_apparmor_profile_ignored_without_apparmor(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+apparmor_profile = "svc"
+"""
+    )
+
+    _check_ignored(run_dir, svlopp_proc, config_path, "apparmor_profile", "AppArmor")
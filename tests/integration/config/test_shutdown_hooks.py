@@ -0,0 +1,76 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+from constants import CONFIG_FILE_NAME
+from helpers.utils import wait_until
+from helpers.status_file import read_status
+
+
+def test_shutdown_hooks(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_path = tmp_path / "hooks.log"
+    service_pid_path = tmp_path / "service.pid"
+
+    # each hook records its name, whether the run directory still exists
+    # and whether the service process is still alive
+    hook_script = (
+        f'echo "$SVLOPP_HOOK $(test -d {run_dir} && echo dir || echo nodir) '
+        f'$(kill -0 $(cat {service_pid_path}) 2>/dev/null && echo alive || echo dead)" '
+        f">> {log_path}"
+    )
+
+    config_path.write_text(
+        f"""
+[on_all_stopped]
+command = "/bin/sh"
+args = ["-c", '{hook_script}']
+
+[on_shutdown_complete]
+command = "/bin/sh"
+args = ["-c", '{hook_script}']
+
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo $$ > {service_pid_path}; exec sleep 10"]
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    def is_running():
+        try:
+            return read_status(run_dir).is_running("test") and service_pid_path.exists()
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_running, timeout=1.0)
+
+    proc.terminate()
+    assert proc.wait(timeout=5.0) == 0
+
+    assert log_path.read_text().splitlines() == [
+        "on_all_stopped dir dead",
+        "on_shutdown_complete nodir dead",
+    ]
+
+
+def test_shutdown_hooks_not_run_without_shutdown(tmp_path, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_path = tmp_path / "hooks.log"
+
+    # the config is invalid, svlopp exits before supervising anything
+    config_path.write_text(
+        f"""
+start_concurrency = 0
+
+[on_shutdown_complete]
+command = "/bin/sh"
+args = ["-c", "echo ran > {log_path}"]
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    assert proc.wait(timeout=2.0) != 0
+    assert not log_path.exists()
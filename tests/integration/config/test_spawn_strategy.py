@@ -0,0 +1,105 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import errno
+import time
+
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+from constants import CONFIG_FILE_NAME, REASON_SPAWN_FAILED
+
+SECRET = "hunter2"
+
+
+def _check_starts_services(tmp_path, run_dir, svlopp_proc, strategy):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    secret_path = tmp_path / "secret"
+    secret_path.write_text(SECRET)
+    work_dir = tmp_path / "work"
+    work_dir.mkdir()
+    log_path = tmp_path / "log"
+
+    script = "cat /proc/self/fd/$TOKEN_FD; echo; pwd; echo $PPID; sleep 10"
+    config_path.write_text(
+        f"""
+spawn_strategy = "{strategy}"
+
+[services.test]
+command = "/bin/sh"
+args = ["-c", '{script}']
+working_directory = "{work_dir}"
+log_file_path = "{log_path}"
+secrets = [{{ env = "TOKEN_FD", path = "{secret_path}" }}]
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    wait_until(
+        lambda: log_path.exists() and len(log_path.read_text().splitlines()) == 3,
+        timeout=2.0,
+    )
+    assert log_path.read_text() == f"{SECRET}\n{work_dir}\n{proc.pid}\n"
+    assert read_status(run_dir).is_running("test")
+
+
+def _check_missing_command(tmp_path, run_dir, svlopp_proc, strategy, reason, extra=""):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        f"""
+spawn_strategy = "{strategy}"
+
+[services.test]
+command = "svlopp-missing-command"
+{extra}
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    wait_until(lambda: (run_dir / "status").exists(), timeout=2.0)
+    time.sleep(0.5)
+
+    assert read_status(run_dir).get("test").pid_or_reason == reason
+
+
+def test_spawn_strategy_fork(tmp_path, run_dir, svlopp_proc):
+    _check_starts_services(tmp_path, run_dir, svlopp_proc, "fork")
+
+
+def test_spawn_strategy_posix_spawn(tmp_path, run_dir, svlopp_proc):
+    _check_starts_services(tmp_path, run_dir, svlopp_proc, "posix_spawn")
+
+
+def test_spawn_strategy_clone3(tmp_path, run_dir, svlopp_proc):
+    _check_starts_services(tmp_path, run_dir, svlopp_proc, "clone3")
+
+
+def test_spawn_strategy_fork_missing_command(tmp_path, run_dir, svlopp_proc):
+    _check_missing_command(
+        tmp_path, run_dir, svlopp_proc, "fork", f"{REASON_SPAWN_FAILED}({errno.ENOENT})"
+    )
+
+
+def test_spawn_strategy_clone3_missing_command(tmp_path, run_dir, svlopp_proc):
+    _check_missing_command(
+        tmp_path, run_dir, svlopp_proc, "clone3", f"{REASON_SPAWN_FAILED}({errno.ENOENT})"
+    )
+
+
+def test_spawn_strategy_posix_spawn_missing_command(tmp_path, run_dir, svlopp_proc):
+    # the exec failure is reported by posix_spawn itself, failing the start
+    _check_missing_command(tmp_path, run_dir, svlopp_proc, "posix_spawn", "never_started")
+
+
+def test_spawn_strategy_posix_spawn_private_keyring(tmp_path, run_dir, svlopp_proc):
+    # forked, the exec failure is reported as with fork
+    _check_missing_command(
+        tmp_path,
+        run_dir,
+        svlopp_proc,
+        "posix_spawn",
+        f"{REASON_SPAWN_FAILED}({errno.ENOENT})",
+        'keyring = "private"',
+    )
@@ -0,0 +1,54 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+from constants import CONFIG_FILE_NAME
+from helpers.utils import wait_until
+from helpers.status_file import read_status
+
+SERVICES_COUNT = 40
+
+
+def test_start_concurrency_starts_all(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    services = "".join(
+        f"""
+[services.test{i}]
+command = "/bin/sleep"
+args = ["10"]
+"""
+        for i in range(SERVICES_COUNT)
+    )
+    config_path.write_text("start_concurrency = 3\n" + services)
+
+    _ = svlopp_proc(config_path)
+
+    def are_all_running():
+        try:
+            status = read_status(run_dir)
+            return all(
+                status.is_running(f"test{i}") for i in range(SERVICES_COUNT)
+            )
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(are_all_running, timeout=3.0)
+
+
+def test_start_concurrency_zero_rejected(tmp_path, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+start_concurrency = 0
+
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    assert proc.wait(timeout=2.0) != 0
@@ -0,0 +1,153 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import time
+
+from constants import CONFIG_FILE_NAME
+from helpers.clock import stepped_clock
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+
+RESTARTS_FILE_NAME = "restarts"
+DIAGNOSTICS_DIR_NAME = "diagnostics"
+
+
+def _write_flapping_config(config_path, output_path):
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo run >> {output_path}; exit 1"]
+on_exit = "Restart"
+start_limit = {{ burst = 3, interval_ms = 60000 }}
+"""
+    )
+
+
+def _runs(output_path):
+    try:
+        return len(output_path.read_text().splitlines())
+    except FileNotFoundError:
+        return 0
+
+
+def test_start_limit_stops_restarts(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    output_path = tmp_path / "output"
+    _write_flapping_config(config_path, output_path)
+
+    _ = svlopp_proc(config_path)
+
+    wait_until(lambda: _runs(output_path) == 3, timeout=5.0)
+    # restarts happen on timerfd ticks
+    time.sleep(2.0)
+
+    assert _runs(output_path) == 3
+    assert read_status(run_dir).get("test").pid_or_reason == "error(1)"
+
+
+def test_start_limit_survives_restart(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    output_path = tmp_path / "output"
+    state_dir = tmp_path / "state"
+    _write_flapping_config(config_path, output_path)
+
+    def restarts():
+        try:
+            return (state_dir / RESTARTS_FILE_NAME).read_text().split()
+        except FileNotFoundError:
+            return []
+
+    proc = svlopp_proc(config_path, "--state-dir", str(state_dir))
+    wait_until(lambda: len(restarts()) == 5, timeout=5.0)
+    proc.terminate()
+    assert proc.wait(timeout=5.0) == 0
+
+    name, start_count, *failures = restarts()
+    assert name == "test"
+    assert start_count == "3"
+    assert len(failures) == 3
+
+    # the failure budget is not reset: the first failure after the restart
+    # hits the limit again
+    _ = svlopp_proc(config_path, "--state-dir", str(state_dir))
+    wait_until(lambda: _runs(output_path) == 4, timeout=2.0)
+    time.sleep(2.0)
+
+    assert _runs(output_path) == 4
+    assert (state_dir / RESTARTS_FILE_NAME).read_text().split()[1] == "4"
+
+
+def test_start_limit_shifted_on_clock_step(tmp_path, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    output_path = tmp_path / "output"
+    state_dir = tmp_path / "state"
+    _write_flapping_config(config_path, output_path)
+
+    def failures():
+        try:
+            return [int(t) for t in (state_dir / RESTARTS_FILE_NAME).read_text().split()[2:]]
+        except FileNotFoundError:
+            return []
+
+    _ = svlopp_proc(config_path, "--state-dir", str(state_dir))
+    wait_until(lambda: len(failures()) == 3, timeout=5.0)
+    before = failures()
+
+    # the failures keep their distance from the current time, so that
+    # the window doesn't end an hour early once the clock is set forward
+    with stepped_clock(-3600):
+        wait_until(lambda: failures() != before, timeout=3.0)
+        shifted = failures()
+
+    assert all(abs(b - 3600000 - s) < 1000 for b, s in zip(before, shifted))
+    wait_until(lambda: failures() != shifted, timeout=3.0)
+    assert all(abs(b - f) < 1000 for b, f in zip(before, failures()))
+
+
+def test_start_limit_burst_too_large(tmp_path, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+start_limit = { burst = 65, interval_ms = 1000 }
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    assert proc.wait(timeout=2.0) != 0
+
+
+def test_start_limit_writes_diagnostics(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_path = tmp_path / "test.log"
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo boom; exit 1"]
+on_exit = "Restart"
+log_file_path = "{log_path}"
+start_limit = {{ burst = 2, interval_ms = 60000 }}
+"""
+    )
+    diagnostics_dir = run_dir / DIAGNOSTICS_DIR_NAME
+
+    _ = svlopp_proc(config_path)
+
+    wait_until(lambda: list(diagnostics_dir.glob("test-*.txt")), timeout=5.0)
+    (bundle,) = diagnostics_dir.glob("test-*.txt")
+    lines = bundle.read_text().splitlines()
+    assert lines[0] == "service: test"
+    assert "command: /bin/sh -c echo boom; exit 1" in lines
+    assert "start_limit: 2 failures within 60000ms" in lines
+    exits = [line for line in lines if " exited error(1) after " in line]
+    assert len(exits) == 2
+    assert all(" user " in line and " sys " in line for line in exits)
+    assert len([line for line in lines if " started pid " in line]) == 2
+    assert "  +SVLOPP_SERVICE_NAME" in lines
+    assert lines[lines.index("log:") + 1 :] == ["boom", "boom"]
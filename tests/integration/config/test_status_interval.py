@@ -0,0 +1,92 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+import signal
+import time
+
+from constants import CONFIG_FILE_NAME, STATUS_FILE_NAME
+from helpers.inotify import IN_MOVED_TO, Inotify
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+
+SERVICES_COUNT = 20
+
+
+def _wait_all_running(run_dir):
+    def are_all_running():
+        try:
+            status = read_status(run_dir)
+            return all(
+                status.is_running(f"test{i}") for i in range(SERVICES_COUNT)
+            )
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(are_all_running, timeout=3.0)
+
+
+def test_status_interval_coalesces_writes(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    services = "".join(
+        f"""
+[services.test{i}]
+command = "/bin/sleep"
+args = ["10"]
+"""
+        for i in range(SERVICES_COUNT)
+    )
+    config_path.write_text("status_interval_ms = 1000\n" + services)
+
+    _ = svlopp_proc(config_path)
+    _wait_all_running(run_dir)
+    status = read_status(run_dir)
+
+    inotify = Inotify(run_dir, IN_MOVED_TO)
+    try:
+        for i in range(SERVICES_COUNT):
+            os.kill(int(status.get(f"test{i}").pid_or_reason), signal.SIGKILL)
+            time.sleep(0.01)
+        events = inotify.read_events(timeout=2.5)
+    finally:
+        inotify.close()
+
+    status_events = [mask for mask, name in events if name == STATUS_FILE_NAME]
+    # the exits are spread over a couple hundred milliseconds, at most one
+    # write per interval
+    assert 1 <= len(status_events) <= 2
+    status = read_status(run_dir)
+    assert all(status.is_stopped(f"test{i}") for i in range(SERVICES_COUNT))
+
+
+def test_status_interval_holds_back_changes(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+status_interval_ms = 2000
+
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    def is_test_running():
+        try:
+            return read_status(run_dir).is_running("test")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_running, timeout=3.0)
+    os.kill(int(read_status(run_dir).get("test").pid_or_reason), signal.SIGKILL)
+
+    time.sleep(0.3)
+    assert read_status(run_dir).is_running("test")
+    # published once the interval has elapsed, without waiting for another
+    # change
+    wait_until(lambda: read_status(run_dir).is_stopped("test"), timeout=2.5)
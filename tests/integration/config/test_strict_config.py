@@ -0,0 +1,173 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import json
+import os
+import signal
+
+from helpers.status_file import read_status
+from helpers.utils import is_running, wait_until
+from constants import CONFIG_FILE_NAME
+
+RELOAD_FILE_NAME = "reload"
+
+TYPO_CONFIG = """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+stop_timout_ms = 100
+"""
+
+
+def _stderr_after_exit(proc) -> str:
+    proc.terminate()
+    _, stderr = proc.communicate(timeout=5)
+    return stderr.decode()
+
+
+def _strict_failure(tmp_path, svlopp_proc, config) -> str:
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(config)
+    proc = svlopp_proc(config_path, "--strict-config")
+    assert proc.wait(timeout=2.0) != 0
+    return proc.stderr.read().decode()
+
+
+def test_unknown_key_warns(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(TYPO_CONFIG)
+    proc = svlopp_proc(config_path)
+    wait_until(lambda: is_running(run_dir, "test"), timeout=2.0)
+
+    stderr = _stderr_after_exit(proc)
+    assert (
+        f"[Warn] {config_path}:5:1: unknown key 'services.test.stop_timout_ms'"
+        in stderr
+    )
+
+
+def test_strict_unknown_key(tmp_path, run_dir, svlopp_proc):
+    stderr = _strict_failure(tmp_path, svlopp_proc, TYPO_CONFIG)
+    assert "5:1: unknown key 'services.test.stop_timout_ms'" in stderr
+    assert not (run_dir / "status").exists()
+
+
+def test_strict_unknown_top_level_key(tmp_path, run_dir, svlopp_proc):
+    stderr = _strict_failure(
+        tmp_path,
+        svlopp_proc,
+        """
+max_service = 2
+
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+""",
+    )
+    assert "2:1: unknown key 'max_service'" in stderr
+
+
+def test_strict_unknown_nested_key(tmp_path, run_dir, svlopp_proc):
+    stderr = _strict_failure(
+        tmp_path,
+        svlopp_proc,
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+finish = { command = "/bin/true", arg = ["x"] }
+""",
+    )
+    assert "5:35: unknown key 'services.test.finish.arg'" in stderr
+
+
+def test_strict_relative_command(tmp_path, run_dir, svlopp_proc):
+    stderr = _strict_failure(
+        tmp_path,
+        svlopp_proc,
+        """
+[services.test]
+command = "sleep"
+args = ["10"]
+""",
+    )
+    assert "3:1: service 'test': relative command 'sleep', looked up in PATH" in stderr
+
+
+def test_strict_world_writable_secret(tmp_path, run_dir, svlopp_proc):
+    secret_path = tmp_path / "secret"
+    secret_path.write_text("hunter2")
+    os.chmod(secret_path, 0o666)
+    stderr = _strict_failure(
+        tmp_path,
+        svlopp_proc,
+        f"""
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+
+[[services.test.secrets]]
+env = "DB_PASSWORD_FD"
+path = "{secret_path}"
+""",
+    )
+    assert (
+        f"8:1: service 'test': secret file '{secret_path}' is world-writable" in stderr
+    )
+
+
+def test_strict_world_writable_env_file(tmp_path, run_dir, svlopp_proc):
+    env_file = tmp_path / "test.env"
+    env_file.write_text("TOKEN=first\n")
+    os.chmod(env_file, 0o666)
+    stderr = _strict_failure(
+        tmp_path,
+        svlopp_proc,
+        f"""
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+env_file = "{env_file}"
+""",
+    )
+    assert f"5:1: service 'test': env_file '{env_file}' is world-writable" in stderr
+
+
+def test_strict_clean_config(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+    proc = svlopp_proc(config_path, "--strict-config")
+    wait_until(lambda: is_running(run_dir, "test"), timeout=2.0)
+
+    assert "[Warn]" not in _stderr_after_exit(proc)
+
+
+def test_strict_reload_rejects_issues(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+    proc = svlopp_proc(config_path, "--strict-config")
+    wait_until(lambda: is_running(run_dir, "test"), timeout=2.0)
+    pid = read_status(run_dir).get("test").pid_or_reason
+
+    config_path.write_text(TYPO_CONFIG)
+    os.kill(proc.pid, signal.SIGHUP)
+
+    report_path = run_dir / RELOAD_FILE_NAME
+    wait_until(report_path.exists, timeout=2.0)
+    report = json.loads(report_path.read_text())
+    assert report["ok"] is False
+    assert "unknown key 'services.test.stop_timout_ms'" in report["error"]
+    assert read_status(run_dir).get("test").pid_or_reason == pid
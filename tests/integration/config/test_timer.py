@@ -0,0 +1,400 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import calendar
+import hashlib
+import time
+from pathlib import Path
+
+from constants import CONFIG_FILE_NAME
+from helpers.clock import stepped_clock
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+
+TIMERS_FILE_NAME = "timers"
+
+
+def _timer(path):
+    """The fields of the `test` timer line: next, last, missed and flags"""
+    try:
+        for line in path.read_text().splitlines():
+            name, *fields = line.split()
+            if name == "test":
+                return fields
+    except FileNotFoundError:
+        pass
+    return None
+
+
+def _missed(path):
+    fields = _timer(path)
+    return int(fields[2]) if fields is not None else 0
+
+
+def _pid(run_dir):
+    try:
+        status = read_status(run_dir)
+        if not status.is_running("test"):
+            return None
+        return status.get("test").pid_or_reason
+    except (FileNotFoundError, KeyError):
+        return None
+
+
+def _next(run_dir):
+    fields = _timer(run_dir / TIMERS_FILE_NAME)
+    return fields[0] if fields is not None else None
+
+
+def _runs(output_path):
+    try:
+        return len(output_path.read_text().split())
+    except FileNotFoundError:
+        return 0
+
+
+def _run_late(tmp_path, run_dir, svlopp_proc, output_path, timer_extra=""):
+    """Run a timer due in 30 seconds, and step the clock 35 seconds forward,
+    for svlopp to check the activation late"""
+    config_path = tmp_path / CONFIG_FILE_NAME
+    due = int(time.time()) + 30
+    on_calendar = time.strftime("%Y-%m-%d %H:%M:%S", time.gmtime(due))
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo run >> {output_path}"]
+timer = {{ on_calendar = "{on_calendar} UTC"{timer_extra} }}
+"""
+    )
+
+    svlopp_proc(config_path)
+
+    wait_until(lambda: _next(run_dir) == str(due), timeout=2.0)
+    wait_until(lambda: _runs(output_path) == 1, timeout=2.0)
+    return stepped_clock(35)
+
+
+def test_timer_starts_service(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    output_path = tmp_path / "output"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo run >> {output_path}"]
+timer = {{ on_calendar = "*:*:* UTC" }}
+"""
+    )
+
+    svlopp_proc(config_path)
+
+    # started along with svlopp, then by the timer
+    wait_until(lambda: _runs(output_path) >= 3, timeout=4.0)
+
+    next_time, last, missed = _timer(run_dir / TIMERS_FILE_NAME)
+    assert int(next_time) > int(last) > time.time() - 5
+    assert missed == "0"
+
+
+def test_timer_overlap_skip(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "exec sleep 10"]
+timer = { on_calendar = "*:*:* UTC", overlap = "skip" }
+"""
+    )
+
+    svlopp_proc(config_path)
+
+    wait_until(lambda: _pid(run_dir) is not None, timeout=3.0)
+    pid = _pid(run_dir)
+    wait_until(lambda: _missed(run_dir / TIMERS_FILE_NAME) >= 2, timeout=4.0)
+
+    assert _pid(run_dir) == pid
+    fields = _timer(run_dir / TIMERS_FILE_NAME)
+    assert fields[1] == "-"
+    assert "queued" not in fields
+
+
+def test_timer_overlap_queue(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    output_path = tmp_path / "output"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo run >> {output_path}; exec sleep 3"]
+autostart = false
+timer = {{ on_calendar = "*:*:* UTC", overlap = "queue" }}
+"""
+    )
+
+    svlopp_proc(config_path)
+
+    wait_until(lambda: _pid(run_dir) is not None, timeout=3.0)
+    wait_until(lambda: "queued" in _timer(run_dir / TIMERS_FILE_NAME), timeout=3.0)
+    wait_until(lambda: _missed(run_dir / TIMERS_FILE_NAME) >= 1, timeout=3.0)
+    # started again once the first run exits
+    wait_until(lambda: _runs(output_path) == 2, timeout=5.0)
+
+
+def test_timer_overlap_kill(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "exec sleep 10"]
+autostart = false
+timer = { on_calendar = "*:*:0/3 UTC", overlap = "kill" }
+"""
+    )
+
+    svlopp_proc(config_path)
+
+    wait_until(lambda: _pid(run_dir) is not None, timeout=5.0)
+    pid = _pid(run_dir)
+
+    def killed_and_started():
+        return _pid(run_dir) not in (None, pid)
+
+    wait_until(killed_and_started, timeout=5.0)
+    assert _missed(run_dir / TIMERS_FILE_NAME) == 0
+
+
+def test_timer_rescheduled_on_clock_step(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "exit 0"]
+timer = { on_calendar = "hourly UTC" }
+"""
+    )
+
+    svlopp_proc(config_path)
+
+    wait_until(lambda: _next(run_dir) is not None, timeout=3.0)
+    next_time = int(_next(run_dir))
+
+    # the next activation is computed from the new time, rather than
+    # waiting for the old one two hours later
+    with stepped_clock(-7200):
+        wait_until(lambda: int(_next(run_dir)) == next_time - 7200, timeout=3.0)
+
+    wait_until(lambda: int(_next(run_dir)) == next_time, timeout=3.0)
+
+
+def test_timer_missed_while_down(tmp_path, run_dir, svlopp_proc):
+    state_dir = tmp_path / "state"
+    state_dir.mkdir()
+    # the next activation was due 30 seconds ago
+    (state_dir / TIMERS_FILE_NAME).write_text(f"test {int(time.time()) - 30} - 4\n")
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "exit 0"]
+timer = { on_calendar = "2199-12-31 UTC" }
+"""
+    )
+
+    svlopp_proc(config_path, "--state-dir", str(state_dir))
+
+    wait_until(lambda: _timer(run_dir / TIMERS_FILE_NAME) is not None, timeout=3.0)
+    assert _timer(run_dir / TIMERS_FILE_NAME)[1] == "-"
+    assert _missed(run_dir / TIMERS_FILE_NAME) == 5
+
+    def persisted():
+        persisted = _timer(state_dir / TIMERS_FILE_NAME)
+        return persisted == _timer(run_dir / TIMERS_FILE_NAME)
+
+    wait_until(persisted, timeout=3.0)
+
+
+def test_timer_persistent_runs_missed(tmp_path, run_dir, svlopp_proc):
+    state_dir = tmp_path / "state"
+    state_dir.mkdir()
+    output_path = tmp_path / "output"
+    # the next activation was due 30 seconds ago
+    (state_dir / TIMERS_FILE_NAME).write_text(f"test {int(time.time()) - 30} - 4\n")
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo run >> {output_path}"]
+timer = {{ on_calendar = "2199-12-31 UTC", persistent = true }}
+"""
+    )
+
+    svlopp_proc(config_path, "--state-dir", str(state_dir))
+
+    def started():
+        fields = _timer(run_dir / TIMERS_FILE_NAME)
+        return fields is not None and fields[1] != "-"
+
+    wait_until(output_path.exists, timeout=3.0)
+    wait_until(started, timeout=3.0)
+    _next_time, last, missed = _timer(run_dir / TIMERS_FILE_NAME)
+    assert int(last) > time.time() - 5
+    assert missed == "4"
+    # the start at startup ran the missed activation
+    time.sleep(1.5)
+    assert output_path.read_text().split() == ["run"]
+
+
+def test_timer_late_activation_caught_up(tmp_path, run_dir, svlopp_proc):
+    output_path = tmp_path / "output"
+    with _run_late(tmp_path, run_dir, svlopp_proc, output_path):
+        wait_until(lambda: _runs(output_path) == 2, timeout=3.0)
+        assert _missed(run_dir / TIMERS_FILE_NAME) == 0
+
+
+def test_timer_late_activation_missed(tmp_path, run_dir, svlopp_proc):
+    output_path = tmp_path / "output"
+    with _run_late(tmp_path, run_dir, svlopp_proc, output_path, ", catch_up = false"):
+        wait_until(lambda: _missed(run_dir / TIMERS_FILE_NAME) == 1, timeout=3.0)
+        time.sleep(1.0)
+        assert _runs(output_path) == 1
+        assert _timer(run_dir / TIMERS_FILE_NAME)[1] == "-"
+        assert _next(run_dir) == "-"
+
+
+def test_timer_monotonic_clock(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    output_path = tmp_path / "output"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo run >> {output_path}"]
+timer = {{ on_calendar = "*:*:* UTC", clock = "monotonic" }}
+"""
+    )
+
+    svlopp_proc(config_path)
+
+    wait_until(lambda: _runs(output_path) >= 2, timeout=3.0)
+    assert _missed(run_dir / TIMERS_FILE_NAME) == 0
+
+
+def test_timer_invalid_clock(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "exit 0"]
+timer = { on_calendar = "*:*:* UTC", clock = "tai" }
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    assert proc.wait(timeout=2.0) != 0
+    assert "unknown variant `tai`" in proc.stderr.read().decode()
+
+
+def _delay(name, randomized_delay_ms):
+    """The delay svlopp picks for the timer of service `name`, in seconds"""
+    boot_id = Path("/proc/sys/kernel/random/boot_id").read_text().strip()
+    digest = hashlib.sha256(f"{boot_id}{name}".encode()).digest()
+    delay_ms = int.from_bytes(digest[:8], "big") % (randomized_delay_ms + 1)
+    return delay_ms // 1000
+
+
+def test_timer_randomized_delay(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "exit 0"]
+timer = { on_calendar = "2199-12-31 UTC", randomized_delay_ms = 3600000 }
+"""
+    )
+
+    svlopp_proc(config_path)
+
+    wait_until(lambda: _next(run_dir) is not None, timeout=3.0)
+    activation = calendar.timegm((2199, 12, 31, 0, 0, 0))
+    assert int(_next(run_dir)) == activation + _delay("test", 3600000)
+
+
+def test_timer_randomized_delay_applied(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    output_path = tmp_path / "output"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "date +%s >> {output_path}"]
+timer = {{ on_calendar = "*:*:0/4 UTC", randomized_delay_ms = 3000 }}
+"""
+    )
+
+    svlopp_proc(config_path)
+
+    # started along with svlopp, then by the timer
+    wait_until(lambda: _runs(output_path) >= 2, timeout=8.0)
+    started = int(output_path.read_text().split()[1])
+    # started within a tick of the delayed activation
+    assert (started - _delay("test", 3000)) % 4 in (0, 1)
+    assert (int(_next(run_dir)) - _delay("test", 3000)) % 4 == 0
+
+
+def test_timer_sub_second_delay_rejected(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "exit 0"]
+timer = { on_calendar = "*:*:* UTC", randomized_delay_ms = 500 }
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    assert proc.wait(timeout=2.0) != 0
+    stderr = proc.stderr.read().decode()
+    assert "randomized_delay_ms must be 0 or at least 1000" in stderr
+
+
+def test_timer_invalid_calendar(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "exit 0"]
+timer = { on_calendar = "Mon..Fri 25:00 UTC" }
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    assert proc.wait(timeout=2.0) != 0
+    assert "invalid timer.on_calendar" in proc.stderr.read().decode()
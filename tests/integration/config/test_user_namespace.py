@@ -0,0 +1,110 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import errno
+
+from constants import CONFIG_FILE_NAME, REASON_SPAWN_FAILED, REASON_SUCCESS
+from helpers.utils import is_stopped, wait_until
+from helpers.status_file import read_status
+
+
+def _run_mapped(tmp_path, run_dir, svlopp_proc, options="", *extra_args):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test.log"
+
+    config_path.write_text(
+        f"""{options}
+[services.test]
+command = "/bin/sh"
+args = ["-c", "cat /proc/self/uid_map /proc/self/gid_map; id -u"]
+user_group = {{ uid = 1000, gid = 1000 }}
+log_file_path = "{log_file_path}"
+
+[services.test.user_namespace]
+uid_map = [{{ inside = 0, outside = 0, count = 1 }}, {{ inside = 1000, outside = 101000, count = 10 }}]
+gid_map = [{{ inside = 0, outside = 0, count = 1 }}, {{ inside = 1000, outside = 101000, count = 10 }}]
+"""
+    )
+
+    _ = svlopp_proc(config_path, *extra_args)
+
+    def has_test_succeeded():
+        try:
+            return read_status(run_dir).get("test").pid_or_reason == REASON_SUCCESS
+        except (FileNotFoundError, KeyError):
+            return False
+
+    # the child waits for the namespace maps to be written before it execs
+    wait_until(has_test_succeeded, timeout=10.0)
+
+    lines = [line.split() for line in log_file_path.read_text().splitlines()]
+    assert lines == [
+        ["0", "0", "1"],
+        ["1000", "101000", "10"],
+        ["0", "0", "1"],
+        ["1000", "101000", "10"],
+        ["1000"],
+    ]
+
+
+def test_user_namespace(tmp_path, run_dir, svlopp_proc):
+    _run_mapped(tmp_path, run_dir, svlopp_proc)
+
+
+def test_user_namespace_clone3(tmp_path, run_dir, svlopp_proc):
+    _run_mapped(tmp_path, run_dir, svlopp_proc, 'spawn_strategy = "clone3"')
+
+
+def test_user_namespace_with_spawner(tmp_path, run_dir, svlopp_proc):
+    _run_mapped(tmp_path, run_dir, svlopp_proc, "", "--spawner")
+
+
+def test_user_namespace_invalid_map(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    # overlapping ranges are rejected by the kernel
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/true"
+
+[services.test.user_namespace]
+uid_map = [{ inside = 0, outside = 0, count = 10 }, { inside = 5, outside = 1000, count = 10 }]
+gid_map = [{ inside = 0, outside = 0, count = 1 }]
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+    wait_until(lambda: is_stopped(run_dir, "test"), timeout=3.0)
+    assert (
+        read_status(run_dir).get("test").pid_or_reason
+        == f"{REASON_SPAWN_FAILED}({errno.EINVAL})"
+    )
+    proc.terminate()
+    proc.wait(timeout=2.0)
+    stderr = proc.stderr.read().decode()
+    assert (
+        "service 'test' failed to start: user_namespace id mapping failed: EINVAL"
+        in stderr
+    )
+
+
+def test_user_namespace_empty_map_fails_load(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/true"
+
+[services.test.user_namespace]
+uid_map = [{ inside = 0, outside = 0, count = 1 }]
+gid_map = []
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+    assert proc.wait(timeout=2.0) != 0
+    stderr = proc.stderr.read().decode()
+    assert "user_namespace gid_map needs between 1 and 340 ranges" in stderr
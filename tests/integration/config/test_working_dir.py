@@ -2,8 +2,9 @@
 # License, v. 2.0. If a copy of the MPL was not distributed with this
 # file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+import errno
 
-from constants import CONFIG_FILE_NAME, REASON_ERROR, STATE_STOPPED
+from constants import CONFIG_FILE_NAME, REASON_SPAWN_FAILED, STATE_STOPPED
 from helpers.utils import wait_until
 from helpers.status_file import read_status
 
@@ -55,19 +56,50 @@ working_directory = "{working_directory}"
 
     _ = svlopp_proc(config_path)
 
-    def is_test_stopped():
+    spawn_failed = f"{REASON_SPAWN_FAILED}({errno.ENOENT})"
+
+    def has_test_failed_to_spawn():
         try:
             status = read_status(run_dir)
-            return status.is_stopped("test")
+            return status.get("test").pid_or_reason == spawn_failed
         except (FileNotFoundError, KeyError):
             return False
 
-    wait_until(is_test_stopped, timeout=3.0)
+    wait_until(has_test_failed_to_spawn, timeout=3.0)
 
     status = read_status(run_dir)
 
     test = status.get("test")
     assert test.state == STATE_STOPPED
-    assert test.pid_or_reason == f"{REASON_ERROR}(111)"
     assert not working_directory.exists()
     assert not output_file_path.exists()
+
+
+def test_working_directory_missing_reports_chdir(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    working_directory = tmp_path / "working"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/true"
+working_directory = "{working_directory}"
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    def is_test_stopped():
+        try:
+            return read_status(run_dir).is_stopped("test")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_stopped, timeout=3.0)
+
+    proc.terminate()
+    _, stderr = proc.communicate(timeout=5)
+    assert (
+        f"service 'test' failed to start: chdir({working_directory}) failed: ENOENT"
+        in stderr.decode()
+    )
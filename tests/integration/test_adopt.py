@@ -0,0 +1,152 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import subprocess
+import time
+from pathlib import Path
+
+import pytest
+
+from helpers.status_file import read_status
+from helpers.utils import service_state, start_svlopp, svloppctl, wait_until
+
+CONFIG = """
+[services.test]
+command = "/bin/sleep"
+args = ["100"]
+
+[services.daemon]
+command = "/bin/sleep"
+args = ["100"]
+autostart = false
+on_exit = "{on_exit}"
+"""
+
+
+def _names(run_dir):
+    try:
+        return {line.service_name for line in read_status(run_dir).lines}
+    except FileNotFoundError:
+        return set()
+
+
+def _start(tmp_path, run_dir, svlopp_proc, on_exit="None"):
+    config = CONFIG.format(on_exit=on_exit)
+    start_svlopp(tmp_path, run_dir, svlopp_proc, config, ["test"])
+
+
+@pytest.fixture
+def external():
+    """A process svlopp didn't start"""
+    proc = subprocess.Popen(["/bin/sleep", "100"])
+    yield proc
+    proc.kill()
+    proc.wait()
+
+
+def _adopt(run_dir, pid, name):
+    return svloppctl(run_dir, "adopt", "--pid", str(pid), "--name", name)
+
+
+def test_adopt_as_transient_service(tmp_path, run_dir, svlopp_proc, external):
+    _start(tmp_path, run_dir, svlopp_proc)
+
+    result = _adopt(run_dir, external.pid, "ext")
+    assert result.returncode == 0, result.stderr
+    assert result.stdout == f"adopted process {external.pid} as service 'ext'\n"
+    assert service_state(run_dir, "ext") == ("running", str(external.pid))
+
+    # stopped like any other, and removed once stopped
+    assert svloppctl(run_dir, "stop", "ext").returncode == 0
+    assert external.wait(timeout=5) == -15
+    wait_until(lambda: _names(run_dir) == {"test", "daemon"}, timeout=3.0)
+
+
+def test_adopt_configured_service(tmp_path, run_dir, svlopp_proc, external):
+    _start(tmp_path, run_dir, svlopp_proc)
+
+    result = _adopt(run_dir, external.pid, "daemon")
+    assert result.returncode == 0, result.stderr
+
+    external.kill()
+    external.wait()
+    wait_until(
+        lambda: service_state(run_dir, "daemon") == ("stopped", "exited"), timeout=3.0
+    )
+    # not a failure, and not removed
+    time.sleep(1.5)
+    assert service_state(run_dir, "daemon") == ("stopped", "exited")
+
+
+def test_adopted_exit_restarts(tmp_path, run_dir, svlopp_proc, external):
+    _start(tmp_path, run_dir, svlopp_proc, on_exit="Restart")
+
+    assert _adopt(run_dir, external.pid, "daemon").returncode == 0
+    external.kill()
+    external.wait()
+
+    # started by svlopp from then on
+    def restarted():
+        state, pid = service_state(run_dir, "daemon")
+        return state == "running" and pid != str(external.pid)
+
+    wait_until(restarted, timeout=5.0)
+
+
+def test_adopt_running_service_rejected(tmp_path, run_dir, svlopp_proc, external):
+    _start(tmp_path, run_dir, svlopp_proc)
+    pid = service_state(run_dir, "test")[1]
+
+    result = _adopt(run_dir, external.pid, "test")
+    assert result.returncode != 0
+    assert service_state(run_dir, "test") == ("running", pid)
+
+
+def test_adopt_unknown_pid_fails(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+
+    proc = subprocess.Popen(["/bin/true"])
+    proc.wait()
+    result = _adopt(run_dir, proc.pid, "ext")
+    assert result.returncode != 0
+    assert "didn't adopt" in result.stderr
+    assert "ext" not in _names(run_dir)
+
+
+def test_adopt_init_svlopp_and_kernel_threads_rejected(tmp_path, run_dir, svlopp_proc):
+    config = CONFIG.format(on_exit="None")
+    proc = start_svlopp(tmp_path, run_dir, svlopp_proc, config, ["test"])
+
+    # kthreadd is pid 2, where there's one
+    for pid in [1, proc.pid, 2]:
+        result = _adopt(run_dir, pid, "ext")
+        assert result.returncode != 0
+    assert "ext" not in _names(run_dir)
+    assert proc.poll() is None
+
+
+def _proc_state(pid):
+    stat = Path(f"/proc/{pid}/stat").read_text()
+    return stat.rsplit(")", 1)[1].split()[0]
+
+
+def test_pause_adopted_process_alone(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+    leader = subprocess.Popen(["/bin/sleep", "100"], process_group=0)
+    other = subprocess.Popen(["/bin/sleep", "100"], process_group=leader.pid)
+
+    try:
+        assert _adopt(run_dir, leader.pid, "ext").returncode == 0
+        assert svloppctl(run_dir, "pause", "ext").returncode == 0
+        wait_until(lambda: _proc_state(leader.pid) == "T", timeout=2.0)
+        wait_until(lambda: service_state(run_dir, "ext")[0] == "paused", timeout=2.0)
+        # the rest of its process group isn't svlopp's
+        assert _proc_state(other.pid) == "S"
+
+        assert svloppctl(run_dir, "resume", "ext").returncode == 0
+        wait_until(lambda: _proc_state(leader.pid) == "S", timeout=2.0)
+    finally:
+        for proc in [leader, other]:
+            proc.kill()
+            proc.wait()
@@ -0,0 +1,80 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import json
+import time
+
+from constants import CONFIG_FILE_NAME
+from helpers.utils import wait_until
+
+ALERT_SCRIPT = (
+    'echo "$SVLOPP_SERVICE $SVLOPP_ALERT_FROM $SVLOPP_ALERT_TO $SVLOPP_ALERT_FAILURES"'
+    ' >> {env}; cat >> {details}'
+)
+
+
+def _lines(path):
+    try:
+        return path.read_text().splitlines()
+    except FileNotFoundError:
+        return []
+
+
+def test_alert_on_repeated_failures(tmp_path, svlopp_proc):
+    env, details = tmp_path / "env", tmp_path / "details"
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        f"""
+[[alerts]]
+on = "* -> Failed"
+min_failures = 2
+command = "sh"
+args = ["-c", '{ALERT_SCRIPT.format(env=env, details=details)}']
+
+[services.test]
+command = "sh"
+args = ["-c", "sleep 0.2; exit 3"]
+on_exit = "Restart"
+"""
+    )
+    svlopp_proc(config_path)
+
+    wait_until(lambda: len(_lines(details)) >= 2, timeout=6.0)
+    first, second = _lines(env)[:2]
+    assert first == "test running failed 2"
+    assert second == "test running failed 3"
+    event = json.loads(_lines(details)[0])
+    assert event["service"] == "test"
+    assert event["from"] == "running"
+    assert event["to"] == "failed"
+    assert event["reason"] == "error(3)"
+    assert event["failures"] == 2
+
+
+def test_alert_pattern_and_service_filter(tmp_path, svlopp_proc):
+    env, details = tmp_path / "env", tmp_path / "details"
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        f"""
+[[alerts]]
+on = "running -> stopped"
+services = ["watched"]
+command = "sh"
+args = ["-c", '{ALERT_SCRIPT.format(env=env, details=details)}']
+
+[services.watched]
+command = "sh"
+args = ["-c", "sleep 0.3"]
+
+[services.ignored]
+command = "sh"
+args = ["-c", "sleep 0.3"]
+"""
+    )
+    svlopp_proc(config_path)
+
+    wait_until(lambda: len(_lines(env)) >= 1, timeout=5.0)
+    # leave time for unexpected alerts
+    time.sleep(1.0)
+    assert _lines(env) == ["watched running stopped 0"]
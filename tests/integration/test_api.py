@@ -0,0 +1,208 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import errno
+import json
+import socket
+import stat
+import subprocess
+
+import pytest
+
+from constants import CONFIG_FILE_NAME
+from helpers.status_file import read_status
+from helpers.utils import status_matches, wait_until
+
+API_SOCKET_NAME = "api.sock"
+
+CONFIG = """
+[services.test]
+command = "sh"
+args = ["-c", "for i in 1 2 3 4 5; do echo line $i; done; exec sleep 60"]
+log_file_path = "{log}"
+"""
+
+
+def _start_svlopp(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(CONFIG.format(log=tmp_path / "test.log"))
+    proc = svlopp_proc(config_path, "--api")
+    try:
+        proc.wait(timeout=0.5)
+    except subprocess.TimeoutExpired:
+        pass
+    else:
+        if b"api feature" in proc.stderr.read():
+            pytest.skip("svlopp built without the api feature")
+    wait_until(status_matches(run_dir, lambda s: s.is_running("test")), timeout=2.0)
+
+
+def _request(run_dir, method, path):
+    """Send a request and return the status code and the body"""
+    with socket.socket(socket.AF_UNIX, socket.SOCK_STREAM) as sock:
+        sock.settimeout(5)
+        sock.connect(str(run_dir / API_SOCKET_NAME))
+        sock.sendall(f"{method} {path} HTTP/1.1\r\nHost: svlopp\r\n\r\n".encode())
+        response = b""
+        while chunk := sock.recv(4096):
+            response += chunk
+    head, body = response.split(b"\r\n\r\n", 1)
+    return int(head.split(b" ")[1]), body
+
+
+def test_api_socket_owner_only(tmp_path, run_dir, svlopp_proc):
+    _start_svlopp(tmp_path, run_dir, svlopp_proc)
+
+    mode = (run_dir / API_SOCKET_NAME).stat().st_mode
+    assert stat.S_IMODE(mode) == 0o600
+
+
+def test_api_list_and_show(tmp_path, run_dir, svlopp_proc):
+    _start_svlopp(tmp_path, run_dir, svlopp_proc)
+
+    status, body = _request(run_dir, "GET", "/v1/services")
+    assert status == 200
+    [svc] = json.loads(body)
+    assert svc["name"] == "test"
+    assert svc["state"] == "running"
+    assert svc["pid"] > 0
+
+    status, body = _request(run_dir, "GET", "/v1/services/test")
+    assert status == 200
+    assert json.loads(body)["command"] == "sh"
+
+    status, body = _request(run_dir, "GET", "/v1/services/missing")
+    assert status == 404
+    assert "missing" in json.loads(body)["error"]
+
+
+def test_api_stop_and_start(tmp_path, run_dir, svlopp_proc):
+    _start_svlopp(tmp_path, run_dir, svlopp_proc)
+
+    status, _ = _request(run_dir, "POST", "/v1/services/test/stop")
+    assert status == 200
+    wait_until(status_matches(run_dir, lambda s: s.is_stopped("test")), timeout=2.0)
+
+    status, _ = _request(run_dir, "GET", "/v1/services/test/stop")
+    assert status == 405
+
+    status, body = _request(run_dir, "POST", "/v1/services/test/start")
+    assert status == 200
+    assert json.loads(body)["state"] == "running"
+    wait_until(status_matches(run_dir, lambda s: s.is_running("test")), timeout=2.0)
+
+
+def test_api_logs(tmp_path, run_dir, svlopp_proc):
+    _start_svlopp(tmp_path, run_dir, svlopp_proc)
+
+    def tail():
+        return _request(run_dir, "GET", "/v1/services/test/logs?lines=2")
+
+    wait_until(lambda: tail()[1].endswith(b"line 5\n"), timeout=2.0)
+    status, body = tail()
+    assert status == 200
+    assert body == b"line 4\nline 5\n"
+
+
+def test_api_events(tmp_path, run_dir, svlopp_proc):
+    _start_svlopp(tmp_path, run_dir, svlopp_proc)
+
+    with socket.socket(socket.AF_UNIX, socket.SOCK_STREAM) as sock:
+        sock.settimeout(5)
+        sock.connect(str(run_dir / API_SOCKET_NAME))
+        sock.sendall(b"GET /v1/events HTTP/1.1\r\n\r\n")
+        stream = sock.makefile("rb")
+        assert stream.readline().startswith(b"HTTP/1.1 200")
+        while stream.readline() != b"\r\n":
+            pass
+
+        def next_event():
+            event = {}
+            while (line := stream.readline().rstrip(b"\n")) != b"":
+                key, value = line.split(b": ", 1)
+                event[key] = value
+            return event[b"event"], json.loads(event[b"data"])
+
+        # the current state of each service comes first
+        assert next_event()[1]["state"] == "running"
+
+        status, _ = _request(run_dir, "POST", "/v1/services/test/stop")
+        assert status == 200
+        states = []
+        while "stopped" not in states:
+            kind, data = next_event()
+            assert kind == b"state"
+            assert data["name"] == "test"
+            states.append(data["state"])
+        assert states == ["stopping", "stopped"]
+
+
+def test_api_dry_run(tmp_path, run_dir, svlopp_proc):
+    _start_svlopp(tmp_path, run_dir, svlopp_proc)
+
+    status, body = _request(run_dir, "POST", "/v1/services/test/stop?dry_run=1")
+    assert status == 200
+    assert json.loads(body) == {"plan": [{"action": "stop", "service": "test"}]}
+    assert read_status(run_dir).is_running("test")
+
+
+def test_api_spawn_failure(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    missing = tmp_path / "missing"
+    config_path.write_text(
+        f"""
+[services.test]
+command = "{missing}"
+"""
+    )
+    proc = svlopp_proc(config_path, "--api")
+    try:
+        proc.wait(timeout=0.5)
+    except subprocess.TimeoutExpired:
+        pass
+    else:
+        if b"api feature" in proc.stderr.read():
+            pytest.skip("svlopp built without the api feature")
+    wait_until(status_matches(run_dir, lambda s: s.is_stopped("test")), timeout=2.0)
+
+    status, body = _request(run_dir, "GET", "/v1/services")
+    assert status == 200
+    [svc] = json.loads(body)
+    assert svc["reason"] == f"spawn_failed({errno.ENOENT})"
+    assert svc["error"] == f"exec({missing}) failed: ENOENT"
+
+
+def test_api_show_redacted(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "sh"
+args = ["-c", "exec sleep 60", "--password", "hunter2", "--token=abc", "--user=svc"]
+env = { TOKEN = "abc", USER = "svc" }
+redact = ["--password", "--token", "TOKEN"]
+"""
+    )
+    proc = svlopp_proc(config_path, "--api")
+    try:
+        proc.wait(timeout=0.5)
+    except subprocess.TimeoutExpired:
+        pass
+    else:
+        if b"api feature" in proc.stderr.read():
+            pytest.skip("svlopp built without the api feature")
+    wait_until(status_matches(run_dir, lambda s: s.is_running("test")), timeout=2.0)
+
+    status, body = _request(run_dir, "GET", "/v1/services/test")
+    assert status == 200
+    svc = json.loads(body)
+    assert svc["args"] == [
+        "-c",
+        "exec sleep 60",
+        "--password",
+        "***",
+        "--token=***",
+        "--user=svc",
+    ]
+    assert svc["env"] == {"TOKEN": "***", "USER": "svc"}
@@ -0,0 +1,137 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import json
+import shutil
+import socket
+import ssl
+import subprocess
+
+import pytest
+
+from constants import CONFIG_FILE_NAME
+from helpers.utils import status_matches, wait_until
+
+TOKEN = "s3cret-token"
+
+SLEEP_CONFIG = """
+[services.test]
+command = "sleep"
+args = ["60"]
+"""
+
+
+def _openssl(*args):
+    subprocess.run(["openssl", *args], check=True, capture_output=True)
+
+
+def _issue(tmp_path, name, ca, extensions):
+    key, csr, cert = (tmp_path / f"{name}.{ext}" for ext in ("key", "csr", "pem"))
+    ext_file = tmp_path / f"{name}.ext"
+    ext_file.write_text(extensions)
+    _openssl(
+        "req", "-new", "-newkey", "ec", "-pkeyopt", "ec_paramgen_curve:P-256",
+        "-nodes", "-keyout", str(key), "-out", str(csr), "-subj", f"/CN={name}",
+    )
+    _openssl(
+        "x509", "-req", "-in", str(csr), "-CA", str(ca[0]), "-CAkey", str(ca[1]),
+        "-CAcreateserial", "-days", "1", "-out", str(cert), "-extfile", str(ext_file),
+    )
+    return cert, key
+
+
+@pytest.fixture
+def certs(tmp_path):
+    if shutil.which("openssl") is None:
+        pytest.skip("needs openssl")
+    ca = (tmp_path / "ca.pem", tmp_path / "ca.key")
+    _openssl(
+        "req", "-x509", "-newkey", "ec", "-pkeyopt", "ec_paramgen_curve:P-256",
+        "-nodes", "-keyout", str(ca[1]), "-out", str(ca[0]), "-subj", "/CN=svlopp test CA",
+        "-days", "1",
+    )
+    server = _issue(
+        tmp_path, "server", ca, "subjectAltName=DNS:localhost,IP:127.0.0.1\n"
+    )
+    client = _issue(tmp_path, "client", ca, "extendedKeyUsage=clientAuth\n")
+    return {"ca": ca[0], "server": server, "client": client}
+
+
+def _free_port():
+    with socket.socket() as sock:
+        sock.bind(("127.0.0.1", 0))
+        return sock.getsockname()[1]
+
+
+def _start_svlopp(tmp_path, run_dir, svlopp_proc, certs):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(SLEEP_CONFIG)
+    token_file = tmp_path / "token"
+    token_file.write_text(TOKEN + "\n")
+    port = _free_port()
+    proc = svlopp_proc(
+        config_path,
+        "--api-listen", f"127.0.0.1:{port}",
+        "--api-tls-cert", str(certs["server"][0]),
+        "--api-tls-key", str(certs["server"][1]),
+        "--api-tls-client-ca", str(certs["ca"]),
+        "--api-token-file", str(token_file),
+    )
+    try:
+        proc.wait(timeout=0.5)
+    except subprocess.TimeoutExpired:
+        pass
+    else:
+        if b"tls feature" in proc.stderr.read():
+            pytest.skip("svlopp built without the tls feature")
+    wait_until(status_matches(run_dir, lambda s: s.is_running("test")), timeout=2.0)
+    return port
+
+
+def _request(port, certs, path, token=None, client_cert=True):
+    ctx = ssl.create_default_context(cafile=str(certs["ca"]))
+    if client_cert:
+        ctx.load_cert_chain(str(certs["client"][0]), str(certs["client"][1]))
+    headers = f"Authorization: Bearer {token}\r\n" if token else ""
+    with socket.create_connection(("127.0.0.1", port), timeout=5) as raw:
+        with ctx.wrap_socket(raw, server_hostname="localhost") as sock:
+            sock.sendall(f"GET {path} HTTP/1.1\r\n{headers}\r\n".encode())
+            response = b""
+            while chunk := sock.recv(4096):
+                response += chunk
+    head, body = response.split(b"\r\n\r\n", 1)
+    return int(head.split(b" ")[1]), body
+
+
+def test_api_tls_with_token(tmp_path, run_dir, svlopp_proc, certs):
+    port = _start_svlopp(tmp_path, run_dir, svlopp_proc, certs)
+
+    status, body = _request(port, certs, "/v1/services", token=TOKEN)
+    assert status == 200
+    assert json.loads(body)[0]["name"] == "test"
+
+    status, _ = _request(port, certs, "/v1/services", token="wrong")
+    assert status == 401
+    status, _ = _request(port, certs, "/v1/services")
+    assert status == 401
+
+
+def test_api_tls_requires_client_certificate(tmp_path, run_dir, svlopp_proc, certs):
+    port = _start_svlopp(tmp_path, run_dir, svlopp_proc, certs)
+
+    with pytest.raises((ssl.SSLError, ConnectionError)):
+        _request(port, certs, "/v1/services", token=TOKEN, client_cert=False)
+
+
+def test_api_unix_socket_needs_no_token(tmp_path, run_dir, svlopp_proc, certs):
+    _start_svlopp(tmp_path, run_dir, svlopp_proc, certs)
+
+    with socket.socket(socket.AF_UNIX, socket.SOCK_STREAM) as sock:
+        sock.settimeout(5)
+        sock.connect(str(run_dir / "api.sock"))
+        sock.sendall(b"GET /v1/services HTTP/1.1\r\n\r\n")
+        response = b""
+        while chunk := sock.recv(4096):
+            response += chunk
+    assert response.startswith(b"HTTP/1.1 200")
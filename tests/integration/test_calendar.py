@@ -0,0 +1,130 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+import subprocess
+from datetime import datetime, timedelta
+from zoneinfo import ZoneInfo
+
+from constants import SVLOPPCTL_BINARY_PATH
+
+# Fri 2026-03-20 09:46:40 UTC
+FROM = 1774000000
+
+
+def _calendar(expr, *extra_args, env=None):
+    return subprocess.run(
+        [SVLOPPCTL_BINARY_PATH, "calendar", expr, "--from", str(FROM), *extra_args],
+        capture_output=True,
+        text=True,
+        timeout=5,
+        env=env,
+    )
+
+
+def _times(expr, count):
+    result = _calendar(expr, "--count", str(count))
+    assert result.returncode == 0, result.stderr
+    return result.stdout.splitlines()
+
+
+def _format(dt):
+    return dt.strftime("%a %Y-%m-%d %H:%M:%S %Z")
+
+
+def test_calendar_weekdays():
+    assert _times("Mon..Fri 02:30 UTC", 3) == [
+        "Mon 2026-03-23 02:30:00 UTC",
+        "Tue 2026-03-24 02:30:00 UTC",
+        "Wed 2026-03-25 02:30:00 UTC",
+    ]
+
+
+def test_calendar_date():
+    assert _times("*-*-01 00:00:00 UTC", 3) == [
+        "Wed 2026-04-01 00:00:00 UTC",
+        "Fri 2026-05-01 00:00:00 UTC",
+        "Mon 2026-06-01 00:00:00 UTC",
+    ]
+
+
+def test_calendar_repetition():
+    assert _times("*:0/20 UTC", 3) == [
+        "Fri 2026-03-20 10:00:00 UTC",
+        "Fri 2026-03-20 10:20:00 UTC",
+        "Fri 2026-03-20 10:40:00 UTC",
+    ]
+
+
+def test_calendar_shorthands():
+    assert _times("weekly UTC", 1) == ["Mon 2026-03-23 00:00:00 UTC"]
+    assert _times("@weekly UTC", 1) == ["Sun 2026-03-22 00:00:00 UTC"]
+
+
+def test_calendar_cron():
+    assert _times("30 4 * * 1-5 UTC", 2) == [
+        "Mon 2026-03-23 04:30:00 UTC",
+        "Tue 2026-03-24 04:30:00 UTC",
+    ]
+
+
+def test_calendar_cron_day_or_weekday():
+    # the 13th of every month, and every Friday
+    assert _times("0 0 13 * fri UTC", 3) == [
+        "Fri 2026-03-27 00:00:00 UTC",
+        "Fri 2026-04-03 00:00:00 UTC",
+        "Fri 2026-04-10 00:00:00 UTC",
+    ]
+    assert _times("0 0 13 * fri UTC", 5)[3:] == [
+        "Mon 2026-04-13 00:00:00 UTC",
+        "Fri 2026-04-17 00:00:00 UTC",
+    ]
+
+
+def test_calendar_time_zone():
+    rome = ZoneInfo("Europe/Rome")
+    start = datetime(2026, 3, 21, 2, 30, tzinfo=rome)
+    # 02:30 doesn't happen on 2026-03-29, as the clock goes forward
+    expected = [
+        _format(start + timedelta(days=days)) for days in range(10) if days != 8
+    ]
+    assert _times("*-*-* 02:30 Europe/Rome", 9) == expected
+
+
+def test_calendar_time_zone_repeated_hour():
+    # 02:30 happens twice on 2026-10-25, as the clock goes back
+    result = _calendar(
+        "*-10-* 02:30 Europe/Rome", "--count", "3", "--from", "1792800000"
+    )
+    assert result.returncode == 0, result.stderr
+    assert result.stdout.splitlines() == [
+        "Sat 2026-10-24 02:30:00 CEST",
+        "Sun 2026-10-25 02:30:00 CEST",
+        "Mon 2026-10-26 02:30:00 CET",
+    ]
+
+
+def test_calendar_local_time_zone():
+    env = {**os.environ, "TZ": "America/New_York"}
+    result = _calendar("0 9 * * *", "--count", "1", env=env)
+
+    assert result.returncode == 0, result.stderr
+    assert result.stdout.splitlines() == ["Fri 2026-03-20 09:00:00 EDT"]
+
+
+def test_calendar_invalid():
+    result = _calendar("Mon..Fri 25:00")
+    assert result.returncode != 0
+    assert "invalid component '25:00'" in result.stderr
+
+    result = _calendar("daily Mars/Olympus_Mons")
+    assert result.returncode != 0
+    assert "unknown time zone 'Mars/Olympus_Mons'" in result.stderr
+
+
+def test_calendar_never():
+    result = _calendar("2026-02-30")
+
+    assert result.returncode != 0
+    assert "never triggers" in result.stderr
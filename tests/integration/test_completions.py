@@ -0,0 +1,121 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+import subprocess
+from pathlib import Path
+
+from helpers.status_file import read_service_status, read_status
+from helpers.utils import svloppctl, wait_until
+from constants import CONFIG_FILE_NAME, STATE_RUNNING, SVLOPPCTL_BINARY_PATH
+
+
+def _start_services(tmp_path, run_dir, svlopp_proc, *extra_args):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.web]
+command = "/bin/sleep"
+args = ["10"]
+
+[services.worker]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+    _ = svlopp_proc(config_path, *extra_args)
+
+    def both_running():
+        try:
+            if "--per-service-status" in extra_args:
+                return all(
+                    read_service_status(run_dir, name).state == STATE_RUNNING
+                    for name in ("web", "worker")
+                )
+            status = read_status(run_dir)
+            return status.is_running("web") and status.is_running("worker")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(both_running, timeout=2.0)
+
+
+def test_list(tmp_path, run_dir, svlopp_proc):
+    _start_services(tmp_path, run_dir, svlopp_proc)
+
+    result = svloppctl(run_dir, "list")
+
+    assert result.returncode == 0
+    assert result.stdout.splitlines() == ["web", "worker"]
+
+
+def test_list_per_service_status(tmp_path, run_dir, svlopp_proc):
+    _start_services(tmp_path, run_dir, svlopp_proc, "--per-service-status")
+
+    result = svloppctl(run_dir, "list")
+
+    assert result.returncode == 0
+    assert result.stdout.splitlines() == ["web", "worker"]
+
+
+def test_bash_completion(tmp_path, run_dir, svlopp_proc):
+    _start_services(tmp_path, run_dir, svlopp_proc)
+    script = svloppctl(None, "completions", "bash").stdout
+    bin_dir = Path(SVLOPPCTL_BINARY_PATH).resolve().parent
+
+    def complete(*words):
+        line = " ".join(["svloppctl", *words])
+        result = subprocess.run(
+            [
+                "bash",
+                "-c",
+                f"""{script}
+COMP_WORDS=({line})
+COMP_CWORD=$((${{#COMP_WORDS[@]}} - 1))
+_svloppctl
+printf '%s\\n' "${{COMPREPLY[@]}}"
+""",
+            ],
+            capture_output=True,
+            text=True,
+            timeout=5,
+            env={**os.environ, "PATH": f"{bin_dir}:{os.environ['PATH']}"},
+        )
+        assert result.returncode == 0, result.stderr
+        return result.stdout.split()
+
+    assert complete("c") == ["cancel", "convert-unit", "calendar", "completions"]
+    assert complete("completions", "z") == ["zsh"]
+    assert complete("--run-dir", str(run_dir), "ps", "w") == ["web", "worker"]
+    assert complete("--run-dir", str(run_dir), "ps", "wo") == ["worker"]
+
+
+def test_completions_unknown_shell():
+    result = svloppctl(None, "completions", "tcsh")
+
+    assert result.returncode != 0
+    assert svloppctl(None, "completions", "zsh").stdout.startswith("#compdef svloppctl")
+    assert "complete -c svloppctl" in svloppctl(None, "completions", "fish").stdout
+
+
+def test_version_verbose(tmp_path, run_dir, svlopp_proc):
+    _start_services(tmp_path, run_dir, svlopp_proc)
+
+    result = svloppctl(run_dir, "version", "--verbose")
+
+    assert result.returncode == 0, result.stderr
+    lines = result.stdout.splitlines()
+    assert lines[0].startswith("svloppctl ")
+    assert lines[1] == "kernel features:"
+    features = dict(line.split() for line in lines[2:])
+    assert list(features) == [
+        "pidfd",
+        "clone3",
+        "close_range",
+        "cgroup_v2",
+        "io_uring",
+        "selinux",
+        "apparmor",
+    ]
+    assert set(features.values()) <= {"yes", "no"}
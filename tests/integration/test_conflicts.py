@@ -0,0 +1,71 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import time
+
+from constants import CONFIG_FILE_NAME, START_OPCDOE
+from helpers.control_fifo import send_control_op
+from helpers.status_file import read_status
+from helpers.utils import status_matches, wait_until
+
+
+def test_conflicting_service_is_stopped(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.v1]
+command = "sleep"
+args = ["60"]
+
+[services.v2]
+command = "sleep"
+args = ["60"]
+autostart = false
+conflicts_with = ["v1"]
+"""
+    )
+    svlopp_proc(config_path)
+    wait_until(status_matches(run_dir, lambda s: s.is_running("v1")), timeout=2.0)
+
+    send_control_op(run_dir, START_OPCDOE, read_status(run_dir).get("v2").service_id)
+
+    wait_until(
+        status_matches(run_dir, lambda s: s.is_stopped("v1") and s.is_running("v2")),
+        timeout=5.0,
+    )
+
+    # conflicts go both ways
+    send_control_op(run_dir, START_OPCDOE, read_status(run_dir).get("v1").service_id)
+    wait_until(
+        status_matches(run_dir, lambda s: s.is_running("v1") and s.is_stopped("v2")),
+        timeout=5.0,
+    )
+
+
+def test_mutex_group_refuses_start(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.a]
+command = "sleep"
+args = ["60"]
+mutex_group = "port-8080"
+
+[services.b]
+command = "sleep"
+args = ["60"]
+autostart = false
+mutex_group = "port-8080"
+on_conflict = "Refuse"
+"""
+    )
+    svlopp_proc(config_path)
+    wait_until(status_matches(run_dir, lambda s: s.is_running("a")), timeout=2.0)
+
+    send_control_op(run_dir, START_OPCDOE, read_status(run_dir).get("b").service_id)
+
+    time.sleep(0.5)
+    status = read_status(run_dir)
+    assert status.is_running("a")
+    assert status.is_stopped("b")
@@ -162,3 +162,45 @@ args = ["10"]
     assert test.state == STATE_RUNNING
     assert pid_exists(int(test.pid_or_reason))
     assert not pid_exists(old_test_pid)
+
+
+def test_control_batch(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    services = "".join(
+        f"""
+[services.test{i}]
+command = "/bin/sleep"
+args = ["10"]
+"""
+        for i in range(5)
+    )
+    config_path.write_text(services)
+
+    _ = svlopp_proc(config_path)
+
+    def are_all_running():
+        try:
+            status = read_status(run_dir)
+            return all(status.is_running(f"test{i}") for i in range(5))
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(are_all_running, timeout=2.0)
+    status = read_status(run_dir)
+
+    # commands written at once are read together, an invalid one in the
+    # middle doesn't drop the others
+    frames = [bytes([0x7F]) + (0).to_bytes(8, "little")] + [
+        bytes([STOP_OPCODE]) + status.get(f"test{i}").service_id.to_bytes(8, "little")
+        for i in range(5)
+    ]
+    frames.insert(3, frames.pop(0))
+    with open(run_dir / "control", "wb", buffering=0) as fh:
+        fh.write(b"".join(frames))
+
+    def are_all_stopped():
+        status = read_status(run_dir)
+        return all(status.is_stopped(f"test{i}") for i in range(5))
+
+    wait_until(are_all_stopped, timeout=5.0)
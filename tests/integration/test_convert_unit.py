@@ -0,0 +1,141 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import subprocess
+import tomllib
+
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+from constants import CONFIG_FILE_NAME, SVLOPPCTL_BINARY_PATH
+
+
+def _convert(unit_path, *extra_args):
+    return subprocess.run(
+        [SVLOPPCTL_BINARY_PATH, "convert-unit", str(unit_path), *extra_args],
+        capture_output=True,
+        text=True,
+        timeout=5,
+    )
+
+
+def test_convert_unit(tmp_path):
+    unit_path = tmp_path / "web.service"
+    unit_path.write_text(
+        """
+[Unit]
+Description=A web server
+After=network.target
+
+[Service]
+ExecStart=/usr/bin/web --listen "0.0.0.0:80" \\
+    --verbose
+Restart=always
+User=0
+Group=0
+Environment="GREETING=hello world" MODE=prod
+WorkingDirectory=/srv/web
+KillSignal=SIGQUIT
+TimeoutStopSec=1min 30s
+RuntimeMaxSec=2h
+LimitNOFILE=4096
+
+[Install]
+WantedBy=multi-user.target
+"""
+    )
+
+    result = _convert(unit_path)
+
+    assert result.returncode == 0
+    service = tomllib.loads(result.stdout)["services"]["web"]
+    assert service == {
+        "command": "/usr/bin/web",
+        "args": ["--listen", "0.0.0.0:80", "--verbose"],
+        "on_exit": "Restart",
+        "user_group": {"uid": 0, "gid": 0},
+        "env": {"GREETING": "hello world", "MODE": "prod"},
+        "working_directory": "/srv/web",
+        "stop_signal": "SIGQUIT",
+        "stop_timeout_ms": 90000,
+        "runtime_max_ms": 7200000,
+    }
+    assert "line 4: After= is not supported" in result.stderr
+    assert "LimitNOFILE= is not supported" in result.stderr
+
+
+def test_convert_unit_runs(tmp_path, run_dir, svlopp_proc):
+    unit_path = tmp_path / "sleeper.service"
+    unit_path.write_text(
+        """
+[Service]
+ExecStart=/bin/sleep 10
+"""
+    )
+
+    result = _convert(unit_path, "renamed")
+
+    assert result.returncode == 0
+    assert result.stderr == ""
+
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(result.stdout)
+    _ = svlopp_proc(config_path)
+
+    def is_running():
+        try:
+            return read_status(run_dir).is_running("renamed")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_running, timeout=2.0)
+
+
+def test_convert_unit_security_labels(tmp_path):
+    unit_path = tmp_path / "web.service"
+    unit_path.write_text(
+        """
+[Service]
+ExecStart=/usr/bin/web
+SELinuxContext=-system_u:system_r:web_t:s0
+"""
+    )
+
+    result = _convert(unit_path)
+
+    assert result.returncode == 0
+    service = tomllib.loads(result.stdout)["services"]["web"]
+    assert service["selinux_context"] == "system_u:system_r:web_t:s0"
+
+    unit_path.write_text("[Service]\nExecStart=/usr/bin/web\nAppArmorProfile=web\n")
+    result = _convert(unit_path)
+
+    assert result.returncode == 0
+    service = tomllib.loads(result.stdout)["services"]["web"]
+    assert service["apparmor_profile"] == "web"
+
+def test_convert_unit_keyring_mode(tmp_path):
+    unit_path = tmp_path / "web.service"
+    unit_path.write_text("[Service]\nExecStart=/usr/bin/web\nKeyringMode=inherit\n")
+
+    result = _convert(unit_path)
+
+    assert result.returncode == 0
+    service = tomllib.loads(result.stdout)["services"]["web"]
+    assert service["keyring"] == "inherit"
+
+    unit_path.write_text("[Service]\nExecStart=/usr/bin/web\nKeyringMode=shared\n")
+    result = _convert(unit_path)
+
+    assert result.returncode == 0
+    assert "keyring" not in tomllib.loads(result.stdout)["services"]["web"]
+    assert "line 3: KeyringMode=shared converted to a private keyring" in result.stderr
+
+def test_convert_unit_no_exec_start(tmp_path):
+    unit_path = tmp_path / "broken.service"
+    unit_path.write_text("[Service]\nUser=0\n")
+
+    result = _convert(unit_path)
+
+    assert result.returncode != 0
+    assert "ExecStart" in result.stderr
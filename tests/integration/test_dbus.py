@@ -0,0 +1,136 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import shutil
+import subprocess
+import time
+
+import pytest
+
+from constants import CONFIG_FILE_NAME
+from helpers.utils import status_matches, wait_until
+
+BUS_CONFIG = """<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-BUS Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>session</type>
+  <listen>unix:path={socket}</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>
+"""
+
+SLEEP_CONFIG = """
+[services.test]
+command = "sleep"
+args = ["60"]
+"""
+
+
+@pytest.fixture
+def bus_address(tmp_path):
+    daemon = shutil.which("dbus-daemon")
+    if daemon is None or shutil.which("dbus-send") is None:
+        pytest.skip("needs dbus-daemon and dbus-send")
+    socket = tmp_path / "bus"
+    config = tmp_path / "bus.conf"
+    config.write_text(BUS_CONFIG.format(socket=socket))
+    proc = subprocess.Popen(
+        [daemon, f"--config-file={config}", "--nofork"],
+        stdout=subprocess.DEVNULL,
+        stderr=subprocess.DEVNULL,
+    )
+    wait_until(socket.exists, timeout=2.0)
+    yield f"unix:path={socket}"
+    proc.terminate()
+    proc.wait()
+
+
+def _start_svlopp(tmp_path, run_dir, svlopp_proc, bus_address):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(SLEEP_CONFIG)
+    proc = svlopp_proc(config_path, "--dbus", bus_address)
+    try:
+        proc.wait(timeout=0.5)
+    except subprocess.TimeoutExpired:
+        pass
+    else:
+        if b"dbus feature" in proc.stderr.read():
+            pytest.skip("svlopp built without the dbus feature")
+    wait_until(status_matches(run_dir, lambda s: s.is_running("test")), timeout=2.0)
+
+
+def _call(bus_address, method, *args):
+    return subprocess.run(
+        [
+            "dbus-send",
+            f"--bus={bus_address}",
+            "--print-reply",
+            "--dest=org.svlopp.Manager",
+            "/org/svlopp/Manager",
+            f"org.svlopp.Manager.{method}",
+            *args,
+        ],
+        capture_output=True,
+        text=True,
+        timeout=5,
+    )
+
+
+def test_dbus_list_and_stop(tmp_path, run_dir, svlopp_proc, bus_address):
+    _start_svlopp(tmp_path, run_dir, svlopp_proc, bus_address)
+
+    listed = _call(bus_address, "ListUnits")
+    assert listed.returncode == 0
+    assert '"test"' in listed.stdout
+    assert '"running"' in listed.stdout
+
+    assert _call(bus_address, "StopUnit", "string:test").returncode == 0
+    wait_until(status_matches(run_dir, lambda s: s.is_stopped("test")), timeout=2.0)
+
+    assert _call(bus_address, "StartUnit", "string:test").returncode == 0
+    wait_until(status_matches(run_dir, lambda s: s.is_running("test")), timeout=2.0)
+
+
+def test_dbus_unknown_unit(tmp_path, run_dir, svlopp_proc, bus_address):
+    _start_svlopp(tmp_path, run_dir, svlopp_proc, bus_address)
+
+    result = _call(bus_address, "RestartUnit", "string:missing")
+
+    assert result.returncode != 0
+    assert "org.svlopp.Error.NoSuchUnit" in result.stderr
+
+
+def test_dbus_state_change_signal(tmp_path, run_dir, svlopp_proc, bus_address):
+    if shutil.which("dbus-monitor") is None:
+        pytest.skip("needs dbus-monitor")
+    _start_svlopp(tmp_path, run_dir, svlopp_proc, bus_address)
+    monitor = subprocess.Popen(
+        [
+            "dbus-monitor",
+            "--address",
+            bus_address,
+            "type='signal',interface='org.svlopp.Manager'",
+        ],
+        stdout=subprocess.PIPE,
+        text=True,
+    )
+    try:
+        # let the monitor subscribe
+        time.sleep(0.5)
+        assert _call(bus_address, "StopUnit", "string:test").returncode == 0
+        wait_until(
+            status_matches(run_dir, lambda s: s.is_stopped("test")), timeout=2.0
+        )
+        time.sleep(0.5)
+    finally:
+        monitor.terminate()
+    output = monitor.communicate(timeout=5)[0]
+    assert "member=UnitStateChanged" in output
+    assert 'string "stopping"' in output
+    assert 'string "stopped"' in output
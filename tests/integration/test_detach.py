@@ -0,0 +1,204 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+import signal
+import time
+from pathlib import Path
+
+import pytest
+
+from helpers.cgroup import make_cgroup, remove_cgroup
+from helpers.status_file import read_status
+from helpers.utils import service_state, start_svlopp, svloppctl, wait_until
+
+CONFIG = """
+[services.test]
+command = "/bin/sleep"
+args = ["100"]
+on_exit = "Restart"
+
+[services.bound]
+command = "/bin/sleep"
+args = ["100"]
+bind_to = "test"
+"""
+
+
+TICKING = """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo $$ > /tmp/owner; while :; do echo tick $$; sleep 0.1; done"]
+log_file_path = "{log}"
+{extra}
+
+# pumped to the log file
+[services.test.log_prefix]
+stream = true
+"""
+
+
+def _names(run_dir):
+    try:
+        return {line.service_name for line in read_status(run_dir).lines}
+    except FileNotFoundError:
+        return set()
+
+
+def _start(tmp_path, run_dir, svlopp_proc):
+    start_svlopp(tmp_path, run_dir, svlopp_proc, CONFIG, ["test", "bound"])
+    return int(service_state(run_dir, "test")[1])
+
+
+def _proc_cgroup(pid):
+    for line in Path(f"/proc/{pid}/cgroup").read_text().splitlines():
+        if line.startswith("0::"):
+            return line[3:]
+    return None
+
+
+def _alive(pid):
+    try:
+        with open(f"/proc/{pid}/stat") as f:
+            # not a zombie
+            return f.read().rsplit(")", 1)[1].split()[0] != "Z"
+    except FileNotFoundError:
+        return False
+
+
+def test_detach_leaves_process_running(tmp_path, run_dir, svlopp_proc):
+    pid = _start(tmp_path, run_dir, svlopp_proc)
+    bound_pid = service_state(run_dir, "bound")[1]
+
+    result = svloppctl(run_dir, "detach", "test")
+    assert result.returncode == 0, result.stderr
+    expected = ("stopped", f"detached({pid})")
+    wait_until(lambda: service_state(run_dir, "test") == expected, timeout=2.0)
+    # not restarted despite `on_exit`, and bound services left alone
+    time.sleep(1.5)
+    assert service_state(run_dir, "test") == expected
+    assert service_state(run_dir, "bound") == ("running", bound_pid)
+    assert _alive(pid)
+
+
+def test_detached_process_reaped(tmp_path, run_dir, svlopp_proc):
+    pid = _start(tmp_path, run_dir, svlopp_proc)
+    assert svloppctl(run_dir, "detach", "test").returncode == 0
+    expected = ("stopped", f"detached({pid})")
+    wait_until(lambda: service_state(run_dir, "test") == expected, timeout=2.0)
+
+    os.kill(pid, signal.SIGKILL)
+    wait_until(lambda: not os.path.exists(f"/proc/{pid}"), timeout=3.0)
+    time.sleep(1.5)
+    assert service_state(run_dir, "test") == expected
+
+
+def test_detached_service_started_again(tmp_path, run_dir, svlopp_proc):
+    pid = _start(tmp_path, run_dir, svlopp_proc)
+    assert svloppctl(run_dir, "detach", "test").returncode == 0
+    expected = ("stopped", f"detached({pid})")
+    wait_until(lambda: service_state(run_dir, "test") == expected, timeout=2.0)
+
+    assert svloppctl(run_dir, "start", "test").returncode == 0
+
+    def restarted():
+        state, new_pid = service_state(run_dir, "test")
+        return state == "running" and new_pid != str(pid)
+
+    wait_until(restarted, timeout=3.0)
+    assert _alive(pid)
+    os.kill(pid, signal.SIGKILL)
+
+
+def _ticks(log_file_path, pid):
+    return log_file_path.read_text().split().count(str(pid))
+
+
+def _detach_and_start_again(run_dir):
+    pid = int(service_state(run_dir, "test")[1])
+    assert svloppctl(run_dir, "detach", "test").returncode == 0
+    expected = ("stopped", f"detached({pid})")
+    wait_until(lambda: service_state(run_dir, "test") == expected, timeout=2.0)
+    assert svloppctl(run_dir, "start", "test").returncode == 0
+    wait_until(lambda: service_state(run_dir, "test")[0] == "running", timeout=3.0)
+    return pid, int(service_state(run_dir, "test")[1])
+
+
+def test_detached_process_untouched_by_restart(tmp_path, run_dir, svlopp_proc):
+    if os.geteuid() != 0:
+        pytest.skip("needs root to mount")
+    log_file_path = tmp_path / "test.log"
+    config = TICKING.format(log=log_file_path, extra="private_tmp = true")
+    start_svlopp(tmp_path, run_dir, svlopp_proc, config, ["test"])
+
+    pid, new_pid = _detach_and_start_again(run_dir)
+    try:
+        # each process keeps its own `/tmp`
+        for owner in (pid, new_pid):
+            path = Path(f"/proc/{owner}/root/tmp/owner")
+            wait_until(path.exists, timeout=2.0)
+            assert path.read_text().strip() == str(owner)
+
+        # nor does stopping the new process end the detached one's output
+        assert svloppctl(run_dir, "stop", "test").returncode == 0
+        wait_until(lambda: service_state(run_dir, "test")[0] == "stopped", timeout=3.0)
+        ticks = _ticks(log_file_path, pid)
+        wait_until(lambda: _ticks(log_file_path, pid) > ticks + 3, timeout=2.0)
+        assert _alive(pid)
+        assert Path(f"/proc/{pid}/root/tmp/owner").read_text().strip() == str(pid)
+        # moved aside, still mounted
+        assert (run_dir / "tmp.d" / f".test.{pid}" / "owner").read_text().strip() == str(pid)
+    finally:
+        os.kill(pid, signal.SIGKILL)
+
+
+def test_detached_process_leaves_cgroup(tmp_path, run_dir, svlopp_proc):
+    cgroup, cgroup_dir = make_cgroup()
+    log_file_path = tmp_path / "test.log"
+    config = TICKING.format(log=log_file_path, extra="cgroup_delegate = true")
+    proc = start_svlopp(tmp_path, run_dir, svlopp_proc, config, ["test"], cgroup=cgroup_dir)
+    pid = None
+    try:
+        pid, new_pid = _detach_and_start_again(run_dir)
+        assert _proc_cgroup(pid) == f"{cgroup}/supervisor"
+        assert _proc_cgroup(new_pid) == f"{cgroup}/test"
+
+        assert svloppctl(run_dir, "stop", "test").returncode == 0
+        wait_until(lambda: service_state(run_dir, "test")[0] == "stopped", timeout=3.0)
+        time.sleep(0.5)
+        assert _alive(pid)
+    finally:
+        if pid is not None:
+            os.kill(pid, signal.SIGKILL)
+            wait_until(lambda: not _alive(pid), timeout=2.0)
+        proc.terminate()
+        proc.wait(timeout=5)
+        # the last `sleep` of the detached process may still be running
+        procs = cgroup_dir / "supervisor" / "cgroup.procs"
+        wait_until(lambda: not procs.read_text(), timeout=2.0)
+        remove_cgroup(cgroup_dir)
+
+
+def test_detach_transient_service(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+    args = ["run", "--name", "batch1", "--", "/bin/sleep", "100"]
+    assert svloppctl(run_dir, *args).returncode == 0
+    wait_until(lambda: service_state(run_dir, "batch1")[0] == "running", timeout=2.0)
+    pid = int(service_state(run_dir, "batch1")[1])
+
+    assert svloppctl(run_dir, "detach", "batch1").returncode == 0
+    wait_until(lambda: "batch1" not in _names(run_dir), timeout=3.0)
+    assert _alive(pid)
+    os.kill(pid, signal.SIGKILL)
+
+
+def test_detach_stopped_service_rejected(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+    assert svloppctl(run_dir, "stop", "bound").returncode == 0
+    wait_until(lambda: service_state(run_dir, "bound")[0] == "stopped", timeout=3.0)
+    reason = service_state(run_dir, "bound")[1]
+
+    assert svloppctl(run_dir, "detach", "bound").returncode == 0
+    time.sleep(1.5)
+    assert service_state(run_dir, "bound") == ("stopped", reason)
@@ -0,0 +1,95 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import subprocess
+import time
+
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+from constants import CONFIG_FILE_NAME, SVLOPPCTL_BINARY_PATH
+
+CONFIG = """
+[services.app]
+command = "sleep"
+args = ["60"]
+
+[services.shipper]
+command = "sleep"
+args = ["60"]
+bind_to = "app"
+
+[services.metrics]
+command = "sleep"
+args = ["60"]
+bind_to = "shipper"
+
+[services.manual]
+command = "sleep"
+args = ["60"]
+autostart = false
+"""
+
+
+def svloppctl(run_dir, *args) -> subprocess.CompletedProcess:
+    return subprocess.run(
+        [SVLOPPCTL_BINARY_PATH, "--run-dir", str(run_dir), *args],
+        capture_output=True,
+        text=True,
+        timeout=5,
+    )
+
+
+def _plan(result):
+    assert result.returncode == 0, result.stderr
+    return [line.split()[1:] for line in result.stdout.splitlines()]
+
+
+def _start(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(CONFIG)
+    svlopp_proc(config_path)
+
+    def all_running():
+        try:
+            status = read_status(run_dir)
+            return all(status.is_running(n) for n in ("app", "shipper", "metrics"))
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(all_running, timeout=3.0)
+
+
+def test_dry_run_stop_follows_bindings(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+
+    plan = _plan(svloppctl(run_dir, "stop", "app", "--dry-run"))
+
+    assert plan == [["stop", "app"], ["stop", "shipper"], ["stop", "metrics"]]
+    time.sleep(0.3)
+    status = read_status(run_dir)
+    assert all(status.is_running(n) for n in ("app", "shipper", "metrics"))
+
+
+def test_dry_run_restart_and_start(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+
+    plan = _plan(svloppctl(run_dir, "restart", "shipper", "--dry-run"))
+    assert plan == [
+        ["stop", "shipper"],
+        ["stop", "metrics"],
+        ["start", "shipper"],
+        ["start", "metrics"],
+    ]
+
+    result = svloppctl(run_dir, "start", "app", "--dry-run")
+    assert result.returncode == 0, result.stderr
+    assert result.stdout == "nothing to do\n"
+
+    assert _plan(svloppctl(run_dir, "start", "manual", "--dry-run")) == [
+        ["start", "manual"]
+    ]
+
+    # without the flag the operation is applied
+    assert svloppctl(run_dir, "start", "manual").returncode == 0
+    wait_until(lambda: read_status(run_dir).is_running("manual"), timeout=2.0)
@@ -0,0 +1,107 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+
+import pytest
+
+from helpers.cgroup import make_cgroup, remove_cgroup
+from helpers.utils import service_state, start_svlopp, svloppctl, wait_until
+
+NOBODY = 65534
+
+CONFIG = """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo private > /tmp/marker; exec sleep 100"]
+user_group = {{ uid = {nobody}, gid = {nobody} }}
+private_tmp = true
+{extra}
+"""
+
+MAPPED = """
+[services.test.user_namespace]
+uid_map = [
+    { inside = 0, outside = 0, count = 1 },
+    { inside = 1000, outside = 101000, count = 10 },
+]
+gid_map = [
+    { inside = 0, outside = 0, count = 1 },
+    { inside = 1000, outside = 101000, count = 10 },
+]
+"""
+
+
+def _start(tmp_path, run_dir, svlopp_proc, config, **kwargs):
+    if os.geteuid() != 0:
+        pytest.skip("needs root to join namespaces")
+    proc = start_svlopp(tmp_path, run_dir, svlopp_proc, config, ["test"], **kwargs)
+    return proc, int(service_state(run_dir, "test")[1])
+
+
+def _enter(run_dir, script):
+    return svloppctl(run_dir, "enter", "test", "--", "/bin/sh", "-c", script)
+
+
+def test_enter_namespaces_and_user(tmp_path, run_dir, svlopp_proc):
+    config = CONFIG.format(nobody=NOBODY, extra="")
+    _, pid = _start(tmp_path, run_dir, svlopp_proc, config)
+
+    script = "readlink /proc/self/ns/mnt; id -u; id -g; cat /tmp/marker"
+    result = _enter(run_dir, script)
+    assert result.returncode == 0, result.stderr
+    mnt = os.readlink(f"/proc/{pid}/ns/mnt")
+    assert result.stdout.split() == [mnt, str(NOBODY), str(NOBODY), "private"]
+
+
+def test_enter_default_shell(tmp_path, run_dir, svlopp_proc):
+    config = CONFIG.format(nobody=NOBODY, extra="")
+    _start(tmp_path, run_dir, svlopp_proc, config)
+
+    result = svloppctl(run_dir, "enter", "test", input="cat /tmp/marker; id -u\n")
+    assert result.returncode == 0, result.stderr
+    assert result.stdout.split() == ["private", str(NOBODY)]
+
+
+def test_enter_exit_status(tmp_path, run_dir, svlopp_proc):
+    config = CONFIG.format(nobody=NOBODY, extra="")
+    _start(tmp_path, run_dir, svlopp_proc, config)
+
+    assert _enter(run_dir, "exit 3").returncode == 3
+
+
+def test_enter_user_namespace(tmp_path, run_dir, svlopp_proc):
+    config = CONFIG.format(nobody=1000, extra=MAPPED)
+    _, pid = _start(tmp_path, run_dir, svlopp_proc, config)
+
+    result = _enter(run_dir, "readlink /proc/self/ns/user; id -u; cat /tmp/marker")
+    assert result.returncode == 0, result.stderr
+    user = os.readlink(f"/proc/{pid}/ns/user")
+    # ids as seen from the namespace
+    assert result.stdout.split() == [user, "1000", "private"]
+
+
+def test_enter_cgroup(tmp_path, run_dir, svlopp_proc):
+    cgroup, cgroup_dir = make_cgroup()
+    config = CONFIG.format(nobody=NOBODY, extra="cgroup_delegate = true")
+    proc, _ = _start(tmp_path, run_dir, svlopp_proc, config, cgroup=cgroup_dir)
+    try:
+        result = _enter(run_dir, "grep ^0:: /proc/self/cgroup")
+        assert result.returncode == 0, result.stderr
+        assert result.stdout == f"0::{cgroup}/test\n"
+    finally:
+        proc.terminate()
+        proc.wait(timeout=5)
+        remove_cgroup(cgroup_dir)
+
+
+def test_enter_stopped_service_fails(tmp_path, run_dir, svlopp_proc):
+    config = CONFIG.format(nobody=NOBODY, extra="")
+    _start(tmp_path, run_dir, svlopp_proc, config)
+    assert svloppctl(run_dir, "stop", "test").returncode == 0
+    wait_until(lambda: service_state(run_dir, "test")[0] == "stopped", timeout=3.0)
+
+    result = _enter(run_dir, "true")
+    assert result.returncode != 0
+    assert "service 'test' isn't running" in result.stderr
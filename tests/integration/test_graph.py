@@ -0,0 +1,77 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import json
+import subprocess
+
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+from constants import CONFIG_FILE_NAME, SVLOPPCTL_BINARY_PATH
+
+CONFIG = """
+[services.app]
+command = "sleep"
+args = ["60"]
+
+[services.shipper]
+command = "sleep"
+args = ["60"]
+bind_to = "app"
+
+[services.manual]
+command = "sleep"
+args = ["60"]
+autostart = false
+"""
+
+
+def graph(run_dir, *args) -> subprocess.CompletedProcess:
+    return subprocess.run(
+        [SVLOPPCTL_BINARY_PATH, "--run-dir", str(run_dir), "graph", *args],
+        capture_output=True,
+        text=True,
+        timeout=5,
+    )
+
+
+def _start(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(CONFIG)
+    svlopp_proc(config_path)
+
+    def app_running():
+        try:
+            return read_status(run_dir).is_running("app")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(app_running, timeout=2.0)
+
+
+def test_graph_dot(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+
+    result = graph(run_dir)
+
+    assert result.returncode == 0, result.stderr
+    lines = result.stdout.splitlines()
+    assert lines[0] == "digraph svlopp {"
+    assert '  "app" [label="app\\nrunning"];' in lines
+    assert '  "manual" [label="manual\\nstopped"];' in lines
+    assert '  "shipper" -> "app" [label="bind_to"];' in lines
+    assert lines[-1] == "}"
+
+
+def test_graph_json(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+
+    result = graph(run_dir, "--json")
+
+    assert result.returncode == 0, result.stderr
+    data = json.loads(result.stdout)
+    assert [svc["name"] for svc in data["services"]] == ["app", "manual", "shipper"]
+    manual = data["services"][1]
+    assert manual["state"] == "stopped"
+    assert manual["autostart"] is False
+    assert data["edges"] == [{"from": "shipper", "to": "app", "kind": "bind_to"}]
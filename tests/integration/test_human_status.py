@@ -0,0 +1,105 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+import pty
+import subprocess
+
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+from constants import CONFIG_FILE_NAME, SVLOPPCTL_BINARY_PATH
+
+GREEN = "\x1b[32m"
+RED = "\x1b[31m"
+
+
+def _start_services(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.sleeper]
+command = "/bin/sleep"
+args = ["10"]
+
+[services.failing]
+command = "/bin/sh"
+args = ["-c", "exit 3"]
+"""
+    )
+    _ = svlopp_proc(config_path)
+
+    def is_settled():
+        try:
+            status = read_status(run_dir)
+            return status.is_running("sleeper") and status.is_stopped("failing")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_settled, timeout=2.0)
+
+
+def _status_on_tty(run_dir, *args, env=None):
+    controller, terminal = pty.openpty()
+    try:
+        proc = subprocess.run(
+            [SVLOPPCTL_BINARY_PATH, "--run-dir", str(run_dir), "status", *args],
+            stdout=terminal,
+            stderr=subprocess.PIPE,
+            timeout=5,
+            env=env,
+        )
+        assert proc.returncode == 0
+        os.close(terminal)
+        terminal = None
+        output = b""
+        while True:
+            try:
+                chunk = os.read(controller, 4096)
+            except OSError:
+                break
+            if not chunk:
+                break
+            output += chunk
+        return output.decode()
+    finally:
+        if terminal is not None:
+            os.close(terminal)
+        os.close(controller)
+
+
+def test_status_table(tmp_path, run_dir, svlopp_proc):
+    _start_services(tmp_path, run_dir, svlopp_proc)
+
+    result = subprocess.run(
+        [SVLOPPCTL_BINARY_PATH, "--run-dir", str(run_dir), "status"],
+        capture_output=True,
+        text=True,
+        timeout=5,
+    )
+
+    assert result.returncode == 0
+    # not a terminal, no colors
+    assert "\x1b[" not in result.stdout
+    header, failing, sleeper = result.stdout.splitlines()
+    assert header.split() == ["NAME", "STATE", "PID/REASON", "UPTIME", "STARTS"]
+    assert failing.split() == ["failing", "stopped", "error(3)", "-", "1"]
+    name, state, pid, uptime, starts = sleeper.split()
+    assert (name, state, starts) == ("sleeper", "running", "1")
+    assert pid == read_status(run_dir).get("sleeper").pid_or_reason
+    assert uptime.endswith("s")
+    # columns are aligned
+    assert header.index("STATE") == failing.index("stopped") == sleeper.index("running")
+
+
+def test_status_colors(tmp_path, run_dir, svlopp_proc):
+    _start_services(tmp_path, run_dir, svlopp_proc)
+    env = {k: v for k, v in os.environ.items() if k != "NO_COLOR"}
+
+    output = _status_on_tty(run_dir, env=env)
+
+    assert f"{GREEN}running" in output
+    assert f"{RED}stopped" in output
+
+    assert "\x1b[" not in _status_on_tty(run_dir, "--no-color", env=env)
+    assert "\x1b[" not in _status_on_tty(run_dir, env={**env, "NO_COLOR": "1"})
@@ -0,0 +1,138 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import signal
+import time
+
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+from constants import CONFIG_FILE_NAME
+
+
+def _wait_running(run_dir, *names):
+    def are_running():
+        try:
+            status = read_status(run_dir)
+            return all(status.is_running(name) for name in names)
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(are_running, timeout=1.0)
+
+
+def test_init_forwards_signals(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    usr1_path = tmp_path / "usr1"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "trap 'echo usr1 >> {usr1_path}' USR1; while true; do sleep 0.1; done"]
+"""
+    )
+
+    proc = svlopp_proc(config_path, "--init")
+    _wait_running(run_dir, "test")
+
+    proc.send_signal(signal.SIGUSR1)
+    wait_until(lambda: usr1_path.exists(), timeout=2.0)
+
+    # not forwarded signals are not fatal to svlopp
+    assert proc.poll() is None
+
+
+def test_init_forward_signals_map(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    usr1_path = tmp_path / "usr1"
+
+    config_path.write_text(
+        f"""
+forward_signals = {{ SIGUSR2 = "SIGUSR1" }}
+
+[services.test]
+command = "/bin/sh"
+args = ["-c", "trap 'echo usr1 >> {usr1_path}' USR1; while true; do sleep 0.1; done"]
+"""
+    )
+
+    proc = svlopp_proc(config_path, "--init")
+    _wait_running(run_dir, "test")
+
+    proc.send_signal(signal.SIGUSR2)
+    wait_until(lambda: usr1_path.exists(), timeout=2.0)
+
+    # SIGUSR1 is not in the map anymore
+    proc.send_signal(signal.SIGUSR1)
+    proc.send_signal(signal.SIGUSR2)
+    wait_until(lambda: len(usr1_path.read_text().splitlines()) == 2, timeout=2.0)
+    assert proc.poll() is None
+
+
+def test_init_exits_with_main_service_code(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+main_service = "app"
+
+[services.app]
+command = "/bin/sh"
+args = ["-c", "trap 'exit 3' TERM; while true; do sleep 0.1; done"]
+
+[services.sidecar]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    proc = svlopp_proc(config_path, "--init")
+    _wait_running(run_dir, "app", "sidecar")
+    # give the shell time to install its trap
+    time.sleep(0.2)
+
+    proc.terminate()
+    assert proc.wait(timeout=5.0) == 3
+
+
+def test_init_main_service_signaled(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+main_service = "app"
+
+[services.app]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    proc = svlopp_proc(config_path, "--init")
+    _wait_running(run_dir, "app")
+
+    proc.terminate()
+    assert proc.wait(timeout=5.0) == 128 + signal.SIGTERM
+
+
+def test_init_waits_for_orphans(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    marker_path = tmp_path / "orphan_done"
+
+    # the background process leaves the service process group, so it
+    # survives the service and is orphaned
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "setsid /bin/sh -c 'sleep 1; touch {marker_path}' & exec sleep 10"]
+"""
+    )
+
+    proc = svlopp_proc(config_path, "--init")
+    _wait_running(run_dir, "test")
+
+    proc.terminate()
+    assert proc.wait(timeout=5.0) == 0
+    assert marker_path.exists()
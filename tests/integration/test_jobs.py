@@ -0,0 +1,256 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import json
+import socket
+import subprocess
+import time
+
+import pytest
+
+from constants import CONFIG_FILE_NAME
+from helpers.utils import service_state, svloppctl, wait_until
+
+API_SOCKET_NAME = "api.sock"
+JOBS_FILE_NAME = "jobs"
+
+# ignores the stop signal, so that it stays stopping until killed
+CONFIG = """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "trap '' TERM; while true; do sleep 0.1; done"]
+stop_timeout_ms = 1500
+"""
+# only stops once killed, or cancelled
+SLOW_STOP_CONFIG = CONFIG.replace("stop_timeout_ms = 1500", "stop_timeout_ms = 30000")
+
+
+# services ignoring the stop signal, stopping for a second each
+LIMITED_CONFIG = """
+max_concurrent_{kind} = 1
+{services}
+"""
+LIMITED_SERVICE = """
+[services.{name}]
+command = "/bin/sh"
+args = ["-c", "{script}"]
+stop_timeout_ms = 1000
+"""
+TRAP_SCRIPT = "trap '' TERM; while true; do sleep 0.1; done"
+
+
+def _jobs(run_dir):
+    try:
+        content = (run_dir / JOBS_FILE_NAME).read_text()
+    except FileNotFoundError:
+        return {}
+    return {key: int(value) for key, value in map(str.split, content.splitlines())}
+
+
+def _start_limited(tmp_path, run_dir, svlopp_proc, kind, names, script=TRAP_SCRIPT):
+    services = "".join(
+        LIMITED_SERVICE.format(name=name, script=script) for name in names
+    )
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(LIMITED_CONFIG.format(kind=kind, services=services))
+    svlopp_proc(config_path)
+
+
+def _start(tmp_path, run_dir, svlopp_proc, *extra_args, config=CONFIG):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(config)
+    proc = svlopp_proc(config_path, *extra_args)
+    try:
+        proc.wait(timeout=0.5)
+    except subprocess.TimeoutExpired:
+        pass
+    else:
+        if b"api feature" in proc.stderr.read():
+            pytest.skip("svlopp built without the api feature")
+    wait_until(lambda: service_state(run_dir, "test")[0] == "running", timeout=2.0)
+    return service_state(run_dir, "test")[1]
+
+
+def _stopping(run_dir):
+    assert svloppctl(run_dir, "stop", "test").returncode == 0
+    wait_until(lambda: service_state(run_dir, "test")[0] == "stopping", timeout=2.0)
+
+
+def _request(run_dir, method, path):
+    """Send a request and return the status code and the body"""
+    with socket.socket(socket.AF_UNIX, socket.SOCK_STREAM) as sock:
+        sock.settimeout(5)
+        sock.connect(str(run_dir / API_SOCKET_NAME))
+        sock.sendall(f"{method} {path} HTTP/1.1\r\nHost: svlopp\r\n\r\n".encode())
+        response = b""
+        while chunk := sock.recv(4096):
+            response += chunk
+    head, body = response.split(b"\r\n\r\n", 1)
+    return int(head.split(b" ")[1]), json.loads(body)
+
+
+def test_restart_while_stopping_is_queued(tmp_path, run_dir, svlopp_proc):
+    pid = _start(tmp_path, run_dir, svlopp_proc)
+    _stopping(run_dir)
+
+    assert svloppctl(run_dir, "restart", "test").returncode == 0
+    # started again once killed after the stop timeout
+    wait_until(
+        lambda: service_state(run_dir, "test")[0] == "running"
+        and service_state(run_dir, "test")[1] != pid,
+        timeout=5.0,
+    )
+
+
+def test_stop_cancels_queued_start(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+    _stopping(run_dir)
+
+    assert svloppctl(run_dir, "start", "test").returncode == 0
+    assert svloppctl(run_dir, "stop", "test").returncode == 0
+    wait_until(lambda: service_state(run_dir, "test")[0] == "stopped", timeout=5.0)
+    # the next timerfd ticks don't start it
+    time.sleep(1.5)
+    assert service_state(run_dir, "test")[0] == "stopped"
+
+
+def test_api_job_merged_and_rejected(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc, "--api")
+    _stopping(run_dir)
+
+    status, body = _request(run_dir, "POST", "/v1/services/test/start")
+    assert status == 200
+    assert body["state"] == "stopping"
+    assert body["job"] == "restart"
+
+    # merged with the queued start
+    status, body = _request(run_dir, "POST", "/v1/services/test/restart")
+    assert status == 200
+    assert body["job"] == "restart"
+
+    status, body = _request(run_dir, "POST", "/v1/services/test/pause")
+    assert status == 409
+    assert body["error"] == "failed to pause 'test': operation in progress"
+
+
+def test_max_concurrent_stops(tmp_path, run_dir, svlopp_proc):
+    names = ["a", "b", "c"]
+    _start_limited(tmp_path, run_dir, svlopp_proc, "stops", names)
+    for name in names:
+        wait_until(lambda: service_state(run_dir, name)[0] == "running", timeout=5.0)
+    pids = {name: service_state(run_dir, name)[1] for name in names}
+
+    for name in names:
+        assert svloppctl(run_dir, "restart", name).returncode == 0
+    wait_until(lambda: _jobs(run_dir).get("queued") == 2, timeout=3.0)
+    states = [service_state(run_dir, name)[0] for name in names]
+    assert states.count("stopping") == 1
+
+    def restarted():
+        return all(
+            service_state(run_dir, name)[0] == "running"
+            and service_state(run_dir, name)[1] != pids[name]
+            for name in names
+        )
+
+    wait_until(restarted, timeout=10.0)
+    wait_until(lambda: _jobs(run_dir).get("queued") == 0, timeout=3.0)
+    jobs = _jobs(run_dir)
+    assert jobs["dispatched"] == 2
+    assert jobs["wait_max_ms"] >= 1000
+
+
+def test_max_concurrent_starts(tmp_path, run_dir, svlopp_proc):
+    output_path = tmp_path / "output"
+    script = f"date +%s%N >> {output_path}; exec sleep 100"
+    names = ["a", "b", "c"]
+    _start_limited(tmp_path, run_dir, svlopp_proc, "starts", names, script)
+
+    def started():
+        return output_path.exists() and len(output_path.read_text().split()) == 3
+
+    wait_until(started, timeout=10.0)
+    times = sorted(int(t) for t in output_path.read_text().split())
+    # each one waits for the previous one to settle, for a second
+    assert times[1] - times[0] >= 900_000_000
+    assert times[2] - times[1] >= 900_000_000
+
+
+def test_stop_drops_queued_start(tmp_path, run_dir, svlopp_proc):
+    _start_limited(tmp_path, run_dir, svlopp_proc, "starts", ["a", "b", "c"])
+    wait_until(lambda: service_state(run_dir, "a")[0] == "running", timeout=5.0)
+    # still waiting in the start queue, behind b
+    assert svloppctl(run_dir, "stop", "c").returncode == 0
+
+    wait_until(lambda: service_state(run_dir, "b")[0] == "running", timeout=5.0)
+    wait_until(lambda: _jobs(run_dir).get("start_queue") == 0, timeout=5.0)
+    time.sleep(1.5)
+    assert service_state(run_dir, "c")[0] == "stopped"
+
+
+def test_start_drops_queued_restart(tmp_path, run_dir, svlopp_proc):
+    _start_limited(tmp_path, run_dir, svlopp_proc, "stops", ["a", "b"])
+    for name in ["a", "b"]:
+        wait_until(lambda: service_state(run_dir, name)[0] == "running", timeout=5.0)
+    pid = service_state(run_dir, "b")[1]
+
+    assert svloppctl(run_dir, "stop", "a").returncode == 0
+    assert svloppctl(run_dir, "restart", "b").returncode == 0
+    wait_until(lambda: _jobs(run_dir).get("queued") == 1, timeout=3.0)
+    # replaces the restart, and has nothing to do
+    assert svloppctl(run_dir, "start", "b").returncode == 0
+    wait_until(lambda: _jobs(run_dir).get("queued") == 0, timeout=3.0)
+
+    wait_until(lambda: service_state(run_dir, "a")[0] == "stopped", timeout=5.0)
+    time.sleep(1.5)
+    assert service_state(run_dir, "b") == ("running", pid)
+
+
+def test_cancel_job_waiting_for_slot(tmp_path, run_dir, svlopp_proc):
+    _start_limited(tmp_path, run_dir, svlopp_proc, "stops", ["a", "b"])
+    for name in ["a", "b"]:
+        wait_until(lambda: service_state(run_dir, name)[0] == "running", timeout=5.0)
+    pid = service_state(run_dir, "b")[1]
+
+    assert svloppctl(run_dir, "stop", "a").returncode == 0
+    assert svloppctl(run_dir, "stop", "b").returncode == 0
+    wait_until(lambda: _jobs(run_dir).get("queued") == 1, timeout=3.0)
+    assert svloppctl(run_dir, "cancel", "b").returncode == 0
+    wait_until(lambda: _jobs(run_dir).get("queued") == 0, timeout=3.0)
+
+    wait_until(lambda: service_state(run_dir, "a")[0] == "stopped", timeout=5.0)
+    time.sleep(1.5)
+    assert service_state(run_dir, "b") == ("running", pid)
+
+
+def test_cancel_restart_then_stop(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc, config=SLOW_STOP_CONFIG)
+    assert svloppctl(run_dir, "restart", "test").returncode == 0
+    wait_until(lambda: service_state(run_dir, "test")[0] == "stopping", timeout=2.0)
+
+    # drops the start, the service still stopping
+    assert svloppctl(run_dir, "cancel", "test").returncode == 0
+    time.sleep(0.5)
+    assert service_state(run_dir, "test")[0] == "stopping"
+
+    # kills it, well before the stop timeout
+    assert svloppctl(run_dir, "cancel", "test").returncode == 0
+    wait_until(lambda: service_state(run_dir, "test")[0] == "stopped", timeout=2.0)
+    time.sleep(1.5)
+    assert service_state(run_dir, "test")[0] == "stopped"
+
+
+def test_api_cancel(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc, "--api", config=SLOW_STOP_CONFIG)
+    _stopping(run_dir)
+    assert svloppctl(run_dir, "start", "test").returncode == 0
+
+    status, body = _request(run_dir, "POST", "/v1/services/test/cancel")
+    assert status == 200
+    assert body["state"] == "stopping"
+    assert body["job"] is None
+
+    status, body = _request(run_dir, "POST", "/v1/services/test/cancel")
+    assert status == 200
+    wait_until(lambda: service_state(run_dir, "test")[0] == "stopped", timeout=2.0)
@@ -2,6 +2,7 @@
 # License, v. 2.0. If a copy of the MPL was not distributed with this
 # file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+import errno
 import os
 import signal
 from pathlib import Path
@@ -12,6 +13,7 @@ from constants import (
     REASON_CRASHED,
     REASON_ERROR,
     REASON_KILLED,
+    REASON_SPAWN_FAILED,
     REASON_SUCCESS,
     STATE_RUNNING,
     STATE_STOPPED,
@@ -112,7 +114,7 @@ command = "/bin/this_does_not_exist"
 
     test = status.get("test")
     assert test.state == STATE_STOPPED
-    assert test.pid_or_reason == f"{REASON_ERROR}(127)"
+    assert test.pid_or_reason == f"{REASON_SPAWN_FAILED}({errno.ENOENT})"
 
 
 def test_service_start_fail_missing_permission(tmp_path, run_dir, svlopp_proc):
@@ -139,7 +141,7 @@ command = "/etc/shadow"
 
     test = status.get("test")
     assert test.state == STATE_STOPPED
-    assert test.pid_or_reason == f"{REASON_ERROR}(127)"
+    assert test.pid_or_reason == f"{REASON_SPAWN_FAILED}({errno.EACCES})"
 
 
 def test_service_start_fail_working_dir_does_not_exist(
@@ -172,7 +174,7 @@ working_directory = "/does/not/exist"
 
     test = status.get("test")
     assert test.state == STATE_STOPPED
-    assert test.pid_or_reason == f"{REASON_ERROR}(111)"
+    assert test.pid_or_reason == f"{REASON_SPAWN_FAILED}({errno.ENOENT})"
 
 
 def test_service_signaled(tmp_path, run_dir, svlopp_proc):
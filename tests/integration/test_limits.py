@@ -0,0 +1,73 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import time
+
+from constants import CONFIG_FILE_NAME, START_OPCDOE
+from helpers.control_fifo import send_control_op
+from helpers.status_file import read_status
+from helpers.utils import status_matches, wait_until
+
+
+def test_max_services(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+max_services = 2
+
+[services.a]
+command = "sleep"
+args = ["60"]
+
+[services.b]
+command = "sleep"
+args = ["60"]
+
+[services.c]
+command = "sleep"
+args = ["60"]
+"""
+    )
+    svlopp_proc(config_path)
+
+    def running(status):
+        return [name for name in "abc" if status.is_running(name)]
+
+    wait_until(status_matches(run_dir, lambda s: len(running(s)) == 2), timeout=2.0)
+    time.sleep(0.5)
+    status = read_status(run_dir)
+    assert len(running(status)) == 2
+    [refused] = [name for name in "abc" if status.is_stopped(name)]
+
+    # still refused when asked explicitly
+    send_control_op(run_dir, START_OPCDOE, status.get(refused).service_id)
+    time.sleep(0.5)
+    assert read_status(run_dir).is_stopped(refused)
+
+
+def test_max_total_processes(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+max_total_processes = 3
+
+[services.forks]
+command = "sh"
+args = ["-c", "sleep 60 & sleep 60 & wait"]
+
+[services.other]
+command = "sleep"
+args = ["60"]
+autostart = false
+"""
+    )
+    svlopp_proc(config_path)
+
+    wait_until(status_matches(run_dir, lambda s: s.is_running("forks")), timeout=2.0)
+    # leave time for the children of `forks` to be forked
+    time.sleep(0.3)
+    other = read_status(run_dir).get("other")
+    send_control_op(run_dir, START_OPCDOE, other.service_id)
+    time.sleep(0.5)
+    assert read_status(run_dir).is_stopped("other")
@@ -0,0 +1,60 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+import shutil
+import subprocess
+
+import pytest
+
+from constants import CONFIG_FILE_NAME
+from helpers.utils import wait_until
+
+NET_STATS_FILE_NAME = "net"
+
+
+def _net_lines(run_dir):
+    try:
+        return (run_dir / NET_STATS_FILE_NAME).read_text().splitlines()
+    except FileNotFoundError:
+        return None
+
+
+def test_net_stats_skip_shared_namespace(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "sleep"
+args = ["60"]
+"""
+    )
+
+    _ = svlopp_proc(config_path, "--net-stats")
+
+    wait_until(lambda: _net_lines(run_dir) is not None, timeout=2.0)
+    assert _net_lines(run_dir) == []
+
+
+def test_net_stats_of_own_namespace(tmp_path, run_dir, svlopp_proc):
+    unshare = shutil.which("unshare")
+    if unshare is None or subprocess.run([unshare, "-n", "true"]).returncode != 0:
+        pytest.skip("needs to create network namespaces")
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        f"""
+[services.test]
+command = "{unshare}"
+args = ["-n", "sleep", "60"]
+"""
+    )
+
+    _ = svlopp_proc(config_path, "--net-stats")
+
+    wait_until(lambda: _net_lines(run_dir), timeout=3.0)
+    fields = _net_lines(run_dir)[0].split()
+    assert fields[0] == "test"
+    assert int(fields[1]) != os.stat("/proc/self/ns/net").st_ino
+    # only the loopback interface, which is not counted
+    assert fields[2:] == ["0", "0", "0", "0"]
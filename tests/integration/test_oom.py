@@ -0,0 +1,61 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+import signal
+
+import pytest
+
+from constants import CONFIG_FILE_NAME, REASON_KILLED, REASON_OOM_KILLED
+from helpers.status_file import read_status
+from helpers.utils import status_matches, wait_until
+
+SLEEP_CONFIG = """
+[services.test]
+command = "sleep"
+args = ["60"]
+"""
+
+
+def _running_pid(run_dir):
+    wait_until(status_matches(run_dir, lambda s: s.is_running("test")), timeout=2.0)
+    return int(read_status(run_dir).get("test").pid_or_reason)
+
+
+def test_oom_kill_is_reported(tmp_path, run_dir, svlopp_proc):
+    if not os.access("/dev/kmsg", os.R_OK | os.W_OK):
+        pytest.skip("needs access to the kernel log")
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(SLEEP_CONFIG)
+
+    _ = svlopp_proc(config_path)
+    pid = _running_pid(run_dir)
+
+    # what the OOM killer logs, before the victim is reaped
+    with open("/dev/kmsg", "w") as kmsg:
+        kmsg.write(f"Out of memory: Killed process {pid} (sleep)\n")
+    os.kill(pid, signal.SIGKILL)
+
+    wait_until(
+        status_matches(
+            run_dir, lambda s: s.get("test").pid_or_reason == REASON_OOM_KILLED
+        ),
+        timeout=2.0,
+    )
+
+
+def test_plain_sigkill_is_not_oom(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(SLEEP_CONFIG)
+
+    _ = svlopp_proc(config_path)
+    pid = _running_pid(run_dir)
+    os.kill(pid, signal.SIGKILL)
+
+    wait_until(
+        status_matches(
+            run_dir, lambda s: s.get("test").pid_or_reason == f"{REASON_KILLED}(9)"
+        ),
+        timeout=2.0,
+    )
@@ -0,0 +1,158 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+from pathlib import Path
+
+from constants import (
+    CONFIG_FILE_NAME,
+    PAUSE_OPCODE,
+    REASON_SIGNALED,
+    REASON_SUPERVISOR_TERMINATED,
+    RESUME_OPCODE,
+    START_OPCDOE,
+    STOP_OPCODE,
+)
+from helpers.cgroup import make_cgroup, remove_cgroup
+from helpers.control_fifo import send_control_op
+from helpers.status_file import read_status
+from helpers.utils import service_state, svloppctl, wait_until
+
+
+def _proc_state(pid):
+    stat = Path(f"/proc/{pid}/stat").read_text()
+    return stat.rsplit(")", 1)[1].split()[0]
+
+
+def _frozen(cgroup_dir):
+    events = (cgroup_dir / "cgroup.events").read_text().split()
+    return events[events.index("frozen") + 1] == "1"
+
+
+def _running_test(run_dir):
+    wait_until(lambda: service_state(run_dir, "test")[0] == "running", timeout=2.0)
+    test = read_status(run_dir).get("test")
+    return test.service_id, int(test.pid_or_reason)
+
+
+def test_pause_resume_process_group(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+    svc_id, pid = _running_test(run_dir)
+
+    send_control_op(run_dir, PAUSE_OPCODE, svc_id)
+    wait_until(lambda: service_state(run_dir, "test")[0] == "paused", timeout=2.0)
+    assert read_status(run_dir).get("test").pid_or_reason == str(pid)
+    wait_until(lambda: _proc_state(pid) == "T", timeout=2.0)
+
+    send_control_op(run_dir, RESUME_OPCODE, svc_id)
+    wait_until(lambda: service_state(run_dir, "test")[0] == "running", timeout=2.0)
+    assert read_status(run_dir).get("test").pid_or_reason == str(pid)
+    wait_until(lambda: _proc_state(pid) != "T", timeout=2.0)
+
+
+def test_pause_resume_cgroup(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+cgroup_delegate = true
+"""
+    )
+
+    _, cgroup_dir = make_cgroup()
+    proc = svlopp_proc(config_path, cgroup=cgroup_dir)
+    try:
+        svc_id, _ = _running_test(run_dir)
+
+        send_control_op(run_dir, PAUSE_OPCODE, svc_id)
+        wait_until(lambda: service_state(run_dir, "test")[0] == "paused", timeout=2.0)
+        wait_until(lambda: _frozen(cgroup_dir / "test"), timeout=2.0)
+
+        send_control_op(run_dir, RESUME_OPCODE, svc_id)
+        wait_until(lambda: service_state(run_dir, "test")[0] == "running", timeout=2.0)
+        wait_until(lambda: not _frozen(cgroup_dir / "test"), timeout=2.0)
+    finally:
+        proc.terminate()
+        proc.wait(timeout=5)
+        remove_cgroup(cgroup_dir)
+
+
+def test_stop_paused(tmp_path, run_dir, svlopp_proc):
+    # killed after the timeout if the stop signal isn't handled
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+stop_timeout_ms = 10000
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+    svc_id, _ = _running_test(run_dir)
+
+    send_control_op(run_dir, PAUSE_OPCODE, svc_id)
+    wait_until(lambda: service_state(run_dir, "test")[0] == "paused", timeout=2.0)
+    send_control_op(run_dir, STOP_OPCODE, svc_id)
+    wait_until(lambda: service_state(run_dir, "test")[0] == "stopped", timeout=2.0)
+
+    assert (
+        read_status(run_dir).get("test").pid_or_reason
+        == f"{REASON_SUPERVISOR_TERMINATED}({REASON_SIGNALED}(15))"
+    )
+
+
+def test_pause_stopped_service(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+autostart = false
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+    wait_until(lambda: service_state(run_dir, "test")[0] == "stopped", timeout=2.0)
+    svc_id = read_status(run_dir).get("test").service_id
+
+    send_control_op(run_dir, PAUSE_OPCODE, svc_id)
+    send_control_op(run_dir, START_OPCDOE, svc_id)
+    wait_until(lambda: service_state(run_dir, "test")[0] == "running", timeout=2.0)
+
+
+def test_svloppctl_pause_resume(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+    _, pid = _running_test(run_dir)
+
+    def status_row():
+        return svloppctl(run_dir, "status", "--no-color").stdout.splitlines()[1].split()[:3]
+
+    assert svloppctl(run_dir, "pause", "test").returncode == 0
+    # the snapshot svloppctl reads is written after the status file
+    wait_until(lambda: status_row() == ["test", "paused", str(pid)], timeout=2.0)
+
+    assert svloppctl(run_dir, "resume", "test").returncode == 0
+    wait_until(lambda: service_state(run_dir, "test")[0] == "running", timeout=2.0)
@@ -0,0 +1,43 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import socket
+import time
+
+from constants import CONFIG_FILE_NAME, START_OPCDOE
+from helpers.control_fifo import send_control_op
+from helpers.status_file import read_status
+from helpers.utils import status_matches, wait_until
+
+
+def test_port_in_use_prevents_start(tmp_path, run_dir, svlopp_proc):
+    holder = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
+    holder.bind(("127.0.0.1", 0))
+    holder.listen()
+    port = holder.getsockname()[1]
+
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        f"""
+[services.web]
+command = "sleep"
+args = ["60"]
+ports = [{port}]
+
+[services.other]
+command = "sleep"
+args = ["60"]
+ports = ["{port}/udp"]
+"""
+    )
+    svlopp_proc(config_path)
+
+    # only the TCP port is held
+    wait_until(status_matches(run_dir, lambda s: s.is_running("other")), timeout=2.0)
+    time.sleep(0.3)
+    assert read_status(run_dir).is_stopped("web")
+
+    holder.close()
+    send_control_op(run_dir, START_OPCDOE, read_status(run_dir).get("web").service_id)
+    wait_until(status_matches(run_dir, lambda s: s.is_running("web")), timeout=2.0)
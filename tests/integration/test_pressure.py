@@ -0,0 +1,81 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+from pathlib import Path
+
+import pytest
+
+from constants import CONFIG_FILE_NAME
+from helpers.utils import wait_until
+
+PRESSURE_FILE_NAME = "pressure"
+
+
+def _cgroup2_mount():
+    for line in Path("/proc/self/mountinfo").read_text().splitlines():
+        mount, _, fs = line.partition(" - ")
+        if fs.startswith("cgroup2 "):
+            return Path(mount.split()[4])
+    return None
+
+
+def _own_cgroup():
+    for line in Path("/proc/self/cgroup").read_text().splitlines():
+        if line.startswith("0::"):
+            return line[3:]
+    return None
+
+
+def _pressure_lines(run_dir):
+    try:
+        return (run_dir / PRESSURE_FILE_NAME).read_text().splitlines()
+    except FileNotFoundError:
+        return None
+
+
+def test_pressure_file_empty_without_own_cgroup(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "sleep"
+args = ["60"]
+"""
+    )
+
+    _ = svlopp_proc(config_path, "--pressure")
+
+    wait_until(lambda: _pressure_lines(run_dir) is not None, timeout=2.0)
+    assert _pressure_lines(run_dir) == []
+
+
+def test_pressure_of_service_cgroup(tmp_path, run_dir, svlopp_proc):
+    mount = _cgroup2_mount()
+    own = _own_cgroup()
+    if mount is None or own is None or not os.access(mount / own.lstrip("/"), os.W_OK):
+        pytest.skip("needs a writable cgroup v2 hierarchy")
+    cgroup = f"{own.rstrip('/')}/svlopp-test-{os.getpid()}"
+    cgroup_dir = mount / cgroup.lstrip("/")
+    cgroup_dir.mkdir()
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "echo $$ > {cgroup_dir}/cgroup.procs; exec sleep 60"]
+"""
+    )
+
+    proc = svlopp_proc(config_path, "--pressure")
+    try:
+        wait_until(lambda: _pressure_lines(run_dir), timeout=3.0)
+        fields = _pressure_lines(run_dir)[0].split()
+        assert fields[:2] == ["test", cgroup]
+        assert len(fields) == 6
+        assert all(float(value) >= 0.0 for value in fields[2:])
+    finally:
+        proc.terminate()
+        proc.wait(timeout=5)
+        cgroup_dir.rmdir()
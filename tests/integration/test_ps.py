@@ -0,0 +1,101 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import subprocess
+
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+from constants import CONFIG_FILE_NAME, SVLOPPCTL_BINARY_PATH, STOP_OPCODE
+from helpers.control_fifo import send_control_op
+
+
+def ps(run_dir, name) -> subprocess.CompletedProcess:
+    return subprocess.run(
+        [SVLOPPCTL_BINARY_PATH, "--run-dir", str(run_dir), "ps", name],
+        capture_output=True,
+        text=True,
+    )
+
+
+def _wait_running(run_dir, name):
+    def is_running():
+        try:
+            return read_status(run_dir).is_running(name)
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_running, timeout=1.0)
+
+
+def test_ps_process_tree(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "/bin/sleep 10 & /bin/sleep 10 & wait"]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+    _wait_running(run_dir, "test")
+    pid = read_status(run_dir).get("test").pid_or_reason
+
+    def has_workers():
+        out = ps(run_dir, "test")
+        return out.returncode == 0 and out.stdout.count("sleep") == 2
+
+    wait_until(has_workers, timeout=2.0)
+
+    header, root, *workers = ps(run_dir, "test").stdout.splitlines()
+    assert header.split() == ["PID", "RSS(KiB)", "CPU(ms)", "COMMAND"]
+    assert root.split()[0] == pid
+    assert root.split()[-1] == "sh"
+    for worker in workers:
+        # children are indented under their parent
+        assert worker.endswith("  sleep")
+        assert int(worker.split()[1]) > 0
+
+
+def test_ps_stopped_service(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+    _wait_running(run_dir, "test")
+
+    svc_id = read_status(run_dir).get("test").service_id
+    send_control_op(run_dir, STOP_OPCODE, svc_id)
+    wait_until(lambda: read_status(run_dir).is_stopped("test"), timeout=2.0)
+
+    out = ps(run_dir, "test")
+    assert out.returncode != 0
+    assert "not running" in out.stderr
+
+
+def test_ps_unknown_service(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+    _wait_running(run_dir, "test")
+
+    out = ps(run_dir, "missing")
+    assert out.returncode != 0
+    assert "unknown service" in out.stderr
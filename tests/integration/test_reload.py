@@ -2,16 +2,22 @@
 # License, v. 2.0. If a copy of the MPL was not distributed with this
 # file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+import json
 import os
 import signal
+import subprocess
+import time
 
 from helpers.status_file import read_status
-from helpers.utils import wait_until, pid_exists
+from helpers.utils import is_running, pid_exists, wait_until
 from constants import (
     STATE_RUNNING,
     CONFIG_FILE_NAME,
+    SVLOPPCTL_BINARY_PATH,
 )
 
+RELOAD_FILE_NAME = "reload"
+
 
 def test_reload_add_service(tmp_path, run_dir, svlopp_proc):
     config_path = tmp_path / CONFIG_FILE_NAME
@@ -381,3 +387,272 @@ args = ["12"]
     assert a.state == STATE_RUNNING
     assert pid_exists(int(a.pid_or_reason))
     assert int(a.pid_or_reason) == a_pid
+
+
+def _reload_report(run_dir):
+    try:
+        return json.loads((run_dir / RELOAD_FILE_NAME).read_text())
+    except FileNotFoundError:
+        return None
+
+
+def test_reload_report(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.a]
+command = "/bin/sleep"
+args = ["10"]
+
+[services.b]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+    proc = svlopp_proc(config_path)
+
+    def both_running():
+        try:
+            status = read_status(run_dir)
+            return status.is_running("a") and status.is_running("b")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(both_running, timeout=2.0)
+
+    config_path.write_text(
+        """
+[services.a]
+command = "/bin/sleep"
+args = ["20"]
+
+[services.c]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+    os.kill(proc.pid, signal.SIGHUP)
+
+    wait_until(lambda: _reload_report(run_dir) is not None, timeout=2.0)
+    report = _reload_report(run_dir)
+    assert report["ok"] is True
+    assert report["rolled_back"] is False
+    assert report["added"] == ["c"]
+    assert report["removed"] == ["b"]
+    assert report["updated"] == ["a"]
+    assert sorted(report["stopped"]) == ["a", "b"]
+
+
+def test_reload_invalid_service_changes_nothing(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.a]
+command = "/bin/sleep"
+args = ["10"]
+
+[services.b]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+    proc = svlopp_proc(config_path)
+
+    def both_running():
+        try:
+            status = read_status(run_dir)
+            return status.is_running("a") and status.is_running("b")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(both_running, timeout=2.0)
+    before = read_status(run_dir)
+
+    # `a` would be restarted and `b` removed, but `c` can't be built: its
+    # argument holds a NUL byte
+    config_path.write_text(
+        """
+[services.a]
+command = "/bin/sleep"
+args = ["20"]
+
+[services.c]
+command = "/bin/sleep"
+args = ["1\\u00000"]
+"""
+    )
+    os.kill(proc.pid, signal.SIGHUP)
+
+    wait_until(lambda: _reload_report(run_dir) is not None, timeout=2.0)
+    report = _reload_report(run_dir)
+    assert report["ok"] is False
+    assert "'c'" in report["error"]
+
+    time.sleep(0.3)
+    status = read_status(run_dir)
+    assert not status.has("c")
+    for name in ("a", "b"):
+        assert status.get(name).state == STATE_RUNNING
+        assert status.get(name).pid_or_reason == before.get(name).pid_or_reason
+
+
+def test_reload_report_changes(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.a]
+command = "/bin/sleep"
+args = ["10"]
+
+[services.b]
+command = "/bin/sleep"
+args = ["10"]
+
+[services.manual]
+command = "/bin/sleep"
+args = ["10"]
+autostart = false
+"""
+    )
+    proc = svlopp_proc(config_path)
+
+    def both_running():
+        try:
+            status = read_status(run_dir)
+            return status.is_running("a") and status.is_running("b")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(both_running, timeout=2.0)
+
+    config_path.write_text(
+        """
+[services.a]
+command = "/bin/sleep"
+args = ["20"]
+stop_timeout_ms = 1000
+
+[services.c]
+command = "/bin/sleep"
+args = ["10"]
+
+[services.manual]
+command = "/bin/sleep"
+args = ["20"]
+autostart = false
+"""
+    )
+    os.kill(proc.pid, signal.SIGHUP)
+
+    wait_until(lambda: _reload_report(run_dir) is not None, timeout=2.0)
+    report = _reload_report(run_dir)
+    assert report["ok"] is True
+    assert report["changes"] == [
+        {
+            "service": "a",
+            "change": "changed",
+            "fields": ["args", "stop_timeout_ms"],
+            "action": "restart",
+        },
+        {"service": "b", "change": "removed", "fields": [], "action": "stop"},
+        {"service": "c", "change": "added", "fields": [], "action": "start"},
+        {
+            "service": "manual",
+            "change": "changed",
+            "fields": ["args"],
+            "action": "none",
+        },
+    ]
+
+
+def test_reload_unchanged_reports_no_changes(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.a]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+    proc = svlopp_proc(config_path)
+    wait_until(lambda: is_running(run_dir, "a"), timeout=2.0)
+
+    os.kill(proc.pid, signal.SIGHUP)
+
+    wait_until(lambda: _reload_report(run_dir) is not None, timeout=2.0)
+    report = _reload_report(run_dir)
+    assert report["ok"] is True
+    assert report["changes"] == []
+
+
+def _svloppctl_reload(run_dir) -> subprocess.CompletedProcess:
+    return subprocess.run(
+        [SVLOPPCTL_BINARY_PATH, "--run-dir", str(run_dir), "reload"],
+        capture_output=True,
+        text=True,
+        timeout=5,
+    )
+
+
+def test_svloppctl_reload(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.a]
+command = "/bin/sleep"
+args = ["10"]
+
+[services.b]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+    svlopp_proc(config_path)
+
+    def both_running():
+        try:
+            status = read_status(run_dir)
+            return status.is_running("a") and status.is_running("b")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(both_running, timeout=2.0)
+
+    config_path.write_text(
+        """
+[services.a]
+command = "/bin/sleep"
+args = ["20"]
+
+[services.b]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+    result = _svloppctl_reload(run_dir)
+    assert result.returncode == 0, result.stderr
+    assert result.stdout.split() == ["changed", "a", "restart", "args"]
+
+    wait_until(lambda: is_running(run_dir, "a"), timeout=3.0)
+
+    result = _svloppctl_reload(run_dir)
+    assert result.returncode == 0, result.stderr
+    assert result.stdout == "nothing changed\n"
+
+
+def test_svloppctl_reload_failure(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.a]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+    svlopp_proc(config_path)
+    wait_until(lambda: is_running(run_dir, "a"), timeout=2.0)
+
+    config_path.write_text("[services.a\n")
+    result = _svloppctl_reload(run_dir)
+    assert result.returncode != 0
+    assert "reload failed" in result.stderr
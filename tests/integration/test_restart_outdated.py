@@ -0,0 +1,102 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+from constants import CONFIG_FILE_NAME
+from helpers.status_file import read_status
+from helpers.utils import start_svlopp, svloppctl, wait_until
+
+RESTART_REQUIRED = "restart_required"
+
+
+def _running(run_dir, name):
+    try:
+        status = read_status(run_dir)
+        if not status.is_running(name):
+            return None
+        return status.get(name)
+    except (FileNotFoundError, KeyError):
+        return None
+
+
+def _config(policy, sleep_args):
+    return f"""
+reload_policy = "{policy}"
+
+[services.test]
+command = "/bin/sleep"
+args = {sleep_args}
+
+[services.other]
+command = "/bin/sleep"
+args = ["10"]
+"""
+
+
+def _start(tmp_path, run_dir, svlopp_proc, policy):
+    config = _config(policy, '["10"]')
+    start_svlopp(tmp_path, run_dir, svlopp_proc, config, ["test", "other"])
+    return tmp_path / CONFIG_FILE_NAME, _running(run_dir, "test")
+
+
+def _reload(run_dir, config_path, policy, sleep_args):
+    config_path.write_text(_config(policy, sleep_args))
+    result = svloppctl(run_dir, "reload")
+    assert result.returncode == 0, result.stderr
+
+
+def test_reload_defer_flags_restart_required(tmp_path, run_dir, svlopp_proc):
+    config_path, test = _start(tmp_path, run_dir, svlopp_proc, "defer")
+    assert test.flags == []
+
+    _reload(run_dir, config_path, "defer", '["11"]')
+    wait_until(
+        lambda: _running(run_dir, "test").flags == [RESTART_REQUIRED], timeout=2.0
+    )
+    assert _running(run_dir, "test").pid_or_reason == test.pid_or_reason
+    assert _running(run_dir, "other").flags == []
+
+
+def test_reload_defer_changed_back(tmp_path, run_dir, svlopp_proc):
+    config_path, test = _start(tmp_path, run_dir, svlopp_proc, "defer")
+
+    _reload(run_dir, config_path, "defer", '["11"]')
+    wait_until(
+        lambda: _running(run_dir, "test").flags == [RESTART_REQUIRED], timeout=2.0
+    )
+    _reload(run_dir, config_path, "defer", '["10"]')
+    wait_until(lambda: _running(run_dir, "test").flags == [], timeout=2.0)
+    assert _running(run_dir, "test").pid_or_reason == test.pid_or_reason
+
+
+def test_restart_outdated(tmp_path, run_dir, svlopp_proc):
+    config_path, test = _start(tmp_path, run_dir, svlopp_proc, "defer")
+    other = _running(run_dir, "other")
+
+    _reload(run_dir, config_path, "defer", '["11"]')
+    wait_until(
+        lambda: _running(run_dir, "test").flags == [RESTART_REQUIRED], timeout=2.0
+    )
+
+    assert svloppctl(run_dir, "restart", "--outdated").returncode == 0
+
+    def restarted():
+        svc = _running(run_dir, "test")
+        return svc is not None and svc.pid_or_reason != test.pid_or_reason
+
+    wait_until(restarted, timeout=3.0)
+    assert _running(run_dir, "test").flags == []
+    assert _running(run_dir, "other").pid_or_reason == other.pid_or_reason
+
+
+def test_reload_restart_policy_restarts(tmp_path, run_dir, svlopp_proc):
+    config_path, test = _start(tmp_path, run_dir, svlopp_proc, "restart")
+
+    _reload(run_dir, config_path, "restart", '["11"]')
+
+    def restarted():
+        svc = _running(run_dir, "test")
+        return svc is not None and svc.pid_or_reason != test.pid_or_reason
+
+    wait_until(restarted, timeout=3.0)
+    assert _running(run_dir, "test").flags == []
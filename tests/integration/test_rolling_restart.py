@@ -0,0 +1,129 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import socket
+import time
+
+from helpers.utils import service_state, start_svlopp, svloppctl, wait_until
+
+ROLLOUT_FILE_NAME = "rollout"
+
+INSTANCE = """
+[services."{name}"]
+command = "/bin/sh"
+args = ["-c", "{script}"]
+{extra}
+"""
+# logs each start of the instance
+LOG_SCRIPT = "echo $(date +%s%N) {name} >> {output}; exec sleep 100"
+# a python3 listener, binding its port a while after starting
+LISTEN_SCRIPT = (
+    "echo $(date +%s%N) {name} >> {output}; sleep 2; exec python3 -c '"
+    "import socket, time; s = socket.socket(); "
+    's.bind((\\"127.0.0.1\\", {port})); s.listen(); time.sleep(100)'
+    "'"
+)
+# fails to come back once restarted
+FAILING_SCRIPT = "[ -e {marker} ] && exit 1; touch {marker}; exec sleep 100"
+
+
+def _rollout(run_dir):
+    try:
+        return (run_dir / ROLLOUT_FILE_NAME).read_text().split()
+    except FileNotFoundError:
+        return []
+
+
+def _start(tmp_path, run_dir, svlopp_proc, instances):
+    """Start svlopp with `instances`, name to (script, extra config) pairs"""
+    config = "".join(
+        INSTANCE.format(name=name, script=script, extra=extra)
+        for name, (script, extra) in instances.items()
+    )
+    start_svlopp(tmp_path, run_dir, svlopp_proc, config, instances)
+    return {name: service_state(run_dir, name)[1] for name in instances}
+
+
+def _restarts(output_path):
+    """Time of the second start of each instance, in nanoseconds"""
+    starts = {}
+    for line in output_path.read_text().splitlines():
+        time_ns, name = line.split()
+        starts.setdefault(name, []).append(int(time_ns))
+    return {name: times[1] for name, times in starts.items() if len(times) > 1}
+
+
+def _free_port():
+    with socket.socket() as sock:
+        sock.bind(("127.0.0.1", 0))
+        return sock.getsockname()[1]
+
+
+def test_rolling_restart_one_at_a_time(tmp_path, run_dir, svlopp_proc):
+    output = tmp_path / "output"
+    names = ["web@a", "web@b", "web@c"]
+    instances = {
+        name: (LOG_SCRIPT.format(name=name, output=output), "") for name in names
+    }
+    instances["other"] = ("exec sleep 100", "")
+    pids = _start(tmp_path, run_dir, svlopp_proc, instances)
+
+    assert svloppctl(run_dir, "rolling-restart", "web").returncode == 0
+    wait_until(lambda: _rollout(run_dir)[:2] == ["web", "running"], timeout=2.0)
+    wait_until(lambda: _rollout(run_dir) == ["web", "done", "3/3", "-"], timeout=10.0)
+
+    restarts = _restarts(output)
+    times = [restarts[name] for name in names]
+    # each one once the previous one ran for a second
+    assert times[1] - times[0] >= 900_000_000
+    assert times[2] - times[1] >= 900_000_000
+    for name in names:
+        assert service_state(run_dir, name)[0] == "running"
+        assert service_state(run_dir, name)[1] != pids[name]
+    assert service_state(run_dir, "other") == ("running", pids["other"])
+
+
+def test_rolling_restart_waits_for_ports(tmp_path, run_dir, svlopp_proc):
+    output = tmp_path / "output"
+    names = ["web@a", "web@b"]
+    instances = {}
+    for name in names:
+        port = _free_port()
+        script = LISTEN_SCRIPT.format(name=name, output=output, port=port)
+        instances[name] = (script, f"ports = [{port}]")
+    _start(tmp_path, run_dir, svlopp_proc, instances)
+
+    assert svloppctl(run_dir, "rolling-restart", "web@b").returncode == 0
+    wait_until(lambda: _rollout(run_dir) == ["web", "done", "2/2", "-"], timeout=15.0)
+
+    restarts = _restarts(output)
+    # the second one once the first one listens, two seconds later
+    assert restarts["web@b"] - restarts["web@a"] >= 1_900_000_000
+
+
+def test_rolling_restart_aborts_on_failure(tmp_path, run_dir, svlopp_proc):
+    marker = tmp_path / "marker"
+    instances = {
+        "web@a": ("exec sleep 100", ""),
+        "web@b": (FAILING_SCRIPT.format(marker=marker), ""),
+        "web@c": ("exec sleep 100", ""),
+    }
+    pids = _start(tmp_path, run_dir, svlopp_proc, instances)
+
+    assert svloppctl(run_dir, "rolling-restart", "web").returncode == 0
+    wait_until(
+        lambda: _rollout(run_dir) == ["web", "aborted", "1/3", "web@b"], timeout=10.0
+    )
+    # the instances left are left as they are
+    time.sleep(1.5)
+    assert service_state(run_dir, "web@c") == ("running", pids["web@c"])
+    assert service_state(run_dir, "web@a")[0] == "running"
+
+
+def test_rolling_restart_requires_instance(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc, {"other": ("exec sleep 100", "")})
+
+    assert svloppctl(run_dir, "rolling-restart", "other").returncode == 0
+    time.sleep(1.5)
+    assert _rollout(run_dir) == []
@@ -0,0 +1,97 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import time
+
+from constants import CONFIG_FILE_NAME
+from helpers.status_file import read_status
+from helpers.utils import service_state, start_svlopp, svloppctl, wait_until
+
+CONFIG = """
+[services.test]
+command = "/bin/sleep"
+args = ["100"]
+"""
+
+
+def _names(run_dir):
+    try:
+        return {line.service_name for line in read_status(run_dir).lines}
+    except FileNotFoundError:
+        return set()
+
+
+def _start(tmp_path, run_dir, svlopp_proc):
+    start_svlopp(tmp_path, run_dir, svlopp_proc, CONFIG, ["test"])
+    return tmp_path / CONFIG_FILE_NAME
+
+
+def test_run_removed_once_exited(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+    output = tmp_path / "output"
+
+    script = f"echo done > {output}; sleep 1"
+    result = svloppctl(
+        run_dir, "run", "--name", "batch1", "--", "/bin/sh", "-c", script
+    )
+    assert result.returncode == 0, result.stderr
+    assert result.stdout == "running as service 'batch1'\n"
+    wait_until(lambda: service_state(run_dir, "batch1")[0] == "running", timeout=2.0)
+
+    wait_until(lambda: _names(run_dir) == {"test"}, timeout=5.0)
+    assert output.read_text() == "done\n"
+
+
+def test_run_default_name(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+
+    result = svloppctl(run_dir, "run", "--", "/bin/sleep", "100")
+    assert result.returncode == 0, result.stderr
+    name = result.stdout.split("'")[1]
+    assert name.startswith("run-")
+    wait_until(lambda: service_state(run_dir, name)[0] == "running", timeout=2.0)
+
+
+def test_run_removed_once_stopped(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+
+    args = ["run", "--name", "batch1", "--", "/bin/sleep", "100"]
+    assert svloppctl(run_dir, *args).returncode == 0
+    wait_until(lambda: service_state(run_dir, "batch1")[0] == "running", timeout=2.0)
+
+    assert svloppctl(run_dir, "stop", "batch1").returncode == 0
+    wait_until(lambda: _names(run_dir) == {"test"}, timeout=5.0)
+
+
+def test_run_existing_name_rejected(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+    pid = service_state(run_dir, "test")[1]
+
+    args = ["run", "--name", "test", "--", "/bin/sleep", "50"]
+    assert svloppctl(run_dir, *args).returncode == 0
+    time.sleep(1.5)
+    assert _names(run_dir) == {"test"}
+    assert service_state(run_dir, "test") == ("running", pid)
+
+
+def test_reload_keeps_transient_service(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+
+    args = ["run", "--name", "batch1", "--", "/bin/sleep", "100"]
+    assert svloppctl(run_dir, *args).returncode == 0
+    wait_until(lambda: service_state(run_dir, "batch1")[0] == "running", timeout=2.0)
+    pid = service_state(run_dir, "batch1")[1]
+
+    result = svloppctl(run_dir, "reload")
+    assert result.returncode == 0, result.stderr
+    time.sleep(1.5)
+    assert service_state(run_dir, "batch1") == ("running", pid)
+
+
+def test_run_requires_command(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+
+    result = svloppctl(run_dir, "run", "--name", "batch1", "--")
+    assert result.returncode != 0
+    assert "run requires a command" in result.stderr
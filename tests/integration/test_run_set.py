@@ -0,0 +1,96 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import subprocess
+
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+from constants import CONFIG_FILE_NAME, SVLOPPCTL_BINARY_PATH
+
+RUN_SET_FILE_NAME = "runset"
+
+CONFIG = """
+[services.app]
+command = "sleep"
+args = ["60"]
+
+[services.shipper]
+command = "sleep"
+args = ["60"]
+bind_to = "app"
+
+[services.manual]
+command = "sleep"
+args = ["60"]
+autostart = false
+"""
+
+
+def svloppctl(run_dir, *args) -> subprocess.CompletedProcess:
+    result = subprocess.run(
+        [SVLOPPCTL_BINARY_PATH, "--run-dir", str(run_dir), *args],
+        capture_output=True,
+        text=True,
+        timeout=5,
+    )
+    assert result.returncode == 0, result.stderr
+    return result
+
+
+def _running(run_dir, names):
+    def check():
+        try:
+            status = read_status(run_dir)
+            return all(status.is_running(n) for n in names)
+        except (FileNotFoundError, KeyError):
+            return False
+
+    return check
+
+
+def _stopped(run_dir, names):
+    def check():
+        try:
+            status = read_status(run_dir)
+            return all(status.is_stopped(n) for n in names)
+        except (FileNotFoundError, KeyError):
+            return False
+
+    return check
+
+
+def _start(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(CONFIG)
+    svlopp_proc(config_path)
+    wait_until(_running(run_dir, ["app", "shipper"]), timeout=3.0)
+
+
+def test_snapshot_and_restore(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+
+    result = svloppctl(run_dir, "snapshot")
+    assert result.stdout.splitlines()[0] == "saved 2 running services"
+    assert (run_dir / RUN_SET_FILE_NAME).read_text() == "app\nshipper\n"
+
+    # maintenance: stopping app stops shipper along with it
+    svloppctl(run_dir, "stop", "app")
+    wait_until(_stopped(run_dir, ["app", "shipper"]), timeout=3.0)
+    svloppctl(run_dir, "start", "manual")
+    wait_until(_running(run_dir, ["manual"]), timeout=2.0)
+
+    svloppctl(run_dir, "restore")
+    wait_until(_running(run_dir, ["app", "shipper", "manual"]), timeout=3.0)
+
+
+def test_restore_exact(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc)
+    svloppctl(run_dir, "snapshot")
+    svloppctl(run_dir, "start", "manual")
+    wait_until(_running(run_dir, ["manual"]), timeout=2.0)
+
+    svloppctl(run_dir, "restore", "--exact")
+
+    wait_until(_stopped(run_dir, ["manual"]), timeout=3.0)
+    assert _running(run_dir, ["app", "shipper"])()
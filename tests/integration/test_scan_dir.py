@@ -0,0 +1,109 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+from helpers.control_fifo import send_control_op
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+from constants import CONFIG_FILE_NAME, START_OPCDOE
+
+
+def _write_script(path, body):
+    path.write_text(f"#!/bin/sh\n{body}\n")
+    path.chmod(0o755)
+
+
+def _make_service(scan_dir, name, run, finish=None, down=False):
+    svc_dir = scan_dir / name
+    svc_dir.mkdir(parents=True)
+    _write_script(svc_dir / "run", run)
+    if finish is not None:
+        _write_script(svc_dir / "finish", finish)
+    if down:
+        (svc_dir / "down").touch()
+    return svc_dir
+
+
+def test_scan_dir_services(tmp_path, run_dir, svlopp_proc):
+    scan_dir = tmp_path / "service"
+    _make_service(scan_dir, "up", "pwd > cwd; exec sleep 10")
+    _make_service(scan_dir, "down", "exec sleep 10", down=True)
+    (scan_dir / "no_run").mkdir()
+
+    _ = svlopp_proc(None, "--scan-dir", str(scan_dir))
+
+    wait_until(lambda: (scan_dir / "up" / "cwd").exists(), timeout=2.0)
+    status = read_status(run_dir)
+
+    assert status.is_running("up")
+    assert (scan_dir / "up" / "cwd").read_text().strip() == str(scan_dir / "up")
+    assert status.get("down").pid_or_reason == "never_started"
+    assert len(status.lines) == 2
+
+    send_control_op(run_dir, START_OPCDOE, status.get("down").service_id)
+
+    wait_until(lambda: read_status(run_dir).is_running("down"), timeout=2.0)
+
+
+def test_scan_dir_finish(tmp_path, run_dir, svlopp_proc):
+    scan_dir = tmp_path / "service"
+    svc_dir = _make_service(
+        scan_dir,
+        "test",
+        "echo run >> runs; exit 3",
+        finish='echo "$@" >> finished',
+    )
+
+    _ = svlopp_proc(None, "--scan-dir", str(scan_dir))
+
+    def runs():
+        try:
+            return len((svc_dir / "runs").read_text().splitlines())
+        except FileNotFoundError:
+            return 0
+
+    # `run` is restarted after `finish`
+    wait_until(lambda: runs() >= 2, timeout=5.0)
+
+    assert (svc_dir / "finished").read_text().splitlines()[0] == "3 0"
+
+
+def test_scan_dir_with_config_file(tmp_path, run_dir, svlopp_proc):
+    scan_dir = tmp_path / "service"
+    _make_service(scan_dir, "scanned", "exec sleep 10")
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.configured]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    _ = svlopp_proc(config_path, "--scan-dir", str(scan_dir))
+
+    def both_running():
+        try:
+            status = read_status(run_dir)
+            return status.is_running("scanned") and status.is_running("configured")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(both_running, timeout=2.0)
+
+
+def test_scan_dir_name_conflict(tmp_path, svlopp_proc):
+    scan_dir = tmp_path / "service"
+    _make_service(scan_dir, "test", "exec sleep 10")
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    proc = svlopp_proc(config_path, "--scan-dir", str(scan_dir))
+
+    assert proc.wait(timeout=2.0) != 0
@@ -0,0 +1,200 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import pytest
+
+from constants import CONFIG_FILE_NAME
+from helpers.cgroup import has_controller, make_cgroup, remove_cgroup
+from helpers.status_file import read_status
+from helpers.utils import is_running, svloppctl, wait_until
+
+PROPERTIES_FILE_NAME = "properties"
+PROPERTY_DIR_NAME = "property.d"
+
+
+def _read_properties(state_dir):
+    try:
+        return (state_dir / PROPERTIES_FILE_NAME).read_text().splitlines()
+    except FileNotFoundError:
+        return None
+
+
+def test_set_property_persisted(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.idle]
+command = "/bin/sleep"
+args = ["10"]
+autostart = false
+
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    state_dir = tmp_path / "state"
+    _ = svlopp_proc(config_path, "--state-dir", str(state_dir))
+    wait_until(lambda: is_running(run_dir, "test"), timeout=2.0)
+
+    assert svloppctl(run_dir, "set-property", "test", "cpu.max=50%").returncode == 0
+    wait_until(lambda: _read_properties(state_dir) == ["test cpu.max=50%"], timeout=2.0)
+    assert not any((run_dir / PROPERTY_DIR_NAME).iterdir())
+
+    assert svloppctl(run_dir, "set-property", "test", "cpu.max=max").returncode == 0
+    wait_until(lambda: _read_properties(state_dir) == [], timeout=2.0)
+
+
+def test_set_property_survives_restart(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.idle]
+command = "/bin/sleep"
+args = ["10"]
+autostart = false
+
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    state_dir = tmp_path / "state"
+    state_dir.mkdir()
+    (state_dir / PROPERTIES_FILE_NAME).write_text("idle cpu.max=25%\ngone cpu.max=10%\n")
+
+    _ = svlopp_proc(config_path, "--state-dir", str(state_dir))
+    wait_until(lambda: is_running(run_dir, "test"), timeout=2.0)
+    assert svloppctl(run_dir, "set-property", "test", "cpu.max=150%").returncode == 0
+
+    # services that don't exist anymore are dropped
+    wait_until(
+        lambda: sorted(_read_properties(state_dir) or [])
+        == ["idle cpu.max=25%", "test cpu.max=150%"],
+        timeout=2.0,
+    )
+
+
+def test_set_property_invalid(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.idle]
+command = "/bin/sleep"
+args = ["10"]
+autostart = false
+
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    state_dir = tmp_path / "state"
+    proc = svlopp_proc(config_path, "--state-dir", str(state_dir))
+    wait_until(lambda: is_running(run_dir, "test"), timeout=2.0)
+
+    for prop in ("cpu.weight=100", "cpu.max=half"):
+        assert svloppctl(run_dir, "set-property", "test", prop).returncode == 0
+        wait_until(lambda: not any((run_dir / PROPERTY_DIR_NAME).iterdir()), timeout=2.0)
+    proc.terminate()
+    proc.wait(timeout=5.0)
+
+    stderr = proc.stderr.read().decode()
+    assert "failed to set property: unknown property 'cpu.weight'" in stderr
+    assert "failed to set property: invalid cpu.max 'half'" in stderr
+    assert _read_properties(state_dir) is None
+
+
+def test_svloppctl_set_property_needs_assignment(run_dir):
+    result = svloppctl(run_dir, "set-property", "test", "cpu.max")
+    assert result.returncode != 0
+    assert "set-property requires a PROPERTY=VALUE assignment" in result.stderr
+
+
+def _run_cpu_limited(svlopp_proc, config_path):
+    _, cgroup_dir = make_cgroup()
+    if not has_controller(cgroup_dir, "cpu"):
+        remove_cgroup(cgroup_dir)
+        pytest.skip("needs the cpu controller")
+    proc = svlopp_proc(config_path, cgroup=cgroup_dir)
+    return proc, cgroup_dir
+
+
+def _cpu_max(cgroup_dir):
+    try:
+        return (cgroup_dir / "test" / "cpu.max").read_text().strip()
+    except FileNotFoundError:
+        return None
+
+
+def test_set_property_cpu_max_live(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.idle]
+command = "/bin/sleep"
+args = ["10"]
+autostart = false
+
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+cgroup_delegate = true
+"""
+    )
+
+    proc, cgroup_dir = _run_cpu_limited(svlopp_proc, config_path)
+    try:
+        wait_until(lambda: is_running(run_dir, "test"), timeout=2.0)
+        pid = read_status(run_dir).get("test").pid_or_reason
+
+        svloppctl(run_dir, "set-property", "test", "cpu.max=50%")
+        wait_until(lambda: _cpu_max(cgroup_dir) == "50000 100000", timeout=2.0)
+        svloppctl(run_dir, "set-property", "test", "cpu.max=max")
+        wait_until(lambda: _cpu_max(cgroup_dir) == "max 100000", timeout=2.0)
+        # not restarted
+        assert read_status(run_dir).get("test").pid_or_reason == pid
+    finally:
+        proc.terminate()
+        proc.wait(timeout=5)
+        remove_cgroup(cgroup_dir)
+
+
+def test_set_property_cpu_max_next_start(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.idle]
+command = "/bin/sleep"
+args = ["10"]
+autostart = false
+
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    proc, cgroup_dir = _run_cpu_limited(svlopp_proc, config_path)
+    try:
+        wait_until(lambda: is_running(run_dir, "test"), timeout=2.0)
+        pid = read_status(run_dir).get("test").pid_or_reason
+
+        # the running process has no cgroup of its own
+        svloppctl(run_dir, "set-property", "test", "cpu.max=200%")
+        svloppctl(run_dir, "restart", "test")
+
+        def restarted():
+            status = read_status(run_dir).get("test")
+            return status.state == "running" and status.pid_or_reason != pid
+
+        wait_until(restarted, timeout=3.0)
+        assert _cpu_max(cgroup_dir) == "200000 100000"
+    finally:
+        proc.terminate()
+        proc.wait(timeout=5)
+        remove_cgroup(cgroup_dir)
@@ -0,0 +1,49 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+from constants import CONFIG_FILE_NAME
+
+
+def test_shutdown_report(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    report_path = tmp_path / "shutdown_report"
+
+    config_path.write_text(
+        """
+[services.graceful]
+command = "/bin/sleep"
+args = ["10"]
+
+[services.stubborn]
+command = "/bin/sh"
+args = ["-c", "trap '' TERM; sleep 10"]
+stop_timeout_ms = 1000
+"""
+    )
+
+    proc = svlopp_proc(config_path, "--shutdown-report", str(report_path))
+
+    def are_all_running():
+        try:
+            status = read_status(run_dir)
+            return status.is_running("graceful") and status.is_running("stubborn")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(are_all_running, timeout=1.0)
+
+    proc.terminate()
+    assert proc.wait(timeout=5.0) == 0
+
+    lines = [line.split() for line in report_path.read_text().splitlines()]
+    assert [fields[0] for fields in lines] == ["graceful", "stubborn"]
+
+    graceful, stubborn = lines
+    assert graceful[3] == "graceful"
+    assert graceful[4] == "supervisor_terminated(signaled(15))"
+    assert stubborn[3] == "killed"
+    assert int(stubborn[2]) >= 1000
+    assert int(stubborn[1]) >= int(graceful[1])
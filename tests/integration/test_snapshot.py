@@ -0,0 +1,111 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+import signal
+import subprocess
+
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+from constants import (
+    CONFIG_FILE_NAME,
+    SNAPSHOT_FILE_NAME,
+    SVLOPPCTL_BINARY_PATH,
+)
+
+
+def inspect_state(run_dir) -> list[list[str]]:
+    out = subprocess.run(
+        [SVLOPPCTL_BINARY_PATH, "--run-dir", str(run_dir), "inspect-state"],
+        check=True,
+        capture_output=True,
+        text=True,
+    ).stdout
+    return [line.split() for line in out.splitlines() if not line.startswith("#")]
+
+
+def test_snapshot_matches_status(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.sleeper]
+command = "/bin/sleep"
+args = ["10"]
+
+[services.failing]
+command = "/bin/sh"
+args = ["-c", "exit 3"]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    def is_settled():
+        try:
+            status = read_status(run_dir)
+            return status.is_running("sleeper") and status.is_stopped("failing")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_settled, timeout=2.0)
+
+    status = read_status(run_dir)
+    records = {fields[0]: fields for fields in inspect_state(run_dir)}
+    assert records.keys() == {"sleeper", "failing"}
+    for name, fields in records.items():
+        line = status.get(name)
+        assert fields[1:4] == [
+            str(line.service_id),
+            line.state,
+            line.pid_or_reason,
+        ]
+        assert fields[4] == "1"
+
+
+def test_snapshot_stale_is_reported(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    proc = svlopp_proc(config_path)
+
+    def is_test_running():
+        try:
+            status = read_status(run_dir)
+            return status.is_running("test")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_running, timeout=1.0)
+
+    test = read_status(run_dir).get("test")
+    proc.kill()
+    proc.wait()
+    assert (run_dir / SNAPSHOT_FILE_NAME).exists()
+
+    try:
+        proc = svlopp_proc(config_path)
+
+        def is_test_restarted():
+            try:
+                status = read_status(run_dir)
+                return status.get("test").pid_or_reason != test.pid_or_reason
+            except (FileNotFoundError, KeyError):
+                return False
+
+        wait_until(is_test_restarted, timeout=1.0)
+        proc.terminate()
+        _, stderr = proc.communicate(timeout=5)
+    finally:
+        os.kill(int(test.pid_or_reason), signal.SIGKILL)
+
+    assert b"did not exit cleanly" in stderr
+    assert f"with pid {test.pid_or_reason}".encode() in stderr
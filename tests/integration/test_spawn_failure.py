@@ -0,0 +1,112 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import errno
+import os
+import signal
+import threading
+import time
+
+from helpers.status_file import read_status
+from helpers.utils import is_stopped, svloppctl, wait_until
+from constants import CONFIG_FILE_NAME, REASON_ERROR, REASON_SPAWN_FAILED, STATE_STOPPED
+
+
+def _stderr_after_exit(proc) -> str:
+    proc.terminate()
+    _, stderr = proc.communicate(timeout=5)
+    return stderr.decode()
+
+
+def _missing_command(tmp_path, run_dir, svlopp_proc, *extra_args):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    missing = tmp_path / "missing"
+    config_path.write_text(
+        f"""
+[services.test]
+command = "{missing}"
+on_exit = "Restart"
+"""
+    )
+    proc = svlopp_proc(config_path, *extra_args)
+    wait_until(lambda: is_stopped(run_dir, "test"), timeout=2.0)
+
+    status = read_status(run_dir).get("test")
+    assert status.state == STATE_STOPPED
+    assert status.pid_or_reason == f"{REASON_SPAWN_FAILED}({errno.ENOENT})"
+
+    # restarts would happen on the next tick
+    time.sleep(1.5)
+    assert read_status(run_dir).get("test").state == STATE_STOPPED
+    stderr = _stderr_after_exit(proc)
+    failure = f"service 'test' failed to start: exec({missing}) failed: ENOENT"
+    assert stderr.count(failure) == 1
+    assert f"not restarting service 'test', command '{missing}' not found" in stderr
+
+
+def test_missing_command_not_restarted(tmp_path, run_dir, svlopp_proc):
+    _missing_command(tmp_path, run_dir, svlopp_proc)
+
+
+def test_missing_command_with_spawner(tmp_path, run_dir, svlopp_proc):
+    _missing_command(tmp_path, run_dir, svlopp_proc, "--spawner")
+
+
+def test_exit_127_is_an_error(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sh"
+args = ["-c", "exit 127"]
+"""
+    )
+    svlopp_proc(config_path)
+    wait_until(lambda: is_stopped(run_dir, "test"), timeout=2.0)
+
+    status = read_status(run_dir).get("test")
+    assert status.pid_or_reason == f"{REASON_ERROR}(127)"
+
+
+def test_spawn_failure_while_interrupted(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    missing = tmp_path / "missing"
+    config_path.write_text(
+        f"""
+[services.test]
+command = "{missing}"
+"""
+    )
+    proc = svlopp_proc(config_path)
+    wait_until(lambda: is_stopped(run_dir, "test"), timeout=2.0)
+
+    # stopping and continuing svlopp interrupts its blocking calls
+    done = threading.Event()
+
+    def interrupt():
+        while not done.is_set():
+            os.kill(proc.pid, signal.SIGSTOP)
+            os.kill(proc.pid, signal.SIGCONT)
+            time.sleep(0.001)
+
+    interrupter = threading.Thread(target=interrupt)
+    interrupter.start()
+    try:
+        for _ in range(20):
+            assert svloppctl(run_dir, "start", "test").returncode == 0
+    finally:
+        done.set()
+        interrupter.join()
+
+    assert proc.poll() is None
+    # for the last start to be handled
+    time.sleep(0.5)
+    status = read_status(run_dir).get("test")
+    assert status.pid_or_reason == f"{REASON_SPAWN_FAILED}({errno.ENOENT})"
+    # the error of each start was read from its exec pipe
+    stderr = _stderr_after_exit(proc)
+    starts = stderr.count("started service 'test'")
+    failure = f"service 'test' failed to start: exec({missing}) failed: ENOENT"
+    assert starts > 1
+    assert stderr.count(failure) == starts
@@ -0,0 +1,99 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import signal
+from pathlib import Path
+
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+from constants import CONFIG_FILE_NAME, REASON_ERROR, STATE_STOPPED
+
+SECRET = "hunter2"
+
+
+def _children(pid: int) -> list[int]:
+    children = []
+    for task in Path(f"/proc/{pid}/task").iterdir():
+        children += [int(c) for c in (task / "children").read_text().split()]
+    return children
+
+
+def _comm(pid: int) -> str:
+    return Path(f"/proc/{pid}/comm").read_text().strip()
+
+
+def test_spawner_starts_services(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    secret_path = tmp_path / "secret"
+    secret_path.write_text(SECRET)
+    work_dir = tmp_path / "work"
+    work_dir.mkdir()
+    output_path = tmp_path / "output"
+
+    script = (
+        f"cat /proc/self/fd/$TOKEN_FD > {output_path}.secret; "
+        f"pwd > {output_path}.cwd; "
+        f"echo $PPID $SVLOPP_SERVICE_NAME > {output_path}; "
+        "sleep 10"
+    )
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", '{script}']
+working_directory = "{work_dir}"
+secrets = [{{ env = "TOKEN_FD", path = "{secret_path}" }}]
+
+[services.failing]
+command = "/bin/sh"
+args = ["-c", "exit 3"]
+"""
+    )
+
+    proc = svlopp_proc(config_path, "--spawner")
+
+    wait_until(lambda: output_path.exists() and output_path.read_text(), timeout=2.0)
+
+    # the service process is a child of svlopp, not of the helper
+    assert output_path.read_text() == f"{proc.pid} test\n"
+    assert (tmp_path / "output.secret").read_text() == SECRET
+    assert (tmp_path / "output.cwd").read_text() == f"{work_dir}\n"
+    assert "svlopp-spawner" in [_comm(pid) for pid in _children(proc.pid)]
+
+    def is_failing_stopped():
+        try:
+            return read_status(run_dir).is_stopped("failing")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_failing_stopped, timeout=2.0)
+    failing = read_status(run_dir).get("failing")
+    assert failing.state == STATE_STOPPED
+    assert failing.pid_or_reason == f"{REASON_ERROR}(3)"
+
+
+def test_spawner_stopped_on_shutdown(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    proc = svlopp_proc(config_path, "--init", "--spawner")
+
+    def is_test_running():
+        try:
+            return read_status(run_dir).is_running("test")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_running, timeout=2.0)
+
+    # with --init svlopp waits for all of its children, the helper must not
+    # hold the shutdown
+    proc.send_signal(signal.SIGTERM)
+    assert proc.wait(timeout=5) == 0
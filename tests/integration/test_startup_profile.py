@@ -0,0 +1,70 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import subprocess
+
+from constants import CONFIG_FILE_NAME, SVLOPPCTL_BINARY_PATH
+from helpers.utils import wait_until
+
+STARTUP_FILE_NAME = "startup"
+
+
+def _profile(run_dir):
+    try:
+        lines = (run_dir / STARTUP_FILE_NAME).read_text().splitlines()
+    except FileNotFoundError:
+        return {}
+    entries = {}
+    for line in lines:
+        name, bind_to, forked, exec_ = line.split(" ")
+        entries[name] = (bind_to, int(forked), int(exec_))
+    return entries
+
+
+def test_startup_profile_and_analyze(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        """
+[services.app]
+command = "sleep"
+args = ["60"]
+
+[services.shipper]
+command = "sleep"
+args = ["60"]
+bind_to = "app"
+
+[services.manual]
+command = "sleep"
+args = ["60"]
+autostart = false
+"""
+    )
+    svlopp_proc(config_path)
+
+    wait_until(lambda: len(_profile(run_dir)) == 2, timeout=3.0)
+    profile = _profile(run_dir)
+    assert "manual" not in profile
+    app_bind, app_forked, app_exec = profile["app"]
+    shipper_bind, shipper_forked, shipper_exec = profile["shipper"]
+    assert (app_bind, shipper_bind) == ("-", "app")
+    assert 0 <= app_forked <= app_exec
+    # bound services are only started once their target runs
+    assert app_forked <= shipper_forked <= shipper_exec
+
+    result = subprocess.run(
+        [SVLOPPCTL_BINARY_PATH, "--run-dir", str(run_dir), "analyze"],
+        capture_output=True,
+        text=True,
+        timeout=5,
+    )
+    assert result.returncode == 0, result.stderr
+    lines = result.stdout.splitlines()
+    assert lines[0].startswith("2 services up in ")
+    chain_start = lines.index("critical chain:")
+    assert lines[chain_start + 1].startswith("  app forked @")
+    # the chain ends with the last service up
+    if shipper_exec > app_exec:
+        assert lines[chain_start + 2].startswith("    shipper forked @")
+    assert "slowest services, from fork to exec:" in lines
@@ -0,0 +1,136 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+import signal
+
+from helpers.control_fifo import send_control_op
+from helpers.status_file import read_service_status
+from helpers.utils import wait_until
+from constants import (
+    CONFIG_FILE_NAME,
+    STATE_RUNNING,
+    STATE_STOPPED,
+    STATUS_DIR_NAME,
+    STATUS_FILE_NAME,
+    STOP_OPCODE,
+)
+
+PER_SERVICE_STATUS = "--per-service-status"
+
+
+def test_status_dir_one_file_per_service(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.a]
+command = "/bin/sleep"
+args = ["10"]
+
+[services.b]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    _ = svlopp_proc(config_path, PER_SERVICE_STATUS)
+
+    def are_all_running():
+        try:
+            return all(
+                read_service_status(run_dir, name).state == STATE_RUNNING
+                for name in ("a", "b")
+            )
+        except FileNotFoundError:
+            return False
+
+    wait_until(are_all_running, timeout=1.0)
+
+    assert sorted(os.listdir(run_dir / STATUS_DIR_NAME)) == ["a", "b"]
+    assert not (run_dir / STATUS_FILE_NAME).exists()
+
+
+def test_status_dir_only_changed_files_rewritten(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.a]
+command = "/bin/sleep"
+args = ["10"]
+
+[services.b]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    _ = svlopp_proc(config_path, PER_SERVICE_STATUS)
+
+    def are_all_running():
+        try:
+            return all(
+                read_service_status(run_dir, name).state == STATE_RUNNING
+                for name in ("a", "b")
+            )
+        except FileNotFoundError:
+            return False
+
+    wait_until(are_all_running, timeout=1.0)
+
+    # files are replaced by rename, so a rewrite changes the inode
+    b_inode = os.stat(run_dir / STATUS_DIR_NAME / "b").st_ino
+    a = read_service_status(run_dir, "a")
+    send_control_op(run_dir, STOP_OPCODE, a.service_id)
+
+    def is_a_stopped():
+        return read_service_status(run_dir, "a").state == STATE_STOPPED
+
+    wait_until(is_a_stopped, timeout=2.0)
+
+    assert os.stat(run_dir / STATUS_DIR_NAME / "b").st_ino == b_inode
+
+
+def test_status_dir_removed_service(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.a]
+command = "/bin/sleep"
+args = ["10"]
+
+[services.b]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    proc = svlopp_proc(config_path, PER_SERVICE_STATUS)
+
+    def is_b_running():
+        try:
+            return read_service_status(run_dir, "b").state == STATE_RUNNING
+        except FileNotFoundError:
+            return False
+
+    wait_until(is_b_running, timeout=1.0)
+
+    config_path.write_text(
+        """
+[services.a]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    os.kill(proc.pid, signal.SIGHUP)
+
+    def is_b_removed():
+        return not (run_dir / STATUS_DIR_NAME / "b").exists()
+
+    wait_until(is_b_removed, timeout=3.0)
+
+    assert read_service_status(run_dir, "a").state == STATE_RUNNING
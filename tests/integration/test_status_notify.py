@@ -0,0 +1,128 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import socket
+import stat
+
+from helpers.control_fifo import send_control_op
+from helpers.inotify import (
+    IN_CLOSE_WRITE,
+    IN_CREATE,
+    IN_MODIFY,
+    IN_MOVED_TO,
+    Inotify,
+)
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+from constants import (
+    CONFIG_FILE_NAME,
+    STATUS_FILE_NAME,
+    STATUS_NOTIFY_SOCKET_NAME,
+    STOP_OPCODE,
+)
+
+
+def test_status_update_is_one_moved_to(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    _ = svlopp_proc(config_path)
+
+    def is_test_running():
+        try:
+            status = read_status(run_dir)
+            return status.is_running("test")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_running, timeout=1.0)
+
+    inotify = Inotify(run_dir, IN_MODIFY | IN_CLOSE_WRITE | IN_MOVED_TO | IN_CREATE)
+    try:
+        test = read_status(run_dir).get("test")
+        send_control_op(run_dir, STOP_OPCODE, test.service_id)
+
+        # covers a couple of timer ticks, which must not rewrite the file
+        events = inotify.read_events(timeout=2.5)
+    finally:
+        inotify.close()
+
+    status_events = [mask for mask, name in events if name == STATUS_FILE_NAME]
+    # stop signal sent, then stopped
+    assert status_events == [IN_MOVED_TO, IN_MOVED_TO]
+    assert read_status(run_dir).is_stopped("test")
+
+
+def test_status_notify_socket(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    _ = svlopp_proc(config_path, "--status-notify")
+
+    def is_test_running():
+        try:
+            status = read_status(run_dir)
+            return status.is_running("test")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_running, timeout=1.0)
+
+    sock = socket.socket(socket.AF_UNIX, socket.SOCK_SEQPACKET)
+    try:
+        sock.connect(str(run_dir / STATUS_NOTIFY_SOCKET_NAME))
+        sock.settimeout(2.0)
+
+        test = read_status(run_dir).get("test")
+        send_control_op(run_dir, STOP_OPCODE, test.service_id)
+
+        def is_test_stopped():
+            sock.recv(16)
+            return read_status(run_dir).is_stopped("test")
+
+        wait_until(is_test_stopped, timeout=3.0)
+
+        # nothing changes anymore, so no further notification
+        sock.settimeout(1.5)
+        try:
+            sock.recv(16)
+            notified = True
+        except TimeoutError:
+            notified = False
+        assert not notified
+    finally:
+        sock.close()
+
+
+def test_status_notify_socket_owner_only(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    _ = svlopp_proc(config_path, "--status-notify")
+
+    socket_path = run_dir / STATUS_NOTIFY_SOCKET_NAME
+    wait_until(socket_path.exists, timeout=1.0)
+
+    assert stat.S_IMODE(socket_path.stat().st_mode) == 0o600
@@ -0,0 +1,100 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import socket
+import time
+
+from helpers.status_file import read_status
+from helpers.utils import service_state, start_svlopp, svloppctl, wait_until
+
+CONFIG = """
+[services.web]
+command = "/bin/sh"
+args = ["-c", "{script}"]
+{extra}
+"""
+# binds the port along with the instance it replaces
+LISTEN_SCRIPT = (
+    "sleep 1; exec python3 -c '"
+    "import socket, time; s = socket.socket(); "
+    "s.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEPORT, 1); "
+    's.bind((\\"127.0.0.1\\", {port})); s.listen(); time.sleep(100)'
+    "'"
+)
+# fails to come up once started again
+FAILING_SCRIPT = "[ -e {marker} ] && exit 1; touch {marker}; exec sleep 100"
+
+
+def _names(run_dir):
+    try:
+        return {line.service_name for line in read_status(run_dir).lines}
+    except FileNotFoundError:
+        return set()
+
+
+def _start(tmp_path, run_dir, svlopp_proc, script, extra=""):
+    config = CONFIG.format(script=script, extra=extra)
+    start_svlopp(tmp_path, run_dir, svlopp_proc, config, ["web"])
+    return service_state(run_dir, "web")[1]
+
+
+def _free_port():
+    with socket.socket() as sock:
+        sock.bind(("127.0.0.1", 0))
+        return sock.getsockname()[1]
+
+
+def test_swap_replaces_running_service(tmp_path, run_dir, svlopp_proc):
+    pid = _start(tmp_path, run_dir, svlopp_proc, "exec sleep 100")
+
+    assert svloppctl(run_dir, "swap", "web").returncode == 0
+    wait_until(lambda: "web-new" in _names(run_dir), timeout=2.0)
+    # the service keeps running until the new instance is ready
+    while "web-new" in _names(run_dir):
+        assert service_state(run_dir, "web") == ("running", pid)
+        time.sleep(0.05)
+
+    state, new_pid = service_state(run_dir, "web")
+    assert state == "running"
+    assert new_pid != pid
+    wait_until(lambda: _names(run_dir) == {"web"}, timeout=5.0)
+    assert service_state(run_dir, "web") == ("running", new_pid)
+
+
+def test_swap_waits_for_ports(tmp_path, run_dir, svlopp_proc):
+    port = _free_port()
+    script = LISTEN_SCRIPT.format(port=port)
+    pid = _start(tmp_path, run_dir, svlopp_proc, script, f"ports = [{port}]")
+
+    assert svloppctl(run_dir, "swap", "web").returncode == 0
+    wait_until(lambda: "web-new" in _names(run_dir), timeout=2.0)
+    started = time.monotonic()
+    wait_until(lambda: "web-new" not in _names(run_dir), timeout=10.0)
+    # the new instance listens a second after starting
+    assert time.monotonic() - started >= 0.5
+    wait_until(lambda: _names(run_dir) == {"web"}, timeout=5.0)
+    state, new_pid = service_state(run_dir, "web")
+    assert state == "running"
+    assert new_pid != pid
+
+
+def test_swap_aborts_on_failure(tmp_path, run_dir, svlopp_proc):
+    script = FAILING_SCRIPT.format(marker=tmp_path / "marker")
+    pid = _start(tmp_path, run_dir, svlopp_proc, script)
+
+    assert svloppctl(run_dir, "swap", "web").returncode == 0
+    wait_until(lambda: "web-new" in _names(run_dir), timeout=2.0)
+    wait_until(lambda: _names(run_dir) == {"web"}, timeout=5.0)
+    time.sleep(1.5)
+    assert service_state(run_dir, "web") == ("running", pid)
+
+
+def test_swap_of_stopped_service_rejected(tmp_path, run_dir, svlopp_proc):
+    _start(tmp_path, run_dir, svlopp_proc, "exec sleep 100")
+    assert svloppctl(run_dir, "stop", "web").returncode == 0
+    wait_until(lambda: service_state(run_dir, "web")[0] == "stopped", timeout=2.0)
+
+    assert svloppctl(run_dir, "swap", "web").returncode == 0
+    time.sleep(1.5)
+    assert _names(run_dir) == {"web"}
@@ -0,0 +1,107 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import ctypes
+import os
+import platform
+import signal
+import time
+
+import pytest
+
+from helpers.utils import is_zombie, service_state, start_svlopp, wait_until
+
+CONFIG = """
+[services.test]
+command = "/bin/sleep"
+args = ["60"]
+"""
+
+# x86_64 ptrace requests, and `struct user_regs_struct`
+PTRACE_PEEKTEXT = 1
+PTRACE_SINGLESTEP = 9
+PTRACE_GETREGS = 12
+PTRACE_SETREGS = 13
+PTRACE_ATTACH = 16
+PTRACE_DETACH = 17
+WALL = 0x40000000
+SYS_WAIT4 = 61
+SYSCALL_INSN = 0x050F
+NO_SYSCALL = 2**64 - 1
+
+libc = ctypes.CDLL(None, use_errno=True)
+libc.ptrace.restype = ctypes.c_long
+libc.ptrace.argtypes = [ctypes.c_long, ctypes.c_long, ctypes.c_void_p, ctypes.c_void_p]
+
+
+class Regs(ctypes.Structure):
+    _fields_ = [
+        (name, ctypes.c_ulonglong)
+        for name in (
+            "r15 r14 r13 r12 rbp rbx r11 r10 r9 r8 rax rcx rdx rsi rdi orig_rax "
+            "rip cs eflags rsp ss fs_base gs_base ds es fs gs"
+        ).split()
+    ]
+
+
+def _ptrace(request, pid, addr=None, data=None):
+    ctypes.set_errno(0)
+    ret = libc.ptrace(request, pid, addr, data)
+    if ret == -1 and (err := ctypes.get_errno()):
+        raise OSError(err, os.strerror(err))
+    return ret
+
+
+def _stop_in_syscall(pid):
+    """Attach to `pid` until it's stopped in a syscall, returning its
+    registers"""
+    for _ in range(100):
+        _ptrace(PTRACE_ATTACH, pid)
+        os.waitpid(pid, WALL)
+        regs = Regs()
+        _ptrace(PTRACE_GETREGS, pid, None, ctypes.byref(regs))
+        insn = _ptrace(PTRACE_PEEKTEXT, pid, regs.rip - 2) & 0xFFFF
+        if regs.orig_rax != NO_SYSCALL and insn == SYSCALL_INSN:
+            return regs
+        _ptrace(PTRACE_DETACH, pid)
+        time.sleep(0.01)
+    pytest.fail(f"process {pid} never stopped in a syscall")
+
+
+def _reap_behind(pid, child):
+    """Have `pid` reap its exited `child` by injecting a `wait4` call, so
+    that its own code never sees the child exit"""
+    regs = _stop_in_syscall(pid)
+    os.kill(child, signal.SIGKILL)
+    wait_until(lambda: is_zombie(child), timeout=2.0)
+
+    call = Regs.from_buffer_copy(regs)
+    call.rip = regs.rip - 2
+    call.orig_rax = NO_SYSCALL
+    call.rax = SYS_WAIT4
+    call.rdi = child
+    call.rsi = call.rdx = call.r10 = 0
+    _ptrace(PTRACE_SETREGS, pid, None, ctypes.byref(call))
+    _ptrace(PTRACE_SINGLESTEP, pid)
+    os.waitpid(pid, WALL)
+    _ptrace(PTRACE_GETREGS, pid, None, ctypes.byref(call))
+    # back to the interrupted syscall
+    _ptrace(PTRACE_SETREGS, pid, None, ctypes.byref(regs))
+    _ptrace(PTRACE_DETACH, pid)
+    assert call.rax == child
+
+
+def test_sweep_lost_process(tmp_path, run_dir, svlopp_proc):
+    if platform.machine() != "x86_64":
+        pytest.skip("injects an x86_64 syscall")
+    proc = start_svlopp(tmp_path, run_dir, svlopp_proc, CONFIG, ["test"])
+    pid = int(service_state(run_dir, "test")[1])
+
+    try:
+        _reap_behind(proc.pid, pid)
+    except PermissionError:
+        pytest.skip("ptrace not permitted")
+
+    # the sweep runs every 30 ticks
+    wait_until(lambda: service_state(run_dir, "test") == ("stopped", "lost"), timeout=35.0)
@@ -0,0 +1,103 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import os
+import signal
+import socket
+import time
+
+from helpers.status_file import read_status
+from helpers.utils import wait_until
+from constants import CONFIG_FILE_NAME
+
+
+def test_watchdog_idle_supervisor(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+
+    config_path.write_text(
+        """
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+"""
+    )
+
+    proc = svlopp_proc(config_path, "--watchdog-timeout-ms", "2000", "--watchdog-abort")
+
+    def is_test_running():
+        try:
+            status = read_status(run_dir)
+            return status.is_running("test")
+        except (FileNotFoundError, KeyError):
+            return False
+
+    wait_until(is_test_running, timeout=1.0)
+
+    # timer ticks keep the watchdog happy
+    time.sleep(3.5)
+    assert proc.poll() is None
+
+
+def test_watchdog_stuck_main_loop_aborts(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    fifo_path = tmp_path / "log_fifo"
+    os.mkfifo(fifo_path)
+
+    # opening a fifo for writing blocks until a reader shows up
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sleep"
+args = ["10"]
+log_file_path = "{fifo_path}"
+"""
+    )
+
+    proc = svlopp_proc(config_path, "--watchdog-timeout-ms", "2000", "--watchdog-abort")
+
+    proc.wait(timeout=5.0)
+    assert proc.returncode == -signal.SIGABRT
+    stderr = proc.stderr.read()
+    assert b"main loop made no progress" in stderr
+    assert b"wchan" in stderr
+
+
+def test_systemd_notify_env_not_inherited(tmp_path, run_dir, svlopp_proc):
+    config_path = tmp_path / CONFIG_FILE_NAME
+    log_file_path = tmp_path / "test.log"
+    socket_path = tmp_path / "notify.sock"
+
+    config_path.write_text(
+        f"""
+[services.test]
+command = "/bin/sh"
+args = ["-c", "env; exec sleep 10"]
+log_file_path = "{log_file_path}"
+"""
+    )
+
+    notify = socket.socket(socket.AF_UNIX, socket.SOCK_DGRAM)
+    notify.bind(str(socket_path))
+    notify.settimeout(3.0)
+    systemd_env = {"NOTIFY_SOCKET": str(socket_path), "WATCHDOG_USEC": "1000000"}
+    os.environ.update(systemd_env)
+    try:
+        svlopp_proc(config_path)
+    finally:
+        for key in systemd_env:
+            del os.environ[key]
+
+    try:
+        # svlopp still notifies its own supervisor
+        assert notify.recv(64) == b"WATCHDOG=1"
+    finally:
+        notify.close()
+
+    def has_env():
+        return log_file_path.exists() and "SVLOPP_SERVICE" in log_file_path.read_text()
+
+    wait_until(has_env, timeout=2.0)
+    names = [line.split("=", 1)[0] for line in log_file_path.read_text().splitlines()]
+    assert "NOTIFY_SOCKET" not in names
+    assert "WATCHDOG_USEC" not in names
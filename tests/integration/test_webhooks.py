@@ -0,0 +1,125 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+import hashlib
+import hmac
+import json
+import threading
+import time
+from http.server import BaseHTTPRequestHandler, HTTPServer
+
+import pytest
+
+from constants import CONFIG_FILE_NAME
+from helpers.utils import wait_until
+
+SECRET = b"webhook-secret"
+
+
+@pytest.fixture
+def endpoint():
+    received = []
+
+    class Handler(BaseHTTPRequestHandler):
+        def do_POST(self):
+            body = self.rfile.read(int(self.headers["Content-Length"]))
+            received.append((self.path, dict(self.headers), body))
+            self.send_response(204)
+            self.end_headers()
+
+        def log_message(self, *args):
+            pass
+
+    server = HTTPServer(("127.0.0.1", 0), Handler)
+    thread = threading.Thread(target=server.serve_forever, daemon=True)
+    thread.start()
+    yield f"http://127.0.0.1:{server.server_address[1]}", received
+    server.shutdown()
+    server.server_close()
+
+
+def test_webhook_signed_state_changes(tmp_path, svlopp_proc, endpoint):
+    url, received = endpoint
+    secret_file = tmp_path / "secret"
+    secret_file.write_bytes(SECRET + b"\n")
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        f"""
+[[webhooks]]
+url = "{url}/hooks/{{name}}/{{event}}"
+secret_file = "{secret_file}"
+
+[services.test]
+command = "sh"
+args = ["-c", "sleep 0.5; exit 3"]
+"""
+    )
+    svlopp_proc(config_path)
+
+    wait_until(lambda: len(received) >= 2, timeout=5.0)
+    assert [path for path, _, _ in received] == [
+        "/hooks/test/running",
+        "/hooks/test/failed",
+    ]
+    for _, headers, body in received:
+        expected = hmac.new(SECRET, body, hashlib.sha256).hexdigest()
+        assert headers["X-Svlopp-Signature"] == f"sha256={expected}"
+    failed = json.loads(received[1][2])
+    assert failed["event"] == "failed"
+    assert failed["name"] == "test"
+    assert failed["reason"] == "error(3)"
+
+
+def test_webhook_event_and_service_filters(tmp_path, svlopp_proc, endpoint):
+    url, received = endpoint
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        f"""
+[[webhooks]]
+url = "{url}/{{name}}"
+on = ["stopped"]
+services = ["watched"]
+
+[services.watched]
+command = "sh"
+args = ["-c", "sleep 0.3"]
+
+[services.ignored]
+command = "sh"
+args = ["-c", "sleep 0.3"]
+"""
+    )
+    svlopp_proc(config_path)
+
+    wait_until(lambda: len(received) >= 1, timeout=5.0)
+    # leave time for unexpected notifications
+    time.sleep(1.0)
+    assert [path for path, _, _ in received] == ["/watched"]
+    assert json.loads(received[0][2])["event"] == "stopped"
+
+
+def test_webhook_fds_high(tmp_path, svlopp_proc, endpoint):
+    url, received = endpoint
+    config_path = tmp_path / CONFIG_FILE_NAME
+    config_path.write_text(
+        f"""
+[[webhooks]]
+url = "{url}/{{name}}/{{event}}"
+on = ["fds_high"]
+
+[services.test]
+command = "sh"
+args = ["-c", "ulimit -n 16; exec 3</dev/null 4</dev/null 5</dev/null 6</dev/null 7</dev/null; exec sleep 10"]
+fd_warn_percent = 50
+"""
+    )
+    svlopp_proc(config_path)
+
+    wait_until(lambda: len(received) >= 1, timeout=5.0)
+    # the flag stays set, which isn't notified again
+    time.sleep(1.0)
+    assert [path for path, _, _ in received] == ["/test/fds_high"]
+    body = json.loads(received[0][2])
+    assert body["event"] == "fds_high"
+    assert body["pid"] is not None
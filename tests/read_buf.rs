@@ -0,0 +1,129 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Records read into a `ReadBuf`, reads being simulated by writing to its
+//! bytes. Also run with `cargo +nightly miri test --test read_buf`.
+
+use svlopp::read_buf::{Pod, ReadBuf, ReadBufError};
+
+/// A record like the ones the kernel returns
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Record {
+    signo: u32,
+    pid: i32,
+    value: u64,
+}
+
+// SAFETY: integers only, without padding
+unsafe impl Pod for Record {}
+
+const RECORD_SIZE: usize = size_of::<Record>();
+
+fn record_bytes(record: &Record) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(RECORD_SIZE);
+    bytes.extend_from_slice(&record.signo.to_ne_bytes());
+    bytes.extend_from_slice(&record.pid.to_ne_bytes());
+    bytes.extend_from_slice(&record.value.to_ne_bytes());
+    bytes
+}
+
+/// Simulate a read of `bytes`, returning its length
+fn read<T: Pod, const N: usize>(buf: &mut ReadBuf<T, N>, bytes: &[u8]) -> usize {
+    buf.as_bytes_mut()[..bytes.len()].copy_from_slice(bytes);
+    bytes.len()
+}
+
+fn records(n: usize) -> Vec<Record> {
+    (0..n)
+        .map(|i| Record {
+            signo: 17,
+            pid: 1000 + i as i32,
+            value: u64::MAX - i as u64,
+        })
+        .collect()
+}
+
+#[test]
+fn bytes_cover_the_buffer() {
+    let mut buf = ReadBuf::<Record, 4>::new();
+    assert_eq!(buf.as_bytes_mut().len(), 4 * RECORD_SIZE);
+    assert!(buf.as_bytes_mut().iter().all(|&b| b == 0));
+}
+
+#[test]
+fn whole_records() {
+    let mut buf = ReadBuf::<Record, 4>::new();
+    let expected = records(3);
+    let bytes: Vec<u8> = expected.iter().flat_map(record_bytes).collect();
+    let n = read(&mut buf, &bytes);
+    assert_eq!(buf.records(n), Ok(&expected[..]));
+}
+
+#[test]
+fn full_buffer() {
+    let mut buf = ReadBuf::<Record, 4>::new();
+    let expected = records(4);
+    let bytes: Vec<u8> = expected.iter().flat_map(record_bytes).collect();
+    let n = read(&mut buf, &bytes);
+    assert_eq!(buf.records(n), Ok(&expected[..]));
+}
+
+#[test]
+fn empty_read() {
+    let buf = ReadBuf::<Record, 4>::new();
+    assert_eq!(buf.records(0), Ok(&[][..]));
+}
+
+#[test]
+fn partial_record() {
+    let mut buf = ReadBuf::<Record, 4>::new();
+    let bytes: Vec<u8> = records(2).iter().flat_map(record_bytes).collect();
+    let n = read(&mut buf, &bytes[..RECORD_SIZE + 5]);
+    assert_eq!(buf.records(n), Err(ReadBufError::Partial(5)));
+}
+
+#[test]
+fn partial_first_record() {
+    let mut buf = ReadBuf::<Record, 4>::new();
+    let n = read(&mut buf, &[0xff; 3]);
+    assert_eq!(buf.records(n), Err(ReadBufError::Partial(3)));
+}
+
+#[test]
+fn oversized_read() {
+    let buf = ReadBuf::<Record, 4>::new();
+    assert_eq!(
+        buf.records(4 * RECORD_SIZE + 1),
+        Err(ReadBufError::Oversized(4 * RECORD_SIZE + 1))
+    );
+    assert_eq!(
+        buf.records(usize::MAX),
+        Err(ReadBufError::Oversized(usize::MAX))
+    );
+}
+
+#[test]
+fn later_read_replaces_records() {
+    let mut buf = ReadBuf::<Record, 4>::new();
+    let first: Vec<u8> = records(4).iter().flat_map(record_bytes).collect();
+    read(&mut buf, &first);
+    let expected = Record {
+        signo: 1,
+        pid: -1,
+        value: 0,
+    };
+    let n = read(&mut buf, &record_bytes(&expected));
+    assert_eq!(buf.records(n), Ok(&[expected][..]));
+}
+
+#[test]
+fn byte_array_records() {
+    let mut buf = ReadBuf::<[u8; 9], 3>::new();
+    let bytes: Vec<u8> = (0..18).collect();
+    let n = read(&mut buf, &bytes);
+    let frames = buf.records(n).expect("whole frames");
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[1], [9, 10, 11, 12, 13, 14, 15, 16, 17]);
+}
@@ -0,0 +1,318 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Property-based tests of the service state machine.
+//!
+//! svlopp is run with a few services, then sent random sequences of
+//! events: control operations, signals to the service processes, reloads
+//! and time passing for timers to fire. After each event the state
+//! snapshot is checked against invariants that hold whatever the events:
+//!
+//! - a running or stopping service has a pid, which is a child of the
+//!   supervisor, and no two services share one
+//! - services keep their id, and none appears or disappears
+//! - start counters never go backwards
+//!
+//! Each case starts a supervisor, so only a few of them run by default.
+//! Set `PROPTEST_CASES` to run more.
+
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use proptest::{prelude::*, test_runner::TestCaseError};
+use svlopp::{
+    CONTROL_PIPE_NAME, SNAPSHOT_FILE_NAME, opcode,
+    snapshot::{RecordState, Snapshot},
+    wire::WireControlCommand,
+};
+
+const DEFAULT_CASES: u32 = 8;
+
+/// How long the snapshot may lag behind a process exiting, between the
+/// supervisor reaping it and publishing its new state
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The services of the config, by name
+const SERVICES: [&str; 4] = ["restarted", "crashing", "manual", "bound"];
+
+const CONFIG: &str = r#"
+[services.restarted]
+command = "/bin/sleep"
+args = ["30"]
+on_exit = "Restart"
+
+[services.crashing]
+command = "/bin/sh"
+args = ["-c", "sleep 0.3; exit 3"]
+on_exit = "Restart"
+
+[services.manual]
+command = "/bin/sleep"
+args = ["30"]
+
+[services.bound]
+command = "/bin/sleep"
+args = ["30"]
+stop_signal = "SIGINT"
+bind_to = "restarted"
+"#;
+
+#[derive(Debug, Clone)]
+enum Event {
+    /// A control FIFO operation on a service
+    Control(u8, usize),
+    /// A signal sent to the process of a service, if running
+    Kill(usize, i32),
+    /// `SIGHUP` to the supervisor, the config being unchanged
+    Reload,
+    /// Time passing, in milliseconds
+    Wait(u64),
+}
+
+fn event() -> impl Strategy<Value = Event> {
+    let service = 0..SERVICES.len();
+    prop_oneof![
+        3 => (
+            prop_oneof![Just(opcode::START), Just(opcode::STOP), Just(opcode::RESTART)],
+            service.clone(),
+        )
+            .prop_map(|(op, svc)| Event::Control(op, svc)),
+        2 => (
+            service,
+            prop_oneof![Just(libc::SIGTERM), Just(libc::SIGKILL), Just(libc::SIGINT)],
+        )
+            .prop_map(|(svc, signal)| Event::Kill(svc, signal)),
+        1 => Just(Event::Reload),
+        2 => (0u64..1200).prop_map(Event::Wait),
+    ]
+}
+
+/// A running supervisor, stopped on drop if the case failed
+struct Supervisor {
+    child: Child,
+    dir: PathBuf,
+}
+
+impl Supervisor {
+    fn start() -> Self {
+        static CASE: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "svlopp-state-machine-{}-{}",
+            std::process::id(),
+            CASE.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).expect("test directory created");
+        let config = dir.join("svlopp.toml");
+        fs::write(&config, CONFIG).expect("config written");
+        let child = Command::new(env!("CARGO_BIN_EXE_svlopp"))
+            .args(["--run-dir".as_ref(), dir.join("run").as_os_str()])
+            .args(["--log-level", "error"])
+            .arg(&config)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("svlopp started");
+        Self { child, dir }
+    }
+
+    fn run_dir(&self) -> PathBuf {
+        self.dir.join("run")
+    }
+
+    fn pid(&self) -> i32 {
+        self.child.id() as i32
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        let buf = fs::read(self.run_dir().join(SNAPSHOT_FILE_NAME)).ok()?;
+        Snapshot::decode(&buf).ok()
+    }
+
+    /// Wait for the snapshot to list all the services
+    fn wait_started(&self) -> Snapshot {
+        let deadline = Instant::now() + SETTLE_TIMEOUT;
+        loop {
+            if let Some(snapshot) = self.snapshot()
+                && snapshot.services.len() == SERVICES.len()
+            {
+                return snapshot;
+            }
+            assert!(Instant::now() < deadline, "svlopp didn't start");
+            sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn send(&self, opcode: u8, service_id: u64) {
+        OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(self.run_dir().join(CONTROL_PIPE_NAME))
+            .and_then(|mut fifo| {
+                fifo.write_all(&WireControlCommand::new(opcode, service_id).encode())
+            })
+            .expect("command sent");
+    }
+
+    /// Stop the supervisor, returning whether it exited successfully
+    fn stop(&mut self) -> bool {
+        unsafe { libc::kill(self.pid(), libc::SIGTERM) };
+        self.child.wait().is_ok_and(|status| status.success())
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        if self.child.try_wait().ok().flatten().is_none() {
+            let _ = self.stop();
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Parent pid of `pid`, `None` once it's reaped
+fn parent_pid(pid: i32) -> Option<i32> {
+    let stat = fs::read_to_string(Path::new("/proc").join(pid.to_string()).join("stat")).ok()?;
+    // the command name may contain spaces, fields are counted after it
+    stat.rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+/// Check the invariants that only hold once the supervisor has caught up
+/// with the processes that exited
+fn check_processes(snapshot: &Snapshot, supervisor_pid: i32) -> Result<(), String> {
+    let mut pids = HashMap::new();
+    for svc in &snapshot.services {
+        let Some(pid) = svc.state.pid() else {
+            continue;
+        };
+        if pid <= 0 {
+            return Err(format!("{} has pid {}", svc.name, pid));
+        }
+        if parent_pid(pid) != Some(supervisor_pid) {
+            return Err(format!("{} pid {} isn't a child of svlopp", svc.name, pid));
+        }
+        if let Some(other) = pids.insert(pid, &svc.name) {
+            return Err(format!("{} and {} share pid {}", svc.name, other, pid));
+        }
+    }
+    Ok(())
+}
+
+/// Check the current snapshot against the invariants, once the supervisor
+/// has caught up, `previous` being the last snapshot checked
+fn check(supervisor: &Supervisor, previous: &Snapshot) -> Result<Snapshot, TestCaseError> {
+    let deadline = Instant::now() + SETTLE_TIMEOUT;
+    let snapshot = loop {
+        let snapshot = supervisor.snapshot().expect("snapshot readable");
+        match check_processes(&snapshot, supervisor.pid()) {
+            Ok(()) => break snapshot,
+            Err(e) if Instant::now() >= deadline => return Err(TestCaseError::fail(e)),
+            Err(_) => sleep(Duration::from_millis(20)),
+        }
+    };
+    prop_assert_eq!(snapshot.pid, supervisor.pid() as u32);
+    prop_assert_eq!(snapshot.services.len(), previous.services.len());
+    for svc in &snapshot.services {
+        let before = previous
+            .services
+            .iter()
+            .find(|before| before.name == svc.name);
+        prop_assert!(before.is_some(), "{} appeared", svc.name);
+        let before = before.expect("checked above");
+        prop_assert_eq!(svc.id, before.id, "{} changed id", &svc.name);
+        prop_assert!(
+            svc.start_count >= before.start_count,
+            "{} start count went from {} to {}",
+            &svc.name,
+            before.start_count,
+            svc.start_count
+        );
+    }
+    Ok(snapshot)
+}
+
+fn run(events: &[Event]) -> Result<(), TestCaseError> {
+    let mut supervisor = Supervisor::start();
+    let mut snapshot = supervisor.wait_started();
+    let ids: HashMap<String, u64> = snapshot
+        .services
+        .iter()
+        .map(|svc| (svc.name.clone(), svc.id))
+        .collect();
+    for event in events {
+        match *event {
+            Event::Control(op, svc) => supervisor.send(op, ids[SERVICES[svc]]),
+            Event::Kill(svc, signal) => {
+                let pid = snapshot
+                    .services
+                    .iter()
+                    .find(|record| record.name == SERVICES[svc])
+                    .and_then(|record| match record.state {
+                        RecordState::Running { pid } => Some(pid),
+                        _ => None,
+                    });
+                if let Some(pid) = pid {
+                    unsafe { libc::kill(pid, signal) };
+                }
+            }
+            Event::Reload => unsafe {
+                libc::kill(supervisor.pid(), libc::SIGHUP);
+            },
+            Event::Wait(ms) => sleep(Duration::from_millis(ms)),
+        }
+        // let the supervisor handle the event
+        sleep(Duration::from_millis(20));
+        snapshot = check(&supervisor, &snapshot)?;
+    }
+    let pids: Vec<i32> = snapshot
+        .services
+        .iter()
+        .filter_map(|svc| svc.state.pid())
+        .collect();
+    prop_assert!(supervisor.stop(), "svlopp didn't exit cleanly");
+    for pid in pids {
+        prop_assert!(
+            parent_pid(pid).is_none(),
+            "service process {} left running",
+            pid
+        );
+    }
+    Ok(())
+}
+
+fn config() -> ProptestConfig {
+    let config = ProptestConfig::default();
+    ProptestConfig {
+        cases: if std::env::var_os("PROPTEST_CASES").is_some() {
+            config.cases
+        } else {
+            DEFAULT_CASES
+        },
+        // each shrinking step runs a supervisor
+        max_shrink_iters: 64,
+        ..config
+    }
+}
+
+proptest! {
+    #![proptest_config(config())]
+
+    #[test]
+    fn invariants_hold(events in prop::collection::vec(event(), 1..12)) {
+        run(&events)?;
+    }
+}